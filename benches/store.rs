@@ -0,0 +1,88 @@
+// A benchmark of reading the channel states one by one versus in one multi_get, as done by
+// `list_channels`. Run it with `cargo bench --bench store`.
+use ckb_types::packed::Script;
+use fnn::fiber::channel::{ChannelActorState, ChannelActorStateStore};
+use fnn::fiber::hash_algorithm::HashAlgorithm;
+use fnn::fiber::types::Hash256;
+use fnn::store::Store;
+use secp256k1::{Keypair, PublicKey, Secp256k1};
+use std::time::{Duration, Instant};
+
+const CHANNEL_COUNTS: [usize; 3] = [100, 1000, 5000];
+const ROUNDS: u32 = 20;
+
+fn gen_rand_public_key() -> PublicKey {
+    let secp = Secp256k1::new();
+    let key_pair = Keypair::new(&secp, &mut rand::thread_rng());
+    PublicKey::from_keypair(&key_pair)
+}
+
+fn mock_channel_actor_state() -> ChannelActorState {
+    let mut state = ChannelActorState::new_outbound_channel(
+        None,
+        &rand::random::<[u8; 32]>(),
+        gen_rand_public_key().into(),
+        gen_rand_public_key().into(),
+        100000000000,
+        6200000000,
+        1000,
+        0,
+        1000,
+        None,
+        Script::default(),
+        100000000000,
+        100,
+        None,
+        None,
+        HashAlgorithm::supported_algorithms(),
+        vec![0],
+    );
+    state.id = Hash256::from(rand::random::<[u8; 32]>());
+    state
+}
+
+fn measure(mut read: impl FnMut() -> usize, expected: usize) -> Duration {
+    let started = Instant::now();
+    for _ in 0..ROUNDS {
+        assert_eq!(read(), expected);
+    }
+    started.elapsed() / ROUNDS
+}
+
+fn main() {
+    for channel_count in CHANNEL_COUNTS {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path().join("store"));
+        let ids: Vec<_> = (0..channel_count)
+            .map(|_| {
+                let state = mock_channel_actor_state();
+                let id = state.id;
+                store.insert_channel_actor_state(state).unwrap();
+                id
+            })
+            .collect();
+
+        let single_gets = measure(
+            || {
+                ids.iter()
+                    .filter_map(|id| store.get_channel_actor_state(id))
+                    .count()
+            },
+            channel_count,
+        );
+        let multi_get = measure(
+            || {
+                store
+                    .get_channel_actor_states(&ids)
+                    .into_iter()
+                    .flatten()
+                    .count()
+            },
+            channel_count,
+        );
+        println!(
+            "reading {:>5} channel states: {:>12?} with single gets, {:>12?} with multi_get",
+            channel_count, single_gets, multi_get
+        );
+    }
+}
@@ -4,6 +4,10 @@ use ractor::{
     concurrency::{sleep, Duration},
     Actor, ActorProcessingErr, ActorRef, RpcReplyPort,
 };
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 
 use crate::ckb::contracts::{get_script_by_contract, Contract};
 
@@ -36,6 +40,17 @@ pub enum CkbChainMessage {
     SendTx(TransactionView, RpcReplyPort<Result<(), RpcError>>),
     TraceTx(TraceTxRequest, RpcReplyPort<TraceTxResponse>),
     GetCurrentBlockNumber((), RpcReplyPort<Result<u64, RpcError>>),
+    // Get the current status of a transaction without waiting for it to be committed.
+    GetTransactionStatus(
+        packed::Byte32,
+        RpcReplyPort<Result<ckb_jsonrpc_types::TxStatus, RpcError>>,
+    ),
+    // Get the fee rate statistics of the recent blocks, the parameter is the number of blocks
+    // to count, and the ckb node will use its default value if it's not set.
+    GetFeeRateStatistics(
+        Option<u64>,
+        RpcReplyPort<Result<Option<ckb_jsonrpc_types::FeeRateStatistics>, RpcError>>,
+    ),
 }
 
 #[derive(Debug)]
@@ -53,6 +68,23 @@ impl TraceTxResponse {
     }
 }
 
+// The tip block number of the chain known by the node. It's refreshed by the network actor
+// and read by the channel actors to tell the block at which a tlc expires.
+#[derive(Clone, Debug, Default)]
+pub struct ChainTip(Arc<AtomicU64>);
+
+impl ChainTip {
+    // None if the tip block number has not been fetched yet.
+    pub fn get(&self) -> Option<u64> {
+        Some(self.0.load(Ordering::Relaxed)).filter(|block_number| *block_number != 0)
+    }
+
+    // The tip never goes backwards, so a lagging ckb node can't postpone the tlc expiries.
+    pub fn update(&self, block_number: u64) {
+        self.0.fetch_max(block_number, Ordering::Relaxed);
+    }
+}
+
 #[ractor::async_trait]
 impl Actor for CkbChainActor {
     type Msg = CkbChainMessage;
@@ -89,7 +121,10 @@ impl Actor for CkbChainActor {
         message: Self::Msg,
         state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
-        use CkbChainMessage::{Fund, GetCurrentBlockNumber, SendTx, Sign, TraceTx};
+        use CkbChainMessage::{
+            Fund, GetCurrentBlockNumber, GetFeeRateStatistics, GetTransactionStatus, SendTx, Sign,
+            TraceTx,
+        };
         match message {
             GetCurrentBlockNumber(_, reply) => {
                 // Have to use block_in_place here, see https://github.com/seanmonstar/reqwest/issues/1017.
@@ -100,6 +135,21 @@ impl Actor for CkbChainActor {
                 });
                 let _ = reply.send(result);
             }
+            GetTransactionStatus(tx_hash, reply) => {
+                let result = tokio::task::block_in_place(move || {
+                    CkbRpcClient::new(&state.config.rpc_url)
+                        .get_only_committed_transaction(tx_hash.unpack())
+                        .map(|resp| resp.tx_status)
+                });
+                let _ = reply.send(result);
+            }
+            GetFeeRateStatistics(target, reply) => {
+                let result = tokio::task::block_in_place(move || {
+                    CkbRpcClient::new(&state.config.rpc_url)
+                        .get_fee_rate_statistics(target.map(Into::into))
+                });
+                let _ = reply.send(result);
+            }
             Fund(tx, request, reply_port) => {
                 let context = state.build_funding_context(&request);
                 if !reply_port.is_closed() {
@@ -175,6 +175,8 @@ fn get_hash_from_environment_variable(
 const ENV_PREFIX: &str = "NEXT_PUBLIC";
 const DEFUALT_SECP256K1_TYPE_HASH: &str =
     "0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8";
+const DEFUALT_SECP256K1_MULTISIG_TYPE_HASH: &str =
+    "0x5c5069eb0857efc65e1bca0c07df34c31663b3622fd3876c876320fc9634e2a8";
 
 fn get_environment_variable(
     contract: Contract,
@@ -471,6 +473,33 @@ impl ContractsContext {
             .build()
     }
 
+    // A standard lock script is the secp256k1_blake160_sighash_all lock with a 20 bytes
+    // pubkey hash as args, or the secp256k1_blake160_multisig_all lock with a 20 bytes
+    // multisig script hash (optionally followed by a 8 bytes since) as args.
+    pub(crate) fn is_standard_lock_script(&self, script: &Script) -> bool {
+        let args_len = script.args().raw_data().len();
+        let secp256k1_lock = self
+            .get_contracts_map()
+            .get(&Contract::Secp256k1Lock)
+            .expect("Secp256k1Lock contract exists");
+        if script.code_hash() == secp256k1_lock.code_hash()
+            && script.hash_type() == secp256k1_lock.hash_type()
+        {
+            return args_len == 20;
+        }
+        let type_hash_matches = |type_hash: &str| {
+            script.hash_type() == ScriptHashType::Type.into()
+                && script.code_hash() == Hash256::from_str(type_hash).expect("valid hash").into()
+        };
+        if type_hash_matches(DEFUALT_SECP256K1_TYPE_HASH) {
+            return args_len == 20;
+        }
+        if type_hash_matches(DEFUALT_SECP256K1_MULTISIG_TYPE_HASH) {
+            return args_len == 20 || args_len == 28;
+        }
+        false
+    }
+
     pub(crate) fn get_udt_info(&self, udt_script: &Script) -> Option<&UdtArgInfo> {
         for udt in &self.get_udt_whitelist().0 {
             let _type: ScriptHashType = udt_script.hash_type().try_into().expect("valid hash type");
@@ -517,7 +546,7 @@ pub fn get_cell_deps_by_contracts(contracts: Vec<Contract>) -> CellDepVec {
     init_contracts_context(None, None).get_cell_deps(contracts)
 }
 
-fn get_udt_info(script: &Script) -> Option<&UdtArgInfo> {
+pub(crate) fn get_udt_info(script: &Script) -> Option<&'static UdtArgInfo> {
     init_contracts_context(None, None).get_udt_info(script)
 }
 
@@ -535,6 +564,10 @@ pub fn get_udt_cell_deps(script: &Script) -> Option<CellDepVec> {
     })
 }
 
+pub fn is_standard_lock_script(script: &Script) -> bool {
+    init_contracts_context(None, None).is_standard_lock_script(script)
+}
+
 pub fn get_udt_whitelist() -> UdtCfgInfos {
     init_contracts_context(None, None)
         .get_udt_whitelist()
@@ -19,6 +19,9 @@ pub enum FundingError {
 
     #[error("The channel is invalid to fund")]
     InvalidChannel,
+
+    #[error("Invalid external funding tx: {0}")]
+    InvalidExternalFundingTx(String),
 }
 
 #[derive(Error, Debug)]
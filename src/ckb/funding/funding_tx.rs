@@ -67,6 +67,11 @@ pub struct FundingRequest {
     pub local_reserved_ckb_amount: u64,
     /// CKB amount to be provided by the remote party.
     pub remote_reserved_ckb_amount: u64,
+    /// A signed transaction provided by an external wallet which transfers the local funds to the
+    /// funding source lock script. Its outputs are spent as the local funding inputs.
+    #[serde_as(as = "Option<EntityHex>")]
+    #[serde(default)]
+    pub external_funding_tx: Option<Transaction>,
 }
 
 // TODO: trace locked cells
@@ -108,6 +113,7 @@ impl TxBuilder for FundingTxBuilder {
             inputs = tx.inputs().into_iter().collect();
             cell_deps = tx.cell_deps().into_iter().collect();
         }
+        self.build_external_funding_inputs(cell_collector, &mut inputs)?;
         self.build_udt_inputs_outputs(
             cell_collector,
             &mut inputs,
@@ -198,6 +204,55 @@ impl FundingTxBuilder {
         }
     }
 
+    // Spend the outputs of the external funding tx which belong to the funding source lock script.
+    // The cells are locked in the collector so that the balancer won't pick them again.
+    fn build_external_funding_inputs(
+        &self,
+        cell_collector: &mut dyn CellCollector,
+        inputs: &mut Vec<CellInput>,
+    ) -> Result<(), TxBuilderError> {
+        let Some(ref external_funding_tx) = self.request.external_funding_tx else {
+            return Ok(());
+        };
+        let tx = external_funding_tx.clone().into_view();
+        for (out_point, _) in external_funding_cells(&tx, &self.context.funding_source_lock_script)
+        {
+            if inputs
+                .iter()
+                .any(|input| input.previous_output() == out_point)
+            {
+                continue;
+            }
+            cell_collector.lock_cell(out_point.clone(), 0)?;
+            inputs.push(CellInput::new(out_point, 0));
+        }
+        Ok(())
+    }
+
+    // The cells of the external funding tx must not be spent by other transactions. The tx pool is
+    // also checked because the external funding tx may not be committed yet.
+    fn check_external_funding_cells(&self, ckb_client: &CkbRpcClient) -> Result<(), FundingError> {
+        let Some(ref external_funding_tx) = self.request.external_funding_tx else {
+            return Ok(());
+        };
+        let tx = external_funding_tx.clone().into_view();
+        for (out_point, _) in external_funding_cells(&tx, &self.context.funding_source_lock_script)
+        {
+            let cell = ckb_client.get_live_cell_with_include_tx_pool(
+                out_point.clone().into(),
+                false,
+                true,
+            )?;
+            if cell.status != "live" {
+                return Err(FundingError::InvalidExternalFundingTx(format!(
+                    "the cell {:?} is not spendable, its status is {}",
+                    out_point, cell.status
+                )));
+            }
+        }
+        Ok(())
+    }
+
     fn build_udt_inputs_outputs(
         &self,
         cell_collector: &mut dyn CellCollector,
@@ -310,7 +365,17 @@ impl FundingTxBuilder {
 
         let header_dep_resolver = DefaultHeaderDepResolver::new(&self.context.rpc_url);
         let mut cell_collector = DefaultCellCollector::new(&self.context.rpc_url);
-        let tx_dep_provider = DefaultTransactionDependencyProvider::new(&self.context.rpc_url, 10);
+        let mut tx_dep_provider =
+            DefaultTransactionDependencyProvider::new(&self.context.rpc_url, 10);
+        // Resolve the cells of the external funding tx from the tx itself, which may be still in
+        // the tx pool.
+        self.check_external_funding_cells(&ckb_client)?;
+        if let Some(ref external_funding_tx) = self.request.external_funding_tx {
+            let tip_block_number: u64 = ckb_client.get_tip_block_number()?.into();
+            tx_dep_provider
+                .apply_tx(external_funding_tx.clone(), tip_block_number)
+                .map_err(|err| TxBuilderError::Other(err.into()))?;
+        }
 
         let (tx, _) = self.build_unlocked(
             &mut cell_collector,
@@ -329,6 +394,62 @@ impl FundingTxBuilder {
     }
 }
 
+/// The plain CKB cells created by the external funding tx for `lock_script`, which are spent by
+/// the funding transaction.
+pub fn external_funding_cells(
+    tx: &TransactionView,
+    lock_script: &Script,
+) -> Vec<(packed::OutPoint, CellOutput)> {
+    tx.outputs()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, output)| &output.lock() == lock_script && output.type_().is_none())
+        .map(|(index, output)| (packed::OutPoint::new(tx.hash(), index as u32), output))
+        .collect()
+}
+
+/// Verify a signed transaction provided by an external wallet to fund a channel.
+///
+/// The transaction must be fully signed, and the plain CKB cells it creates for `lock_script`
+/// must hold at least `required_capacity` shannons.
+pub fn verify_external_funding_tx(
+    tx: &Transaction,
+    lock_script: &Script,
+    required_capacity: u64,
+) -> Result<(), FundingError> {
+    let tx = tx.clone().into_view();
+    if tx.inputs().is_empty() {
+        return Err(FundingError::InvalidExternalFundingTx(
+            "the transaction has no inputs".to_string(),
+        ));
+    }
+    let signed = (0..tx.inputs().len()).all(|i| {
+        tx.witnesses()
+            .get(i)
+            .map(|witness| !witness.is_empty())
+            .unwrap_or(false)
+    });
+    if !signed {
+        return Err(FundingError::InvalidExternalFundingTx(
+            "all the inputs must be signed".to_string(),
+        ));
+    }
+    let mut capacity: u64 = 0;
+    for (_, output) in external_funding_cells(&tx, lock_script) {
+        let output_capacity: u64 = output.capacity().unpack();
+        capacity = capacity.checked_add(output_capacity).ok_or_else(|| {
+            FundingError::InvalidExternalFundingTx("capacity overflow".to_string())
+        })?;
+    }
+    if capacity < required_capacity {
+        return Err(FundingError::InvalidExternalFundingTx(format!(
+            "the transaction only provides {} shannons to the funding lock script, {} required",
+            capacity, required_capacity
+        )));
+    }
+    Ok(())
+}
+
 impl FundingTx {
     pub fn new() -> Self {
         Self::default()
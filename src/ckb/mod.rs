@@ -2,10 +2,10 @@ mod actor;
 mod error;
 mod funding;
 
-pub use actor::{CkbChainActor, CkbChainMessage, TraceTxRequest, TraceTxResponse};
+pub use actor::{ChainTip, CkbChainActor, CkbChainMessage, TraceTxRequest, TraceTxResponse};
 pub use config::{CkbConfig, DEFAULT_CKB_BASE_DIR_NAME};
 pub use error::{CkbChainError, FundingError};
-pub use funding::{FundingRequest, FundingTx};
+pub use funding::{verify_external_funding_tx, FundingRequest, FundingTx};
 
 pub mod config;
 pub mod contracts;
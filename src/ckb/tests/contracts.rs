@@ -1,7 +1,15 @@
-use ckb_types::{core::TransactionView, packed::CellOutput, prelude::Pack};
+use ckb_types::{
+    core::{ScriptHashType, TransactionView},
+    packed::{CellOutput, Script},
+    prelude::Pack,
+};
 use molecule::prelude::{Builder, Entity};
+use std::str::FromStr;
 
-use crate::ckb::contracts::{get_script_by_contract, Contract, ContractsContext, MockContext};
+use crate::ckb::contracts::{
+    get_script_by_contract, is_standard_lock_script, Contract, ContractsContext, MockContext,
+};
+use crate::fiber::types::Hash256;
 
 // This test is to ensure that the same transaction is generated for different mock contexts.
 // If different transactions are generated, then the mock context is not deterministic.
@@ -31,3 +39,38 @@ fn test_same_tx_for_different_mock_context() {
             .build()
     );
 }
+
+#[test]
+fn test_is_standard_lock_script() {
+    assert!(is_standard_lock_script(&get_script_by_contract(
+        Contract::Secp256k1Lock,
+        &[0u8; 20]
+    )));
+    // secp256k1_blake160_multisig_all with and without since
+    let multisig_lock = |args: &[u8]| {
+        Script::new_builder()
+            .code_hash(
+                Hash256::from_str(
+                    "0x5c5069eb0857efc65e1bca0c07df34c31663b3622fd3876c876320fc9634e2a8",
+                )
+                .unwrap()
+                .into(),
+            )
+            .hash_type(ScriptHashType::Type.into())
+            .args(args.pack())
+            .build()
+    };
+    assert!(is_standard_lock_script(&multisig_lock(&[0u8; 20])));
+    assert!(is_standard_lock_script(&multisig_lock(&[0u8; 28])));
+    assert!(!is_standard_lock_script(&multisig_lock(&[0u8; 21])));
+
+    assert!(!is_standard_lock_script(&get_script_by_contract(
+        Contract::Secp256k1Lock,
+        &[0u8; 19]
+    )));
+    assert!(!is_standard_lock_script(&get_script_by_contract(
+        Contract::FundingLock,
+        &[0u8; 20]
+    )));
+    assert!(!is_standard_lock_script(&Script::default()));
+}
@@ -0,0 +1,59 @@
+use ckb_types::{
+    core::TransactionView,
+    packed::{Bytes, CellInput, CellOutput, OutPoint, Script},
+    prelude::{Builder, Entity, Pack},
+};
+
+use crate::ckb::contracts::{get_script_by_contract, Contract};
+use crate::ckb::{verify_external_funding_tx, FundingError};
+
+fn external_funding_tx(lock: &Script, capacities: &[u64], signed: bool) -> TransactionView {
+    let witness: Bytes = if signed {
+        vec![1u8; 85].pack()
+    } else {
+        Bytes::default()
+    };
+    let mut builder = TransactionView::new_advanced_builder()
+        .input(CellInput::new(OutPoint::default(), 0))
+        .witness(witness);
+    for capacity in capacities {
+        builder = builder
+            .output(
+                CellOutput::new_builder()
+                    .capacity(capacity.pack())
+                    .lock(lock.clone())
+                    .build(),
+            )
+            .output_data(Default::default());
+    }
+    builder.build()
+}
+
+#[test]
+fn test_verify_external_funding_tx() {
+    let lock = get_script_by_contract(Contract::Secp256k1Lock, &[1u8; 20]);
+    let other_lock = get_script_by_contract(Contract::Secp256k1Lock, &[2u8; 20]);
+
+    let tx = external_funding_tx(&lock, &[600, 500], true);
+    assert!(verify_external_funding_tx(&tx.data(), &lock, 1000).is_ok());
+    assert!(matches!(
+        verify_external_funding_tx(&tx.data(), &lock, 1200),
+        Err(FundingError::InvalidExternalFundingTx(_))
+    ));
+    assert!(matches!(
+        verify_external_funding_tx(&tx.data(), &other_lock, 1000),
+        Err(FundingError::InvalidExternalFundingTx(_))
+    ));
+
+    let unsigned_tx = external_funding_tx(&lock, &[1100], false);
+    assert!(matches!(
+        verify_external_funding_tx(&unsigned_tx.data(), &lock, 1000),
+        Err(FundingError::InvalidExternalFundingTx(_))
+    ));
+
+    let no_inputs_tx = tx.as_advanced_builder().set_inputs(vec![]).build();
+    assert!(matches!(
+        verify_external_funding_tx(&no_inputs_tx.data(), &lock, 1000),
+        Err(FundingError::InvalidExternalFundingTx(_))
+    ));
+}
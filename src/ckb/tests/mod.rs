@@ -1,5 +1,6 @@
 mod actor;
 mod config;
 mod contracts;
+mod funding;
 
 pub mod test_utils;
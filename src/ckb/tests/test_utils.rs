@@ -4,14 +4,14 @@ use anyhow::anyhow;
 use ckb_jsonrpc_types::TxStatus;
 use ckb_types::{
     core::TransactionView,
-    packed::{CellOutput, OutPoint},
-    prelude::{Builder, Entity, Pack, PackVec, Unpack},
+    packed::{CellInput, CellOutput, OutPoint},
+    prelude::{Builder, Entity, IntoTransactionView, Pack, PackVec, Unpack},
 };
 
 use crate::ckb::{TraceTxRequest, TraceTxResponse};
 
-use crate::ckb::contracts::MockContext;
-use crate::ckb::CkbChainMessage;
+use crate::ckb::contracts::{get_cell_deps_by_contracts, Contract, MockContext};
+use crate::ckb::{CkbChainMessage, FundingError};
 
 use ckb_types::packed::Byte32;
 use ractor::{call_t, Actor, ActorProcessingErr, ActorRef};
@@ -91,6 +91,23 @@ impl Actor for MockChainActor {
             GetCurrentBlockNumber(_, reply) => {
                 let _ = reply.send(Ok(0));
             }
+            GetFeeRateStatistics(_, reply) => {
+                let _ = reply.send(Ok(None));
+            }
+            GetTransactionStatus(tx_hash, reply) => {
+                let status = state
+                    .tx_status
+                    .get(&tx_hash)
+                    .map(|(_, status)| status.clone())
+                    .unwrap_or(ckb_jsonrpc_types::Status::Unknown);
+                let _ = reply.send(Ok(TxStatus {
+                    status,
+                    block_number: Some(Default::default()),
+                    block_hash: None,
+                    tx_index: None,
+                    reason: None,
+                }));
+            }
             Fund(tx, request, reply_port) => {
                 let mut fulfilled_tx = tx.clone();
                 let outputs = fulfilled_tx
@@ -132,11 +149,34 @@ impl Actor for MockChainActor {
                     outputs_data
                 };
 
-                let tx_builder = fulfilled_tx
+                let mut tx_builder = fulfilled_tx
                     .take()
                     .map(|x| x.as_advanced_builder())
                     .unwrap_or_default();
 
+                // The mock chain has no wallet, all the plain CKB cells of the external funding
+                // tx are taken as the funding inputs, which are locked by the default lock.
+                if let Some(external_funding_tx) = request.external_funding_tx.as_ref() {
+                    tx_builder = tx_builder
+                        .cell_deps(get_cell_deps_by_contracts(vec![Contract::Secp256k1Lock]));
+                    let external_funding_tx = external_funding_tx.clone().into_view();
+                    for out_point in external_funding_tx
+                        .output_pts()
+                        .into_iter()
+                        .zip(external_funding_tx.outputs())
+                        .filter(|(_, output)| output.type_().is_none())
+                        .map(|(out_point, _)| out_point)
+                    {
+                        if state.cell_status.get(&out_point) == Some(&CellStatus::Consumed) {
+                            let _ = reply_port.send(Err(FundingError::InvalidExternalFundingTx(
+                                format!("the cell {:?} is not spendable", out_point),
+                            )));
+                            return Ok(());
+                        }
+                        tx_builder = tx_builder.input(CellInput::new(out_point, 0));
+                    }
+                }
+
                 fulfilled_tx
                     .update_for_self(
                         tx_builder
@@ -38,6 +38,8 @@ pub enum Error {
     FundingError(#[from] FundingError),
     #[error("Send payment error: {0}")]
     SendPaymentError(String),
+    #[error("Node not ready: {0}")]
+    NodeNotReady(String),
     #[error("InvalidParameter: {0}")]
     InvalidParameter(String),
     #[error("Network Graph error: {0}")]
@@ -1,15 +1,15 @@
 use bitflags::bitflags;
-use ckb_jsonrpc_types::BlockNumber;
+use ckb_jsonrpc_types::{BlockNumber, JsonBytes};
 use secp256k1::XOnlyPublicKey;
-use tracing::{debug, error, info, trace, warn};
+use tracing::{debug, error, info, info_span, trace, warn, Instrument};
 
 use crate::{
     fiber::{
         fee::calculate_tlc_forward_fee,
-        network::{get_chain_hash, SendOnionPacketCommand},
+        network::{get_chain_hash, RejectedTlc, SendOnionPacketCommand, SendPaymentData},
         types::{ChannelUpdate, TlcErr, TlcErrPacket, TlcErrorCode},
     },
-    invoice::InvoiceStore,
+    invoice::{FeesPaidBy, InvoiceStore},
 };
 use ckb_hash::{blake2b_256, new_blake2b};
 use ckb_sdk::{Since, SinceType};
@@ -32,8 +32,9 @@ use ractor::{
     RpcReplyPort, SpawnErr,
 };
 
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use serde_with::serde_as;
+use serde_with::{serde_as, DisplayFromStr};
 use tentacle::secio::PeerId;
 use thiserror::Error;
 use tokio::sync::oneshot;
@@ -43,20 +44,21 @@ use std::{
     collections::BTreeMap,
     fmt::Debug,
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
     ckb::{
         contracts::{get_cell_deps, get_script_by_contract, Contract},
-        FundingRequest,
+        ChainTip, FundingRequest,
     },
     fiber::{
         config::{DEFAULT_UDT_MINIMAL_CKB_AMOUNT, MIN_OCCUPIED_CAPACITY},
         fee::{calculate_commitment_tx_fee, shutdown_tx_size},
         network::{emit_service_event, sign_network_message},
-        types::{AnnouncementSignatures, Shutdown},
+        types::{AnnouncementSignatures, Shutdown, ShutdownFeeCounter},
     },
+    metrics::metrics,
     NetworkServiceEvent,
 };
 
@@ -66,12 +68,14 @@ use super::{
     hash_algorithm::HashAlgorithm,
     key::blake2b_hash_with_salt,
     network::FiberMessageWithPeerId,
-    serde_utils::EntityHex,
+    serde_utils::{EntityHex, U128Hex, U64Hex},
     types::{
-        AcceptChannel, AddTlc, ChannelAnnouncement, ChannelReady, ClosingSigned, CommitmentSigned,
-        EcdsaSignature, FiberChannelMessage, FiberMessage, Hash256, LockTime, OpenChannel, Privkey,
-        Pubkey, ReestablishChannel, RemoveTlc, RemoveTlcFulfill, RemoveTlcReason, RevokeAndAck,
-        TxCollaborationMsg, TxComplete, TxUpdate,
+        get_onion_packet_version, hash_algorithm_feature, node_features, onion_version_feature,
+        secp256k1_instance, AcceptChannel, AddTlc, ChannelAnnouncement, ChannelReady,
+        ClosingSigned, CommitmentSigned, EcdsaSignature, FiberChannelMessage, FiberMessage,
+        Hash256, LockTime, OpenChannel, Privkey, Pubkey, ReestablishChannel, RemoveTlc,
+        RemoveTlcFulfill, RemoveTlcReason, RevokeAndAck, TxAbort, TxCollaborationMsg, TxComplete,
+        TxUpdate, LEGACY_NODE_FEATURES,
     },
     NetworkActorCommand, NetworkActorEvent, NetworkActorMessage, ASSUME_NETWORK_ACTOR_ALIVE,
 };
@@ -86,6 +90,16 @@ pub const FUNDING_CELL_WITNESS_LEN: usize = 16 + 32 + 64;
 // is funded or not.
 pub const INITIAL_COMMITMENT_NUMBER: u64 = 0;
 
+// The seed of the signer replacing the real one in the redacted channel states.
+const REDACTED_SIGNER_SEED: &[u8] = b"redacted";
+
+// The shortest average block interval of ckb in milliseconds. An epoch lasts about 4 hours
+// and has at most 1800 blocks.
+pub(crate) const MIN_AVERAGE_BLOCK_INTERVAL_MS: u64 = 8_000;
+
+// The interval to retry the delayed tlc removal when the channel is not ready for it.
+const DELAYED_TLC_REMOVAL_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
 // The channel is disabled, and no more tlcs can be added to the channel.
 pub const CHANNEL_DISABLED_FLAG: u32 = 1;
 
@@ -125,6 +139,11 @@ pub enum ChannelCommand {
     RemoveTlc(RemoveTlcCommand, RpcReplyPort<Result<(), String>>),
     Shutdown(ShutdownCommand, RpcReplyPort<Result<(), String>>),
     Update(UpdateCommand, RpcReplyPort<Result<(), String>>),
+    // Re-sign the channel update and gossip it along with the channel announcement again.
+    RebroadcastAnnouncement(RpcReplyPort<Result<(), String>>),
+    // Fail back a received tlc which is forwarded but not resolved by the downstream yet, the
+    // reason is decided by the network actor from the downstream tlc.
+    FailForwardedTlc(RemoveTlcCommand, RpcReplyPort<Result<(), String>>),
 }
 
 #[derive(Debug)]
@@ -154,6 +173,8 @@ pub struct RemoveTlcCommand {
 pub struct ShutdownCommand {
     pub close_script: Script,
     pub fee_rate: FeeRate,
+    // The maximal fee rate we are willing to pay if the remote peer counter-proposes a higher fee rate.
+    pub max_fee_rate: Option<FeeRate>,
     pub force: bool,
 }
 
@@ -164,6 +185,59 @@ pub struct UpdateCommand {
     pub tlc_minimum_value: Option<u128>,
     pub tlc_maximum_value: Option<u128>,
     pub tlc_fee_proportional_millionths: Option<u128>,
+    pub rebalance_policy: Option<RebalancePolicy>,
+    pub dust_tlc_limit: Option<DustTlcLimit>,
+    pub max_htlc_fraction_of_capacity: Option<u64>,
+}
+
+// The policy to keep the local balance of a channel around a target ratio, the network actor
+// periodically sends circular payments out of the channel when its local balance exceeds
+// the target by more than the trigger threshold.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RebalancePolicy {
+    pub enabled: bool,
+    // The target ratio of the local balance to the channel capacity, in percent.
+    #[serde_as(as = "U64Hex")]
+    pub target_local_ratio: u64,
+    // The channel is rebalanced only if its local balance ratio exceeds the target ratio
+    // by more than this threshold, in percent.
+    #[serde_as(as = "U64Hex")]
+    pub trigger_threshold: u64,
+    // The maximal fee we are willing to pay for a single rebalance payment.
+    #[serde_as(as = "U128Hex")]
+    pub max_fee_amount: u128,
+}
+
+impl RebalancePolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.target_local_ratio > 100 {
+            return Err(format!(
+                "target_local_ratio should be at most 100, got {}",
+                self.target_local_ratio
+            ));
+        }
+        if self.trigger_threshold > 100 {
+            return Err(format!(
+                "trigger_threshold should be at most 100, got {}",
+                self.trigger_threshold
+            ));
+        }
+        Ok(())
+    }
+
+    // The amount to be sent out of the channel to bring the local balance back to the target ratio,
+    // returns None if the channel doesn't need to be rebalanced.
+    pub fn rebalance_amount(&self, local_balance: u128, remote_balance: u128) -> Option<u128> {
+        let capacity = local_balance + remote_balance;
+        if !self.enabled || capacity == 0 {
+            return None;
+        }
+        let target = capacity * self.target_local_ratio as u128 / 100;
+        let trigger_ratio = (self.target_local_ratio + self.trigger_threshold).min(100);
+        let trigger = capacity * trigger_ratio as u128 / 100;
+        (local_balance > trigger).then(|| local_balance - target)
+    }
 }
 
 fn get_random_preimage() -> Hash256 {
@@ -208,6 +282,17 @@ pub struct OpenChannelParameter {
     pub funding_fee_rate: Option<u64>,
     pub max_tlc_value_in_flight: Option<u128>,
     pub max_tlc_number_in_flight: Option<u64>,
+    pub max_inbound_tlc_value: Option<u128>,
+    pub max_outbound_tlc_value: Option<u128>,
+    pub external_funding_tx: Option<Transaction>,
+    pub supported_hash_algorithms: Vec<HashAlgorithm>,
+    pub supported_onion_versions: Vec<u8>,
+    /// The feature bits announced by the peer, None if the peer has not announced any.
+    pub remote_features: Option<u64>,
+    /// The timeout in seconds to abort the open if our funding transaction signatures are not sent yet, 0 means never aborting.
+    pub open_timeout_seconds: u64,
+    /// Whether to ask the peer to use the channel before the funding transaction is confirmed.
+    pub zero_conf: bool,
 }
 
 pub struct AcceptChannelParameter {
@@ -218,6 +303,16 @@ pub struct AcceptChannelParameter {
     pub open_channel: OpenChannel,
     pub shutdown_script: Script,
     pub channel_id_sender: Option<oneshot::Sender<Hash256>>,
+    pub max_inbound_tlc_value: Option<u128>,
+    pub max_outbound_tlc_value: Option<u128>,
+    pub supported_hash_algorithms: Vec<HashAlgorithm>,
+    pub supported_onion_versions: Vec<u8>,
+    /// The feature bits announced by the peer, None if the peer has not announced any.
+    pub remote_features: Option<u64>,
+    /// The timeout in seconds to abort the open if our funding transaction signatures are not sent yet, 0 means never aborting.
+    pub open_timeout_seconds: u64,
+    /// Whether to use the channel before the funding transaction is confirmed if the opener asks for it.
+    pub zero_conf: bool,
 }
 
 pub enum ChannelInitializationParameter {
@@ -255,18 +350,49 @@ pub struct ChannelActor<S> {
     network: ActorRef<NetworkActorMessage>,
     store: S,
     subscribers: ChannelSubscribers,
+    // Whether to remove the invoice preimage from the store once the tlc is settled.
+    forget_settled_preimages: bool,
+    // The minimal shutdown fee rate accepted when the remote peer initiates the shutdown.
+    min_shutdown_fee_rate: u64,
+    // The maximal number of the events kept in the event log of the channel, 0 means disabled.
+    event_log_max_size: u64,
+    // Whether to force close the channel if its state diverges from the peer on reestablish.
+    reestablish_force_close_on_divergence: bool,
+    // Whether to audit the channel on each commitment update and halt it on any inconsistency.
+    audit_commitment_updates: bool,
+    // The delay before failing back the received tlcs.
+    tlc_fail_delay: TlcFailDelay,
+    // The random delay before fulfilling the forwarded tlcs.
+    tlc_fulfill_delay: TlcFulfillDelay,
+    // The dust tlc limit of the CKB channels which don't set their own limit.
+    default_dust_tlc_limit: DustTlcLimit,
+    // The delay before gossiping the channel update changed by the update command, in milliseconds.
+    channel_update_broadcast_delay_ms: u64,
+    // The tip block number of the chain, which is recorded in the added tlcs.
+    chain_tip: ChainTip,
 }
 
 impl<S> ChannelActor<S>
 where
     S: InvoiceStore,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         local_pubkey: Pubkey,
         remote_pubkey: Pubkey,
         network: ActorRef<NetworkActorMessage>,
         store: S,
         subscribers: ChannelSubscribers,
+        forget_settled_preimages: bool,
+        min_shutdown_fee_rate: u64,
+        event_log_max_size: u64,
+        reestablish_force_close_on_divergence: bool,
+        audit_commitment_updates: bool,
+        tlc_fail_delay: TlcFailDelay,
+        tlc_fulfill_delay: TlcFulfillDelay,
+        default_dust_tlc_limit: DustTlcLimit,
+        channel_update_broadcast_delay_ms: u64,
+        chain_tip: ChainTip,
     ) -> Self {
         Self {
             local_pubkey,
@@ -274,6 +400,16 @@ where
             network,
             store,
             subscribers,
+            forget_settled_preimages,
+            min_shutdown_fee_rate,
+            event_log_max_size,
+            reestablish_force_close_on_divergence,
+            audit_commitment_updates,
+            tlc_fail_delay,
+            tlc_fulfill_delay,
+            default_dust_tlc_limit,
+            channel_update_broadcast_delay_ms,
+            chain_tip,
         }
     }
 
@@ -297,7 +433,11 @@ where
         if state.reestablishing {
             match message {
                 FiberChannelMessage::ReestablishChannel(ref reestablish_channel) => {
-                    state.handle_reestablish_channel_message(reestablish_channel, &self.network)?;
+                    state.handle_reestablish_channel_message(
+                        reestablish_channel,
+                        &self.network,
+                        self.reestablish_force_close_on_divergence,
+                    )?;
                 }
                 _ => {
                     debug!("Ignoring message while reestablishing: {:?}", message);
@@ -356,6 +496,7 @@ where
                             state.local_reserved_ckb_amount,
                             state.remote_reserved_ckb_amount,
                             state.funding_fee_rate,
+                            state.external_funding_tx.clone(),
                         ),
                     ))
                     .expect(ASSUME_NETWORK_ACTOR_ALIVE);
@@ -497,23 +638,43 @@ where
                         error!("Error handling AddTlc message: {:?}", e);
                         assert!(tlc_count == state.tlcs.len());
                         let error_detail = self.get_tlc_detail_error(state, &e).await;
+                        self.network
+                            .send_message(NetworkActorMessage::new_event(
+                                NetworkActorEvent::TlcRejected(RejectedTlc {
+                                    peer_id: state.get_remote_peer_id(),
+                                    channel_id: state.get_id(),
+                                    tlc_id,
+                                    payment_hash: add_tlc.payment_hash,
+                                    amount: add_tlc.amount,
+                                    error_code: error_detail.error_code,
+                                    reason: e.to_string(),
+                                    timestamp: std::time::UNIX_EPOCH.elapsed().unwrap().as_millis()
+                                        as u64,
+                                }),
+                            ))
+                            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
                         if state.get_received_tlc(tlc_id).is_none() {
-                            self.network
-                                .send_message(NetworkActorMessage::new_command(
-                                    NetworkActorCommand::SendFiberMessage(
-                                        FiberMessageWithPeerId::new(
-                                            state.get_remote_peer_id(),
-                                            FiberMessage::remove_tlc(RemoveTlc {
-                                                channel_id: state.get_id(),
-                                                tlc_id,
-                                                reason: RemoveTlcReason::RemoveTlcFail(
-                                                    TlcErrPacket::new(error_detail),
-                                                ),
-                                            }),
-                                        ),
-                                    ),
-                                ))
-                                .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                            let message = NetworkActorMessage::new_command(
+                                NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
+                                    state.get_remote_peer_id(),
+                                    FiberMessage::remove_tlc(RemoveTlc {
+                                        channel_id: state.get_id(),
+                                        tlc_id,
+                                        reason: RemoveTlcReason::RemoveTlcFail(TlcErrPacket::new(
+                                            error_detail,
+                                        )),
+                                    }),
+                                )),
+                            );
+                            match self.tlc_fail_delay.sample() {
+                                Some(delay) => {
+                                    send_message_after(self.network.clone(), delay, message)
+                                }
+                                None => self
+                                    .network
+                                    .send_message(message)
+                                    .expect(ASSUME_NETWORK_ACTOR_ALIVE),
+                            }
                         }
                         Err(e)
                     }
@@ -544,6 +705,14 @@ where
                 }
                 if let Some((previous_channel_id, previous_tlc)) = tlc_details.tlc.previous_tlc {
                     assert!(previous_tlc.is_received());
+                    if matches!(remove_tlc.reason, RemoveTlcReason::RemoveTlcFulfill(_)) {
+                        metrics().record_forwarded_tlc(tlc_details.tlc.amount);
+                        if let Some(added_at) = tlc_details.added_at {
+                            metrics().record_forwarded_tlc_latency(Duration::from_millis(
+                                now_timestamp_in_millis().saturating_sub(added_at),
+                            ));
+                        }
+                    }
                     info!(
                         "begin to remove tlc from previous channel: {:?}",
                         &previous_tlc
@@ -599,11 +768,20 @@ where
                         )));
                     }
                 };
-                let shutdown_info = ShutdownInfo {
+                let mut shutdown_info = ShutdownInfo {
                     close_script: shutdown.close_script,
                     fee_rate: shutdown.fee_rate.as_u64(),
                     signature: None,
+                    max_fee_rate: None,
                 };
+                if let Some(local_shutdown_info) = state.local_shutdown_info.clone() {
+                    let (local_fee_rate, remote_fee_rate) =
+                        state.negotiate_shutdown_fee_rate(&local_shutdown_info, &shutdown_info)?;
+                    if let Some(info) = state.local_shutdown_info.as_mut() {
+                        info.fee_rate = local_fee_rate;
+                    }
+                    shutdown_info.fee_rate = remote_fee_rate;
+                }
                 state.remote_shutdown_info = Some(shutdown_info);
 
                 let mut flags = flags | ShuttingDownFlags::THEIR_SHUTDOWN_SENT;
@@ -615,17 +793,40 @@ where
                 let should_we_reply_shutdown =
                     matches!(flags, ShuttingDownFlags::THEIR_SHUTDOWN_SENT);
 
-                if state.check_valid_to_auto_accept_shutdown() && should_we_reply_shutdown {
+                // Counter-propose our minimal fee rate if the proposed one is lower than it,
+                // the remote peer should raise its fee rate to ours, and pay the whole fee.
+                let proposed_fee_rate = shutdown.fee_rate.as_u64();
+                let accepted_fee_rate = proposed_fee_rate.max(self.min_shutdown_fee_rate);
+                if should_we_reply_shutdown
+                    && proposed_fee_rate != 0
+                    && state.check_valid_to_auto_accept_shutdown(accepted_fee_rate)
+                {
                     let close_script = state.get_local_shutdown_script();
+                    let message = if accepted_fee_rate > proposed_fee_rate {
+                        debug!(
+                            "Counter-proposing shutdown fee rate {} to the proposed {}",
+                            accepted_fee_rate, proposed_fee_rate
+                        );
+                        if let Some(info) = state.remote_shutdown_info.as_mut() {
+                            info.fee_rate = accepted_fee_rate;
+                        }
+                        FiberMessage::shutdown_fee_counter(ShutdownFeeCounter {
+                            channel_id: state.get_id(),
+                            close_script: close_script.clone(),
+                            fee_rate: FeeRate::from_u64(accepted_fee_rate),
+                        })
+                    } else {
+                        FiberMessage::shutdown(Shutdown {
+                            channel_id: state.get_id(),
+                            close_script: close_script.clone(),
+                            fee_rate: FeeRate::from_u64(0),
+                        })
+                    };
                     self.network
                         .send_message(NetworkActorMessage::new_command(
                             NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
                                 state.get_remote_peer_id(),
-                                FiberMessage::shutdown(Shutdown {
-                                    channel_id: state.get_id(),
-                                    close_script: close_script.clone(),
-                                    fee_rate: FeeRate::from_u64(0),
-                                }),
+                                message,
                             )),
                         ))
                         .expect(ASSUME_NETWORK_ACTOR_ALIVE);
@@ -633,6 +834,7 @@ where
                         close_script,
                         fee_rate: 0,
                         signature: None,
+                        max_fee_rate: None,
                     };
                     state.local_shutdown_info = Some(shutdown_info);
                     flags |= ShuttingDownFlags::OUR_SHUTDOWN_SENT;
@@ -642,6 +844,27 @@ where
                 state.maybe_transition_to_shutdown(&self.network)?;
                 Ok(())
             }
+            FiberChannelMessage::ShutdownFeeCounter(counter) => {
+                let awaiting_reply = matches!(
+                    state.state,
+                    ChannelState::ShuttingDown(flags)
+                        if flags.contains(ShuttingDownFlags::OUR_SHUTDOWN_SENT)
+                            && !flags.contains(ShuttingDownFlags::THEIR_SHUTDOWN_SENT)
+                );
+                if !awaiting_reply || state.local_shutdown_info.is_none() {
+                    return Err(ProcessingChannelError::InvalidState(format!(
+                        "received ShutdownFeeCounter message, but we're not waiting for the reply to our Shutdown, state is currently {:?}",
+                        state.state
+                    )));
+                }
+                state.pending_shutdown_fee_counter = Some(ShutdownInfo {
+                    close_script: counter.close_script,
+                    fee_rate: counter.fee_rate.as_u64(),
+                    signature: None,
+                    max_fee_rate: None,
+                });
+                state.maybe_accept_shutdown_fee_counter(&self.network)
+            }
             FiberChannelMessage::ClosingSigned(closing) => {
                 let ClosingSigned {
                     partial_signature,
@@ -660,7 +883,13 @@ where
                 // We do this to simplify the handling of the message.
                 // We may change this in the future.
                 // We also didn't check the state here.
-                if let Some(shutdown_info) = state.remote_shutdown_info.as_mut() {
+                // The remote peer may sign the closing transaction before we accept its
+                // counter-proposed fee rate, keep the signature along with the counter-proposal.
+                if let Some(shutdown_info) = state
+                    .remote_shutdown_info
+                    .as_mut()
+                    .or(state.pending_shutdown_fee_counter.as_mut())
+                {
                     shutdown_info.signature = Some(partial_signature);
                 }
 
@@ -668,12 +897,30 @@ where
                 Ok(())
             }
             FiberChannelMessage::ReestablishChannel(ref reestablish_channel) => {
-                state.handle_reestablish_channel_message(reestablish_channel, &self.network)?;
+                state.handle_reestablish_channel_message(
+                    reestablish_channel,
+                    &self.network,
+                    self.reestablish_force_close_on_divergence,
+                )?;
+                Ok(())
+            }
+            FiberChannelMessage::TxAbort(tx_abort) => {
+                if !state.can_abort_open() {
+                    warn!(
+                        "Ignoring tx_abort of channel {:?} in state {:?}",
+                        state.get_id(),
+                        &state.state
+                    );
+                    return Ok(());
+                }
+                let reason = format!(
+                    "Channel open is aborted by the peer: {}",
+                    String::from_utf8_lossy(&tx_abort.message)
+                );
+                state.abort_open(&reason, &self.network);
                 Ok(())
             }
-            FiberChannelMessage::TxAbort(_)
-            | FiberChannelMessage::TxInitRBF(_)
-            | FiberChannelMessage::TxAckRBF(_) => {
+            FiberChannelMessage::TxInitRBF(_) | FiberChannelMessage::TxAckRBF(_) => {
                 warn!("Received unsupported message: {:?}", &message);
                 Ok(())
             }
@@ -688,6 +935,7 @@ where
         let error_code = match error {
             ProcessingChannelError::PeelingOnionPacketError(_) => TlcErrorCode::InvalidOnionPayload,
             ProcessingChannelError::TlcForwardFeeIsTooLow => TlcErrorCode::FeeInsufficient,
+            ProcessingChannelError::TlcExpiryTooSoon => TlcErrorCode::ExpiryTooSoon,
             ProcessingChannelError::FinalIncorrectPreimage
             | ProcessingChannelError::FinalIncorrectPaymentHash => {
                 TlcErrorCode::IncorrectOrUnknownPaymentDetails
@@ -696,8 +944,18 @@ where
                 TlcErrorCode::FinalIncorrectHtlcAmount
             }
             ProcessingChannelError::TlcAmountIsTooLow => TlcErrorCode::AmountBelowMinimum,
+            ProcessingChannelError::UnsupportedHashAlgorithm(_) => {
+                TlcErrorCode::RequiredChannelFeatureMissing
+            }
+            ProcessingChannelError::UnsupportedOnionVersion(_) => TlcErrorCode::InvalidOnionVersion,
+            ProcessingChannelError::TrampolineForwardFailed(_) => {
+                TlcErrorCode::TemporaryNodeFailure
+            }
             ProcessingChannelError::TlcNumberExceedLimit
-            | ProcessingChannelError::TlcValueInflightExceedLimit => {
+            | ProcessingChannelError::TlcAmountExceedLimit
+            | ProcessingChannelError::TlcValueInflightExceedLimit
+            | ProcessingChannelError::DustTlcExposureExceedLimit
+            | ProcessingChannelError::TlcAmountExceedCapacityFraction(_) => {
                 TlcErrorCode::TemporaryChannelFailure
             }
             ProcessingChannelError::InvalidState(_) => match state.state {
@@ -751,7 +1009,7 @@ where
             let preimage = if let Some(preimage) = tlc.payment_preimage {
                 preimage
             } else if let Some(preimage) = self.store.get_invoice_preimage(&tlc.payment_hash) {
-                preimage
+                *preimage
             } else {
                 error!(
                     "No preimage found for payment hash: {:?}",
@@ -767,6 +1025,19 @@ where
             };
             let result = self.handle_remove_tlc_command(state, command);
             info!("try to settle down tlc: {:?} result: {:?}", &tlc, &result);
+            if result.is_ok() {
+                metrics().record_payment_received();
+                // Keep the time of the first settlement, the payment may be settled again with
+                // the same preimage.
+                if self
+                    .store
+                    .get_invoice_settled_at(&tlc.payment_hash)
+                    .is_none()
+                {
+                    self.store
+                        .insert_invoice_settled_at(&tlc.payment_hash, now_timestamp_in_millis());
+                }
+            }
             // we only handle one tlc at a time.
             break;
         }
@@ -778,6 +1049,10 @@ where
         add_tlc: AddTlc,
     ) -> Result<(TLCId, Option<Vec<u8>>), ProcessingChannelError> {
         state.check_for_tlc_update(Some(add_tlc.amount))?;
+        state.check_dust_tlc_exposure(add_tlc.amount, self.default_dust_tlc_limit)?;
+        state.check_tlc_fraction_of_capacity(add_tlc.amount)?;
+        state.check_tlc_hash_algorithm(add_tlc.hash_algorithm)?;
+        state.check_tlc_onion_version(&add_tlc.onion_packet)?;
 
         // check the onion_packet is valid or not, if not, we should return an error.
         // If there is a next hop, we should send the AddTlc message to the next hop.
@@ -820,20 +1095,96 @@ where
                     return Err(ProcessingChannelError::FinalIncorrectHTLCAmount);
                 }
 
-                // if this is the last hop, store the preimage.
-                // though we will RemoveTlcFulfill the TLC in try_to_settle_down_tlc function,
-                // here we can do error check early here for better error handling.
-                preimage = peeled_packet
-                    .current
-                    .preimage
-                    .or_else(|| self.store.get_invoice_preimage(&add_tlc.payment_hash));
-                if let Some(preimage) = preimage {
-                    let filled_payment_hash: Hash256 = add_tlc.hash_algorithm.hash(preimage).into();
-                    if add_tlc.payment_hash != filled_payment_hash {
-                        return Err(ProcessingChannelError::FinalIncorrectPreimage);
-                    }
+                if let Some(trampoline) = peeled_packet.current.trampoline.clone() {
+                    // We are the trampoline node, complete the route to the target with the received
+                    // amount minus our forwarding fee as the fee budget, and the received locktime
+                    // minus our expiry delta as the expiry budget.
+                    let Some(max_expiry) = u64::from(add_tlc.expiry)
+                        .checked_sub(state.get_our_locktime_expiry_delta().unwrap_or_default())
+                    else {
+                        error!(
+                            "too soon trampoline expiry: {:?}, expected delta: {:?}",
+                            add_tlc.expiry,
+                            state.get_our_locktime_expiry_delta()
+                        );
+                        return Err(ProcessingChannelError::TlcExpiryTooSoon);
+                    };
+                    let fee_rate: u128 = state
+                        .public_channel_info
+                        .as_ref()
+                        .and_then(|info| info.tlc_fee_proportional_millionths)
+                        .unwrap_or_default();
+                    let expected_fee = calculate_tlc_forward_fee(trampoline.amount, fee_rate);
+                    let Some(max_fee_amount) = received_amount
+                        .checked_sub(trampoline.amount)
+                        .and_then(|fee| fee.checked_sub(expected_fee))
+                    else {
+                        error!(
+                            "too low trampoline fee, received_amount: {}, target amount: {}, expected_fee: {}",
+                            received_amount, trampoline.amount, expected_fee
+                        );
+                        return Err(ProcessingChannelError::TlcForwardFeeIsTooLow);
+                    };
+                    let payment_data = SendPaymentData {
+                        target_pubkey: trampoline.target_pubkey,
+                        amount: trampoline.amount,
+                        payment_hash: add_tlc.payment_hash,
+                        invoice: None,
+                        final_cltv_delta: None,
+                        timeout: None,
+                        max_fee_amount: Some(max_fee_amount),
+                        max_parts: None,
+                        keysend: trampoline.preimage.is_some(),
+                        udt_type_script: state.funding_udt_type_script.clone(),
+                        preimage: trampoline.preimage,
+                        allow_self_payment: false,
+                        outgoing_channel: None,
+                        probe: false,
+                        trampoline_node: None,
+                        keep_reserve: None,
+                        // The fee budget is already approved by the payer.
+                        max_fee_percent: None,
+                        max_fee_rate_ppm: None,
+                        fees_paid_by: FeesPaidBy::Sender,
+                        first_hop_limits: None,
+                    };
+                    let packet = call!(self.network, |tx| NetworkActorMessage::Command(
+                        NetworkActorCommand::BuildTrampolineOnionPacket(
+                            payment_data,
+                            add_tlc.hash_algorithm,
+                            max_expiry,
+                            tx
+                        )
+                    ))
+                    .expect(ASSUME_NETWORK_ACTOR_ALIVE)
+                    .map_err(ProcessingChannelError::TrampolineForwardFailed)?;
+                    peeled_packet_bytes = Some(packet);
                 } else {
-                    return Err(ProcessingChannelError::FinalIncorrectPaymentHash);
+                    if let Some(invoice) = self.store.get_invoice(&add_tlc.payment_hash) {
+                        if !invoice.is_amount_acceptable(add_tlc.amount) {
+                            return Err(ProcessingChannelError::FinalIncorrectHTLCAmount);
+                        }
+                    }
+                    // if this is the last hop, store the preimage carried by the onion packet.
+                    // though we will RemoveTlcFulfill the TLC in try_to_settle_down_tlc function,
+                    // here we can do error check early here for better error handling.
+                    // The preimage of our invoice is not copied into the tlc, it's read from
+                    // the store again when the tlc is fulfilled.
+                    preimage = peeled_packet.current.preimage;
+                    let filled_payment_hash: Option<Hash256> = match preimage {
+                        Some(preimage) => Some(add_tlc.hash_algorithm.hash(preimage).into()),
+                        None => self
+                            .store
+                            .get_invoice_preimage(&add_tlc.payment_hash)
+                            .map(|preimage| add_tlc.hash_algorithm.hash(&*preimage).into()),
+                    };
+                    match filled_payment_hash {
+                        Some(hash) if hash != add_tlc.payment_hash => {
+                            return Err(ProcessingChannelError::FinalIncorrectPreimage);
+                        }
+                        Some(_) => {}
+                        None => return Err(ProcessingChannelError::FinalIncorrectPaymentHash),
+                    }
                 }
             } else {
                 peeled_packet_bytes = Some(peeled_packet.serialize());
@@ -858,6 +1209,7 @@ where
 
         let tlc = state.create_inbounding_tlc(add_tlc.clone(), preimage)?;
         state.insert_tlc(tlc.clone())?;
+        state.set_tlc_added_at_block(tlc.id, self.chain_tip.get());
         if let Some(ref udt_type_script) = state.funding_udt_type_script {
             self.subscribers
                 .pending_received_tlcs_subscribers
@@ -899,6 +1251,9 @@ where
             Ok(tlc_id) => Ok(tlc_id),
             Err(e) => Err(e),
         };
+        if res.is_ok() {
+            state.mark_tlc_forwarded(TLCId::Received(added_tlc_id));
+        }
         // If we failed to forward the onion packet, we should remove the tlc.
         if let Err(res) = res {
             error!("Error forwarding onion packet: {:?}", res);
@@ -991,6 +1346,7 @@ where
             funding_tx_partial_signature,
             commitment_tx_partial_signature,
         } = state.build_and_sign_commitment_tx()?;
+        state.latest_remote_commitment_transaction = Some(commitment_tx.data());
 
         debug!(
             "Sending next local nonce {:?} (previous nonce {:?})",
@@ -1047,8 +1403,22 @@ where
     ) -> Result<u64, ProcessingChannelError> {
         debug!("handle add tlc command : {:?}", &command);
         state.check_for_tlc_update(Some(command.amount))?;
+        state.check_dust_tlc_exposure(command.amount, self.default_dust_tlc_limit)?;
+        state.check_tlc_fraction_of_capacity(command.amount)?;
+        state.check_tlc_hash_algorithm(command.hash_algorithm)?;
+        state.check_tlc_onion_version(&command.onion_packet)?;
+        if let (Some(preimage), Some(payment_hash)) = (command.preimage, command.payment_hash) {
+            let filled_payment_hash: Hash256 = command.hash_algorithm.hash(preimage).into();
+            if filled_payment_hash != payment_hash {
+                return Err(ProcessingChannelError::InvalidParameter(format!(
+                    "Preimage doesn't match the payment hash {:?} with hash algorithm {:?}",
+                    payment_hash, command.hash_algorithm
+                )));
+            }
+        }
         let tlc = state.create_outbounding_tlc(command);
         state.insert_tlc(tlc.clone())?;
+        state.set_tlc_added_at_block(tlc.id, self.chain_tip.get());
 
         debug!("Inserted tlc into channel state: {:?}", &tlc);
         // TODO: Note that since message sending is async,
@@ -1114,6 +1484,124 @@ where
         Ok(())
     }
 
+    // Fail the received tlc after the delay, so that the prober can't tell the reason of the
+    // failure from the time it takes. The forwarded tlc is fulfilled after a random delay, so
+    // that the time it takes doesn't reveal the length of the remaining route. The delayed
+    // removal is saved in the channel state to survive restarts, and the command is checked
+    // again when it's processed.
+    fn delay_remove_tlc_command(
+        &self,
+        myself: &ActorRef<ChannelActorMessage>,
+        state: &mut ChannelActorState,
+        command: RemoveTlcCommand,
+    ) -> ProcessingChannelResult {
+        if state.delayed_tlc_removals.contains_key(&command.id) {
+            return Err(ProcessingChannelError::InvalidState(format!(
+                "Received tlc {} is already being removed",
+                command.id
+            )));
+        }
+        let delay = match command.reason {
+            RemoveTlcReason::RemoveTlcFail(_) if state.get_received_tlc(command.id).is_some() => {
+                self.tlc_fail_delay.sample()
+            }
+            // The tlcs of our own invoices are settled without this command, hence never delayed.
+            RemoveTlcReason::RemoveTlcFulfill(_) => state
+                .get_received_tlc(command.id)
+                .filter(|tlc| tlc.forwarded_at.is_some())
+                .and_then(|tlc| tlc.expires_at_block())
+                .zip(self.chain_tip.get())
+                .and_then(|(expires_at, tip)| {
+                    self.tlc_fulfill_delay
+                        .sample(expires_at.saturating_sub(tip))
+                }),
+            _ => None,
+        };
+        match delay {
+            Some(delay) => {
+                debug!(
+                    "Removing tlc {} of channel {:?} after {:?}",
+                    command.id,
+                    state.get_id(),
+                    delay
+                );
+                state.delayed_tlc_removals.insert(
+                    command.id,
+                    DelayedTlcRemoval {
+                        reason: command.reason,
+                        due_at: now_timestamp_in_millis().saturating_add(delay.as_millis() as u64),
+                    },
+                );
+                send_message_after(
+                    myself.clone(),
+                    delay,
+                    ChannelActorMessage::Event(ChannelEvent::DelayedRemoveTlc(command.id)),
+                );
+                Ok(())
+            }
+            None => self.handle_remove_tlc_command(state, command),
+        }
+    }
+
+    fn schedule_delayed_tlc_removals(
+        &self,
+        myself: &ActorRef<ChannelActorMessage>,
+        state: &ChannelActorState,
+    ) {
+        let now = now_timestamp_in_millis();
+        for (tlc_id, removal) in &state.delayed_tlc_removals {
+            send_message_after(
+                myself.clone(),
+                Duration::from_millis(removal.due_at.saturating_sub(now)),
+                ChannelActorMessage::Event(ChannelEvent::DelayedRemoveTlc(*tlc_id)),
+            );
+        }
+    }
+
+    // Remove the tlc whose delay is over. The removal is retried while the channel is not ready
+    // for tlc updates, e.g. it's still being reestablished after the node restarts.
+    fn handle_delayed_remove_tlc(
+        &self,
+        myself: &ActorRef<ChannelActorMessage>,
+        state: &mut ChannelActorState,
+        tlc_id: u64,
+    ) -> ProcessingChannelResult {
+        let Some(removal) = state.delayed_tlc_removals.get(&tlc_id).cloned() else {
+            return Ok(());
+        };
+        let command = RemoveTlcCommand {
+            id: tlc_id,
+            reason: removal.reason,
+        };
+        let result = if state.reestablishing {
+            Err(ProcessingChannelError::InvalidState(
+                "Channel is being reestablished".to_string(),
+            ))
+        } else {
+            self.handle_remove_tlc_command(state, command)
+        };
+        match result {
+            Err(err) if state.is_received_tlc_pending(tlc_id) => {
+                warn!(
+                    "Failed to remove the delayed tlc {} of channel {:?}, retrying: {:?}",
+                    tlc_id,
+                    state.get_id(),
+                    err
+                );
+                send_message_after(
+                    myself.clone(),
+                    DELAYED_TLC_REMOVAL_RETRY_INTERVAL,
+                    ChannelActorMessage::Event(ChannelEvent::DelayedRemoveTlc(tlc_id)),
+                );
+                Ok(())
+            }
+            result => {
+                state.delayed_tlc_removals.remove(&tlc_id);
+                result
+            }
+        }
+    }
+
     pub fn handle_shutdown_command(
         &self,
         state: &mut ChannelActorState,
@@ -1127,6 +1615,24 @@ where
                 debug!("Handling shutdown command in ChannelReady state");
                 ShuttingDownFlags::empty()
             }
+            // Re-issuing the shutdown command is how the user accepts a counter-proposed
+            // fee rate that was rejected by the previous command.
+            ChannelState::ShuttingDown(_) if state.pending_shutdown_fee_counter.is_some() => {
+                let local_shutdown_info = state
+                    .local_shutdown_info
+                    .as_mut()
+                    .expect("local shutdown info exists");
+                if local_shutdown_info.close_script != command.close_script {
+                    return Err(ProcessingChannelError::InvalidParameter(
+                        "The close script can't be changed after the Shutdown message is sent"
+                            .to_string(),
+                    ));
+                }
+                local_shutdown_info.fee_rate = command.fee_rate.as_u64();
+                local_shutdown_info.max_fee_rate =
+                    command.max_fee_rate.map(|fee_rate| fee_rate.as_u64());
+                return state.maybe_accept_shutdown_fee_counter(&self.network);
+            }
             _ => {
                 debug!("Handling shutdown command in state {:?}", &state.state);
                 return Err(ProcessingChannelError::InvalidState(format!(
@@ -1154,6 +1660,7 @@ where
             close_script: command.close_script,
             fee_rate: command.fee_rate.as_u64(),
             signature: None,
+            max_fee_rate: command.max_fee_rate.map(|fee_rate| fee_rate.as_u64()),
         };
         state.local_shutdown_info = Some(shutdown_info);
         state.update_state(ChannelState::ShuttingDown(
@@ -1169,6 +1676,7 @@ where
 
     pub async fn handle_update_command(
         &self,
+        myself: &ActorRef<ChannelActorMessage>,
         state: &mut ChannelActorState,
         command: UpdateCommand,
     ) -> ProcessingChannelResult {
@@ -1184,8 +1692,33 @@ where
             tlc_minimum_value,
             tlc_maximum_value,
             tlc_fee_proportional_millionths,
+            rebalance_policy,
+            dust_tlc_limit,
+            max_htlc_fraction_of_capacity,
         } = command;
 
+        // The rebalance policy is only used locally, so there is no need to broadcast it.
+        if let Some(policy) = rebalance_policy {
+            policy
+                .validate()
+                .map_err(ProcessingChannelError::InvalidParameter)?;
+            state.rebalance_policy = Some(policy);
+        }
+
+        // So are the dust tlc limit and the max tlc fraction of the capacity.
+        if let Some(limit) = dust_tlc_limit {
+            state.dust_tlc_limit = Some(limit);
+        }
+        if let Some(fraction) = max_htlc_fraction_of_capacity {
+            if fraction == 0 || fraction > 1_000_000 {
+                return Err(ProcessingChannelError::InvalidParameter(format!(
+                    "The max tlc fraction of the capacity {} must be in the range of 1 to 1000000",
+                    fraction
+                )));
+            }
+            state.max_htlc_fraction_of_capacity = Some(fraction);
+        }
+
         let mut updated = false;
 
         if let Some(enabled) = enabled {
@@ -1209,14 +1742,91 @@ where
         }
 
         if updated {
-            state
-                .generate_and_broadcast_channel_update(&self.network)
-                .await;
+            if self.channel_update_broadcast_delay_ms == 0 {
+                state
+                    .generate_and_broadcast_channel_update(&self.network)
+                    .await;
+            } else if !state.channel_update_broadcast_pending {
+                // The channel update is generated when the delay is over, so the updates in
+                // between are coalesced into the gossiped one.
+                state.channel_update_broadcast_pending = true;
+                self.schedule_channel_update_broadcast(myself);
+            }
         }
 
         Ok(())
     }
 
+    fn schedule_channel_update_broadcast(&self, myself: &ActorRef<ChannelActorMessage>) {
+        send_message_after(
+            myself.clone(),
+            Duration::from_millis(self.channel_update_broadcast_delay_ms),
+            ChannelActorMessage::Event(ChannelEvent::BroadcastChannelUpdate),
+        );
+    }
+
+    // Fail back the forwarded tlc manually, e.g. when the downstream peer is compromised. The
+    // network actor has checked that the downstream tlc can't be fulfilled anymore.
+    pub fn handle_fail_forwarded_tlc_command(
+        &self,
+        state: &mut ChannelActorState,
+        command: RemoveTlcCommand,
+    ) -> ProcessingChannelResult {
+        state.check_forwarded_tlc_unresolved(command.id)?;
+        warn!(
+            "Failing back forwarded tlc {} of channel {:?} manually",
+            command.id,
+            state.get_id()
+        );
+        self.handle_remove_tlc_command(state, command)
+    }
+
+    pub async fn handle_rebroadcast_announcement_command(
+        &self,
+        state: &mut ChannelActorState,
+    ) -> ProcessingChannelResult {
+        if !state.is_public() {
+            return Err(ProcessingChannelError::InvalidState(
+                "Only public channel can be announced".to_string(),
+            ));
+        }
+        if !matches!(state.state, ChannelState::ChannelReady()) {
+            return Err(ProcessingChannelError::InvalidState(format!(
+                "Channel announcement can only be rebroadcasted in state ChannelReady, but got state {:?}",
+                &state.state
+            )));
+        }
+        // The channel announcement is signed by both parties, so we can only gossip the
+        // one we have completed, while the channel update is ours to sign again.
+        let channel_announcement = match state
+            .public_channel_info
+            .as_ref()
+            .and_then(|info| info.channel_announcement.clone())
+        {
+            Some(channel_announcement) if channel_announcement.is_signed() => channel_announcement,
+            _ => {
+                return Err(ProcessingChannelError::InvalidState(
+                    "Channel announcement is not signed by both parties yet".to_string(),
+                ))
+            }
+        };
+        let channel_update = state.generate_channel_update(&self.network).await;
+
+        debug!(
+            "Rebroadcasting channel announcement and update of channel {:?}",
+            state.get_id()
+        );
+        self.network
+            .send_message(NetworkActorMessage::new_command(
+                NetworkActorCommand::RebroadcastChannelMessages(
+                    channel_announcement,
+                    channel_update,
+                ),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+        Ok(())
+    }
+
     // This is the dual of `handle_tx_collaboration_msg`. Any logic error here is likely
     // to present in the other function as well.
     pub fn handle_tx_collaboration_command(
@@ -1320,6 +1930,7 @@ where
 
     pub async fn handle_command(
         &self,
+        myself: &ActorRef<ChannelActorMessage>,
         state: &mut ChannelActorState,
         command: ChannelCommand,
     ) -> Result<(), ProcessingChannelError> {
@@ -1342,7 +1953,7 @@ where
                 }
             }
             ChannelCommand::RemoveTlc(command, reply) => {
-                match self.handle_remove_tlc_command(state, command) {
+                match self.delay_remove_tlc_command(myself, state, command) {
                     Ok(_) => {
                         let _ = reply.send(Ok(()));
                         Ok(())
@@ -1368,7 +1979,7 @@ where
                 }
             }
             ChannelCommand::Update(command, reply) => {
-                match self.handle_update_command(state, command).await {
+                match self.handle_update_command(myself, state, command).await {
                     Ok(_) => {
                         debug!("Update command processed successfully");
                         let _ = reply.send(Ok(()));
@@ -1381,6 +1992,35 @@ where
                     }
                 }
             }
+            ChannelCommand::FailForwardedTlc(command, reply) => {
+                match self.handle_fail_forwarded_tlc_command(state, command) {
+                    Ok(_) => {
+                        let _ = reply.send(Ok(()));
+                        Ok(())
+                    }
+                    Err(err) => {
+                        debug!("Error processing fail forwarded tlc command: {:?}", &err);
+                        let _ = reply.send(Err(err.to_string()));
+                        Err(err)
+                    }
+                }
+            }
+            ChannelCommand::RebroadcastAnnouncement(reply) => {
+                match self.handle_rebroadcast_announcement_command(state).await {
+                    Ok(_) => {
+                        let _ = reply.send(Ok(()));
+                        Ok(())
+                    }
+                    Err(err) => {
+                        debug!(
+                            "Error processing rebroadcast announcement command: {:?}",
+                            &err
+                        );
+                        let _ = reply.send(Err(err.to_string()));
+                        Err(err)
+                    }
+                }
+            }
         }
     }
 
@@ -1391,9 +2031,50 @@ where
         event: ChannelEvent,
     ) -> Result<(), ProcessingChannelError> {
         match event {
-            ChannelEvent::FundingTransactionConfirmed(block_number, tx_index) => {
+            ChannelEvent::FundingTransactionConfirmed(block_number, tx_index, trusted) => {
                 debug!("Funding transaction confirmed");
                 let flags = match state.state {
+                    ChannelState::AwaitingChannelReady(flags)
+                        if flags.contains(AwaitingChannelReadyFlags::FUNDING_REORGED) =>
+                    {
+                        // Both parties have already exchanged ChannelReady messages,
+                        // so we can resume the channel directly.
+                        info!(
+                            "Funding transaction of channel {:?} is confirmed again at block {}",
+                            state.get_id(),
+                            block_number
+                        );
+                        state.set_funding_tx_confirmed_at(block_number, tx_index, trusted);
+                        state.update_state(ChannelState::ChannelReady());
+                        self.network
+                            .send_message(NetworkActorMessage::new_event(
+                                NetworkActorEvent::ChannelReady(
+                                    state.get_id(),
+                                    state.get_remote_peer_id(),
+                                    state.get_funding_transaction_outpoint(),
+                                ),
+                            ))
+                            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                        return Ok(());
+                    }
+                    ChannelState::AwaitingChannelReady(flags)
+                        if flags.contains(AwaitingChannelReadyFlags::OUR_CHANNEL_READY) =>
+                    {
+                        // We have sent ChannelReady before the confirmation for a zero-conf channel.
+                        state.set_funding_tx_confirmed_at(block_number, tx_index, trusted);
+                        return Ok(());
+                    }
+                    ChannelState::ChannelReady() | ChannelState::ShuttingDown(_)
+                        if state.zero_conf && state.funding_tx_confirmed_at.is_none() =>
+                    {
+                        info!(
+                            "Funding transaction of zero-conf channel {:?} is confirmed at block {}",
+                            state.get_id(),
+                            block_number
+                        );
+                        state.set_funding_tx_confirmed_at(block_number, tx_index, trusted);
+                        return Ok(());
+                    }
                     ChannelState::AwaitingChannelReady(flags) => flags,
                     ChannelState::AwaitingTxSignatures(f)
                         if f.contains(AwaitingTxSignaturesFlags::TX_SIGNATURES_SENT) =>
@@ -1405,7 +2086,45 @@ where
                             "Expecting funding transaction confirmed event in state AwaitingChannelReady or after TX_SIGNATURES_SENT, but got state {:?}", &state.state)));
                     }
                 };
-                state.funding_tx_confirmed_at = Some((block_number, tx_index));
+                state.set_funding_tx_confirmed_at(block_number, tx_index, trusted);
+                self.network
+                    .send_message(NetworkActorMessage::new_command(
+                        NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
+                            state.get_remote_peer_id(),
+                            FiberMessage::channel_ready(ChannelReady {
+                                channel_id: state.get_id(),
+                            }),
+                        )),
+                    ))
+                    .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                let flags = flags | AwaitingChannelReadyFlags::OUR_CHANNEL_READY;
+                state.update_state(ChannelState::AwaitingChannelReady(flags));
+                state.maybe_channel_is_ready(&self.network).await;
+            }
+            ChannelEvent::FundingTransactionPending => {
+                if !state.zero_conf {
+                    return Ok(());
+                }
+                let flags = match state.state {
+                    ChannelState::AwaitingChannelReady(flags)
+                        if !flags.intersects(
+                            AwaitingChannelReadyFlags::OUR_CHANNEL_READY
+                                | AwaitingChannelReadyFlags::FUNDING_REORGED,
+                        ) =>
+                    {
+                        flags
+                    }
+                    ChannelState::AwaitingTxSignatures(f)
+                        if f.contains(AwaitingTxSignaturesFlags::TX_SIGNATURES_SENT) =>
+                    {
+                        AwaitingChannelReadyFlags::empty()
+                    }
+                    _ => return Ok(()),
+                };
+                debug!(
+                    "Sending ChannelReady for zero-conf channel {:?} before the funding transaction is confirmed",
+                    state.get_id()
+                );
                 self.network
                     .send_message(NetworkActorMessage::new_command(
                         NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
@@ -1420,7 +2139,31 @@ where
                 state.update_state(ChannelState::AwaitingChannelReady(flags));
                 state.maybe_channel_is_ready(&self.network).await;
             }
-            ChannelEvent::CommitmentTransactionConfirmed => {
+            ChannelEvent::FundingTransactionFullyConfirmed => {
+                if state.trusted_funding_pending {
+                    info!(
+                        "Funding transaction of channel {:?} with a trusted peer is fully confirmed",
+                        state.get_id()
+                    );
+                    state.trusted_funding_pending = false;
+                }
+            }
+            ChannelEvent::FundingTransactionReorged => {
+                if !matches!(state.state, ChannelState::ChannelReady()) {
+                    return Err(ProcessingChannelError::InvalidState(format!(
+                        "Expecting funding transaction reorged event in state ChannelReady, but got state {:?}", &state.state)));
+                }
+                warn!(
+                    "Funding transaction of channel {:?} is no longer confirmed at block {:?}",
+                    state.get_id(),
+                    state.funding_tx_confirmed_at
+                );
+                state.funding_tx_confirmed_at = None;
+                state.update_state(ChannelState::AwaitingChannelReady(
+                    AwaitingChannelReadyFlags::FUNDING_REORGED,
+                ));
+            }
+            ChannelEvent::CommitmentTransactionConfirmed(tx_hash) => {
                 match state.state {
                     ChannelState::ShuttingDown(flags)
                         if flags.contains(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION) => {}
@@ -1430,27 +2173,64 @@ where
                         ));
                     }
                 };
+                state.closing_tx_hash = Some(tx_hash);
                 state.update_state(ChannelState::Closed(CloseFlags::UNCOOPERATIVE));
                 debug!("Channel closed with uncooperative close");
             }
             ChannelEvent::PeerDisconnected => {
+                state
+                    .connection_stats
+                    .on_peer_disconnected(now_timestamp_in_millis());
                 myself.stop(Some("PeerDisconnected".to_string()));
             }
             ChannelEvent::ClosingTransactionConfirmed => {
                 // Broadcast the channel update message which disables the channel.
-                let update = state.generate_disabled_channel_update(&self.network).await;
+                // Private channels are never gossiped, so there is nothing to disable.
+                if state.is_public() {
+                    let update = state.generate_disabled_channel_update(&self.network).await;
 
-                self.network
-                    .send_message(NetworkActorMessage::new_command(
-                        NetworkActorCommand::ProccessChannelUpdate(
-                            self.get_remote_peer_id(),
-                            update,
-                        ),
-                    ))
-                    .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                    self.network
+                        .send_message(NetworkActorMessage::new_command(
+                            NetworkActorCommand::ProccessChannelUpdate(
+                                self.get_remote_peer_id(),
+                                update,
+                            ),
+                        ))
+                        .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                }
 
                 myself.stop(Some("ChannelClosed".to_string()));
             }
+            ChannelEvent::DelayedRemoveTlc(tlc_id) => {
+                self.handle_delayed_remove_tlc(myself, state, tlc_id)?;
+            }
+            ChannelEvent::BroadcastChannelUpdate => {
+                if state.channel_update_broadcast_pending {
+                    state.channel_update_broadcast_pending = false;
+                    if state.is_public() {
+                        state
+                            .generate_and_broadcast_channel_update(&self.network)
+                            .await;
+                    }
+                }
+            }
+            ChannelEvent::OpenTimeout => {
+                if state.can_abort_open() {
+                    let reason = "Channel open is not completed within the open timeout";
+                    self.network
+                        .send_message(NetworkActorMessage::new_command(
+                            NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
+                                self.get_remote_peer_id(),
+                                FiberMessage::tx_abort(TxAbort {
+                                    channel_id: state.get_id(),
+                                    message: reason.as_bytes().to_vec(),
+                                }),
+                            )),
+                        ))
+                        .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                    state.abort_open(reason, &self.network);
+                }
+            }
         }
         Ok(())
     }
@@ -1488,7 +2268,7 @@ where
 
     async fn pre_start(
         &self,
-        _myself: ActorRef<Self::Msg>,
+        myself: ActorRef<Self::Msg>,
         args: Self::Arguments,
     ) -> Result<Self::State, ActorProcessingErr> {
         // startup the event processing
@@ -1501,6 +2281,13 @@ where
                 seed,
                 open_channel,
                 channel_id_sender,
+                max_inbound_tlc_value,
+                max_outbound_tlc_value,
+                supported_hash_algorithms,
+                supported_onion_versions,
+                remote_features,
+                open_timeout_seconds,
+                zero_conf,
             }) => {
                 let peer_id = self.get_remote_peer_id();
                 debug!(
@@ -1510,6 +2297,7 @@ where
 
                 let counterpart_pubkeys = (&open_channel).into();
                 let public = open_channel.is_public();
+                let zero_conf = zero_conf && open_channel.is_zero_conf() && !public;
                 let OpenChannel {
                     channel_id,
                     chain_hash,
@@ -1546,6 +2334,12 @@ where
                     )));
                 }
 
+                let (hash_algorithms, onion_versions, features) = negotiate_features(
+                    &supported_hash_algorithms,
+                    &supported_onion_versions,
+                    remote_features,
+                )?;
+
                 let mut state = ChannelActorState::new_inbound_channel(
                     *channel_id,
                     public_channel_info,
@@ -1569,7 +2363,13 @@ where
                     *second_per_commitment_point,
                     *max_tlc_value_in_flight,
                     *max_tlc_number_in_flight,
+                    max_inbound_tlc_value,
+                    max_outbound_tlc_value,
+                    hash_algorithms,
+                    onion_versions,
                 );
+                state.zero_conf = zero_conf;
+                state.features = Some(features);
 
                 state.check_ckb_params(vec![
                     "local_reserved_ckb_amount",
@@ -1605,6 +2405,11 @@ where
                         .get_commitment_point(commitment_number + 1),
                     channel_announcement_nonce,
                     next_local_nonce: state.get_local_musig2_pubnonce(),
+                    channel_flags: if zero_conf {
+                        ChannelFlags::ZERO_CONF
+                    } else {
+                        ChannelFlags::empty()
+                    },
                 };
 
                 let command = FiberMessageWithPeerId::new(
@@ -1624,6 +2429,7 @@ where
                 if let Some(sender) = channel_id_sender {
                     sender.send(state.get_id()).expect("Receive not dropped");
                 }
+                schedule_open_timeout(&myself, open_timeout_seconds);
                 Ok(state)
             }
             ChannelInitializationParameter::OpenChannel(OpenChannelParameter {
@@ -1638,6 +2444,14 @@ where
                 funding_fee_rate,
                 max_tlc_number_in_flight,
                 max_tlc_value_in_flight,
+                max_inbound_tlc_value,
+                max_outbound_tlc_value,
+                external_funding_tx,
+                supported_hash_algorithms,
+                supported_onion_versions,
+                remote_features,
+                open_timeout_seconds,
+                zero_conf,
             }) => {
                 let public = public_channel_info.is_some();
                 let peer_id = self.get_remote_peer_id();
@@ -1650,6 +2464,12 @@ where
                 let (funding_amount, reserved_ckb_amount) =
                     self.get_funding_and_reserved_amount(funding_amount, &funding_udt_type_script)?;
 
+                let (hash_algorithms, onion_versions, features) = negotiate_features(
+                    &supported_hash_algorithms,
+                    &supported_onion_versions,
+                    remote_features,
+                )?;
+
                 let mut channel = ChannelActorState::new_outbound_channel(
                     public_channel_info,
                     &seed,
@@ -1670,7 +2490,14 @@ where
                     shutdown_script.clone(),
                     max_tlc_value_in_flight.unwrap_or(DEFAULT_MAX_TLC_VALUE_IN_FLIGHT),
                     max_tlc_number_in_flight.unwrap_or(DEFAULT_MAX_TLC_NUMBER_IN_FLIGHT),
+                    max_inbound_tlc_value,
+                    max_outbound_tlc_value,
+                    hash_algorithms,
+                    onion_versions,
                 );
+                channel.external_funding_tx = external_funding_tx;
+                channel.zero_conf = zero_conf;
+                channel.features = Some(features);
 
                 channel.check_ckb_params(vec![
                     "commitment_fee_rate",
@@ -1680,11 +2507,14 @@ where
                     "max_tlc_number_in_flight",
                 ])?;
 
-                let channel_flags = if public {
+                let mut channel_flags = if public {
                     ChannelFlags::PUBLIC
                 } else {
                     ChannelFlags::empty()
                 };
+                if zero_conf {
+                    channel_flags |= ChannelFlags::ZERO_CONF;
+                }
                 let channel_announcement_nonce = if public {
                     Some(channel.get_channel_announcement_musig2_pubnonce())
                 } else {
@@ -1744,6 +2574,7 @@ where
                 channel_id_sender
                     .send(channel.get_id())
                     .expect("Receive not dropped");
+                schedule_open_timeout(&myself, open_timeout_seconds);
                 Ok(channel)
             }
             ChannelInitializationParameter::ReestablishChannel(channel_id) => {
@@ -1752,6 +2583,15 @@ where
                     .get_channel_actor_state(&channel_id)
                     .expect("channel should exist");
                 channel.reestablishing = true;
+                channel
+                    .connection_stats
+                    .on_peer_connected(now_timestamp_in_millis());
+                // The pending channel update is not gossiped yet before the node is stopped.
+                if channel.channel_update_broadcast_pending {
+                    self.schedule_channel_update_broadcast(&myself);
+                }
+                // The delayed tlc removals are not done yet before the node is stopped.
+                self.schedule_delayed_tlc_removals(&myself, &channel);
 
                 let reestablish_channel = ReestablishChannel {
                     channel_id,
@@ -1800,25 +2640,88 @@ where
             &message,
             &state.state
         );
-        match message {
-            ChannelActorMessage::PeerMessage(message) => {
-                if let Err(error) = self.handle_peer_message(state, message).await {
-                    error!("Error while processing channel message: {:?}", error);
+        let snapshot = (self.event_log_max_size > 0).then(|| ChannelLogSnapshot::new(state));
+        let was_closed = state.is_closed();
+        let was_halted = state.halted.is_some();
+        let commitment_numbers = state.get_current_commitment_numbers();
+        // Attach the channel id to all the logs emitted while processing the message.
+        let span = info_span!("channel", channel_id = %state.get_id());
+        async {
+            match message {
+                ChannelActorMessage::PeerMessage(message) => {
+                    if let Err(error) = self.handle_peer_message(state, message).await {
+                        error!("Error while processing channel message: {:?}", error);
+                        state.record_open_error(&error);
+                    }
                 }
-            }
-            ChannelActorMessage::Command(command) => {
-                if let Err(err) = self.handle_command(state, command).await {
-                    error!("Error while processing channel command: {:?}", err);
+                ChannelActorMessage::Command(command) => {
+                    if let Err(err) = self.handle_command(&myself, state, command).await {
+                        error!("Error while processing channel command: {:?}", err);
+                        state.record_open_error(&err);
+                    }
                 }
-            }
-            ChannelActorMessage::Event(e) => {
-                if let Err(err) = self.handle_event(&myself, state, e).await {
-                    error!("Error while processing channel event: {:?}", err);
+                ChannelActorMessage::Event(e) => {
+                    if let Err(err) = self.handle_event(&myself, state, e).await {
+                        error!("Error while processing channel event: {:?}", err);
+                        state.record_open_error(&err);
+                    }
                 }
             }
         }
+        .instrument(span)
+        .await;
+
+        // The commitment numbers only change when a commitment transaction is revoked.
+        if self.audit_commitment_updates
+            && state.get_current_commitment_numbers() != commitment_numbers
+        {
+            let audit = state.audit();
+            if !audit.issues.is_empty() && state.halted.is_none() {
+                error!(
+                    "Channel {:?} failed the commitment audit, halting the channel: {:?}",
+                    state.get_id(),
+                    audit.issues
+                );
+                state.halted = Some(ChannelHaltReason::AuditFailed {
+                    issues: audit.issues.clone(),
+                });
+            }
+            state.last_commitment_audit = Some(audit);
+        }
+        // The preimage is kept until the fulfillment can't be replayed on reestablish anymore,
+        // forgetting it then also prevents the invoice from being settled again.
+        if self.forget_settled_preimages
+            && state.get_current_commitment_numbers() != commitment_numbers
+        {
+            for payment_hash in state.forget_irrevocably_fulfilled_preimages() {
+                self.store.remove_invoice_preimage(&payment_hash);
+            }
+        }
 
-        self.store.insert_channel_actor_state(state.clone());
+        if !was_closed && state.is_closed() {
+            self.store.insert_closed_channel_actor_state(
+                state.clone(),
+                ClosedChannel::new(state, now_timestamp_in_millis()),
+            )?;
+        } else {
+            self.store.insert_channel_actor_state(state.clone())?;
+        }
+        // The channel actor is stopped once the channel is halted, and it's not restarted.
+        if !was_halted && state.halted.is_some() {
+            error!("Channel {:?} is halted: {:?}", state.get_id(), state.halted);
+            myself.stop(Some("ChannelHalted".to_string()));
+        }
+        if let Some(snapshot) = snapshot {
+            let events = snapshot.events_since(state);
+            if !events.is_empty() {
+                self.store.append_channel_events(
+                    &state.get_id(),
+                    events,
+                    now_timestamp_in_millis(),
+                    self.event_log_max_size,
+                );
+            }
+        }
         Ok(())
     }
 }
@@ -2017,6 +2920,102 @@ pub struct ChannelActorState {
     // The maximum number of tlcs that we can accept.
     pub max_tlc_number_in_flight: u64,
 
+    // The maximum amount of a single tlc received from the remote peer, enforced locally
+    // while accepting incoming tlcs. None means no limit.
+    #[serde(default)]
+    pub max_inbound_tlc_value: Option<u128>,
+
+    // The maximum amount of a single tlc offered to the remote peer, enforced locally
+    // while adding outgoing tlcs. None means no limit.
+    #[serde(default)]
+    pub max_outbound_tlc_value: Option<u128>,
+
+    // The signed transaction provided by an external wallet to fund our part of the channel,
+    // which is spent by the funding transaction.
+    #[serde_as(as = "Option<EntityHex>")]
+    #[serde(default)]
+    pub external_funding_tx: Option<Transaction>,
+
+    // The hash algorithms which can be used by the payment hashes of the tlcs,
+    // negotiated with the remote peer while opening the channel.
+    #[serde(default = "HashAlgorithm::supported_algorithms")]
+    pub hash_algorithms: Vec<HashAlgorithm>,
+
+    // The onion packet versions which can be used by the tlcs, negotiated with the remote
+    // peer while opening the channel. The channels opened before the negotiation use version 0.
+    #[serde(default = "default_onion_versions")]
+    pub onion_versions: Vec<u8>,
+
+    // The feature bits supported by both peers, negotiated while opening the channel.
+    // None means the channel was opened before the negotiation, and all features are assumed.
+    #[serde(default)]
+    pub features: Option<u64>,
+
+    // The policy to rebalance this channel automatically, set by the update command.
+    #[serde(default)]
+    pub rebalance_policy: Option<RebalancePolicy>,
+
+    // The dust tlc limit of this channel set by the update command, None means the default limit
+    // of the node is used, which only applies to CKB channels.
+    #[serde(default)]
+    pub dust_tlc_limit: Option<DustTlcLimit>,
+
+    // The maximum amount of a single tlc in both directions, in millionths of the channel
+    // capacity, set by the update command. None means no limit.
+    #[serde(default)]
+    pub max_htlc_fraction_of_capacity: Option<u64>,
+
+    // Whether the channel update changed by the update command is waiting to be gossiped.
+    #[serde(default)]
+    pub channel_update_broadcast_pending: bool,
+
+    // The connection history of the remote peer, to assess the reliability of the channel.
+    #[serde(default)]
+    pub connection_stats: ChannelConnectionStats,
+
+    // The latest error encountered while opening the channel, to diagnose the stalled opens.
+    #[serde(default)]
+    pub open_error: Option<String>,
+
+    // The timestamp in milliseconds when the channel entered the current state, None if the state
+    // is not changed since the channel is created or the channel is saved before it's tracked.
+    #[serde(default)]
+    pub state_changed_at: Option<u64>,
+
+    // The hash of the transaction which closes the channel, either the shutdown transaction of
+    // a cooperative close or the confirmed commitment transaction of an uncooperative close.
+    #[serde(default)]
+    pub closing_tx_hash: Option<Hash256>,
+
+    // Whether both parties agreed to use the channel before the funding transaction is confirmed,
+    // the funds are at risk until funding_tx_confirmed_at is set.
+    #[serde(default)]
+    pub zero_conf: bool,
+
+    // Whether the funding transaction is treated as confirmed with fewer confirmations because the
+    // remote peer is trusted, the funds are at risk until it reaches the full confirmations.
+    #[serde(default)]
+    pub trusted_funding_pending: bool,
+
+    // The result of the audit run on the last commitment update, None if no commitment update
+    // is audited yet.
+    #[serde(default)]
+    pub last_commitment_audit: Option<ChannelAudit>,
+
+    // Why the channel is halted, None if it's not halted.
+    #[serde(default)]
+    pub halted: Option<ChannelHaltReason>,
+
+    // The removals of the received tlcs delayed by the tlc fail delay or the tlc fulfill delay,
+    // keyed by the tlc ids. They are saved to be replayed after the node restarts.
+    #[serde(default)]
+    pub delayed_tlc_removals: BTreeMap<u64, DelayedTlcRemoval>,
+
+    // The shutdown fee rate counter-proposed by the remote peer that exceeds our max fee rate,
+    // it's accepted once the user re-issues the shutdown command with a high enough max fee rate.
+    #[serde(default)]
+    pub pending_shutdown_fee_counter: Option<ShutdownInfo>,
+
     // Below are fields that are only usable after the channel is funded,
     // (or at some point of the state).
 
@@ -2042,6 +3041,12 @@ pub struct ChannelActorState {
     #[serde_as(as = "Option<EntityHex>")]
     pub latest_commitment_transaction: Option<Transaction>,
 
+    // The latest commitment transaction we signed for the counterparty, without the witnesses
+    // which only the counterparty can complete.
+    #[serde_as(as = "Option<EntityHex>")]
+    #[serde(default)]
+    pub latest_remote_commitment_transaction: Option<Transaction>,
+
     // All the commitment point that are sent from the counterparty.
     // We need to save all these points to derive the keys for the commitment transactions.
     pub remote_commitment_points: Vec<Pubkey>,
@@ -2057,6 +3062,58 @@ pub struct ChannelActorState {
     pub created_at: SystemTime,
 }
 
+// The connection history of the remote peer of a channel, all the timestamps and durations
+// are in milliseconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelConnectionStats {
+    // The timestamp since when the peer is connected, None if the peer is disconnected.
+    pub connected_at: Option<u64>,
+    // The cumulative connected duration of the previous connections.
+    pub connected_duration: u64,
+    // The timestamp when the peer was disconnected last time.
+    pub last_disconnected_at: Option<u64>,
+    // The number of times the peer was disconnected.
+    pub disconnection_count: u64,
+}
+
+impl ChannelConnectionStats {
+    pub fn connected_since(now: u64) -> Self {
+        Self {
+            connected_at: Some(now),
+            ..Default::default()
+        }
+    }
+
+    pub fn on_peer_connected(&mut self, now: u64) {
+        // The previous connection may not be ended with a disconnection event if the node was
+        // stopped, its duration after the last update is unknown and not counted.
+        self.connected_at = Some(now);
+    }
+
+    pub fn on_peer_disconnected(&mut self, now: u64) {
+        if let Some(connected_at) = self.connected_at.take() {
+            self.connected_duration += now.saturating_sub(connected_at);
+        }
+        self.last_disconnected_at = Some(now);
+        self.disconnection_count += 1;
+    }
+
+    // The cumulative connected duration including the current connection.
+    pub fn total_connected_duration(&self, now: u64) -> u64 {
+        self.connected_duration
+            + self
+                .connected_at
+                .map_or(0, |connected_at| now.saturating_sub(connected_at))
+    }
+}
+
+fn now_timestamp_in_millis() -> u64 {
+    UNIX_EPOCH
+        .elapsed()
+        .expect("duration since epoch")
+        .as_millis() as u64
+}
+
 #[serde_as]
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ShutdownInfo {
@@ -2064,6 +3121,9 @@ pub struct ShutdownInfo {
     pub close_script: Script,
     pub fee_rate: u64,
     pub signature: Option<PartialSignature>,
+    // The maximal fee rate we are willing to pay if the remote peer counter-proposes
+    // a higher fee rate, only set in our own shutdown info.
+    pub max_fee_rate: Option<u64>,
 }
 
 // This struct holds the channel information that are only relevant when the channel
@@ -2115,15 +3175,87 @@ impl PublicChannelInfo {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug)]
-pub struct ClosedChannel {}
+// The default routing policy of the public channels with a peer, set by the operator.
+// The channels opened or accepted with the peer inherit it, the unset fields fall back to
+// the node's config, and the policy given while opening the channel takes precedence.
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerFeePolicy {
+    pub tlc_locktime_expiry_delta: Option<u64>,
+    pub tlc_min_value: Option<u128>,
+    pub tlc_max_value: Option<u128>,
+    pub tlc_fee_proportional_millionths: Option<u128>,
+}
+
+/// The archived summary of a closed channel, kept for the accounting after the channel is closed.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClosedChannel {
+    pub channel_id: Hash256,
+    #[serde_as(as = "DisplayFromStr")]
+    pub peer_id: PeerId,
+    #[serde_as(as = "Option<EntityHex>")]
+    pub funding_udt_type_script: Option<Script>,
+    pub close_flags: CloseFlags,
+    /// The final balances of both parties, excluding the reserved CKB amounts.
+    #[serde_as(as = "U128Hex")]
+    pub local_balance: u128,
+    #[serde_as(as = "U128Hex")]
+    pub remote_balance: u128,
+    #[serde_as(as = "U64Hex")]
+    pub local_reserved_ckb_amount: u64,
+    #[serde_as(as = "U64Hex")]
+    pub remote_reserved_ckb_amount: u64,
+    /// None if the channel open is aborted before the funding transaction is signed.
+    pub closing_tx_hash: Option<Hash256>,
+    /// The time when the channel is closed, in milliseconds since UNIX epoch.
+    #[serde_as(as = "U64Hex")]
+    pub closed_at: u64,
+}
+
+impl ClosedChannel {
+    pub fn new(state: &ChannelActorState, closed_at: u64) -> Self {
+        let close_flags = match state.state {
+            ChannelState::Closed(flags) => flags,
+            _ => CloseFlags::empty(),
+        };
+        Self {
+            channel_id: state.get_id(),
+            peer_id: state.get_remote_peer_id(),
+            funding_udt_type_script: state.funding_udt_type_script.clone(),
+            close_flags,
+            local_balance: state.get_local_balance(),
+            remote_balance: state.get_remote_balance(),
+            local_reserved_ckb_amount: state.local_reserved_ckb_amount,
+            remote_reserved_ckb_amount: state.remote_reserved_ckb_amount,
+            closing_tx_hash: state.closing_tx_hash,
+            closed_at,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum ChannelEvent {
     PeerDisconnected,
-    FundingTransactionConfirmed(BlockNumber, u32),
-    CommitmentTransactionConfirmed,
+    // The funding transaction is confirmed at the block with the given transaction index, the flag
+    // tells whether it's only confirmed by the fewer confirmations required for a trusted peer.
+    FundingTransactionConfirmed(BlockNumber, u32, bool),
+    // The funding transaction confirmed for a trusted peer reaches the full confirmations.
+    FundingTransactionFullyConfirmed,
+    // The fully signed funding transaction is being broadcasted.
+    FundingTransactionPending,
+    // The funding transaction is no longer in the canonical chain (or it has been
+    // included in another block), we need to wait for it to be confirmed again.
+    FundingTransactionReorged,
+    // The commitment transaction with the given hash is confirmed.
+    CommitmentTransactionConfirmed(Hash256),
     ClosingTransactionConfirmed,
+    // The open timeout of the channel is reached, the open is aborted if our funding
+    // transaction signatures are not sent yet.
+    OpenTimeout,
+    // The delay before removing the received tlc with the given id is over.
+    DelayedRemoveTlc(u64),
+    // The delay before gossiping the channel update changed by the update command is over.
+    BroadcastChannelUpdate,
 }
 
 pub type ProcessingChannelResult = Result<(), ProcessingChannelError>;
@@ -2152,12 +3284,30 @@ pub enum ProcessingChannelError {
     FinalIncorrectPreimage,
     #[error("The tlc forward fee is tow low")]
     TlcForwardFeeIsTooLow,
+    #[error("The tlc expires too soon to forward")]
+    TlcExpiryTooSoon,
     #[error("The tlc number exceed limit of this channel")]
     TlcNumberExceedLimit,
     #[error("The tlc flight value exceed limit of this channel")]
     TlcValueInflightExceedLimit,
     #[error("The tlc amount below minimal")]
     TlcAmountIsTooLow,
+    #[error("The tlc amount exceed limit of this channel")]
+    TlcAmountExceedLimit,
+    #[error("The dust tlc exposure exceed limit of this channel")]
+    DustTlcExposureExceedLimit,
+    #[error("The tlc amount exceed the max value {0} relative to the capacity of this channel")]
+    TlcAmountExceedCapacityFraction(u128),
+    #[error("The hash algorithm {0:?} is not supported by this channel")]
+    UnsupportedHashAlgorithm(HashAlgorithm),
+    #[error("The onion packet version {0} is not supported by this channel")]
+    UnsupportedOnionVersion(u8),
+    #[error("Failed to forward the trampoline payment: {0}")]
+    TrampolineForwardFailed(String),
+    #[error("Channel id {0:?} is already used by another channel")]
+    ChannelIdCollision(Hash256),
+    #[error("Failed to read the store: {0}")]
+    StoreReadFailed(String),
 }
 
 bitflags! {
@@ -2165,6 +3315,7 @@ bitflags! {
     #[serde(transparent)]
     pub struct ChannelFlags: u8 {
         const PUBLIC = 1;
+        const ZERO_CONF = 1 << 1;
     }
 
     #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -2207,6 +3358,8 @@ bitflags! {
         const OUR_CHANNEL_READY = 1;
         const THEIR_CHANNEL_READY = 1 << 1;
         const CHANNEL_READY = AwaitingChannelReadyFlags::OUR_CHANNEL_READY.bits() | AwaitingChannelReadyFlags::THEIR_CHANNEL_READY.bits();
+        /// The channel was ready, but the funding transaction was reorged out of the chain.
+        const FUNDING_REORGED = 1 << 2;
     }
 
     #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -2232,6 +3385,8 @@ bitflags! {
         const COOPERATIVE = 1;
         /// Indicates that channel is closed uncooperatively, initiated by one party forcely.
         const UNCOOPERATIVE = 1 << 1;
+        /// Indicates that channel open is aborted before the funding transaction is signed.
+        const ABORTED = 1 << 2;
     }
 }
 
@@ -2278,6 +3433,135 @@ impl ChannelState {
     fn is_closed(&self) -> bool {
         matches!(self, ChannelState::Closed(_))
     }
+
+    /// The step of the channel opening handshake the channel is in.
+    pub fn open_step(&self) -> ChannelOpenStep {
+        match self {
+            ChannelState::NegotiatingFunding(_) | ChannelState::CollaboratingFundingTx(_) => {
+                ChannelOpenStep::Negotiating
+            }
+            ChannelState::SigningCommitment(_) | ChannelState::AwaitingTxSignatures(_) => {
+                ChannelOpenStep::AwaitingFundingSignature
+            }
+            ChannelState::AwaitingChannelReady(_) => ChannelOpenStep::AwaitingConfirmation,
+            ChannelState::ChannelReady() => ChannelOpenStep::Ready,
+            ChannelState::Closed(flags) if flags.contains(CloseFlags::ABORTED) => {
+                ChannelOpenStep::Aborted
+            }
+            ChannelState::ShuttingDown(_) | ChannelState::Closed(_) => ChannelOpenStep::Closed,
+        }
+    }
+}
+
+/// The coarse steps of opening a channel, derived from the channel state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelOpenStep {
+    /// Negotiating the channel parameters and building the funding transaction with the peer.
+    Negotiating,
+    /// Exchanging the signatures of the commitment and funding transactions.
+    AwaitingFundingSignature,
+    /// Waiting for the funding transaction to be confirmed on chain.
+    AwaitingConfirmation,
+    /// The channel is opened and ready to use.
+    Ready,
+    /// The open is aborted before the funding transaction is signed, e.g. it's not completed within the open timeout.
+    Aborted,
+    /// The channel is shutting down or closed.
+    Closed,
+}
+
+impl ChannelOpenStep {
+    pub fn is_opening(&self) -> bool {
+        !matches!(
+            self,
+            ChannelOpenStep::Ready | ChannelOpenStep::Aborted | ChannelOpenStep::Closed
+        )
+    }
+}
+
+// The limit of the dust tlcs in flight. A tlc with an amount below the threshold is dust, which
+// is not worth claiming on chain and is lost to the fees in a force close, so the total amount
+// of the dust tlcs is capped to bound the loss.
+#[serde_as]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DustTlcLimit {
+    #[serde_as(as = "U128Hex")]
+    pub threshold: u128,
+    // The maximal total amount of the dust tlcs in flight, 0 means no limit.
+    #[serde_as(as = "U128Hex")]
+    pub max_exposure: u128,
+}
+
+// A removal of the received tlc which is delayed by the tlc fail delay or the tlc fulfill delay.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DelayedTlcRemoval {
+    pub reason: RemoveTlcReason,
+    // The timestamp in milliseconds when the tlc is due to be removed.
+    pub due_at: u64,
+}
+
+// The delay before failing back a received tlc, which is the fixed delay plus a random jitter.
+// Delaying the failures makes it harder to probe the channel balances by the response time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TlcFailDelay {
+    pub delay_ms: u64,
+    pub jitter_ms: u64,
+}
+
+impl TlcFailDelay {
+    pub fn sample(&self) -> Option<Duration> {
+        let jitter_ms = if self.jitter_ms > 0 {
+            rand::thread_rng().gen_range(0..=self.jitter_ms)
+        } else {
+            0
+        };
+        let delay_ms = self.delay_ms.saturating_add(jitter_ms);
+        (delay_ms > 0).then(|| Duration::from_millis(delay_ms))
+    }
+}
+
+// The random delay before fulfilling a forwarded tlc, which is at most the jitter. A forwarding
+// node reveals the preimage as soon as the downstream hop does, so the upstream peers may infer
+// the length of the remaining route, and whether the next hop is the final recipient, from the
+// time it takes to settle. The delay blurs the timing at the cost of slower payments, and it
+// holds the liquidity of the upstream channel longer.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TlcFulfillDelay {
+    pub jitter_ms: u64,
+}
+
+impl TlcFulfillDelay {
+    // The delay never exceeds half of the time left before the tlc expires, so that the upstream
+    // peer still has enough time to settle it before the expiry. The time left is estimated from
+    // the blocks left with the shortest average block interval.
+    pub fn sample(&self, expires_in_blocks: u64) -> Option<Duration> {
+        let expires_in_ms = expires_in_blocks.saturating_mul(MIN_AVERAGE_BLOCK_INTERVAL_MS);
+        let max_delay_ms = self.jitter_ms.min(expires_in_ms / 2);
+        if max_delay_ms == 0 {
+            return None;
+        }
+        let delay_ms = rand::thread_rng().gen_range(0..=max_delay_ms);
+        (delay_ms > 0).then(|| Duration::from_millis(delay_ms))
+    }
+}
+
+// Send the message to the actor after the delay. Unlike `ActorRef::send_after`, the message
+// doesn't need to be built by the closure again and again.
+fn send_message_after<M: ractor::Message>(actor: ActorRef<M>, delay: Duration, message: M) {
+    ractor::concurrency::spawn(async move {
+        ractor::concurrency::sleep(delay).await;
+        let _ = actor.send_message(message);
+    });
+}
+
+// Send an OpenTimeout event to the channel actor after the open timeout, 0 means no timeout.
+fn schedule_open_timeout(myself: &ActorRef<ChannelActorMessage>, open_timeout_seconds: u64) {
+    if open_timeout_seconds != 0 {
+        myself.send_after(Duration::from_secs(open_timeout_seconds), || {
+            ChannelActorMessage::Event(ChannelEvent::OpenTimeout)
+        });
+    }
 }
 
 fn new_channel_id_from_seed(seed: &[u8]) -> Hash256 {
@@ -2295,6 +3579,46 @@ fn derive_temp_channel_id_from_tlc_key(tlc_basepoint: &Pubkey) -> Hash256 {
     new_channel_id_from_seed(&preimage)
 }
 
+fn default_onion_versions() -> Vec<u8> {
+    vec![0]
+}
+
+// The hash algorithms and onion packet versions supported by both sides of the channel, in the
+// order of our preference, and the feature bits supported by both sides. The remote features are
+// taken from the node announcement of the peer, a peer which has not announced any features is
+// assumed to support `LEGACY_NODE_FEATURES`.
+pub(crate) fn negotiate_features(
+    local_hash_algorithms: &[HashAlgorithm],
+    local_onion_versions: &[u8],
+    remote_features: Option<u64>,
+) -> Result<(Vec<HashAlgorithm>, Vec<u8>, u64), ProcessingChannelError> {
+    let remote_features = remote_features.unwrap_or(LEGACY_NODE_FEATURES);
+    let features = node_features(local_hash_algorithms, local_onion_versions) & remote_features;
+    let hash_algorithms: Vec<_> = local_hash_algorithms
+        .iter()
+        .filter(|algorithm| features & hash_algorithm_feature(**algorithm) != 0)
+        .cloned()
+        .collect();
+    if hash_algorithms.is_empty() {
+        return Err(ProcessingChannelError::InvalidParameter(format!(
+            "No common hash algorithm, local: {:?}, remote features: {:#x}",
+            local_hash_algorithms, remote_features
+        )));
+    }
+    let onion_versions: Vec<_> = local_onion_versions
+        .iter()
+        .filter(|version| features & onion_version_feature(**version) != 0)
+        .cloned()
+        .collect();
+    if onion_versions.is_empty() {
+        return Err(ProcessingChannelError::InvalidParameter(format!(
+            "No common onion version, local: {:?}, remote features: {:#x}",
+            local_onion_versions, remote_features
+        )));
+    }
+    Ok((hash_algorithms, onion_versions, features))
+}
+
 pub fn get_commitment_secret(commitment_seed: &[u8; 32], commitment_number: u64) -> [u8; 32] {
     // Note that here, we hold the same assumption to bolts for commitment number,
     // i.e. this number should be in the range [0, 2^48).
@@ -2402,6 +3726,34 @@ impl ChannelActorState {
         self.public_channel_info.is_some()
     }
 
+    // The funds of a zero-conf channel are at risk if the channel is used before the funding
+    // transaction is confirmed, as it may be double spent.
+    // The same applies to the channels whose funding transactions are treated as confirmed
+    // with fewer confirmations because the remote peer is trusted.
+    pub fn is_funding_at_risk(&self) -> bool {
+        (self.zero_conf && self.funding_tx_confirmed_at.is_none()) || self.trusted_funding_pending
+    }
+
+    // The local balance can't be claimed if the funding transaction of a channel at risk is
+    // double spent, 0 if the funding is not at risk.
+    pub fn get_funding_at_risk_amount(&self) -> u128 {
+        if self.is_funding_at_risk() {
+            self.get_local_balance()
+        } else {
+            0
+        }
+    }
+
+    fn set_funding_tx_confirmed_at(
+        &mut self,
+        block_number: BlockNumber,
+        tx_index: u32,
+        trusted: bool,
+    ) {
+        self.funding_tx_confirmed_at = Some((block_number, tx_index));
+        self.trusted_funding_pending = trusted;
+    }
+
     pub async fn try_create_channel_messages(
         &mut self,
         network: &ActorRef<NetworkActorMessage>,
@@ -2659,6 +4011,10 @@ impl ChannelActorState {
         second_commitment_point: Pubkey,
         max_tlc_value_in_flight: u128,
         max_tlc_number_in_flight: u64,
+        max_inbound_tlc_value: Option<u128>,
+        max_outbound_tlc_value: Option<u128>,
+        hash_algorithms: Vec<HashAlgorithm>,
+        onion_versions: Vec<u8>,
     ) -> Self {
         let signer = InMemorySigner::generate_from_seed(seed);
         let local_base_pubkeys = signer.get_base_public_keys();
@@ -2704,8 +4060,29 @@ impl ChannelActorState {
             local_reserved_ckb_amount,
             remote_reserved_ckb_amount,
             latest_commitment_transaction: None,
+            latest_remote_commitment_transaction: None,
             max_tlc_value_in_flight,
             max_tlc_number_in_flight,
+            max_inbound_tlc_value,
+            max_outbound_tlc_value,
+            external_funding_tx: None,
+            hash_algorithms,
+            onion_versions,
+            features: None,
+            rebalance_policy: None,
+            dust_tlc_limit: None,
+            max_htlc_fraction_of_capacity: None,
+            channel_update_broadcast_pending: false,
+            connection_stats: ChannelConnectionStats::connected_since(now_timestamp_in_millis()),
+            open_error: None,
+            state_changed_at: None,
+            closing_tx_hash: None,
+            zero_conf: false,
+            trusted_funding_pending: false,
+            last_commitment_audit: None,
+            halted: None,
+            delayed_tlc_removals: Default::default(),
+            pending_shutdown_fee_counter: None,
 
             reestablishing: false,
             created_at: SystemTime::now(),
@@ -2731,6 +4108,10 @@ impl ChannelActorState {
         shutdown_script: Script,
         max_tlc_value_in_flight: u128,
         max_tlc_number_in_flight: u64,
+        max_inbound_tlc_value: Option<u128>,
+        max_outbound_tlc_value: Option<u128>,
+        hash_algorithms: Vec<HashAlgorithm>,
+        onion_versions: Vec<u8>,
     ) -> Self {
         let signer = InMemorySigner::generate_from_seed(seed);
         let local_pubkeys = signer.get_base_public_keys();
@@ -2756,6 +4137,26 @@ impl ChannelActorState {
             local_channel_public_keys: local_pubkeys,
             max_tlc_number_in_flight,
             max_tlc_value_in_flight,
+            max_inbound_tlc_value,
+            max_outbound_tlc_value,
+            external_funding_tx: None,
+            hash_algorithms,
+            onion_versions,
+            features: None,
+            rebalance_policy: None,
+            dust_tlc_limit: None,
+            max_htlc_fraction_of_capacity: None,
+            channel_update_broadcast_pending: false,
+            connection_stats: ChannelConnectionStats::connected_since(now_timestamp_in_millis()),
+            open_error: None,
+            state_changed_at: None,
+            closing_tx_hash: None,
+            zero_conf: false,
+            trusted_funding_pending: false,
+            last_commitment_audit: None,
+            halted: None,
+            delayed_tlc_removals: Default::default(),
+            pending_shutdown_fee_counter: None,
             remote_channel_public_keys: None,
             previous_remote_nonce: None,
             remote_nonce: None,
@@ -2768,6 +4169,7 @@ impl ChannelActorState {
             local_reserved_ckb_amount,
             remote_reserved_ckb_amount: 0,
             latest_commitment_transaction: None,
+            latest_remote_commitment_transaction: None,
 
             reestablishing: false,
             created_at: SystemTime::now(),
@@ -2932,6 +4334,65 @@ impl ChannelActorState {
             .sum::<u128>()
     }
 
+    // The maximal amount that the remote party can send to us with a single tlc right now,
+    // limited by the remote balance, the tlc limits and our max tlc value.
+    pub fn get_max_receivable_amount(&self) -> u128 {
+        if self.state != ChannelState::ChannelReady() || self.reestablishing {
+            return 0;
+        }
+        let active_tlcs = self
+            .get_active_received_tlcs(true)
+            .chain(self.get_active_offered_tlcs(true));
+        let (active_tlc_number, active_tlc_value) = active_tlcs
+            .fold((0_u64, 0_u128), |(number, value), tlc| {
+                (number + 1, value + tlc.tlc.amount)
+            });
+        if active_tlc_number >= self.max_tlc_number_in_flight {
+            return 0;
+        }
+        let max_amount = self
+            .to_remote_amount
+            .saturating_sub(self.get_received_tlc_balance())
+            .min(
+                self.max_tlc_value_in_flight
+                    .saturating_sub(active_tlc_value),
+            );
+        // A single received tlc above our inbound limit is rejected while being added.
+        [self.get_max_tlc_value(), self.max_inbound_tlc_value]
+            .into_iter()
+            .flatten()
+            .fold(max_amount, u128::min)
+    }
+
+    // Whether the feature is supported by both peers of the channel, the channels opened before
+    // the feature negotiation are assumed to support all the features.
+    pub fn supports_feature(&self, feature: u64) -> bool {
+        self.features
+            .map_or(true, |features| features & feature == feature)
+    }
+
+    // The maximum amount of a single tlc relative to the capacity of the channel, None if
+    // `max_htlc_fraction_of_capacity` is not set.
+    pub fn get_max_tlc_value_of_capacity(&self) -> Option<u128> {
+        self.max_htlc_fraction_of_capacity.map(|fraction| {
+            (self.to_local_amount + self.to_remote_amount) * fraction as u128 / 1_000_000
+        })
+    }
+
+    // The maximum amount of a single tlc, the smaller one of our absolute tlc max value and the
+    // max value relative to the capacity. None means no limit.
+    pub fn get_max_tlc_value(&self) -> Option<u128> {
+        let tlc_max_value = self
+            .public_channel_info
+            .as_ref()
+            .and_then(|info| info.tlc_max_value)
+            .filter(|value| *value != 0);
+        match (tlc_max_value, self.get_max_tlc_value_of_capacity()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
     pub fn get_created_at_in_microseconds(&self) -> u64 {
         self.created_at
             .duration_since(UNIX_EPOCH)
@@ -2948,9 +4409,144 @@ impl ChannelActorState {
             "Updating channel state from {:?} to {:?}",
             &self.state, &new_state
         );
+        if self.state != new_state {
+            self.state_changed_at = Some(now_timestamp_in_millis());
+        }
         self.state = new_state;
     }
 
+    // Checks that the commitment points, the latest commitment transaction and the tlcs
+    // are consistent with the commitment numbers, to detect the latent state corruptions.
+    pub fn audit(&self) -> ChannelAudit {
+        let commitment_numbers = self.get_current_commitment_numbers();
+        let mut issues = vec![];
+        let is_funded = matches!(
+            self.state,
+            ChannelState::ChannelReady() | ChannelState::ShuttingDown(_)
+        );
+        // A commitment point is received for each commitment of the peer, including the next one.
+        let remote_commitment_points = self.remote_commitment_points.len() as u64;
+        if is_funded && remote_commitment_points != commitment_numbers.local + 1 {
+            issues.push(format!(
+                "Expected {} commitment points of the peer for local commitment number {}, found {}",
+                commitment_numbers.local + 1,
+                commitment_numbers.local,
+                remote_commitment_points
+            ));
+        }
+        if is_funded && self.latest_commitment_transaction.is_none() {
+            issues.push("Missing the latest commitment transaction".to_string());
+        }
+        // The commitment secrets are only derivable for the commitment numbers below 2^48.
+        if commitment_numbers.local >= 1 << 48 || commitment_numbers.remote >= 1 << 48 {
+            issues.push(format!(
+                "Commitment numbers {:?} are out of the range of the commitment secrets",
+                commitment_numbers
+            ));
+        }
+        // The secret revoking our previous commitment must be derivable from the commitment seed,
+        // and match the commitment point used for that commitment.
+        if let Some(previous) = commitment_numbers
+            .local
+            .checked_sub(1)
+            .filter(|number| *number < 1 << 48)
+        {
+            let secret = self.signer.get_commitment_secret(previous);
+            match secp256k1::SecretKey::from_slice(&secret) {
+                Ok(key) => {
+                    let point = self.signer.get_commitment_point(previous);
+                    if Pubkey::from(key.public_key(secp256k1_instance())) != point {
+                        issues.push(format!(
+                            "Revocation secret of local commitment #{} doesn't match its commitment point",
+                            previous
+                        ));
+                    }
+                    if point == self.signer.get_commitment_point(commitment_numbers.local) {
+                        issues.push(format!(
+                            "Local commitment point #{} is not rotated, it's the same as the previous one",
+                            commitment_numbers.local
+                        ));
+                    }
+                }
+                Err(_) => issues.push(format!(
+                    "Revocation secret of local commitment #{} is not a valid secret key",
+                    previous
+                )),
+            }
+        }
+        // The peer never discloses its commitment secrets, a commitment is revoked by signing
+        // the revocation transaction instead. Each revocation by the peer provides the commitment
+        // point of its next commitment, which must be rotated from the previous one. The earlier
+        // points were already checked when they were received.
+        if let [.., previous, latest] = self.remote_commitment_points.as_slice() {
+            if previous == latest {
+                issues.push(format!(
+                    "Commitment point #{} of the peer is not rotated, it's the same as the previous one",
+                    self.remote_commitment_points.len() - 1
+                ));
+            }
+        }
+        let is_ahead = |numbers: &CommitmentNumbers| {
+            numbers.local > commitment_numbers.local || numbers.remote > commitment_numbers.remote
+        };
+        for tlc in self.tlcs.values() {
+            let id = tlc.tlc.id;
+            let steps = [
+                ("created", Some(&tlc.created_at)),
+                ("confirmed", tlc.creation_confirmed_at.as_ref()),
+                (
+                    "removed",
+                    tlc.removed_at.as_ref().map(|(numbers, _)| numbers),
+                ),
+                ("removal confirmed", tlc.removal_confirmed_at.as_ref()),
+            ];
+            for (step, numbers) in steps {
+                if let Some(numbers) = numbers.filter(|numbers| is_ahead(numbers)) {
+                    issues.push(format!(
+                        "Tlc {:?} is {} at commitment numbers {:?}, ahead of the current {:?}",
+                        id, step, numbers, commitment_numbers
+                    ));
+                }
+            }
+            if tlc.removal_confirmed_at.is_some() && tlc.removed_at.is_none() {
+                issues.push(format!(
+                    "Tlc {:?} has its removal confirmed without being removed",
+                    id
+                ));
+            }
+        }
+        ChannelAudit {
+            audited_at: now_timestamp_in_millis(),
+            commitment_numbers,
+            remote_commitment_points,
+            revoked_remote_commitments: commitment_numbers.local,
+            issues,
+        }
+    }
+
+    // A copy of the state which can be shared for diagnosis. The keys derived from the channel
+    // seed are replaced with the ones of a well-known seed and the preimages are removed. The
+    // latest commitment transaction is removed as it's fully signed, anyone holding it can force
+    // close the channel.
+    pub fn redacted(&self) -> Self {
+        let mut state = self.clone();
+        state.signer = InMemorySigner::generate_from_seed(REDACTED_SIGNER_SEED);
+        state.latest_commitment_transaction = None;
+        for tlc in state.tlcs.values_mut() {
+            tlc.tlc.payment_preimage = None;
+            if let Some((_, RemoveTlcReason::RemoveTlcFulfill(fulfill))) = &mut tlc.removed_at {
+                fulfill.payment_preimage = Hash256::default();
+            }
+        }
+        state
+    }
+
+    // The timestamp in milliseconds when the channel entered the current state.
+    pub fn get_state_changed_at(&self) -> u64 {
+        self.state_changed_at
+            .unwrap_or_else(|| self.get_created_at_in_microseconds() / 1000)
+    }
+
     fn local_is_node1(&self) -> bool {
         self.local_pubkey < self.remote_pubkey
     }
@@ -3223,6 +4819,30 @@ impl ChannelActorState {
             .expect(ASSUME_NETWORK_ACTOR_ALIVE);
     }
 
+    // Removes the preimages of the received tlcs whose fulfillment is irrevocably committed,
+    // i.e. both parties have revoked their commitments since the tlc was removed, so the
+    // fulfillment is never retransmitted. Returns the payment hashes of these tlcs.
+    fn forget_irrevocably_fulfilled_preimages(&mut self) -> Vec<Hash256> {
+        let commitment_numbers = self.get_current_commitment_numbers();
+        let mut payment_hashes = vec![];
+        for tlc in self.tlcs.values_mut().filter(|tlc| !tlc.is_offered()) {
+            if let Some((removed_at, RemoveTlcReason::RemoveTlcFulfill(fulfill))) =
+                &mut tlc.removed_at
+            {
+                if tlc.removal_confirmed_at.is_some()
+                    && commitment_numbers.local > removed_at.local
+                    && commitment_numbers.remote > removed_at.remote
+                    && fulfill.payment_preimage != Hash256::default()
+                {
+                    fulfill.payment_preimage = Hash256::default();
+                    tlc.tlc.payment_preimage = None;
+                    payment_hashes.push(tlc.tlc.payment_hash);
+                }
+            }
+        }
+        payment_hashes
+    }
+
     fn get_tlcs_for_settle_down(&self) -> Vec<DetailedTLCInfo> {
         self.tlcs
             .values()
@@ -3404,24 +5024,93 @@ impl ChannelActorState {
         self.tlc_ids.get_next_offering()
     }
 
-    pub fn get_next_received_tlc_id(&self) -> u64 {
-        self.tlc_ids.get_next_received()
+    pub fn get_next_received_tlc_id(&self) -> u64 {
+        self.tlc_ids.get_next_received()
+    }
+
+    pub fn increment_next_offered_tlc_id(&mut self) {
+        self.tlc_ids.increment_offering();
+    }
+
+    pub fn increment_next_received_tlc_id(&mut self) {
+        self.tlc_ids.increment_received();
+    }
+
+    pub fn get_offered_tlc(&self, tlc_id: u64) -> Option<&DetailedTLCInfo> {
+        self.tlcs.get(&TLCId::Offered(tlc_id))
+    }
+
+    pub fn get_received_tlc(&self, tlc_id: u64) -> Option<&DetailedTLCInfo> {
+        self.tlcs.get(&TLCId::Received(tlc_id))
+    }
+
+    pub fn mark_tlc_forwarded(&mut self, tlc_id: TLCId) {
+        if let Some(tlc) = self.tlcs.get_mut(&tlc_id) {
+            tlc.forwarded_at = Some(now_timestamp_in_millis());
+        }
+    }
+
+    pub fn set_tlc_added_at_block(&mut self, tlc_id: TLCId, block_number: Option<u64>) {
+        if let Some(tlc) = self.tlcs.get_mut(&tlc_id) {
+            tlc.added_at_block = block_number;
+        }
     }
 
-    pub fn increment_next_offered_tlc_id(&mut self) {
-        self.tlc_ids.increment_offering();
+    pub fn is_received_tlc_pending(&self, tlc_id: u64) -> bool {
+        self.get_received_tlc(tlc_id)
+            .is_some_and(|tlc| tlc.removed_at.is_none())
     }
 
-    pub fn increment_next_received_tlc_id(&mut self) {
-        self.tlc_ids.increment_received();
+    // The offered tlcs which forward the received tlcs of the previous channels.
+    pub fn get_forwarding_tlcs(&self) -> Vec<ForwardingTlc> {
+        self.tlcs
+            .values()
+            .filter(|tlc| tlc.is_offered())
+            .filter_map(|tlc| {
+                Some(ForwardingTlc {
+                    tlc_id: tlc.tlc.id,
+                    previous_tlc: tlc.tlc.previous_tlc?,
+                    added_at_block: tlc.added_at_block,
+                    removed: tlc.removed_at.as_ref().map(|(_, reason)| reason.clone()),
+                })
+            })
+            .collect()
     }
 
-    pub fn get_offered_tlc(&self, tlc_id: u64) -> Option<&DetailedTLCInfo> {
-        self.tlcs.get(&TLCId::Offered(tlc_id))
+    // Check that the received tlc is forwarded and still not resolved by the downstream node.
+    pub fn check_forwarded_tlc_unresolved(&self, tlc_id: u64) -> ProcessingChannelResult {
+        let Some(tlc) = self.get_received_tlc(tlc_id) else {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Received tlc {} not found",
+                tlc_id
+            )));
+        };
+        if tlc.forwarded_at.is_none() {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Received tlc {} is not forwarded",
+                tlc_id
+            )));
+        }
+        if tlc.removed_at.is_some() || self.delayed_tlc_removals.contains_key(&tlc_id) {
+            return Err(ProcessingChannelError::InvalidState(format!(
+                "Received tlc {} is already resolved",
+                tlc_id
+            )));
+        }
+        Ok(())
     }
 
-    pub fn get_received_tlc(&self, tlc_id: u64) -> Option<&DetailedTLCInfo> {
-        self.tlcs.get(&TLCId::Received(tlc_id))
+    // The pending tlcs which expire before the block `deadline`, along with their expiry blocks.
+    // The lock time of a tlc is the number of blocks after the block at which it's added.
+    pub fn get_tlcs_expiring_before(&self, deadline: u64) -> Vec<(TLCId, u64)> {
+        self.tlcs
+            .values()
+            .filter(|tlc| tlc.removed_at.is_none())
+            .filter_map(|tlc| {
+                let expires_at_block = tlc.expires_at_block()?;
+                (expires_at_block < deadline).then_some((tlc.tlc.id, expires_at_block))
+            })
+            .collect()
     }
 
     pub fn insert_tlc(&mut self, tlc: TLC) -> Result<DetailedTLCInfo, ProcessingChannelError> {
@@ -3453,6 +5142,14 @@ impl ChannelActorState {
         if tlc.amount == 0 {
             return Err(ProcessingChannelError::TlcAmountIsTooLow);
         }
+        let max_tlc_value = if tlc.is_offered() {
+            self.max_outbound_tlc_value
+        } else {
+            self.max_inbound_tlc_value
+        };
+        if max_tlc_value.is_some_and(|max_value| tlc.amount > max_value) {
+            return Err(ProcessingChannelError::TlcAmountExceedLimit);
+        }
         if tlc.is_offered() {
             // TODO: We should actually also consider all our fulfilled tlcs here.
             // Because this is also the amount that we can actually spend.
@@ -3497,6 +5194,9 @@ impl ChannelActorState {
             creation_confirmed_at: None,
             removed_at: None,
             removal_confirmed_at: None,
+            forwarded_at: None,
+            added_at: Some(now_timestamp_in_millis()),
+            added_at_block: None,
         };
         self.tlcs.insert(tlc.id, detailed_tlc.clone());
         if tlc.is_offered() {
@@ -3587,6 +5287,22 @@ impl ChannelActorState {
         OutPoint::new(tx.calc_tx_hash(), 0)
     }
 
+    // Whether the two states belong to the same channel. The funding transaction is still
+    // being built while negotiating, so the funding outpoints are only compared once both
+    // are confirmed, before that the funding pubkeys derived from the channel seeds are used.
+    pub fn is_same_channel(&self, other: &ChannelActorState) -> bool {
+        if self.local_channel_public_keys.funding_pubkey
+            != other.local_channel_public_keys.funding_pubkey
+        {
+            return false;
+        }
+        if self.funding_tx_confirmed_at.is_some() && other.funding_tx_confirmed_at.is_some() {
+            return self.get_funding_transaction_outpoint()
+                == other.get_funding_transaction_outpoint();
+        }
+        true
+    }
+
     pub fn get_funding_transaction_block_number(&self) -> BlockNumber {
         self.funding_tx_confirmed_at.unwrap().0
     }
@@ -3662,6 +5378,7 @@ impl ChannelActorState {
             remote_amount: self.to_remote_amount as u64,
             local_reserved_ckb_amount: self.local_reserved_ckb_amount,
             remote_reserved_ckb_amount: self.remote_reserved_ckb_amount,
+            external_funding_tx: self.external_funding_tx.clone(),
         }
     }
 
@@ -3899,10 +5616,10 @@ impl ChannelActorState {
         &self.get_remote_channel_public_keys().funding_pubkey
     }
 
-    fn check_valid_to_auto_accept_shutdown(&self) -> bool {
-        let Some(remote_fee_rate) = self.remote_shutdown_info.as_ref().map(|i| i.fee_rate) else {
+    fn check_valid_to_auto_accept_shutdown(&self, remote_fee_rate: u64) -> bool {
+        if self.remote_shutdown_info.is_none() {
             return false;
-        };
+        }
         if remote_fee_rate < self.commitment_fee_rate {
             return false;
         }
@@ -3922,6 +5639,179 @@ impl ChannelActorState {
         return fee <= remote_available_max_fee;
     }
 
+    // Accept the fee rate counter-proposed by the remote peer if it doesn't exceed our max fee rate,
+    // we then pay the whole fee at that rate. Otherwise the counter-proposal is kept pending, and
+    // the channel stays in ShuttingDown until the user re-issues the shutdown command with a high
+    // enough max fee rate, or force closes the channel.
+    fn maybe_accept_shutdown_fee_counter(
+        &mut self,
+        network: &ActorRef<NetworkActorMessage>,
+    ) -> ProcessingChannelResult {
+        let (Some(local_shutdown_info), Some(counter)) = (
+            self.local_shutdown_info.as_ref(),
+            self.pending_shutdown_fee_counter.as_ref(),
+        ) else {
+            return Ok(());
+        };
+        let counter_fee_rate = counter.fee_rate;
+        let max_fee_rate = local_shutdown_info
+            .max_fee_rate
+            .unwrap_or(local_shutdown_info.fee_rate);
+        if counter_fee_rate > max_fee_rate {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Counter-proposed shutdown fee rate {} exceeds our max fee rate {}, \
+                 re-issue the shutdown command with a max fee rate not less than it to accept it, \
+                 or force close the channel",
+                counter_fee_rate, max_fee_rate
+            )));
+        }
+        self.check_shutdown_fee_rate(
+            FeeRate::from_u64(counter_fee_rate),
+            &local_shutdown_info.close_script,
+        )?;
+        debug!(
+            "Accepting counter-proposed shutdown fee rate {} (proposed {})",
+            counter_fee_rate, local_shutdown_info.fee_rate
+        );
+        let mut remote_shutdown_info = self
+            .pending_shutdown_fee_counter
+            .take()
+            .expect("counter-proposal exists");
+        remote_shutdown_info.fee_rate = 0;
+        self.remote_shutdown_info = Some(remote_shutdown_info);
+        if let Some(info) = self.local_shutdown_info.as_mut() {
+            info.fee_rate = counter_fee_rate;
+        }
+        let flags = match self.state {
+            ChannelState::ShuttingDown(flags) => flags,
+            _ => ShuttingDownFlags::OUR_SHUTDOWN_SENT,
+        };
+        self.update_state(ChannelState::ShuttingDown(
+            flags | ShuttingDownFlags::THEIR_SHUTDOWN_SENT,
+        ));
+        self.maybe_transition_to_shutdown(network)
+    }
+
+    // Both parties have proposed a fee rate, converge on the higher one, and the party who proposed
+    // the lower one pays the whole fee. Returns the fee rates paid by us and the remote peer.
+    fn negotiate_shutdown_fee_rate(
+        &self,
+        local_shutdown_info: &ShutdownInfo,
+        remote_shutdown_info: &ShutdownInfo,
+    ) -> Result<(u64, u64), ProcessingChannelError> {
+        let local_fee_rate = local_shutdown_info.fee_rate;
+        let remote_fee_rate = remote_shutdown_info.fee_rate;
+        if local_fee_rate == 0 || remote_fee_rate == 0 || local_fee_rate == remote_fee_rate {
+            return Ok((local_fee_rate, remote_fee_rate));
+        }
+        if remote_fee_rate < local_fee_rate {
+            return Ok((0, local_fee_rate));
+        }
+        let max_fee_rate = local_shutdown_info.max_fee_rate.unwrap_or(local_fee_rate);
+        if remote_fee_rate > max_fee_rate {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Proposed shutdown fee rate {} exceeds our max fee rate {}",
+                remote_fee_rate, max_fee_rate
+            )));
+        }
+        self.check_shutdown_fee_rate(
+            FeeRate::from_u64(remote_fee_rate),
+            &local_shutdown_info.close_script,
+        )?;
+        debug!(
+            "Accepting counter-proposed shutdown fee rate {} (proposed {})",
+            remote_fee_rate, local_fee_rate
+        );
+        Ok((remote_fee_rate, 0))
+    }
+
+    /// The fee of the shutdown transaction, available once both parties have agreed on the fee rate.
+    pub fn get_shutdown_fee(&self) -> Option<u64> {
+        let local_shutdown_info = self.local_shutdown_info.as_ref()?;
+        let remote_shutdown_info = self.remote_shutdown_info.as_ref()?;
+        let local_shutdown_fee = calculate_shutdown_tx_fee(
+            local_shutdown_info.fee_rate,
+            &self.funding_udt_type_script,
+            (
+                remote_shutdown_info.close_script.clone(),
+                local_shutdown_info.close_script.clone(),
+            ),
+        );
+        let remote_shutdown_fee = calculate_shutdown_tx_fee(
+            remote_shutdown_info.fee_rate,
+            &self.funding_udt_type_script,
+            (
+                local_shutdown_info.close_script.clone(),
+                remote_shutdown_info.close_script.clone(),
+            ),
+        );
+        Some(local_shutdown_fee + remote_shutdown_fee)
+    }
+
+    pub fn check_tlc_hash_algorithm(
+        &self,
+        hash_algorithm: HashAlgorithm,
+    ) -> ProcessingChannelResult {
+        if !self.hash_algorithms.contains(&hash_algorithm) {
+            return Err(ProcessingChannelError::UnsupportedHashAlgorithm(
+                hash_algorithm,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn check_tlc_onion_version(&self, onion_packet: &[u8]) -> ProcessingChannelResult {
+        match get_onion_packet_version(onion_packet) {
+            Some(version) if !self.onion_versions.contains(&version) => {
+                Err(ProcessingChannelError::UnsupportedOnionVersion(version))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // The preferred onion packet version of this channel.
+    pub fn get_onion_version(&self) -> u8 {
+        self.onion_versions.first().copied().unwrap_or_default()
+    }
+
+    // The open can be aborted safely only if we have not sent our signatures of the funding
+    // transaction, otherwise the peer may broadcast it anytime.
+    fn can_abort_open(&self) -> bool {
+        match self.state {
+            ChannelState::NegotiatingFunding(_)
+            | ChannelState::CollaboratingFundingTx(_)
+            | ChannelState::SigningCommitment(_) => true,
+            ChannelState::AwaitingTxSignatures(flags) => {
+                !flags.contains(AwaitingTxSignaturesFlags::OUR_TX_SIGNATURES_SENT)
+            }
+            _ => false,
+        }
+    }
+
+    // Mark the open as aborted, the network actor stops this channel actor and forgets the
+    // channel once it's notified.
+    fn abort_open(&mut self, reason: &str, network: &ActorRef<NetworkActorMessage>) {
+        warn!(
+            "Aborting the open of channel {:?}: {}",
+            self.get_id(),
+            reason
+        );
+        self.open_error = Some(reason.to_string());
+        self.update_state(ChannelState::Closed(CloseFlags::ABORTED));
+        network
+            .send_message(NetworkActorMessage::new_event(
+                NetworkActorEvent::ChannelOpenAborted(self.get_id(), self.get_remote_peer_id()),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+    }
+
+    // Keep the error encountered before the channel is ready, so that a stalled open can be diagnosed.
+    fn record_open_error(&mut self, error: &ProcessingChannelError) {
+        if self.state.open_step().is_opening() {
+            self.open_error = Some(error.to_string());
+        }
+    }
+
     pub fn check_for_tlc_update(&self, add_tlc_amount: Option<u128>) -> ProcessingChannelResult {
         match self.state {
             ChannelState::ChannelReady() => {}
@@ -3960,6 +5850,53 @@ impl ChannelActorState {
         Ok(())
     }
 
+    // The dust tlc limit of this channel, the default limit of the node only applies to CKB
+    // channels because the amounts of the UDT channels are not comparable with it.
+    pub fn get_dust_tlc_limit(&self, default_limit: DustTlcLimit) -> Option<DustTlcLimit> {
+        self.dust_tlc_limit.or_else(|| {
+            self.funding_udt_type_script
+                .is_none()
+                .then_some(default_limit)
+        })
+    }
+
+    // The total amount of the dust tlcs in flight, in both directions.
+    pub fn get_dust_tlc_exposure(&self, limit: &DustTlcLimit) -> u128 {
+        self.get_active_received_tlcs(true)
+            .chain(self.get_active_offered_tlcs(true))
+            .filter(|tlc| tlc.tlc.amount < limit.threshold)
+            .map(|tlc| tlc.tlc.amount)
+            .sum()
+    }
+
+    pub fn check_dust_tlc_exposure(
+        &self,
+        add_tlc_amount: u128,
+        default_limit: DustTlcLimit,
+    ) -> ProcessingChannelResult {
+        let Some(limit) = self.get_dust_tlc_limit(default_limit) else {
+            return Ok(());
+        };
+        if limit.max_exposure == 0 || add_tlc_amount >= limit.threshold {
+            return Ok(());
+        }
+        if self.get_dust_tlc_exposure(&limit) + add_tlc_amount > limit.max_exposure {
+            return Err(ProcessingChannelError::DustTlcExposureExceedLimit);
+        }
+        Ok(())
+    }
+
+    // The absolute tlc max value is enforced by the forwarding policy, while the max value
+    // relative to the capacity is checked here for the tlcs in both directions.
+    pub fn check_tlc_fraction_of_capacity(&self, add_tlc_amount: u128) -> ProcessingChannelResult {
+        match self.get_max_tlc_value_of_capacity() {
+            Some(max_value) if add_tlc_amount > max_value => Err(
+                ProcessingChannelError::TlcAmountExceedCapacityFraction(max_value),
+            ),
+            _ => Ok(()),
+        }
+    }
+
     pub fn create_outbounding_tlc(&self, command: AddTlcCommand) -> TLC {
         // TODO: we are filling the user command with a new id here.
         // The advantage of this is that we don't need to burden the users to
@@ -4135,12 +6072,21 @@ impl ChannelActorState {
                     shutdown_tx_size(
                         &self.funding_udt_type_script,
                         (
-                            self.local_shutdown_script.clone().unwrap(),
-                            self.remote_shutdown_script.clone().unwrap()
+                            self.local_shutdown_info
+                                .as_ref()
+                                .unwrap()
+                                .close_script
+                                .clone(),
+                            self.remote_shutdown_info
+                                .as_ref()
+                                .unwrap()
+                                .close_script
+                                .clone()
                         )
                     )
                 );
 
+                self.closing_tx_hash = Some(tx.hash().into());
                 self.update_state(ChannelState::Closed(CloseFlags::COOPERATIVE));
 
                 network
@@ -4184,6 +6130,8 @@ impl ChannelActorState {
 
         self.to_remote_amount = accept_channel.funding_amount;
         self.remote_reserved_ckb_amount = accept_channel.reserved_ckb_amount;
+        // The channel is zero-conf only if the acceptor agrees, older peers never agree.
+        self.zero_conf = self.zero_conf && accept_channel.is_zero_conf();
 
         self.remote_nonce = Some(accept_channel.next_local_nonce.clone());
         let remote_pubkeys = (&accept_channel).into();
@@ -4736,6 +6684,7 @@ impl ChannelActorState {
         &mut self,
         reestablish_channel: &ReestablishChannel,
         network: &ActorRef<NetworkActorMessage>,
+        force_close_on_divergence: bool,
     ) -> ProcessingChannelResult {
         debug!(
             "Handling reestablish channel message: {:?}, our commitment_numbers {:?}",
@@ -4750,6 +6699,56 @@ impl ChannelActorState {
             ChannelState::ChannelReady() => {
                 let expected_local_commitment_number = self.get_local_commitment_number();
                 let acutal_local_commitment_number = reestablish_channel.remote_commitment_number;
+                let expected_remote_commitment_number = self.get_remote_commitment_number();
+                let acutal_remote_commitment_number = reestablish_channel.local_commitment_number;
+
+                // Only a lost RevokeAndAck message can leave the commitment numbers of the two
+                // parties one apart. Any other difference can't be fixed by retransmitting messages,
+                // e.g. one of the parties has restored the channel from a stale backup.
+                if (acutal_local_commitment_number != expected_local_commitment_number
+                    && acutal_local_commitment_number != expected_local_commitment_number + 1)
+                    || (acutal_remote_commitment_number != expected_remote_commitment_number
+                        && acutal_remote_commitment_number + 1 != expected_remote_commitment_number)
+                {
+                    let reason = format!(
+                        "local commitment number expected {}, actual {}, remote commitment number expected {}, actual {}",
+                        expected_local_commitment_number,
+                        acutal_local_commitment_number,
+                        expected_remote_commitment_number,
+                        acutal_remote_commitment_number
+                    );
+                    error!(
+                        "Channel {:?} diverged from the peer on reestablish: {}",
+                        self.get_id(),
+                        &reason
+                    );
+                    // The peer claims commitments which we don't know, our latest commitment
+                    // transaction may have been revoked, and broadcasting it would give the peer
+                    // the whole channel balance. Only the peer can close the channel safely.
+                    let stale_local_state = acutal_local_commitment_number
+                        > expected_local_commitment_number + 1
+                        || acutal_remote_commitment_number > expected_remote_commitment_number;
+                    let mut force_closed = false;
+                    if stale_local_state {
+                        self.halted = Some(ChannelHaltReason::StaleLocalState {
+                            reason: reason.clone(),
+                        });
+                    } else if force_close_on_divergence {
+                        self.force_shutdown(network)?;
+                        force_closed = true;
+                    }
+                    self.notify_reestablish_outcome(
+                        network,
+                        ReestablishOutcome::Diverged {
+                            reason,
+                            stale_local_state,
+                            force_closed,
+                        },
+                    );
+                    return Ok(());
+                }
+
+                let mut outcome = ReestablishOutcome::Synced;
                 if acutal_local_commitment_number == expected_local_commitment_number {
                     // resend AddTlc, RemoveTlc and CommitmentSigned messages if needed
                     let mut need_resend_commitment_signed = false;
@@ -4812,33 +6811,21 @@ impl ChannelActorState {
                                 }),
                             ))
                             .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                        outcome = ReestablishOutcome::Resynced;
                     }
-                } else if acutal_local_commitment_number == expected_local_commitment_number + 1 {
-                    // wait for remote to resend the RevokeAndAck message, do nothing here
                 } else {
-                    // unreachable state, just log an error for potential bugs
-                    error!(
-                        "Reestablish channel message with invalid local commitment number: expected {}, actual {}",
-                        expected_local_commitment_number, acutal_local_commitment_number
-                    );
+                    // wait for remote to resend the RevokeAndAck message, do nothing here
+                    outcome = ReestablishOutcome::Resynced;
                 }
 
-                let expected_remote_commitment_number = self.get_remote_commitment_number();
-                let acutal_remote_commitment_number = reestablish_channel.local_commitment_number;
-                if expected_remote_commitment_number == acutal_remote_commitment_number {
-                    // synced with remote, do nothing
-                } else if expected_remote_commitment_number == acutal_remote_commitment_number + 1 {
+                if acutal_remote_commitment_number + 1 == expected_remote_commitment_number {
                     // Resetting our remote commitment number to the actual remote commitment number
                     // and resend the RevokeAndAck message.
                     self.set_remote_commitment_number(acutal_remote_commitment_number);
                     self.send_revoke_and_ack_message(network);
-                } else {
-                    // unreachable state, just log an error for potential bugs
-                    error!(
-                        "Reestablish channel message with invalid remote commitment number: expected {}, actual {}",
-                        expected_remote_commitment_number, acutal_remote_commitment_number
-                    );
+                    outcome = ReestablishOutcome::Resynced;
                 }
+                self.notify_reestablish_outcome(network, outcome);
             }
             _ => {
                 // TODO: @quake we need to handle other states.
@@ -4851,6 +6838,65 @@ impl ChannelActorState {
         Ok(())
     }
 
+    fn notify_reestablish_outcome(
+        &self,
+        network: &ActorRef<NetworkActorMessage>,
+        outcome: ReestablishOutcome,
+    ) {
+        network
+            .send_message(NetworkActorMessage::new_event(
+                NetworkActorEvent::NetworkServiceEvent(NetworkServiceEvent::ChannelReestablished(
+                    self.get_remote_peer_id(),
+                    self.get_id(),
+                    outcome,
+                )),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+    }
+
+    // Broadcast our latest commitment transaction to close the channel unilaterally. It's refused
+    // if our state is known to be stale, as the latest commitment transaction may have been revoked.
+    pub fn force_shutdown(
+        &mut self,
+        network: &ActorRef<NetworkActorMessage>,
+    ) -> ProcessingChannelResult {
+        match self.state {
+            ChannelState::ChannelReady() => {
+                debug!("Handling force shutdown command in ChannelReady state");
+            }
+            ChannelState::ShuttingDown(flags) => {
+                debug!(
+                    "Handling force shutdown command in ShuttingDown state, flags: {:?}",
+                    &flags
+                );
+            }
+            _ => {
+                return Err(ProcessingChannelError::InvalidState(format!(
+                    "Handling force shutdown command invalid state {:?}",
+                    &self.state
+                )));
+            }
+        };
+        if let Some(ChannelHaltReason::StaleLocalState { reason }) = &self.halted {
+            return Err(ProcessingChannelError::InvalidState(format!(
+                "Refusing to force shutdown a channel with stale state ({}), the latest commitment transaction may have been revoked",
+                reason
+            )));
+        }
+
+        // when channel is in ChannelReady or ShuttingDown state, the latest_commitment_transaction should exist
+        let transaction = self.latest_commitment_transaction.clone().unwrap();
+        network
+            .send_message(NetworkActorMessage::new_event(
+                NetworkActorEvent::CommitmentTransactionPending(transaction, self.get_id()),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+        self.update_state(ChannelState::ShuttingDown(
+            ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION,
+        ));
+        Ok(())
+    }
+
     fn is_tx_final(&self, tx: &Transaction) -> Result<bool, ProcessingChannelError> {
         // TODO: check if the tx is valid
         let tx = tx.clone().into_view();
@@ -5391,10 +7437,200 @@ impl ChannelActorState {
     }
 }
 
+/// The outcome of the reestablish handshake of a ready channel after the peer reconnects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReestablishOutcome {
+    /// Both parties agree on the commitment numbers, nothing is lost in the disconnection.
+    Synced,
+    /// The messages lost in the disconnection are retransmitted by either party.
+    Resynced,
+    /// The commitment numbers claimed by the peer can't be reconciled with ours. If the peer is
+    /// behind, the channel is force closed if the node is configured to do so. If the peer claims
+    /// commitments ahead of ours, our state is stale and the channel is halted instead.
+    Diverged {
+        reason: String,
+        stale_local_state: bool,
+        force_closed: bool,
+    },
+}
+
+/// Why a channel is halted, a halted channel is not reestablished nor updated any more.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ChannelHaltReason {
+    /// The peer claims commitments ahead of ours on reestablish. Our latest commitment transaction
+    /// may have been revoked and is never broadcasted, the peer is expected to close the channel.
+    StaleLocalState { reason: String },
+    /// The audit run on a commitment update found inconsistencies in the channel state.
+    AuditFailed { issues: Vec<String> },
+}
+
+/// The result of auditing the commitment numbers of a channel against the states depending on them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChannelAudit {
+    /// The time when the audit is run, in milliseconds since UNIX epoch.
+    pub audited_at: u64,
+    pub commitment_numbers: CommitmentNumbers,
+    /// The number of the commitment points received from the peer.
+    pub remote_commitment_points: u64,
+    /// The number of the commitment transactions revoked by the peer, each revocation
+    /// increments the local commitment number and provides the next commitment point.
+    pub revoked_remote_commitments: u64,
+    /// The inconsistencies found, empty if the channel is consistent.
+    pub issues: Vec<String>,
+}
+
+/// An event recorded in the event log of a channel.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ChannelLogEvent {
+    StateChanged {
+        from: ChannelState,
+        to: ChannelState,
+    },
+    TlcAdded {
+        tlc_id: TLCId,
+        #[serde_as(as = "U128Hex")]
+        amount: u128,
+        payment_hash: Hash256,
+    },
+    TlcSettled {
+        tlc_id: TLCId,
+    },
+    TlcFailed {
+        tlc_id: TLCId,
+    },
+}
+
+/// An entry of the append-only event log of a channel.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelLogEntry {
+    /// The index of the entry in the log, increasing from 0.
+    #[serde_as(as = "U64Hex")]
+    pub index: u64,
+    /// The time when the event is recorded, in milliseconds since UNIX epoch.
+    #[serde_as(as = "U64Hex")]
+    pub timestamp: u64,
+    pub event: ChannelLogEvent,
+}
+
+// The state of the channel which the events are derived from, taken before and after
+// processing a message.
+struct ChannelLogSnapshot {
+    state: ChannelState,
+    tlcs: Vec<(TLCId, bool)>,
+}
+
+impl ChannelLogSnapshot {
+    fn new(state: &ChannelActorState) -> Self {
+        Self {
+            state: state.state,
+            tlcs: state
+                .tlcs
+                .iter()
+                .map(|(id, tlc)| (*id, tlc.removed_at.is_some()))
+                .collect(),
+        }
+    }
+
+    // The events happened between this snapshot and the current state.
+    fn events_since(&self, state: &ChannelActorState) -> Vec<ChannelLogEvent> {
+        let mut events = vec![];
+        if self.state != state.state {
+            events.push(ChannelLogEvent::StateChanged {
+                from: self.state,
+                to: state.state,
+            });
+        }
+        let previous: BTreeMap<TLCId, bool> = self.tlcs.iter().copied().collect();
+        for (id, tlc) in &state.tlcs {
+            let was_removed = previous.get(id).copied();
+            if was_removed.is_none() {
+                events.push(ChannelLogEvent::TlcAdded {
+                    tlc_id: *id,
+                    amount: tlc.tlc.amount,
+                    payment_hash: tlc.tlc.payment_hash,
+                });
+            }
+            if was_removed != Some(true) {
+                match &tlc.removed_at {
+                    Some((_, RemoveTlcReason::RemoveTlcFulfill(_))) => {
+                        events.push(ChannelLogEvent::TlcSettled { tlc_id: *id })
+                    }
+                    Some((_, RemoveTlcReason::RemoveTlcFail(_))) => {
+                        events.push(ChannelLogEvent::TlcFailed { tlc_id: *id })
+                    }
+                    None => {}
+                }
+            }
+        }
+        events
+    }
+}
+
 pub trait ChannelActorStateStore {
     fn get_channel_actor_state(&self, id: &Hash256) -> Option<ChannelActorState>;
-    fn insert_channel_actor_state(&self, state: ChannelActorState);
+    // Gets the states of the channels in one read, the result is in the same order as `ids`.
+    fn get_channel_actor_states(&self, ids: &[Hash256]) -> Vec<Option<ChannelActorState>> {
+        ids.iter()
+            .map(|id| self.get_channel_actor_state(id))
+            .collect()
+    }
+    // Returns an error instead of overwriting the state of another channel with the same id.
+    fn insert_channel_actor_state(
+        &self,
+        state: ChannelActorState,
+    ) -> Result<(), ProcessingChannelError>;
+    // Saves the state of the channel which has just been closed along with its archive, in one
+    // atomic write so that a closed channel is never left without the archive.
+    fn insert_closed_channel_actor_state(
+        &self,
+        state: ChannelActorState,
+        closed_channel: ClosedChannel,
+    ) -> Result<(), ProcessingChannelError>;
     fn delete_channel_actor_state(&self, id: &Hash256);
+    // Appends the events to the event log of the channel, the oldest entries are removed so that
+    // at most `max_size` entries are kept.
+    fn append_channel_events(
+        &self,
+        id: &Hash256,
+        events: Vec<ChannelLogEvent>,
+        timestamp: u64,
+        max_size: u64,
+    );
+    // Returns at most `limit` entries of the event log of the channel, starting from the entry
+    // following the index `after`.
+    fn get_channel_events(
+        &self,
+        id: &Hash256,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Vec<ChannelLogEntry>;
+    // The label set by the operator to identify the channel.
+    fn get_channel_label(&self, id: &Hash256) -> Option<String>;
+    // Sets the label of the channel, or removes it if `label` is None.
+    fn set_channel_label(&self, id: &Hash256, label: Option<String>);
+    // The default routing policy of the new channels with the peer.
+    fn get_peer_fee_policy(&self, peer_id: &PeerId) -> Option<PeerFeePolicy>;
+    // Sets the default routing policy of the peer, or removes it if `policy` is None.
+    fn set_peer_fee_policy(&self, peer_id: &PeerId, policy: Option<PeerFeePolicy>);
+    // Archives the summary of the closed channel, indexed by the time it's closed.
+    fn insert_closed_channel(&self, closed_channel: ClosedChannel);
+    // Returns at most `limit` closed channels in the order of the closing time, starting from
+    // the one following the cursor `after`, or from `closed_after` (inclusive) if there is no
+    // cursor. The channels closed at or after `closed_before` are not returned.
+    fn get_closed_channels(
+        &self,
+        limit: usize,
+        after: Option<JsonBytes>,
+        closed_after: Option<u64>,
+        closed_before: Option<u64>,
+    ) -> (Vec<ClosedChannel>, JsonBytes);
+    // Removes the archives of the channels closed before the timestamp, along with the states,
+    // labels and event logs of the channels. Returns the number of the removed archives.
+    fn prune_closed_channels(&self, before: u64) -> usize;
     fn get_channel_ids_by_peer(&self, peer_id: &PeerId) -> Vec<Hash256>;
     fn get_active_channel_ids_by_peer(&self, peer_id: &PeerId) -> Vec<Hash256> {
         self.get_channel_ids_by_peer(peer_id)
@@ -5642,6 +7878,30 @@ impl TLC {
     }
 }
 
+// An offered tlc which forwards a received tlc of the previous channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForwardingTlc {
+    pub tlc_id: TLCId,
+    pub previous_tlc: (Hash256, TLCId),
+    // The tip block number when the tlc is added to the channel.
+    pub added_at_block: Option<u64>,
+    // The reason of the removal if the tlc is already removed by the next hop.
+    pub removed: Option<RemoveTlcReason>,
+}
+
+impl ForwardingTlc {
+    // The reason to fail back the previous tlc, None if the tlc is fulfilled by the next hop or
+    // may still be fulfilled. A pending tlc can be fulfilled by the next hop even after its lock
+    // time, which only starts counting once the commitment transaction is confirmed on-chain, so
+    // only a failure removed by the next hop is passed on with its original reason.
+    pub fn fail_back_reason(&self) -> Option<RemoveTlcReason> {
+        match &self.removed {
+            Some(RemoveTlcReason::RemoveTlcFail(_)) => self.removed.clone(),
+            _ => None,
+        }
+    }
+}
+
 /// A tlc output in a commitment transaction, including both the tlc output
 /// and the commitment_number that it first appeared (will appear) in the
 /// commitment transaction.
@@ -5667,6 +7927,16 @@ pub struct DetailedTLCInfo {
     // The initial commitment number of the party (the offerer) that
     // has confirmed the removal of this tlc.
     removal_confirmed_at: Option<CommitmentNumbers>,
+    // The timestamp in milliseconds when this received tlc is forwarded to the next hop.
+    #[serde(default)]
+    forwarded_at: Option<u64>,
+    // The timestamp in milliseconds when this tlc is added to the channel,
+    // None if the tlc is saved before it's tracked.
+    #[serde(default)]
+    added_at: Option<u64>,
+    // The tip block number when this tlc is added to the channel, None if it's unknown.
+    #[serde(default)]
+    added_at_block: Option<u64>,
 }
 
 impl DetailedTLCInfo {
@@ -5674,6 +7944,15 @@ impl DetailedTLCInfo {
         self.tlc.is_offered()
     }
 
+    // The block number at which the tlc expires, the lock time is the number of blocks
+    // after the block at which it's added.
+    fn expires_at_block(&self) -> Option<u64> {
+        Some(
+            self.added_at_block?
+                .saturating_add(u64::from(self.tlc.lock_time)),
+        )
+    }
+
     fn get_commitment_numbers(&self, local: bool) -> CommitmentNumbers {
         let am_i_sending_the_tlc = {
             if self.is_offered() {
@@ -1,5 +1,14 @@
-use crate::Result;
+use super::fee::SuggestedFeeRates;
+use super::graph::GossipFilter;
+use super::hash_algorithm::HashAlgorithm;
+use super::types::{HopData, PaymentHopData, SUPPORTED_ONION_VERSIONS};
+use crate::store::{
+    MaxValueSizes, DEFAULT_CHANNEL_STATE_CACHE_SIZE, DEFAULT_MAX_GOSSIP_VALUE_SIZE,
+    DEFAULT_MAX_VALUE_SIZE, DEFAULT_READ_MAX_RETRIES,
+};
+use crate::{ckb::contracts::get_udt_info, Result};
 use ckb_sdk::NetworkType;
+use ckb_types::packed::Script;
 use clap::ValueEnum;
 use clap_serde_derive::{
     clap::{self},
@@ -7,9 +16,9 @@ use clap_serde_derive::{
 };
 #[cfg(not(test))]
 use once_cell::sync::OnceCell;
-use serde::{Deserialize, Deserializer, Serializer};
-use std::{fs, path::PathBuf};
-use tentacle::secio::{PublicKey, SecioKeyPair};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fs, path::PathBuf, str::FromStr};
+use tentacle::secio::{PeerId, PublicKey, SecioKeyPair};
 
 pub const CKB_SHANNONS: u64 = 100_000_000; // 1 CKB = 10 ^ 8 shannons
 pub const DEFAULT_MIN_INBOUND_LIQUIDITY: u64 = 100 * CKB_SHANNONS; // 100 CKB for minimal inbound liquidity
@@ -39,6 +48,10 @@ pub const DEFAULT_CHANNEL_MIN_AUTO_CKB_AMOUNT: u64 =
 /// The locktime expiry delta to forward a tlc, in seconds. 86400 means 1 day.
 pub const DEFAULT_TLC_LOCKTIME_EXPIRY_DELTA: u64 = 86400;
 
+/// The locktime expiry delta reserved by the payer for a trampoline node, which covers the expiry
+/// delta of the trampoline node itself and the route from it to the target.
+pub const DEFAULT_TRAMPOLINE_EXPIRY_DELTA: u64 = 3 * DEFAULT_TLC_LOCKTIME_EXPIRY_DELTA;
+
 /// The minimal value of a tlc. 0 means no minimal value.
 pub const DEFAULT_TLC_MIN_VALUE: u128 = 0;
 
@@ -48,6 +61,9 @@ pub const DEFAULT_TLC_MAX_VALUE: u128 = 0;
 /// The fee for forwarding peer tlcs. Proportional to the amount of the forwarded tlc. The unit is millionths of the amount. 1000 means 0.1%.
 pub const DEFAULT_TLC_FEE_PROPORTIONAL_MILLIONTHS: u128 = 1000;
 
+/// The timeout to fail back an unresolved forwarded tlc, in blocks. 0 means never failing back before the tlc expires.
+pub const DEFAULT_TLC_RESOLUTION_TIMEOUT_BLOCKS: u64 = 0;
+
 /// Whether to automatically announce the node on startup. false means not announcing.
 pub const DEFAULT_AUTO_ANNOUNCE_NODE: bool = true;
 
@@ -57,6 +73,116 @@ pub const DEFAULT_ANNOUNCE_NODE_INTERVAL_SECONDS: u64 = 3600;
 /// Whether to sync the network graph from the network. true means syncing.
 pub const DEFAULT_SYNC_NETWORK_GRAPH: bool = true;
 
+/// The percentage of the network graph to sync before the node is ready to send payments. 0 means not gating on the graph sync.
+pub const DEFAULT_PAYMENT_READY_MIN_GRAPH_SYNC_PROGRESS: u64 = 0;
+
+/// The number of the connected peers before the node is ready to send payments. 0 means not gating on the peers.
+pub const DEFAULT_PAYMENT_READY_MIN_PEERS: u64 = 0;
+
+/// The maximal number of gossip messages accepted from a single peer within one rate limit interval. 0 means no limit.
+pub const DEFAULT_GOSSIP_RATE_LIMIT_MAX_MESSAGES: u64 = 1000;
+
+/// The interval of the gossip rate limit window, in seconds.
+pub const DEFAULT_GOSSIP_RATE_LIMIT_INTERVAL_SECONDS: u64 = 60;
+
+/// The maximal tolerated skew of the gossip message timestamps ahead of the local time, in seconds. 0 means no limit.
+pub const DEFAULT_GOSSIP_MAX_FUTURE_SKEW_SECONDS: u64 = 60 * 60;
+
+/// The maximal number of concurrently connected peers, beyond which the inbound connections are rejected. 0 means no limit.
+pub const DEFAULT_MAX_PEERS: u64 = 128;
+
+/// The maximal number of inbound connections accepted from a single IP address within one rate limit interval. 0 means no limit.
+pub const DEFAULT_CONNECTION_RATE_LIMIT_MAX_CONNECTIONS: u64 = 20;
+
+/// The interval of the connection rate limit window, in seconds.
+pub const DEFAULT_CONNECTION_RATE_LIMIT_INTERVAL_SECONDS: u64 = 60;
+
+/// The interval in seconds to persist the network graph snapshot used for fast restart. 0 means disabled.
+pub const DEFAULT_GRAPH_SNAPSHOT_INTERVAL_SECONDS: u64 = 600;
+
+/// The channels of the nodes within this number of hops from the gossip filter nodes are also accepted.
+pub const DEFAULT_GOSSIP_FILTER_MAX_HOPS: u8 = 0;
+
+/// The default timeout of a payment in seconds, used when the timeout is not specified in the payment request.
+pub const DEFAULT_PAYMENT_TIMEOUT_SECONDS: u64 = 60;
+
+/// The maximal fee of a payment route in percentage of the payment amount. 0 means no limit.
+pub const DEFAULT_PAYMENT_MAX_FEE_PERCENT: u64 = 5;
+
+/// The age in seconds after which a finished payment session is pruned from the store. 0 means never pruning.
+pub const DEFAULT_PAYMENT_SESSION_RETENTION_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// The number of the most recent payment sessions which are always kept in the store regardless of their age.
+pub const DEFAULT_PAYMENT_SESSION_RETENTION_COUNT: u64 = 1000;
+
+/// The age in seconds after which the archive of a closed channel is pruned from the store. 0 means never pruning.
+pub const DEFAULT_CLOSED_CHANNEL_RETENTION_SECONDS: u64 = 0;
+
+/// Whether to remove the invoice preimages from the store and the channel states once the settlements are irrevocably committed. false means keeping them.
+pub const DEFAULT_FORGET_SETTLED_PREIMAGES: bool = false;
+
+/// The percentage by which a counter-proposed shutdown fee rate may exceed our own proposal and still be accepted,
+/// used when the max fee rate is not specified in the shutdown request.
+pub const DEFAULT_SHUTDOWN_FEE_RATE_TOLERANCE_PERCENT: u64 = 10;
+
+/// The minimal fee rate of the shutdown transaction accepted when the remote peer initiates the shutdown,
+/// a lower proposal is countered with this fee rate. Same as the default commitment fee rate.
+pub const DEFAULT_MIN_SHUTDOWN_FEE_RATE: u64 = 1000;
+
+/// The maximal funding fee rate of the inbound channels, the opens with a higher funding fee rate are rejected.
+/// 100 times of the default funding fee rate.
+pub const DEFAULT_MAX_ACCEPTED_FUNDING_FEE_RATE: u64 = 100_000;
+
+/// The maximal number of the events kept in the event log of a channel, the oldest events are
+/// removed when the log is full.
+pub const DEFAULT_CHANNEL_EVENT_LOG_MAX_SIZE: u64 = 1000;
+
+/// Whether to force close a channel whose peer is behind our state on reestablish.
+pub const DEFAULT_REESTABLISH_FORCE_CLOSE_ON_DIVERGENCE: bool = false;
+
+/// Whether to audit a channel on each commitment update and halt it on any inconsistency.
+pub const DEFAULT_AUDIT_COMMITMENT_UPDATES: bool = true;
+
+/// The fixed delay before failing back a received tlc, in milliseconds. 0 means no delay.
+pub const DEFAULT_TLC_FAIL_DELAY_MS: u64 = 0;
+
+/// The maximal random delay added to the fixed delay before failing back a received tlc, in milliseconds.
+pub const DEFAULT_TLC_FAIL_DELAY_JITTER_MS: u64 = 0;
+
+/// The maximal random delay before fulfilling a forwarded tlc, in milliseconds. 0 means no delay.
+pub const DEFAULT_TLC_FULFILL_DELAY_JITTER_MS: u64 = 0;
+
+/// The upper bound of `tlc_fulfill_delay_jitter_ms`, in milliseconds, larger values are capped.
+pub const MAX_TLC_FULFILL_DELAY_JITTER_MS: u64 = 30_000;
+
+/// The delay before gossiping the channel update changed by `update_channel`, in milliseconds. 0 means no delay.
+pub const DEFAULT_CHANNEL_UPDATE_BROADCAST_DELAY_MS: u64 = 0;
+
+/// The tlcs of CKB channels with an amount below this threshold are dust, in shannons.
+pub const DEFAULT_DUST_TLC_THRESHOLD: u128 = 100000000;
+
+/// The maximal total amount of the dust tlcs in flight of a CKB channel, in shannons. 0 means no limit.
+pub const DEFAULT_MAX_DUST_TLC_EXPOSURE: u128 = 1000000000;
+
+/// The expiry time of the invoices created without an expiry, in seconds.
+pub const DEFAULT_INVOICE_EXPIRY_SECONDS: u64 = 60 * 60;
+
+/// The maximal expiry time of the invoices created by the node, in seconds. 0 means no limit.
+pub const DEFAULT_MAX_INVOICE_EXPIRY_SECONDS: u64 = 30 * 24 * 60 * 60;
+
+/// The maximal size of the onion payload of a payment, in bytes, which is the whole onion packet data.
+pub const DEFAULT_MAX_ONION_PAYLOAD_SIZE: usize = PaymentHopData::PACKET_DATA_LEN;
+
+/// The maximal number of the recently rejected inbound tlcs kept in memory. 0 means not keeping them.
+pub const DEFAULT_REJECTED_TLCS_CAPACITY: usize = 1000;
+
+/// The timeout to abort a channel open whose funding transaction is not signed yet, in seconds. 0 means never aborting.
+pub const DEFAULT_OPEN_CHANNEL_TIMEOUT_SECONDS: u64 = 600;
+
+/// The number of confirmations after which the funding transaction of a channel with a trusted peer is
+/// treated as confirmed. 0 means treating it as confirmed once it's committed in a block.
+pub const DEFAULT_TRUSTED_FUNDING_CONFIRMATIONS: u64 = 1;
+
 // See comment in `LdkConfig` for why do we need to specify both name and long,
 // and prefix them with `ckb-`/`CKB_`.
 #[derive(ClapSerde, Debug, Clone)]
@@ -154,6 +280,15 @@ pub struct FiberConfig {
     )]
     pub tlc_fee_proportional_millionths: Option<u128>,
 
+    /// The timeout to fail back a forwarded tlc which is not resolved by the downstream node, in blocks since the tlc is added. The tlc is failed back only after the downstream tlc is failed, never while the downstream node may still fulfill it. It must be less than the tlc locktime expiry delta, which is also in blocks. 0 means never failing back before the tlc expires. [default: 0]
+    #[arg(
+        name = "FIBER_TLC_RESOLUTION_TIMEOUT_BLOCKS",
+        long = "fiber-tlc-resolution-timeout-blocks",
+        env,
+        help = "The timeout to fail back a forwarded tlc which is not resolved by the downstream node, in blocks since the tlc is added. The tlc is failed back only after the downstream tlc is failed, never while the downstream node may still fulfill it. It must be less than the tlc locktime expiry delta, which is also in blocks. 0 means never failing back before the tlc expires. [default: 0]"
+    )]
+    pub tlc_resolution_timeout_blocks: Option<u64>,
+
     /// Whether to automatically announce the node on startup. [default: true]
     #[arg(
         name = "FIBER_AUTO_ANNOUNCE_NODE",
@@ -182,6 +317,493 @@ pub struct FiberConfig {
         help = "Whether to sync the network graph from the network. [default: true]"
     )]
     pub(crate) sync_network_graph: Option<bool>,
+
+    /// The strategy to select the peers to sync the network graph from, can be any of `all`/`preferred` [default: all]
+    #[arg(
+        name = "FIBER_GRAPH_SYNC_PEER_STRATEGY",
+        long = "fiber-graph-sync-peer-strategy",
+        env
+    )]
+    pub(crate) graph_sync_peer_strategy: Option<GraphSyncPeerStrategy>,
+
+    /// The percentage of the network graph to sync after starting before the node is ready to send payments, the node is ready once either this or payment_ready_min_peers is reached. 0 means not gating on the graph sync. [default: 0]
+    #[arg(
+        name = "FIBER_PAYMENT_READY_MIN_GRAPH_SYNC_PROGRESS",
+        long = "fiber-payment-ready-min-graph-sync-progress",
+        env,
+        help = "The percentage of the network graph to sync after starting before the node is ready to send payments, the node is ready once either this or payment_ready_min_peers is reached. 0 means not gating on the graph sync. [default: 0]"
+    )]
+    pub(crate) payment_ready_min_graph_sync_progress: Option<u64>,
+
+    /// The number of the peers to connect after starting before the node is ready to send payments, the node is ready once either this or payment_ready_min_graph_sync_progress is reached. 0 means not gating on the peers. [default: 0]
+    #[arg(
+        name = "FIBER_PAYMENT_READY_MIN_PEERS",
+        long = "fiber-payment-ready-min-peers",
+        env,
+        help = "The number of the peers to connect after starting before the node is ready to send payments, the node is ready once either this or payment_ready_min_graph_sync_progress is reached. 0 means not gating on the peers. [default: 0]"
+    )]
+    pub(crate) payment_ready_min_peers: Option<u64>,
+
+    /// The maximal number of gossip messages accepted from a single peer within one rate limit interval, excess messages are dropped. 0 means no limit. [default: 1000]
+    #[arg(
+        name = "FIBER_GOSSIP_RATE_LIMIT_MAX_MESSAGES",
+        long = "fiber-gossip-rate-limit-max-messages",
+        env,
+        help = "The maximal number of gossip messages accepted from a single peer within one rate limit interval, excess messages are dropped. 0 means no limit. [default: 1000]"
+    )]
+    pub(crate) gossip_rate_limit_max_messages: Option<u64>,
+
+    /// The interval of the gossip rate limit window, in seconds. [default: 60]
+    #[arg(
+        name = "FIBER_GOSSIP_RATE_LIMIT_INTERVAL_SECONDS",
+        long = "fiber-gossip-rate-limit-interval-seconds",
+        env,
+        help = "The interval of the gossip rate limit window, in seconds. [default: 60]"
+    )]
+    pub(crate) gossip_rate_limit_interval_seconds: Option<u64>,
+
+    /// The maximal tolerated skew of the gossip message timestamps ahead of the local time, in seconds. Messages further in the future are dropped, as they would shadow the later legitimate updates. 0 means no limit. [default: 3600]
+    #[arg(
+        name = "FIBER_GOSSIP_MAX_FUTURE_SKEW_SECONDS",
+        long = "fiber-gossip-max-future-skew-seconds",
+        env,
+        help = "The maximal tolerated skew of the gossip message timestamps ahead of the local time, in seconds. Messages further in the future are dropped, as they would shadow the later legitimate updates. 0 means no limit. [default: 3600]"
+    )]
+    pub(crate) gossip_max_future_skew_seconds: Option<u64>,
+
+    /// The maximal number of concurrently connected peers, the inbound connections beyond it are rejected, while the peers with active channels are always accepted. It can be changed at runtime by the set_connection_limits RPC. 0 means no limit. [default: 128]
+    #[arg(
+        name = "FIBER_MAX_PEERS",
+        long = "fiber-max-peers",
+        env,
+        help = "The maximal number of concurrently connected peers, the inbound connections beyond it are rejected, while the peers with active channels are always accepted. It can be changed at runtime by the set_connection_limits RPC. 0 means no limit. [default: 128]"
+    )]
+    pub(crate) max_peers: Option<u64>,
+
+    /// The maximal number of inbound connections accepted from a single IP address within one rate limit interval. An address exceeding it is rejected for a backoff period, which starts from the interval and doubles on each repeated violation. It can be changed at runtime by the set_connection_limits RPC. 0 means no limit. [default: 20]
+    #[arg(
+        name = "FIBER_CONNECTION_RATE_LIMIT_MAX_CONNECTIONS",
+        long = "fiber-connection-rate-limit-max-connections",
+        env,
+        help = "The maximal number of inbound connections accepted from a single IP address within one rate limit interval. An address exceeding it is rejected for a backoff period, which starts from the interval and doubles on each repeated violation. It can be changed at runtime by the set_connection_limits RPC. 0 means no limit. [default: 20]"
+    )]
+    pub(crate) connection_rate_limit_max_connections: Option<u64>,
+
+    /// The interval of the connection rate limit window, in seconds. [default: 60]
+    #[arg(
+        name = "FIBER_CONNECTION_RATE_LIMIT_INTERVAL_SECONDS",
+        long = "fiber-connection-rate-limit-interval-seconds",
+        env,
+        help = "The interval of the connection rate limit window, in seconds. [default: 60]"
+    )]
+    pub(crate) connection_rate_limit_interval_seconds: Option<u64>,
+
+    /// The interval in seconds to persist a snapshot of the network graph, which is loaded at startup along with the channels and nodes changed after it instead of scanning all the stored channels and nodes. The snapshot is also persisted on shutdown. 0 means disabled. [default: 600]
+    #[arg(
+        name = "FIBER_GRAPH_SNAPSHOT_INTERVAL_SECONDS",
+        long = "fiber-graph-snapshot-interval-seconds",
+        env,
+        help = "The interval in seconds to persist a snapshot of the network graph, which is loaded at startup along with the channels and nodes changed after it instead of scanning all the stored channels and nodes. The snapshot is also persisted on shutdown. 0 means disabled. [default: 600]"
+    )]
+    pub(crate) graph_snapshot_interval_seconds: Option<u64>,
+
+    /// The default timeout of a payment in seconds, it bounds the total time of all the attempts of the payment. [default: 60]
+    #[arg(
+        name = "FIBER_PAYMENT_TIMEOUT_SECONDS",
+        long = "fiber-payment-timeout-seconds",
+        env,
+        help = "The default timeout of a payment in seconds, it bounds the total time of all the attempts of the payment. [default: 60]"
+    )]
+    pub(crate) payment_timeout_seconds: Option<u64>,
+
+    /// The maximal fee of a payment route in percentage of the payment amount, used when max_fee_percent is not specified in send_payment. 0 means no limit. [default: 5]
+    #[arg(
+        name = "FIBER_PAYMENT_MAX_FEE_PERCENT",
+        long = "fiber-payment-max-fee-percent",
+        env,
+        help = "The maximal fee of a payment route in percentage of the payment amount, used when max_fee_percent is not specified in send_payment. 0 means no limit. [default: 5]"
+    )]
+    pub(crate) payment_max_fee_percent: Option<u64>,
+
+    /// The age in seconds after which a finished (succeeded or failed) payment session is pruned from the store. 0 means never pruning. [default: 604800 (7 days)]
+    #[arg(
+        name = "FIBER_PAYMENT_SESSION_RETENTION_SECONDS",
+        long = "fiber-payment-session-retention-seconds",
+        env,
+        help = "The age in seconds after which a finished (succeeded or failed) payment session is pruned from the store. 0 means never pruning. [default: 604800 (7 days)]"
+    )]
+    pub(crate) payment_session_retention_seconds: Option<u64>,
+
+    /// The number of the most recent payment sessions which are always kept in the store as payment history, regardless of their age. [default: 1000]
+    #[arg(
+        name = "FIBER_PAYMENT_SESSION_RETENTION_COUNT",
+        long = "fiber-payment-session-retention-count",
+        env,
+        help = "The number of the most recent payment sessions which are always kept in the store as payment history, regardless of their age. [default: 1000]"
+    )]
+    pub(crate) payment_session_retention_count: Option<u64>,
+
+    /// The age in seconds after which the archive of a closed channel is pruned from the store along with the channel state, the closed channels can't be listed by list_closed_channels after that. 0 means never pruning. [default: 0]
+    #[arg(
+        name = "FIBER_CLOSED_CHANNEL_RETENTION_SECONDS",
+        long = "fiber-closed-channel-retention-seconds",
+        env,
+        help = "The age in seconds after which the archive of a closed channel is pruned from the store along with the channel state, the closed channels can't be listed by list_closed_channels after that. 0 means never pruning. [default: 0]"
+    )]
+    pub(crate) closed_channel_retention_seconds: Option<u64>,
+
+    /// Whether to remove the invoice preimages from the store and the channel states once the settlements are irrevocably committed, a settled invoice can't be paid again after its preimage is removed. [default: false]
+    #[arg(
+        name = "FIBER_FORGET_SETTLED_PREIMAGES",
+        long = "fiber-forget-settled-preimages",
+        env,
+        help = "Whether to remove the invoice preimages from the store and the channel states once the settlements are irrevocably committed, a settled invoice can't be paid again after its preimage is removed. [default: false]"
+    )]
+    pub(crate) forget_settled_preimages: Option<bool>,
+
+    /// The maximal number of the events kept in the event log of a channel, the oldest events are removed when the log is full, 0 means disabling the event log. [default: 1000]
+    #[arg(
+        name = "FIBER_CHANNEL_EVENT_LOG_MAX_SIZE",
+        long = "fiber-channel-event-log-max-size",
+        env,
+        help = "The maximal number of the events kept in the event log of a channel, the oldest events are removed when the log is full, 0 means disabling the event log. [default: 1000]"
+    )]
+    pub(crate) channel_event_log_max_size: Option<u64>,
+
+    /// Whether to force close a channel by broadcasting the latest commitment transaction when the commitment numbers claimed by the peer on reestablish are behind ours, i.e. the peer has lost its latest state. The channel is never force closed when the peer claims commitment numbers ahead of ours, as our latest commitment transaction may have been revoked, e.g. when the node is restored from a stale backup. Such a channel is halted instead, waiting for the peer to close it. [default: false]
+    #[arg(
+        name = "FIBER_REESTABLISH_FORCE_CLOSE_ON_DIVERGENCE",
+        long = "fiber-reestablish-force-close-on-divergence",
+        env,
+        help = "Whether to force close a channel by broadcasting the latest commitment transaction when the commitment numbers claimed by the peer on reestablish are behind ours, i.e. the peer has lost its latest state. The channel is never force closed when the peer claims commitment numbers ahead of ours, as our latest commitment transaction may have been revoked, e.g. when the node is restored from a stale backup. Such a channel is halted instead, waiting for the peer to close it. [default: false]"
+    )]
+    pub(crate) reestablish_force_close_on_divergence: Option<bool>,
+
+    /// Whether to audit a channel on each commitment update, e.g. the rotation of the commitment secrets and the commitment points of the peer. The channel is halted on any inconsistency instead of proceeding, and it is not reestablished after the node is restarted. The last audit result is returned by the audit_channel RPC. [default: true]
+    #[arg(
+        name = "FIBER_AUDIT_COMMITMENT_UPDATES",
+        long = "fiber-audit-commitment-updates",
+        env,
+        help = "Whether to audit a channel on each commitment update, e.g. the rotation of the commitment secrets and the commitment points of the peer. The channel is halted on any inconsistency instead of proceeding, and it is not reestablished after the node is restarted. The last audit result is returned by the audit_channel RPC. [default: true]"
+    )]
+    pub(crate) audit_commitment_updates: Option<bool>,
+
+    /// The fixed delay before failing back a received tlc, in milliseconds. A prober sending tlcs which are bound to fail can infer the liquidity of the channels from how fast the failures come back, delaying the failures makes the inference harder at the cost of slower retries for the legitimate payments which fail. Fulfilled tlcs are not delayed by it. [default: 0]
+    #[arg(
+        name = "FIBER_TLC_FAIL_DELAY_MS",
+        long = "fiber-tlc-fail-delay-ms",
+        env,
+        help = "The fixed delay before failing back a received tlc, in milliseconds. A prober sending tlcs which are bound to fail can infer the liquidity of the channels from how fast the failures come back, delaying the failures makes the inference harder at the cost of slower retries for the legitimate payments which fail. Fulfilled tlcs are not delayed by it. [default: 0]"
+    )]
+    pub(crate) tlc_fail_delay_ms: Option<u64>,
+
+    /// The maximal random delay added to `tlc_fail_delay_ms` before failing back a received tlc, in milliseconds. The jitter hides the time spent on processing the tlc, which a fixed delay alone doesn't. [default: 0]
+    #[arg(
+        name = "FIBER_TLC_FAIL_DELAY_JITTER_MS",
+        long = "fiber-tlc-fail-delay-jitter-ms",
+        env,
+        help = "The maximal random delay added to `tlc_fail_delay_ms` before failing back a received tlc, in milliseconds. The jitter hides the time spent on processing the tlc, which a fixed delay alone doesn't. [default: 0]"
+    )]
+    pub(crate) tlc_fail_delay_jitter_ms: Option<u64>,
+
+    /// The maximal random delay before fulfilling a forwarded tlc, in milliseconds, capped at 30000. The upstream peers may tell whether the next hop is the final recipient from how fast a forwarded tlc is fulfilled, the random delay blurs the timing at the cost of slower payments and longer locked liquidity in the upstream channels. The delay never exceeds half of the time left before the tlc expires, estimated from the blocks left, and the tlcs of our own invoices are fulfilled without delay. The delayed fulfillments are saved and resumed after the node restarts. [default: 0]
+    #[arg(
+        name = "FIBER_TLC_FULFILL_DELAY_JITTER_MS",
+        long = "fiber-tlc-fulfill-delay-jitter-ms",
+        env,
+        help = "The maximal random delay before fulfilling a forwarded tlc, in milliseconds, capped at 30000. The upstream peers may tell whether the next hop is the final recipient from how fast a forwarded tlc is fulfilled, the random delay blurs the timing at the cost of slower payments and longer locked liquidity in the upstream channels. The delay never exceeds half of the time left before the tlc expires, estimated from the blocks left, and the tlcs of our own invoices are fulfilled without delay. The delayed fulfillments are saved and resumed after the node restarts. [default: 0]"
+    )]
+    pub(crate) tlc_fulfill_delay_jitter_ms: Option<u64>,
+
+    /// The delay before gossiping the channel update changed by `update_channel`, in milliseconds. The changes are applied locally at once, but the updates within the delay are coalesced into a single gossiped channel update reflecting the last change, which avoids spamming the network while scripting many updates. 0 means the channel update is gossiped immediately. [default: 0]
+    #[arg(
+        name = "FIBER_CHANNEL_UPDATE_BROADCAST_DELAY_MS",
+        long = "fiber-channel-update-broadcast-delay-ms",
+        env,
+        help = "The delay before gossiping the channel update changed by `update_channel`, in milliseconds. The changes are applied locally at once, but the updates within the delay are coalesced into a single gossiped channel update reflecting the last change, which avoids spamming the network while scripting many updates. 0 means the channel update is gossiped immediately. [default: 0]"
+    )]
+    pub(crate) channel_update_broadcast_delay_ms: Option<u64>,
+
+    /// The tlcs of CKB channels with an amount below this threshold are dust, in shannons. A dust tlc is not worth claiming on chain, so it's lost to the fees in a force close. It can be overridden per channel by `update_channel`. [default: 100000000 (1 CKB)]
+    #[arg(
+        name = "FIBER_DUST_TLC_THRESHOLD",
+        long = "fiber-dust-tlc-threshold",
+        env,
+        help = "The tlcs of CKB channels with an amount below this threshold are dust, in shannons. A dust tlc is not worth claiming on chain, so it's lost to the fees in a force close. It can be overridden per channel by `update_channel`. [default: 100000000 (1 CKB)]"
+    )]
+    pub(crate) dust_tlc_threshold: Option<u128>,
+
+    /// The maximal total amount of the dust tlcs in flight of a CKB channel, in shannons. A new dust tlc, either offered or received, is rejected once the total exceeds it. It can be overridden per channel by `update_channel`. 0 means no limit. [default: 1000000000 (10 CKB)]
+    #[arg(
+        name = "FIBER_MAX_DUST_TLC_EXPOSURE",
+        long = "fiber-max-dust-tlc-exposure",
+        env,
+        help = "The maximal total amount of the dust tlcs in flight of a CKB channel, in shannons. A new dust tlc, either offered or received, is rejected once the total exceeds it. It can be overridden per channel by `update_channel`. 0 means no limit. [default: 1000000000 (10 CKB)]"
+    )]
+    pub(crate) max_dust_tlc_exposure: Option<u128>,
+
+    /// The expiry time of the invoices created by `new_invoice` without an expiry, in seconds. [default: 3600 (1 hour)]
+    #[arg(
+        name = "FIBER_DEFAULT_INVOICE_EXPIRY",
+        long = "fiber-default-invoice-expiry",
+        env,
+        help = "The expiry time of the invoices created by `new_invoice` without an expiry, in seconds. [default: 3600 (1 hour)]"
+    )]
+    pub(crate) default_invoice_expiry: Option<u64>,
+
+    /// The maximal expiry time of the invoices created by `new_invoice`, in seconds, invoices with longer expiries are rejected since they keep the preimages in the store for long. 0 means no limit. [default: 2592000 (30 days)]
+    #[arg(
+        name = "FIBER_MAX_INVOICE_EXPIRY",
+        long = "fiber-max-invoice-expiry",
+        env,
+        help = "The maximal expiry time of the invoices created by `new_invoice`, in seconds, invoices with longer expiries are rejected since they keep the preimages in the store for long. 0 means no limit. [default: 2592000 (30 days)]"
+    )]
+    pub(crate) max_invoice_expiry: Option<u64>,
+
+    /// The maximal size of the onion payload taken by the hops of a payment route, in bytes. Payments whose routes don't fit are rejected before sending any tlc. It can't exceed the onion packet data size 1300. [default: 1300]
+    #[arg(
+        name = "FIBER_MAX_ONION_PAYLOAD_SIZE",
+        long = "fiber-max-onion-payload-size",
+        env,
+        help = "The maximal size of the onion payload taken by the hops of a payment route, in bytes. Payments whose routes don't fit are rejected before sending any tlc. It can't exceed the onion packet data size 1300. [default: 1300]"
+    )]
+    pub(crate) max_onion_payload_size: Option<usize>,
+
+    /// The percentage by which a counter-proposed shutdown fee rate may exceed our own proposal and still be accepted, used when max_fee_rate is not specified in shutdown_channel. [default: 10]
+    #[arg(
+        name = "FIBER_SHUTDOWN_FEE_RATE_TOLERANCE_PERCENT",
+        long = "fiber-shutdown-fee-rate-tolerance-percent",
+        env,
+        help = "The percentage by which a counter-proposed shutdown fee rate may exceed our own proposal and still be accepted, used when max_fee_rate is not specified in shutdown_channel. [default: 10]"
+    )]
+    pub(crate) shutdown_fee_rate_tolerance_percent: Option<u64>,
+
+    /// The minimal fee rate of the shutdown transaction accepted when the remote peer initiates the shutdown, a lower proposal is countered with this fee rate. [default: 1000]
+    #[arg(
+        name = "FIBER_MIN_SHUTDOWN_FEE_RATE",
+        long = "fiber-min-shutdown-fee-rate",
+        env,
+        help = "The minimal fee rate of the shutdown transaction accepted when the remote peer initiates the shutdown, a lower proposal is countered with this fee rate. [default: 1000]"
+    )]
+    pub(crate) min_shutdown_fee_rate: Option<u64>,
+
+    /// The maximal funding fee rate of the inbound channels, the opens with a higher funding fee rate are rejected. [default: 100000]
+    #[arg(
+        name = "FIBER_MAX_ACCEPTED_FUNDING_FEE_RATE",
+        long = "fiber-max-accepted-funding-fee-rate",
+        env,
+        help = "The maximal funding fee rate of the inbound channels, the opens with a higher funding fee rate are rejected. [default: 100000]"
+    )]
+    pub(crate) max_accepted_funding_fee_rate: Option<u64>,
+
+    /// The listening address of the Prometheus metrics endpoint, e.g. 127.0.0.1:8229. [default: disabled]
+    #[arg(
+        name = "FIBER_METRICS_LISTENING_ADDR",
+        long = "fiber-metrics-listening-addr",
+        env,
+        help = "The listening address of the Prometheus metrics endpoint, e.g. 127.0.0.1:8229. [default: disabled]"
+    )]
+    pub(crate) metrics_listening_addr: Option<String>,
+
+    /// The maximal size of a value in the store, larger values are rejected while reading, unit: bytes. [default: 67108864]
+    #[arg(
+        name = "FIBER_STORE_MAX_VALUE_SIZE",
+        long = "fiber-store-max-value-size",
+        env,
+        help = "The maximal size of a value in the store, larger values are rejected while reading, unit: bytes. [default: 67108864]"
+    )]
+    pub(crate) store_max_value_size: Option<usize>,
+
+    /// The maximal size of a gossip value (channel and node info) in the store, larger values are rejected while reading, unit: bytes. [default: 1048576]
+    #[arg(
+        name = "FIBER_STORE_MAX_GOSSIP_VALUE_SIZE",
+        long = "fiber-store-max-gossip-value-size",
+        env,
+        help = "The maximal size of a gossip value (channel and node info) in the store, larger values are rejected while reading, unit: bytes. [default: 1048576]"
+    )]
+    pub(crate) store_max_gossip_value_size: Option<usize>,
+
+    /// The policy to write the updates to the store, can be any of `sync`/`async`. The channel states are always written durably regardless of the policy. [default: async]
+    #[arg(
+        name = "FIBER_STORE_WRITE_POLICY",
+        long = "fiber-store-write-policy",
+        env
+    )]
+    pub(crate) store_write_policy: Option<StoreWritePolicy>,
+
+    /// The maximal number of retries of a store read failed with a transient error like an IO error, with an exponential backoff from 10 milliseconds up to 1 second. 0 means never retrying. [default: 3]
+    #[arg(
+        name = "FIBER_STORE_READ_MAX_RETRIES",
+        long = "fiber-store-read-max-retries",
+        env,
+        help = "The maximal number of retries of a store read failed with a transient error like an IO error, with an exponential backoff from 10 milliseconds up to 1 second. 0 means never retrying. [default: 3]"
+    )]
+    pub(crate) store_read_max_retries: Option<u32>,
+
+    /// The maximal number of the deserialized channel states cached in memory, to speed up the repeated reads of the same channels. 0 means disabled. [default: 0]
+    #[arg(
+        name = "FIBER_STORE_CHANNEL_STATE_CACHE_SIZE",
+        long = "fiber-store-channel-state-cache-size",
+        env,
+        help = "The maximal number of the deserialized channel states cached in memory, to speed up the repeated reads of the same channels. 0 means disabled. [default: 0]"
+    )]
+    pub(crate) store_channel_state_cache_size: Option<usize>,
+
+    /// The minimal ckb funding amount of the remote peer to accept an inbound channel, unit: shannons. [default: 0 (no minimal amount)]
+    #[arg(
+        name = "FIBER_ACCEPT_CHANNEL_MIN_CKB_FUNDING_AMOUNT",
+        long = "fiber-accept-channel-min-ckb-funding-amount",
+        env,
+        help = "The minimal ckb funding amount of the remote peer to accept an inbound channel, unit: shannons. [default: 0 (no minimal amount)]"
+    )]
+    pub(crate) accept_channel_min_ckb_funding_amount: Option<u64>,
+
+    /// The maximal ckb funding amount of the remote peer to accept an inbound channel, unit: shannons. [default: 0 (no maximal amount)]
+    #[arg(
+        name = "FIBER_ACCEPT_CHANNEL_MAX_CKB_FUNDING_AMOUNT",
+        long = "fiber-accept-channel-max-ckb-funding-amount",
+        env,
+        help = "The maximal ckb funding amount of the remote peer to accept an inbound channel, unit: shannons. [default: 0 (no maximal amount)]"
+    )]
+    pub(crate) accept_channel_max_ckb_funding_amount: Option<u64>,
+
+    /// names of the udts in the ckb udt whitelist which can fund an inbound channel (separated by `,`) [default: all the whitelisted udts]
+    #[arg(name = "FIBER_ACCEPT_CHANNEL_UDT_NAMES", long = "fiber-accept-channel-udt-names", env, value_parser, num_args = 0.., value_delimiter = ',')]
+    pub(crate) accept_channel_udt_names: Vec<String>,
+
+    /// peer ids allowed to open channels to this node (separated by `,`) [default: all peers]
+    #[arg(name = "FIBER_ACCEPT_CHANNEL_ALLOWED_PEERS", long = "fiber-accept-channel-allowed-peers", env, value_parser, num_args = 0.., value_delimiter = ',')]
+    pub(crate) accept_channel_allowed_peers: Vec<String>,
+
+    /// peer ids not allowed to open channels to this node (separated by `,`) [default: none]
+    #[arg(name = "FIBER_ACCEPT_CHANNEL_DENIED_PEERS", long = "fiber-accept-channel-denied-peers", env, value_parser, num_args = 0.., value_delimiter = ',')]
+    pub(crate) accept_channel_denied_peers: Vec<String>,
+
+    /// hash algorithms of the payment hashes which can be used in the channels of this node,
+    /// can be any of `ckb_hash`/`sha256` (separated by `,`) [default: all the hash algorithms]
+    #[arg(name = "FIBER_HASH_ALGORITHMS", long = "fiber-hash-algorithms", env, value_parser, num_args = 0.., value_delimiter = ',')]
+    pub(crate) hash_algorithms: Vec<HashAlgorithm>,
+
+    /// versions of the onion packet format which can be used in the channels of this node,
+    /// in the order of preference (separated by `,`) [default: all the supported versions]
+    #[arg(name = "FIBER_ONION_VERSIONS", long = "fiber-onion-versions", env, value_parser, num_args = 0.., value_delimiter = ',')]
+    pub(crate) onion_versions: Vec<u8>,
+
+    /// public keys of the nodes (hex encoded, separated by `,`) whose channels are accepted from gossip,
+    /// other channels and nodes are dropped and not saved [default: accepting the whole network graph]
+    #[arg(name = "FIBER_GOSSIP_FILTER_NODES", long = "fiber-gossip-filter-nodes", env, value_parser, num_args = 0.., value_delimiter = ',')]
+    pub(crate) gossip_filter_nodes: Vec<String>,
+
+    /// The channels of the nodes within this number of hops from the gossip filter nodes are also accepted, only used when `gossip_filter_nodes` is set. [default: 0]
+    #[arg(
+        name = "FIBER_GOSSIP_FILTER_MAX_HOPS",
+        long = "fiber-gossip-filter-max-hops",
+        env,
+        help = "The channels of the nodes within this number of hops from the gossip filter nodes are also accepted, only used when `gossip_filter_nodes` is set. [default: 0]"
+    )]
+    pub(crate) gossip_filter_max_hops: Option<u8>,
+
+    /// fixed low, medium and high fee rates (shannons per KB, separated by `,`) returned by the
+    /// `suggest_fee_rates` rpc, used as a fee oracle instead of the estimation from recent blocks
+    #[arg(name = "FIBER_SUGGESTED_FEE_RATES", long = "fiber-suggested-fee-rates", env, value_parser, num_args = 0.., value_delimiter = ',')]
+    pub(crate) suggested_fee_rates: Vec<u64>,
+
+    /// The maximal number of the recently rejected inbound tlcs kept in memory for debugging, the oldest ones are dropped. 0 means not keeping them. [default: 1000]
+    #[arg(
+        name = "FIBER_REJECTED_TLCS_CAPACITY",
+        long = "fiber-rejected-tlcs-capacity",
+        env,
+        help = "The maximal number of the recently rejected inbound tlcs kept in memory for debugging, the oldest ones are dropped. 0 means not keeping them. [default: 1000]"
+    )]
+    pub(crate) rejected_tlcs_capacity: Option<usize>,
+
+    /// The timeout to abort a channel open which has not reached the point of signing the funding transaction, in seconds. It's used when open_timeout_seconds is not specified in open_channel, and for the channels opened by the remote peers. 0 means never aborting. [default: 600]
+    #[arg(
+        name = "FIBER_OPEN_CHANNEL_TIMEOUT_SECONDS",
+        long = "fiber-open-channel-timeout-seconds",
+        env,
+        help = "The timeout to abort a channel open which has not reached the point of signing the funding transaction, in seconds. It's used when open_timeout_seconds is not specified in open_channel, and for the channels opened by the remote peers. 0 means never aborting. [default: 600]"
+    )]
+    pub(crate) open_channel_timeout_seconds: Option<u64>,
+
+    /// peer ids whose funding transactions are treated as confirmed after `trusted_funding_confirmations` confirmations,
+    /// instead of the full confirmations required for the other peers (separated by `,`) [default: none]
+    #[arg(name = "FIBER_TRUSTED_FUNDING_PEERS", long = "fiber-trusted-funding-peers", env, value_parser, num_args = 0.., value_delimiter = ',')]
+    pub(crate) trusted_funding_peers: Vec<String>,
+
+    /// The number of confirmations after which the funding transaction of a channel with a trusted peer is treated as confirmed, the funds are at risk until the funding transaction reaches the full confirmations. 0 means treating it as confirmed once it's committed in a block. [default: 1]
+    #[arg(
+        name = "FIBER_TRUSTED_FUNDING_CONFIRMATIONS",
+        long = "fiber-trusted-funding-confirmations",
+        env,
+        help = "The number of confirmations after which the funding transaction of a channel with a trusted peer is treated as confirmed, the funds are at risk until the funding transaction reaches the full confirmations. 0 means treating it as confirmed once it's committed in a block. [default: 1]"
+    )]
+    pub(crate) trusted_funding_confirmations: Option<u64>,
+}
+
+/// The policy to decide whether to accept a channel opened by a remote peer.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelAcceptancePolicy {
+    /// The minimal ckb funding amount of the remote peer, 0 means no minimal amount.
+    pub min_ckb_funding_amount: u64,
+    /// The maximal ckb funding amount of the remote peer, 0 means no maximal amount.
+    pub max_ckb_funding_amount: u64,
+    /// Names of the whitelisted udts which can fund the channel, empty means all the whitelisted udts.
+    pub udt_names: Vec<String>,
+    /// Peers allowed to open channels, empty means all peers.
+    pub allowed_peers: Vec<PeerId>,
+    /// Peers not allowed to open channels.
+    pub denied_peers: Vec<PeerId>,
+}
+
+impl ChannelAcceptancePolicy {
+    /// Returns the reason to reject the channel if it is not accepted by the policy.
+    pub fn check(
+        &self,
+        peer_id: &PeerId,
+        funding_amount: u128,
+        funding_udt_type_script: Option<&Script>,
+    ) -> std::result::Result<(), String> {
+        if self.denied_peers.contains(peer_id) {
+            return Err(format!("Peer {:?} is denied to open channels", peer_id));
+        }
+        if !self.allowed_peers.is_empty() && !self.allowed_peers.contains(peer_id) {
+            return Err(format!(
+                "Peer {:?} is not allowed to open channels",
+                peer_id
+            ));
+        }
+        match funding_udt_type_script {
+            Some(udt_type_script) => {
+                let Some(udt_info) = get_udt_info(udt_type_script) else {
+                    return Err(format!("Invalid UDT type script: {:?}", udt_type_script));
+                };
+                if !self.udt_names.is_empty() && !self.udt_names.contains(&udt_info.name) {
+                    return Err(format!(
+                        "UDT {} is not accepted to fund channels",
+                        udt_info.name
+                    ));
+                }
+            }
+            None => {
+                if funding_amount < self.min_ckb_funding_amount as u128 {
+                    return Err(format!(
+                        "Funding amount {} is less than the minimal amount {}",
+                        funding_amount, self.min_ckb_funding_amount
+                    ));
+                }
+                if self.max_ckb_funding_amount != 0
+                    && funding_amount > self.max_ckb_funding_amount as u128
+                {
+                    return Err(format!(
+                        "Funding amount {} is greater than the maximal amount {}",
+                        funding_amount, self.max_ckb_funding_amount
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(PartialEq, Copy, Clone, Default)]
@@ -333,6 +955,11 @@ impl FiberConfig {
             .unwrap_or(DEFAULT_TLC_FEE_PROPORTIONAL_MILLIONTHS)
     }
 
+    pub fn tlc_resolution_timeout_blocks(&self) -> u64 {
+        self.tlc_resolution_timeout_blocks
+            .unwrap_or(DEFAULT_TLC_RESOLUTION_TIMEOUT_BLOCKS)
+    }
+
     pub fn auto_announce_node(&self) -> bool {
         self.auto_announce_node
             .unwrap_or(DEFAULT_AUTO_ANNOUNCE_NODE)
@@ -355,6 +982,318 @@ impl FiberConfig {
         self.sync_network_graph
             .unwrap_or(DEFAULT_SYNC_NETWORK_GRAPH)
     }
+
+    pub fn payment_ready_min_graph_sync_progress(&self) -> u64 {
+        self.payment_ready_min_graph_sync_progress
+            .unwrap_or(DEFAULT_PAYMENT_READY_MIN_GRAPH_SYNC_PROGRESS)
+    }
+
+    pub fn payment_ready_min_peers(&self) -> u64 {
+        self.payment_ready_min_peers
+            .unwrap_or(DEFAULT_PAYMENT_READY_MIN_PEERS)
+    }
+
+    pub fn graph_sync_peer_strategy(&self) -> GraphSyncPeerStrategy {
+        self.graph_sync_peer_strategy.unwrap_or_default()
+    }
+
+    pub fn gossip_rate_limit_max_messages(&self) -> u64 {
+        self.gossip_rate_limit_max_messages
+            .unwrap_or(DEFAULT_GOSSIP_RATE_LIMIT_MAX_MESSAGES)
+    }
+
+    pub fn gossip_rate_limit_interval_seconds(&self) -> u64 {
+        self.gossip_rate_limit_interval_seconds
+            .unwrap_or(DEFAULT_GOSSIP_RATE_LIMIT_INTERVAL_SECONDS)
+    }
+
+    pub fn gossip_max_future_skew_seconds(&self) -> u64 {
+        self.gossip_max_future_skew_seconds
+            .unwrap_or(DEFAULT_GOSSIP_MAX_FUTURE_SKEW_SECONDS)
+    }
+
+    pub fn max_peers(&self) -> u64 {
+        self.max_peers.unwrap_or(DEFAULT_MAX_PEERS)
+    }
+
+    pub fn connection_rate_limit_max_connections(&self) -> u64 {
+        self.connection_rate_limit_max_connections
+            .unwrap_or(DEFAULT_CONNECTION_RATE_LIMIT_MAX_CONNECTIONS)
+    }
+
+    pub fn connection_rate_limit_interval_seconds(&self) -> u64 {
+        self.connection_rate_limit_interval_seconds
+            .unwrap_or(DEFAULT_CONNECTION_RATE_LIMIT_INTERVAL_SECONDS)
+    }
+
+    pub fn graph_snapshot_interval_seconds(&self) -> u64 {
+        self.graph_snapshot_interval_seconds
+            .unwrap_or(DEFAULT_GRAPH_SNAPSHOT_INTERVAL_SECONDS)
+    }
+
+    pub fn payment_timeout_seconds(&self) -> u64 {
+        self.payment_timeout_seconds
+            .unwrap_or(DEFAULT_PAYMENT_TIMEOUT_SECONDS)
+    }
+
+    pub fn payment_max_fee_percent(&self) -> u64 {
+        self.payment_max_fee_percent
+            .unwrap_or(DEFAULT_PAYMENT_MAX_FEE_PERCENT)
+    }
+
+    pub fn payment_session_retention_seconds(&self) -> u64 {
+        self.payment_session_retention_seconds
+            .unwrap_or(DEFAULT_PAYMENT_SESSION_RETENTION_SECONDS)
+    }
+
+    pub fn payment_session_retention_count(&self) -> u64 {
+        self.payment_session_retention_count
+            .unwrap_or(DEFAULT_PAYMENT_SESSION_RETENTION_COUNT)
+    }
+
+    pub fn closed_channel_retention_seconds(&self) -> u64 {
+        self.closed_channel_retention_seconds
+            .unwrap_or(DEFAULT_CLOSED_CHANNEL_RETENTION_SECONDS)
+    }
+
+    pub fn forget_settled_preimages(&self) -> bool {
+        self.forget_settled_preimages
+            .unwrap_or(DEFAULT_FORGET_SETTLED_PREIMAGES)
+    }
+
+    pub fn channel_event_log_max_size(&self) -> u64 {
+        self.channel_event_log_max_size
+            .unwrap_or(DEFAULT_CHANNEL_EVENT_LOG_MAX_SIZE)
+    }
+
+    pub fn reestablish_force_close_on_divergence(&self) -> bool {
+        self.reestablish_force_close_on_divergence
+            .unwrap_or(DEFAULT_REESTABLISH_FORCE_CLOSE_ON_DIVERGENCE)
+    }
+
+    pub fn audit_commitment_updates(&self) -> bool {
+        self.audit_commitment_updates
+            .unwrap_or(DEFAULT_AUDIT_COMMITMENT_UPDATES)
+    }
+
+    pub fn tlc_fail_delay_ms(&self) -> u64 {
+        self.tlc_fail_delay_ms.unwrap_or(DEFAULT_TLC_FAIL_DELAY_MS)
+    }
+
+    pub fn tlc_fail_delay_jitter_ms(&self) -> u64 {
+        self.tlc_fail_delay_jitter_ms
+            .unwrap_or(DEFAULT_TLC_FAIL_DELAY_JITTER_MS)
+    }
+
+    pub fn tlc_fulfill_delay_jitter_ms(&self) -> u64 {
+        self.tlc_fulfill_delay_jitter_ms
+            .unwrap_or(DEFAULT_TLC_FULFILL_DELAY_JITTER_MS)
+            .min(MAX_TLC_FULFILL_DELAY_JITTER_MS)
+    }
+
+    pub fn channel_update_broadcast_delay_ms(&self) -> u64 {
+        self.channel_update_broadcast_delay_ms
+            .unwrap_or(DEFAULT_CHANNEL_UPDATE_BROADCAST_DELAY_MS)
+    }
+
+    pub fn dust_tlc_threshold(&self) -> u128 {
+        self.dust_tlc_threshold
+            .unwrap_or(DEFAULT_DUST_TLC_THRESHOLD)
+    }
+
+    pub fn max_dust_tlc_exposure(&self) -> u128 {
+        self.max_dust_tlc_exposure
+            .unwrap_or(DEFAULT_MAX_DUST_TLC_EXPOSURE)
+    }
+
+    pub fn default_invoice_expiry(&self) -> u64 {
+        self.default_invoice_expiry
+            .unwrap_or(DEFAULT_INVOICE_EXPIRY_SECONDS)
+    }
+
+    pub fn max_invoice_expiry(&self) -> u64 {
+        self.max_invoice_expiry
+            .unwrap_or(DEFAULT_MAX_INVOICE_EXPIRY_SECONDS)
+    }
+
+    pub fn max_onion_payload_size(&self) -> usize {
+        self.max_onion_payload_size
+            .unwrap_or(DEFAULT_MAX_ONION_PAYLOAD_SIZE)
+    }
+
+    pub fn shutdown_fee_rate_tolerance_percent(&self) -> u64 {
+        self.shutdown_fee_rate_tolerance_percent
+            .unwrap_or(DEFAULT_SHUTDOWN_FEE_RATE_TOLERANCE_PERCENT)
+    }
+
+    pub fn min_shutdown_fee_rate(&self) -> u64 {
+        self.min_shutdown_fee_rate
+            .unwrap_or(DEFAULT_MIN_SHUTDOWN_FEE_RATE)
+    }
+
+    pub fn max_accepted_funding_fee_rate(&self) -> u64 {
+        self.max_accepted_funding_fee_rate
+            .unwrap_or(DEFAULT_MAX_ACCEPTED_FUNDING_FEE_RATE)
+    }
+
+    /// The hash algorithms supported by the channels of this node, in the order of preference.
+    pub fn hash_algorithms(&self) -> Vec<HashAlgorithm> {
+        if self.hash_algorithms.is_empty() {
+            HashAlgorithm::supported_algorithms()
+        } else {
+            let mut hash_algorithms = vec![];
+            for hash_algorithm in &self.hash_algorithms {
+                if !hash_algorithms.contains(hash_algorithm) {
+                    hash_algorithms.push(*hash_algorithm);
+                }
+            }
+            hash_algorithms
+        }
+    }
+
+    /// The onion packet versions supported by the channels of this node, in the order of preference.
+    pub fn onion_versions(&self) -> Vec<u8> {
+        if self.onion_versions.is_empty() {
+            SUPPORTED_ONION_VERSIONS.to_vec()
+        } else {
+            let mut onion_versions = vec![];
+            for onion_version in &self.onion_versions {
+                if !onion_versions.contains(onion_version) {
+                    onion_versions.push(*onion_version);
+                }
+            }
+            onion_versions
+        }
+    }
+
+    /// The fixed fee rates to suggest, `None` means estimating the fee rates from recent blocks.
+    pub fn suggested_fee_rates(&self) -> Option<SuggestedFeeRates> {
+        match self.suggested_fee_rates.as_slice() {
+            [] => None,
+            [low, medium, high] => Some(SuggestedFeeRates {
+                low: *low,
+                medium: *medium,
+                high: *high,
+            }),
+            _ => panic!("suggested fee rates must be low, medium and high fee rates"),
+        }
+    }
+
+    pub fn rejected_tlcs_capacity(&self) -> usize {
+        self.rejected_tlcs_capacity
+            .unwrap_or(DEFAULT_REJECTED_TLCS_CAPACITY)
+    }
+
+    pub fn open_channel_timeout_seconds(&self) -> u64 {
+        self.open_channel_timeout_seconds
+            .unwrap_or(DEFAULT_OPEN_CHANNEL_TIMEOUT_SECONDS)
+    }
+
+    pub fn trusted_funding_peers(&self) -> Vec<PeerId> {
+        self.trusted_funding_peers
+            .iter()
+            .map(|peer_id| PeerId::from_str(peer_id).expect("valid trusted funding peer id"))
+            .collect()
+    }
+
+    pub fn trusted_funding_confirmations(&self) -> u64 {
+        self.trusted_funding_confirmations
+            .unwrap_or(DEFAULT_TRUSTED_FUNDING_CONFIRMATIONS)
+    }
+
+    pub fn store_max_value_sizes(&self) -> MaxValueSizes {
+        MaxValueSizes::new(
+            self.store_max_value_size.unwrap_or(DEFAULT_MAX_VALUE_SIZE),
+            self.store_max_gossip_value_size
+                .unwrap_or(DEFAULT_MAX_GOSSIP_VALUE_SIZE),
+        )
+    }
+
+    pub fn store_write_policy(&self) -> StoreWritePolicy {
+        self.store_write_policy.unwrap_or_default()
+    }
+
+    pub fn store_read_max_retries(&self) -> u32 {
+        self.store_read_max_retries
+            .unwrap_or(DEFAULT_READ_MAX_RETRIES)
+    }
+
+    pub fn store_channel_state_cache_size(&self) -> usize {
+        self.store_channel_state_cache_size
+            .unwrap_or(DEFAULT_CHANNEL_STATE_CACHE_SIZE)
+    }
+
+    pub fn metrics_listening_addr(&self) -> Option<&str> {
+        self.metrics_listening_addr.as_deref()
+    }
+
+    /// The filter of the accepted gossip messages, `None` means accepting the whole network graph.
+    pub fn gossip_filter(&self) -> Option<GossipFilter> {
+        if self.gossip_filter_nodes.is_empty() {
+            return None;
+        }
+        let nodes = self
+            .gossip_filter_nodes
+            .iter()
+            .map(|node| {
+                let bytes = hex::decode(node.trim_start_matches("0x"))
+                    .expect("valid gossip filter node public key");
+                secp256k1::PublicKey::from_slice(&bytes)
+                    .expect("valid gossip filter node public key")
+                    .into()
+            })
+            .collect();
+        Some(GossipFilter {
+            nodes,
+            max_hops: self
+                .gossip_filter_max_hops
+                .unwrap_or(DEFAULT_GOSSIP_FILTER_MAX_HOPS),
+        })
+    }
+
+    pub fn channel_acceptance_policy(&self) -> ChannelAcceptancePolicy {
+        let parse_peer_ids = |peer_ids: &[String]| {
+            peer_ids
+                .iter()
+                .map(|peer_id| PeerId::from_str(peer_id).expect("valid accept channel peer id"))
+                .collect()
+        };
+        ChannelAcceptancePolicy {
+            min_ckb_funding_amount: self.accept_channel_min_ckb_funding_amount.unwrap_or(0),
+            max_ckb_funding_amount: self.accept_channel_max_ckb_funding_amount.unwrap_or(0),
+            udt_names: self.accept_channel_udt_names.clone(),
+            allowed_peers: parse_peer_ids(&self.accept_channel_allowed_peers),
+            denied_peers: parse_peer_ids(&self.accept_channel_denied_peers),
+        }
+    }
+}
+
+/// The strategy to select the peers to sync the network graph from while starting up.
+#[derive(Debug, Clone, Copy, Default, ValueEnum, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum GraphSyncPeerStrategy {
+    /// Sync with every connected peer until the syncing with any of them succeeds.
+    #[default]
+    All,
+    /// Sync with one peer at a time, preferring the peers with the most recent node announcements
+    /// and the most channels in the local graph, and falling back to the next one on failure.
+    Preferred,
+}
+
+/// The policy to write the updates to the store.
+#[derive(Debug, Clone, Copy, Default, ValueEnum, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum StoreWritePolicy {
+    /// Every write is synced to the disk before returning, which survives a machine crash.
+    Sync,
+    /// The writes are appended to the write-ahead log without syncing, which survives a process
+    /// crash but may lose the latest updates on a machine crash. The channel states are still
+    /// synced, and syncing them also makes the writes before them durable, so the other updates
+    /// are batched into the next sync of a channel state.
+    #[default]
+    Async,
 }
 
 // Basically ckb_sdk::types::NetworkType. But we added a `Mocknet` variant.
@@ -1,6 +1,7 @@
 use super::channel::FUNDING_CELL_WITNESS_LEN;
 use super::config::{DEFAULT_CHANNEL_MINIMAL_CKB_AMOUNT, DEFAULT_UDT_MINIMAL_CKB_AMOUNT};
 use crate::ckb::contracts::{get_cell_deps, get_script_by_contract, Contract};
+use ckb_jsonrpc_types::FeeRateStatistics;
 use ckb_types::core::TransactionBuilder;
 use ckb_types::packed::{Bytes, Script};
 use ckb_types::prelude::Builder;
@@ -12,6 +13,46 @@ use ckb_types::{
 use molecule::prelude::Entity;
 use tracing::debug;
 
+/// The numbers of recent blocks used to estimate the low/medium/high fee rates,
+/// fewer blocks make the estimation follow the latest fee rates more closely.
+pub(crate) const LOW_FEE_RATE_TARGET_BLOCKS: u64 = 101;
+pub(crate) const MEDIUM_FEE_RATE_TARGET_BLOCKS: u64 = 21;
+pub(crate) const HIGH_FEE_RATE_TARGET_BLOCKS: u64 = 5;
+
+/// The fee rates (shannons per KB) suggested to the users for the on-chain transactions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SuggestedFeeRates {
+    pub low: u64,
+    pub medium: u64,
+    pub high: u64,
+}
+
+impl SuggestedFeeRates {
+    /// Estimate the fee rates from the statistics of the recent blocks, the statistics are `None`
+    /// if there is no transaction in the blocks. The fee rates are never less than `min_fee_rate`,
+    /// and the low fee rate is never greater than the medium one, nor the medium than the high one.
+    pub fn from_statistics(
+        low: Option<FeeRateStatistics>,
+        medium: Option<FeeRateStatistics>,
+        high: Option<FeeRateStatistics>,
+        min_fee_rate: u64,
+    ) -> Self {
+        let low = low
+            .map(|s| s.mean.value().min(s.median.value()))
+            .unwrap_or_default()
+            .max(min_fee_rate);
+        let medium = medium
+            .map(|s| s.median.value())
+            .unwrap_or_default()
+            .max(low);
+        let high = high
+            .map(|s| s.mean.value().max(s.median.value()))
+            .unwrap_or_default()
+            .max(medium);
+        Self { low, medium, high }
+    }
+}
+
 pub(crate) fn default_minimal_ckb_amount(is_udt: bool) -> u64 {
     if is_udt {
         DEFAULT_UDT_MINIMAL_CKB_AMOUNT
@@ -5265,12 +5265,7 @@ impl ::core::fmt::Display for AcceptChannel {
             self.max_tlc_number_in_flight()
         )?;
         write!(f, ", {}: {}", "min_tlc_value", self.min_tlc_value())?;
-        write!(
-            f,
-            ", {}: {}",
-            "commitment_delay_epoch",
-            self.commitment_delay_epoch()
-        )?;
+        write!(f, ", {}: {}", "channel_flags", self.channel_flags())?;
         write!(f, ", {}: {}", "funding_pubkey", self.funding_pubkey())?;
         write!(
             f,
@@ -5398,7 +5393,7 @@ impl AcceptChannel {
         let end = molecule::unpack_number(&slice[32..]) as usize;
         Uint128::new_unchecked(self.0.slice(start..end))
     }
-    pub fn commitment_delay_epoch(&self) -> Uint64 {
+    pub fn channel_flags(&self) -> Uint64 {
         let slice = self.as_slice();
         let start = molecule::unpack_number(&slice[32..]) as usize;
         let end = molecule::unpack_number(&slice[36..]) as usize;
@@ -5496,7 +5491,7 @@ impl molecule::prelude::Entity for AcceptChannel {
             .max_tlc_value_in_flight(self.max_tlc_value_in_flight())
             .max_tlc_number_in_flight(self.max_tlc_number_in_flight())
             .min_tlc_value(self.min_tlc_value())
-            .commitment_delay_epoch(self.commitment_delay_epoch())
+            .channel_flags(self.channel_flags())
             .funding_pubkey(self.funding_pubkey())
             .revocation_basepoint(self.revocation_basepoint())
             .payment_basepoint(self.payment_basepoint())
@@ -5549,12 +5544,7 @@ impl<'r> ::core::fmt::Display for AcceptChannelReader<'r> {
             self.max_tlc_number_in_flight()
         )?;
         write!(f, ", {}: {}", "min_tlc_value", self.min_tlc_value())?;
-        write!(
-            f,
-            ", {}: {}",
-            "commitment_delay_epoch",
-            self.commitment_delay_epoch()
-        )?;
+        write!(f, ", {}: {}", "channel_flags", self.channel_flags())?;
         write!(f, ", {}: {}", "funding_pubkey", self.funding_pubkey())?;
         write!(
             f,
@@ -5656,7 +5646,7 @@ impl<'r> AcceptChannelReader<'r> {
         let end = molecule::unpack_number(&slice[32..]) as usize;
         Uint128Reader::new_unchecked(&self.as_slice()[start..end])
     }
-    pub fn commitment_delay_epoch(&self) -> Uint64Reader<'r> {
+    pub fn channel_flags(&self) -> Uint64Reader<'r> {
         let slice = self.as_slice();
         let start = molecule::unpack_number(&slice[32..]) as usize;
         let end = molecule::unpack_number(&slice[36..]) as usize;
@@ -5796,7 +5786,7 @@ pub struct AcceptChannelBuilder {
     pub(crate) max_tlc_value_in_flight: Uint128,
     pub(crate) max_tlc_number_in_flight: Uint64,
     pub(crate) min_tlc_value: Uint128,
-    pub(crate) commitment_delay_epoch: Uint64,
+    pub(crate) channel_flags: Uint64,
     pub(crate) funding_pubkey: Pubkey,
     pub(crate) revocation_basepoint: Pubkey,
     pub(crate) payment_basepoint: Pubkey,
@@ -5837,8 +5827,8 @@ impl AcceptChannelBuilder {
         self.min_tlc_value = v;
         self
     }
-    pub fn commitment_delay_epoch(mut self, v: Uint64) -> Self {
-        self.commitment_delay_epoch = v;
+    pub fn channel_flags(mut self, v: Uint64) -> Self {
+        self.channel_flags = v;
         self
     }
     pub fn funding_pubkey(mut self, v: Pubkey) -> Self {
@@ -5890,7 +5880,7 @@ impl molecule::prelude::Builder for AcceptChannelBuilder {
             + self.max_tlc_value_in_flight.as_slice().len()
             + self.max_tlc_number_in_flight.as_slice().len()
             + self.min_tlc_value.as_slice().len()
-            + self.commitment_delay_epoch.as_slice().len()
+            + self.channel_flags.as_slice().len()
             + self.funding_pubkey.as_slice().len()
             + self.revocation_basepoint.as_slice().len()
             + self.payment_basepoint.as_slice().len()
@@ -5919,7 +5909,7 @@ impl molecule::prelude::Builder for AcceptChannelBuilder {
         offsets.push(total_size);
         total_size += self.min_tlc_value.as_slice().len();
         offsets.push(total_size);
-        total_size += self.commitment_delay_epoch.as_slice().len();
+        total_size += self.channel_flags.as_slice().len();
         offsets.push(total_size);
         total_size += self.funding_pubkey.as_slice().len();
         offsets.push(total_size);
@@ -5949,7 +5939,7 @@ impl molecule::prelude::Builder for AcceptChannelBuilder {
         writer.write_all(self.max_tlc_value_in_flight.as_slice())?;
         writer.write_all(self.max_tlc_number_in_flight.as_slice())?;
         writer.write_all(self.min_tlc_value.as_slice())?;
-        writer.write_all(self.commitment_delay_epoch.as_slice())?;
+        writer.write_all(self.channel_flags.as_slice())?;
         writer.write_all(self.funding_pubkey.as_slice())?;
         writer.write_all(self.revocation_basepoint.as_slice())?;
         writer.write_all(self.payment_basepoint.as_slice())?;
@@ -8385,6 +8375,296 @@ impl molecule::prelude::Builder for ShutdownBuilder {
     }
 }
 #[derive(Clone)]
+pub struct ShutdownFeeCounter(molecule::bytes::Bytes);
+impl ::core::fmt::LowerHex for ShutdownFeeCounter {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        use molecule::hex_string;
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex_string(self.as_slice()))
+    }
+}
+impl ::core::fmt::Debug for ShutdownFeeCounter {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{}({:#x})", Self::NAME, self)
+    }
+}
+impl ::core::fmt::Display for ShutdownFeeCounter {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{} {{ ", Self::NAME)?;
+        write!(f, "{}: {}", "channel_id", self.channel_id())?;
+        write!(f, ", {}: {}", "fee_rate", self.fee_rate())?;
+        write!(f, ", {}: {}", "close_script", self.close_script())?;
+        let extra_count = self.count_extra_fields();
+        if extra_count != 0 {
+            write!(f, ", .. ({} fields)", extra_count)?;
+        }
+        write!(f, " }}")
+    }
+}
+impl ::core::default::Default for ShutdownFeeCounter {
+    fn default() -> Self {
+        let v = molecule::bytes::Bytes::from_static(&Self::DEFAULT_VALUE);
+        ShutdownFeeCounter::new_unchecked(v)
+    }
+}
+impl ShutdownFeeCounter {
+    const DEFAULT_VALUE: [u8; 109] = [
+        109, 0, 0, 0, 16, 0, 0, 0, 48, 0, 0, 0, 56, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 53, 0, 0,
+        0, 16, 0, 0, 0, 48, 0, 0, 0, 49, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    pub const FIELD_COUNT: usize = 3;
+    pub fn total_size(&self) -> usize {
+        molecule::unpack_number(self.as_slice()) as usize
+    }
+    pub fn field_count(&self) -> usize {
+        if self.total_size() == molecule::NUMBER_SIZE {
+            0
+        } else {
+            (molecule::unpack_number(&self.as_slice()[molecule::NUMBER_SIZE..]) as usize / 4) - 1
+        }
+    }
+    pub fn count_extra_fields(&self) -> usize {
+        self.field_count() - Self::FIELD_COUNT
+    }
+    pub fn has_extra_fields(&self) -> bool {
+        Self::FIELD_COUNT != self.field_count()
+    }
+    pub fn channel_id(&self) -> Byte32 {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[4..]) as usize;
+        let end = molecule::unpack_number(&slice[8..]) as usize;
+        Byte32::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn fee_rate(&self) -> Uint64 {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[8..]) as usize;
+        let end = molecule::unpack_number(&slice[12..]) as usize;
+        Uint64::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn close_script(&self) -> Script {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[12..]) as usize;
+        if self.has_extra_fields() {
+            let end = molecule::unpack_number(&slice[16..]) as usize;
+            Script::new_unchecked(self.0.slice(start..end))
+        } else {
+            Script::new_unchecked(self.0.slice(start..))
+        }
+    }
+    pub fn as_reader<'r>(&'r self) -> ShutdownFeeCounterReader<'r> {
+        ShutdownFeeCounterReader::new_unchecked(self.as_slice())
+    }
+}
+impl molecule::prelude::Entity for ShutdownFeeCounter {
+    type Builder = ShutdownFeeCounterBuilder;
+    const NAME: &'static str = "ShutdownFeeCounter";
+    fn new_unchecked(data: molecule::bytes::Bytes) -> Self {
+        ShutdownFeeCounter(data)
+    }
+    fn as_bytes(&self) -> molecule::bytes::Bytes {
+        self.0.clone()
+    }
+    fn as_slice(&self) -> &[u8] {
+        &self.0[..]
+    }
+    fn from_slice(slice: &[u8]) -> molecule::error::VerificationResult<Self> {
+        ShutdownFeeCounterReader::from_slice(slice).map(|reader| reader.to_entity())
+    }
+    fn from_compatible_slice(slice: &[u8]) -> molecule::error::VerificationResult<Self> {
+        ShutdownFeeCounterReader::from_compatible_slice(slice).map(|reader| reader.to_entity())
+    }
+    fn new_builder() -> Self::Builder {
+        ::core::default::Default::default()
+    }
+    fn as_builder(self) -> Self::Builder {
+        Self::new_builder()
+            .channel_id(self.channel_id())
+            .fee_rate(self.fee_rate())
+            .close_script(self.close_script())
+    }
+}
+#[derive(Clone, Copy)]
+pub struct ShutdownFeeCounterReader<'r>(&'r [u8]);
+impl<'r> ::core::fmt::LowerHex for ShutdownFeeCounterReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        use molecule::hex_string;
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex_string(self.as_slice()))
+    }
+}
+impl<'r> ::core::fmt::Debug for ShutdownFeeCounterReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{}({:#x})", Self::NAME, self)
+    }
+}
+impl<'r> ::core::fmt::Display for ShutdownFeeCounterReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{} {{ ", Self::NAME)?;
+        write!(f, "{}: {}", "channel_id", self.channel_id())?;
+        write!(f, ", {}: {}", "fee_rate", self.fee_rate())?;
+        write!(f, ", {}: {}", "close_script", self.close_script())?;
+        let extra_count = self.count_extra_fields();
+        if extra_count != 0 {
+            write!(f, ", .. ({} fields)", extra_count)?;
+        }
+        write!(f, " }}")
+    }
+}
+impl<'r> ShutdownFeeCounterReader<'r> {
+    pub const FIELD_COUNT: usize = 3;
+    pub fn total_size(&self) -> usize {
+        molecule::unpack_number(self.as_slice()) as usize
+    }
+    pub fn field_count(&self) -> usize {
+        if self.total_size() == molecule::NUMBER_SIZE {
+            0
+        } else {
+            (molecule::unpack_number(&self.as_slice()[molecule::NUMBER_SIZE..]) as usize / 4) - 1
+        }
+    }
+    pub fn count_extra_fields(&self) -> usize {
+        self.field_count() - Self::FIELD_COUNT
+    }
+    pub fn has_extra_fields(&self) -> bool {
+        Self::FIELD_COUNT != self.field_count()
+    }
+    pub fn channel_id(&self) -> Byte32Reader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[4..]) as usize;
+        let end = molecule::unpack_number(&slice[8..]) as usize;
+        Byte32Reader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn fee_rate(&self) -> Uint64Reader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[8..]) as usize;
+        let end = molecule::unpack_number(&slice[12..]) as usize;
+        Uint64Reader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn close_script(&self) -> ScriptReader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[12..]) as usize;
+        if self.has_extra_fields() {
+            let end = molecule::unpack_number(&slice[16..]) as usize;
+            ScriptReader::new_unchecked(&self.as_slice()[start..end])
+        } else {
+            ScriptReader::new_unchecked(&self.as_slice()[start..])
+        }
+    }
+}
+impl<'r> molecule::prelude::Reader<'r> for ShutdownFeeCounterReader<'r> {
+    type Entity = ShutdownFeeCounter;
+    const NAME: &'static str = "ShutdownFeeCounterReader";
+    fn to_entity(&self) -> Self::Entity {
+        Self::Entity::new_unchecked(self.as_slice().to_owned().into())
+    }
+    fn new_unchecked(slice: &'r [u8]) -> Self {
+        ShutdownFeeCounterReader(slice)
+    }
+    fn as_slice(&self) -> &'r [u8] {
+        self.0
+    }
+    fn verify(slice: &[u8], compatible: bool) -> molecule::error::VerificationResult<()> {
+        use molecule::verification_error as ve;
+        let slice_len = slice.len();
+        if slice_len < molecule::NUMBER_SIZE {
+            return ve!(Self, HeaderIsBroken, molecule::NUMBER_SIZE, slice_len);
+        }
+        let total_size = molecule::unpack_number(slice) as usize;
+        if slice_len != total_size {
+            return ve!(Self, TotalSizeNotMatch, total_size, slice_len);
+        }
+        if slice_len < molecule::NUMBER_SIZE * 2 {
+            return ve!(Self, HeaderIsBroken, molecule::NUMBER_SIZE * 2, slice_len);
+        }
+        let offset_first = molecule::unpack_number(&slice[molecule::NUMBER_SIZE..]) as usize;
+        if offset_first % molecule::NUMBER_SIZE != 0 || offset_first < molecule::NUMBER_SIZE * 2 {
+            return ve!(Self, OffsetsNotMatch);
+        }
+        if slice_len < offset_first {
+            return ve!(Self, HeaderIsBroken, offset_first, slice_len);
+        }
+        let field_count = offset_first / molecule::NUMBER_SIZE - 1;
+        if field_count < Self::FIELD_COUNT {
+            return ve!(Self, FieldCountNotMatch, Self::FIELD_COUNT, field_count);
+        } else if !compatible && field_count > Self::FIELD_COUNT {
+            return ve!(Self, FieldCountNotMatch, Self::FIELD_COUNT, field_count);
+        };
+        let mut offsets: Vec<usize> = slice[molecule::NUMBER_SIZE..offset_first]
+            .chunks_exact(molecule::NUMBER_SIZE)
+            .map(|x| molecule::unpack_number(x) as usize)
+            .collect();
+        offsets.push(total_size);
+        if offsets.windows(2).any(|i| i[0] > i[1]) {
+            return ve!(Self, OffsetsNotMatch);
+        }
+        Byte32Reader::verify(&slice[offsets[0]..offsets[1]], compatible)?;
+        Uint64Reader::verify(&slice[offsets[1]..offsets[2]], compatible)?;
+        ScriptReader::verify(&slice[offsets[2]..offsets[3]], compatible)?;
+        Ok(())
+    }
+}
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownFeeCounterBuilder {
+    pub(crate) channel_id: Byte32,
+    pub(crate) fee_rate: Uint64,
+    pub(crate) close_script: Script,
+}
+impl ShutdownFeeCounterBuilder {
+    pub const FIELD_COUNT: usize = 3;
+    pub fn channel_id(mut self, v: Byte32) -> Self {
+        self.channel_id = v;
+        self
+    }
+    pub fn fee_rate(mut self, v: Uint64) -> Self {
+        self.fee_rate = v;
+        self
+    }
+    pub fn close_script(mut self, v: Script) -> Self {
+        self.close_script = v;
+        self
+    }
+}
+impl molecule::prelude::Builder for ShutdownFeeCounterBuilder {
+    type Entity = ShutdownFeeCounter;
+    const NAME: &'static str = "ShutdownFeeCounterBuilder";
+    fn expected_length(&self) -> usize {
+        molecule::NUMBER_SIZE * (Self::FIELD_COUNT + 1)
+            + self.channel_id.as_slice().len()
+            + self.fee_rate.as_slice().len()
+            + self.close_script.as_slice().len()
+    }
+    fn write<W: molecule::io::Write>(&self, writer: &mut W) -> molecule::io::Result<()> {
+        let mut total_size = molecule::NUMBER_SIZE * (Self::FIELD_COUNT + 1);
+        let mut offsets = Vec::with_capacity(Self::FIELD_COUNT);
+        offsets.push(total_size);
+        total_size += self.channel_id.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.fee_rate.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.close_script.as_slice().len();
+        writer.write_all(&molecule::pack_number(total_size as molecule::Number))?;
+        for offset in offsets.into_iter() {
+            writer.write_all(&molecule::pack_number(offset as molecule::Number))?;
+        }
+        writer.write_all(self.channel_id.as_slice())?;
+        writer.write_all(self.fee_rate.as_slice())?;
+        writer.write_all(self.close_script.as_slice())?;
+        Ok(())
+    }
+    fn build(&self) -> Self::Entity {
+        let mut inner = Vec::with_capacity(self.expected_length());
+        self.write(&mut inner)
+            .unwrap_or_else(|_| panic!("{} build should be ok", Self::NAME));
+        ShutdownFeeCounter::new_unchecked(inner.into())
+    }
+}
+#[derive(Clone)]
 pub struct ClosingSigned(molecule::bytes::Bytes);
 impl ::core::fmt::LowerHex for ClosingSigned {
     fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
@@ -18358,7 +18638,7 @@ impl FiberMessage {
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
     ];
-    pub const ITEMS_COUNT: usize = 26;
+    pub const ITEMS_COUNT: usize = 27;
     pub fn item_id(&self) -> molecule::Number {
         molecule::unpack_number(self.as_slice())
     }
@@ -18391,6 +18671,7 @@ impl FiberMessage {
             23 => QueryChannelsWithinBlockRangeResult::new_unchecked(inner).into(),
             24 => QueryBroadcastMessagesWithinTimeRange::new_unchecked(inner).into(),
             25 => QueryBroadcastMessagesWithinTimeRangeResult::new_unchecked(inner).into(),
+            26 => ShutdownFeeCounter::new_unchecked(inner).into(),
             _ => panic!("{}: invalid data", Self::NAME),
         }
     }
@@ -18447,7 +18728,7 @@ impl<'r> ::core::fmt::Display for FiberMessageReader<'r> {
     }
 }
 impl<'r> FiberMessageReader<'r> {
-    pub const ITEMS_COUNT: usize = 26;
+    pub const ITEMS_COUNT: usize = 27;
     pub fn item_id(&self) -> molecule::Number {
         molecule::unpack_number(self.as_slice())
     }
@@ -18480,6 +18761,7 @@ impl<'r> FiberMessageReader<'r> {
             23 => QueryChannelsWithinBlockRangeResultReader::new_unchecked(inner).into(),
             24 => QueryBroadcastMessagesWithinTimeRangeReader::new_unchecked(inner).into(),
             25 => QueryBroadcastMessagesWithinTimeRangeResultReader::new_unchecked(inner).into(),
+            26 => ShutdownFeeCounterReader::new_unchecked(inner).into(),
             _ => panic!("{}: invalid data", Self::NAME),
         }
     }
@@ -18533,6 +18815,7 @@ impl<'r> molecule::prelude::Reader<'r> for FiberMessageReader<'r> {
             25 => {
                 QueryBroadcastMessagesWithinTimeRangeResultReader::verify(inner_slice, compatible)
             }
+            26 => ShutdownFeeCounterReader::verify(inner_slice, compatible),
             _ => ve!(Self, UnknownItem, Self::ITEMS_COUNT, item_id),
         }?;
         Ok(())
@@ -18541,7 +18824,7 @@ impl<'r> molecule::prelude::Reader<'r> for FiberMessageReader<'r> {
 #[derive(Clone, Debug, Default)]
 pub struct FiberMessageBuilder(pub(crate) FiberMessageUnion);
 impl FiberMessageBuilder {
-    pub const ITEMS_COUNT: usize = 26;
+    pub const ITEMS_COUNT: usize = 27;
     pub fn set<I>(mut self, v: I) -> Self
     where
         I: ::core::convert::Into<FiberMessageUnion>,
@@ -18595,6 +18878,7 @@ pub enum FiberMessageUnion {
     QueryChannelsWithinBlockRangeResult(QueryChannelsWithinBlockRangeResult),
     QueryBroadcastMessagesWithinTimeRange(QueryBroadcastMessagesWithinTimeRange),
     QueryBroadcastMessagesWithinTimeRangeResult(QueryBroadcastMessagesWithinTimeRangeResult),
+    ShutdownFeeCounter(ShutdownFeeCounter),
 }
 #[derive(Debug, Clone, Copy)]
 pub enum FiberMessageUnionReader<'r> {
@@ -18626,6 +18910,7 @@ pub enum FiberMessageUnionReader<'r> {
     QueryBroadcastMessagesWithinTimeRangeResult(
         QueryBroadcastMessagesWithinTimeRangeResultReader<'r>,
     ),
+    ShutdownFeeCounter(ShutdownFeeCounterReader<'r>),
 }
 impl ::core::default::Default for FiberMessageUnion {
     fn default() -> Self {
@@ -18755,6 +19040,9 @@ impl ::core::fmt::Display for FiberMessageUnion {
                     item
                 )
             }
+            FiberMessageUnion::ShutdownFeeCounter(ref item) => {
+                write!(f, "{}::{}({})", Self::NAME, ShutdownFeeCounter::NAME, item)
+            }
         }
     }
 }
@@ -18881,6 +19169,9 @@ impl<'r> ::core::fmt::Display for FiberMessageUnionReader<'r> {
                     item
                 )
             }
+            FiberMessageUnionReader::ShutdownFeeCounter(ref item) => {
+                write!(f, "{}::{}({})", Self::NAME, ShutdownFeeCounter::NAME, item)
+            }
         }
     }
 }
@@ -18919,6 +19210,9 @@ impl FiberMessageUnion {
             FiberMessageUnion::QueryBroadcastMessagesWithinTimeRangeResult(ref item) => {
                 write!(f, "{}", item)
             }
+            FiberMessageUnion::ShutdownFeeCounter(ref item) => {
+                write!(f, "{}", item)
+            }
         }
     }
 }
@@ -18959,6 +19253,9 @@ impl<'r> FiberMessageUnionReader<'r> {
             FiberMessageUnionReader::QueryBroadcastMessagesWithinTimeRangeResult(ref item) => {
                 write!(f, "{}", item)
             }
+            FiberMessageUnionReader::ShutdownFeeCounter(ref item) => {
+                write!(f, "{}", item)
+            }
         }
     }
 }
@@ -19092,6 +19389,11 @@ impl ::core::convert::From<QueryBroadcastMessagesWithinTimeRangeResult> for Fibe
         FiberMessageUnion::QueryBroadcastMessagesWithinTimeRangeResult(item)
     }
 }
+impl ::core::convert::From<ShutdownFeeCounter> for FiberMessageUnion {
+    fn from(item: ShutdownFeeCounter) -> Self {
+        FiberMessageUnion::ShutdownFeeCounter(item)
+    }
+}
 impl<'r> ::core::convert::From<OpenChannelReader<'r>> for FiberMessageUnionReader<'r> {
     fn from(item: OpenChannelReader<'r>) -> Self {
         FiberMessageUnionReader::OpenChannel(item)
@@ -19232,6 +19534,11 @@ impl<'r> ::core::convert::From<QueryBroadcastMessagesWithinTimeRangeResultReader
         FiberMessageUnionReader::QueryBroadcastMessagesWithinTimeRangeResult(item)
     }
 }
+impl<'r> ::core::convert::From<ShutdownFeeCounterReader<'r>> for FiberMessageUnionReader<'r> {
+    fn from(item: ShutdownFeeCounterReader<'r>) -> Self {
+        FiberMessageUnionReader::ShutdownFeeCounter(item)
+    }
+}
 impl FiberMessageUnion {
     pub const NAME: &'static str = "FiberMessageUnion";
     pub fn as_bytes(&self) -> molecule::bytes::Bytes {
@@ -19262,6 +19569,7 @@ impl FiberMessageUnion {
             FiberMessageUnion::QueryChannelsWithinBlockRangeResult(item) => item.as_bytes(),
             FiberMessageUnion::QueryBroadcastMessagesWithinTimeRange(item) => item.as_bytes(),
             FiberMessageUnion::QueryBroadcastMessagesWithinTimeRangeResult(item) => item.as_bytes(),
+            FiberMessageUnion::ShutdownFeeCounter(item) => item.as_bytes(),
         }
     }
     pub fn as_slice(&self) -> &[u8] {
@@ -19292,6 +19600,7 @@ impl FiberMessageUnion {
             FiberMessageUnion::QueryChannelsWithinBlockRangeResult(item) => item.as_slice(),
             FiberMessageUnion::QueryBroadcastMessagesWithinTimeRange(item) => item.as_slice(),
             FiberMessageUnion::QueryBroadcastMessagesWithinTimeRangeResult(item) => item.as_slice(),
+            FiberMessageUnion::ShutdownFeeCounter(item) => item.as_slice(),
         }
     }
     pub fn item_id(&self) -> molecule::Number {
@@ -19322,6 +19631,7 @@ impl FiberMessageUnion {
             FiberMessageUnion::QueryChannelsWithinBlockRangeResult(_) => 23,
             FiberMessageUnion::QueryBroadcastMessagesWithinTimeRange(_) => 24,
             FiberMessageUnion::QueryBroadcastMessagesWithinTimeRangeResult(_) => 25,
+            FiberMessageUnion::ShutdownFeeCounter(_) => 26,
         }
     }
     pub fn item_name(&self) -> &str {
@@ -19358,6 +19668,7 @@ impl FiberMessageUnion {
             FiberMessageUnion::QueryBroadcastMessagesWithinTimeRangeResult(_) => {
                 "QueryBroadcastMessagesWithinTimeRangeResult"
             }
+            FiberMessageUnion::ShutdownFeeCounter(_) => "ShutdownFeeCounter",
         }
     }
     pub fn as_reader<'r>(&'r self) -> FiberMessageUnionReader<'r> {
@@ -19392,6 +19703,7 @@ impl FiberMessageUnion {
             FiberMessageUnion::QueryBroadcastMessagesWithinTimeRangeResult(item) => {
                 item.as_reader().into()
             }
+            FiberMessageUnion::ShutdownFeeCounter(item) => item.as_reader().into(),
         }
     }
 }
@@ -19427,6 +19739,7 @@ impl<'r> FiberMessageUnionReader<'r> {
             FiberMessageUnionReader::QueryBroadcastMessagesWithinTimeRangeResult(item) => {
                 item.as_slice()
             }
+            FiberMessageUnionReader::ShutdownFeeCounter(item) => item.as_slice(),
         }
     }
     pub fn item_id(&self) -> molecule::Number {
@@ -19457,6 +19770,7 @@ impl<'r> FiberMessageUnionReader<'r> {
             FiberMessageUnionReader::QueryChannelsWithinBlockRangeResult(_) => 23,
             FiberMessageUnionReader::QueryBroadcastMessagesWithinTimeRange(_) => 24,
             FiberMessageUnionReader::QueryBroadcastMessagesWithinTimeRangeResult(_) => 25,
+            FiberMessageUnionReader::ShutdownFeeCounter(_) => 26,
         }
     }
     pub fn item_name(&self) -> &str {
@@ -19495,6 +19809,7 @@ impl<'r> FiberMessageUnionReader<'r> {
             FiberMessageUnionReader::QueryBroadcastMessagesWithinTimeRangeResult(_) => {
                 "QueryBroadcastMessagesWithinTimeRangeResult"
             }
+            FiberMessageUnionReader::ShutdownFeeCounter(_) => "ShutdownFeeCounter",
         }
     }
 }
@@ -19628,3 +19943,8 @@ impl From<QueryBroadcastMessagesWithinTimeRangeResult> for FiberMessage {
         Self::new_builder().set(value).build()
     }
 }
+impl From<ShutdownFeeCounter> for FiberMessage {
+    fn from(value: ShutdownFeeCounter) -> Self {
+        Self::new_builder().set(value).build()
+    }
+}
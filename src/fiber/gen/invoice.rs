@@ -4844,6 +4844,450 @@ impl molecule::prelude::Builder for HashAlgorithmBuilder {
     }
 }
 #[derive(Clone)]
+pub struct MinAmount(molecule::bytes::Bytes);
+impl ::core::fmt::LowerHex for MinAmount {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        use molecule::hex_string;
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex_string(self.as_slice()))
+    }
+}
+impl ::core::fmt::Debug for MinAmount {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{}({:#x})", Self::NAME, self)
+    }
+}
+impl ::core::fmt::Display for MinAmount {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{} {{ ", Self::NAME)?;
+        write!(f, "{}: {}", "value", self.value())?;
+        write!(f, " }}")
+    }
+}
+impl ::core::default::Default for MinAmount {
+    fn default() -> Self {
+        let v = molecule::bytes::Bytes::from_static(&Self::DEFAULT_VALUE);
+        MinAmount::new_unchecked(v)
+    }
+}
+impl MinAmount {
+    const DEFAULT_VALUE: [u8; 16] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    pub const TOTAL_SIZE: usize = 16;
+    pub const FIELD_SIZES: [usize; 1] = [16];
+    pub const FIELD_COUNT: usize = 1;
+    pub fn value(&self) -> Uint128 {
+        Uint128::new_unchecked(self.0.slice(0..16))
+    }
+    pub fn as_reader<'r>(&'r self) -> MinAmountReader<'r> {
+        MinAmountReader::new_unchecked(self.as_slice())
+    }
+}
+impl molecule::prelude::Entity for MinAmount {
+    type Builder = MinAmountBuilder;
+    const NAME: &'static str = "MinAmount";
+    fn new_unchecked(data: molecule::bytes::Bytes) -> Self {
+        MinAmount(data)
+    }
+    fn as_bytes(&self) -> molecule::bytes::Bytes {
+        self.0.clone()
+    }
+    fn as_slice(&self) -> &[u8] {
+        &self.0[..]
+    }
+    fn from_slice(slice: &[u8]) -> molecule::error::VerificationResult<Self> {
+        MinAmountReader::from_slice(slice).map(|reader| reader.to_entity())
+    }
+    fn from_compatible_slice(slice: &[u8]) -> molecule::error::VerificationResult<Self> {
+        MinAmountReader::from_compatible_slice(slice).map(|reader| reader.to_entity())
+    }
+    fn new_builder() -> Self::Builder {
+        ::core::default::Default::default()
+    }
+    fn as_builder(self) -> Self::Builder {
+        Self::new_builder().value(self.value())
+    }
+}
+#[derive(Clone, Copy)]
+pub struct MinAmountReader<'r>(&'r [u8]);
+impl<'r> ::core::fmt::LowerHex for MinAmountReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        use molecule::hex_string;
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex_string(self.as_slice()))
+    }
+}
+impl<'r> ::core::fmt::Debug for MinAmountReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{}({:#x})", Self::NAME, self)
+    }
+}
+impl<'r> ::core::fmt::Display for MinAmountReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{} {{ ", Self::NAME)?;
+        write!(f, "{}: {}", "value", self.value())?;
+        write!(f, " }}")
+    }
+}
+impl<'r> MinAmountReader<'r> {
+    pub const TOTAL_SIZE: usize = 16;
+    pub const FIELD_SIZES: [usize; 1] = [16];
+    pub const FIELD_COUNT: usize = 1;
+    pub fn value(&self) -> Uint128Reader<'r> {
+        Uint128Reader::new_unchecked(&self.as_slice()[0..16])
+    }
+}
+impl<'r> molecule::prelude::Reader<'r> for MinAmountReader<'r> {
+    type Entity = MinAmount;
+    const NAME: &'static str = "MinAmountReader";
+    fn to_entity(&self) -> Self::Entity {
+        Self::Entity::new_unchecked(self.as_slice().to_owned().into())
+    }
+    fn new_unchecked(slice: &'r [u8]) -> Self {
+        MinAmountReader(slice)
+    }
+    fn as_slice(&self) -> &'r [u8] {
+        self.0
+    }
+    fn verify(slice: &[u8], _compatible: bool) -> molecule::error::VerificationResult<()> {
+        use molecule::verification_error as ve;
+        let slice_len = slice.len();
+        if slice_len != Self::TOTAL_SIZE {
+            return ve!(Self, TotalSizeNotMatch, Self::TOTAL_SIZE, slice_len);
+        }
+        Ok(())
+    }
+}
+#[derive(Clone, Debug, Default)]
+pub struct MinAmountBuilder {
+    pub(crate) value: Uint128,
+}
+impl MinAmountBuilder {
+    pub const TOTAL_SIZE: usize = 16;
+    pub const FIELD_SIZES: [usize; 1] = [16];
+    pub const FIELD_COUNT: usize = 1;
+    pub fn value(mut self, v: Uint128) -> Self {
+        self.value = v;
+        self
+    }
+}
+impl molecule::prelude::Builder for MinAmountBuilder {
+    type Entity = MinAmount;
+    const NAME: &'static str = "MinAmountBuilder";
+    fn expected_length(&self) -> usize {
+        Self::TOTAL_SIZE
+    }
+    fn write<W: molecule::io::Write>(&self, writer: &mut W) -> molecule::io::Result<()> {
+        writer.write_all(self.value.as_slice())?;
+        Ok(())
+    }
+    fn build(&self) -> Self::Entity {
+        let mut inner = Vec::with_capacity(self.expected_length());
+        self.write(&mut inner)
+            .unwrap_or_else(|_| panic!("{} build should be ok", Self::NAME));
+        MinAmount::new_unchecked(inner.into())
+    }
+}
+#[derive(Clone)]
+pub struct MaxAmount(molecule::bytes::Bytes);
+impl ::core::fmt::LowerHex for MaxAmount {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        use molecule::hex_string;
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex_string(self.as_slice()))
+    }
+}
+impl ::core::fmt::Debug for MaxAmount {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{}({:#x})", Self::NAME, self)
+    }
+}
+impl ::core::fmt::Display for MaxAmount {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{} {{ ", Self::NAME)?;
+        write!(f, "{}: {}", "value", self.value())?;
+        write!(f, " }}")
+    }
+}
+impl ::core::default::Default for MaxAmount {
+    fn default() -> Self {
+        let v = molecule::bytes::Bytes::from_static(&Self::DEFAULT_VALUE);
+        MaxAmount::new_unchecked(v)
+    }
+}
+impl MaxAmount {
+    const DEFAULT_VALUE: [u8; 16] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    pub const TOTAL_SIZE: usize = 16;
+    pub const FIELD_SIZES: [usize; 1] = [16];
+    pub const FIELD_COUNT: usize = 1;
+    pub fn value(&self) -> Uint128 {
+        Uint128::new_unchecked(self.0.slice(0..16))
+    }
+    pub fn as_reader<'r>(&'r self) -> MaxAmountReader<'r> {
+        MaxAmountReader::new_unchecked(self.as_slice())
+    }
+}
+impl molecule::prelude::Entity for MaxAmount {
+    type Builder = MaxAmountBuilder;
+    const NAME: &'static str = "MaxAmount";
+    fn new_unchecked(data: molecule::bytes::Bytes) -> Self {
+        MaxAmount(data)
+    }
+    fn as_bytes(&self) -> molecule::bytes::Bytes {
+        self.0.clone()
+    }
+    fn as_slice(&self) -> &[u8] {
+        &self.0[..]
+    }
+    fn from_slice(slice: &[u8]) -> molecule::error::VerificationResult<Self> {
+        MaxAmountReader::from_slice(slice).map(|reader| reader.to_entity())
+    }
+    fn from_compatible_slice(slice: &[u8]) -> molecule::error::VerificationResult<Self> {
+        MaxAmountReader::from_compatible_slice(slice).map(|reader| reader.to_entity())
+    }
+    fn new_builder() -> Self::Builder {
+        ::core::default::Default::default()
+    }
+    fn as_builder(self) -> Self::Builder {
+        Self::new_builder().value(self.value())
+    }
+}
+#[derive(Clone, Copy)]
+pub struct MaxAmountReader<'r>(&'r [u8]);
+impl<'r> ::core::fmt::LowerHex for MaxAmountReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        use molecule::hex_string;
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex_string(self.as_slice()))
+    }
+}
+impl<'r> ::core::fmt::Debug for MaxAmountReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{}({:#x})", Self::NAME, self)
+    }
+}
+impl<'r> ::core::fmt::Display for MaxAmountReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{} {{ ", Self::NAME)?;
+        write!(f, "{}: {}", "value", self.value())?;
+        write!(f, " }}")
+    }
+}
+impl<'r> MaxAmountReader<'r> {
+    pub const TOTAL_SIZE: usize = 16;
+    pub const FIELD_SIZES: [usize; 1] = [16];
+    pub const FIELD_COUNT: usize = 1;
+    pub fn value(&self) -> Uint128Reader<'r> {
+        Uint128Reader::new_unchecked(&self.as_slice()[0..16])
+    }
+}
+impl<'r> molecule::prelude::Reader<'r> for MaxAmountReader<'r> {
+    type Entity = MaxAmount;
+    const NAME: &'static str = "MaxAmountReader";
+    fn to_entity(&self) -> Self::Entity {
+        Self::Entity::new_unchecked(self.as_slice().to_owned().into())
+    }
+    fn new_unchecked(slice: &'r [u8]) -> Self {
+        MaxAmountReader(slice)
+    }
+    fn as_slice(&self) -> &'r [u8] {
+        self.0
+    }
+    fn verify(slice: &[u8], _compatible: bool) -> molecule::error::VerificationResult<()> {
+        use molecule::verification_error as ve;
+        let slice_len = slice.len();
+        if slice_len != Self::TOTAL_SIZE {
+            return ve!(Self, TotalSizeNotMatch, Self::TOTAL_SIZE, slice_len);
+        }
+        Ok(())
+    }
+}
+#[derive(Clone, Debug, Default)]
+pub struct MaxAmountBuilder {
+    pub(crate) value: Uint128,
+}
+impl MaxAmountBuilder {
+    pub const TOTAL_SIZE: usize = 16;
+    pub const FIELD_SIZES: [usize; 1] = [16];
+    pub const FIELD_COUNT: usize = 1;
+    pub fn value(mut self, v: Uint128) -> Self {
+        self.value = v;
+        self
+    }
+}
+impl molecule::prelude::Builder for MaxAmountBuilder {
+    type Entity = MaxAmount;
+    const NAME: &'static str = "MaxAmountBuilder";
+    fn expected_length(&self) -> usize {
+        Self::TOTAL_SIZE
+    }
+    fn write<W: molecule::io::Write>(&self, writer: &mut W) -> molecule::io::Result<()> {
+        writer.write_all(self.value.as_slice())?;
+        Ok(())
+    }
+    fn build(&self) -> Self::Entity {
+        let mut inner = Vec::with_capacity(self.expected_length());
+        self.write(&mut inner)
+            .unwrap_or_else(|_| panic!("{} build should be ok", Self::NAME));
+        MaxAmount::new_unchecked(inner.into())
+    }
+}
+#[derive(Clone)]
+pub struct FeesPaidBy(molecule::bytes::Bytes);
+impl ::core::fmt::LowerHex for FeesPaidBy {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        use molecule::hex_string;
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex_string(self.as_slice()))
+    }
+}
+impl ::core::fmt::Debug for FeesPaidBy {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{}({:#x})", Self::NAME, self)
+    }
+}
+impl ::core::fmt::Display for FeesPaidBy {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{} {{ ", Self::NAME)?;
+        write!(f, "{}: {}", "value", self.value())?;
+        write!(f, " }}")
+    }
+}
+impl ::core::default::Default for FeesPaidBy {
+    fn default() -> Self {
+        let v = molecule::bytes::Bytes::from_static(&Self::DEFAULT_VALUE);
+        FeesPaidBy::new_unchecked(v)
+    }
+}
+impl FeesPaidBy {
+    const DEFAULT_VALUE: [u8; 1] = [0];
+    pub const TOTAL_SIZE: usize = 1;
+    pub const FIELD_SIZES: [usize; 1] = [1];
+    pub const FIELD_COUNT: usize = 1;
+    pub fn value(&self) -> Byte {
+        Byte::new_unchecked(self.0.slice(0..1))
+    }
+    pub fn as_reader<'r>(&'r self) -> FeesPaidByReader<'r> {
+        FeesPaidByReader::new_unchecked(self.as_slice())
+    }
+}
+impl molecule::prelude::Entity for FeesPaidBy {
+    type Builder = FeesPaidByBuilder;
+    const NAME: &'static str = "FeesPaidBy";
+    fn new_unchecked(data: molecule::bytes::Bytes) -> Self {
+        FeesPaidBy(data)
+    }
+    fn as_bytes(&self) -> molecule::bytes::Bytes {
+        self.0.clone()
+    }
+    fn as_slice(&self) -> &[u8] {
+        &self.0[..]
+    }
+    fn from_slice(slice: &[u8]) -> molecule::error::VerificationResult<Self> {
+        FeesPaidByReader::from_slice(slice).map(|reader| reader.to_entity())
+    }
+    fn from_compatible_slice(slice: &[u8]) -> molecule::error::VerificationResult<Self> {
+        FeesPaidByReader::from_compatible_slice(slice).map(|reader| reader.to_entity())
+    }
+    fn new_builder() -> Self::Builder {
+        ::core::default::Default::default()
+    }
+    fn as_builder(self) -> Self::Builder {
+        Self::new_builder().value(self.value())
+    }
+}
+#[derive(Clone, Copy)]
+pub struct FeesPaidByReader<'r>(&'r [u8]);
+impl<'r> ::core::fmt::LowerHex for FeesPaidByReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        use molecule::hex_string;
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex_string(self.as_slice()))
+    }
+}
+impl<'r> ::core::fmt::Debug for FeesPaidByReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{}({:#x})", Self::NAME, self)
+    }
+}
+impl<'r> ::core::fmt::Display for FeesPaidByReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{} {{ ", Self::NAME)?;
+        write!(f, "{}: {}", "value", self.value())?;
+        write!(f, " }}")
+    }
+}
+impl<'r> FeesPaidByReader<'r> {
+    pub const TOTAL_SIZE: usize = 1;
+    pub const FIELD_SIZES: [usize; 1] = [1];
+    pub const FIELD_COUNT: usize = 1;
+    pub fn value(&self) -> ByteReader<'r> {
+        ByteReader::new_unchecked(&self.as_slice()[0..1])
+    }
+}
+impl<'r> molecule::prelude::Reader<'r> for FeesPaidByReader<'r> {
+    type Entity = FeesPaidBy;
+    const NAME: &'static str = "FeesPaidByReader";
+    fn to_entity(&self) -> Self::Entity {
+        Self::Entity::new_unchecked(self.as_slice().to_owned().into())
+    }
+    fn new_unchecked(slice: &'r [u8]) -> Self {
+        FeesPaidByReader(slice)
+    }
+    fn as_slice(&self) -> &'r [u8] {
+        self.0
+    }
+    fn verify(slice: &[u8], _compatible: bool) -> molecule::error::VerificationResult<()> {
+        use molecule::verification_error as ve;
+        let slice_len = slice.len();
+        if slice_len != Self::TOTAL_SIZE {
+            return ve!(Self, TotalSizeNotMatch, Self::TOTAL_SIZE, slice_len);
+        }
+        Ok(())
+    }
+}
+#[derive(Clone, Debug, Default)]
+pub struct FeesPaidByBuilder {
+    pub(crate) value: Byte,
+}
+impl FeesPaidByBuilder {
+    pub const TOTAL_SIZE: usize = 1;
+    pub const FIELD_SIZES: [usize; 1] = [1];
+    pub const FIELD_COUNT: usize = 1;
+    pub fn value(mut self, v: Byte) -> Self {
+        self.value = v;
+        self
+    }
+}
+impl molecule::prelude::Builder for FeesPaidByBuilder {
+    type Entity = FeesPaidBy;
+    const NAME: &'static str = "FeesPaidByBuilder";
+    fn expected_length(&self) -> usize {
+        Self::TOTAL_SIZE
+    }
+    fn write<W: molecule::io::Write>(&self, writer: &mut W) -> molecule::io::Result<()> {
+        writer.write_all(self.value.as_slice())?;
+        Ok(())
+    }
+    fn build(&self) -> Self::Entity {
+        let mut inner = Vec::with_capacity(self.expected_length());
+        self.write(&mut inner)
+            .unwrap_or_else(|_| panic!("{} build should be ok", Self::NAME));
+        FeesPaidBy::new_unchecked(inner.into())
+    }
+}
+#[derive(Clone)]
 pub struct InvoiceAttr(molecule::bytes::Bytes);
 impl ::core::fmt::LowerHex for InvoiceAttr {
     fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
@@ -4874,7 +5318,7 @@ impl ::core::default::Default for InvoiceAttr {
 }
 impl InvoiceAttr {
     const DEFAULT_VALUE: [u8; 20] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
-    pub const ITEMS_COUNT: usize = 9;
+    pub const ITEMS_COUNT: usize = 12;
     pub fn item_id(&self) -> molecule::Number {
         molecule::unpack_number(self.as_slice())
     }
@@ -4890,6 +5334,9 @@ impl InvoiceAttr {
             6 => UdtScript::new_unchecked(inner).into(),
             7 => PayeePublicKey::new_unchecked(inner).into(),
             8 => HashAlgorithm::new_unchecked(inner).into(),
+            9 => MinAmount::new_unchecked(inner).into(),
+            10 => MaxAmount::new_unchecked(inner).into(),
+            11 => FeesPaidBy::new_unchecked(inner).into(),
             _ => panic!("{}: invalid data", Self::NAME),
         }
     }
@@ -4946,7 +5393,7 @@ impl<'r> ::core::fmt::Display for InvoiceAttrReader<'r> {
     }
 }
 impl<'r> InvoiceAttrReader<'r> {
-    pub const ITEMS_COUNT: usize = 9;
+    pub const ITEMS_COUNT: usize = 12;
     pub fn item_id(&self) -> molecule::Number {
         molecule::unpack_number(self.as_slice())
     }
@@ -4962,6 +5409,9 @@ impl<'r> InvoiceAttrReader<'r> {
             6 => UdtScriptReader::new_unchecked(inner).into(),
             7 => PayeePublicKeyReader::new_unchecked(inner).into(),
             8 => HashAlgorithmReader::new_unchecked(inner).into(),
+            9 => MinAmountReader::new_unchecked(inner).into(),
+            10 => MaxAmountReader::new_unchecked(inner).into(),
+            11 => FeesPaidByReader::new_unchecked(inner).into(),
             _ => panic!("{}: invalid data", Self::NAME),
         }
     }
@@ -4996,6 +5446,9 @@ impl<'r> molecule::prelude::Reader<'r> for InvoiceAttrReader<'r> {
             6 => UdtScriptReader::verify(inner_slice, compatible),
             7 => PayeePublicKeyReader::verify(inner_slice, compatible),
             8 => HashAlgorithmReader::verify(inner_slice, compatible),
+            9 => MinAmountReader::verify(inner_slice, compatible),
+            10 => MaxAmountReader::verify(inner_slice, compatible),
+            11 => FeesPaidByReader::verify(inner_slice, compatible),
             _ => ve!(Self, UnknownItem, Self::ITEMS_COUNT, item_id),
         }?;
         Ok(())
@@ -5004,7 +5457,7 @@ impl<'r> molecule::prelude::Reader<'r> for InvoiceAttrReader<'r> {
 #[derive(Clone, Debug, Default)]
 pub struct InvoiceAttrBuilder(pub(crate) InvoiceAttrUnion);
 impl InvoiceAttrBuilder {
-    pub const ITEMS_COUNT: usize = 9;
+    pub const ITEMS_COUNT: usize = 12;
     pub fn set<I>(mut self, v: I) -> Self
     where
         I: ::core::convert::Into<InvoiceAttrUnion>,
@@ -5041,6 +5494,9 @@ pub enum InvoiceAttrUnion {
     UdtScript(UdtScript),
     PayeePublicKey(PayeePublicKey),
     HashAlgorithm(HashAlgorithm),
+    MinAmount(MinAmount),
+    MaxAmount(MaxAmount),
+    FeesPaidBy(FeesPaidBy),
 }
 #[derive(Debug, Clone, Copy)]
 pub enum InvoiceAttrUnionReader<'r> {
@@ -5053,6 +5509,9 @@ pub enum InvoiceAttrUnionReader<'r> {
     UdtScript(UdtScriptReader<'r>),
     PayeePublicKey(PayeePublicKeyReader<'r>),
     HashAlgorithm(HashAlgorithmReader<'r>),
+    MinAmount(MinAmountReader<'r>),
+    MaxAmount(MaxAmountReader<'r>),
+    FeesPaidBy(FeesPaidByReader<'r>),
 }
 impl ::core::default::Default for InvoiceAttrUnion {
     fn default() -> Self {
@@ -5095,6 +5554,15 @@ impl ::core::fmt::Display for InvoiceAttrUnion {
             InvoiceAttrUnion::HashAlgorithm(ref item) => {
                 write!(f, "{}::{}({})", Self::NAME, HashAlgorithm::NAME, item)
             }
+            InvoiceAttrUnion::MinAmount(ref item) => {
+                write!(f, "{}::{}({})", Self::NAME, MinAmount::NAME, item)
+            }
+            InvoiceAttrUnion::MaxAmount(ref item) => {
+                write!(f, "{}::{}({})", Self::NAME, MaxAmount::NAME, item)
+            }
+            InvoiceAttrUnion::FeesPaidBy(ref item) => {
+                write!(f, "{}::{}({})", Self::NAME, FeesPaidBy::NAME, item)
+            }
         }
     }
 }
@@ -5134,6 +5602,15 @@ impl<'r> ::core::fmt::Display for InvoiceAttrUnionReader<'r> {
             InvoiceAttrUnionReader::HashAlgorithm(ref item) => {
                 write!(f, "{}::{}({})", Self::NAME, HashAlgorithm::NAME, item)
             }
+            InvoiceAttrUnionReader::MinAmount(ref item) => {
+                write!(f, "{}::{}({})", Self::NAME, MinAmount::NAME, item)
+            }
+            InvoiceAttrUnionReader::MaxAmount(ref item) => {
+                write!(f, "{}::{}({})", Self::NAME, MaxAmount::NAME, item)
+            }
+            InvoiceAttrUnionReader::FeesPaidBy(ref item) => {
+                write!(f, "{}::{}({})", Self::NAME, FeesPaidBy::NAME, item)
+            }
         }
     }
 }
@@ -5149,6 +5626,9 @@ impl InvoiceAttrUnion {
             InvoiceAttrUnion::UdtScript(ref item) => write!(f, "{}", item),
             InvoiceAttrUnion::PayeePublicKey(ref item) => write!(f, "{}", item),
             InvoiceAttrUnion::HashAlgorithm(ref item) => write!(f, "{}", item),
+            InvoiceAttrUnion::MinAmount(ref item) => write!(f, "{}", item),
+            InvoiceAttrUnion::MaxAmount(ref item) => write!(f, "{}", item),
+            InvoiceAttrUnion::FeesPaidBy(ref item) => write!(f, "{}", item),
         }
     }
 }
@@ -5164,6 +5644,9 @@ impl<'r> InvoiceAttrUnionReader<'r> {
             InvoiceAttrUnionReader::UdtScript(ref item) => write!(f, "{}", item),
             InvoiceAttrUnionReader::PayeePublicKey(ref item) => write!(f, "{}", item),
             InvoiceAttrUnionReader::HashAlgorithm(ref item) => write!(f, "{}", item),
+            InvoiceAttrUnionReader::MinAmount(ref item) => write!(f, "{}", item),
+            InvoiceAttrUnionReader::MaxAmount(ref item) => write!(f, "{}", item),
+            InvoiceAttrUnionReader::FeesPaidBy(ref item) => write!(f, "{}", item),
         }
     }
 }
@@ -5212,6 +5695,21 @@ impl ::core::convert::From<HashAlgorithm> for InvoiceAttrUnion {
         InvoiceAttrUnion::HashAlgorithm(item)
     }
 }
+impl ::core::convert::From<MinAmount> for InvoiceAttrUnion {
+    fn from(item: MinAmount) -> Self {
+        InvoiceAttrUnion::MinAmount(item)
+    }
+}
+impl ::core::convert::From<MaxAmount> for InvoiceAttrUnion {
+    fn from(item: MaxAmount) -> Self {
+        InvoiceAttrUnion::MaxAmount(item)
+    }
+}
+impl ::core::convert::From<FeesPaidBy> for InvoiceAttrUnion {
+    fn from(item: FeesPaidBy) -> Self {
+        InvoiceAttrUnion::FeesPaidBy(item)
+    }
+}
 impl<'r> ::core::convert::From<ExpiryTimeReader<'r>> for InvoiceAttrUnionReader<'r> {
     fn from(item: ExpiryTimeReader<'r>) -> Self {
         InvoiceAttrUnionReader::ExpiryTime(item)
@@ -5259,6 +5757,21 @@ impl<'r> ::core::convert::From<HashAlgorithmReader<'r>> for InvoiceAttrUnionRead
         InvoiceAttrUnionReader::HashAlgorithm(item)
     }
 }
+impl<'r> ::core::convert::From<MinAmountReader<'r>> for InvoiceAttrUnionReader<'r> {
+    fn from(item: MinAmountReader<'r>) -> Self {
+        InvoiceAttrUnionReader::MinAmount(item)
+    }
+}
+impl<'r> ::core::convert::From<MaxAmountReader<'r>> for InvoiceAttrUnionReader<'r> {
+    fn from(item: MaxAmountReader<'r>) -> Self {
+        InvoiceAttrUnionReader::MaxAmount(item)
+    }
+}
+impl<'r> ::core::convert::From<FeesPaidByReader<'r>> for InvoiceAttrUnionReader<'r> {
+    fn from(item: FeesPaidByReader<'r>) -> Self {
+        InvoiceAttrUnionReader::FeesPaidBy(item)
+    }
+}
 impl InvoiceAttrUnion {
     pub const NAME: &'static str = "InvoiceAttrUnion";
     pub fn as_bytes(&self) -> molecule::bytes::Bytes {
@@ -5272,6 +5785,9 @@ impl InvoiceAttrUnion {
             InvoiceAttrUnion::UdtScript(item) => item.as_bytes(),
             InvoiceAttrUnion::PayeePublicKey(item) => item.as_bytes(),
             InvoiceAttrUnion::HashAlgorithm(item) => item.as_bytes(),
+            InvoiceAttrUnion::MinAmount(item) => item.as_bytes(),
+            InvoiceAttrUnion::MaxAmount(item) => item.as_bytes(),
+            InvoiceAttrUnion::FeesPaidBy(item) => item.as_bytes(),
         }
     }
     pub fn as_slice(&self) -> &[u8] {
@@ -5285,6 +5801,9 @@ impl InvoiceAttrUnion {
             InvoiceAttrUnion::UdtScript(item) => item.as_slice(),
             InvoiceAttrUnion::PayeePublicKey(item) => item.as_slice(),
             InvoiceAttrUnion::HashAlgorithm(item) => item.as_slice(),
+            InvoiceAttrUnion::MinAmount(item) => item.as_slice(),
+            InvoiceAttrUnion::MaxAmount(item) => item.as_slice(),
+            InvoiceAttrUnion::FeesPaidBy(item) => item.as_slice(),
         }
     }
     pub fn item_id(&self) -> molecule::Number {
@@ -5298,6 +5817,9 @@ impl InvoiceAttrUnion {
             InvoiceAttrUnion::UdtScript(_) => 6,
             InvoiceAttrUnion::PayeePublicKey(_) => 7,
             InvoiceAttrUnion::HashAlgorithm(_) => 8,
+            InvoiceAttrUnion::MinAmount(_) => 9,
+            InvoiceAttrUnion::MaxAmount(_) => 10,
+            InvoiceAttrUnion::FeesPaidBy(_) => 11,
         }
     }
     pub fn item_name(&self) -> &str {
@@ -5311,6 +5833,9 @@ impl InvoiceAttrUnion {
             InvoiceAttrUnion::UdtScript(_) => "UdtScript",
             InvoiceAttrUnion::PayeePublicKey(_) => "PayeePublicKey",
             InvoiceAttrUnion::HashAlgorithm(_) => "HashAlgorithm",
+            InvoiceAttrUnion::MinAmount(_) => "MinAmount",
+            InvoiceAttrUnion::MaxAmount(_) => "MaxAmount",
+            InvoiceAttrUnion::FeesPaidBy(_) => "FeesPaidBy",
         }
     }
     pub fn as_reader<'r>(&'r self) -> InvoiceAttrUnionReader<'r> {
@@ -5324,6 +5849,9 @@ impl InvoiceAttrUnion {
             InvoiceAttrUnion::UdtScript(item) => item.as_reader().into(),
             InvoiceAttrUnion::PayeePublicKey(item) => item.as_reader().into(),
             InvoiceAttrUnion::HashAlgorithm(item) => item.as_reader().into(),
+            InvoiceAttrUnion::MinAmount(item) => item.as_reader().into(),
+            InvoiceAttrUnion::MaxAmount(item) => item.as_reader().into(),
+            InvoiceAttrUnion::FeesPaidBy(item) => item.as_reader().into(),
         }
     }
 }
@@ -5340,6 +5868,9 @@ impl<'r> InvoiceAttrUnionReader<'r> {
             InvoiceAttrUnionReader::UdtScript(item) => item.as_slice(),
             InvoiceAttrUnionReader::PayeePublicKey(item) => item.as_slice(),
             InvoiceAttrUnionReader::HashAlgorithm(item) => item.as_slice(),
+            InvoiceAttrUnionReader::MinAmount(item) => item.as_slice(),
+            InvoiceAttrUnionReader::MaxAmount(item) => item.as_slice(),
+            InvoiceAttrUnionReader::FeesPaidBy(item) => item.as_slice(),
         }
     }
     pub fn item_id(&self) -> molecule::Number {
@@ -5353,6 +5884,9 @@ impl<'r> InvoiceAttrUnionReader<'r> {
             InvoiceAttrUnionReader::UdtScript(_) => 6,
             InvoiceAttrUnionReader::PayeePublicKey(_) => 7,
             InvoiceAttrUnionReader::HashAlgorithm(_) => 8,
+            InvoiceAttrUnionReader::MinAmount(_) => 9,
+            InvoiceAttrUnionReader::MaxAmount(_) => 10,
+            InvoiceAttrUnionReader::FeesPaidBy(_) => 11,
         }
     }
     pub fn item_name(&self) -> &str {
@@ -5366,6 +5900,9 @@ impl<'r> InvoiceAttrUnionReader<'r> {
             InvoiceAttrUnionReader::UdtScript(_) => "UdtScript",
             InvoiceAttrUnionReader::PayeePublicKey(_) => "PayeePublicKey",
             InvoiceAttrUnionReader::HashAlgorithm(_) => "HashAlgorithm",
+            InvoiceAttrUnionReader::MinAmount(_) => "MinAmount",
+            InvoiceAttrUnionReader::MaxAmount(_) => "MaxAmount",
+            InvoiceAttrUnionReader::FeesPaidBy(_) => "FeesPaidBy",
         }
     }
 }
@@ -5414,6 +5951,21 @@ impl From<HashAlgorithm> for InvoiceAttr {
         Self::new_builder().set(value).build()
     }
 }
+impl From<MinAmount> for InvoiceAttr {
+    fn from(value: MinAmount) -> Self {
+        Self::new_builder().set(value).build()
+    }
+}
+impl From<MaxAmount> for InvoiceAttr {
+    fn from(value: MaxAmount) -> Self {
+        Self::new_builder().set(value).build()
+    }
+}
+impl From<FeesPaidBy> for InvoiceAttr {
+    fn from(value: FeesPaidBy) -> Self {
+        Self::new_builder().set(value).build()
+    }
+}
 #[derive(Clone)]
 pub struct InvoiceAttrsVec(molecule::bytes::Bytes);
 impl ::core::fmt::LowerHex for InvoiceAttrsVec {
@@ -1,18 +1,24 @@
 use super::network::{get_chain_hash, SendPaymentData, SendPaymentResponse};
 use super::path::NodeHeap;
 use super::types::Pubkey;
-use super::types::{ChannelAnnouncement, ChannelUpdate, Hash256, NodeAnnouncement};
+use super::types::{
+    ChannelAnnouncement, ChannelUpdate, FiberBroadcastMessage, Hash256, NodeAnnouncement,
+};
 use crate::fiber::channel::CHANNEL_DISABLED_FLAG;
+use crate::fiber::config::DEFAULT_TRAMPOLINE_EXPIRY_DELTA;
 use crate::fiber::fee::calculate_tlc_forward_fee;
+use crate::fiber::hash_algorithm::HashAlgorithm;
 use crate::fiber::path::{NodeHeapElement, ProbabilityEvaluator};
 use crate::fiber::serde_utils::EntityHex;
-use crate::fiber::types::PaymentHopData;
-use crate::invoice::CkbInvoice;
+use crate::fiber::types::{PaymentHopData, TrampolineHopData};
+use crate::invoice::{CkbInvoice, FeesPaidBy};
+use crate::metrics::metrics;
 use ckb_jsonrpc_types::JsonBytes;
 use ckb_types::packed::{OutPoint, Script};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use thiserror::Error;
 use tracing::log::error;
 use tracing::{debug, info, warn};
@@ -32,6 +38,16 @@ pub struct NodeInfo {
     pub anouncement_msg: NodeAnnouncement,
 }
 
+impl NodeInfo {
+    // Whether this node is not newer than the stored one by the version of the node announcement,
+    // instead of the local time it is processed. An announcement of the same version is still
+    // newer if its content differs.
+    pub fn is_outdated_by(&self, stored: &NodeInfo) -> bool {
+        self.anouncement_msg.version < stored.anouncement_msg.version
+            || self.anouncement_msg == stored.anouncement_msg
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ChannelInfo {
     pub funding_tx_block_number: u64,
@@ -41,6 +57,10 @@ pub struct ChannelInfo {
     pub node2_to_node1: Option<ChannelUpdateInfo>,
     // The time that the channel was announced to the network.
     pub timestamp: u64,
+    // Private channels are imported by the node operator. They are only used by
+    // the local pathfinder, and they are never gossiped to peers.
+    #[serde(default)]
+    pub private: bool,
 }
 
 impl ChannelInfo {
@@ -83,6 +103,41 @@ impl ChannelInfo {
             .max(self.node2_to_node1.as_ref().map(|n| n.timestamp))
     }
 
+    // The time that the channel announcement or any of the channel updates was last processed.
+    pub fn last_update_timestamp(&self) -> u64 {
+        self.channel_last_update_time()
+            .map_or(self.timestamp, |t| t.max(self.timestamp))
+    }
+
+    // Whether this channel is not newer than the stored one by the gossip messages themselves,
+    // instead of the local time they are processed. It's outdated if the channel update of either
+    // direction goes backwards, or nothing is changed. A channel update of the same version is
+    // still newer if its content differs.
+    pub fn is_outdated_by(&self, stored: &ChannelInfo) -> bool {
+        let regressed =
+            |new: &Option<ChannelUpdateInfo>, old: &Option<ChannelUpdateInfo>| match (new, old) {
+                (_, None) => false,
+                (None, Some(_)) => true,
+                (Some(new), Some(old)) => new.version < old.version,
+            };
+        let changed =
+            |new: &Option<ChannelUpdateInfo>, old: &Option<ChannelUpdateInfo>| match (new, old) {
+                (None, _) => false,
+                (Some(_), None) => true,
+                (Some(new), Some(old)) => {
+                    new.version > old.version || new.last_update_message != old.last_update_message
+                }
+            };
+        regressed(&self.node1_to_node2, &stored.node1_to_node2)
+            || regressed(&self.node2_to_node1, &stored.node2_to_node1)
+            || !(changed(&self.node1_to_node2, &stored.node1_to_node2)
+                || changed(&self.node2_to_node1, &stored.node2_to_node1)
+                || self.announcement_msg != stored.announcement_msg
+                || (self.funding_tx_block_number, self.funding_tx_index)
+                    != (stored.funding_tx_block_number, stored.funding_tx_index)
+                || self.private != stored.private)
+    }
+
     // Whether this channel is explicitly disabled in either direction.
     // TODO: we currently deem a channel as disabled if one direction is disabled.
     // Is it possible that one direction is disabled while the other is not?
@@ -127,6 +182,15 @@ pub struct ChannelUpdateInfo {
     pub last_update_message: ChannelUpdate,
 }
 
+/// The filter of the gossip messages accepted by a node which only maintains a subgraph of the network.
+#[derive(Clone, Debug, Default)]
+pub struct GossipFilter {
+    /// Nodes whose channels are accepted, our own node is always included.
+    pub nodes: Vec<Pubkey>,
+    /// The channels of the nodes within this number of hops from the filter nodes are also accepted.
+    pub max_hops: u8,
+}
+
 #[derive(Clone, Debug)]
 pub struct NetworkGraph<S> {
     source: Pubkey,
@@ -141,6 +205,77 @@ pub struct NetworkGraph<S> {
     nodes: HashMap<Pubkey, NodeInfo>,
     store: S,
     chain_hash: Hash256,
+    // Only the gossip messages relevant to the filter are accepted, None means accepting all.
+    gossip_filter: Option<GossipFilter>,
+    // Whether the stored channels or nodes are changed since the graph snapshot is persisted.
+    snapshot_outdated: bool,
+}
+
+/// The version of the graph snapshot format, the snapshots of other versions are ignored.
+const GRAPH_SNAPSHOT_VERSION: u32 = 1;
+
+/// A snapshot of the stored channels and nodes persisted as a whole, so that the graph can
+/// be loaded at startup without scanning all the stored channels and nodes. The changes of
+/// the channels and nodes are logged, and the snapshot covers the changes before `change_index`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GraphSnapshot {
+    pub version: u32,
+    pub chain_hash: Hash256,
+    pub change_index: u64,
+    pub best_height: u64,
+    pub last_update_timestamp: u64,
+    pub channels: Vec<ChannelInfo>,
+    pub nodes: Vec<NodeInfo>,
+}
+
+impl GraphSnapshot {
+    fn apply_changes(&mut self, changes: GraphChanges) {
+        let mut channel_positions: HashMap<_, _> = self
+            .channels
+            .iter()
+            .enumerate()
+            .map(|(position, channel)| (channel.out_point(), position))
+            .collect();
+        for channel in changes.channels {
+            self.best_height = self.best_height.max(channel.funding_tx_block_number());
+            self.last_update_timestamp = self
+                .last_update_timestamp
+                .max(channel.last_update_timestamp());
+            match channel_positions.get(&channel.out_point()) {
+                Some(&position) => self.channels[position] = channel,
+                None => {
+                    channel_positions.insert(channel.out_point(), self.channels.len());
+                    self.channels.push(channel);
+                }
+            }
+        }
+        let mut node_positions: HashMap<_, _> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(position, node)| (node.node_id, position))
+            .collect();
+        for node in changes.nodes {
+            self.last_update_timestamp = self.last_update_timestamp.max(node.timestamp);
+            match node_positions.get(&node.node_id) {
+                Some(&position) => self.nodes[position] = node,
+                None => {
+                    node_positions.insert(node.node_id, self.nodes.len());
+                    self.nodes.push(node);
+                }
+            }
+        }
+        self.change_index = changes.next_index;
+    }
+}
+
+/// The channels and nodes changed since a logged change, `next_index` is the index of the
+/// change following the last one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GraphChanges {
+    pub channels: Vec<ChannelInfo>,
+    pub nodes: Vec<NodeInfo>,
+    pub next_index: u64,
 }
 
 #[derive(Error, Debug)]
@@ -153,6 +288,24 @@ pub enum GraphError {
     Other(String),
 }
 
+// Reject the route if its fee exceeds `max_fee_percent` of the amount, to avoid overpaying
+// unknowingly for a small payment which can only be routed through expensive channels.
+fn check_route_fee_percent(
+    fee: u128,
+    amount: u128,
+    max_fee_percent: Option<u64>,
+) -> Result<(), GraphError> {
+    match max_fee_percent {
+        Some(max_fee_percent) if max_fee_percent > 0 && fee * 100 > amount * max_fee_percent as u128 => {
+            Err(GraphError::PathFind(format!(
+                "the fee {} of the route exceeds {}% of the amount {}, set max_fee_percent to 0 to send the payment anyway",
+                fee, max_fee_percent, amount
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PathEdge {
     pub target: Pubkey,
@@ -172,6 +325,8 @@ where
             nodes: HashMap::new(),
             store,
             chain_hash: get_chain_hash(),
+            gossip_filter: None,
+            snapshot_outdated: false,
         };
         network_graph.load_from_store();
         network_graph
@@ -181,33 +336,165 @@ where
         self.chain_hash
     }
 
+    /// Loads the graph from the persisted snapshot along with the channels and nodes changed
+    /// after it, falls back to scanning the stored channels and nodes if the snapshot is missing.
     pub(crate) fn load_from_store(&mut self) {
-        let channels = self.store.get_channels(None);
-        for channel in channels.iter() {
-            if self.best_height < channel.funding_tx_block_number() {
-                self.best_height = channel.funding_tx_block_number();
-            }
-            if self.last_update_timestamp < channel.timestamp {
-                self.last_update_timestamp = channel.timestamp;
-            }
-            if let Some(channel_update) = channel.node1_to_node2.as_ref() {
-                if self.last_update_timestamp < channel_update.timestamp {
-                    self.last_update_timestamp = channel_update.timestamp;
+        let snapshot = self.get_updated_snapshot();
+        self.best_height = self.best_height.max(snapshot.best_height);
+        self.last_update_timestamp = self
+            .last_update_timestamp
+            .max(snapshot.last_update_timestamp);
+        for channel in snapshot.channels {
+            self.channels.insert(channel.out_point(), channel);
+        }
+        for node in snapshot.nodes {
+            self.nodes.insert(node.node_id, node);
+        }
+        self.update_graph_size_metrics();
+    }
+
+    // Returns the persisted snapshot updated with the changes logged after it, or the snapshot
+    // built by scanning the stored channels and nodes if there is no usable one.
+    fn get_updated_snapshot(&mut self) -> GraphSnapshot {
+        let Some(mut snapshot) = self.store.get_graph_snapshot().filter(|snapshot| {
+            snapshot.version == GRAPH_SNAPSHOT_VERSION && snapshot.chain_hash == self.chain_hash
+        }) else {
+            self.snapshot_outdated = true;
+            return self.snapshot_from_store();
+        };
+        let changes = self.store.get_graph_changes(snapshot.change_index);
+        debug!(
+            "Updating network graph snapshot of {} channels and {} nodes with {} changed channels and {} changed nodes",
+            snapshot.channels.len(),
+            snapshot.nodes.len(),
+            changes.channels.len(),
+            changes.nodes.len()
+        );
+        if !changes.channels.is_empty() || !changes.nodes.is_empty() {
+            self.snapshot_outdated = true;
+        }
+        snapshot.apply_changes(changes);
+        snapshot
+    }
+
+    // Build the graph snapshot by scanning the stored channels and nodes.
+    fn snapshot_from_store(&self) -> GraphSnapshot {
+        // The logged changes are all covered by the scan.
+        let change_index = self.store.get_graph_changes(0).next_index;
+        let mut snapshot = GraphSnapshot {
+            version: GRAPH_SNAPSHOT_VERSION,
+            chain_hash: self.chain_hash,
+            change_index,
+            best_height: 0,
+            last_update_timestamp: 0,
+            channels: vec![],
+            nodes: vec![],
+        };
+        snapshot.apply_changes(GraphChanges {
+            channels: self.store.get_channels(None),
+            nodes: self.store.get_nodes(None),
+            next_index: change_index,
+        });
+        snapshot
+    }
+
+    /// Persists the graph snapshot if the stored channels or nodes are changed since the last
+    /// snapshot, only the changes are read from the store unless there is no snapshot yet.
+    /// Returns whether a new snapshot is persisted.
+    pub(crate) fn save_snapshot(&mut self) -> bool {
+        if !self.snapshot_outdated {
+            return false;
+        }
+        let snapshot = self.get_updated_snapshot();
+        debug!(
+            "Saving network graph snapshot with {} channels and {} nodes",
+            snapshot.channels.len(),
+            snapshot.nodes.len()
+        );
+        self.store.insert_graph_snapshot(snapshot);
+        self.snapshot_outdated = false;
+        true
+    }
+
+    fn update_graph_size_metrics(&self) {
+        metrics().set_graph_size(self.nodes.len(), self.channels.len());
+    }
+
+    pub fn set_gossip_filter(&mut self, gossip_filter: Option<GossipFilter>) {
+        self.gossip_filter = gossip_filter;
+    }
+
+    // Returns the distances of the nodes within `max_distance` hops from the filter nodes,
+    // walking through the channels already in the graph.
+    fn get_gossip_filter_distances(
+        &self,
+        gossip_filter: &GossipFilter,
+        max_distance: u8,
+    ) -> HashMap<Pubkey, u8> {
+        let mut distances: HashMap<Pubkey, u8> = gossip_filter
+            .nodes
+            .iter()
+            .chain(std::iter::once(&self.source))
+            .map(|node| (*node, 0))
+            .collect();
+        let mut frontier: HashSet<Pubkey> = distances.keys().cloned().collect();
+        for distance in 1..=max_distance {
+            let mut next_frontier = HashSet::new();
+            for channel in self.channels.values() {
+                for (from, to) in [
+                    (channel.node1(), channel.node2()),
+                    (channel.node2(), channel.node1()),
+                ] {
+                    if frontier.contains(&from) && !distances.contains_key(&to) {
+                        distances.insert(to, distance);
+                        next_frontier.insert(to);
+                    }
                 }
             }
-            if let Some(channel_update) = channel.node2_to_node1.as_ref() {
-                if self.last_update_timestamp < channel_update.timestamp {
-                    self.last_update_timestamp = channel_update.timestamp;
-                }
+            if next_frontier.is_empty() {
+                break;
             }
-            self.channels.insert(channel.out_point(), channel.clone());
+            frontier = next_frontier;
         }
-        let nodes = self.store.get_nodes(None);
-        for node in nodes.iter() {
-            if self.last_update_timestamp < node.timestamp {
-                self.last_update_timestamp = node.timestamp;
+        distances
+    }
+
+    /// Whether the channel is relevant to the gossip filter, i.e. one of its nodes is within
+    /// `max_hops` hops from the filter nodes.
+    pub fn is_channel_in_gossip_filter(&self, channel_announcement: &ChannelAnnouncement) -> bool {
+        let Some(gossip_filter) = &self.gossip_filter else {
+            return true;
+        };
+        let distances = self.get_gossip_filter_distances(gossip_filter, gossip_filter.max_hops);
+        distances.contains_key(&channel_announcement.node1_id)
+            || distances.contains_key(&channel_announcement.node2_id)
+    }
+
+    /// Whether the node is relevant to the gossip filter, i.e. it is a node of the channels
+    /// accepted by the gossip filter.
+    pub fn is_node_in_gossip_filter(&self, node_id: &Pubkey) -> bool {
+        let Some(gossip_filter) = &self.gossip_filter else {
+            return true;
+        };
+        self.get_gossip_filter_distances(gossip_filter, gossip_filter.max_hops.saturating_add(1))
+            .contains_key(node_id)
+    }
+
+    /// Whether the broadcasted message should be processed and relayed, messages outside of
+    /// the gossip filter are dropped before saving to the store.
+    pub fn is_gossip_message_accepted(&self, message: &FiberBroadcastMessage) -> bool {
+        match message {
+            FiberBroadcastMessage::NodeAnnouncement(node_announcement) => {
+                self.is_node_in_gossip_filter(&node_announcement.node_id)
+            }
+            FiberBroadcastMessage::ChannelAnnouncement(channel_announcement) => {
+                self.is_channel_in_gossip_filter(channel_announcement)
+            }
+            // Updates of the channels dropped by the filter are also dropped.
+            FiberBroadcastMessage::ChannelUpdate(channel_update) => {
+                self.gossip_filter.is_none()
+                    || self.channels.contains_key(&channel_update.channel_outpoint)
             }
-            self.nodes.insert(node.node_id, node.clone());
         }
     }
 
@@ -251,6 +538,8 @@ where
         }
         self.nodes.insert(node_id, node_info.clone());
         self.store.insert_node(node_info);
+        self.snapshot_outdated = true;
+        self.update_graph_size_metrics();
     }
 
     // TODO: If we are syncing with the peers for newest graph, we should
@@ -298,9 +587,69 @@ where
         let outpoint = channel_info.out_point();
         self.channels.insert(outpoint.clone(), channel_info.clone());
         self.store.insert_channel(channel_info);
+        self.snapshot_outdated = true;
+        self.update_graph_size_metrics();
         debug!("Successfully added channel {:?}", outpoint);
     }
 
+    /// Add a channel known out of band to the graph. The channel is marked as private,
+    /// and the given channel update is applied to both directions of the channel.
+    pub fn add_private_channel(
+        &mut self,
+        announcement: ChannelAnnouncement,
+        update: ChannelUpdate,
+    ) -> Result<(), GraphError> {
+        if announcement.node1_id == announcement.node2_id {
+            return Err(GraphError::Other(
+                "the two nodes of a channel must be different".to_string(),
+            ));
+        }
+        if announcement.capacity == 0 {
+            return Err(GraphError::Other(
+                "the capacity of a channel must be greater than 0".to_string(),
+            ));
+        }
+        if update.tlc_maximum_value != 0 && update.tlc_minimum_value > update.tlc_maximum_value {
+            return Err(GraphError::Other(
+                "the minimal tlc value must not exceed the maximal tlc value".to_string(),
+            ));
+        }
+        let outpoint = announcement.channel_outpoint.clone();
+        if self.channels.contains_key(&outpoint) {
+            return Err(GraphError::Other(format!(
+                "channel {:?} already exists",
+                &outpoint
+            )));
+        }
+        debug!(
+            "Adding private channel to network graph: {:?}",
+            &announcement
+        );
+        let channel_info = ChannelInfo {
+            funding_tx_block_number: 0,
+            funding_tx_index: 0,
+            announcement_msg: announcement,
+            node1_to_node2: None,
+            node2_to_node1: None,
+            timestamp: std::time::UNIX_EPOCH.elapsed().unwrap().as_millis() as u64,
+            private: true,
+        };
+        // Don't touch the best height and last update timestamp here, they are
+        // only used to sync the public graph from peers.
+        self.channels.insert(outpoint.clone(), channel_info.clone());
+        self.store.insert_channel(channel_info);
+        self.snapshot_outdated = true;
+        for message_flags in [0, 1] {
+            self.process_channel_update(ChannelUpdate {
+                channel_outpoint: outpoint.clone(),
+                message_flags,
+                ..update.clone()
+            })?;
+        }
+        self.update_graph_size_metrics();
+        Ok(())
+    }
+
     pub fn nodes(&self) -> impl Iterator<Item = &NodeInfo> {
         self.nodes.values()
     }
@@ -410,12 +759,14 @@ where
         });
 
         self.store.insert_channel(channel.to_owned());
+        self.snapshot_outdated = true;
         debug!(
             "Processed channel update: channel {:?}, update {:?}",
             &channel, &update
         );
         if disabled {
             self.channels.remove(channel_outpoint);
+            self.update_graph_size_metrics();
         }
         Ok(())
     }
@@ -480,10 +831,12 @@ where
     }
 
     /// Returns a list of `PaymentHopData` for all nodes in the route, including the origin and the target node.
+    /// The route of a trampoline payment ends at the trampoline node instead of the target node.
     pub fn build_route(
         &self,
         payment_data: &SendPaymentData,
     ) -> Result<Vec<PaymentHopData>, GraphError> {
+        let max_fee_amount = payment_data.fee_cap().map(|(cap, _)| cap);
         let payment_data = payment_data.clone();
         let source = self.get_source_pubkey();
         let target = payment_data.target_pubkey;
@@ -504,6 +857,7 @@ where
             source, target, amount, payment_hash
         );
 
+        let max_fee_percent = payment_data.max_fee_percent;
         let allow_self_payment = payment_data.allow_self_payment;
         if source == target && !allow_self_payment {
             return Err(GraphError::PathFind(
@@ -511,18 +865,222 @@ where
             ));
         }
 
+        let Some(trampoline_node) = payment_data.trampoline_node else {
+            let route = self.find_route(
+                source,
+                target,
+                amount,
+                max_fee_amount,
+                udt_type_script.clone(),
+                allow_self_payment,
+                payment_data.outgoing_channel.as_ref(),
+                payment_data.first_hop_limits.as_ref(),
+            );
+            let route = match (route, payment_data.keep_reserve) {
+                (Err(err), Some(keep_reserve)) if payment_data.first_hop_limits.is_some() => {
+                    // Tell whether the payment is infeasible only because of the reserve.
+                    return match self.find_route(
+                        source,
+                        target,
+                        amount,
+                        max_fee_amount,
+                        udt_type_script,
+                        allow_self_payment,
+                        payment_data.outgoing_channel.as_ref(),
+                        None,
+                    ) {
+                        Ok(_) => Err(GraphError::PathFind(format!(
+                            "no path found which keeps the reserve {} in the first channel",
+                            keep_reserve
+                        ))),
+                        Err(_) => Err(err),
+                    };
+                }
+                (route, _) => route?,
+            };
+            let build_onion_infos = |amount| {
+                self.build_onion_infos(&route, amount, payment_hash, hash_algorithm, preimage, None)
+            };
+            let mut onion_infos = build_onion_infos(amount);
+            let mut received_amount = amount;
+            if payment_data.fees_paid_by == FeesPaidBy::Receiver {
+                // The fees are deducted from the amount, scale down the amount received by
+                // the target until no more than the amount is sent through the first hop.
+                while onion_infos[0].amount > amount {
+                    let sent_amount = onion_infos[0].amount;
+                    received_amount = received_amount
+                        .checked_mul(amount)
+                        .map(|product| product / sent_amount)
+                        .unwrap_or(received_amount - (sent_amount - amount));
+                    if received_amount == 0 {
+                        return Err(GraphError::Amount(format!(
+                            "the amount {} can't cover the fee of the route",
+                            amount
+                        )));
+                    }
+                    onion_infos = build_onion_infos(received_amount);
+                }
+            }
+            let fee = onion_infos[0].amount - received_amount;
+            check_route_fee_percent(fee, amount, max_fee_percent)?;
+            return Ok(onion_infos);
+        };
+
+        // The route ends at the trampoline node, which is paid with the fee budget left after
+        // the fee of the route to it, and completes the route to the target with the budget.
+        // The budget is cut down to the `max_fee_rate_ppm` of the amount if that's less.
+        let Some(fee_budget) = payment_data.max_fee_amount.and(max_fee_amount) else {
+            return Err(GraphError::Amount(
+                "max_fee_amount is required for trampoline payment".to_string(),
+            ));
+        };
         let route = self.find_route(
             source,
-            target,
-            amount,
-            payment_data.max_fee_amount,
+            trampoline_node,
+            amount + fee_budget,
+            Some(fee_budget),
             udt_type_script,
             allow_self_payment,
+            payment_data.outgoing_channel.as_ref(),
+            payment_data.first_hop_limits.as_ref(),
+        )?;
+        let onion_infos = self.build_onion_infos(
+            &route,
+            amount + fee_budget,
+            payment_hash,
+            hash_algorithm,
+            None,
+            None,
+        );
+        let route_fee = onion_infos[0].amount - (amount + fee_budget);
+        if route_fee > fee_budget {
+            return Err(GraphError::PathFind(format!(
+                "no path found to trampoline node within max_fee_amount: {:?}",
+                trampoline_node
+            )));
+        }
+        // The fee of the route only decreases with a smaller amount, so the total fee is within the budget.
+        check_route_fee_percent(fee_budget, amount, max_fee_percent)?;
+        Ok(self.build_onion_infos(
+            &route,
+            amount + fee_budget - route_fee,
+            payment_hash,
+            hash_algorithm,
+            None,
+            Some(TrampolineHopData {
+                target_pubkey: target,
+                amount,
+                preimage,
+            }),
+        ))
+    }
+
+    /// Returns a list of `PaymentHopData` for the circular route which leaves through the `outgoing_channel`
+    /// and comes back through the `incoming_channel`, both of which are channels of the source node.
+    pub fn build_circular_route(
+        &self,
+        outgoing_channel: &OutPoint,
+        incoming_channel: &OutPoint,
+        amount: u128,
+        max_fee_amount: Option<u128>,
+        udt_type_script: Option<Script>,
+    ) -> Result<Vec<PaymentHopData>, GraphError> {
+        let source = self.get_source_pubkey();
+        if outgoing_channel == incoming_channel {
+            return Err(GraphError::PathFind(
+                "the outgoing and incoming channels are the same".to_string(),
+            ));
+        }
+        let Some(channel) = self.get_channel(incoming_channel) else {
+            return Err(GraphError::PathFind(format!(
+                "incoming channel not found: {:?}",
+                incoming_channel
+            )));
+        };
+        // The last hop is the peer of the incoming channel, which forwards the tlc back to us.
+        let (peer, channel_update) = if channel.node1() == source {
+            (channel.node2(), channel.node2_to_node1.as_ref())
+        } else if channel.node2() == source {
+            (channel.node1(), channel.node1_to_node2.as_ref())
+        } else {
+            return Err(GraphError::PathFind(format!(
+                "incoming channel not found: {:?}",
+                incoming_channel
+            )));
+        };
+        let Some(channel_update) = channel_update.filter(|update| update.enabled) else {
+            return Err(GraphError::PathFind(format!(
+                "incoming channel is not enabled by the peer: {:?}",
+                incoming_channel
+            )));
+        };
+        if udt_type_script != channel.announcement_msg.udt_type_script {
+            return Err(GraphError::PathFind(format!(
+                "incoming channel holds a different asset: {:?}",
+                incoming_channel
+            )));
+        }
+        if amount < channel_update.htlc_minimum_value
+            || (channel_update.htlc_maximum_value != 0
+                && amount > channel_update.htlc_maximum_value)
+        {
+            return Err(GraphError::Amount(format!(
+                "the amount {} is out of the tlc value range of the incoming channel: [{}, {}]",
+                amount, channel_update.htlc_minimum_value, channel_update.htlc_maximum_value
+            )));
+        }
+        let last_hop_fee = calculate_tlc_forward_fee(amount, channel_update.fee_rate as u128);
+        let max_fee_amount = match max_fee_amount {
+            Some(max_fee_amount) if last_hop_fee > max_fee_amount => {
+                return Err(GraphError::PathFind(format!(
+                    "the fee {} of the incoming channel exceeds max_fee_amount {}",
+                    last_hop_fee, max_fee_amount
+                )));
+            }
+            max_fee_amount => max_fee_amount.map(|max_fee_amount| max_fee_amount - last_hop_fee),
+        };
+        let mut route = self.find_route(
+            source,
+            peer,
+            amount + last_hop_fee,
+            max_fee_amount,
+            udt_type_script,
+            false,
+            Some(outgoing_channel),
+            None,
         )?;
+        route.push(PathEdge {
+            target: source,
+            channel_outpoint: incoming_channel.clone(),
+        });
+        Ok(self.build_onion_infos(
+            &route,
+            amount,
+            Hash256::default(),
+            HashAlgorithm::default(),
+            None,
+            None,
+        ))
+    }
+
+    fn build_onion_infos(
+        &self,
+        route: &[PathEdge],
+        amount: u128,
+        payment_hash: Hash256,
+        hash_algorithm: HashAlgorithm,
+        preimage: Option<Hash256>,
+        trampoline: Option<TrampolineHopData>,
+    ) -> Vec<PaymentHopData> {
         assert!(!route.is_empty());
 
         let mut current_amount = amount;
-        let mut current_expiry = 0;
+        // The tlc received by a trampoline node must outlive the tlc it offers to the rest of the route.
+        let mut current_expiry = if trampoline.is_some() {
+            DEFAULT_TRAMPOLINE_EXPIRY_DELTA
+        } else {
+            0
+        };
         let mut onion_infos = vec![];
         for i in (0..route.len()).rev() {
             let is_last = i == route.len() - 1;
@@ -562,6 +1120,7 @@ where
                 expiry: current_expiry,
                 channel_outpoint: next_channel_outpoint,
                 preimage: if is_last { preimage } else { None },
+                trampoline: if is_last { trampoline.clone() } else { None },
             });
             current_amount += fee;
             current_expiry += expiry;
@@ -575,14 +1134,64 @@ where
             expiry: current_expiry,
             channel_outpoint: Some(route[0].channel_outpoint.clone()),
             preimage: None,
+            trampoline: None,
         });
         onion_infos.reverse();
         assert_eq!(onion_infos.len(), route.len() + 1);
         assert_eq!(onion_infos[route.len()].amount, amount);
-        Ok(onion_infos)
+        onion_infos
+    }
+
+    // the algorithm works from target-to-source to find the shortest path,
+    // the first hop is restricted to the `outgoing_channel` if it is given,
+    // and the amount sent through the first hop is limited by `first_hop_limits` if it is given.
+    /// Evaluates the best route of a payment without the fee constraints, and returns it if it's
+    /// rejected by any of them, to tell why no route can be built for the payment.
+    pub fn find_rejected_route(&self, payment_data: &SendPaymentData) -> Option<RejectedRoute> {
+        // The trampoline routes are bounded by the fee budget in another way.
+        if payment_data.trampoline_node.is_some() {
+            return None;
+        }
+        let amount = payment_data.amount;
+        let route = self
+            .find_route(
+                self.get_source_pubkey(),
+                payment_data.target_pubkey,
+                amount,
+                None,
+                payment_data.udt_type_script.clone(),
+                payment_data.allow_self_payment,
+                payment_data.outgoing_channel.as_ref(),
+                payment_data.first_hop_limits.as_ref(),
+            )
+            .ok()?;
+        let onion_infos = self.build_onion_infos(
+            &route,
+            amount,
+            payment_data.payment_hash,
+            HashAlgorithm::default(),
+            None,
+            None,
+        );
+        let fee = onion_infos[0].amount - amount;
+        if let Some((fee_cap, cap)) = payment_data.fee_cap() {
+            if fee > fee_cap {
+                let reason = match cap {
+                    PaymentFeeCap::MaxFeeAmount => RouteRejectReason::MaxFeeAmount,
+                    PaymentFeeCap::MaxFeeRate => RouteRejectReason::MaxFeeRate,
+                };
+                return Some(RejectedRoute { fee, reason });
+            }
+        }
+        check_route_fee_percent(fee, amount, payment_data.max_fee_percent)
+            .err()
+            .map(|_| RejectedRoute {
+                fee,
+                reason: RouteRejectReason::MaxFeePercent,
+            })
     }
 
-    // the algorithm works from target-to-source to find the shortest path
+    #[allow(clippy::too_many_arguments)]
     pub fn find_route(
         &self,
         source: Pubkey,
@@ -591,6 +1200,8 @@ where
         max_fee_amount: Option<u128>,
         udt_type_script: Option<Script>,
         allow_self: bool,
+        outgoing_channel: Option<&OutPoint>,
+        first_hop_limits: Option<&HashMap<OutPoint, u128>>,
     ) -> Result<Vec<PathEdge>, GraphError> {
         let started_time = std::time::Instant::now();
         let nodes_len = self.nodes.len();
@@ -624,6 +1235,17 @@ where
                 &target
             )));
         };
+        if let Some(outgoing_channel) = outgoing_channel {
+            match self.get_channel(outgoing_channel) {
+                Some(channel) if channel.node1() == source || channel.node2() == source => {}
+                _ => {
+                    return Err(GraphError::PathFind(format!(
+                        "outgoing channel not found: {:?}",
+                        outgoing_channel
+                    )));
+                }
+            }
+        }
 
         // initialize the target node
         nodes_heap.push(NodeHeapElement {
@@ -644,6 +1266,20 @@ where
                 if from == target && !route_to_self {
                     continue;
                 }
+                if from == source
+                    && outgoing_channel
+                        .is_some_and(|outpoint| outpoint != &channel_info.out_point())
+                {
+                    continue;
+                }
+                // a circular route should not come back through the outgoing channel
+                if route_to_self
+                    && cur_hop.node_id == target
+                    && outgoing_channel
+                        .is_some_and(|outpoint| outpoint == &channel_info.out_point())
+                {
+                    continue;
+                }
                 // if charge inbound fees for exit hop
                 if udt_type_script != channel_info.announcement_msg.udt_type_script {
                     continue;
@@ -681,6 +1317,20 @@ where
                     );
                     continue;
                 }
+                if from == source
+                    && first_hop_limits.is_some_and(|limits| {
+                        limits
+                            .get(&channel_info.out_point())
+                            .map_or(true, |limit| amount_to_send > *limit)
+                    })
+                {
+                    debug!(
+                        "amount_to_send: {:?} exceeds the first hop limit of channel {:?}",
+                        amount_to_send,
+                        channel_info.out_point()
+                    );
+                    continue;
+                }
                 if amount_to_send < channel_update.htlc_minimum_value {
                     debug!(
                         "amount_to_send is less than htlc_minimum_value: {:?} min_value: {:?}",
@@ -757,7 +1407,10 @@ where
             started_time.elapsed()
         );
         if result.is_empty() || current != target {
-            return Err(GraphError::PathFind("no path found".to_string()));
+            return Err(GraphError::PathFind(match outgoing_channel {
+                Some(outpoint) => format!("no path found through outgoing channel: {:?}", outpoint),
+                None => "no path found".to_string(),
+            }));
         }
         Ok(result)
     }
@@ -793,10 +1446,28 @@ pub trait NetworkGraphStateStore {
         after: Option<JsonBytes>,
         outpoint: Option<OutPoint>,
     ) -> (Vec<ChannelInfo>, JsonBytes);
-    fn insert_channel(&self, channel: ChannelInfo);
-    fn insert_node(&self, node: NodeInfo);
+    /// Saves the channel, returns false and leaves the store untouched if the given
+    /// channel is outdated by the stored one, see `ChannelInfo::is_outdated_by`.
+    fn insert_channel(&self, channel: ChannelInfo) -> bool;
+    /// Saves the node, returns false and leaves the store untouched if the given
+    /// node is outdated by the stored one, see `NodeInfo::is_outdated_by`.
+    fn insert_node(&self, node: NodeInfo) -> bool;
+    fn get_graph_snapshot(&self) -> Option<GraphSnapshot>;
+    /// Returns the channels and nodes changed since the logged change at index `from`.
+    fn get_graph_changes(&self, from: u64) -> GraphChanges;
+    /// Saves the graph snapshot and removes the logged changes covered by it.
+    fn insert_graph_snapshot(&self, snapshot: GraphSnapshot);
     fn get_payment_session(&self, payment_hash: Hash256) -> Option<PaymentSession>;
     fn insert_payment_session(&self, session: PaymentSession);
+    /// Removes the finished payment sessions last updated before `before` (in milliseconds),
+    /// the `keep_recent` most recently created sessions are always kept. Returns the number
+    /// of removed sessions.
+    fn prune_payment_sessions(&self, before: u128, keep_recent: usize) -> usize;
+    /// Returns at most `limit` failed payment sessions, the most recently created ones first.
+    fn get_failed_payment_sessions(&self, limit: usize) -> Vec<PaymentSession>;
+    /// Removes the failed payment sessions created before `before` (in milliseconds),
+    /// returns the number of removed sessions.
+    fn clear_failed_payment_sessions(&self, before: u128) -> usize;
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -809,6 +1480,57 @@ pub enum PaymentSessionStatus {
     Success,
     // related HTLC is failed
     Failed,
+    // the probe HTLC reached the target and is failed by it as expected, the route is viable
+    ProbeSucceeded,
+}
+
+impl PaymentSessionStatus {
+    /// Whether the payment session is finished and will never be updated again.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            PaymentSessionStatus::Success
+                | PaymentSessionStatus::Failed
+                | PaymentSessionStatus::ProbeSucceeded
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PaymentFailureReason {
+    // the payment is not completed within the timeout, no more attempts are made
+    Timeout,
+    // no more route can be found, or the try limit of the payment is reached
+    RoutesExhausted,
+    // the payment is rejected with an error which can't be recovered by retrying
+    Rejected,
+}
+
+// The fee cap of a payment which is more restrictive, and thus bounds the route.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PaymentFeeCap {
+    // the absolute `max_fee_amount`
+    MaxFeeAmount,
+    // the proportional `max_fee_rate_ppm` of the amount
+    MaxFeeRate,
+}
+
+// The constraint of a payment which rejects the best route found for it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RouteRejectReason {
+    // the fee of the route exceeds `max_fee_amount`
+    MaxFeeAmount,
+    // the fee of the route exceeds `max_fee_rate_ppm` of the amount
+    MaxFeeRate,
+    // the fee of the route exceeds `max_fee_percent` of the amount
+    MaxFeePercent,
+}
+
+// The best route of a payment which is rejected by the constraints of the payment.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RejectedRoute {
+    pub fee: u128,
+    pub reason: RouteRejectReason,
 }
 
 #[serde_as]
@@ -817,6 +1539,7 @@ pub struct PaymentSession {
     pub request: SendPaymentData,
     pub retried_times: u32,
     pub last_error: Option<String>,
+    pub failure_reason: Option<PaymentFailureReason>,
     pub try_limit: u32,
     pub status: PaymentSessionStatus,
     pub created_at: u128,
@@ -825,6 +1548,16 @@ pub struct PaymentSession {
     #[serde_as(as = "Option<EntityHex>")]
     pub first_hop_channel_outpoint: Option<OutPoint>,
     pub first_hop_tlc_id: Option<u64>,
+    // The fee of the latest route tried by the payment.
+    #[serde(default)]
+    pub fee: Option<u128>,
+    // The best route rejected by the fee constraints when no route can be built for the payment.
+    #[serde(default)]
+    pub rejected_route: Option<RejectedRoute>,
+    // The payment timed out while its attempt is still in flight, it's failed once the tlc of the
+    // attempt is removed.
+    #[serde(default)]
+    pub deadline_exceeded: bool,
 }
 
 impl PaymentSession {
@@ -834,12 +1567,16 @@ impl PaymentSession {
             request,
             retried_times: 0,
             last_error: None,
+            failure_reason: None,
             try_limit,
             status: PaymentSessionStatus::Created,
             created_at: now,
             last_updated_at: now,
             first_hop_channel_outpoint: None,
             first_hop_tlc_id: None,
+            fee: None,
+            rejected_route: None,
+            deadline_exceeded: false,
         }
     }
 
@@ -860,26 +1597,75 @@ impl PaymentSession {
     pub fn set_success_status(&mut self) {
         self.set_status(PaymentSessionStatus::Success);
         self.last_error = None;
+        self.failure_reason = None;
+    }
+
+    pub fn set_probe_succeeded_status(&mut self) {
+        self.set_status(PaymentSessionStatus::ProbeSucceeded);
+        self.last_error = None;
+        self.failure_reason = None;
     }
 
-    pub fn set_failed_status(&mut self, error: &str) {
+    pub fn set_failed_status(&mut self, error: &str, reason: PaymentFailureReason) {
         self.set_status(PaymentSessionStatus::Failed);
         self.last_error = Some(error.to_string());
+        self.failure_reason = Some(reason);
     }
 
     pub fn can_retry(&self) -> bool {
         self.retried_times < self.try_limit
     }
+
+    /// Whether the tlc of the latest attempt is not removed yet. Such a session must not be
+    /// retried, cleared or pruned, otherwise the same payment hash may be paid twice.
+    pub fn has_unresolved_attempt(&self) -> bool {
+        self.status == PaymentSessionStatus::Inflight
+    }
+
+    /// The time elapsed since the payment is sent.
+    pub fn elapsed(&self) -> Duration {
+        let now = std::time::UNIX_EPOCH.elapsed().unwrap().as_millis();
+        Duration::from_millis(now.saturating_sub(self.created_at) as u64)
+    }
+
+    /// The time left before the payment times out, None if the payment has no timeout.
+    pub fn time_to_deadline(&self) -> Option<Duration> {
+        self.request
+            .timeout
+            .map(|timeout| Duration::from_secs(timeout).saturating_sub(self.elapsed()))
+    }
+
+    // The timeout bounds the total time of the payment, including all the retries.
+    pub fn is_timed_out(&self) -> bool {
+        match self.request.timeout {
+            Some(timeout) => {
+                let now = std::time::UNIX_EPOCH.elapsed().unwrap().as_millis();
+                now >= self.created_at + timeout as u128 * 1000
+            }
+            None => false,
+        }
+    }
 }
 
 impl From<PaymentSession> for SendPaymentResponse {
     fn from(session: PaymentSession) -> Self {
+        let fee_ratio_millionths = session.fee.and_then(|fee| {
+            (fee * 1_000_000)
+                .checked_div(session.request.amount)
+                .map(|ratio| ratio as u64)
+        });
         Self {
             payment_hash: session.request.payment_hash,
             status: session.status,
             failed_error: session.last_error,
+            failure_reason: session.failure_reason,
+            deadline_exceeded: session.deadline_exceeded,
             created_at: session.created_at,
             last_updated_at: session.last_updated_at,
+            fee: session.fee,
+            fee_ratio_millionths,
+            fee_cap: session.request.fee_cap().map(|(_, fee_cap)| fee_cap),
+            rejected_route: session.rejected_route,
         }
     }
 }
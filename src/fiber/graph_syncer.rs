@@ -14,7 +14,7 @@ use super::{
 
 // We assume all the channels with funding trsaction block number
 // < latest height - ASSUME_MAX_CHANNEL_HEIGHT_GAP are already synced.
-const ASSUME_MAX_CHANNEL_HEIGHT_GAP: u64 = 1000;
+pub(crate) const ASSUME_MAX_CHANNEL_HEIGHT_GAP: u64 = 1000;
 
 // We assume all the messages with timestamp <
 // latest timestamp - ASSUME_MAX_MESSAGE_TIMESTAMP_GAP are already synced.
@@ -1,6 +1,6 @@
 use ckb_hash::blake2b_256;
 use ckb_jsonrpc_types::{BlockNumber, Status, TxStatus};
-use ckb_types::core::{EpochNumberWithFraction, TransactionView};
+use ckb_types::core::{EpochNumberWithFraction, FeeRate, TransactionView};
 use ckb_types::packed::{self, Byte32, CellOutput, OutPoint, Script, Transaction};
 use ckb_types::prelude::{IntoTransactionView, Pack, Unpack};
 use musig2::CompactSignature;
@@ -15,8 +15,9 @@ use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use std::borrow::Cow;
 use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::RandomState;
+use std::net::IpAddr;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
@@ -42,45 +43,58 @@ use tentacle::{
 };
 use tokio::sync::{mpsc, oneshot, RwLock};
 use tokio_util::task::TaskTracker;
-use tracing::{debug, error, info, trace, warn};
+use tracing::{debug, error, info, info_span, trace, warn, Instrument};
 
 use super::channel::{
     AcceptChannelParameter, ChannelActor, ChannelActorMessage, ChannelActorStateStore,
-    ChannelCommand, ChannelCommandWithId, ChannelEvent, ChannelInitializationParameter,
-    ChannelState, ChannelSubscribers, OpenChannelParameter, ProcessingChannelError,
-    ProcessingChannelResult, PublicChannelInfo, ShuttingDownFlags, DEFAULT_COMMITMENT_FEE_RATE,
+    ChannelCommand, ChannelCommandWithId, ChannelEvent, ChannelHaltReason,
+    ChannelInitializationParameter, ChannelOpenStep, ChannelState, ChannelSubscribers,
+    DustTlcLimit, OpenChannelParameter, ProcessingChannelError, ProcessingChannelResult,
+    PublicChannelInfo, TlcFailDelay, TlcFulfillDelay, DEFAULT_COMMITMENT_FEE_RATE,
     DEFAULT_FEE_RATE,
 };
-use super::config::AnnouncedNodeName;
-use super::fee::{calculate_commitment_tx_fee, default_minimal_ckb_amount};
+use super::config::{AnnouncedNodeName, ChannelAcceptancePolicy, GraphSyncPeerStrategy};
+use super::fee::{
+    calculate_commitment_tx_fee, default_minimal_ckb_amount, SuggestedFeeRates,
+    HIGH_FEE_RATE_TARGET_BLOCKS, LOW_FEE_RATE_TARGET_BLOCKS, MEDIUM_FEE_RATE_TARGET_BLOCKS,
+};
 use super::graph::{NetworkGraph, NetworkGraphStateStore};
-use super::graph_syncer::{GraphSyncer, GraphSyncerMessage};
+use super::graph_syncer::{GraphSyncer, GraphSyncerMessage, ASSUME_MAX_CHANNEL_HEIGHT_GAP};
+use super::hash_algorithm::HashAlgorithm;
 use super::key::blake2b_hash_with_salt;
 use super::types::{
-    ChannelAnnouncement, ChannelAnnouncementQuery, ChannelUpdate, ChannelUpdateQuery,
-    EcdsaSignature, FiberBroadcastMessage, FiberBroadcastMessageQuery, FiberMessage,
-    FiberQueryInformation, GetBroadcastMessages, GetBroadcastMessagesResult, Hash256,
+    node_features, ChannelAnnouncement, ChannelAnnouncementQuery, ChannelUpdate,
+    ChannelUpdateQuery, EcdsaSignature, FiberBroadcastMessage, FiberBroadcastMessageQuery,
+    FiberMessage, FiberQueryInformation, GetBroadcastMessages, GetBroadcastMessagesResult, Hash256,
     NodeAnnouncement, NodeAnnouncementQuery, OpenChannel, Privkey, Pubkey,
     QueryBroadcastMessagesWithinTimeRange, QueryBroadcastMessagesWithinTimeRangeResult,
     QueryChannelsWithinBlockRange, QueryChannelsWithinBlockRangeResult, RemoveTlc, RemoveTlcReason,
-    TlcErr, TlcErrData, TlcErrPacket, TlcErrorCode,
+    TlcErr, TlcErrData, TlcErrPacket, TlcErrorCode, NODE_FEATURE_KEYSEND, SUPPORTED_ONION_VERSIONS,
 };
-use super::{FiberConfig, ASSUME_NETWORK_ACTOR_ALIVE};
+use super::FiberConfig;
 
 use crate::ckb::config::UdtCfgInfos;
 use crate::ckb::contracts::{check_udt_script, get_udt_whitelist, is_udt_type_auto_accept};
-use crate::ckb::{CkbChainMessage, FundingRequest, FundingTx, TraceTxRequest, TraceTxResponse};
+use crate::ckb::{
+    verify_external_funding_tx, ChainTip, CkbChainMessage, FundingRequest, FundingTx,
+    TraceTxRequest, TraceTxResponse,
+};
 use crate::fiber::channel::{
-    AddTlcCommand, AddTlcResponse, TxCollaborationCommand, TxUpdateCommand,
+    AddTlcCommand, AddTlcResponse, ReestablishOutcome, RemoveTlcCommand, TLCId,
+    TxCollaborationCommand, TxUpdateCommand,
 };
-use crate::fiber::graph::{ChannelInfo, PaymentSession, PaymentSessionStatus};
-use crate::fiber::serde_utils::EntityHex;
+use crate::fiber::graph::{
+    ChannelInfo, PaymentFailureReason, PaymentFeeCap, PaymentSession, PaymentSessionStatus,
+    RejectedRoute,
+};
+use crate::fiber::serde_utils::{EntityHex, U64Hex};
 use crate::fiber::types::{
-    secp256k1_instance, FiberChannelMessage, PaymentOnionPacket, PeeledPaymentOnionPacket,
-    TxSignatures,
+    get_onion_payload_size, secp256k1_instance, FiberChannelMessage, HopData, PaymentHopData,
+    PaymentOnionPacket, PeeledPaymentOnionPacket, TxSignatures,
 };
 use crate::fiber::KeyPair;
-use crate::invoice::{CkbInvoice, InvoiceStore};
+use crate::invoice::{CkbInvoice, FeesPaidBy, InvoiceStore};
+use crate::metrics::metrics;
 use crate::{unwrap_or_return, Error};
 
 pub const FIBER_PROTOCOL_ID: ProtocolId = ProtocolId::new(42);
@@ -107,6 +121,34 @@ const NUM_PEER_CONNECTIONS: usize = 40;
 // The duration for which we will try to maintain the number of peers in connection.
 const MAINTAINING_CONNECTIONS_INTERVAL: Duration = Duration::from_secs(3600);
 
+// The interval to check whether the funding transactions of ready channels are still
+// in the canonical chain.
+const CHECKING_FUNDING_TRANSACTIONS_INTERVAL: Duration = Duration::from_secs(60);
+
+// The interval to check whether the channels with a rebalance policy need to be rebalanced.
+const REBALANCING_CHANNELS_INTERVAL: Duration = Duration::from_secs(600);
+
+// The interval to refresh the tip block number of the chain.
+const UPDATING_CHAIN_TIP_INTERVAL: Duration = Duration::from_secs(30);
+
+// The interval to check whether the forwarded tlcs are resolved within the resolution timeout.
+const CHECKING_TLC_RESOLUTION_INTERVAL: Duration = Duration::from_secs(60);
+
+// The interval to prune the finished payment sessions older than the retention age.
+const PRUNING_PAYMENT_SESSIONS_INTERVAL: Duration = Duration::from_secs(3600);
+
+// The interval to prune the archives of the channels closed before the retention age.
+const PRUNING_CLOSED_CHANNELS_INTERVAL: Duration = Duration::from_secs(3600);
+
+// TODO: make number of confirmation to transaction configurable.
+const NUM_CONFIRMATIONS: u64 = 4;
+
+// The minimal interval to rebroadcast the announcement of the same channel.
+const REBROADCASTING_CHANNEL_ANNOUNCEMENT_INTERVAL: Duration = Duration::from_secs(300);
+
+// The amount of a probe payment if it's not specified, probing the route only needs a tiny amount.
+const DEFAULT_PROBE_PAYMENT_AMOUNT: u128 = 1;
+
 pub(crate) fn get_chain_hash() -> Hash256 {
     Default::default()
 }
@@ -129,6 +171,132 @@ pub struct SendPaymentResponse {
     pub created_at: u128,
     pub last_updated_at: u128,
     pub failed_error: Option<String>,
+    pub failure_reason: Option<PaymentFailureReason>,
+    // The payment timed out while its attempt is still in flight.
+    pub deadline_exceeded: bool,
+    // The fee of the latest route tried by the payment.
+    pub fee: Option<u128>,
+    // The fee to amount ratio of the latest route tried by the payment, in millionths.
+    pub fee_ratio_millionths: Option<u64>,
+    // The fee cap which bounds the route of the payment, None if the payment has no fee cap.
+    pub fee_cap: Option<PaymentFeeCap>,
+    // The best route rejected by the fee constraints when no route can be built for the payment.
+    pub rejected_route: Option<RejectedRoute>,
+}
+
+#[derive(Debug)]
+pub struct GetRebalanceRouteCommand {
+    // The channel to send the balance out, i.e. the first hop of the route.
+    pub source_channel: Hash256,
+    // The channel to receive the balance back, i.e. the last hop of the route.
+    pub destination_channel: Hash256,
+    // The amount to be received through the destination channel.
+    pub amount: u128,
+    // The maximal fee which the route may charge, None means no limit.
+    pub max_fee_amount: Option<u128>,
+}
+
+/// The circular route to move the balance from a channel to another one.
+#[derive(Clone, Debug)]
+pub struct RebalanceRoute {
+    // The amount to be received through the destination channel.
+    pub amount: u128,
+    // The fee charged by the nodes along the route.
+    pub fee: u128,
+    pub hops: Vec<RebalanceRouteHop>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RebalanceRouteHop {
+    // The node which receives the tlc of this hop, the last one is ourselves.
+    pub target: Pubkey,
+    pub channel_outpoint: OutPoint,
+    // The amount of the tlc sent through the channel.
+    pub amount: u128,
+}
+
+/// The progress of opening a channel.
+#[derive(Clone, Debug)]
+pub struct ChannelOpenStatus {
+    // The final channel id if the channel is accepted, otherwise the temporary channel id.
+    pub channel_id: Hash256,
+    pub step: ChannelOpenStep,
+    // The state of the channel actor, None if the channel is not accepted by the peer yet.
+    pub state: Option<ChannelState>,
+    // The latest error encountered while opening the channel.
+    pub error: Option<String>,
+}
+
+/// The funding cell expected by a channel, so that it can be funded from an external wallet.
+#[derive(Clone, Debug)]
+pub struct ChannelFundingAddress {
+    // The final channel id if the channel is accepted, otherwise the temporary channel id.
+    pub channel_id: Hash256,
+    // The lock script of the funding cell, derived from the funding pubkeys of both parties.
+    pub funding_script: Script,
+    pub funding_udt_type_script: Option<Script>,
+    // The capacity required by the funding cell, including the reserved ckb amount of both parties.
+    pub ckb_amount: u64,
+    // The udt amount required by the funding cell, None for the ckb channels.
+    pub udt_amount: Option<u128>,
+}
+
+/// The thresholds to decide whether a channel needs the attention of the operator,
+/// all the durations are in milliseconds.
+#[derive(Clone, Debug)]
+pub struct ChannelAttentionThresholds {
+    // The pending tlcs which expire within this number of blocks are reported.
+    pub tlc_expiry_blocks: u64,
+    // The channels which stay in a transitional state longer than this duration are reported.
+    pub stuck_state: u64,
+}
+
+/// Why a channel needs the attention of the operator, all the timestamps are in milliseconds.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ChannelAttentionReason {
+    /// A pending tlc is near its expiry at block `expires_at_block`, the channel may be force
+    /// closed to settle it on chain.
+    TlcNearExpiry {
+        tlc_id: TLCId,
+        #[serde_as(as = "U64Hex")]
+        expires_at_block: u64,
+    },
+    /// The channel is stuck in a state other than `CHANNEL_READY` since `since`.
+    StuckState {
+        #[serde_as(as = "U64Hex")]
+        since: u64,
+    },
+    /// The peer of the channel is not connected.
+    PeerOffline {
+        #[serde_as(as = "Option<U64Hex>")]
+        last_disconnected_at: Option<u64>,
+    },
+    /// The channel is halted, e.g. our state is stale and the peer is expected to close it.
+    Halted { reason: ChannelHaltReason },
+}
+
+/// A channel which needs the attention of the operator.
+#[derive(Clone, Debug)]
+pub struct ChannelNeedingAction {
+    pub peer_id: PeerId,
+    pub channel_id: Hash256,
+    pub state: ChannelState,
+    pub reason: ChannelAttentionReason,
+}
+
+/// An inbound tlc rejected by this node, kept for diagnosing why the payments of a peer fail.
+#[derive(Clone, Debug)]
+pub struct RejectedTlc {
+    pub peer_id: PeerId,
+    pub channel_id: Hash256,
+    pub tlc_id: u64,
+    pub payment_hash: Hash256,
+    pub amount: u128,
+    pub error_code: TlcErrorCode,
+    pub reason: String,
+    pub timestamp: u64,
 }
 
 /// What kind of local information should be broadcasted to the network.
@@ -152,11 +320,46 @@ pub struct NodeInfoResponse {
     pub tlc_fee_proportional_millionths: u128,
     pub channel_count: u32,
     pub pending_channel_count: u32,
+    // The total local balance of the CKB channels whose funding transactions are not fully confirmed yet.
+    pub funding_at_risk_amount: u128,
     pub peers_count: u32,
     pub network_sync_status: String,
     pub udt_cfg_infos: UdtCfgInfos,
 }
 
+#[derive(Clone, Debug)]
+pub struct HealthResponse {
+    // Whether the node is ready to send payments, see `NetworkActorState::is_ready_for_payments`.
+    pub ready_for_payments: bool,
+    pub peers_count: u32,
+    // The percentage of the network graph synced, same as the progress of the graph sync status.
+    pub graph_sync_progress: u64,
+    pub inbound_peers_count: u32,
+    pub outbound_peers_count: u32,
+    // The number of the inbound connections rejected by the connection limits since the node started.
+    pub rejected_connections_count: u64,
+}
+
+// The limits of the inbound connections to change, None means unchanged.
+#[derive(Clone, Debug, Default)]
+pub struct SetConnectionLimitsCommand {
+    pub max_peers: Option<u64>,
+    pub connection_rate_limit_max_connections: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GraphSyncStatusResponse {
+    pub status: String,
+    pub strategy: GraphSyncPeerStrategy,
+    // The percentage of the channels within the syncing block range received from peers.
+    pub progress: u64,
+    // The peers which have been queried, in the order of querying.
+    pub queried_peers: Vec<PeerId>,
+    pub syncing_peers: Vec<PeerId>,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
 /// The struct here is used both internally and as an API to the outside world.
 /// If we want to send a reply to the caller, we need to wrap the message with
 /// a RpcReplyPort. Since outsider users have no knowledge of RpcReplyPort, we
@@ -173,6 +376,22 @@ pub enum NetworkActorCommand {
     SavePeerAddress(Multiaddr),
     // We need to maintain a certain number of peers connections to keep the network running.
     MaintainConnections(usize),
+    // Check whether the funding transactions of ready channels are reorged out of the chain.
+    CheckFundingTransactions,
+    // Send circular payments out of the channels which drift past their rebalance policy.
+    RebalanceChannels,
+    // Refresh the tip block number of the chain shared with the channel actors.
+    UpdateChainTip,
+    // Fail back the forwarded tlcs which are not resolved by the downstream within the resolution timeout.
+    FailUnresolvedForwardedTlcs,
+    // Record the timeout of the payment whose attempt is still in flight at its deadline.
+    CheckPaymentDeadline(Hash256),
+    // Remove the finished payment sessions which are older than the retention age.
+    PrunePaymentSessions,
+    // Remove the archives of the channels closed before the retention age.
+    PruneClosedChannels,
+    // Persist the network graph snapshot if the graph is changed since the last snapshot.
+    SaveGraphSnapshot,
     // For internal use and debugging only. Most of the messages requires some
     // changes to local state. Even if we can send a message to a peer, some
     // part of the local state is not changed.
@@ -189,6 +408,8 @@ pub enum NetworkActorCommand {
     ),
     // Send a command to a channel.
     ControlFiberChannel(ChannelCommandWithId),
+    // Fail back a received tlc of the channel which is forwarded, after checking the downstream tlc.
+    FailForwardedTlc(Hash256, u64, RpcReplyPort<Result<(), String>>),
     // The first parameter is the peeled onion in binary via `PeeledOnionPacket::serialize`. `PeeledOnionPacket::current`
     // is for the current node.
     SendPaymentOnionPacket(
@@ -200,6 +421,16 @@ pub enum NetworkActorCommand {
         Hash256, // payment_hash
         RpcReplyPort<Result<PeeledPaymentOnionPacket, String>>,
     ),
+    // Build the onion packet to forward a trampoline payment from this node to its target,
+    // the hash algorithm is the one of the received tlc, and the route must expire within
+    // the given locktime. The reply is the serialized `PeeledPaymentOnionPacket` whose
+    // current hop is the instruction for this node.
+    BuildTrampolineOnionPacket(
+        SendPaymentData,
+        HashAlgorithm,
+        u64,
+        RpcReplyPort<Result<Vec<u8>, String>>,
+    ),
     UpdateChannelFunding(Hash256, Transaction, FundingRequest),
     SignTx(PeerId, Hash256, Transaction, Option<Vec<Vec<u8>>>),
     // A ChannelAnnouncement is ready to broadcast, we need to
@@ -212,6 +443,12 @@ pub enum NetworkActorCommand {
     // The channel counterparty should definitely be part of the
     // nodes that are going to receive this message.
     ProccessChannelUpdate(PeerId, ChannelUpdate),
+    // Gossip the announcement and the latest update of a local public channel again,
+    // it's rate limited by REBROADCASTING_CHANNEL_ANNOUNCEMENT_INTERVAL for each channel.
+    RebroadcastChannelAnnouncement(Hash256, RpcReplyPort<Result<(), String>>),
+    // The re-signed messages of a local public channel are ready, we need to update
+    // our network graph and send them to all the connected peers.
+    RebroadcastChannelMessages(ChannelAnnouncement, ChannelUpdate),
     // Broadcast node/channel information to the network.
     BroadcastMessage(FiberBroadcastMessage),
     // Broadcast local information to the network.
@@ -236,6 +473,28 @@ pub enum NetworkActorCommand {
     StopSyncing,
     MarkSyncingDone,
     NodeInfo((), RpcReplyPort<Result<NodeInfoResponse, String>>),
+    GraphSyncStatus((), RpcReplyPort<Result<GraphSyncStatusResponse, String>>),
+    Health((), RpcReplyPort<Result<HealthResponse, String>>),
+    // Change the limits of the inbound connections without restarting the node.
+    SetConnectionLimits(SetConnectionLimitsCommand, RpcReplyPort<Result<(), String>>),
+    // Suggest the fee rates for the on-chain transactions, e.g. opening or shutting down a channel.
+    SuggestFeeRates((), RpcReplyPort<Result<SuggestedFeeRates, String>>),
+    // List the recently rejected inbound tlcs, the latest ones first.
+    ListRejectedTlcs((), RpcReplyPort<Result<Vec<RejectedTlc>, String>>),
+    // Get the progress of opening a channel by its temporary or final channel id.
+    GetChannelOpenStatus(Hash256, RpcReplyPort<Result<ChannelOpenStatus, String>>),
+    // Get the funding cell expected by a channel by its temporary or final channel id.
+    GetChannelFundingAddress(Hash256, RpcReplyPort<Result<ChannelFundingAddress, String>>),
+    // Find the circular route to move the balance between two of our channels without sending a payment.
+    GetRebalanceRoute(
+        GetRebalanceRouteCommand,
+        RpcReplyPort<Result<RebalanceRoute, String>>,
+    ),
+    // List the active channels needing the attention of the operator, one entry for each reason.
+    ListChannelsNeedingAction(
+        ChannelAttentionThresholds,
+        RpcReplyPort<Result<Vec<ChannelNeedingAction>, String>>,
+    ),
 }
 
 pub async fn sign_network_message(
@@ -265,6 +524,17 @@ pub struct OpenChannelCommand {
     pub tlc_fee_proportional_millionths: Option<u128>,
     pub max_tlc_value_in_flight: Option<u128>,
     pub max_tlc_number_in_flight: Option<u64>,
+    pub max_inbound_tlc_value: Option<u128>,
+    pub max_outbound_tlc_value: Option<u128>,
+    // A signed transaction from an external wallet which transfers the funds of this channel to
+    // the node's funding lock script.
+    pub external_funding_tx: Option<Transaction>,
+    // The timeout in seconds to abort the open if the funding transaction is not signed yet,
+    // default is the node's open_channel_timeout_seconds, 0 means never aborting.
+    pub open_timeout_seconds: Option<u64>,
+    // Whether to use the channel before the funding transaction is confirmed, only takes effect
+    // if the peer also agrees to it while accepting the channel, default is false.
+    pub zero_conf: Option<bool>,
 }
 
 #[serde_as]
@@ -294,6 +564,22 @@ pub struct SendPaymentCommand {
     pub udt_type_script: Option<Script>,
     // allow self payment, default is false
     pub allow_self_payment: bool,
+    // the channel to be used as the first hop of the payment
+    pub outgoing_channel: Option<Hash256>,
+    // probe payment to discover whether the route to the target is viable, default is false
+    pub probe: Option<bool>,
+    // the trampoline node to complete the route to the target, for the nodes without the full graph
+    pub trampoline_node: Option<Pubkey>,
+    // the outbound balance to be left in the first channel of the payment
+    pub keep_reserve: Option<u128>,
+    // the maximal fee of the route in percentage of the amount, 0 means no limit,
+    // default is the node's payment_max_fee_percent
+    pub max_fee_percent: Option<u64>,
+    // the maximal fee of the route in parts per million of the amount, the more restrictive
+    // one of this and max_fee_amount is applied
+    pub max_fee_rate_ppm: Option<u64>,
+    // who pays the routing fees, default is the one specified by the invoice, or the sender
+    pub fees_paid_by: Option<FeesPaidBy>,
 }
 
 #[serde_as]
@@ -312,6 +598,35 @@ pub struct SendPaymentData {
     pub udt_type_script: Option<Script>,
     pub preimage: Option<Hash256>,
     pub allow_self_payment: bool,
+    // The funding outpoint of the channel to be used as the first hop, resolved from
+    // the outgoing channel id of the command.
+    #[serde_as(as = "Option<EntityHex>")]
+    #[serde(default)]
+    pub outgoing_channel: Option<OutPoint>,
+    // A probe payment carries a random payment hash which no one knows the preimage of,
+    // so it's never fulfilled and the target fails it with `IncorrectOrUnknownPaymentDetails`.
+    #[serde(default)]
+    pub probe: bool,
+    // The route ends at the trampoline node if it's set, and the trampoline node completes
+    // the route to the target with the fee budget left.
+    #[serde(default)]
+    pub trampoline_node: Option<Pubkey>,
+    // The outbound balance to be left in the first channel of the payment.
+    #[serde(default)]
+    pub keep_reserve: Option<u128>,
+    // The route is rejected if its fee exceeds this percentage of the amount, 0 means no limit.
+    #[serde(default)]
+    pub max_fee_percent: Option<u64>,
+    // The route is rejected if its fee exceeds this rate of the amount in parts per million.
+    #[serde(default)]
+    pub max_fee_rate_ppm: Option<u64>,
+    // The fees are deducted from the amount if they are paid by the receiver.
+    #[serde(default)]
+    pub fees_paid_by: FeesPaidBy,
+    // The maximal amounts which can be sent through our channels while keeping the reserve,
+    // set by the network actor from the current channel balances, so it's not persisted.
+    #[serde(skip)]
+    pub first_hop_limits: Option<HashMap<OutPoint, u128>>,
 }
 
 impl SendPaymentData {
@@ -359,11 +674,60 @@ impl SendPaymentData {
             return Err("allow_self_payment is not enable, can not pay self".to_string());
         }
 
+        if let Some(trampoline_node) = command.trampoline_node {
+            if trampoline_node == target || trampoline_node == source {
+                return Err(
+                    "trampoline_node should be neither the target nor the source".to_string(),
+                );
+            }
+            if command.max_fee_amount.is_none() {
+                return Err("max_fee_amount is required for trampoline payment".to_string());
+            }
+        }
+
+        let probe = command.probe.unwrap_or(false);
+        if probe && (invoice.is_some() || command.payment_hash.is_some()) {
+            return Err("probe payment should not have invoice or payment_hash".to_string());
+        }
+
         let amount = validate_field(
-            command.amount,
+            command
+                .amount
+                .or(probe.then_some(DEFAULT_PROBE_PAYMENT_AMOUNT)),
             invoice.as_ref().and_then(|i| i.amount()),
             "amount",
         )?;
+        if let Some(invoice) = invoice.as_ref() {
+            if !invoice.is_amount_acceptable(amount) {
+                return Err(format!(
+                    "amount {} is out of the acceptable range of the invoice",
+                    amount
+                ));
+            }
+        }
+
+        let fees_paid_by = match invoice.as_ref() {
+            Some(invoice) => validate_field(
+                command.fees_paid_by,
+                Some(invoice.fees_paid_by().copied().unwrap_or_default()),
+                "fees_paid_by",
+            )?,
+            None => command.fees_paid_by.unwrap_or_default(),
+        };
+        // The receiver only absorbs the fees up to the limit of the invoice.
+        let max_fee_amount = match invoice.as_ref().and_then(|i| i.receiver_max_fee()) {
+            Some(receiver_max_fee) => Some(
+                command
+                    .max_fee_amount
+                    .map_or(receiver_max_fee, |fee| fee.min(receiver_max_fee)),
+            ),
+            None => command.max_fee_amount,
+        };
+        if fees_paid_by == FeesPaidBy::Receiver && command.trampoline_node.is_some() {
+            return Err(
+                "fees paid by the receiver is not supported for trampoline payment".to_string(),
+            );
+        }
 
         let udt_type_script = match validate_field(
             command.udt_type_script.clone(),
@@ -376,7 +740,16 @@ impl SendPaymentData {
         };
 
         let keysend = command.keysend.unwrap_or(false);
-        let (payment_hash, preimage) = if !keysend {
+        let (payment_hash, preimage) = if probe {
+            if keysend {
+                return Err("probe payment should not be keysend".to_string());
+            }
+            // a random payment hash without preimage, so the probe can't be fulfilled by anyone
+            let mut rng = rand::thread_rng();
+            let mut result = [0u8; 32];
+            rng.fill(&mut result[..]);
+            (result.into(), None)
+        } else if !keysend {
             (
                 validate_field(
                     command.payment_hash,
@@ -406,14 +779,38 @@ impl SendPaymentData {
             invoice: command.invoice,
             final_cltv_delta: command.final_cltv_delta,
             timeout: command.timeout,
-            max_fee_amount: command.max_fee_amount,
+            max_fee_amount,
             max_parts: command.max_parts,
             keysend,
             udt_type_script,
             preimage,
             allow_self_payment: command.allow_self_payment,
+            outgoing_channel: None,
+            probe,
+            trampoline_node: command.trampoline_node,
+            keep_reserve: command.keep_reserve,
+            max_fee_percent: command.max_fee_percent,
+            max_fee_rate_ppm: command.max_fee_rate_ppm,
+            fees_paid_by,
+            first_hop_limits: None,
         })
     }
+
+    // The fee cap applied when finding the route, i.e. the more restrictive one of
+    // `max_fee_amount` and `max_fee_rate_ppm`, along with which of the two it is.
+    pub fn fee_cap(&self) -> Option<(u128, PaymentFeeCap)> {
+        let rate_cap = self
+            .max_fee_rate_ppm
+            .map(|ppm| self.amount.saturating_mul(ppm as u128) / 1_000_000);
+        match (self.max_fee_amount, rate_cap) {
+            (Some(amount_cap), Some(rate_cap)) if rate_cap < amount_cap => {
+                Some((rate_cap, PaymentFeeCap::MaxFeeRate))
+            }
+            (Some(amount_cap), _) => Some((amount_cap, PaymentFeeCap::MaxFeeAmount)),
+            (None, Some(rate_cap)) => Some((rate_cap, PaymentFeeCap::MaxFeeRate)),
+            (None, None) => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -421,6 +818,11 @@ pub struct AcceptChannelCommand {
     pub temp_channel_id: Hash256,
     pub funding_amount: u128,
     pub shutdown_script: Option<Script>,
+    pub max_inbound_tlc_value: Option<u128>,
+    pub max_outbound_tlc_value: Option<u128>,
+    // Whether to use the channel before the funding transaction is confirmed if the peer asks
+    // for it, default is false.
+    pub zero_conf: Option<bool>,
 }
 
 #[derive(Debug)]
@@ -454,7 +856,12 @@ pub enum NetworkServiceEvent {
     // The channel is ready to use (with funding transaction confirmed
     // and both parties sent ChannelReady messages).
     ChannelReady(PeerId, Hash256, OutPoint),
+    // The funding transaction of a ready channel is no longer in the canonical chain,
+    // the channel is not usable until the funding transaction is confirmed again.
+    ChannelFundingReorged(PeerId, Hash256, OutPoint),
     ChannelClosed(PeerId, Hash256, Byte32),
+    // The reestablish handshake of a ready channel is completed after the peer reconnects.
+    ChannelReestablished(PeerId, Hash256, ReestablishOutcome),
     // We should sign a commitment transaction and send it to the other party.
     CommitmentSignaturePending(PeerId, Hash256, u64),
     // We have signed a commitment transaction and sent it to the other party.
@@ -498,6 +905,7 @@ pub enum NetworkActorEvent {
     /// The two Hash256 are respectively newly agreed channel id and temp channel id,
     /// The two u128 are respectively local and remote funding amount,
     /// and the script is the lock script of the agreed funding cell.
+    /// The last transaction is the external funding tx provided while opening the channel.
     ChannelAccepted(
         PeerId,
         Hash256,
@@ -509,9 +917,12 @@ pub enum NetworkActorEvent {
         u64,
         u64,
         u64,
+        Option<Transaction>,
     ),
     /// A channel is ready to use.
     ChannelReady(Hash256, PeerId, OutPoint),
+    /// The open of a channel is aborted before the funding transaction is signed.
+    ChannelOpenAborted(Hash256, PeerId),
     /// A channel is already closed.
     ClosingTransactionPending(Hash256, PeerId, TransactionView),
 
@@ -525,6 +936,12 @@ pub enum NetworkActorEvent {
     /// A funding transaction has been confirmed.
     FundingTransactionFailed(OutPoint),
 
+    /// The funding transaction of a ready channel is no longer in the canonical chain.
+    FundingTransactionReorged(Hash256, PeerId, OutPoint),
+
+    /// The funding transaction of a channel with a trusted peer reaches the full confirmations.
+    FundingTransactionFullyConfirmed(Hash256),
+
     /// A commitment transaction is signed by us and has sent to the other party.
     LocalCommitmentSigned(PeerId, Hash256, u64, TransactionView),
 
@@ -549,6 +966,9 @@ pub enum NetworkActorEvent {
     // A tlc remove message is received. (payment_hash, remove_tlc)
     TlcRemoveReceived(Hash256, RemoveTlc),
 
+    // An inbound tlc is rejected by us.
+    TlcRejected(RejectedTlc),
+
     /// Network service events to be sent to outside observers.
     /// These events may be both present at `NetworkActorEvent` and
     /// this branch of `NetworkActorEvent`. This is because some events
@@ -643,7 +1063,7 @@ where
             FiberMessage::ChannelInitialization(open_channel) => {
                 let temp_channel_id = open_channel.channel_id;
                 match state
-                    .on_open_channel_msg(peer_id, open_channel.clone())
+                    .on_open_channel_msg(peer_id.clone(), open_channel.clone())
                     .await
                 {
                     Ok(()) => {
@@ -665,8 +1085,14 @@ where
                                     state.auto_accept_channel_ckb_funding_amount as u128
                                 },
                                 shutdown_script: None,
+                                max_inbound_tlc_value: None,
+                                max_outbound_tlc_value: None,
+                                zero_conf: None,
                             };
-                            state.create_inbound_channel(accept_channel).await?;
+                            let remote_features = self.get_peer_features(state, &peer_id).await;
+                            state
+                                .create_inbound_channel(accept_channel, remote_features)
+                                .await?;
                         }
                     }
                     Err(err) => {
@@ -682,6 +1108,19 @@ where
                     error!("Failed to process broadcasted message: {:?}", e);
                 }
             }
+            // The channel is aborted by the peer before we accept it.
+            FiberMessage::ChannelNormalOperation(FiberChannelMessage::TxAbort(tx_abort))
+                if state
+                    .to_be_accepted_channels
+                    .get(&tx_abort.channel_id)
+                    .is_some_and(|(id, _)| id == &peer_id) =>
+            {
+                debug!(
+                    "Channel {:?} pending to be accepted is aborted by peer {:?}",
+                    &tx_abort.channel_id, &peer_id
+                );
+                state.to_be_accepted_channels.remove(&tx_abort.channel_id);
+            }
             FiberMessage::ChannelNormalOperation(m) => {
                 let channel_id = m.get_channel_id();
                 state
@@ -923,7 +1362,11 @@ where
         let network_graph = self.network_graph.read().await;
         let (channels, next_offset, is_finished) =
             network_graph.get_channels_within_block_range(start_block, end_block);
-        (channels.cloned().collect(), next_offset, is_finished)
+        (
+            channels.filter(|c| !c.private).cloned().collect(),
+            next_offset,
+            is_finished,
+        )
     }
 
     // TODO: set a upper limit for the number of message to send.
@@ -947,7 +1390,7 @@ where
                 ));
             }
         }
-        for channel_info in network_graph.channels() {
+        for channel_info in network_graph.channels().filter(|c| !c.private) {
             if let Some(t) = channel_info.channel_update_node1_to_node2_timestamp() {
                 if is_within_range(t) {
                     queries.push(FiberBroadcastMessageQuery::ChannelUpdate(
@@ -998,7 +1441,10 @@ where
                 channel_outpoint,
                 flags: _,
             }) => {
-                let channel_info = network_graph.get_channel(&channel_outpoint);
+                // Private channels are never gossiped to peers.
+                let channel_info = network_graph
+                    .get_channel(&channel_outpoint)
+                    .filter(|c| !c.private);
                 match channel_info {
                     Some(channel_info) => {
                         let channel_announcement = FiberBroadcastMessage::ChannelAnnouncement(
@@ -1016,7 +1462,10 @@ where
                 channel_outpoint,
                 flags,
             }) => {
-                let channel_info = network_graph.get_channel(&channel_outpoint);
+                // Private channels are never gossiped to peers.
+                let channel_info = network_graph
+                    .get_channel(&channel_outpoint)
+                    .filter(|c| !c.private);
                 let is_node1_to_node2 = flags & 1 == 0;
                 match channel_info {
                     Some(channel_info) => {
@@ -1070,7 +1519,17 @@ where
                 self.on_service_event(e).await;
             }
             NetworkActorEvent::PeerConnected(id, pubkey, session) => {
+                if let Err(reason) = state.check_connection_limits(&id, &session) {
+                    warn!(
+                        "Rejecting the connection from peer {:?} at {}: {}",
+                        &id, &session.address, reason
+                    );
+                    state.rejected_connections += 1;
+                    state.control.disconnect(session.id).await?;
+                    return Ok(());
+                }
                 state.on_peer_connected(&id, pubkey, &session).await;
+                self.maybe_sync_network_graph(state, vec![id.clone()]).await;
                 // Notify outside observers.
                 myself
                     .send_message(NetworkActorMessage::new_event(
@@ -1082,6 +1541,15 @@ where
                     .expect(ASSUME_NETWORK_MYSELF_ALIVE);
             }
             NetworkActorEvent::PeerDisconnected(id, session) => {
+                // The sessions rejected by the connection limits are never registered, and the
+                // peer may still be connected through another session.
+                if state.peer_session_map.get(&id) != Some(&session.id) {
+                    debug!(
+                        "Ignoring the disconnection of the unregistered session {:?} of peer {:?}",
+                        session.id, &id
+                    );
+                    return Ok(());
+                }
                 state.on_peer_disconnected(&id);
                 // Notify outside observers.
                 myself
@@ -1103,6 +1571,7 @@ where
                 local_reserved_ckb_amount,
                 remote_reserved_ckb_amount,
                 funding_fee_rate,
+                external_funding_tx,
             ) => {
                 assert_ne!(new, old, "new and old channel id must be different");
                 if let Some(session) = state.get_peer_session(&peer_id) {
@@ -1115,6 +1584,7 @@ where
                     if let Some(channel) = state.channels.remove(&old) {
                         debug!("Channel accepted: {:?} -> {:?}", old, new);
                         state.channels.insert(new, channel);
+                        state.temp_channel_ids.insert(old, new);
                         if let Some(set) = state.session_channels_map.get_mut(&session) {
                             set.remove(&old);
                             set.insert(new);
@@ -1136,6 +1606,7 @@ where
                                         remote_amount: remote as u64,
                                         local_reserved_ckb_amount,
                                         remote_reserved_ckb_amount,
+                                        external_funding_tx,
                                     },
                                 ),
                             ))
@@ -1143,6 +1614,20 @@ where
                     }
                 }
             }
+            NetworkActorEvent::ChannelOpenAborted(channel_id, peer_id) => {
+                info!(
+                    "Open of channel ({:?}) to peer {:?} is aborted",
+                    channel_id, peer_id
+                );
+                if let Some(channel) = state.remove_channel(&channel_id) {
+                    channel.stop(Some("ChannelOpenAborted".to_string()));
+                }
+                if let Some(session) = state.get_peer_session(&peer_id) {
+                    if let Some(set) = state.session_channels_map.get_mut(&session) {
+                        set.remove(&channel_id);
+                    }
+                }
+            }
             NetworkActorEvent::ChannelReady(channel_id, peer_id, channel_outpoint) => {
                 info!(
                     "Channel ({:?}) to peer {:?} is now ready",
@@ -1153,6 +1638,7 @@ where
                 state
                     .outpoint_channel_map
                     .insert(channel_outpoint.clone(), channel_id);
+                state.update_active_channels_metrics();
 
                 // Notify outside observers.
                 myself
@@ -1182,11 +1668,13 @@ where
                 state
                     .on_commitment_transaction_pending(transaction, channel_id)
                     .await;
+                state.update_active_channels_metrics();
             }
             NetworkActorEvent::CommitmentTransactionConfirmed(tx_hash, channel_id) => {
                 state
                     .on_commitment_transaction_confirmed(tx_hash, channel_id)
                     .await;
+                state.update_active_channels_metrics();
             }
             NetworkActorEvent::CommitmentTransactionFailed(tx_hash, channel_id) => {
                 error!(
@@ -1197,15 +1685,41 @@ where
             NetworkActorEvent::FundingTransactionFailed(outpoint) => {
                 error!("Funding transaction failed: {:?}", outpoint);
             }
+            NetworkActorEvent::FundingTransactionReorged(channel_id, peer_id, outpoint) => {
+                state
+                    .on_funding_transaction_reorged(channel_id, outpoint.clone())
+                    .await;
+                // Notify outside observers.
+                myself
+                    .send_message(NetworkActorMessage::new_event(
+                        NetworkActorEvent::NetworkServiceEvent(
+                            NetworkServiceEvent::ChannelFundingReorged(
+                                peer_id, channel_id, outpoint,
+                            ),
+                        ),
+                    ))
+                    .expect(ASSUME_NETWORK_MYSELF_ALIVE);
+            }
+            NetworkActorEvent::FundingTransactionFullyConfirmed(channel_id) => {
+                state
+                    .send_message_to_channel_actor(
+                        channel_id,
+                        None,
+                        ChannelActorMessage::Event(ChannelEvent::FundingTransactionFullyConfirmed),
+                    )
+                    .await;
+            }
             NetworkActorEvent::ClosingTransactionPending(channel_id, peer_id, tx) => {
                 state
                     .on_closing_transaction_pending(channel_id, peer_id.clone(), tx.clone())
                     .await;
+                state.update_active_channels_metrics();
             }
             NetworkActorEvent::ClosingTransactionConfirmed(peer_id, channel_id, tx_hash) => {
                 state
                     .on_closing_transaction_confirmed(&peer_id, &channel_id, tx_hash)
                     .await;
+                state.update_active_channels_metrics();
             }
             NetworkActorEvent::ClosingTransactionFailed(peer_id, tx_hash, channel_id) => {
                 error!(
@@ -1230,7 +1744,9 @@ where
                     "Graph syncer to peer {:?} has exited with reason {:?}",
                     &peer_id, &reason
                 );
-                if let NetworkSyncStatus::Running(state) = &mut state.sync_status {
+                if let NetworkSyncStatus::Running(state) | NetworkSyncStatus::Done(state) =
+                    &mut state.sync_status
+                {
                     if let Some(actor) = state.active_syncers.remove(&peer_id) {
                         actor
                             .get_cell()
@@ -1243,12 +1759,23 @@ where
                     }
                 }
                 state.maybe_finish_sync();
+                // Fall back to the next peer if the syncing with the preferred peer failed.
+                self.maybe_sync_network_graph(state, vec![]).await;
             }
             NetworkActorEvent::TlcRemoveReceived(payment_hash, remove_tlc) => {
                 // When a node is restarted, RemoveTLC will also be resent if necessary
                 self.on_tlc_remove_received(state, payment_hash, remove_tlc.reason)
+                    .instrument(info_span!("payment", payment_hash = %payment_hash))
                     .await;
             }
+            NetworkActorEvent::TlcRejected(rejected_tlc) => {
+                if state.rejected_tlcs_capacity > 0 {
+                    if state.rejected_tlcs.len() >= state.rejected_tlcs_capacity {
+                        state.rejected_tlcs.pop_front();
+                    }
+                    state.rejected_tlcs.push_back(rejected_tlc);
+                }
+            }
         }
         Ok(())
     }
@@ -1298,10 +1825,14 @@ where
             }
 
             NetworkActorCommand::SavePeerAddress(addr) => match extract_peer_id(&addr) {
-                Some(peer) => {
-                    debug!("Saved peer id {:?} with address {:?}", &peer, &addr);
-                    state.save_peer_address(peer, addr);
-                }
+                Some(peer) => match state.save_peer_address(peer.clone(), addr.clone()) {
+                    Ok(_) => {
+                        debug!("Saved peer id {:?} with address {:?}", &peer, &addr);
+                    }
+                    Err(err) => {
+                        error!("Failed to save address to peer store: {}", err);
+                    }
+                },
                 None => {
                     error!("Failed to save address to peer store: unable to extract peer id from address {:?}", &addr);
                 }
@@ -1344,8 +1875,72 @@ where
                 }
             }
 
+            NetworkActorCommand::CheckFundingTransactions => {
+                state.check_funding_transactions();
+            }
+
+            NetworkActorCommand::RebalanceChannels => {
+                self.rebalance_channels(state).await;
+            }
+
+            NetworkActorCommand::UpdateChainTip => {
+                let chain = self.chain_actor.clone();
+                let chain_tip = state.chain_tip.clone();
+                // Don't block the network actor while waiting for the ckb node.
+                ractor::concurrency::tokio_primatives::spawn(async move {
+                    match call_t!(
+                        chain,
+                        CkbChainMessage::GetCurrentBlockNumber,
+                        DEFAULT_CHAIN_ACTOR_TIMEOUT,
+                        ()
+                    ) {
+                        Ok(Ok(block_number)) => chain_tip.update(block_number),
+                        result => warn!("Failed to get the tip block number: {:?}", result),
+                    }
+                });
+            }
+
+            NetworkActorCommand::FailUnresolvedForwardedTlcs => {
+                self.fail_unresolved_forwarded_tlcs(state).await;
+            }
+
+            NetworkActorCommand::FailForwardedTlc(channel_id, tlc_id, reply) => {
+                match self.get_manual_fail_back_reason(channel_id, tlc_id) {
+                    Ok(reason) => {
+                        let command = ChannelCommand::FailForwardedTlc(
+                            RemoveTlcCommand { id: tlc_id, reason },
+                            reply,
+                        );
+                        state.send_command_to_channel(channel_id, command).await?;
+                    }
+                    Err(err) => {
+                        let _ = reply.send(Err(err.to_string()));
+                    }
+                }
+            }
+
+            NetworkActorCommand::CheckPaymentDeadline(payment_hash) => {
+                self.on_payment_deadline_reached(payment_hash);
+            }
+
+            NetworkActorCommand::PrunePaymentSessions => {
+                state.prune_payment_sessions();
+            }
+
+            NetworkActorCommand::PruneClosedChannels => {
+                state.prune_closed_channels();
+            }
+
+            NetworkActorCommand::SaveGraphSnapshot => {
+                self.network_graph.write().await.save_snapshot();
+            }
+
             NetworkActorCommand::OpenChannel(open_channel, reply) => {
-                match state.create_outbound_channel(open_channel).await {
+                let remote_features = self.get_peer_features(state, &open_channel.peer_id).await;
+                match state
+                    .create_outbound_channel(open_channel, remote_features)
+                    .await
+                {
                     Ok((_, channel_id)) => {
                         let _ = reply.send(Ok(OpenChannelResponse { channel_id }));
                     }
@@ -1356,7 +1951,17 @@ where
                 }
             }
             NetworkActorCommand::AcceptChannel(accept_channel, reply) => {
-                match state.create_inbound_channel(accept_channel).await {
+                let remote_features = match state
+                    .to_be_accepted_channels
+                    .get(&accept_channel.temp_channel_id)
+                {
+                    Some((peer_id, _)) => self.get_peer_features(state, peer_id).await,
+                    None => None,
+                };
+                match state
+                    .create_inbound_channel(accept_channel, remote_features)
+                    .await
+                {
                     Ok((_, old_channel_id, new_channel_id)) => {
                         let _ = reply.send(Ok(AcceptChannelResponse {
                             old_channel_id,
@@ -1393,6 +1998,41 @@ where
 
                 let _ = reply.send(response);
             }
+            NetworkActorCommand::BuildTrampolineOnionPacket(
+                payment_data,
+                hash_algorithm,
+                max_expiry,
+                reply,
+            ) => {
+                let response = self
+                    .network_graph
+                    .read()
+                    .await
+                    .build_route(&payment_data)
+                    .map_err(|err| err.to_string())
+                    .and_then(|mut hops_infos| {
+                        if hops_infos[0].expiry > max_expiry {
+                            return Err(format!(
+                                "The route to the target needs locktime {}, more than the budget {}",
+                                hops_infos[0].expiry, max_expiry
+                            ));
+                        }
+                        for hop in hops_infos.iter_mut() {
+                            hop.tlc_hash_algorithm = hash_algorithm;
+                        }
+                        let session_key =
+                            Privkey::from_slice(KeyPair::generate_random_key().as_ref());
+                        PeeledPaymentOnionPacket::create(
+                            session_key,
+                            hops_infos,
+                            &Secp256k1::signing_only(),
+                        )
+                        .map(|packet| packet.serialize())
+                        .map_err(|err| err.to_string())
+                    });
+
+                let _ = reply.send(response);
+            }
 
             NetworkActorCommand::UpdateChannelFunding(channel_id, transaction, request) => {
                 let old_tx = transaction.into_view();
@@ -1415,10 +2055,16 @@ where
                     Ok(Err(err)) => {
                         // FIXME(yukang): we need to handle this error properly
                         error!("Failed to fund channel: {}", err);
+                        state
+                            .channel_funding_errors
+                            .insert(channel_id, format!("Failed to fund channel: {}", err));
                         return Ok(());
                     }
                     Err(err) => {
                         error!("Failed to call chain actor: {}", err);
+                        state
+                            .channel_funding_errors
+                            .insert(channel_id, format!("Failed to call chain actor: {}", err));
                         return Ok(());
                     }
                 };
@@ -1611,7 +2257,9 @@ where
             },
             NetworkActorCommand::MarkSyncingDone => {
                 info!("Syncing network information finished");
-                state.sync_status = NetworkSyncStatus::Done;
+                if let NetworkSyncStatus::Running(sync_state) = &state.sync_status {
+                    state.sync_status = NetworkSyncStatus::Done(sync_state.clone());
+                }
                 let mut broadcasted_message_queue = vec![];
                 // Consume broadcasted message queue without consue the whole state.
                 std::mem::swap(
@@ -1637,6 +2285,7 @@ where
                 // TODO: We need to send a reply to the caller if enough time passed,
                 // but we still do not get a reply from the peer.
                 let (peer_id, start_block, end_block) = request;
+                state.sync_status.on_channels_requested(start_block);
                 let id = state.create_request_id_for_reply_port(&peer_id, reply);
                 let message = FiberMessage::QueryInformation(
                     FiberQueryInformation::QueryChannelsWithinBlockRange(
@@ -1657,6 +2306,8 @@ where
                 // TODO: We need to send a reply to the caller if enough time passed,
                 // but we still do not get a reply from the peer.
                 let (peer_id, start_time, end_time) = request;
+                // The broadcast messages are only requested after all the channels are received.
+                state.sync_status.on_channels_synced();
                 let id = state.create_request_id_for_reply_port(&peer_id, reply);
                 let message = FiberMessage::QueryInformation(
                     FiberQueryInformation::QueryBroadcastMessagesWithinTimeRange(
@@ -1683,9 +2334,7 @@ where
                         "Trying to sync network information from peers: {:?}, sync parameters: {:?}",
                         &peers, &state.sync_status
                     );
-                    for peer_id in peers {
-                        state.maybe_sync_network_graph(&peer_id).await;
-                    }
+                    self.maybe_sync_network_graph(state, peers).await;
                 }
                 _ => {
                     error!(
@@ -1734,6 +2383,7 @@ where
                     node1_to_node2: None, // wait for channel update message
                     node2_to_node1: None,
                     timestamp: std::time::UNIX_EPOCH.elapsed().unwrap().as_millis() as u64,
+                    private: false,
                 };
                 let mut graph = self.network_graph.write().await;
                 graph.add_channel(channel_info);
@@ -1787,6 +2437,84 @@ where
                     ))
                     .expect(ASSUME_NETWORK_MYSELF_ALIVE);
             }
+            NetworkActorCommand::RebroadcastChannelAnnouncement(channel_id, rpc) => {
+                match self.store.get_channel_actor_state(&channel_id) {
+                    Some(channel_state) if channel_state.is_public() => {}
+                    Some(_) => {
+                        let _ = rpc.send(Err(format!(
+                            "Channel {:?} is not public, only public channel can be announced",
+                            channel_id
+                        )));
+                        return Ok(());
+                    }
+                    None => {
+                        let _ = rpc.send(Err(format!("Channel not found: {:?}", channel_id)));
+                        return Ok(());
+                    }
+                }
+                let now = std::time::Instant::now();
+                if let Some(last) = state.channel_announcement_rebroadcasts.get(&channel_id) {
+                    let elapsed = now.duration_since(*last);
+                    if elapsed < REBROADCASTING_CHANNEL_ANNOUNCEMENT_INTERVAL {
+                        let _ = rpc.send(Err(format!(
+                            "Channel {:?} is rebroadcasted too frequently, retry after {} seconds",
+                            channel_id,
+                            (REBROADCASTING_CHANNEL_ANNOUNCEMENT_INTERVAL - elapsed).as_secs() + 1
+                        )));
+                        return Ok(());
+                    }
+                }
+                match state.channels.get(&channel_id) {
+                    Some(actor) => {
+                        actor.send_message(ChannelActorMessage::Command(
+                            ChannelCommand::RebroadcastAnnouncement(rpc),
+                        ))?;
+                        state
+                            .channel_announcement_rebroadcasts
+                            .insert(channel_id, now);
+                    }
+                    None => {
+                        let _ = rpc.send(Err(format!(
+                            "Channel {:?} is not active, the peer may be disconnected",
+                            channel_id
+                        )));
+                    }
+                }
+            }
+            NetworkActorCommand::RebroadcastChannelMessages(
+                channel_announcement,
+                channel_update,
+            ) => {
+                let mut graph = self.network_graph.write().await;
+                graph
+                    .process_channel_update(channel_update.clone())
+                    .expect("Valid channel update");
+                drop(graph);
+
+                // Unlike BroadcastMessage, the messages are sent to all the connected peers,
+                // regardless of whether they have been broadcasted before.
+                let peer_ids: Vec<PeerId> = state.peer_session_map.keys().cloned().collect();
+                for message in [
+                    FiberBroadcastMessage::ChannelAnnouncement(channel_announcement),
+                    FiberBroadcastMessage::ChannelUpdate(channel_update),
+                ] {
+                    state.should_message_be_broadcasted(&message);
+                    for peer_id in &peer_ids {
+                        if let Err(e) = state
+                            .send_message_to_peer(
+                                peer_id,
+                                FiberMessage::BroadcastMessage(message.clone()),
+                            )
+                            .await
+                        {
+                            error!(
+                                "Failed to rebroadcast message {:?} to peer {:?}: {:?}",
+                                &message, peer_id, e
+                            );
+                        }
+                    }
+                }
+            }
             NetworkActorCommand::NodeInfo(_, rpc) => {
                 let response = NodeInfoResponse {
                     node_name: state.node_name.clone(),
@@ -1804,12 +2532,95 @@ where
                     tlc_fee_proportional_millionths: state.tlc_fee_proportional_millionths,
                     channel_count: state.channels.len() as u32,
                     pending_channel_count: state.pending_channels.len() as u32,
+                    funding_at_risk_amount: state.get_funding_at_risk_amount(),
                     peers_count: state.peer_session_map.len() as u32,
                     network_sync_status: state.sync_status.as_str().to_string(),
                     udt_cfg_infos: get_udt_whitelist(),
                 };
                 let _ = rpc.send(Ok(response));
             }
+            NetworkActorCommand::GraphSyncStatus(_, rpc) => {
+                let _ = rpc.send(Ok(state.sync_status.to_response()));
+            }
+            NetworkActorCommand::Health(_, rpc) => {
+                let peers_count = state.peer_session_map.len() as u32;
+                let inbound_peers_count = state.inbound_peers.len() as u32;
+                let response = HealthResponse {
+                    ready_for_payments: state.is_ready_for_payments(),
+                    peers_count,
+                    graph_sync_progress: state.sync_status.progress(),
+                    inbound_peers_count,
+                    outbound_peers_count: peers_count.saturating_sub(inbound_peers_count),
+                    rejected_connections_count: state.rejected_connections,
+                };
+                let _ = rpc.send(Ok(response));
+            }
+            NetworkActorCommand::SetConnectionLimits(command, rpc) => {
+                if let Some(max_peers) = command.max_peers {
+                    state.max_peers = max_peers;
+                }
+                if let Some(max_connections) = command.connection_rate_limit_max_connections {
+                    state.connection_rate_limiter.max_connections = max_connections;
+                }
+                let _ = rpc.send(Ok(()));
+            }
+            NetworkActorCommand::ListRejectedTlcs(_, rpc) => {
+                let rejected_tlcs = state.rejected_tlcs.iter().rev().cloned().collect();
+                let _ = rpc.send(Ok(rejected_tlcs));
+            }
+            NetworkActorCommand::GetChannelOpenStatus(channel_id, rpc) => {
+                let _ = rpc.send(self.get_channel_open_status(state, channel_id));
+            }
+            NetworkActorCommand::GetChannelFundingAddress(channel_id, rpc) => {
+                let _ = rpc.send(self.get_channel_funding_address(state, channel_id));
+            }
+            NetworkActorCommand::GetRebalanceRoute(command, rpc) => {
+                let _ = rpc.send(self.get_rebalance_route(command).await);
+            }
+            NetworkActorCommand::ListChannelsNeedingAction(thresholds, rpc) => {
+                let _ = rpc.send(Ok(self.list_channels_needing_action(state, &thresholds)));
+            }
+            NetworkActorCommand::SuggestFeeRates(_, rpc) => {
+                if let Some(fee_rates) = state.suggested_fee_rates {
+                    let _ = rpc.send(Ok(fee_rates));
+                    return Ok(());
+                }
+                let chain = self.chain_actor.clone();
+                // Don't block the network actor while waiting for the ckb node.
+                ractor::concurrency::tokio_primatives::spawn(async move {
+                    let mut statistics = vec![];
+                    for target in [
+                        LOW_FEE_RATE_TARGET_BLOCKS,
+                        MEDIUM_FEE_RATE_TARGET_BLOCKS,
+                        HIGH_FEE_RATE_TARGET_BLOCKS,
+                    ] {
+                        match call_t!(
+                            chain,
+                            CkbChainMessage::GetFeeRateStatistics,
+                            DEFAULT_CHAIN_ACTOR_TIMEOUT,
+                            Some(target)
+                        ) {
+                            Ok(Ok(result)) => statistics.push(result),
+                            result => {
+                                let _ = rpc.send(Err(format!(
+                                    "Failed to get fee rate statistics of {} blocks: {:?}",
+                                    target, result
+                                )));
+                                return;
+                            }
+                        }
+                    }
+                    let high = statistics.pop().flatten();
+                    let medium = statistics.pop().flatten();
+                    let low = statistics.pop().flatten();
+                    let _ = rpc.send(Ok(SuggestedFeeRates::from_statistics(
+                        low,
+                        medium,
+                        high,
+                        DEFAULT_FEE_RATE,
+                    )));
+                });
+            }
         };
         Ok(())
     }
@@ -1820,15 +2631,38 @@ where
         peer_id: PeerId,
         message: FiberBroadcastMessage,
     ) -> Result<(), Error> {
-        if state.sync_status.is_syncing() {
-            debug!(
-                "Saving broadcasted message to queue as we are syncing: {:?}",
+        if !state.gossip_rate_limiter.check(&peer_id) {
+            trace!(
+                "Dropping broadcasted message from peer {:?} due to rate limit: {:?}",
+                &peer_id,
                 &message
             );
-            state.broadcasted_message_queue.push((peer_id, message));
             return Ok(());
         }
-        // Rebroadcast the message to other peers if necessary.
+        if !self
+            .network_graph
+            .read()
+            .await
+            .is_gossip_message_accepted(&message)
+        {
+            trace!(
+                "Dropping broadcasted message outside of the gossip filter: {:?}",
+                &message
+            );
+            return Ok(());
+        }
+        if state.is_gossip_message_too_far_in_future(&message) {
+            return Ok(());
+        }
+        if state.sync_status.is_syncing() {
+            debug!(
+                "Saving broadcasted message to queue as we are syncing: {:?}",
+                &message
+            );
+            state.broadcasted_message_queue.push((peer_id, message));
+            return Ok(());
+        }
+        // Rebroadcast the message to other peers if necessary.
         state
             .network
             .send_message(NetworkActorMessage::new_command(
@@ -1843,6 +2677,22 @@ where
         state: &mut NetworkActorState<S>,
         message: FiberBroadcastMessage,
     ) -> Result<(), Error> {
+        // The messages received while syncing are also checked here.
+        if !self
+            .network_graph
+            .read()
+            .await
+            .is_gossip_message_accepted(&message)
+        {
+            trace!(
+                "Dropping broadcasted message outside of the gossip filter: {:?}",
+                &message
+            );
+            return Ok(());
+        }
+        if state.is_gossip_message_too_far_in_future(&message) {
+            return Ok(());
+        }
         match message {
             FiberBroadcastMessage::NodeAnnouncement(node_announcement) => {
                 let message = node_announcement.message_to_sign();
@@ -2035,6 +2885,7 @@ where
                     node1_to_node2: None, // wait for channel update message
                     node2_to_node1: None,
                     timestamp: std::time::UNIX_EPOCH.elapsed().unwrap().as_millis() as u64,
+                    private: false,
                 };
                 self.network_graph.write().await.add_channel(channel_info);
                 Ok(())
@@ -2191,20 +3042,49 @@ where
                 match reason {
                     RemoveTlcReason::RemoveTlcFulfill(_) => {
                         payment_session.set_success_status();
+                        metrics().record_payment_sent();
+                        metrics().record_payment_latency(true, payment_session.elapsed());
                         self.store.insert_payment_session(payment_session);
                     }
                     RemoveTlcReason::RemoveTlcFail(reason) => {
                         let detail_error = reason.decode().expect("decoded error");
                         self.update_with_tcl_fail(&detail_error).await;
-                        if payment_session.can_retry() && !detail_error.error_code.payment_failed()
+                        if payment_session.request.probe
+                            && detail_error.error_code
+                                == TlcErrorCode::IncorrectOrUnknownPaymentDetails
                         {
-                            let res = self.try_payment_session(state, payment_session).await;
-                            if res.is_err() {
-                                debug!("Failed to retry payment session: {:?}", res);
-                            }
-                        } else {
-                            payment_session.set_failed_status(detail_error.error_code.as_ref());
+                            // The probe reached the target, which doesn't know the payment hash.
+                            payment_session.set_probe_succeeded_status();
                             self.store.insert_payment_session(payment_session);
+                            return;
+                        }
+                        let failure_reason = if payment_session.deadline_exceeded
+                            || payment_session.is_timed_out()
+                        {
+                            Some(PaymentFailureReason::Timeout)
+                        } else if detail_error.error_code.payment_failed() {
+                            Some(PaymentFailureReason::Rejected)
+                        } else if !payment_session.can_retry() {
+                            Some(PaymentFailureReason::RoutesExhausted)
+                        } else {
+                            None
+                        };
+                        match failure_reason {
+                            None => {
+                                let res = self.try_payment_session(state, payment_session).await;
+                                if res.is_err() {
+                                    debug!("Failed to retry payment session: {:?}", res);
+                                }
+                            }
+                            Some(failure_reason) => {
+                                payment_session.set_failed_status(
+                                    detail_error.error_code.as_ref(),
+                                    failure_reason,
+                                );
+                                metrics().record_payment_failed();
+                                metrics().record_payment_latency(false, payment_session.elapsed());
+                                self.store.insert_payment_session(payment_session);
+                            }
                         }
                     }
                 }
@@ -2212,6 +3092,27 @@ where
         }
     }
 
+    // The attempt may never be resolved by the downstream in time, e.g. the peer is offline, so
+    // the timeout is recorded once the deadline passes. The session stays in flight, as its tlc
+    // may still be fulfilled, and it's failed once the tlc is removed.
+    fn on_payment_deadline_reached(&self, payment_hash: Hash256) {
+        let Some(mut payment_session) = self.store.get_payment_session(payment_hash) else {
+            return;
+        };
+        if !payment_session.has_unresolved_attempt()
+            || payment_session.deadline_exceeded
+            || !payment_session.is_timed_out()
+        {
+            return;
+        }
+        debug!(
+            "Payment {:?} timed out with the attempt in flight",
+            payment_hash
+        );
+        payment_session.deadline_exceeded = true;
+        self.store.insert_payment_session(payment_session);
+    }
+
     async fn update_with_tcl_fail(&self, tcl_error_detail: &TlcErr) {
         let error_code = tcl_error_detail.error_code();
         // https://github.com/lightning/bolts/blob/master/04-onion-routing.md#rationale-6
@@ -2249,6 +3150,508 @@ where
         }
     }
 
+    // The feature bits announced by the peer, None if the peer has not announced any, e.g. its
+    // node announcement is not received yet or it is running a version predating the features.
+    async fn get_peer_features(
+        &self,
+        state: &NetworkActorState<S>,
+        peer_id: &PeerId,
+    ) -> Option<u64> {
+        let pubkey = state.get_peer_pubkey(peer_id)?;
+        let graph = self.network_graph.read().await;
+        graph
+            .get_node(pubkey)
+            .map(|node_info| node_info.anouncement_msg.features)
+            .filter(|features| *features != 0)
+    }
+
+    // The payment relying on a feature is rejected early if the recipient announced its features
+    // without it, instead of failing after an attempt. The nodes announcing no features may be
+    // running an older version, and are not checked.
+    async fn check_features_supported_by_recipient(
+        &self,
+        payment_data: &SendPaymentData,
+    ) -> Result<(), Error> {
+        let graph = self.network_graph.read().await;
+        let Some(node_info) = graph.get_node(payment_data.target_pubkey) else {
+            return Ok(());
+        };
+        let node_announcement = &node_info.anouncement_msg;
+        if node_announcement.features == 0 {
+            return Ok(());
+        }
+        if payment_data.keysend && !node_announcement.supports_feature(NODE_FEATURE_KEYSEND) {
+            return Err(Error::InvalidParameter(format!(
+                "Recipient {:?} does not support keysend payments, announced features: {:#x}",
+                payment_data.target_pubkey, node_announcement.features
+            )));
+        }
+        Ok(())
+    }
+
+    fn get_outgoing_channel_outpoint(&self, channel_id: Hash256) -> Result<OutPoint, Error> {
+        match self.store.get_channel_actor_state(&channel_id) {
+            Some(state) if state.state == ChannelState::ChannelReady() => {
+                Ok(state.get_funding_transaction_outpoint())
+            }
+            Some(state) => Err(Error::InvalidParameter(format!(
+                "Outgoing channel {:?} is not ready: {:?}",
+                channel_id, state.state
+            ))),
+            None => Err(Error::InvalidParameter(format!(
+                "Outgoing channel not found: {:?}",
+                channel_id
+            ))),
+        }
+    }
+
+    // Rebalance the ready channels whose local balance exceeds the target of their rebalance policy,
+    // by sending a circular payment to ourselves with the channel as the first hop.
+    // Start syncing the network graph with the given newly available peers. With the preferred
+    // strategy, the best connected peer not yet queried is selected instead, the given peers
+    // are only retried after all the connected peers are queried.
+    async fn maybe_sync_network_graph(
+        &self,
+        state: &mut NetworkActorState<S>,
+        peer_ids: Vec<PeerId>,
+    ) {
+        let sync_state = match &state.sync_status {
+            NetworkSyncStatus::Running(sync_state) => sync_state,
+            _ => return,
+        };
+        let peer_ids = match sync_state.strategy {
+            GraphSyncPeerStrategy::All => peer_ids,
+            GraphSyncPeerStrategy::Preferred => {
+                let graph = self.network_graph.read().await;
+                state
+                    .peer_session_map
+                    .keys()
+                    .filter(|peer_id| !sync_state.queried_peers.contains(peer_id))
+                    .max_by_key(|peer_id| {
+                        state
+                            .get_peer_pubkey(peer_id)
+                            .map(|pubkey| {
+                                (
+                                    graph.get_node(pubkey).map(|node| node.timestamp),
+                                    graph.get_channels_by_peer(pubkey).count(),
+                                )
+                            })
+                            .unwrap_or_default()
+                    })
+                    .cloned()
+                    .map_or(peer_ids, |peer_id| vec![peer_id])
+            }
+        };
+        for peer_id in peer_ids {
+            state.maybe_sync_network_graph(&peer_id).await;
+        }
+    }
+
+    async fn rebalance_channels(&self, state: &mut NetworkActorState<S>) {
+        let our_pubkey = state.get_public_key();
+        let channels: Vec<_> = self
+            .store
+            .get_channel_states(None)
+            .into_iter()
+            .filter(|(_, _, state)| matches!(state, ChannelState::ChannelReady()))
+            .filter_map(|(_, channel_id, _)| {
+                let state = self.store.get_channel_actor_state(&channel_id)?;
+                // Skip the channels with pending tlcs, which may be a rebalance payment in flight.
+                if state.get_offered_tlc_balance() != 0 || state.get_received_tlc_balance() != 0 {
+                    return None;
+                }
+                let policy = state.rebalance_policy.clone()?;
+                let amount = policy
+                    .rebalance_amount(state.get_local_balance(), state.get_remote_balance())?;
+                Some((channel_id, amount, policy, state.funding_udt_type_script))
+            })
+            .collect();
+        for (channel_id, amount, policy, udt_type_script) in channels {
+            debug!(
+                "Rebalancing channel {:?} with amount {} and max fee {}",
+                channel_id, amount, policy.max_fee_amount
+            );
+            let command = SendPaymentCommand {
+                target_pubkey: Some(our_pubkey),
+                amount: Some(amount),
+                payment_hash: None,
+                invoice: None,
+                final_cltv_delta: None,
+                timeout: None,
+                max_fee_amount: Some(policy.max_fee_amount),
+                max_parts: None,
+                keysend: Some(true),
+                udt_type_script,
+                allow_self_payment: true,
+                outgoing_channel: Some(channel_id),
+                probe: None,
+                trampoline_node: None,
+                keep_reserve: None,
+                max_fee_percent: None,
+                max_fee_rate_ppm: None,
+                fees_paid_by: None,
+            };
+            if let Err(err) = self.on_send_payment(state, command).await {
+                warn!("Failed to rebalance channel {:?}: {:?}", channel_id, err);
+            }
+        }
+    }
+
+    async fn get_rebalance_route(
+        &self,
+        command: GetRebalanceRouteCommand,
+    ) -> Result<RebalanceRoute, String> {
+        let GetRebalanceRouteCommand {
+            source_channel,
+            destination_channel,
+            amount,
+            max_fee_amount,
+        } = command;
+        let get_ready_channel = |name: &str, channel_id: Hash256| {
+            match self.store.get_channel_actor_state(&channel_id) {
+                Some(state) if state.state != ChannelState::ChannelReady() => Err(format!(
+                    "{} channel {:?} is not ready: {:?}",
+                    name, channel_id, state.state
+                )),
+                // Private channels are not in the network graph, so they can't be routed through.
+                Some(state) if !state.is_public() => Err(format!(
+                    "{} channel {:?} is not public, only public channels can be rebalanced",
+                    name, channel_id
+                )),
+                Some(state) => Ok(state),
+                None => Err(format!("{} channel not found: {:?}", name, channel_id)),
+            }
+        };
+        let source = get_ready_channel("Source", source_channel)?;
+        let destination = get_ready_channel("Destination", destination_channel)?;
+        if source.funding_udt_type_script != destination.funding_udt_type_script {
+            return Err(
+                "Source and destination channels hold different assets, can't be rebalanced"
+                    .to_string(),
+            );
+        }
+
+        let hops = self
+            .network_graph
+            .read()
+            .await
+            .build_circular_route(
+                &source.get_funding_transaction_outpoint(),
+                &destination.get_funding_transaction_outpoint(),
+                amount,
+                max_fee_amount,
+                source.funding_udt_type_script.clone(),
+            )
+            .map_err(|err| format!("No rebalance route found: {}", err))?;
+        let sent_amount = hops[0].amount;
+        let fee = sent_amount - amount;
+        let available = source
+            .get_local_balance()
+            .saturating_sub(source.get_offered_tlc_balance());
+        if sent_amount > available {
+            return Err(format!(
+                "The available local balance {} of the source channel can't cover the amount {} plus the fee {}",
+                available, amount, fee
+            ));
+        }
+        let receivable = destination
+            .get_remote_balance()
+            .saturating_sub(destination.get_received_tlc_balance());
+        if amount > receivable {
+            return Err(format!(
+                "The available remote balance {} of the destination channel can't cover the amount {}",
+                receivable, amount
+            ));
+        }
+        Ok(RebalanceRoute {
+            amount,
+            fee,
+            hops: hops
+                .into_iter()
+                .filter_map(|hop| {
+                    Some(RebalanceRouteHop {
+                        target: hop.next_hop?,
+                        channel_outpoint: hop.channel_outpoint?,
+                        amount: hop.amount,
+                    })
+                })
+                .collect(),
+        })
+    }
+
+    fn get_channel_open_status(
+        &self,
+        state: &NetworkActorState<S>,
+        channel_id: Hash256,
+    ) -> Result<ChannelOpenStatus, String> {
+        let channel_id = state
+            .temp_channel_ids
+            .get(&channel_id)
+            .copied()
+            .unwrap_or(channel_id);
+        let funding_error = state.channel_funding_errors.get(&channel_id).cloned();
+        match self.store.get_channel_actor_state(&channel_id) {
+            Some(channel_state) => Ok(ChannelOpenStatus {
+                channel_id,
+                step: channel_state.state.open_step(),
+                state: Some(channel_state.state),
+                error: funding_error.or(channel_state.open_error),
+            }),
+            // The outbound channel is not saved until the peer accepts it, and the inbound
+            // channel is not created until we accept it.
+            None if state.channels.contains_key(&channel_id)
+                || state.to_be_accepted_channels.contains_key(&channel_id) =>
+            {
+                Ok(ChannelOpenStatus {
+                    channel_id,
+                    step: ChannelOpenStep::Negotiating,
+                    state: None,
+                    error: None,
+                })
+            }
+            None => Err(format!("Channel not found: {:?}", channel_id)),
+        }
+    }
+
+    fn get_channel_funding_address(
+        &self,
+        state: &NetworkActorState<S>,
+        channel_id: Hash256,
+    ) -> Result<ChannelFundingAddress, String> {
+        let channel_id = state
+            .temp_channel_ids
+            .get(&channel_id)
+            .copied()
+            .unwrap_or(channel_id);
+        // The funding script can only be derived after both parties exchanged their funding
+        // pubkeys, i.e. the channel is accepted by the peer.
+        let channel_state = self
+            .store
+            .get_channel_actor_state(&channel_id)
+            .ok_or_else(|| {
+                format!(
+                    "Channel not found or not accepted by the peer yet: {:?}",
+                    channel_id
+                )
+            })?;
+        if channel_state.is_closed() {
+            return Err(format!("Channel {:?} is already closed", channel_id));
+        }
+        let funding_script = channel_state.get_funding_lock_script();
+        let (ckb_amount, udt_amount) = match channel_state.funding_udt_type_script {
+            Some(_) => (
+                channel_state.local_reserved_ckb_amount + channel_state.remote_reserved_ckb_amount,
+                Some(channel_state.to_local_amount + channel_state.to_remote_amount),
+            ),
+            None => (
+                (channel_state.to_local_amount + channel_state.to_remote_amount) as u64
+                    + channel_state.local_reserved_ckb_amount
+                    + channel_state.remote_reserved_ckb_amount,
+                None,
+            ),
+        };
+        // Make sure the funding transaction built so far pays to the same cell, otherwise the
+        // negotiated parameters are inconsistent and the channel must not be funded.
+        if let Some(funding_cell) = channel_state
+            .funding_tx
+            .as_ref()
+            .and_then(|tx| tx.raw().outputs().get(0))
+        {
+            if funding_cell.lock() != funding_script {
+                return Err(format!(
+                    "Funding transaction of channel {:?} does not pay to the funding script",
+                    channel_id
+                ));
+            }
+            if funding_cell.type_().to_opt() != channel_state.funding_udt_type_script {
+                return Err(format!(
+                    "Funding transaction of channel {:?} does not match the funding udt type script",
+                    channel_id
+                ));
+            }
+            let capacity: u64 = funding_cell.capacity().unpack();
+            if capacity > ckb_amount {
+                return Err(format!(
+                    "Funding transaction of channel {:?} exceeds the negotiated capacity: {} > {}",
+                    channel_id, capacity, ckb_amount
+                ));
+            }
+        }
+        Ok(ChannelFundingAddress {
+            channel_id,
+            funding_script,
+            funding_udt_type_script: channel_state.funding_udt_type_script,
+            ckb_amount,
+            udt_amount,
+        })
+    }
+
+    fn list_channels_needing_action(
+        &self,
+        state: &NetworkActorState<S>,
+        thresholds: &ChannelAttentionThresholds,
+    ) -> Vec<ChannelNeedingAction> {
+        let now = std::time::UNIX_EPOCH.elapsed().unwrap().as_millis() as u64;
+        // The tlc expiries are unknown until the tip block number is fetched.
+        let tlc_deadline = state
+            .chain_tip
+            .get()
+            .map(|tip| tip.saturating_add(thresholds.tlc_expiry_blocks));
+        let mut result = vec![];
+        for (peer_id, channel_id, channel_state) in self.store.get_active_channel_states(None) {
+            let Some(actor_state) = self.store.get_channel_actor_state(&channel_id) else {
+                continue;
+            };
+            let mut reasons: Vec<_> = tlc_deadline
+                .map(|deadline| actor_state.get_tlcs_expiring_before(deadline))
+                .unwrap_or_default()
+                .into_iter()
+                .map(
+                    |(tlc_id, expires_at_block)| ChannelAttentionReason::TlcNearExpiry {
+                        tlc_id,
+                        expires_at_block,
+                    },
+                )
+                .collect();
+            let since = actor_state.get_state_changed_at();
+            if channel_state != ChannelState::ChannelReady()
+                && now.saturating_sub(since) >= thresholds.stuck_state
+            {
+                reasons.push(ChannelAttentionReason::StuckState { since });
+            }
+            if !state.peer_session_map.contains_key(&peer_id) {
+                reasons.push(ChannelAttentionReason::PeerOffline {
+                    last_disconnected_at: actor_state.connection_stats.last_disconnected_at,
+                });
+            }
+            if let Some(reason) = actor_state.halted.clone() {
+                reasons.push(ChannelAttentionReason::Halted { reason });
+            }
+            result.extend(reasons.into_iter().map(|reason| ChannelNeedingAction {
+                peer_id: peer_id.clone(),
+                channel_id,
+                state: channel_state,
+                reason,
+            }));
+        }
+        result
+    }
+
+    // Fail back the received tlcs which are forwarded for longer than the resolution timeout,
+    // once the downstream tlcs are failed. The timeout is counted in blocks like the lock time of
+    // the tlcs. The downstream tlcs which are still pending may be fulfilled late, even past their
+    // lock time, and the received tlcs must be kept to claim the payments then.
+    async fn fail_unresolved_forwarded_tlcs(&self, state: &mut NetworkActorState<S>) {
+        let Some(tip) = state.chain_tip.get() else {
+            debug!("Skipping the check of the forwarded tlcs as the chain tip is unknown");
+            return;
+        };
+        let mut tlcs = vec![];
+        for (_, channel_id, _) in self.store.get_channel_states(None) {
+            let Some(channel_state) = self.store.get_channel_actor_state(&channel_id) else {
+                continue;
+            };
+            for tlc in channel_state.get_forwarding_tlcs() {
+                if !tlc.added_at_block.is_some_and(|added_at| {
+                    tip.saturating_sub(added_at) >= state.tlc_resolution_timeout_blocks
+                }) {
+                    continue;
+                }
+                // The fulfillment is relayed to the previous hop by the downstream channel.
+                let Some(reason) = tlc.fail_back_reason() else {
+                    debug!(
+                        "Tlc {:?} of channel {:?} is fulfilled or still pending in the downstream channel {:?}",
+                        tlc.previous_tlc.1, tlc.previous_tlc.0, channel_id
+                    );
+                    continue;
+                };
+                let (previous_channel_id, previous_tlc) = tlc.previous_tlc;
+                if self
+                    .store
+                    .get_channel_actor_state(&previous_channel_id)
+                    .is_some_and(|state| state.is_received_tlc_pending(previous_tlc.into()))
+                {
+                    tlcs.push((previous_channel_id, previous_tlc, reason));
+                }
+            }
+        }
+        for (channel_id, tlc_id, reason) in tlcs {
+            warn!(
+                "Failing back tlc {:?} of channel {:?} whose downstream tlc is failed",
+                tlc_id, channel_id
+            );
+            // Don't wait for the reply, the channel actor may be calling the network actor.
+            let (send, _recv) = oneshot::channel::<Result<(), String>>();
+            let command = ChannelCommand::RemoveTlc(
+                RemoveTlcCommand {
+                    id: u64::from(tlc_id),
+                    reason,
+                },
+                RpcReplyPort::from(send),
+            );
+            if let Err(err) = state.send_command_to_channel(channel_id, command).await {
+                error!(
+                    "Failed to fail back tlc {:?} of channel {:?}: {:?}",
+                    tlc_id, channel_id, err
+                );
+            }
+        }
+    }
+
+    // The reason to fail back the received tlc manually, refused if the downstream tlc is
+    // fulfilled or may still be fulfilled, as the amount would be lost then. A forwarded tlc
+    // whose downstream tlc is not found can't be checked, hence it's refused as well.
+    fn get_manual_fail_back_reason(
+        &self,
+        channel_id: Hash256,
+        tlc_id: u64,
+    ) -> Result<RemoveTlcReason, Error> {
+        let previous_tlc = (channel_id, TLCId::Received(tlc_id));
+        for (_, downstream_channel_id, _) in self.store.get_channel_states(None) {
+            let Some(channel_state) = self.store.get_channel_actor_state(&downstream_channel_id)
+            else {
+                continue;
+            };
+            let Some(tlc) = channel_state
+                .get_forwarding_tlcs()
+                .into_iter()
+                .find(|tlc| tlc.previous_tlc == previous_tlc)
+            else {
+                continue;
+            };
+            return tlc.fail_back_reason().ok_or_else(|| {
+                Error::InvalidParameter(format!(
+                    "Downstream tlc {:?} of channel {:?} is fulfilled or still pending, it can only be failed by the downstream peer",
+                    tlc.tlc_id, downstream_channel_id
+                ))
+            });
+        }
+        Err(Error::InvalidParameter(format!(
+            "Downstream tlc of the received tlc {} of channel {:?} is not found",
+            tlc_id, channel_id
+        )))
+    }
+
+    // The maximal amounts which can be sent through each of our ready channels,
+    // while leaving at least `keep_reserve` of outbound balance in the channel.
+    fn get_first_hop_limits(&self, keep_reserve: u128) -> HashMap<OutPoint, u128> {
+        self.store
+            .get_channel_states(None)
+            .into_iter()
+            .filter(|(_, _, state)| matches!(state, ChannelState::ChannelReady()))
+            .filter_map(|(_, channel_id, _)| {
+                let state = self.store.get_channel_actor_state(&channel_id)?;
+                let available = state
+                    .get_local_balance()
+                    .saturating_sub(state.get_offered_tlc_balance());
+                Some((
+                    state.get_funding_transaction_outpoint(),
+                    available.saturating_sub(keep_reserve),
+                ))
+            })
+            .collect()
+    }
+
     fn on_get_payment(&self, payment_hash: &Hash256) -> Result<SendPaymentResponse, Error> {
         match self.store.get_payment_session(*payment_hash) {
             Some(payment_session) => Ok(payment_session.into()),
@@ -2267,20 +3670,51 @@ where
         let payment_data = payment_session.request.clone();
         let payment_hash = payment_data.payment_hash;
         let mut error = None;
+        let mut failure_reason = PaymentFailureReason::RoutesExhausted;
         while payment_session.can_retry() {
+            if payment_session.is_timed_out() {
+                error = Some(format!("Payment timed out: {:?}", payment_hash));
+                failure_reason = PaymentFailureReason::Timeout;
+                break;
+            }
             payment_session.retried_times += 1;
             let hops_infos = match self.network_graph.read().await.build_route(&payment_data) {
                 Err(e) => {
                     error!("Failed to build route: {:?}", e);
-                    error = Some(format!("Failed to build route: {:?}", payment_hash));
+                    error = Some(format!("Failed to build route: {:?}, {}", payment_hash, e));
+                    payment_session.rejected_route = self
+                        .network_graph
+                        .read()
+                        .await
+                        .find_rejected_route(&payment_data);
                     break;
                 }
                 Ok(onion_path) => onion_path,
             };
+            // Otherwise the onion packet can't be created, or the downstream hops can't peel it.
+            let onion_payload_size = get_onion_payload_size(&hops_infos);
+            if onion_payload_size > state.max_onion_payload_size {
+                error = Some(format!(
+                    "The route of {} hops needs an onion payload of {} bytes, exceeding the maximum {} bytes: {:?}",
+                    hops_infos.len() - 1,
+                    onion_payload_size,
+                    state.max_onion_payload_size,
+                    payment_hash
+                ));
+                failure_reason = PaymentFailureReason::Rejected;
+                break;
+            }
             let first_channel_outpoint = hops_infos[0]
                 .channel_outpoint
                 .clone()
                 .expect("first hop channel outpoint");
+            payment_session.fee = Some(match payment_data.fees_paid_by {
+                FeesPaidBy::Sender => hops_infos[0].amount - payment_data.amount,
+                // The fees are deducted from the amount received by the target.
+                FeesPaidBy::Receiver => {
+                    hops_infos[0].amount - hops_infos.last().expect("target hop").amount
+                }
+            });
 
             // generate session key
             let session_key = Privkey::from_slice(KeyPair::generate_random_key().as_ref());
@@ -2317,13 +3751,23 @@ where
                     payment_session.set_status(PaymentSessionStatus::Inflight);
                     payment_session.set_first_hop_info(first_channel_outpoint, tlc_id);
                     self.store.insert_payment_session(payment_session.clone());
+                    // The deadline is checked again if the attempt is still in flight by then,
+                    // right away if the payment already timed out while the tlc was added.
+                    if let Some(time_to_deadline) = payment_session.time_to_deadline() {
+                        state.network.send_after(time_to_deadline, move || {
+                            NetworkActorMessage::new_command(
+                                NetworkActorCommand::CheckPaymentDeadline(payment_hash),
+                            )
+                        });
+                    }
                     return Ok(payment_session);
                 }
             }
         }
-        payment_session.set_status(PaymentSessionStatus::Failed);
         let final_error = error.expect("expect error details");
-        payment_session.set_failed_status(&final_error);
+        payment_session.set_failed_status(&final_error, failure_reason);
+        metrics().record_payment_failed();
+        metrics().record_payment_latency(false, payment_session.elapsed());
         self.store.insert_payment_session(payment_session);
         return Err(Error::SendPaymentError(final_error));
     }
@@ -2333,17 +3777,41 @@ where
         state: &mut NetworkActorState<S>,
         payment_request: SendPaymentCommand,
     ) -> Result<SendPaymentResponse, Error> {
-        let payment_data = SendPaymentData::new(payment_request.clone(), state.get_public_key())
-            .map_err(|e| {
+        if !state.is_ready_for_payments() {
+            return Err(Error::NodeNotReady(format!(
+                "the node is still starting up, connected peers: {}, graph sync progress: {}%",
+                state.peer_session_map.len(),
+                state.sync_status.progress()
+            )));
+        }
+        let mut payment_data =
+            SendPaymentData::new(payment_request.clone(), state.get_public_key()).map_err(|e| {
                 error!("Failed to validate payment request: {:?}", e);
                 Error::InvalidParameter(format!("Failed to validate payment request: {:?}", e))
             })?;
+        payment_data
+            .timeout
+            .get_or_insert(state.payment_timeout_seconds);
+        payment_data
+            .max_fee_percent
+            .get_or_insert(state.payment_max_fee_percent);
+        if let Some(channel_id) = payment_request.outgoing_channel {
+            payment_data.outgoing_channel = Some(self.get_outgoing_channel_outpoint(channel_id)?);
+        }
+        if let Some(keep_reserve) = payment_data.keep_reserve {
+            payment_data.first_hop_limits = Some(self.get_first_hop_limits(keep_reserve));
+        }
+        self.check_features_supported_by_recipient(&payment_data)
+            .await?;
 
         // initialize the payment session in db and begin the payment process lifecycle
         if let Some(payment_session) = self.store.get_payment_session(payment_data.payment_hash) {
-            // we only allow retrying payment session with status failed
+            // we only allow retrying payment session with status failed, a session with an
+            // unresolved attempt is never retried, or the payment hash may be paid twice
             debug!("Payment session already exists: {:?}", payment_session);
-            if payment_session.status != PaymentSessionStatus::Failed {
+            if payment_session.status != PaymentSessionStatus::Failed
+                || payment_session.has_unresolved_attempt()
+            {
                 return Err(Error::InvalidParameter(format!(
                     "Payment session already exists: {} with payment session status: {:?}",
                     payment_data.payment_hash, payment_session.status
@@ -2353,7 +3821,11 @@ where
 
         let payment_session = PaymentSession::new(payment_data.clone(), 5);
         self.store.insert_payment_session(payment_session.clone());
-        let session = self.try_payment_session(state, payment_session).await?;
+        // Attach the payment hash to all the logs emitted while sending the payment.
+        let session = self
+            .try_payment_session(state, payment_session)
+            .instrument(info_span!("payment", payment_hash = %payment_data.payment_hash))
+            .await?;
         return Ok(session.into());
     }
 }
@@ -2375,7 +3847,13 @@ struct NetworkSyncState {
     // to random peers. If this functionality is desired, we should make a config option for it.
     // Otherwise, remove this completely.
     pinned_syncing_peers: Vec<(PeerId, Multiaddr)>,
+    // The strategy to select the peers to sync with.
+    strategy: GraphSyncPeerStrategy,
     active_syncers: HashMap<PeerId, ActorRef<GraphSyncerMessage>>,
+    // All the peers we have created syncers for, in the order of creation.
+    queried_peers: Vec<PeerId>,
+    // The channels below this block number are already received from peers.
+    synced_height: u64,
     // Number of peers with whom we succeeded to sync.
     succeeded: usize,
     // Number of peers with whom we failed to sync.
@@ -2386,6 +3864,8 @@ impl NetworkSyncState {
     async fn refresh(&self, chain_actor: ActorRef<CkbChainMessage>) -> Self {
         let mut cloned = self.clone();
         cloned.active_syncers.clear();
+        cloned.queried_peers.clear();
+        cloned.synced_height = 0;
         cloned.succeeded = 0;
         cloned.failed = 0;
         // TODO: The calling to chain actor will block the calling actor from handling other messages.
@@ -2414,8 +3894,12 @@ impl NetworkSyncState {
                 // In the second case, if self.failed is larger than the length of pinned_syncing_peers,
                 // then all of pinned sync peers failed to sync. This is because
                 // we will always try to sync with all the pinned syncing peers first.
-                if self.succeeded != 0 {
-                    // TODO: we may want more than one successful syncing.
+                // TODO: we may want more than one successful syncing.
+                // With the preferred strategy, we only sync with one peer at a time.
+                if self.succeeded != 0
+                    || (self.strategy == GraphSyncPeerStrategy::Preferred
+                        && !self.active_syncers.is_empty())
+                {
                     false
                 } else {
                     debug!("Adding peer to dynamic syncing peers list: peer {:?}, succeeded syncing {}, failed syncing {}, pinned syncing peers {}",
@@ -2446,6 +3930,9 @@ impl NetworkSyncState {
             .0;
             self.active_syncers
                 .insert(peer_id.clone(), graph_syncer.clone());
+            if !self.queried_peers.contains(peer_id) {
+                self.queried_peers.push(peer_id.clone());
+            }
             Some(graph_syncer)
         } else {
             None
@@ -2455,6 +3942,23 @@ impl NetworkSyncState {
     fn get_graph_syncer(&self, peer_id: &PeerId) -> Option<&ActorRef<GraphSyncerMessage>> {
         self.active_syncers.get(peer_id)
     }
+
+    // The percentage of the channels within the syncing block range that are received,
+    // the graph syncers start syncing channels from a gap before the starting height.
+    fn progress(&self) -> u64 {
+        let starting_height = self
+            .starting_height
+            .saturating_sub(ASSUME_MAX_CHANNEL_HEIGHT_GAP);
+        let total = (self.ending_height + 1).saturating_sub(starting_height);
+        if total == 0 {
+            return 100;
+        }
+        let synced = self
+            .synced_height
+            .clamp(starting_height, self.ending_height + 1)
+            - starting_height;
+        synced * 100 / total
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -2465,7 +3969,8 @@ enum NetworkSyncStatus {
     Running(NetworkSyncState),
     // Syncing done, unless we restart the node, we don't have to sync again
     // (we will automatically process the newest broadcasted network messages).
-    Done,
+    // The final sync state is kept for inspection.
+    Done(NetworkSyncState),
 }
 
 impl NetworkSyncStatus {
@@ -2475,13 +3980,17 @@ impl NetworkSyncStatus {
         ending_height: u64,
         starting_time: u64,
         pinned_syncing_peers: Vec<(PeerId, Multiaddr)>,
+        strategy: GraphSyncPeerStrategy,
     ) -> Self {
         let state = NetworkSyncState {
             starting_height,
             ending_height,
             starting_time,
             pinned_syncing_peers,
+            strategy,
             active_syncers: Default::default(),
+            queried_peers: Default::default(),
+            synced_height: 0,
             succeeded: 0,
             failed: 0,
         };
@@ -2496,7 +4005,43 @@ impl NetworkSyncStatus {
         match self {
             NetworkSyncStatus::NotRunning(_) => false,
             NetworkSyncStatus::Running(_) => true,
-            NetworkSyncStatus::Done => false,
+            NetworkSyncStatus::Done(_) => false,
+        }
+    }
+
+    fn on_channels_requested(&mut self, starting_height: u64) {
+        if let NetworkSyncStatus::Running(state) = self {
+            state.synced_height = state.synced_height.max(starting_height);
+        }
+    }
+
+    fn on_channels_synced(&mut self) {
+        if let NetworkSyncStatus::Running(state) = self {
+            state.synced_height = state.ending_height + 1;
+        }
+    }
+
+    fn progress(&self) -> u64 {
+        match self {
+            NetworkSyncStatus::Done(_) => 100,
+            NetworkSyncStatus::NotRunning(state) | NetworkSyncStatus::Running(state) => {
+                state.progress()
+            }
+        }
+    }
+
+    fn to_response(&self) -> GraphSyncStatusResponse {
+        let (NetworkSyncStatus::NotRunning(state)
+        | NetworkSyncStatus::Running(state)
+        | NetworkSyncStatus::Done(state)) = self;
+        GraphSyncStatusResponse {
+            status: self.as_str().to_string(),
+            strategy: state.strategy,
+            progress: self.progress(),
+            queried_peers: state.queried_peers.clone(),
+            syncing_peers: state.active_syncers.keys().cloned().collect(),
+            succeeded: state.succeeded,
+            failed: state.failed,
         }
     }
 
@@ -2504,7 +4049,7 @@ impl NetworkSyncStatus {
         match self {
             NetworkSyncStatus::NotRunning(_) => "NotRunning",
             NetworkSyncStatus::Running(_) => "Running",
-            NetworkSyncStatus::Done => "Done",
+            NetworkSyncStatus::Done(_) => "Done",
         }
     }
 }
@@ -2515,6 +4060,176 @@ enum RequestState {
     RequestReturned(u64, bool),
 }
 
+// The number of the tracked peers beyond which the expired gossip windows are cleaned up.
+const MAX_TRACKED_GOSSIP_PEERS: usize = 10000;
+
+// A fixed window rate limiter for the gossip messages received from peers.
+// Excess messages in a window are dropped and counted in the metrics. The window of a peer
+// is kept after it disconnects until the window expires, so that a flooding peer can't reset
+// its window by reconnecting.
+#[derive(Debug)]
+struct GossipRateLimiter {
+    // Maximal number of messages accepted from a peer within one window, 0 means no limit.
+    max_messages: u64,
+    interval: Duration,
+    // The start of the current window and the number of messages accepted within it.
+    windows: HashMap<PeerId, (std::time::Instant, u64)>,
+}
+
+impl GossipRateLimiter {
+    fn new(max_messages: u64, interval: Duration) -> Self {
+        Self {
+            max_messages,
+            interval,
+            windows: Default::default(),
+        }
+    }
+
+    // Returns true if the message from this peer should be processed.
+    fn check(&mut self, peer_id: &PeerId) -> bool {
+        if self.max_messages == 0 {
+            return true;
+        }
+        let now = std::time::Instant::now();
+        if self.windows.len() >= MAX_TRACKED_GOSSIP_PEERS {
+            let interval = self.interval;
+            self.windows
+                .retain(|_, (window_start, _)| now.duration_since(*window_start) < interval);
+        }
+        let (window_start, count) = self.windows.entry(peer_id.clone()).or_insert((now, 0));
+        if now.duration_since(*window_start) >= self.interval {
+            *window_start = now;
+            *count = 0;
+        }
+        if *count < self.max_messages {
+            *count += 1;
+            return true;
+        }
+        metrics().record_gossip_message_dropped();
+        // Only log the first dropped message in a window to avoid log flooding.
+        if *count == self.max_messages {
+            *count += 1;
+            warn!(
+                "Peer {:?} exceeded gossip rate limit ({} messages in {:?}), dropping messages",
+                peer_id, self.max_messages, self.interval
+            );
+        }
+        false
+    }
+}
+
+// The maximal backoff of an IP address which repeatedly exceeds the connection rate limit.
+const MAX_CONNECTION_BACKOFF: Duration = Duration::from_secs(3600);
+
+// The number of the tracked IP addresses beyond which the expired windows are cleaned up, at most
+// once per window interval.
+const MAX_TRACKED_CONNECTION_ADDRESSES: usize = 10000;
+
+#[derive(Debug)]
+struct ConnectionWindow {
+    start: std::time::Instant,
+    count: u64,
+    // Whether the limit is exceeded within the current window.
+    exceeded: bool,
+    banned_until: Option<std::time::Instant>,
+    backoff: Duration,
+}
+
+// A fixed window rate limiter for the inbound connections from each IP address.
+// An address exceeding the limit is rejected for a backoff period, which starts from the
+// window interval and doubles on each following violation, it's reset after a window
+// without violation.
+#[derive(Debug)]
+pub(crate) struct ConnectionRateLimiter {
+    // Maximal number of connections accepted from an address within one window, 0 means no limit.
+    max_connections: u64,
+    interval: Duration,
+    windows: HashMap<IpAddr, ConnectionWindow>,
+    // The time when the expired windows were last cleaned up.
+    cleaned_up_at: Option<std::time::Instant>,
+}
+
+impl ConnectionRateLimiter {
+    pub(crate) fn new(max_connections: u64, interval: Duration) -> Self {
+        Self {
+            max_connections,
+            interval,
+            windows: Default::default(),
+            cleaned_up_at: None,
+        }
+    }
+
+    // Returns true if the connection from this address should be accepted.
+    fn check(&mut self, ip: IpAddr) -> bool {
+        self.check_at(ip, std::time::Instant::now())
+    }
+
+    // Returns true if the connection from this address at the given time should be accepted.
+    pub(crate) fn check_at(&mut self, ip: IpAddr, now: std::time::Instant) -> bool {
+        if self.max_connections == 0 {
+            return true;
+        }
+        if self.windows.len() >= MAX_TRACKED_CONNECTION_ADDRESSES
+            && self
+                .cleaned_up_at
+                .map_or(true, |at| now.duration_since(at) >= self.interval)
+        {
+            let interval = self.interval;
+            self.windows.retain(|_, window| {
+                now.duration_since(window.start) < interval
+                    || window.banned_until.map_or(false, |until| now < until)
+            });
+            self.cleaned_up_at = Some(now);
+        }
+        let window = self.windows.entry(ip).or_insert(ConnectionWindow {
+            start: now,
+            count: 0,
+            exceeded: false,
+            banned_until: None,
+            backoff: Duration::ZERO,
+        });
+        if let Some(until) = window.banned_until {
+            if now < until {
+                return false;
+            }
+            window.banned_until = None;
+        }
+        if now.duration_since(window.start) >= self.interval {
+            if !window.exceeded {
+                window.backoff = Duration::ZERO;
+            }
+            window.start = now;
+            window.count = 0;
+            window.exceeded = false;
+        }
+        if window.count < self.max_connections {
+            window.count += 1;
+            return true;
+        }
+        window.exceeded = true;
+        window.backoff = if window.backoff.is_zero() {
+            self.interval
+        } else {
+            (window.backoff * 2).min(MAX_CONNECTION_BACKOFF)
+        };
+        window.banned_until = Some(now + window.backoff);
+        warn!(
+            "Address {} exceeded connection rate limit ({} connections in {:?}), rejecting connections for {:?}",
+            ip, self.max_connections, self.interval, window.backoff
+        );
+        false
+    }
+}
+
+// The IP address of a peer, the IPv4-mapped IPv6 addresses are converted to IPv4.
+fn get_ip_address(address: &Multiaddr) -> Option<IpAddr> {
+    address.iter().find_map(|component| match component {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4)),
+        _ => None,
+    })
+}
+
 pub struct NetworkActorState<S> {
     store: S,
     state_to_be_persisted: PersistentNetworkActorState,
@@ -2549,6 +4264,11 @@ pub struct NetworkActorState<S> {
     to_be_accepted_channels: HashMap<Hash256, (PeerId, OpenChannel)>,
     // Channels in this hashmap are pending for funding transaction confirmation.
     pending_channels: HashMap<OutPoint, Hash256>,
+    // The final channel ids of the channels opened in this run, indexed by the temporary channel ids.
+    temp_channel_ids: HashMap<Hash256, Hash256>,
+    // The errors encountered while funding the channels, the errors of the channel actors are
+    // kept in the channel states.
+    channel_funding_errors: HashMap<Hash256, String>,
     // Used to broadcast and query network info.
     chain_actor: ActorRef<CkbChainMessage>,
     // If the other party funding more than this amount, we will automatically accept the channel.
@@ -2562,6 +4282,63 @@ pub struct NetworkActorState<S> {
     tlc_max_value: u128,
     // The default tlc fee proportional millionths to be used when auto accepting a channel.
     tlc_fee_proportional_millionths: u128,
+    // The default timeout of a payment in seconds, if it is not specified in the payment request.
+    payment_timeout_seconds: u64,
+    // The default maximal fee of a payment route in percentage of the amount, 0 means no limit.
+    payment_max_fee_percent: u64,
+    // The timeout in blocks to fail back the forwarded tlcs not resolved by the downstream, 0 means disabled.
+    tlc_resolution_timeout_blocks: u64,
+    // The age in seconds after which the finished payment sessions are pruned, 0 means never pruning.
+    payment_session_retention_seconds: u64,
+    // The number of the most recent payment sessions which are never pruned.
+    payment_session_retention_count: u64,
+    // The age in seconds after which the archives of the closed channels are pruned, 0 means never pruning.
+    closed_channel_retention_seconds: u64,
+    // The interval in seconds to persist the network graph snapshot, 0 means disabled.
+    graph_snapshot_interval_seconds: u64,
+    // Whether to remove the invoice preimages from the store once the tlcs are settled.
+    forget_settled_preimages: bool,
+    // The tolerance percentage to accept a counter-proposed shutdown fee rate, if the max fee rate is not specified.
+    shutdown_fee_rate_tolerance_percent: u64,
+    // The minimal shutdown fee rate accepted when the remote peer initiates the shutdown.
+    min_shutdown_fee_rate: u64,
+    // The maximal funding fee rate accepted from the inbound opens.
+    max_accepted_funding_fee_rate: u64,
+    // The maximal number of the events kept in the event log of a channel, 0 means disabled.
+    channel_event_log_max_size: u64,
+    // Whether to force close a channel if its state diverges from the peer on reestablish.
+    reestablish_force_close_on_divergence: bool,
+    // Whether to audit a channel on each commitment update and halt it on any inconsistency.
+    audit_commitment_updates: bool,
+    // The delay before failing back the received tlcs, to resist balance probing.
+    tlc_fail_delay: TlcFailDelay,
+    // The random delay before fulfilling the forwarded tlcs, to hide the timing of the settlements.
+    tlc_fulfill_delay: TlcFulfillDelay,
+    // The dust tlc limit of the CKB channels which don't set their own limit.
+    default_dust_tlc_limit: DustTlcLimit,
+    // The delay before gossiping the channel update changed by the update command, in milliseconds.
+    channel_update_broadcast_delay_ms: u64,
+    // The tip block number of the chain, shared with the channel actors.
+    chain_tip: ChainTip,
+    // The maximal size of the onion payload taken by the hops of a payment route.
+    max_onion_payload_size: usize,
+    // The policy to decide whether to accept the channels opened by remote peers.
+    channel_acceptance_policy: ChannelAcceptancePolicy,
+    // The hash algorithms supported by the channels of this node.
+    hash_algorithms: Vec<HashAlgorithm>,
+    // The onion packet versions supported by the channels of this node.
+    onion_versions: Vec<u8>,
+    // The fixed fee rates to suggest, the fee rates are estimated from recent blocks if it's not set.
+    suggested_fee_rates: Option<SuggestedFeeRates>,
+    // The recently rejected inbound tlcs, the oldest ones are dropped once the capacity is reached.
+    rejected_tlcs: VecDeque<RejectedTlc>,
+    rejected_tlcs_capacity: usize,
+    // The timeout in seconds to abort the channel opens whose funding transactions are not signed yet,
+    // used when the timeout is not specified in the open channel request, 0 means never aborting.
+    open_channel_timeout_seconds: u64,
+    // The peers whose funding transactions are treated as confirmed after the trusted funding confirmations.
+    trusted_funding_peers: Vec<PeerId>,
+    trusted_funding_confirmations: u64,
     // A hashset to store the list of all broadcasted messages.
     // This is used to avoid re-broadcasting the same message over and over again
     // TODO: some more intelligent way to manage broadcasting.
@@ -2588,6 +4365,26 @@ pub struct NetworkActorState<S> {
     // A queue of messages that are received while we are syncing network messages.
     // Need to be processed after the sync is done.
     broadcasted_message_queue: Vec<(PeerId, FiberBroadcastMessage)>,
+    // Rate limiter for the broadcasted messages received from peers.
+    gossip_rate_limiter: GossipRateLimiter,
+    // The maximal tolerated skew of the gossip message timestamps ahead of the local time, 0 means no limit.
+    gossip_max_future_skew: Duration,
+    // The maximal number of connected peers beyond which the inbound connections are rejected, 0 means no limit.
+    max_peers: u64,
+    // Rate limiter for the inbound connections from each IP address.
+    connection_rate_limiter: ConnectionRateLimiter,
+    // The connected peers which connected to us.
+    inbound_peers: HashSet<PeerId>,
+    // The number of the inbound connections rejected by the connection limits.
+    rejected_connections: u64,
+    // The graph sync progress percentage to reach before sending payments, 0 means not gating on it.
+    payment_ready_min_graph_sync_progress: u64,
+    // The number of the connected peers to reach before sending payments, 0 means not gating on it.
+    payment_ready_min_peers: u64,
+    // Whether the node has been ready to send payments, the readiness is kept once reached.
+    ready_for_payments: bool,
+    // The last time the announcement of a channel was rebroadcasted by request, used to rate limit it.
+    channel_announcement_rebroadcasts: HashMap<Hash256, std::time::Instant>,
 }
 
 #[serde_as]
@@ -2624,10 +4421,18 @@ impl PersistentNetworkActorState {
             .collect::<HashSet<_, RandomState>>()
     }
 
-    /// Save a single peer address to the peer store. If this address for the peer does not exist,
-    /// then return false, otherwise return true.
-    fn save_peer_address(&mut self, peer_id: PeerId, addr: Multiaddr) -> bool {
-        match self.saved_peer_addresses.entry(peer_id) {
+    /// Save a single peer address to the peer store, the address is normalized first so that the
+    /// same address in different forms is saved only once. Return true if the address is newly
+    /// saved, false if it exists, or an error if the address is invalid.
+    fn save_peer_address(&mut self, peer_id: PeerId, addr: Multiaddr) -> Result<bool, Error> {
+        let addr = normalize_peer_address(&addr)?;
+        if extract_peer_id(&addr).as_ref() != Some(&peer_id) {
+            return Err(Error::InvalidParameter(format!(
+                "The peer id of address {} is not {:?}",
+                addr, peer_id
+            )));
+        }
+        Ok(match self.saved_peer_addresses.entry(peer_id) {
             Entry::Occupied(mut entry) => {
                 if entry.get().contains(&addr) {
                     false
@@ -2640,7 +4445,7 @@ impl PersistentNetworkActorState {
                 entry.insert(vec![addr]);
                 true
             }
-        }
+        })
     }
 
     /// Save announced peer addresses to the peer store. If the peer addresses are updated,
@@ -2683,11 +4488,138 @@ impl PersistentNetworkActorState {
     }
 }
 
+// Set the component of a peer address, a repeated component is redundant and dropped,
+// but a component with different values is ambiguous and rejected.
+fn set_address_component(
+    component: &mut Option<Protocol<'static>>,
+    value: Protocol<'static>,
+    address: &Multiaddr,
+) -> Result<(), Error> {
+    match component {
+        Some(existing) if *existing != value => Err(Error::InvalidParameter(format!(
+            "The peer address {} has conflicting components {} and {}",
+            address, existing, value
+        ))),
+        _ => {
+            *component = Some(value);
+            Ok(())
+        }
+    }
+}
+
+/// Normalize a peer address to the canonical form
+/// `/<host>/tcp/<port>[/tls/<domain>][/ws|/wss]/p2p/<peer id>` (or `/memory/<port>/p2p/<peer id>`).
+/// The repeated components are dropped, an IPv4-mapped IPv6 address is converted to IPv4,
+/// and the DNS name is lowercased. The address which can't be dialed is rejected, e.g. it has
+/// no host, port or peer id, or its host is a multicast IP.
+pub(crate) fn normalize_peer_address(address: &Multiaddr) -> Result<Multiaddr, Error> {
+    let invalid = |reason: &str| {
+        Error::InvalidParameter(format!("Invalid peer address {}: {}", address, reason))
+    };
+    let (mut host, mut port, mut tls, mut websocket, mut peer_id, mut memory) =
+        (None, None, None, None, None, None);
+    for component in address.iter() {
+        let component = component.acquire();
+        let slot = match &component {
+            Protocol::Ip4(_) | Protocol::Ip6(_) | Protocol::Dns4(_) | Protocol::Dns6(_) => {
+                &mut host
+            }
+            Protocol::Tcp(_) => &mut port,
+            Protocol::Tls(_) => &mut tls,
+            Protocol::Ws | Protocol::Wss => &mut websocket,
+            Protocol::P2P(_) => &mut peer_id,
+            Protocol::Memory(_) => &mut memory,
+        };
+        let component = match component {
+            Protocol::Ip6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => Protocol::Ip4(ip),
+                None => Protocol::Ip6(ip),
+            },
+            Protocol::Dns4(name) => {
+                Protocol::Dns4(Cow::Owned(name.trim_end_matches('.').to_lowercase()))
+            }
+            Protocol::Dns6(name) => {
+                Protocol::Dns6(Cow::Owned(name.trim_end_matches('.').to_lowercase()))
+            }
+            component => component,
+        };
+        set_address_component(slot, component, address)?;
+    }
+    let Some(peer_id) = peer_id else {
+        return Err(invalid("no peer id"));
+    };
+    if let Some(memory) = memory {
+        if host.is_some() || port.is_some() || tls.is_some() || websocket.is_some() {
+            return Err(invalid(
+                "the memory transport can't be combined with others",
+            ));
+        }
+        return Ok(Multiaddr::from_iter([memory, peer_id]));
+    }
+    match &host {
+        None => return Err(invalid("no host")),
+        Some(Protocol::Ip4(ip)) if ip.is_multicast() => {
+            return Err(invalid("the host is not dialable"))
+        }
+        Some(Protocol::Ip6(ip)) if ip.is_multicast() => {
+            return Err(invalid("the host is not dialable"))
+        }
+        Some(Protocol::Dns4(name)) | Some(Protocol::Dns6(name)) if name.is_empty() => {
+            return Err(invalid("the host name is empty"))
+        }
+        _ => {}
+    }
+    match &port {
+        None => return Err(invalid("no tcp port")),
+        Some(Protocol::Tcp(0)) => return Err(invalid("the tcp port is 0")),
+        _ => {}
+    }
+    Ok([host, port, tls, websocket, Some(peer_id)]
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
 pub trait NetworkActorStateStore {
     fn get_network_actor_state(&self, id: &PeerId) -> Option<PersistentNetworkActorState>;
     fn insert_network_actor_state(&self, id: &PeerId, state: PersistentNetworkActorState);
 }
 
+// Reject opening a channel which has no usable balance once it's open. The reserved CKB amount
+// must cover a reserve of twice the commitment transaction fee, which is also required by the
+// channel when it's created, and the funding amount must leave some balance after the reserved
+// amount and the fee reserve. For an UDT channel, the funding amount is the UDT amount.
+pub(crate) fn check_open_channel_usable_balance(
+    funding_amount: u128,
+    reserved_ckb_amount: u64,
+    commitment_fee_rate: u64,
+    udt_type_script: &Option<Script>,
+) -> Result<(), ProcessingChannelError> {
+    let commitment_fee_reserve =
+        calculate_commitment_tx_fee(commitment_fee_rate, udt_type_script) * 2;
+    if reserved_ckb_amount < commitment_fee_reserve {
+        return Err(ProcessingChannelError::InvalidParameter(format!(
+            "The commitment fee reserve {} at the commitment fee rate {} exceeds the reserved CKB amount {}, \
+            please set a lower commitment fee rate",
+            commitment_fee_reserve, commitment_fee_rate, reserved_ckb_amount
+        )));
+    }
+    let (minimal_funding_amount, unusable_amount) = if udt_type_script.is_some() {
+        (1, 0)
+    } else {
+        let unusable_amount = reserved_ckb_amount as u128 + commitment_fee_reserve as u128;
+        (unusable_amount + 1, unusable_amount)
+    };
+    if funding_amount < minimal_funding_amount {
+        return Err(ProcessingChannelError::InvalidParameter(format!(
+            "The funding amount {} leaves no usable balance after the reserved amount and the commitment fee reserve ({} in total) \
+            at the commitment fee rate {}, the minimal viable funding amount is {}",
+            funding_amount, unusable_amount, commitment_fee_rate, minimal_funding_amount
+        )));
+    }
+    Ok(())
+}
+
 static CHANNEL_ACTOR_NAME_PREFIX: AtomicU64 = AtomicU64::new(0u64);
 
 // ractor requires that the actor name is unique, so we add a prefix to the actor name.
@@ -2711,6 +4643,37 @@ where
         + Sync
         + 'static,
 {
+    // A freshly started node may not know enough of the network to route payments, so payments
+    // are rejected until the graph sync progress or the connected peers reach the configured
+    // minimum. Once ready, the node stays ready even if the peers disconnect later.
+    pub fn is_ready_for_payments(&mut self) -> bool {
+        if !self.ready_for_payments {
+            let min_progress = self.payment_ready_min_graph_sync_progress;
+            let min_peers = self.payment_ready_min_peers;
+            self.ready_for_payments = (min_progress == 0 && min_peers == 0)
+                || (min_progress > 0 && self.sync_status.progress() >= min_progress)
+                || (min_peers > 0 && self.peer_session_map.len() as u64 >= min_peers);
+        }
+        self.ready_for_payments
+    }
+
+    // Message versions act as timestamps, a newer version replaces the older one. A message from
+    // the far future would shadow all the legitimate updates until then, so it is dropped.
+    fn is_gossip_message_too_far_in_future(&self, message: &FiberBroadcastMessage) -> bool {
+        if self.gossip_max_future_skew.is_zero() {
+            return false;
+        }
+        let now = std::time::UNIX_EPOCH.elapsed().unwrap();
+        if message.is_too_far_in_future(now, self.gossip_max_future_skew) {
+            warn!(
+                "Dropping broadcasted message too far in the future (max skew {:?}): {:?}",
+                self.gossip_max_future_skew, message
+            );
+            return true;
+        }
+        false
+    }
+
     pub fn get_or_create_new_node_announcement_message(&mut self) -> NodeAnnouncement {
         let now = std::time::UNIX_EPOCH.elapsed().unwrap().as_millis() as u64;
         match self.last_node_announcement_message {
@@ -2725,13 +4688,16 @@ where
             _ => {
                 let alias = self.node_name.unwrap_or_default();
                 let addresses = self.announced_addrs.clone();
-                let announcement = NodeAnnouncement::new(
+                let mut announcement = NodeAnnouncement::new_unsigned(
                     alias,
                     addresses,
-                    &self.private_key,
+                    self.get_public_key(),
                     now,
                     self.open_channel_auto_accept_min_ckb_funding_amount,
                 );
+                announcement.features = node_features(&self.hash_algorithms, &self.onion_versions);
+                announcement.signature =
+                    Some(self.private_key.sign(announcement.message_to_sign()));
                 debug!(
                     "Created new node announcement message: {:?}, previous {:?}",
                     &announcement, self.last_node_announcement_message
@@ -2849,6 +4815,7 @@ where
     pub async fn create_outbound_channel(
         &mut self,
         open_channel: OpenChannelCommand,
+        remote_features: Option<u64>,
     ) -> Result<(ActorRef<ChannelActorMessage>, Hash256), ProcessingChannelError> {
         let store = self.store.clone();
         let network = self.network.clone();
@@ -2867,7 +4834,18 @@ where
             tlc_fee_proportional_millionths,
             max_tlc_value_in_flight,
             max_tlc_number_in_flight,
+            max_inbound_tlc_value,
+            max_outbound_tlc_value,
+            external_funding_tx,
+            open_timeout_seconds,
+            zero_conf,
         } = open_channel;
+        let zero_conf = zero_conf.unwrap_or_default();
+        if zero_conf && public {
+            return Err(ProcessingChannelError::InvalidParameter(
+                "Zero-conf is only supported by private channels".to_string(),
+            ));
+        }
         let remote_pubkey =
             self.get_peer_pubkey(&peer_id)
                 .ok_or(ProcessingChannelError::InvalidParameter(format!(
@@ -2882,9 +4860,24 @@ where
             }
         }
         // NOTE: here we only check the amount is valid, we will also check more in the `pre_start` from channel creation
-        let (_funding_amount, _reserved_ckb_amount) =
+        let (_funding_amount, reserved_ckb_amount) =
             self.get_funding_and_reserved_amount(funding_amount, &funding_udt_type_script)?;
+        check_open_channel_usable_balance(
+            funding_amount,
+            reserved_ckb_amount,
+            commitment_fee_rate.unwrap_or(DEFAULT_COMMITMENT_FEE_RATE),
+            &funding_udt_type_script,
+        )?;
+        if let Some(external_funding_tx) = external_funding_tx.as_ref() {
+            self.submit_external_funding_tx(
+                external_funding_tx,
+                funding_amount,
+                &funding_udt_type_script,
+            )
+            .await?;
+        }
 
+        let peer_fee_policy = self.store.get_peer_fee_policy(&peer_id).unwrap_or_default();
         let seed = self.generate_channel_seed();
         let (tx, rx) = oneshot::channel::<Hash256>();
         let channel = Actor::spawn_linked(
@@ -2895,15 +4888,33 @@ where
                 network.clone(),
                 store,
                 self.channel_subscribers.clone(),
+                self.forget_settled_preimages,
+                self.min_shutdown_fee_rate,
+                self.channel_event_log_max_size,
+                self.reestablish_force_close_on_divergence,
+                self.audit_commitment_updates,
+                self.tlc_fail_delay,
+                self.tlc_fulfill_delay,
+                self.default_dust_tlc_limit,
+                self.channel_update_broadcast_delay_ms,
+                self.chain_tip.clone(),
             ),
             ChannelInitializationParameter::OpenChannel(OpenChannelParameter {
                 funding_amount,
                 seed,
                 public_channel_info: public.then_some(PublicChannelInfo::new(
-                    tlc_locktime_expiry_delta.unwrap_or(self.tlc_locktime_expiry_delta),
-                    tlc_min_value.unwrap_or(self.tlc_min_value),
-                    tlc_max_value.unwrap_or(self.tlc_max_value),
-                    tlc_fee_proportional_millionths.unwrap_or(self.tlc_fee_proportional_millionths),
+                    tlc_locktime_expiry_delta
+                        .or(peer_fee_policy.tlc_locktime_expiry_delta)
+                        .unwrap_or(self.tlc_locktime_expiry_delta),
+                    tlc_min_value
+                        .or(peer_fee_policy.tlc_min_value)
+                        .unwrap_or(self.tlc_min_value),
+                    tlc_max_value
+                        .or(peer_fee_policy.tlc_max_value)
+                        .unwrap_or(self.tlc_max_value),
+                    tlc_fee_proportional_millionths
+                        .or(peer_fee_policy.tlc_fee_proportional_millionths)
+                        .unwrap_or(self.tlc_fee_proportional_millionths),
                 )),
                 funding_udt_type_script,
                 shutdown_script: shutdown_script
@@ -2914,6 +4925,15 @@ where
                 funding_fee_rate,
                 max_tlc_value_in_flight,
                 max_tlc_number_in_flight,
+                max_inbound_tlc_value,
+                max_outbound_tlc_value,
+                external_funding_tx,
+                supported_hash_algorithms: self.hash_algorithms.clone(),
+                supported_onion_versions: self.onion_versions.clone(),
+                remote_features,
+                open_timeout_seconds: open_timeout_seconds
+                    .unwrap_or(self.open_channel_timeout_seconds),
+                zero_conf,
             }),
             network.clone().get_cell(),
         )
@@ -2927,12 +4947,16 @@ where
     pub async fn create_inbound_channel(
         &mut self,
         accept_channel: AcceptChannelCommand,
+        remote_features: Option<u64>,
     ) -> Result<(ActorRef<ChannelActorMessage>, Hash256, Hash256), ProcessingChannelError> {
         let store = self.store.clone();
         let AcceptChannelCommand {
             temp_channel_id,
             funding_amount,
             shutdown_script,
+            max_inbound_tlc_value,
+            max_outbound_tlc_value,
+            zero_conf,
         } = accept_channel;
 
         let (peer_id, open_channel) = self
@@ -2962,6 +4986,7 @@ where
             return Ok((channel.clone(), temp_channel_id, id));
         }
 
+        let peer_fee_policy = self.store.get_peer_fee_policy(&peer_id).unwrap_or_default();
         let seed = self.generate_channel_seed();
         let (tx, rx) = oneshot::channel::<Hash256>();
         let channel = Actor::spawn_linked(
@@ -2972,21 +4997,42 @@ where
                 network.clone(),
                 store,
                 self.channel_subscribers.clone(),
+                self.forget_settled_preimages,
+                self.min_shutdown_fee_rate,
+                self.channel_event_log_max_size,
+                self.reestablish_force_close_on_divergence,
+                self.audit_commitment_updates,
+                self.tlc_fail_delay,
+                self.tlc_fulfill_delay,
+                self.default_dust_tlc_limit,
+                self.channel_update_broadcast_delay_ms,
+                self.chain_tip.clone(),
             ),
             ChannelInitializationParameter::AcceptChannel(AcceptChannelParameter {
                 funding_amount,
                 reserved_ckb_amount,
                 public_channel_info: open_channel.is_public().then_some(PublicChannelInfo::new(
-                    self.tlc_locktime_expiry_delta,
-                    self.tlc_min_value,
-                    self.tlc_max_value,
-                    self.tlc_fee_proportional_millionths,
+                    peer_fee_policy
+                        .tlc_locktime_expiry_delta
+                        .unwrap_or(self.tlc_locktime_expiry_delta),
+                    peer_fee_policy.tlc_min_value.unwrap_or(self.tlc_min_value),
+                    peer_fee_policy.tlc_max_value.unwrap_or(self.tlc_max_value),
+                    peer_fee_policy
+                        .tlc_fee_proportional_millionths
+                        .unwrap_or(self.tlc_fee_proportional_millionths),
                 )),
                 seed,
                 open_channel,
                 shutdown_script: shutdown_script
                     .unwrap_or_else(|| self.default_shutdown_script.clone()),
                 channel_id_sender: Some(tx),
+                max_inbound_tlc_value,
+                max_outbound_tlc_value,
+                supported_hash_algorithms: self.hash_algorithms.clone(),
+                supported_onion_versions: self.onion_versions.clone(),
+                remote_features,
+                open_timeout_seconds: self.open_channel_timeout_seconds,
+                zero_conf: zero_conf.unwrap_or_default(),
             }),
             network.clone().get_cell(),
         )
@@ -2994,14 +5040,19 @@ where
         .0;
         let new_id = rx.await.expect("msg received");
         self.on_channel_created(new_id, &peer_id, channel.clone());
+        self.temp_channel_ids.insert(temp_channel_id, new_id);
         Ok((channel, temp_channel_id, new_id))
     }
 
-    // This function send the transaction to the network and then trace the transaction status.
-    // Either the sending or the tracing may fail, in which case the callback will be called with
-    // the error.
-    async fn broadcast_tx_with_callback<F>(&self, transaction: TransactionView, callback: F)
-    where
+    // This function send the transaction to the network and then trace the transaction status
+    // until it reaches the given confirmations. Either the sending or the tracing may fail,
+    // in which case the callback will be called with the error.
+    async fn broadcast_tx_with_callback<F>(
+        &self,
+        transaction: TransactionView,
+        confirmations: u64,
+        callback: F,
+    ) where
         F: Send + 'static + FnOnce(Result<TraceTxResponse, RactorErr<CkbChainMessage>>),
     {
         let chain = self.chain_actor.clone();
@@ -3033,11 +5084,9 @@ where
                 }
                 Ok(_) => {
                     let tx_hash = transaction.hash();
-                    // TODO: make number of confirmation to transaction configurable.
-                    const NUM_CONFIRMATIONS: u64 = 4;
                     let request = TraceTxRequest {
                         tx_hash: tx_hash.clone(),
-                        confirmations: NUM_CONFIRMATIONS,
+                        confirmations,
                     };
                     debug!(
                         "Transaction sent to the network, waiting for it to be confirmed: {:?}",
@@ -3057,6 +5106,33 @@ where
         });
     }
 
+    // This function only traces the transaction status, without sending the transaction
+    // to the network. It is used for transactions which were already broadcasted.
+    async fn trace_tx_with_callback<F>(&self, tx_hash: Byte32, confirmations: u64, callback: F)
+    where
+        F: Send + 'static + FnOnce(Result<TraceTxResponse, RactorErr<CkbChainMessage>>),
+    {
+        let chain = self.chain_actor.clone();
+        ractor::concurrency::tokio_primatives::spawn(async move {
+            let request = TraceTxRequest {
+                tx_hash,
+                confirmations,
+            };
+            debug!(
+                "Waiting for transaction to be confirmed: {:?}",
+                &request.tx_hash
+            );
+            let result = call_t!(
+                chain,
+                CkbChainMessage::TraceTx,
+                DEFAULT_CHAIN_ACTOR_TIMEOUT,
+                request
+            );
+            debug!("Transaction trace result: {:?}", &result);
+            callback(result);
+        });
+    }
+
     fn get_peer_session(&self, peer_id: &PeerId) -> Option<SessionId> {
         self.peer_session_map.get(peer_id).cloned()
     }
@@ -3082,6 +5158,40 @@ where
         self.peer_pubkey_map.get(peer_id).cloned()
     }
 
+    // Verify the external funding tx pays enough to the funding lock script of this node, and
+    // send it to the chain, so that its outputs can be spent by the funding transaction.
+    async fn submit_external_funding_tx(
+        &self,
+        external_funding_tx: &Transaction,
+        funding_amount: u128,
+        udt_type_script: &Option<Script>,
+    ) -> Result<(), ProcessingChannelError> {
+        if udt_type_script.is_some() {
+            return Err(ProcessingChannelError::InvalidParameter(
+                "External funding tx is not supported for UDT channels".to_string(),
+            ));
+        }
+        verify_external_funding_tx(
+            external_funding_tx,
+            &self.default_shutdown_script,
+            funding_amount as u64,
+        )
+        .map_err(|err| ProcessingChannelError::InvalidParameter(err.to_string()))?;
+        call_t!(
+            &self.chain_actor,
+            CkbChainMessage::SendTx,
+            DEFAULT_CHAIN_ACTOR_TIMEOUT,
+            external_funding_tx.clone().into_view()
+        )
+        .expect(ASSUME_CHAIN_ACTOR_ALWAYS_ALIVE_FOR_NOW)
+        .map_err(|err| {
+            ProcessingChannelError::InvalidParameter(format!(
+                "Failed to send the external funding tx: {}",
+                err
+            ))
+        })
+    }
+
     fn get_funding_and_reserved_amount(
         &self,
         funding_amount: u128,
@@ -3158,6 +5268,14 @@ where
             )));
         }
 
+        // The acceptor pays a share of the funding fee if it contributes to the funding.
+        if open_channel.funding_fee_rate > self.max_accepted_funding_fee_rate {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Funding fee rate {} is greater than the maximal accepted funding fee rate {}",
+                open_channel.funding_fee_rate, self.max_accepted_funding_fee_rate,
+            )));
+        }
+
         if open_channel.commitment_fee_rate < DEFAULT_COMMITMENT_FEE_RATE {
             return Err(ProcessingChannelError::InvalidParameter(format!(
                 "Commitment fee rate is less than {}",
@@ -3218,41 +5336,15 @@ where
             ChannelCommand::Shutdown(shutdown, rpc_reply) if shutdown.force => {
                 match self.store.get_channel_actor_state(&channel_id) {
                     Some(mut state) => {
-                        match state.state {
-                            ChannelState::ChannelReady() => {
-                                debug!("Handling force shutdown command in ChannelReady state");
-                            }
-                            ChannelState::ShuttingDown(flags) => {
-                                debug!("Handling force shutdown command in ShuttingDown state, flags: {:?}", &flags);
-                            }
-                            _ => {
-                                let error = Error::ChannelError(
-                                    ProcessingChannelError::InvalidState(format!(
-                                        "Handling force shutdown command invalid state {:?}",
-                                        &state.state
-                                    )),
-                                );
-
-                                let _ = rpc_reply.send(Err(error.to_string()));
-                                return Err(error);
-                            }
-                        };
-
-                        // when channel is in ChannelReady or ShuttingDown state, the latest_commitment_transaction should exist
-                        let transaction = state.latest_commitment_transaction.clone().unwrap();
-                        self.network
-                            .send_message(NetworkActorMessage::new_event(
-                                NetworkActorEvent::CommitmentTransactionPending(
-                                    transaction,
-                                    channel_id,
-                                ),
-                            ))
-                            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
-
-                        state.update_state(ChannelState::ShuttingDown(
-                            ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION,
-                        ));
-                        self.store.insert_channel_actor_state(state);
+                        if let Err(error) = state.force_shutdown(&self.network) {
+                            let error = Error::ChannelError(error);
+                            let _ = rpc_reply.send(Err(error.to_string()));
+                            return Err(error);
+                        }
+                        if let Err(error) = self.store.insert_channel_actor_state(state) {
+                            let _ = rpc_reply.send(Err(error.to_string()));
+                            return Err(error.into());
+                        }
 
                         let _ = rpc_reply.send(Ok(()));
                         Ok(())
@@ -3260,6 +5352,25 @@ where
                     None => Err(Error::ChannelNotFound(channel_id)),
                 }
             }
+            ChannelCommand::Shutdown(mut shutdown, rpc_reply) => {
+                match self.channels.get(&channel_id) {
+                    Some(actor) => {
+                        // Accept a counter-proposed fee rate within the tolerance if the max fee rate is not specified.
+                        let fee_rate = shutdown.fee_rate.as_u64();
+                        shutdown.max_fee_rate.get_or_insert(FeeRate::from_u64(
+                            fee_rate.saturating_add(
+                                fee_rate.saturating_mul(self.shutdown_fee_rate_tolerance_percent)
+                                    / 100,
+                            ),
+                        ));
+                        actor.send_message(ChannelActorMessage::Command(
+                            ChannelCommand::Shutdown(shutdown, rpc_reply),
+                        ))?;
+                        Ok(())
+                    }
+                    None => Err(Error::ChannelNotFound(channel_id)),
+                }
+            }
             _ => match self.channels.get(&channel_id) {
                 Some(actor) => {
                     actor.send_message(ChannelActorMessage::Command(command))?;
@@ -3282,6 +5393,15 @@ where
             );
             return Ok(actor.clone());
         }
+        if let Some(halted) = self
+            .store
+            .get_channel_actor_state(&channel_id)
+            .and_then(|state| state.halted)
+        {
+            return Err(Error::ChannelError(ProcessingChannelError::InvalidState(
+                format!("Channel {:x} is halted: {:?}", &channel_id, halted),
+            )));
+        }
         let remote_pubkey =
             self.get_peer_pubkey(peer_id)
                 .ok_or(ProcessingChannelError::InvalidState(format!(
@@ -3298,6 +5418,16 @@ where
                 self.network.clone(),
                 self.store.clone(),
                 self.channel_subscribers.clone(),
+                self.forget_settled_preimages,
+                self.min_shutdown_fee_rate,
+                self.channel_event_log_max_size,
+                self.reestablish_force_close_on_divergence,
+                self.audit_commitment_updates,
+                self.tlc_fail_delay,
+                self.tlc_fulfill_delay,
+                self.default_dust_tlc_limit,
+                self.channel_update_broadcast_delay_ms,
+                self.chain_tip.clone(),
             ),
             ChannelInitializationParameter::ReestablishChannel(channel_id),
             self.network.get_cell(),
@@ -3308,6 +5438,32 @@ where
         Ok(channel)
     }
 
+    // Only the inbound connections are limited, the peers with active channels are always
+    // accepted so that the channels can be reestablished.
+    fn check_connection_limits(
+        &mut self,
+        peer_id: &PeerId,
+        session: &SessionContext,
+    ) -> Result<(), String> {
+        if !session.ty.is_inbound()
+            || !self
+                .store
+                .get_active_channel_ids_by_peer(peer_id)
+                .is_empty()
+        {
+            return Ok(());
+        }
+        if self.max_peers != 0 && self.peer_session_map.len() as u64 >= self.max_peers {
+            return Err(format!("the number of peers reaches {}", self.max_peers));
+        }
+        if let Some(ip) = get_ip_address(&session.address) {
+            if !self.connection_rate_limiter.check(ip) {
+                return Err(format!("address {} exceeds the connection rate limit", ip));
+            }
+        }
+        Ok(())
+    }
+
     async fn on_peer_connected(
         &mut self,
         remote_peer_id: &PeerId,
@@ -3317,6 +5473,9 @@ where
         let store = self.store.clone();
         self.peer_session_map
             .insert(remote_peer_id.clone(), session.id);
+        if session.ty.is_inbound() {
+            self.inbound_peers.insert(remote_peer_id.clone());
+        }
         self.peer_pubkey_map
             .insert(remote_peer_id.clone(), remote_pubkey);
 
@@ -3352,7 +5511,6 @@ where
                 error!("Failed to reestablish channel {:x}: {:?}", &channel_id, &e);
             }
         }
-        self.maybe_sync_network_graph(remote_peer_id).await;
     }
 
     fn remove_channel(&mut self, channel_id: &Hash256) -> Option<ActorRef<ChannelActorMessage>> {
@@ -3374,6 +5532,7 @@ where
                 }
             }
         }
+        self.inbound_peers.remove(id);
         self.maybe_tell_syncer_peer_disconnected(id);
     }
 
@@ -3381,16 +5540,18 @@ where
         self.state_to_be_persisted.get_peer_addresses(peer_id)
     }
 
-    pub(crate) fn save_peer_address(&mut self, peer_id: PeerId, address: Multiaddr) -> bool {
-        if self
+    pub(crate) fn save_peer_address(
+        &mut self,
+        peer_id: PeerId,
+        address: Multiaddr,
+    ) -> Result<bool, Error> {
+        let saved = self
             .state_to_be_persisted
-            .save_peer_address(peer_id, address)
-        {
+            .save_peer_address(peer_id, address)?;
+        if saved {
             self.persist_state();
-            true
-        } else {
-            false
         }
+        Ok(saved)
     }
 
     pub(crate) fn save_announced_peer_addresses(
@@ -3462,6 +5623,16 @@ where
         }
     }
 
+    fn update_active_channels_metrics(&self) {
+        let active_channels = self
+            .store
+            .get_channel_states(None)
+            .into_iter()
+            .filter(|(_, _, state)| matches!(state, ChannelState::ChannelReady()))
+            .count();
+        metrics().set_active_channels(active_channels);
+    }
+
     fn on_channel_created(
         &mut self,
         id: Hash256,
@@ -3499,7 +5670,7 @@ where
             &channel_id, &peer_id, &tx_hash
         );
         let network: ActorRef<NetworkActorMessage> = self.network.clone();
-        self.broadcast_tx_with_callback(transaction, move |result| {
+        self.broadcast_tx_with_callback(transaction, NUM_CONFIRMATIONS, move |result| {
             let message = match result {
                 Ok(TraceTxResponse {
                     status:
@@ -3568,14 +5739,13 @@ where
     ) -> ProcessingChannelResult {
         self.check_open_ckb_parameters(&open_channel)?;
 
-        if let Some(udt_type_script) = &open_channel.funding_udt_type_script {
-            if !check_udt_script(udt_type_script) {
-                return Err(ProcessingChannelError::InvalidParameter(format!(
-                    "Invalid UDT type script: {:?}",
-                    udt_type_script
-                )));
-            }
-        }
+        self.channel_acceptance_policy
+            .check(
+                &peer_id,
+                open_channel.funding_amount,
+                open_channel.funding_udt_type_script.as_ref(),
+            )
+            .map_err(ProcessingChannelError::InvalidParameter)?;
 
         let id = open_channel.channel_id;
         if let Some(channel) = self.to_be_accepted_channels.get(&id) {
@@ -3619,6 +5789,13 @@ where
             }
         }
         self.pending_channels.insert(outpoint.clone(), channel_id);
+        // A zero-conf channel may be ready once the funding transaction is broadcasted.
+        self.send_message_to_channel_actor(
+            channel_id,
+            None,
+            ChannelActorMessage::Event(ChannelEvent::FundingTransactionPending),
+        )
+        .await;
         // TODO: try to broadcast the transaction to the network.
         let transaction = transaction.into_view();
         let tx_hash: Byte32 = transaction.hash();
@@ -3627,46 +5804,189 @@ where
             &outpoint, &channel_id, &tx_hash
         );
         let network = self.network.clone();
-        self.broadcast_tx_with_callback(transaction, move |result| {
+        let confirmations = self.get_funding_confirmations(&channel_id);
+        self.broadcast_tx_with_callback(transaction, confirmations, move |result| {
             debug!("Funding transaction broadcast result: {:?}", &result);
-            let message = match result {
-                Ok(TraceTxResponse {
-                    status:
-                        TxStatus {
-                            status: Status::Committed,
-                            block_number: Some(block_number),
-                            ..
-                        },
-                    ..
-                }) => {
-                    info!("Funding transaction {:?} confirmed", &tx_hash);
-                    NetworkActorEvent::FundingTransactionConfirmed(
-                        outpoint,
-                        block_number.into(),
-                        DUMMY_FUNDING_TX_INDEX,
-                    )
-                }
-                Ok(status) => {
-                    error!(
-                        "Funding transaction {:?} failed to be confirmed with final status {:?}",
-                        &tx_hash, &status
-                    );
-                    NetworkActorEvent::FundingTransactionFailed(outpoint)
-                }
-                Err(err) => {
-                    error!("Failed to trace transaction {:?}: {:?}", &tx_hash, &err);
-                    NetworkActorEvent::FundingTransactionFailed(outpoint)
-                }
-            };
+            on_funding_transaction_traced(&network, tx_hash, outpoint, result);
+        })
+        .await;
+    }
 
-            // Notify outside observers.
-            network
-                .send_message(NetworkActorMessage::new_event(message))
-                .expect(ASSUME_NETWORK_MYSELF_ALIVE);
+    // The confirmations required for the funding transaction of the channel, which are fewer
+    // if the remote peer is trusted.
+    fn get_funding_confirmations(&self, channel_id: &Hash256) -> u64 {
+        let trusted = !self.trusted_funding_peers.is_empty()
+            && self
+                .store
+                .get_channel_actor_state(channel_id)
+                .is_some_and(|state| {
+                    self.trusted_funding_peers
+                        .contains(&state.get_remote_peer_id())
+                });
+        if trusted {
+            self.trusted_funding_confirmations.min(NUM_CONFIRMATIONS)
+        } else {
+            NUM_CONFIRMATIONS
+        }
+    }
+
+    // The total local balance of the CKB channels whose funding transactions may be double spent.
+    fn get_funding_at_risk_amount(&self) -> u128 {
+        self.channels
+            .keys()
+            .filter_map(|channel_id| self.store.get_channel_actor_state(channel_id))
+            .filter(|state| state.funding_udt_type_script.is_none())
+            .map(|state| state.get_funding_at_risk_amount())
+            .sum()
+    }
+
+    async fn on_funding_transaction_reorged(&mut self, channel_id: Hash256, outpoint: OutPoint) {
+        warn!(
+            "Funding transaction (outpoint {:?}) for channel {:?} is reorged, waiting for it to be confirmed again",
+            &outpoint, &channel_id
+        );
+        self.send_message_to_channel_actor(
+            channel_id,
+            None,
+            ChannelActorMessage::Event(ChannelEvent::FundingTransactionReorged),
+        )
+        .await;
+        self.pending_channels.insert(outpoint.clone(), channel_id);
+        // The funding transaction was already broadcasted, it should be committed again
+        // once it is included in the new canonical chain.
+        let tx_hash = outpoint.tx_hash();
+        let network = self.network.clone();
+        let confirmations = self.get_funding_confirmations(&channel_id);
+        self.trace_tx_with_callback(tx_hash.clone(), confirmations, move |result| {
+            on_funding_transaction_traced(&network, tx_hash, outpoint, result);
         })
         .await;
     }
 
+    // Remove the finished payment sessions which are not updated within the retention age,
+    // the most recent payment sessions are always kept as the payment history.
+    fn prune_payment_sessions(&self) {
+        if self.payment_session_retention_seconds == 0 {
+            return;
+        }
+        let now = std::time::UNIX_EPOCH.elapsed().unwrap().as_millis();
+        let before = now.saturating_sub(self.payment_session_retention_seconds as u128 * 1000);
+        let pruned = self
+            .store
+            .prune_payment_sessions(before, self.payment_session_retention_count as usize);
+        if pruned > 0 {
+            debug!("Pruned {} finished payment sessions", pruned);
+        }
+    }
+
+    // Remove the archives and the states of the channels closed before the retention age.
+    fn prune_closed_channels(&self) {
+        if self.closed_channel_retention_seconds == 0 {
+            return;
+        }
+        let now = std::time::UNIX_EPOCH.elapsed().unwrap().as_millis() as u64;
+        let before = now.saturating_sub(self.closed_channel_retention_seconds.saturating_mul(1000));
+        let pruned = self.store.prune_closed_channels(before);
+        if pruned > 0 {
+            debug!("Pruned {} closed channels", pruned);
+        }
+    }
+
+    // Check whether the funding transactions of ready channels are still committed in the
+    // block recorded by the channels. The chain is queried in a spawned task, and
+    // a FundingTransactionReorged event is sent for each diverged funding transaction.
+    // A FundingTransactionFullyConfirmed event is sent for each funding transaction confirmed
+    // for a trusted peer once it reaches the full confirmations.
+    fn check_funding_transactions(&self) {
+        let channels: Vec<_> = self
+            .store
+            .get_channel_states(None)
+            .into_iter()
+            .filter(|(_, _, state)| matches!(state, ChannelState::ChannelReady()))
+            .filter_map(|(peer_id, channel_id, _)| {
+                let state = self.store.get_channel_actor_state(&channel_id)?;
+                let (block_number, _) = state.funding_tx_confirmed_at?;
+                Some((
+                    channel_id,
+                    peer_id,
+                    state.get_funding_transaction_outpoint(),
+                    block_number,
+                    state.trusted_funding_pending,
+                ))
+            })
+            .collect();
+        if channels.is_empty() {
+            return;
+        }
+        let chain = self.chain_actor.clone();
+        let network = self.network.clone();
+        ractor::concurrency::tokio_primatives::spawn(async move {
+            let tip_number = if channels.iter().any(|channel| channel.4) {
+                match call_t!(
+                    chain,
+                    CkbChainMessage::GetCurrentBlockNumber,
+                    DEFAULT_CHAIN_ACTOR_TIMEOUT,
+                    ()
+                ) {
+                    Ok(Ok(tip_number)) => Some(tip_number),
+                    result => {
+                        warn!("Failed to get current block number: {:?}", &result);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            for (channel_id, peer_id, outpoint, block_number, trusted_funding_pending) in channels {
+                let status = match call_t!(
+                    chain,
+                    CkbChainMessage::GetTransactionStatus,
+                    DEFAULT_CHAIN_ACTOR_TIMEOUT,
+                    outpoint.tx_hash()
+                ) {
+                    Ok(Ok(status)) => status,
+                    result => {
+                        warn!(
+                            "Failed to get status of funding transaction {:?}: {:?}",
+                            &outpoint, &result
+                        );
+                        continue;
+                    }
+                };
+                let reorged = match status {
+                    TxStatus {
+                        status: Status::Committed,
+                        block_number: Some(committed_at),
+                        ..
+                    } => committed_at != block_number,
+                    _ => true,
+                };
+                if reorged {
+                    warn!(
+                        "Funding transaction {:?} confirmed at block {} diverged from the chain, current status: {:?}",
+                        &outpoint, block_number, &status
+                    );
+                    network
+                        .send_message(NetworkActorMessage::new_event(
+                            NetworkActorEvent::FundingTransactionReorged(
+                                channel_id, peer_id, outpoint,
+                            ),
+                        ))
+                        .expect(ASSUME_NETWORK_MYSELF_ALIVE);
+                } else if trusted_funding_pending
+                    && tip_number
+                        .is_some_and(|tip| tip >= u64::from(block_number) + NUM_CONFIRMATIONS)
+                {
+                    network
+                        .send_message(NetworkActorMessage::new_event(
+                            NetworkActorEvent::FundingTransactionFullyConfirmed(channel_id),
+                        ))
+                        .expect(ASSUME_NETWORK_MYSELF_ALIVE);
+                }
+            }
+        });
+    }
+
     async fn on_commitment_transaction_pending(
         &mut self,
         transaction: Transaction,
@@ -3680,7 +6000,7 @@ where
         );
 
         let network = self.network.clone();
-        self.broadcast_tx_with_callback(transaction, move |result| {
+        self.broadcast_tx_with_callback(transaction, NUM_CONFIRMATIONS, move |result| {
             let message = match result {
                 Ok(TraceTxResponse {
                     status:
@@ -3732,12 +6052,16 @@ where
                 return;
             }
         };
+        // The funding transaction is only confirmed by the fewer confirmations if the peer is
+        // trusted, it's checked again for the full confirmations by check_funding_transactions.
+        let trusted = self.get_funding_confirmations(&channel_id) < NUM_CONFIRMATIONS;
         self.send_message_to_channel_actor(
             channel_id,
             None,
             ChannelActorMessage::Event(ChannelEvent::FundingTransactionConfirmed(
                 block_number,
                 tx_index,
+                trusted,
             )),
         )
         .await;
@@ -3748,7 +6072,7 @@ where
         self.send_message_to_channel_actor(
             channel_id,
             None,
-            ChannelActorMessage::Event(ChannelEvent::CommitmentTransactionConfirmed),
+            ChannelActorMessage::Event(ChannelEvent::CommitmentTransactionConfirmed(tx_hash)),
         )
         .await;
     }
@@ -3804,6 +6128,7 @@ pub struct NetworkActorStartArguments {
     pub tracker: TaskTracker,
     pub channel_subscribers: ChannelSubscribers,
     pub default_shutdown_script: Script,
+    pub chain_tip: ChainTip,
 }
 
 #[rasync_trait]
@@ -3832,7 +6157,33 @@ where
             tracker,
             channel_subscribers,
             default_shutdown_script,
+            chain_tip,
         } = args;
+        if config.tlc_resolution_timeout_blocks() != 0
+            && config.tlc_resolution_timeout_blocks() >= config.tlc_locktime_expiry_delta()
+        {
+            return Err(format!(
+                "The tlc resolution timeout of {} blocks must be less than the tlc locktime expiry delta of {} blocks",
+                config.tlc_resolution_timeout_blocks(),
+                config.tlc_locktime_expiry_delta()
+            )
+            .into());
+        }
+        if config.max_onion_payload_size() > PaymentHopData::PACKET_DATA_LEN {
+            return Err(format!(
+                "The max onion payload size {} must not exceed the onion packet data size {}",
+                config.max_onion_payload_size(),
+                PaymentHopData::PACKET_DATA_LEN
+            )
+            .into());
+        }
+        if let Some(version) = config
+            .onion_versions()
+            .into_iter()
+            .find(|version| !SUPPORTED_ONION_VERSIONS.contains(version))
+        {
+            return Err(format!("Unsupported onion version {}", version).into());
+        }
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .expect("SystemTime::now() should after UNIX_EPOCH");
@@ -3908,7 +6259,9 @@ where
         for bootnode in &config.bootnode_addrs {
             let addr = Multiaddr::from_str(bootnode.as_str()).expect("valid bootnode");
             let peer_id = extract_peer_id(&addr).expect("valid peer id");
-            state_to_be_persisted.save_peer_address(peer_id, addr);
+            if let Err(err) = state_to_be_persisted.save_peer_address(peer_id, addr) {
+                error!("Failed to save bootnode address: {}", err);
+            }
         }
 
         let height = graph.get_best_height();
@@ -3924,6 +6277,7 @@ where
             current_block_number,
             last_update,
             vec![],
+            config.graph_sync_peer_strategy(),
         );
 
         let mut state = NetworkActorState {
@@ -3946,6 +6300,8 @@ where
             outpoint_channel_map: Default::default(),
             to_be_accepted_channels: Default::default(),
             pending_channels: Default::default(),
+            temp_channel_ids: Default::default(),
+            channel_funding_errors: Default::default(),
             chain_actor,
             open_channel_auto_accept_min_ckb_funding_amount: config
                 .open_channel_auto_accept_min_ckb_funding_amount(),
@@ -3954,6 +6310,43 @@ where
             tlc_min_value: config.tlc_min_value(),
             tlc_max_value: config.tlc_max_value(),
             tlc_fee_proportional_millionths: config.tlc_fee_proportional_millionths(),
+            payment_timeout_seconds: config.payment_timeout_seconds(),
+            payment_max_fee_percent: config.payment_max_fee_percent(),
+            tlc_resolution_timeout_blocks: config.tlc_resolution_timeout_blocks(),
+            payment_session_retention_seconds: config.payment_session_retention_seconds(),
+            payment_session_retention_count: config.payment_session_retention_count(),
+            closed_channel_retention_seconds: config.closed_channel_retention_seconds(),
+            graph_snapshot_interval_seconds: config.graph_snapshot_interval_seconds(),
+            forget_settled_preimages: config.forget_settled_preimages(),
+            shutdown_fee_rate_tolerance_percent: config.shutdown_fee_rate_tolerance_percent(),
+            min_shutdown_fee_rate: config.min_shutdown_fee_rate(),
+            max_accepted_funding_fee_rate: config.max_accepted_funding_fee_rate(),
+            channel_event_log_max_size: config.channel_event_log_max_size(),
+            reestablish_force_close_on_divergence: config.reestablish_force_close_on_divergence(),
+            audit_commitment_updates: config.audit_commitment_updates(),
+            tlc_fail_delay: TlcFailDelay {
+                delay_ms: config.tlc_fail_delay_ms(),
+                jitter_ms: config.tlc_fail_delay_jitter_ms(),
+            },
+            tlc_fulfill_delay: TlcFulfillDelay {
+                jitter_ms: config.tlc_fulfill_delay_jitter_ms(),
+            },
+            default_dust_tlc_limit: DustTlcLimit {
+                threshold: config.dust_tlc_threshold(),
+                max_exposure: config.max_dust_tlc_exposure(),
+            },
+            channel_update_broadcast_delay_ms: config.channel_update_broadcast_delay_ms(),
+            chain_tip,
+            max_onion_payload_size: config.max_onion_payload_size(),
+            channel_acceptance_policy: config.channel_acceptance_policy(),
+            hash_algorithms: config.hash_algorithms(),
+            onion_versions: config.onion_versions(),
+            suggested_fee_rates: config.suggested_fee_rates(),
+            rejected_tlcs: Default::default(),
+            rejected_tlcs_capacity: config.rejected_tlcs_capacity(),
+            open_channel_timeout_seconds: config.open_channel_timeout_seconds(),
+            trusted_funding_peers: config.trusted_funding_peers(),
+            trusted_funding_confirmations: config.trusted_funding_confirmations(),
             broadcasted_messages: Default::default(),
             channel_subscribers,
             next_request_id: Default::default(),
@@ -3961,6 +6354,22 @@ where
             original_requests: Default::default(),
             sync_status,
             broadcasted_message_queue: Default::default(),
+            gossip_rate_limiter: GossipRateLimiter::new(
+                config.gossip_rate_limit_max_messages(),
+                Duration::from_secs(config.gossip_rate_limit_interval_seconds()),
+            ),
+            gossip_max_future_skew: Duration::from_secs(config.gossip_max_future_skew_seconds()),
+            max_peers: config.max_peers(),
+            connection_rate_limiter: ConnectionRateLimiter::new(
+                config.connection_rate_limit_max_connections(),
+                Duration::from_secs(config.connection_rate_limit_interval_seconds()),
+            ),
+            inbound_peers: Default::default(),
+            rejected_connections: 0,
+            payment_ready_min_graph_sync_progress: config.payment_ready_min_graph_sync_progress(),
+            payment_ready_min_peers: config.payment_ready_min_peers(),
+            ready_for_payments: false,
+            channel_announcement_rebroadcasts: Default::default(),
         };
 
         // Save our own NodeInfo to the network graph.
@@ -3987,6 +6396,7 @@ where
         myself: ActorRef<Self::Msg>,
         state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
+        state.update_active_channels_metrics();
         debug!("Trying to connect to peers with mutual channels");
         for (peer_id, channel_id, channel_state) in self.store.get_channel_states(None) {
             let addresses = state.get_peer_addresses(&peer_id);
@@ -4014,6 +6424,41 @@ where
                 NUM_PEER_CONNECTIONS,
             ))
         });
+        myself.send_interval(CHECKING_FUNDING_TRANSACTIONS_INTERVAL, || {
+            NetworkActorMessage::new_command(NetworkActorCommand::CheckFundingTransactions)
+        });
+        myself.send_interval(REBALANCING_CHANNELS_INTERVAL, || {
+            NetworkActorMessage::new_command(NetworkActorCommand::RebalanceChannels)
+        });
+        myself
+            .send_message(NetworkActorMessage::new_command(
+                NetworkActorCommand::UpdateChainTip,
+            ))
+            .expect(ASSUME_NETWORK_MYSELF_ALIVE);
+        myself.send_interval(UPDATING_CHAIN_TIP_INTERVAL, || {
+            NetworkActorMessage::new_command(NetworkActorCommand::UpdateChainTip)
+        });
+        if state.tlc_resolution_timeout_blocks != 0 {
+            myself.send_interval(CHECKING_TLC_RESOLUTION_INTERVAL, || {
+                NetworkActorMessage::new_command(NetworkActorCommand::FailUnresolvedForwardedTlcs)
+            });
+        }
+        if state.payment_session_retention_seconds != 0 {
+            myself.send_interval(PRUNING_PAYMENT_SESSIONS_INTERVAL, || {
+                NetworkActorMessage::new_command(NetworkActorCommand::PrunePaymentSessions)
+            });
+        }
+        if state.closed_channel_retention_seconds != 0 {
+            myself.send_interval(PRUNING_CLOSED_CHANNELS_INTERVAL, || {
+                NetworkActorMessage::new_command(NetworkActorCommand::PruneClosedChannels)
+            });
+        }
+        if state.graph_snapshot_interval_seconds != 0 {
+            myself.send_interval(
+                Duration::from_secs(state.graph_snapshot_interval_seconds),
+                || NetworkActorMessage::new_command(NetworkActorCommand::SaveGraphSnapshot),
+            );
+        }
         Ok(())
     }
 
@@ -4048,6 +6493,9 @@ where
         }
         debug!("Saving network actor state for {:?}", state.peer_id);
         state.persist_state();
+        if state.graph_snapshot_interval_seconds != 0 {
+            self.network_graph.write().await.save_snapshot();
+        }
         debug!("Network service for {:?} shutdown", state.peer_id);
         // The event receiver may have been closed already.
         // We ignore the error here.
@@ -4062,11 +6510,20 @@ where
         &self,
         _myself: ActorRef<Self::Msg>,
         message: SupervisionEvent,
-        _state: &mut Self::State,
+        state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
         match message {
             SupervisionEvent::ActorTerminated(who, _, _) => {
                 debug!("Actor {:?} terminated", who);
+                // Forget the channel actors which stop on their own, e.g. the halted channels.
+                let channel_id = state
+                    .channels
+                    .iter()
+                    .find(|(_, actor)| actor.get_id() == who.get_id())
+                    .map(|(channel_id, _)| *channel_id);
+                if let Some(channel_id) = channel_id {
+                    state.remove_channel(&channel_id);
+                }
             }
             SupervisionEvent::ActorPanicked(who, _) => {
                 error!("Actor {:?} panicked", who);
@@ -4184,6 +6641,49 @@ impl ServiceHandle for Handle {
     }
 }
 
+// Map the trace result of a funding transaction to the event for the network actor.
+fn on_funding_transaction_traced(
+    network: &ActorRef<NetworkActorMessage>,
+    tx_hash: Byte32,
+    outpoint: OutPoint,
+    result: Result<TraceTxResponse, RactorErr<CkbChainMessage>>,
+) {
+    let message = match result {
+        Ok(TraceTxResponse {
+            status:
+                TxStatus {
+                    status: Status::Committed,
+                    block_number: Some(block_number),
+                    ..
+                },
+            ..
+        }) => {
+            info!("Funding transaction {:?} confirmed", &tx_hash);
+            NetworkActorEvent::FundingTransactionConfirmed(
+                outpoint,
+                block_number.into(),
+                DUMMY_FUNDING_TX_INDEX,
+            )
+        }
+        Ok(status) => {
+            error!(
+                "Funding transaction {:?} failed to be confirmed with final status {:?}",
+                &tx_hash, &status
+            );
+            NetworkActorEvent::FundingTransactionFailed(outpoint)
+        }
+        Err(err) => {
+            error!("Failed to trace transaction {:?}: {:?}", &tx_hash, &err);
+            NetworkActorEvent::FundingTransactionFailed(outpoint)
+        }
+    };
+
+    // Notify outside observers.
+    network
+        .send_message(NetworkActorMessage::new_event(message))
+        .expect(ASSUME_NETWORK_MYSELF_ALIVE);
+}
+
 pub(crate) fn emit_service_event(
     network: &ActorRef<NetworkActorMessage>,
     event: NetworkServiceEvent,
@@ -4226,6 +6726,7 @@ pub async fn start_network<
             tracker,
             channel_subscribers,
             default_shutdown_script,
+            chain_tip: Default::default(),
         },
         root_actor,
     )
@@ -1,28 +1,48 @@
 use crate::{
-    ckb::contracts::{get_cell_deps, Contract},
+    ckb::contracts::{get_cell_deps, get_cell_deps_by_contracts, get_script_by_contract, Contract},
     fiber::{
         channel::{
-            derive_private_key, derive_tlc_pubkey, AddTlcCommand, ChannelActorStateStore,
-            ChannelCommand, ChannelCommandWithId, InMemorySigner, RemoveTlcCommand,
-            ShutdownCommand, DEFAULT_COMMITMENT_FEE_RATE,
+            derive_private_key, derive_tlc_pubkey, negotiate_features, AddTlcCommand,
+            ChannelActorState, ChannelActorStateStore, ChannelCommand, ChannelCommandWithId,
+            ChannelConnectionStats, ChannelHaltReason, ChannelLogEvent, ChannelOpenStep,
+            ChannelState, CloseFlags, DustTlcLimit, ForwardingTlc, InMemorySigner, PeerFeePolicy,
+            RebalancePolicy, ReestablishOutcome, RemoveTlcCommand, ShutdownCommand,
+            ShuttingDownFlags, TLCId, TlcFulfillDelay, UpdateCommand, DEFAULT_COMMITMENT_FEE_RATE,
+            TLC,
         },
-        config::DEFAULT_CHANNEL_MINIMAL_CKB_AMOUNT,
+        config::{DEFAULT_CHANNEL_MINIMAL_CKB_AMOUNT, DEFAULT_TRAMPOLINE_EXPIRY_DELTA},
+        graph::{NetworkGraphStateStore, PaymentSessionStatus},
         hash_algorithm::HashAlgorithm,
-        network::{AcceptChannelCommand, OpenChannelCommand},
-        types::{Hash256, LockTime, Privkey, RemoveTlcFulfill, RemoveTlcReason},
-        NetworkActorCommand, NetworkActorMessage,
+        network::{
+            AcceptChannelCommand, ChannelAttentionReason, ChannelAttentionThresholds,
+            FiberMessageWithPeerId, GetRebalanceRouteCommand, OpenChannelCommand,
+            SendPaymentCommand,
+        },
+        types::{
+            AddTlc, FiberMessage, Hash256, LockTime, Privkey, ReestablishChannel, RemoveTlcFulfill,
+            RemoveTlcReason, TlcErr, TlcErrPacket, TlcErrorCode, LEGACY_NODE_FEATURES,
+            NODE_FEATURE_CKB_HASH, NODE_FEATURE_KEYSEND, NODE_FEATURE_ONION_V0,
+            NODE_FEATURE_SHA256, SUPPORTED_NODE_FEATURES,
+        },
+        NetworkActorCommand, NetworkActorEvent, NetworkActorMessage,
     },
+    invoice::{Currency, InvoiceBuilder, InvoiceStore},
     NetworkServiceEvent,
 };
 use ckb_jsonrpc_types::Status;
 use ckb_types::{
     core::FeeRate,
-    packed::{CellInput, Script, Transaction},
+    packed::{CellInput, CellOutput, Script, Transaction},
     prelude::{AsTransactionBuilder, Builder, Entity, IntoTransactionView, Pack, Unpack},
 };
 use ractor::call;
+use std::time::Duration;
+use tentacle::secio::PeerId;
 
-use super::test_utils::{init_tracing, NetworkNode};
+use super::test_utils::{
+    gen_sha256_hash, init_tracing, MemoryStore, NetworkNode, NetworkNodeConfig,
+    NetworkNodeConfigBuilder,
+};
 
 #[test]
 fn test_per_commitment_point_and_secret_consistency() {
@@ -44,149 +64,3461 @@ fn test_derive_private_and_public_tlc_keys() {
     assert_eq!(derived_privkey.pubkey(), derived_pubkey);
 }
 
-#[tokio::test]
-async fn test_open_channel_to_peer() {
-    let [node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+#[test]
+fn test_rebalance_policy_amount() {
+    let policy = RebalancePolicy {
+        enabled: true,
+        target_local_ratio: 50,
+        trigger_threshold: 10,
+        max_fee_amount: 1000,
+    };
+    assert_eq!(policy.rebalance_amount(600, 400), None);
+    assert_eq!(policy.rebalance_amount(610, 390), Some(110));
+    assert_eq!(policy.rebalance_amount(1000, 0), Some(500));
+    // Only the excess local balance is sent out of the channel.
+    assert_eq!(policy.rebalance_amount(100, 900), None);
+    assert_eq!(policy.rebalance_amount(0, 0), None);
+    assert_eq!(
+        RebalancePolicy {
+            enabled: false,
+            ..policy.clone()
+        }
+        .rebalance_amount(1000, 0),
+        None
+    );
 
-    let message = |rpc_reply| {
-        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
-            OpenChannelCommand {
-                peer_id: node_b.peer_id.clone(),
-                public: false,
-                shutdown_script: None,
-                funding_amount: 100000000000,
-                funding_udt_type_script: None,
-                commitment_fee_rate: None,
-                commitment_delay_epoch: None,
-                funding_fee_rate: None,
-                tlc_locktime_expiry_delta: None,
-                tlc_min_value: None,
-                tlc_max_value: None,
-                tlc_fee_proportional_millionths: None,
-                max_tlc_number_in_flight: None,
-                max_tlc_value_in_flight: None,
+    assert!(policy.validate().is_ok());
+    assert!(RebalancePolicy {
+        target_local_ratio: 101,
+        ..policy.clone()
+    }
+    .validate()
+    .is_err());
+    assert!(RebalancePolicy {
+        trigger_threshold: 101,
+        ..policy
+    }
+    .validate()
+    .is_err());
+}
+
+async fn update_rebalance_policy(
+    node: &NetworkNode,
+    channel_id: Hash256,
+    policy: RebalancePolicy,
+) -> Result<(), String> {
+    let result = call!(node.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::Update(
+                    UpdateCommand {
+                        enabled: None,
+                        tlc_locktime_expiry_delta: None,
+                        tlc_minimum_value: None,
+                        tlc_maximum_value: None,
+                        tlc_fee_proportional_millionths: None,
+                        rebalance_policy: Some(policy),
+                        dust_tlc_limit: None,
+                        max_htlc_fraction_of_capacity: None,
+                    },
+                    rpc_reply,
+                ),
             },
-            rpc_reply,
         ))
+    })
+    .expect("node alive");
+    // Wait for the channel state to be persisted.
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    result
+}
+
+#[tokio::test]
+async fn test_update_channel_rebalance_policy() {
+    init_tracing();
+
+    let (node_a, _node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, true).await;
+    let policy = RebalancePolicy {
+        enabled: true,
+        target_local_ratio: 50,
+        trigger_threshold: 10,
+        max_fee_amount: 1000,
     };
-    let _open_channel_result = call!(node_a.network_actor, message)
-        .expect("node_a alive")
-        .expect("open channel success");
+    update_rebalance_policy(&node_a, channel_id, policy.clone())
+        .await
+        .expect("update rebalance policy");
+    let state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    assert_eq!(state.rebalance_policy, Some(policy.clone()));
 
-    node_b
-        .expect_event(|event| match event {
-            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, channel_id) => {
-                println!("A channel ({:?}) to {:?} create", channel_id, peer_id);
-                assert_eq!(peer_id, &node_a.peer_id);
-                true
-            }
-            _ => false,
-        })
-        .await;
+    let error = update_rebalance_policy(
+        &node_a,
+        channel_id,
+        RebalancePolicy {
+            target_local_ratio: 200,
+            ..policy.clone()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(error.contains("target_local_ratio should be at most 100"));
+    let state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    assert_eq!(state.rebalance_policy, Some(policy));
+}
+
+async fn add_tlc(
+    node: &NetworkNode,
+    channel_id: Hash256,
+    amount: u128,
+) -> Result<u64, TlcErrPacket> {
+    let result = call!(node.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: Some(gen_sha256_hash()),
+                        expiry: LockTime::new(100),
+                        preimage: None,
+                        onion_packet: vec![],
+                        previous_tlc: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node alive");
+    // Wait for the tlc to be committed by both parties.
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    result.map(|response| response.tlc_id)
 }
 
 #[tokio::test]
-async fn test_open_and_accept_channel() {
-    let [node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+async fn test_dust_tlc_exposure_limit() {
+    init_tracing();
 
-    let message = |rpc_reply| {
-        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
-            OpenChannelCommand {
-                peer_id: node_b.peer_id.clone(),
-                public: false,
-                shutdown_script: None,
-                funding_amount: 100000000000,
-                funding_udt_type_script: None,
-                commitment_fee_rate: None,
-                commitment_delay_epoch: None,
-                funding_fee_rate: None,
-                tlc_locktime_expiry_delta: None,
-                tlc_min_value: None,
-                tlc_max_value: None,
-                tlc_fee_proportional_millionths: None,
-                max_tlc_number_in_flight: None,
-                max_tlc_value_in_flight: None,
+    let (node_a, _node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, true).await;
+    let limit = DustTlcLimit {
+        threshold: 1000,
+        max_exposure: 1500,
+    };
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::Update(
+                    UpdateCommand {
+                        enabled: None,
+                        tlc_locktime_expiry_delta: None,
+                        tlc_minimum_value: None,
+                        tlc_maximum_value: None,
+                        tlc_fee_proportional_millionths: None,
+                        rebalance_policy: None,
+                        dust_tlc_limit: Some(limit),
+                        max_htlc_fraction_of_capacity: None,
+                    },
+                    rpc_reply,
+                ),
             },
-            rpc_reply,
         ))
-    };
-    let open_channel_result = call!(node_a.network_actor, message)
-        .expect("node_a alive")
-        .expect("open channel success");
+    })
+    .expect("node alive")
+    .expect("update dust tlc limit");
 
-    node_b
-        .expect_event(|event| match event {
-            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, channel_id) => {
-                println!("A channel ({:?}) to {:?} create", &channel_id, peer_id);
-                assert_eq!(peer_id, &node_a.peer_id);
-                true
-            }
-            _ => false,
-        })
-        .await;
+    // The tlcs at or above the threshold are not dust.
+    add_tlc(&node_a, channel_id, 1000).await.expect("add tlc");
+    add_tlc(&node_a, channel_id, 800).await.expect("add tlc");
+    let state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    assert_eq!(state.dust_tlc_limit, Some(limit));
+    assert_eq!(state.get_dust_tlc_exposure(&limit), 800);
 
-    let message = |rpc_reply| {
-        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
-            AcceptChannelCommand {
-                temp_channel_id: open_channel_result.channel_id,
-                funding_amount: DEFAULT_CHANNEL_MINIMAL_CKB_AMOUNT as u128,
-                shutdown_script: None,
+    let error = add_tlc(&node_a, channel_id, 800).await.unwrap_err();
+    assert_eq!(
+        error.decode().unwrap().error_code,
+        TlcErrorCode::TemporaryChannelFailure
+    );
+    add_tlc(&node_a, channel_id, 700).await.expect("add tlc");
+    let state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    assert_eq!(state.get_dust_tlc_exposure(&limit), 1500);
+}
+
+async fn update_max_htlc_fraction_of_capacity(
+    node: &NetworkNode,
+    channel_id: Hash256,
+    fraction: u64,
+) -> Result<(), String> {
+    call!(node.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::Update(
+                    UpdateCommand {
+                        enabled: None,
+                        tlc_locktime_expiry_delta: None,
+                        tlc_minimum_value: None,
+                        tlc_maximum_value: None,
+                        tlc_fee_proportional_millionths: None,
+                        rebalance_policy: None,
+                        dust_tlc_limit: None,
+                        max_htlc_fraction_of_capacity: Some(fraction),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node alive")
+}
+
+#[tokio::test]
+async fn test_max_htlc_fraction_of_capacity() {
+    init_tracing();
+
+    let (node_a, _node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, true).await;
+    assert!(update_max_htlc_fraction_of_capacity(&node_a, channel_id, 0)
+        .await
+        .is_err());
+    assert!(
+        update_max_htlc_fraction_of_capacity(&node_a, channel_id, 1_000_001)
+            .await
+            .is_err()
+    );
+
+    // 1% of the channel capacity.
+    update_max_htlc_fraction_of_capacity(&node_a, channel_id, 10000)
+        .await
+        .expect("update max htlc fraction of capacity");
+    let state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    let max_value = (state.to_local_amount + state.to_remote_amount) / 100;
+    assert_eq!(state.max_htlc_fraction_of_capacity, Some(10000));
+    assert_eq!(state.get_max_tlc_value(), Some(max_value));
+
+    let error = add_tlc(&node_a, channel_id, max_value + 1)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.decode().unwrap().error_code,
+        TlcErrorCode::TemporaryChannelFailure
+    );
+    add_tlc(&node_a, channel_id, max_value)
+        .await
+        .expect("add tlc");
+}
+
+async fn update_tlc_fee_proportional_millionths(
+    node: &NetworkNode,
+    channel_id: Hash256,
+    fee: u128,
+) {
+    call!(node.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::Update(
+                    UpdateCommand {
+                        enabled: None,
+                        tlc_locktime_expiry_delta: None,
+                        tlc_minimum_value: None,
+                        tlc_maximum_value: None,
+                        tlc_fee_proportional_millionths: Some(fee),
+                        rebalance_policy: None,
+                        dust_tlc_limit: None,
+                        max_htlc_fraction_of_capacity: None,
+                    },
+                    rpc_reply,
+                ),
             },
-            rpc_reply,
         ))
+    })
+    .expect("node alive")
+    .expect("update channel");
+}
+
+#[tokio::test]
+async fn test_channel_update_broadcast_delay() {
+    init_tracing();
+
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .fiber_config_updater(|config| {
+                config.channel_update_broadcast_delay_ms = Some(1000);
+            })
+            .build()
+    })
+    .await;
+    let [node_a, node_b]: [NetworkNode; 2] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
     };
+    let (node_a, _node_b, channel_id) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, true).await;
+    let gossiped_fee = |state: &ChannelActorState| {
+        state
+            .public_channel_info
+            .as_ref()
+            .and_then(|info| info.channel_update.as_ref())
+            .map(|update| update.tlc_fee_proportional_millionths)
+    };
+    let old_fee = gossiped_fee(
+        &node_a
+            .store
+            .get_channel_actor_state(&channel_id)
+            .expect("channel found"),
+    );
 
-    let _accept_channel_result = call!(node_b.network_actor, message)
-        .expect("node_b alive")
-        .expect("accept channel success");
+    // The rapid updates are applied at once, but coalesced into a single channel update.
+    update_tlc_fee_proportional_millionths(&node_a, channel_id, 2000).await;
+    update_tlc_fee_proportional_millionths(&node_a, channel_id, 3000).await;
+    let state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel found");
+    assert!(state.channel_update_broadcast_pending);
+    assert_eq!(
+        state
+            .public_channel_info
+            .as_ref()
+            .and_then(|info| info.tlc_fee_proportional_millionths),
+        Some(3000)
+    );
+    assert_eq!(gossiped_fee(&state), old_fee);
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(1500)).await;
+    let state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel found");
+    assert!(!state.channel_update_broadcast_pending);
+    assert_eq!(gossiped_fee(&state), Some(3000));
 }
 
 #[tokio::test]
-async fn test_create_public_channel() {
+async fn test_get_rebalance_route_errors() {
     init_tracing();
 
-    let _span = tracing::info_span!("node", node = "test").entered();
+    let (node_a, _node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, true).await;
+    let get_rebalance_route = |source_channel, destination_channel| {
+        let network_actor = node_a.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::GetRebalanceRoute(
+                    GetRebalanceRouteCommand {
+                        source_channel,
+                        destination_channel,
+                        amount: 1000,
+                        max_fee_amount: None,
+                    },
+                    rpc_reply,
+                ))
+            })
+            .expect("node_a alive")
+        }
+    };
 
-    let node_a_funding_amount = 100000000000;
-    let node_b_funding_amount = 6200000000;
+    let error = get_rebalance_route(channel_id, Hash256::default())
+        .await
+        .unwrap_err();
+    assert!(error.contains("Destination channel not found"), "{}", error);
+    let error = get_rebalance_route(channel_id, channel_id)
+        .await
+        .unwrap_err();
+    assert!(
+        error.contains("the outgoing and incoming channels are the same"),
+        "{}",
+        error
+    );
+}
 
-    let (_node_a, _node_b, _new_channel_id) =
-        create_nodes_with_established_channel(node_a_funding_amount, node_b_funding_amount, true)
-            .await;
-    // Wait for the channel announcement to be broadcasted
-    tokio::time::sleep(tokio::time::Duration::from_millis(5000)).await;
-    // FIXME: add assertion
+#[tokio::test]
+async fn test_get_forwarding_tlcs() {
+    init_tracing();
+
+    let (node_a, _node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 100000000000, true).await;
+    let mut state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    let new_tlc = |id: TLCId, previous_tlc: Option<(Hash256, TLCId)>| TLC {
+        id,
+        amount: 1000,
+        lock_time: LockTime::new(100),
+        payment_hash: gen_sha256_hash(),
+        payment_preimage: None,
+        hash_algorithm: HashAlgorithm::Sha256,
+        onion_packet: vec![],
+        previous_tlc,
+    };
+    for id in 0..2 {
+        state
+            .insert_tlc(new_tlc(TLCId::Received(id), None))
+            .expect("insert tlc");
+    }
+
+    // Only the forwarded tlc can be failed back.
+    assert!(state.check_forwarded_tlc_unresolved(1).is_err());
+    state.mark_tlc_forwarded(TLCId::Received(1));
+    assert!(state.check_forwarded_tlc_unresolved(1).is_ok());
+    assert!(state.check_forwarded_tlc_unresolved(2).is_err());
+    assert!(state.is_received_tlc_pending(1));
+    state
+        .remove_tlc_with_reason(
+            TLCId::Received(1),
+            &RemoveTlcReason::RemoveTlcFail(TlcErrPacket::new(TlcErr::new(
+                TlcErrorCode::TemporaryChannelFailure,
+            ))),
+        )
+        .expect("remove tlc");
+    assert!(state.check_forwarded_tlc_unresolved(1).is_err());
+    assert!(!state.is_received_tlc_pending(1));
+
+    // Only the offered tlcs with a previous tlc are forwarding tlcs.
+    let previous_tlc = (gen_sha256_hash(), TLCId::Received(5));
+    state
+        .insert_tlc(new_tlc(TLCId::Offered(0), Some(previous_tlc)))
+        .expect("insert tlc");
+    state
+        .insert_tlc(new_tlc(TLCId::Offered(1), None))
+        .expect("insert tlc");
+    let forwarding_tlcs = state.get_forwarding_tlcs();
+    assert_eq!(forwarding_tlcs.len(), 1);
+    assert_eq!(forwarding_tlcs[0].tlc_id, TLCId::Offered(0));
+    assert_eq!(forwarding_tlcs[0].previous_tlc, previous_tlc);
+    assert!(forwarding_tlcs[0].added_at.is_some());
+    assert_eq!(forwarding_tlcs[0].removed, None);
+
+    let reason = RemoveTlcReason::RemoveTlcFail(TlcErrPacket::new(TlcErr::new(
+        TlcErrorCode::IncorrectOrUnknownPaymentDetails,
+    )));
+    state
+        .remove_tlc_with_reason(TLCId::Offered(0), &reason)
+        .expect("remove tlc");
+    assert_eq!(state.get_forwarding_tlcs()[0].removed, Some(reason));
+}
+
+async fn add_tlc_with_preimage(
+    node: &NetworkNode,
+    channel_id: Hash256,
+    preimage: [u8; 32],
+    previous_tlc: Option<(Hash256, u64)>,
+) -> u64 {
+    call!(node.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: Some(HashAlgorithm::CkbHash.hash(preimage).into()),
+                        expiry: LockTime::new(100),
+                        preimage: None,
+                        onion_packet: vec![],
+                        previous_tlc,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node alive")
+    .expect("successfully added tlc")
+    .tlc_id
 }
 
 #[tokio::test]
-async fn test_stash_broadcast_messages() {
+async fn test_forget_settled_preimage_after_fulfillment_committed() {
     init_tracing();
 
-    let _span = tracing::info_span!("node", node = "test").entered();
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .fiber_config_updater(move |config| {
+                if i == 1 {
+                    config.forget_settled_preimages = Some(true);
+                }
+            })
+            .build()
+    })
+    .await;
+    let [node_a, node_b]: [NetworkNode; 2] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
+    };
+    let (node_a, node_b, channel_id) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, false).await;
 
-    let node_a_funding_amount = 100000000000;
-    let node_b_funding_amount = 6200000000;
+    let preimage = [7; 32];
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1000000000))
+        .payment_preimage(preimage.into())
+        .hash_algorithm(HashAlgorithm::CkbHash)
+        .build()
+        .expect("build invoice");
+    let payment_hash = *invoice.payment_hash();
+    node_b
+        .store
+        .insert_invoice(invoice, Some(preimage.into()))
+        .expect("insert invoice");
 
-    let (node_a, _node_b, _new_channel_id) =
-        create_nodes_with_established_channel(node_a_funding_amount, node_b_funding_amount, true)
+    add_tlc_with_preimage(&node_a, channel_id, preimage, None).await;
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    // The tlc is fulfilled and the removal is committed by both parties, the preimage is
+    // removed from the store and from the persisted channel state.
+    assert!(node_b.store.get_invoice_settled_at(&payment_hash).is_some());
+    assert!(node_b.store.get_invoice_preimage(&payment_hash).is_none());
+    let state = node_b
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel state");
+    let serialized = serde_json::to_string(&state).expect("serialize state");
+    assert!(!serialized.contains(&hex::encode(preimage)));
+    // The preimage is still known by the payer.
+    let state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel state");
+    let serialized = serde_json::to_string(&state).expect("serialize state");
+    assert!(serialized.contains(&hex::encode(preimage)));
+}
+
+#[tokio::test]
+async fn test_fail_back_forwarded_tlc_after_resolution_timeout() {
+    init_tracing();
+
+    // Node b fails back the forwarded tlcs after 10 blocks, if the downstream tlcs are failed.
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(3, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .fiber_config_updater(move |config| {
+                if i == 1 {
+                    config.tlc_resolution_timeout_blocks = Some(10);
+                }
+            })
+            .build()
+    })
+    .await;
+    let [node_a, node_b, node_c]: [NetworkNode; 3] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
+    };
+    let (node_a, node_b, channel_ab) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, false).await;
+    let (node_b, node_c, channel_bc) =
+        establish_channel_between_nodes(node_b, node_c, 100000000000, 6200000000, false).await;
+
+    // Node b forwards the tlc offered by node a to node c.
+    let forward_tlc = |preimage: [u8; 32]| {
+        let (node_a, node_b) = (&node_a, &node_b);
+        async move {
+            let received_tlc_id = add_tlc_with_preimage(node_a, channel_ab, preimage, None).await;
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let offered_tlc_id = add_tlc_with_preimage(
+                node_b,
+                channel_bc,
+                preimage,
+                Some((channel_ab, received_tlc_id)),
+            )
+            .await;
+            (received_tlc_id, offered_tlc_id)
+        }
+    };
+    let is_received_tlc_pending = |tlc_id: u64| {
+        node_b
+            .store
+            .get_channel_actor_state(&channel_ab)
+            .unwrap()
+            .is_received_tlc_pending(tlc_id)
+    };
+    let has_event = |event: ChannelLogEvent| {
+        node_b
+            .store
+            .get_channel_events(&channel_ab, None, usize::MAX)
+            .into_iter()
+            .any(|entry| entry.event == event)
+    };
+    let check_unresolved_tlcs = || {
+        let node_b = &node_b;
+        async move {
+            node_b
+                .network_actor
+                .send_message(NetworkActorMessage::new_command(
+                    NetworkActorCommand::FailUnresolvedForwardedTlcs,
+                ))
+                .expect("node_b alive");
+            tokio::time::sleep(Duration::from_secs(3)).await;
+        }
+    };
+
+    // The received tlc is kept while the downstream tlc is pending, and fulfilled when
+    // the downstream tlc is fulfilled after the resolution timeout.
+    let preimage = [5; 32];
+    node_b.chain_tip.update(1000);
+    let (received_tlc_id, offered_tlc_id) = forward_tlc(preimage).await;
+    node_b.chain_tip.update(1010);
+    check_unresolved_tlcs().await;
+    assert!(is_received_tlc_pending(received_tlc_id));
+    call!(node_c.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: channel_bc,
+                command: ChannelCommand::RemoveTlc(
+                    RemoveTlcCommand {
+                        id: offered_tlc_id,
+                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+                            payment_preimage: preimage.into(),
+                        }),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_c alive")
+    .expect("successfully removed tlc");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(!is_received_tlc_pending(received_tlc_id));
+    assert!(has_event(ChannelLogEvent::TlcSettled {
+        tlc_id: TLCId::Received(received_tlc_id),
+    }));
+
+    // The received tlc is kept past the lock time of the downstream tlc, which may still be
+    // fulfilled then, and failed back once the downstream tlc is failed.
+    let (received_tlc_id, offered_tlc_id) = forward_tlc([6; 32]).await;
+    node_b.chain_tip.update(1110);
+    check_unresolved_tlcs().await;
+    assert!(is_received_tlc_pending(received_tlc_id));
+    call!(node_c.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: channel_bc,
+                command: ChannelCommand::RemoveTlc(
+                    RemoveTlcCommand {
+                        id: offered_tlc_id,
+                        reason: RemoveTlcReason::RemoveTlcFail(TlcErrPacket::new(TlcErr::new(
+                            TlcErrorCode::IncorrectOrUnknownPaymentDetails,
+                        ))),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_c alive")
+    .expect("successfully removed tlc");
+    check_unresolved_tlcs().await;
+    assert!(!is_received_tlc_pending(received_tlc_id));
+    assert!(has_event(ChannelLogEvent::TlcFailed {
+        tlc_id: TLCId::Received(received_tlc_id),
+    }));
+}
+
+#[tokio::test]
+async fn test_manual_fail_back_refused_until_downstream_tlc_failed() {
+    init_tracing();
+
+    let [node_a, node_b, node_c] = NetworkNode::new_n_interconnected_nodes().await;
+    let (node_a, node_b, channel_ab) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, false).await;
+    let (node_b, node_c, channel_bc) =
+        establish_channel_between_nodes(node_b, node_c, 100000000000, 6200000000, false).await;
+
+    let preimage = [7; 32];
+    node_b.chain_tip.update(1000);
+    let received_tlc_id = add_tlc_with_preimage(&node_a, channel_ab, preimage, None).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let offered_tlc_id = add_tlc_with_preimage(
+        &node_b,
+        channel_bc,
+        preimage,
+        Some((channel_ab, received_tlc_id)),
+    )
+    .await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let fail_forwarded_tlc = |tlc_id: u64| {
+        let node_b = &node_b;
+        async move {
+            call!(node_b.network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::FailForwardedTlc(
+                    channel_ab, tlc_id, rpc_reply,
+                ))
+            })
+            .expect("node_b alive")
+        }
+    };
+
+    // The downstream tlc may still be fulfilled past the block at which its lock time elapses.
+    node_b.chain_tip.update(1100);
+    let error = fail_forwarded_tlc(received_tlc_id).await.unwrap_err();
+    assert!(error.contains("is fulfilled or still pending"), "{}", error);
+    // The downstream tlc of an unknown received tlc can't be checked.
+    let error = fail_forwarded_tlc(received_tlc_id + 1).await.unwrap_err();
+    assert!(error.contains("is not found"), "{}", error);
+
+    // The late fulfillment is relayed to the previous hop, so the forwarded amount is claimed.
+    call!(node_c.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: channel_bc,
+                command: ChannelCommand::RemoveTlc(
+                    RemoveTlcCommand {
+                        id: offered_tlc_id,
+                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+                            payment_preimage: preimage.into(),
+                        }),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_c alive")
+    .expect("successfully removed tlc");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(!node_b
+        .store
+        .get_channel_actor_state(&channel_ab)
+        .unwrap()
+        .is_received_tlc_pending(received_tlc_id));
+    assert!(node_b
+        .store
+        .get_channel_events(&channel_ab, None, usize::MAX)
+        .into_iter()
+        .any(|entry| entry.event
+            == ChannelLogEvent::TlcSettled {
+                tlc_id: TLCId::Received(received_tlc_id),
+            }));
+    assert!(fail_forwarded_tlc(received_tlc_id).await.is_err());
+}
+
+#[test]
+fn test_forwarding_tlc_fail_back_reason() {
+    let downstream_failure = RemoveTlcReason::RemoveTlcFail(TlcErrPacket::new(TlcErr::new(
+        TlcErrorCode::IncorrectOrUnknownPaymentDetails,
+    )));
+    let tlc = |removed: Option<RemoveTlcReason>| ForwardingTlc {
+        tlc_id: TLCId::Offered(0),
+        previous_tlc: (gen_sha256_hash(), TLCId::Received(0)),
+        added_at_block: None,
+        removed,
+    };
+
+    // A pending downstream tlc may still be fulfilled, however long it has been pending.
+    assert_eq!(tlc(None).fail_back_reason(), None);
+
+    // A removed downstream tlc is fulfilled or its failure is relayed as is.
+    let fulfill = RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+        payment_preimage: gen_sha256_hash(),
+    });
+    assert_eq!(tlc(Some(fulfill)).fail_back_reason(), None);
+    assert_eq!(
+        tlc(Some(downstream_failure.clone())).fail_back_reason(),
+        Some(downstream_failure)
+    );
+}
+
+#[test]
+fn test_channel_connection_stats() {
+    let mut stats = ChannelConnectionStats::connected_since(1000);
+    assert_eq!(stats.total_connected_duration(1500), 500);
+
+    stats.on_peer_disconnected(2000);
+    assert_eq!(stats.connected_at, None);
+    assert_eq!(stats.last_disconnected_at, Some(2000));
+    assert_eq!(stats.disconnection_count, 1);
+    assert_eq!(stats.total_connected_duration(5000), 1000);
+
+    stats.on_peer_connected(6000);
+    assert_eq!(stats.total_connected_duration(6500), 1500);
+    stats.on_peer_disconnected(7000);
+    assert_eq!(stats.last_disconnected_at, Some(7000));
+    assert_eq!(stats.disconnection_count, 2);
+    assert_eq!(stats.total_connected_duration(8000), 2000);
+}
+
+#[tokio::test]
+async fn test_open_channel_to_peer() {
+    let [node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_locktime_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_max_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: None,
+                open_timeout_seconds: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let _open_channel_result = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("open channel success");
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, channel_id) => {
+                println!("A channel ({:?}) to {:?} create", channel_id, peer_id);
+                assert_eq!(peer_id, &node_a.peer_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_open_channel_with_insufficient_external_funding_tx() {
+    let [node_a, node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let external_funding_tx = Transaction::default()
+        .as_advanced_builder()
+        .input(CellInput::new_builder().build())
+        .output(
+            CellOutput::new_builder()
+                .capacity(100u64.pack())
+                .lock(Script::default())
+                .build(),
+        )
+        .output_data(Default::default())
+        .witness(vec![1u8; 85].pack())
+        .build()
+        .data();
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_locktime_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_max_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: Some(external_funding_tx),
+                open_timeout_seconds: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let open_channel_result = call!(node_a.network_actor, message).expect("node_a alive");
+    assert!(open_channel_result
+        .expect_err("open channel should fail")
+        .contains("Invalid external funding tx"));
+}
+
+#[tokio::test]
+async fn test_open_channel_with_external_funding_tx() {
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    // The cell of the external wallet which funds the channel.
+    let wallet_lock = get_script_by_contract(Contract::Secp256k1Lock, &[1u8; 20]);
+    let wallet_tx = Transaction::default()
+        .as_advanced_builder()
+        .output(
+            CellOutput::new_builder()
+                .capacity(200000000000u64.pack())
+                .lock(wallet_lock)
+                .build(),
+        )
+        .output_data(Default::default())
+        .build();
+    assert_eq!(node_a.submit_tx(wallet_tx.clone()).await, Status::Committed);
+
+    let external_funding_tx = Transaction::default()
+        .as_advanced_builder()
+        .input(CellInput::new(wallet_tx.output_pts()[0].clone(), 0))
+        .cell_deps(get_cell_deps_by_contracts(vec![Contract::Secp256k1Lock]))
+        .output(
+            CellOutput::new_builder()
+                .capacity(200000000000u64.pack())
+                .lock(node_a.default_shutdown_script.clone())
+                .build(),
+        )
+        .output_data(Default::default())
+        .witness(vec![1u8; 85].pack())
+        .build();
+    // Each node has its own mock chain, which must know the external funding cells to accept the
+    // funding transaction.
+    assert_eq!(node_b.submit_tx(wallet_tx.clone()).await, Status::Committed);
+    assert_eq!(
+        node_b.submit_tx(external_funding_tx.clone()).await,
+        Status::Committed
+    );
+    let network_actor = node_a.network_actor.clone();
+    let peer_id = node_b.peer_id.clone();
+    let open_channel = |external_funding_tx: Transaction| {
+        let network_actor = network_actor.clone();
+        let peer_id = peer_id.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+                    OpenChannelCommand {
+                        peer_id,
+                        public: false,
+                        shutdown_script: None,
+                        funding_amount: 100000000000,
+                        funding_udt_type_script: None,
+                        commitment_fee_rate: None,
+                        commitment_delay_epoch: None,
+                        funding_fee_rate: None,
+                        tlc_locktime_expiry_delta: None,
+                        tlc_min_value: None,
+                        tlc_max_value: None,
+                        tlc_fee_proportional_millionths: None,
+                        max_tlc_number_in_flight: None,
+                        max_tlc_value_in_flight: None,
+                        max_inbound_tlc_value: None,
+                        max_outbound_tlc_value: None,
+                        external_funding_tx: Some(external_funding_tx),
+                        open_timeout_seconds: None,
+                        zero_conf: None,
+                    },
+                    rpc_reply,
+                ))
+            })
+            .expect("node_a alive")
+        }
+    };
+    let open_channel_result = open_channel(external_funding_tx.data())
+        .await
+        .expect("open channel success");
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, _) if peer_id == &node_a.peer_id)
+        })
+        .await;
+    let accept_channel_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: 6200000000,
+                shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_b alive")
+    .expect("accept channel success");
+    let channel_id = accept_channel_result.new_channel_id;
+    node_a
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::ChannelReady(_, id, _) if id == &channel_id)
+        })
+        .await;
+
+    // The funding transaction spends the cell created by the external funding tx.
+    let funding_tx = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .and_then(|state| state.funding_tx)
+        .expect("funding tx");
+    assert!(funding_tx
+        .into_view()
+        .input_pts_iter()
+        .any(|out_point| out_point == external_funding_tx.output_pts()[0]));
+
+    // The cell is spent, so the external funding tx can't be used again.
+    assert!(open_channel(external_funding_tx.data()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_open_and_accept_channel() {
+    let [node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_locktime_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_max_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: None,
+                open_timeout_seconds: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let open_channel_result = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("open channel success");
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, channel_id) => {
+                println!("A channel ({:?}) to {:?} create", &channel_id, peer_id);
+                assert_eq!(peer_id, &node_a.peer_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: DEFAULT_CHANNEL_MINIMAL_CKB_AMOUNT as u128,
+                shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+
+    let _accept_channel_result = call!(node_b.network_actor, message)
+        .expect("node_b alive")
+        .expect("accept channel success");
+}
+
+#[tokio::test]
+async fn test_get_channel_open_status() {
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_locktime_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_max_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: None,
+                open_timeout_seconds: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let temp_channel_id = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("open channel success")
+        .channel_id;
+
+    node_b
+        .expect_event(|event| {
+            matches!(
+                event,
+                NetworkServiceEvent::ChannelPendingToBeAccepted(_, channel_id) if channel_id == &temp_channel_id
+            )
+        })
+        .await;
+
+    let get_channel_open_status = |node: &NetworkNode, channel_id| {
+        let network_actor = node.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| NetworkActorMessage::Command(
+                NetworkActorCommand::GetChannelOpenStatus(channel_id, rpc_reply)
+            ))
+            .expect("node alive")
+        }
+    };
+    for node in [&node_a, &node_b] {
+        let status = get_channel_open_status(node, temp_channel_id)
+            .await
+            .expect("channel found");
+        assert_eq!(status.channel_id, temp_channel_id);
+        assert_eq!(status.step, ChannelOpenStep::Negotiating);
+        assert_eq!(status.state, None);
+        assert_eq!(status.error, None);
+    }
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id,
+                funding_amount: DEFAULT_CHANNEL_MINIMAL_CKB_AMOUNT as u128,
+                shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let new_channel_id = call!(node_b.network_actor, message)
+        .expect("node_b alive")
+        .expect("accept channel success")
+        .new_channel_id;
+
+    for node in [&mut node_a, &mut node_b] {
+        node.expect_event(|event| {
+            matches!(
+                event,
+                NetworkServiceEvent::ChannelReady(_, channel_id, _) if channel_id == &new_channel_id
+            )
+        })
+        .await;
+    }
+    for node in [&node_a, &node_b] {
+        for channel_id in [temp_channel_id, new_channel_id] {
+            let status = get_channel_open_status(node, channel_id)
+                .await
+                .expect("channel found");
+            assert_eq!(status.channel_id, new_channel_id);
+            assert_eq!(status.step, ChannelOpenStep::Ready);
+            assert_eq!(status.state, Some(ChannelState::ChannelReady()));
+            assert_eq!(status.error, None);
+        }
+    }
+
+    assert!(get_channel_open_status(&node_a, Hash256::default())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_get_channel_funding_address() {
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_locktime_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_max_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: None,
+                open_timeout_seconds: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let temp_channel_id = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("open channel success")
+        .channel_id;
+
+    node_b
+        .expect_event(|event| {
+            matches!(
+                event,
+                NetworkServiceEvent::ChannelPendingToBeAccepted(_, channel_id) if channel_id == &temp_channel_id
+            )
+        })
+        .await;
+
+    let get_channel_funding_address = |node: &NetworkNode, channel_id| {
+        let network_actor = node.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| NetworkActorMessage::Command(
+                NetworkActorCommand::GetChannelFundingAddress(channel_id, rpc_reply)
+            ))
+            .expect("node alive")
+        }
+    };
+    // The funding pubkey of the peer is unknown before the channel is accepted.
+    assert!(get_channel_funding_address(&node_a, temp_channel_id)
+        .await
+        .is_err());
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id,
+                funding_amount: DEFAULT_CHANNEL_MINIMAL_CKB_AMOUNT as u128,
+                shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let new_channel_id = call!(node_b.network_actor, message)
+        .expect("node_b alive")
+        .expect("accept channel success")
+        .new_channel_id;
+
+    for node in [&mut node_a, &mut node_b] {
+        node.expect_event(|event| {
+            matches!(
+                event,
+                NetworkServiceEvent::ChannelReady(_, channel_id, _) if channel_id == &new_channel_id
+            )
+        })
+        .await;
+    }
+
+    let funding_tx = node_a
+        .store
+        .get_channel_actor_state(&new_channel_id)
+        .expect("channel saved")
+        .funding_tx
+        .expect("funding tx built");
+    let funding_cell = funding_tx.raw().outputs().get(0).expect("funding cell");
+    for (node, channel_id) in [(&node_a, temp_channel_id), (&node_b, new_channel_id)] {
+        let funding_address = get_channel_funding_address(node, channel_id)
+            .await
+            .expect("funding address derived");
+        assert_eq!(funding_address.channel_id, new_channel_id);
+        assert_eq!(funding_address.funding_script, funding_cell.lock());
+        assert_eq!(funding_address.funding_udt_type_script, None);
+        assert_eq!(
+            funding_address.ckb_amount,
+            Unpack::<u64>::unpack(&funding_cell.capacity())
+        );
+        assert_eq!(funding_address.udt_amount, None);
+    }
+}
+
+#[tokio::test]
+async fn test_open_zero_conf_channel() {
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let open_channel_command = |public| OpenChannelCommand {
+        peer_id: node_b.peer_id.clone(),
+        public,
+        shutdown_script: None,
+        funding_amount: 100000000000,
+        funding_udt_type_script: None,
+        commitment_fee_rate: None,
+        commitment_delay_epoch: None,
+        funding_fee_rate: None,
+        tlc_locktime_expiry_delta: None,
+        tlc_min_value: None,
+        tlc_max_value: None,
+        tlc_fee_proportional_millionths: None,
+        max_tlc_number_in_flight: None,
+        max_tlc_value_in_flight: None,
+        max_inbound_tlc_value: None,
+        max_outbound_tlc_value: None,
+        external_funding_tx: None,
+        open_timeout_seconds: None,
+        zero_conf: Some(true),
+    };
+
+    // Zero-conf public channels are rejected as the announcement requires a confirmed funding.
+    let command = open_channel_command(true);
+    let result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(command, rpc_reply))
+    })
+    .expect("node_a alive");
+    assert!(result.is_err());
+
+    let command = open_channel_command(false);
+    let temp_channel_id = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(command, rpc_reply))
+    })
+    .expect("node_a alive")
+    .expect("open channel success")
+    .channel_id;
+
+    node_b
+        .expect_event(|event| {
+            matches!(
+                event,
+                NetworkServiceEvent::ChannelPendingToBeAccepted(_, channel_id) if channel_id == &temp_channel_id
+            )
+        })
+        .await;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id,
+                funding_amount: DEFAULT_CHANNEL_MINIMAL_CKB_AMOUNT as u128,
+                shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: Some(true),
+            },
+            rpc_reply,
+        ))
+    };
+    let new_channel_id = call!(node_b.network_actor, message)
+        .expect("node_b alive")
+        .expect("accept channel success")
+        .new_channel_id;
+
+    for node in [&mut node_a, &mut node_b] {
+        node.expect_event(|event| {
+            matches!(
+                event,
+                NetworkServiceEvent::ChannelReady(_, channel_id, _) if channel_id == &new_channel_id
+            )
+        })
+        .await;
+    }
+
+    for node in [&node_a, &node_b] {
+        let state = node
+            .store
+            .get_channel_actor_state(&new_channel_id)
+            .expect("channel found");
+        assert!(state.zero_conf);
+        assert_eq!(state.state, ChannelState::ChannelReady());
+    }
+
+    // The funds are no longer at risk once the funding transaction is confirmed.
+    for node in [&node_a, &node_b] {
+        let mut confirmed = false;
+        for _ in 0..50 {
+            let state = node
+                .store
+                .get_channel_actor_state(&new_channel_id)
+                .expect("channel found");
+            if !state.is_funding_at_risk() {
+                confirmed = true;
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        }
+        assert!(confirmed);
+    }
+}
+
+#[tokio::test]
+async fn test_open_zero_conf_channel_declined_by_acceptor() {
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let command = OpenChannelCommand {
+        peer_id: node_b.peer_id.clone(),
+        public: false,
+        shutdown_script: None,
+        funding_amount: 100000000000,
+        funding_udt_type_script: None,
+        commitment_fee_rate: None,
+        commitment_delay_epoch: None,
+        funding_fee_rate: None,
+        tlc_locktime_expiry_delta: None,
+        tlc_min_value: None,
+        tlc_max_value: None,
+        tlc_fee_proportional_millionths: None,
+        max_tlc_number_in_flight: None,
+        max_tlc_value_in_flight: None,
+        max_inbound_tlc_value: None,
+        max_outbound_tlc_value: None,
+        external_funding_tx: None,
+        open_timeout_seconds: None,
+        zero_conf: Some(true),
+    };
+    let temp_channel_id = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(command, rpc_reply))
+    })
+    .expect("node_a alive")
+    .expect("open channel success")
+    .channel_id;
+
+    node_b
+        .expect_event(|event| {
+            matches!(
+                event,
+                NetworkServiceEvent::ChannelPendingToBeAccepted(_, channel_id) if channel_id == &temp_channel_id
+            )
+        })
+        .await;
+
+    // Node b accepts the channel without agreeing to use it before the confirmation.
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id,
+                funding_amount: DEFAULT_CHANNEL_MINIMAL_CKB_AMOUNT as u128,
+                shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: Some(false),
+            },
+            rpc_reply,
+        ))
+    };
+    let new_channel_id = call!(node_b.network_actor, message)
+        .expect("node_b alive")
+        .expect("accept channel success")
+        .new_channel_id;
+
+    for node in [&mut node_a, &mut node_b] {
+        node.expect_event(|event| {
+            matches!(
+                event,
+                NetworkServiceEvent::ChannelReady(_, channel_id, _) if channel_id == &new_channel_id
+            )
+        })
+        .await;
+    }
+
+    // Neither side treats the channel as zero-conf, it's ready only after the confirmation.
+    for node in [&node_a, &node_b] {
+        let state = node
+            .store
+            .get_channel_actor_state(&new_channel_id)
+            .expect("channel found");
+        assert!(!state.zero_conf);
+        assert!(state.funding_tx_confirmed_at.is_some());
+        assert!(!state.is_funding_at_risk());
+    }
+}
+
+#[tokio::test]
+async fn test_trusted_funding_peer() {
+    init_tracing();
+
+    let node_b = NetworkNode::new_with_config(NetworkNodeConfigBuilder::new().build()).await;
+    // Node a treats the funding transactions with node b as confirmed once committed.
+    let trusted_peer = node_b.peer_id.to_base58();
+    let mut node_a = NetworkNode::new_with_config(
+        NetworkNodeConfigBuilder::new()
+            .fiber_config_updater(move |config| {
+                config.trusted_funding_peers = vec![trusted_peer.clone()];
+                config.trusted_funding_confirmations = Some(0);
+            })
+            .build(),
+    )
+    .await;
+    node_a.connect_to(&node_b).await;
+
+    let (node_a, node_b, channel_id) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, false).await;
+
+    // The funds of node a are at risk until the funding transaction reaches the full confirmations.
+    let state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel found");
+    assert!(state.trusted_funding_pending);
+    assert!(state.is_funding_at_risk());
+    assert_eq!(
+        state.get_funding_at_risk_amount(),
+        state.get_local_balance()
+    );
+    let node_info = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::NodeInfo((), rpc_reply))
+    })
+    .expect("node_a alive")
+    .expect("node info");
+    assert_eq!(node_info.funding_at_risk_amount, state.get_local_balance());
+
+    // Node b doesn't trust node a, so it waits for the full confirmations.
+    let state = node_b
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel found");
+    assert!(!state.trusted_funding_pending);
+    assert_eq!(state.get_funding_at_risk_amount(), 0);
+
+    node_a
+        .network_actor
+        .send_message(NetworkActorMessage::new_event(
+            NetworkActorEvent::FundingTransactionFullyConfirmed(channel_id),
+        ))
+        .expect("node_a alive");
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    let state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel found");
+    assert!(!state.is_funding_at_risk());
+    assert_eq!(state.get_funding_at_risk_amount(), 0);
+}
+
+#[tokio::test]
+async fn test_channel_inherits_peer_fee_policy() {
+    let [node_a, node_b] = NetworkNode::new_n_interconnected_nodes().await;
+    node_a.store.set_peer_fee_policy(
+        &node_b.peer_id,
+        Some(PeerFeePolicy {
+            tlc_fee_proportional_millionths: Some(2000),
+            ..Default::default()
+        }),
+    );
+    node_b.store.set_peer_fee_policy(
+        &node_a.peer_id,
+        Some(PeerFeePolicy {
+            tlc_locktime_expiry_delta: Some(86400000),
+            tlc_fee_proportional_millionths: Some(3000),
+            ..Default::default()
+        }),
+    );
+    let (node_a, node_b, channel_id) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, true).await;
+
+    let info_a = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel found")
+        .public_channel_info
+        .expect("public channel");
+    assert_eq!(info_a.tlc_fee_proportional_millionths, Some(2000));
+    assert_eq!(
+        info_a.tlc_locktime_expiry_delta,
+        Some(node_a.fiber_config.tlc_locktime_expiry_delta())
+    );
+
+    let info_b = node_b
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel found")
+        .public_channel_info
+        .expect("public channel");
+    assert_eq!(info_b.tlc_fee_proportional_millionths, Some(3000));
+    assert_eq!(info_b.tlc_locktime_expiry_delta, Some(86400000));
+}
+
+#[tokio::test]
+async fn test_abort_channel_open_after_timeout() {
+    let [node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_locktime_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_max_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: None,
+                open_timeout_seconds: Some(1),
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let temp_channel_id = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("open channel success")
+        .channel_id;
+
+    node_b
+        .expect_event(|event| {
+            matches!(
+                event,
+                NetworkServiceEvent::ChannelPendingToBeAccepted(_, channel_id) if channel_id == &temp_channel_id
+            )
+        })
+        .await;
+
+    // node_b never accepts the channel, so the open is aborted by node_a after the timeout.
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+
+    let status = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::GetChannelOpenStatus(
+            temp_channel_id,
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("channel found");
+    assert_eq!(status.step, ChannelOpenStep::Aborted);
+    assert_eq!(
+        status.state,
+        Some(ChannelState::Closed(CloseFlags::ABORTED))
+    );
+    assert!(status.error.is_some());
+
+    // The aborted channel is archived without a closing transaction.
+    let (closed_channels, _) = node_a.store.get_closed_channels(10, None, None, None);
+    assert_eq!(closed_channels.len(), 1);
+    assert_eq!(closed_channels[0].channel_id, temp_channel_id);
+    assert_eq!(closed_channels[0].close_flags, CloseFlags::ABORTED);
+    assert_eq!(closed_channels[0].closing_tx_hash, None);
+
+    // The pending channel is forgotten by node_b once it receives the tx_abort.
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id,
+                funding_amount: DEFAULT_CHANNEL_MINIMAL_CKB_AMOUNT as u128,
+                shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    assert!(call!(node_b.network_actor, message)
+        .expect("node_b alive")
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_create_public_channel() {
+    init_tracing();
+
+    let _span = tracing::info_span!("node", node = "test").entered();
+
+    let node_a_funding_amount = 100000000000;
+    let node_b_funding_amount = 6200000000;
+
+    let (_node_a, _node_b, _new_channel_id) =
+        create_nodes_with_established_channel(node_a_funding_amount, node_b_funding_amount, true)
+            .await;
+    // Wait for the channel announcement to be broadcasted
+    tokio::time::sleep(tokio::time::Duration::from_millis(5000)).await;
+    // FIXME: add assertion
+}
+
+#[tokio::test]
+async fn test_private_channel_not_gossiped() {
+    init_tracing();
+
+    let node_a_funding_amount = 100000000000;
+    let node_b_funding_amount = 6200000000;
+
+    let (node_a, node_b, new_channel_id) =
+        create_nodes_with_established_channel(node_a_funding_amount, node_b_funding_amount, false)
+            .await;
+    // Give the nodes a chance to (wrongly) gossip the channel.
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let state = node_a
+        .store
+        .get_channel_actor_state(&new_channel_id)
+        .expect("channel state");
+    assert!(!state.is_public());
+    let channel_outpoint = state.get_funding_transaction_outpoint();
+
+    for node in [&node_a, &node_b] {
+        // The channel is not in the network graph of either party.
+        assert!(node
+            .store
+            .get_channels(Some(channel_outpoint.clone()))
+            .is_empty());
+        // But it is still listed as one of the node's own channels.
+        assert!(node
+            .store
+            .get_active_channel_states(None)
+            .iter()
+            .any(|(_, channel_id, _)| channel_id == &new_channel_id));
+    }
+}
+
+#[tokio::test]
+async fn test_rebroadcast_channel_announcement() {
+    init_tracing();
+
+    let rebroadcast = |node: &NetworkNode, channel_id: Hash256| {
+        let network_actor = node.network_actor.clone();
+        async move {
+            let message = |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::RebroadcastChannelAnnouncement(
+                    channel_id, rpc_reply,
+                ))
+            };
+            call!(network_actor, message).expect("node alive")
+        }
+    };
+
+    let (node_a, _node_b, public_channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, true).await;
+    // Wait for both parties to sign the channel announcement.
+    let mut signed = false;
+    for _ in 0..50 {
+        signed = node_a
+            .store
+            .get_channel_actor_state(&public_channel_id)
+            .and_then(|state| state.public_channel_info)
+            .and_then(|info| info.channel_announcement)
+            .is_some_and(|announcement| announcement.is_signed());
+        if signed {
+            break;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    }
+    assert!(signed);
+    let old_update = node_a
+        .store
+        .get_channel_actor_state(&public_channel_id)
+        .and_then(|state| state.public_channel_info)
+        .and_then(|info| info.channel_update)
+        .expect("channel update");
+
+    // The channel update timestamp is in seconds, make sure the re-signed one is newer.
+    tokio::time::sleep(tokio::time::Duration::from_millis(1100)).await;
+    rebroadcast(&node_a, public_channel_id)
+        .await
+        .expect("rebroadcast public channel");
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    let new_update = node_a
+        .store
+        .get_channel_actor_state(&public_channel_id)
+        .and_then(|state| state.public_channel_info)
+        .and_then(|info| info.channel_update)
+        .expect("channel update");
+    assert!(new_update.version > old_update.version);
+
+    // Rebroadcasting the same channel again is rate limited.
+    let error = rebroadcast(&node_a, public_channel_id)
+        .await
+        .expect_err("rate limited");
+    assert!(error.contains("too frequently"), "{}", error);
+
+    let (node_c, _node_d, private_channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
+    let error = rebroadcast(&node_c, private_channel_id)
+        .await
+        .expect_err("private channel");
+    assert!(error.contains("not public"), "{}", error);
+
+    let error = rebroadcast(&node_c, Hash256::default())
+        .await
+        .expect_err("unknown channel");
+    assert!(error.contains("not found"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_stash_broadcast_messages() {
+    init_tracing();
+
+    let _span = tracing::info_span!("node", node = "test").entered();
+
+    let node_a_funding_amount = 100000000000;
+    let node_b_funding_amount = 6200000000;
+
+    let (node_a, _node_b, _new_channel_id) =
+        create_nodes_with_established_channel(node_a_funding_amount, node_b_funding_amount, true)
             .await;
 
     // Mark sync done for node_a after 1 second
     node_a
         .network_actor
-        .send_after(ractor::concurrency::Duration::from_secs(1), || {
-            NetworkActorMessage::new_command(NetworkActorCommand::MarkSyncingDone)
-        });
+        .send_after(ractor::concurrency::Duration::from_secs(1), || {
+            NetworkActorMessage::new_command(NetworkActorCommand::MarkSyncingDone)
+        });
+
+    // Wait for the channel announcement to be broadcasted
+    tokio::time::sleep(tokio::time::Duration::from_millis(5000)).await;
+}
+
+async fn do_test_channel_commitment_tx_after_add_tlc(algorithm: HashAlgorithm) {
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let node_a_funding_amount = 100000000000;
+    let node_b_funidng_amount = 6200000000;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: node_a_funding_amount,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_locktime_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_max_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: None,
+                open_timeout_seconds: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let open_channel_result = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("open channel success");
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, channel_id) => {
+                println!("A channel ({:?}) to {:?} create", &channel_id, peer_id);
+                assert_eq!(peer_id, &node_a.peer_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: node_b_funidng_amount,
+                shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let accept_channel_result = call!(node_b.network_actor, message)
+        .expect("node_b alive")
+        .expect("accept channel success");
+    let new_channel_id = accept_channel_result.new_channel_id;
+
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelReady(peer_id, channel_id, _funding_tx_hash) => {
+                println!(
+                    "A channel ({:?}) to {:?} is now ready",
+                    &channel_id, &peer_id
+                );
+                assert_eq!(peer_id, &node_b.peer_id);
+                assert_eq!(channel_id, &new_channel_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelReady(peer_id, channel_id, _funding_tx_hash) => {
+                println!(
+                    "A channel ({:?}) to {:?} is now ready",
+                    &channel_id, &peer_id
+                );
+                assert_eq!(peer_id, &node_a.peer_id);
+                assert_eq!(channel_id, &new_channel_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+
+    let preimage = [1; 32];
+    let digest = algorithm.hash(&preimage);
+    let tlc_amount = 1000000000;
+
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: tlc_amount,
+                        hash_algorithm: algorithm,
+                        payment_hash: Some(digest.into()),
+                        expiry: LockTime::new(100),
+                        preimage: None,
+                        onion_packet: vec![],
+                        previous_tlc: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully added tlc");
+
+    dbg!(&add_tlc_result);
+
+    // Since we currently automatically send a `CommitmentSigned` message
+    // after sending a `AddTlc` message, we can expect the `RemoteCommitmentSigned`
+    // to be received by node b.
+    let node_b_commitment_tx = node_b
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::RemoteCommitmentSigned(peer_id, channel_id, num, tx) => {
+                println!(
+                    "Commitment tx (#{}) {:?} from {:?} for channel {:?} received",
+                    num, &tx, peer_id, channel_id
+                );
+                assert_eq!(peer_id, &node_a.peer_id);
+                assert_eq!(channel_id, &new_channel_id);
+                Some(tx.clone())
+            }
+            _ => None,
+        })
+        .await;
+
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::RemoveTlc(
+                    RemoveTlcCommand {
+                        id: add_tlc_result.tlc_id,
+                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+                            payment_preimage: preimage.into(),
+                        }),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully removed tlc");
+
+    // Since we currently automatically send a `CommitmentSigned` message
+    // after sending a `RemoveTlc` message, we can expect the `RemoteCommitmentSigned`
+    // to be received by node a.
+    let node_a_commitment_tx = node_a
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::RemoteCommitmentSigned(peer_id, channel_id, num, tx) => {
+                println!(
+                    "Commitment tx (#{}) {:?} from {:?} for channel {:?} received",
+                    num, &tx, peer_id, channel_id
+                );
+                assert_eq!(peer_id, &node_b.peer_id);
+                assert_eq!(channel_id, &new_channel_id);
+                Some(tx.clone())
+            }
+            _ => None,
+        })
+        .await;
+
+    assert_eq!(
+        node_a.submit_tx(node_a_commitment_tx.clone()).await,
+        Status::Committed
+    );
+
+    assert_eq!(
+        node_b.submit_tx(node_b_commitment_tx.clone()).await,
+        Status::Committed
+    );
+}
+
+#[tokio::test]
+async fn test_channel_commitment_tx_after_add_tlc_ckbhash() {
+    do_test_channel_commitment_tx_after_add_tlc(HashAlgorithm::CkbHash).await
+}
+
+#[tokio::test]
+async fn test_channel_commitment_tx_after_add_tlc_sha256() {
+    do_test_channel_commitment_tx_after_add_tlc(HashAlgorithm::Sha256).await
+}
+
+async fn create_nodes_with_established_channel(
+    node_a_funding_amount: u128,
+    node_b_funding_amount: u128,
+    public: bool,
+) -> (NetworkNode, NetworkNode, Hash256) {
+    let [node_a, node_b] = NetworkNode::new_n_interconnected_nodes().await;
+    establish_channel_between_nodes(
+        node_a,
+        node_b,
+        node_a_funding_amount,
+        node_b_funding_amount,
+        public,
+    )
+    .await
+}
+
+async fn establish_channel_between_nodes(
+    node_a: NetworkNode,
+    node_b: NetworkNode,
+    node_a_funding_amount: u128,
+    node_b_funding_amount: u128,
+    public: bool,
+) -> (NetworkNode, NetworkNode, Hash256) {
+    establish_channel_between_nodes_with_tlc_value_caps(
+        node_a,
+        node_b,
+        node_a_funding_amount,
+        node_b_funding_amount,
+        public,
+        None,
+        None,
+    )
+    .await
+}
+
+// Create 3 nodes with the public channels a-b and b-c, which are known by all the nodes, so
+// that node a can pay node c through node b.
+async fn create_3_nodes_with_public_channels(
+    config_gen: impl Fn(usize) -> NetworkNodeConfig,
+) -> ([NetworkNode; 3], [Hash256; 2]) {
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(3, config_gen).await;
+    let [node_a, node_b, node_c]: [NetworkNode; 3] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
+    };
+    let (mut node_a, node_b, channel_ab) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 100000000000, true).await;
+    let (node_b, mut node_c, channel_bc) =
+        establish_channel_between_nodes(node_b, node_c, 100000000000, 100000000000, true).await;
+    // Wait for both parties to sign the channel announcements.
+    tokio::time::sleep(Duration::from_secs(2)).await;
+
+    // Each node has its own mock chain, the funding transactions must be found on the chain
+    // of the other node to accept the channel announcements.
+    let funding_tx = |channel_id| {
+        node_b
+            .store
+            .get_channel_actor_state(&channel_id)
+            .and_then(|state| state.funding_tx)
+            .expect("funding tx")
+            .into_view()
+    };
+    assert_eq!(
+        node_a.submit_tx(funding_tx(channel_bc)).await,
+        Status::Committed
+    );
+    assert_eq!(
+        node_c.submit_tx(funding_tx(channel_ab)).await,
+        Status::Committed
+    );
+    // The announcement must be accepted before the channel update of the other party.
+    for (node, channel_id) in [
+        (&node_a, channel_ab),
+        (&node_c, channel_bc),
+        (&node_b, channel_ab),
+        (&node_b, channel_bc),
+    ] {
+        call!(node.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::RebroadcastChannelAnnouncement(
+                channel_id, rpc_reply,
+            ))
+        })
+        .expect("node alive")
+        .expect("rebroadcast channel announcement");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    ([node_a, node_b, node_c], [channel_ab, channel_bc])
+}
+
+async fn send_keysend_payment(node: &NetworkNode, target: &NetworkNode) -> Hash256 {
+    let command = SendPaymentCommand {
+        target_pubkey: Some(target.fiber_config.public_key().into()),
+        amount: Some(10000000),
+        payment_hash: None,
+        final_cltv_delta: None,
+        invoice: None,
+        timeout: None,
+        max_fee_amount: None,
+        max_parts: None,
+        keysend: Some(true),
+        udt_type_script: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: None,
+    };
+    call!(node.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::SendPayment(command, rpc_reply))
+    })
+    .expect("node alive")
+    .expect("send payment")
+    .payment_hash
+}
+
+async fn get_payment_status(node: &NetworkNode, payment_hash: Hash256) -> PaymentSessionStatus {
+    call!(node.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::GetPayment(payment_hash, rpc_reply))
+    })
+    .expect("node alive")
+    .expect("get payment")
+    .status
+}
+
+// Node a limits the tlcs it offers, and node b limits the tlcs it receives.
+async fn establish_channel_between_nodes_with_tlc_value_caps(
+    mut node_a: NetworkNode,
+    mut node_b: NetworkNode,
+    node_a_funding_amount: u128,
+    node_b_funding_amount: u128,
+    public: bool,
+    node_a_max_outbound_tlc_value: Option<u128>,
+    node_b_max_inbound_tlc_value: Option<u128>,
+) -> (NetworkNode, NetworkNode, Hash256) {
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public,
+                shutdown_script: None,
+                funding_amount: node_a_funding_amount,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_locktime_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_max_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: node_a_max_outbound_tlc_value,
+                external_funding_tx: None,
+                open_timeout_seconds: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let open_channel_result = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("open channel success");
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, channel_id) => {
+                assert_eq!(peer_id, &node_a.peer_id);
+                assert_eq!(channel_id, &open_channel_result.channel_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: node_b_funding_amount,
+                shutdown_script: None,
+                max_inbound_tlc_value: node_b_max_inbound_tlc_value,
+                max_outbound_tlc_value: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let accept_channel_result = call!(node_b.network_actor, message)
+        .expect("node_b alive")
+        .expect("accept channel success");
+    let new_channel_id = accept_channel_result.new_channel_id;
+
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelReady(peer_id, channel_id, _funding_tx_hash) => {
+                assert_eq!(peer_id, &node_b.peer_id);
+                assert_eq!(channel_id, &new_channel_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelReady(peer_id, channel_id, _funding_tx_hash) => {
+                assert_eq!(peer_id, &node_a.peer_id);
+                assert_eq!(channel_id, &new_channel_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+    (node_a, node_b, new_channel_id)
+}
+
+async fn do_test_remove_tlc_with_wrong_hash_algorithm(
+    correct_algorithm: HashAlgorithm,
+    wrong_algorithm: HashAlgorithm,
+) {
+    let node_a_funding_amount = 100000000000;
+    let node_b_funding_amount = 6200000000;
+
+    let (node_a, node_b, new_channel_id) =
+        create_nodes_with_established_channel(node_a_funding_amount, node_b_funding_amount, false)
+            .await;
+
+    let preimage = [1; 32];
+    let digest = correct_algorithm.hash(&preimage);
+    let tlc_amount = 1000000000;
+
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: tlc_amount,
+                        hash_algorithm: correct_algorithm,
+                        payment_hash: Some(digest.into()),
+                        expiry: LockTime::new(100),
+                        preimage: None,
+                        onion_packet: vec![],
+                        previous_tlc: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully added tlc");
+
+    dbg!(&add_tlc_result);
+
+    dbg!("Sleeping for some time to wait for the AddTlc processed by both party");
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::RemoveTlc(
+                    RemoveTlcCommand {
+                        id: add_tlc_result.tlc_id,
+                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+                            payment_preimage: preimage.into(),
+                        }),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully removed tlc");
+
+    dbg!("Sleeping for some time to wait for the RemoveTlc processed by both party");
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: tlc_amount,
+                        hash_algorithm: wrong_algorithm,
+                        payment_hash: Some(digest.into()),
+                        expiry: LockTime::new(100),
+                        preimage: None,
+                        onion_packet: vec![],
+                        previous_tlc: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully added tlc");
+
+    dbg!(&add_tlc_result);
+
+    dbg!("Sleeping for some time to wait for the AddTlc processed by both party");
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    let remove_tlc_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::RemoveTlc(
+                    RemoveTlcCommand {
+                        id: add_tlc_result.tlc_id,
+                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+                            payment_preimage: preimage.into(),
+                        }),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive");
+
+    dbg!(&remove_tlc_result);
+    assert!(remove_tlc_result.is_err());
+}
+
+#[tokio::test]
+async fn test_remove_tlc_with_wrong_hash_algorithm() {
+    let supported_algorithms = HashAlgorithm::supported_algorithms();
+    for algorithm1 in &supported_algorithms {
+        for algorithm2 in &supported_algorithms {
+            if algorithm2 == algorithm1 {
+                continue;
+            }
+            do_test_remove_tlc_with_wrong_hash_algorithm(*algorithm1, *algorithm2).await;
+        }
+    }
+}
+
+async fn do_test_channel_with_simple_update_operation(algorithm: HashAlgorithm) {
+    let node_a_funding_amount = 100000000000;
+    let node_b_funding_amount = 6200000000;
+
+    let (mut node_a, mut node_b, new_channel_id) =
+        create_nodes_with_established_channel(node_a_funding_amount, node_b_funding_amount, false)
+            .await;
+
+    let preimage = [1; 32];
+    let digest = algorithm.hash(&preimage);
+    let tlc_amount = 1000000000;
+
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: tlc_amount,
+                        hash_algorithm: algorithm,
+                        payment_hash: Some(digest.into()),
+                        expiry: LockTime::new(100),
+                        preimage: None,
+                        onion_packet: vec![],
+                        previous_tlc: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully added tlc");
+
+    dbg!(&add_tlc_result);
+
+    dbg!("Sleeping for some time to wait for the AddTlc processed by both party");
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::RemoveTlc(
+                    RemoveTlcCommand {
+                        id: add_tlc_result.tlc_id,
+                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+                            payment_preimage: preimage.into(),
+                        }),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully removed tlc");
+
+    let fee_rate = FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE);
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::Shutdown(
+                    ShutdownCommand {
+                        close_script: Script::default().as_builder().build(),
+                        fee_rate,
+                        max_fee_rate: None,
+                        force: false,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully shutdown channel");
+
+    let node_a_shutdown_tx_hash = node_a
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::ChannelClosed(peer_id, channel_id, tx_hash) => {
+                println!(
+                    "Shutdown tx ({:?}) from {:?} for channel {:?} received",
+                    &tx_hash, &peer_id, channel_id
+                );
+                assert_eq!(peer_id, &node_b.peer_id);
+                assert_eq!(channel_id, &new_channel_id);
+                Some(tx_hash.clone())
+            }
+            _ => None,
+        })
+        .await;
+
+    dbg!(&node_a_shutdown_tx_hash);
+
+    let node_b_shutdown_tx_hash = node_b
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::ChannelClosed(peer_id, channel_id, tx_hash) => {
+                println!(
+                    "Shutdown tx ({:?}) from {:?} for channel {:?} received",
+                    &tx_hash, &peer_id, channel_id
+                );
+                assert_eq!(peer_id, &node_a.peer_id);
+                assert_eq!(channel_id, &new_channel_id);
+                Some(tx_hash.clone())
+            }
+            _ => None,
+        })
+        .await;
+
+    dbg!(&node_b_shutdown_tx_hash);
+
+    assert_eq!(node_a_shutdown_tx_hash, node_b_shutdown_tx_hash);
+
+    assert_eq!(
+        node_a.trace_tx_hash(node_a_shutdown_tx_hash.clone()).await,
+        Status::Committed
+    );
+    assert_eq!(
+        node_b.trace_tx_hash(node_b_shutdown_tx_hash.clone()).await,
+        Status::Committed
+    );
+
+    // TODO: maybe also check shutdown tx outputs and output balances here.
+}
+
+// Create two nodes with an established channel, node b only accepts shutdown fee rate
+// no less than the given min_shutdown_fee_rate.
+async fn create_nodes_with_min_shutdown_fee_rate(
+    min_shutdown_fee_rate: u64,
+) -> (NetworkNode, NetworkNode, Hash256) {
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .fiber_config_updater(move |config| {
+                if i == 1 {
+                    config.min_shutdown_fee_rate = Some(min_shutdown_fee_rate);
+                }
+            })
+            .build()
+    })
+    .await;
+    let [node_a, node_b]: [NetworkNode; 2] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
+    };
+    establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, false).await
+}
+
+async fn shutdown_channel(
+    node: &NetworkNode,
+    channel_id: Hash256,
+    fee_rate: u64,
+    max_fee_rate: Option<u64>,
+) {
+    call!(node.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::Shutdown(
+                    ShutdownCommand {
+                        close_script: Script::default(),
+                        fee_rate: FeeRate::from_u64(fee_rate),
+                        max_fee_rate: max_fee_rate.map(FeeRate::from_u64),
+                        force: false,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node alive")
+    .expect("successfully shutdown channel");
+}
+
+#[tokio::test]
+async fn test_shutdown_fee_rate_counter_proposal_accepted() {
+    init_tracing();
+
+    let (mut node_a, mut node_b, channel_id) = create_nodes_with_min_shutdown_fee_rate(2000).await;
+    shutdown_channel(&node_a, channel_id, 1000, Some(3000)).await;
+
+    let node_a_shutdown_tx_hash = node_a
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::ChannelClosed(_, id, tx_hash) if id == &channel_id => {
+                Some(tx_hash.clone())
+            }
+            _ => None,
+        })
+        .await;
+    let node_b_shutdown_tx_hash = node_b
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::ChannelClosed(_, id, tx_hash) if id == &channel_id => {
+                Some(tx_hash.clone())
+            }
+            _ => None,
+        })
+        .await;
+    assert_eq!(node_a_shutdown_tx_hash, node_b_shutdown_tx_hash);
+    assert_eq!(
+        node_a.trace_tx_hash(node_a_shutdown_tx_hash).await,
+        Status::Committed
+    );
+
+    // Node a raised its fee rate to the counter-proposed one, and pays the whole fee.
+    let state_a = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    let state_b = node_b.store.get_channel_actor_state(&channel_id).unwrap();
+    assert_eq!(state_a.local_shutdown_info.as_ref().unwrap().fee_rate, 2000);
+    assert_eq!(state_a.remote_shutdown_info.as_ref().unwrap().fee_rate, 0);
+    assert_eq!(state_b.local_shutdown_info.as_ref().unwrap().fee_rate, 0);
+    assert_eq!(
+        state_b.remote_shutdown_info.as_ref().unwrap().fee_rate,
+        2000
+    );
+    assert!(state_a.get_shutdown_fee().is_some());
+    assert_eq!(state_a.get_shutdown_fee(), state_b.get_shutdown_fee());
+
+    let closing_tx_hash = Some(node_b_shutdown_tx_hash.into());
+    assert_eq!(state_a.closing_tx_hash, closing_tx_hash);
+    assert_eq!(state_b.closing_tx_hash, closing_tx_hash);
+}
+
+#[tokio::test]
+async fn test_shutdown_fee_rate_counter_proposal_exceeds_tolerance() {
+    init_tracing();
+
+    let (mut node_a, mut node_b, channel_id) = create_nodes_with_min_shutdown_fee_rate(2000).await;
+    // The default tolerance only allows node a to pay up to 1100.
+    shutdown_channel(&node_a, channel_id, 1000, None).await;
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    let state_a = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    assert_eq!(
+        state_a.state,
+        ChannelState::ShuttingDown(ShuttingDownFlags::OUR_SHUTDOWN_SENT)
+    );
+    assert!(state_a.remote_shutdown_info.is_none());
+    assert_eq!(state_a.get_shutdown_fee(), None);
+    assert_eq!(
+        state_a
+            .pending_shutdown_fee_counter
+            .as_ref()
+            .map(|counter| counter.fee_rate),
+        Some(2000)
+    );
+
+    // Re-issuing the shutdown command with a high enough max fee rate accepts the counter-proposal.
+    shutdown_channel(&node_a, channel_id, 1000, Some(2000)).await;
+    let node_a_shutdown_tx_hash = node_a
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::ChannelClosed(_, id, tx_hash) if id == &channel_id => {
+                Some(tx_hash.clone())
+            }
+            _ => None,
+        })
+        .await;
+    let node_b_shutdown_tx_hash = node_b
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::ChannelClosed(_, id, tx_hash) if id == &channel_id => {
+                Some(tx_hash.clone())
+            }
+            _ => None,
+        })
+        .await;
+    assert_eq!(node_a_shutdown_tx_hash, node_b_shutdown_tx_hash);
+
+    let state_a = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    assert!(state_a.pending_shutdown_fee_counter.is_none());
+    assert_eq!(state_a.local_shutdown_info.as_ref().unwrap().fee_rate, 2000);
+    assert_eq!(state_a.remote_shutdown_info.as_ref().unwrap().fee_rate, 0);
+}
+
+#[tokio::test]
+async fn test_open_channel_rejected_by_acceptance_policy() {
+    init_tracing();
+
+    // Node b only accepts channels from a peer other than node a.
+    let allowed_peer = PeerId::random().to_base58();
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        let allowed_peer = allowed_peer.clone();
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .fiber_config_updater(move |config| {
+                if i == 1 {
+                    config.accept_channel_allowed_peers = vec![allowed_peer];
+                }
+            })
+            .build()
+    })
+    .await;
+    let [node_a, node_b]: [NetworkNode; 2] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
+    };
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_locktime_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_max_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: None,
+                open_timeout_seconds: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let open_channel_result = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("open channel success");
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: 6200000000,
+                shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let accept_channel_result = call!(node_b.network_actor, message).expect("node_b alive");
+    assert!(accept_channel_result
+        .err()
+        .unwrap()
+        .contains("No channel with temp id"));
+}
+
+#[tokio::test]
+async fn test_open_channel_rejected_by_max_accepted_funding_fee_rate() {
+    init_tracing();
+
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .fiber_config_updater(move |config| {
+                if i == 1 {
+                    config.max_accepted_funding_fee_rate = Some(2000);
+                }
+            })
+            .build()
+    })
+    .await;
+    let [node_a, mut node_b]: [NetworkNode; 2] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
+    };
+
+    let open_channel = |funding_fee_rate| {
+        let network_actor = node_a.network_actor.clone();
+        let peer_id = node_b.peer_id.clone();
+        async move {
+            call!(network_actor, |rpc_reply| NetworkActorMessage::Command(
+                NetworkActorCommand::OpenChannel(
+                    OpenChannelCommand {
+                        peer_id,
+                        public: false,
+                        shutdown_script: None,
+                        funding_amount: 100000000000,
+                        funding_udt_type_script: None,
+                        commitment_fee_rate: None,
+                        commitment_delay_epoch: None,
+                        funding_fee_rate: Some(funding_fee_rate),
+                        tlc_locktime_expiry_delta: None,
+                        tlc_min_value: None,
+                        tlc_max_value: None,
+                        tlc_fee_proportional_millionths: None,
+                        max_tlc_number_in_flight: None,
+                        max_tlc_value_in_flight: None,
+                        max_inbound_tlc_value: None,
+                        max_outbound_tlc_value: None,
+                        external_funding_tx: None,
+                        open_timeout_seconds: None,
+                        zero_conf: None,
+                    },
+                    rpc_reply,
+                )
+            ))
+            .expect("node_a alive")
+            .expect("open channel success")
+            .channel_id
+        }
+    };
+
+    // The open with a funding fee rate above the limit of node b is never pending to be accepted.
+    let rejected_channel_id = open_channel(3000).await;
+    let accepted_channel_id = open_channel(2000).await;
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelPendingToBeAccepted(_, channel_id) => {
+                assert_ne!(channel_id, &rejected_channel_id);
+                channel_id == &accepted_channel_id
+            }
+            _ => false,
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_channel_negotiated_hash_algorithms() {
+    init_tracing();
+
+    // Node b only supports ckb hash for the payment hashes.
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .fiber_config_updater(move |config| {
+                if i == 1 {
+                    config.hash_algorithms = vec![HashAlgorithm::CkbHash];
+                }
+            })
+            .build()
+    })
+    .await;
+    let [node_a, node_b]: [NetworkNode; 2] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
+    };
+    // Wait for node a to receive the node announcement of node b, which carries its features.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let node_b_info = node_a
+        .store
+        .get_nodes(Some(node_b.fiber_config.public_key().into()));
+    assert_eq!(
+        node_b_info[0].anouncement_msg.features,
+        NODE_FEATURE_KEYSEND | NODE_FEATURE_CKB_HASH | NODE_FEATURE_ONION_V0
+    );
+
+    let (node_a, node_b, channel_id) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, false).await;
+
+    for node in [&node_a, &node_b] {
+        let state = node.store.get_channel_actor_state(&channel_id).unwrap();
+        assert_eq!(state.hash_algorithms, vec![HashAlgorithm::CkbHash]);
+        assert!(!state.supports_feature(NODE_FEATURE_SHA256));
+    }
+
+    let add_tlc = |algorithm: HashAlgorithm| {
+        let digest = algorithm.hash([1; 32]);
+        move |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id,
+                    command: ChannelCommand::AddTlc(
+                        AddTlcCommand {
+                            amount: 1000000000,
+                            hash_algorithm: algorithm,
+                            payment_hash: Some(digest.into()),
+                            expiry: LockTime::new(100),
+                            preimage: None,
+                            onion_packet: vec![],
+                            previous_tlc: None,
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        }
+    };
+    let add_tlc_result =
+        call!(node_a.network_actor, add_tlc(HashAlgorithm::Sha256)).expect("node_a alive");
+    let tlc_err = add_tlc_result.err().unwrap().decode().unwrap();
+    assert_eq!(
+        tlc_err.error_code,
+        TlcErrorCode::RequiredChannelFeatureMissing
+    );
+
+    call!(node_a.network_actor, add_tlc(HashAlgorithm::CkbHash))
+        .expect("node_a alive")
+        .expect("successfully added tlc");
+}
+
+#[tokio::test]
+async fn test_channel_negotiated_features() {
+    init_tracing();
+
+    let (node_a, node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
+    for node in [&node_a, &node_b] {
+        let mut state = node.store.get_channel_actor_state(&channel_id).unwrap();
+        assert_eq!(state.features, Some(SUPPORTED_NODE_FEATURES));
+        assert!(state.supports_feature(NODE_FEATURE_KEYSEND));
+
+        state.features = Some(0);
+        assert!(!state.supports_feature(NODE_FEATURE_KEYSEND));
+        // The channels opened before the negotiation support all the features.
+        state.features = None;
+        assert!(state.supports_feature(NODE_FEATURE_KEYSEND));
+    }
+}
+
+#[test]
+fn test_negotiate_features() {
+    // A peer which has not announced any features is assumed to support the legacy features.
+    let (hash_algorithms, onion_versions, features) =
+        negotiate_features(&[HashAlgorithm::Sha256, HashAlgorithm::CkbHash], &[0], None)
+            .expect("negotiate features");
+    assert_eq!(
+        hash_algorithms,
+        vec![HashAlgorithm::Sha256, HashAlgorithm::CkbHash]
+    );
+    assert_eq!(onion_versions, vec![0]);
+    assert_eq!(features, LEGACY_NODE_FEATURES);
+
+    let remote_features = NODE_FEATURE_KEYSEND | NODE_FEATURE_CKB_HASH | NODE_FEATURE_ONION_V0;
+    let (hash_algorithms, _, features) = negotiate_features(
+        &[HashAlgorithm::Sha256, HashAlgorithm::CkbHash],
+        &[0],
+        Some(remote_features),
+    )
+    .expect("negotiate features");
+    assert_eq!(hash_algorithms, vec![HashAlgorithm::CkbHash]);
+    assert_eq!(features, remote_features);
+
+    assert!(negotiate_features(&[HashAlgorithm::Sha256], &[0], Some(remote_features)).is_err());
+    assert!(negotiate_features(
+        &[HashAlgorithm::CkbHash],
+        &[1],
+        Some(remote_features | NODE_FEATURE_SHA256)
+    )
+    .is_err());
+}
+
+#[tokio::test]
+async fn test_channel_negotiated_onion_versions() {
+    init_tracing();
+
+    let (node_a, node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
+    for node in [&node_a, &node_b] {
+        let state = node.store.get_channel_actor_state(&channel_id).unwrap();
+        assert_eq!(state.onion_versions, vec![0]);
+        assert_eq!(state.get_onion_version(), 0);
+    }
+
+    // The first byte of the onion packet is the version, which is not negotiated in the channel.
+    let mut onion_packet = vec![0u8; 100];
+    onion_packet[0] = 1;
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: Some(HashAlgorithm::CkbHash.hash([1; 32]).into()),
+                        expiry: LockTime::new(100),
+                        preimage: None,
+                        onion_packet,
+                        previous_tlc: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    };
+    let add_tlc_result = call!(node_a.network_actor, message).expect("node_a alive");
+    let tlc_err = add_tlc_result.err().unwrap().decode().unwrap();
+    assert_eq!(tlc_err.error_code, TlcErrorCode::InvalidOnionVersion);
+}
+
+#[tokio::test]
+async fn test_list_rejected_tlcs() {
+    init_tracing();
+
+    // Node b only supports ckb hash for the payment hashes.
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .fiber_config_updater(move |config| {
+                if i == 1 {
+                    config.hash_algorithms = vec![HashAlgorithm::CkbHash];
+                }
+            })
+            .build()
+    })
+    .await;
+    let [node_a, node_b]: [NetworkNode; 2] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
+    };
+    let (node_a, node_b, channel_id) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, false).await;
+
+    // Bypass the local checks of node a, so that node b rejects the tlc.
+    let payment_hash: Hash256 = HashAlgorithm::Sha256.hash([1; 32]).into();
+    node_a
+        .network_actor
+        .send_message(NetworkActorMessage::new_command(
+            NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
+                node_b.peer_id.clone(),
+                FiberMessage::add_tlc(AddTlc {
+                    channel_id,
+                    tlc_id: 0,
+                    amount: 1000000000,
+                    payment_hash,
+                    expiry: LockTime::new(100),
+                    hash_algorithm: HashAlgorithm::Sha256,
+                    onion_packet: vec![],
+                }),
+            )),
+        ))
+        .expect("node_a alive");
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ListRejectedTlcs((), rpc_reply))
+    };
+    let rejected_tlcs = call!(node_b.network_actor, message)
+        .expect("node_b alive")
+        .expect("list rejected tlcs");
+    assert_eq!(rejected_tlcs.len(), 1);
+    let rejected_tlc = &rejected_tlcs[0];
+    assert_eq!(rejected_tlc.peer_id, node_a.peer_id);
+    assert_eq!(rejected_tlc.channel_id, channel_id);
+    assert_eq!(rejected_tlc.payment_hash, payment_hash);
+    assert_eq!(rejected_tlc.amount, 1000000000);
+    assert_eq!(
+        rejected_tlc.error_code,
+        TlcErrorCode::RequiredChannelFeatureMissing
+    );
+
+    let rejected_tlcs = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("list rejected tlcs");
+    assert!(rejected_tlcs.is_empty());
+}
+
+#[tokio::test]
+async fn test_list_channels_needing_action() {
+    init_tracing();
+
+    let (mut node_a, node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
+    let thresholds = ChannelAttentionThresholds {
+        tlc_expiry_blocks: 450,
+        stuck_state: 3600 * 1000,
+    };
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ListChannelsNeedingAction(
+            thresholds.clone(),
+            rpc_reply,
+        ))
+    };
+    let channels = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("list channels needing action");
+    assert!(channels.is_empty());
+
+    node_a.chain_tip.update(1000);
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: Some(HashAlgorithm::CkbHash.hash([1; 32]).into()),
+                        expiry: LockTime::new(100),
+                        preimage: None,
+                        onion_packet: vec![],
+                        previous_tlc: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    // The tlc expires in 100 blocks, which is within the threshold.
+    let channels = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("list channels needing action");
+    assert_eq!(channels.len(), 1);
+    assert_eq!(channels[0].channel_id, channel_id);
+    assert_eq!(channels[0].peer_id, node_b.peer_id);
+    assert_eq!(
+        channels[0].reason,
+        ChannelAttentionReason::TlcNearExpiry {
+            tlc_id: TLCId::Offered(add_tlc_result.tlc_id),
+            expires_at_block: 1100,
+        }
+    );
+
+    node_a
+        .network_actor
+        .send_message(NetworkActorMessage::new_command(
+            NetworkActorCommand::DisconnectPeer(node_b.peer_id.clone()),
+        ))
+        .expect("node_a alive");
+    node_a
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::PeerDisConnected(peer_id, _) if peer_id == &node_b.peer_id)
+        })
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let channels = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("list channels needing action");
+    assert!(channels.iter().any(|channel| matches!(
+        channel.reason,
+        ChannelAttentionReason::PeerOffline {
+            last_disconnected_at: Some(_)
+        }
+    )));
+}
+
+async fn do_test_reestablish_channel_divergence(force_close_on_divergence: bool, peer_ahead: bool) {
+    init_tracing();
+
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .fiber_config_updater(move |config| {
+                if i == 0 {
+                    config.reestablish_force_close_on_divergence = Some(force_close_on_divergence);
+                }
+            })
+            .build()
+    })
+    .await;
+    let [node_a, node_b]: [NetworkNode; 2] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
+    };
+    let (mut node_a, node_b, channel_id) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, false).await;
+
+    // Advance the commitment numbers so that the peer can claim to be behind.
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: Some(HashAlgorithm::CkbHash.hash([7; 32]).into()),
+                        expiry: LockTime::new(100),
+                        preimage: None,
+                        onion_packet: vec![],
+                        previous_tlc: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    let state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    let local_commitment_number = state.get_local_commitment_number();
+    let remote_commitment_number = state.get_remote_commitment_number();
+    assert!(local_commitment_number > 0);
+    let node_a_peer_id = node_a.peer_id.clone();
+    // Node b reestablishes the channel with the given commitment numbers of its own view.
+    let reestablish = |local_commitment_number, remote_commitment_number| {
+        node_b
+            .network_actor
+            .send_message(NetworkActorMessage::new_command(
+                NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
+                    node_a_peer_id.clone(),
+                    FiberMessage::reestablish_channel(ReestablishChannel {
+                        channel_id,
+                        local_commitment_number,
+                        remote_commitment_number,
+                    }),
+                )),
+            ))
+            .expect("node_b alive");
+    };
+
+    reestablish(remote_commitment_number, local_commitment_number);
+    node_a
+        .expect_event(|event| {
+            matches!(
+                event,
+                NetworkServiceEvent::ChannelReestablished(_, id, ReestablishOutcome::Synced)
+                    if id == &channel_id
+            )
+        })
+        .await;
+
+    if peer_ahead {
+        // Node b claims a commitment number of node a far ahead of what node a knows,
+        // the state of node a is stale.
+        reestablish(remote_commitment_number, local_commitment_number + 5);
+    } else {
+        // Node b claims a commitment number of node a behind what node a knows.
+        reestablish(remote_commitment_number, local_commitment_number - 1);
+    }
+    let force_closed = force_close_on_divergence && !peer_ahead;
+    node_a
+        .expect_event(|event| {
+            matches!(
+                event,
+                NetworkServiceEvent::ChannelReestablished(
+                    _,
+                    id,
+                    ReestablishOutcome::Diverged { stale_local_state, force_closed: closed, .. },
+                ) if id == &channel_id && *stale_local_state == peer_ahead && *closed == force_closed
+            )
+        })
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    if force_closed {
+        assert!(matches!(
+            state.state,
+            ChannelState::ShuttingDown(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION)
+                | ChannelState::Closed(CloseFlags::UNCOOPERATIVE)
+        ));
+    } else {
+        assert_eq!(state.state, ChannelState::ChannelReady());
+    }
+    assert_eq!(state.halted.is_some(), peer_ahead);
+
+    if peer_ahead {
+        // The stale commitment transaction is never broadcasted, even by the force shutdown.
+        let error = call!(node_a.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id,
+                    command: ChannelCommand::Shutdown(
+                        ShutdownCommand {
+                            close_script: Script::default(),
+                            fee_rate: FeeRate::from_u64(1000),
+                            max_fee_rate: None,
+                            force: true,
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        })
+        .expect("node_a alive")
+        .unwrap_err();
+        assert!(error.contains("stale state"), "unexpected error: {}", error);
+        let state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+        assert_eq!(state.state, ChannelState::ChannelReady());
+    }
+}
+
+#[tokio::test]
+async fn test_reestablish_channel_divergence_force_close() {
+    do_test_reestablish_channel_divergence(true, false).await;
+}
+
+#[tokio::test]
+async fn test_reestablish_channel_divergence_keep_channel() {
+    do_test_reestablish_channel_divergence(false, false).await;
+}
+
+#[tokio::test]
+async fn test_reestablish_channel_divergence_stale_local_state() {
+    do_test_reestablish_channel_divergence(true, true).await;
+}
+
+#[tokio::test]
+async fn test_channel_inbound_and_outbound_tlc_value_caps() {
+    init_tracing();
+
+    let [node_a, node_b] = NetworkNode::new_n_interconnected_nodes().await;
+    let (node_a, node_b, channel_id) = establish_channel_between_nodes_with_tlc_value_caps(
+        node_a,
+        node_b,
+        100000000000,
+        6200000000,
+        false,
+        Some(2000000000),
+        Some(500000000),
+    )
+    .await;
+
+    let state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    assert_eq!(state.max_inbound_tlc_value, None);
+    assert_eq!(state.max_outbound_tlc_value, Some(2000000000));
+    let state = node_b.store.get_channel_actor_state(&channel_id).unwrap();
+    assert_eq!(state.max_inbound_tlc_value, Some(500000000));
+    assert_eq!(state.max_outbound_tlc_value, None);
+
+    let add_tlc = |amount| {
+        let preimage = [2; 32];
+        move |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id,
+                    command: ChannelCommand::AddTlc(
+                        AddTlcCommand {
+                            amount,
+                            hash_algorithm: HashAlgorithm::CkbHash,
+                            payment_hash: Some(HashAlgorithm::CkbHash.hash(preimage).into()),
+                            expiry: LockTime::new(100),
+                            preimage: None,
+                            onion_packet: vec![],
+                            previous_tlc: None,
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        }
+    };
+
+    // The outbound cap of node a is enforced before offering the tlc.
+    let error = call!(node_a.network_actor, add_tlc(3000000000))
+        .expect("node_a alive")
+        .unwrap_err();
+    assert_eq!(
+        error.decode().map(|error| error.error_code()),
+        Some(TlcErrorCode::TemporaryChannelFailure)
+    );
+
+    // The tlc is within the outbound cap of node a, but exceeds the inbound cap of node b.
+    let payment_hash: Hash256 = HashAlgorithm::CkbHash.hash([2; 32]).into();
+    call!(node_a.network_actor, add_tlc(1000000000))
+        .expect("node_a alive")
+        .expect("add tlc");
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ListRejectedTlcs((), rpc_reply))
+    };
+    let rejected_tlcs = call!(node_b.network_actor, message)
+        .expect("node_b alive")
+        .expect("list rejected tlcs");
+    assert_eq!(rejected_tlcs.len(), 1);
+    assert_eq!(rejected_tlcs[0].payment_hash, payment_hash);
+    assert_eq!(rejected_tlcs[0].amount, 1000000000);
+    assert_eq!(
+        rejected_tlcs[0].error_code,
+        TlcErrorCode::TemporaryChannelFailure
+    );
+}
+
+#[tokio::test]
+async fn test_channel_funding_transaction_reorged() {
+    init_tracing();
+
+    let (mut node_a, node_b, channel_id) =
+        create_nodes_with_established_channel(16200000000, 6200000000, false).await;
+    let state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    let outpoint = state.get_funding_transaction_outpoint();
+
+    node_a
+        .network_actor
+        .send_message(NetworkActorMessage::new_event(
+            NetworkActorEvent::FundingTransactionReorged(
+                channel_id,
+                node_b.peer_id.clone(),
+                outpoint.clone(),
+            ),
+        ))
+        .expect("node_a alive");
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelFundingReorged(peer_id, id, funding_outpoint) => {
+                assert_eq!(peer_id, &node_b.peer_id);
+                assert_eq!(funding_outpoint, &outpoint);
+                id == &channel_id
+            }
+            _ => false,
+        })
+        .await;
+
+    // The funding transaction is still committed in the mock chain,
+    // so the channel becomes ready again once it is traced.
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelReady(_, id, _) => id == &channel_id,
+            _ => false,
+        })
+        .await;
+    let new_state = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    assert_eq!(new_state.state, ChannelState::ChannelReady());
+    assert!(new_state.funding_tx_confirmed_at.is_some());
+    assert_eq!(
+        new_state.get_local_commitment_number(),
+        state.get_local_commitment_number()
+    );
+}
+
+#[tokio::test]
+async fn test_open_channel_with_invalid_ckb_amount_range() {
+    init_tracing();
+
+    let [node_a, node_b] = NetworkNode::new_n_interconnected_nodes().await;
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: true,
+                shutdown_script: None,
+                funding_amount: 0xfffffffffffffffffffffffffffffff,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_locktime_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_max_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: None,
+                open_timeout_seconds: None,
+                zero_conf: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let open_channel_result = call!(node_a.network_actor, message).expect("node_a alive");
+    eprintln!("{:?}", open_channel_result.as_ref().err().unwrap());
+    assert!(open_channel_result
+        .err()
+        .unwrap()
+        .contains("The funding amount should be less than 18446744073709551615"));
+}
+
+#[tokio::test]
+async fn test_open_channel_without_usable_balance() {
+    init_tracing();
+
+    let [node_a, node_b] = NetworkNode::new_n_interconnected_nodes().await;
+    let open_channel = |funding_amount: u128, commitment_fee_rate: Option<u64>| {
+        let network_actor = node_a.network_actor.clone();
+        let peer_id = node_b.peer_id.clone();
+        async move {
+            let message = |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+                    OpenChannelCommand {
+                        peer_id,
+                        public: true,
+                        shutdown_script: None,
+                        funding_amount,
+                        funding_udt_type_script: None,
+                        commitment_fee_rate,
+                        commitment_delay_epoch: None,
+                        funding_fee_rate: None,
+                        tlc_locktime_expiry_delta: None,
+                        tlc_min_value: None,
+                        tlc_max_value: None,
+                        tlc_fee_proportional_millionths: None,
+                        max_tlc_number_in_flight: None,
+                        max_tlc_value_in_flight: None,
+                        max_inbound_tlc_value: None,
+                        max_outbound_tlc_value: None,
+                        external_funding_tx: None,
+                        open_timeout_seconds: None,
+                        zero_conf: None,
+                    },
+                    rpc_reply,
+                ))
+            };
+            call!(network_actor, message).expect("node_a alive")
+        }
+    };
+
+    // The funding amount only covers the reserved amount.
+    let error = open_channel(DEFAULT_CHANNEL_MINIMAL_CKB_AMOUNT as u128, None)
+        .await
+        .unwrap_err();
+    assert!(
+        error.contains("the minimal viable funding amount is"),
+        "{}",
+        error
+    );
 
-    // Wait for the channel announcement to be broadcasted
-    tokio::time::sleep(tokio::time::Duration::from_millis(5000)).await;
+    // The reserved amount can't cover the commitment fee at a high fee rate.
+    let error = open_channel(100000000000, Some(100000000000))
+        .await
+        .unwrap_err();
+    assert!(
+        error.contains("please set a lower commitment fee rate"),
+        "{}",
+        error
+    );
 }
 
-async fn do_test_channel_commitment_tx_after_add_tlc(algorithm: HashAlgorithm) {
-    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+#[tokio::test]
+async fn test_revoke_old_commitment_transaction() {
+    init_tracing();
 
-    let node_a_funding_amount = 100000000000;
-    let node_b_funidng_amount = 6200000000;
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
 
     let message = |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
@@ -194,7 +3526,7 @@ async fn do_test_channel_commitment_tx_after_add_tlc(algorithm: HashAlgorithm) {
                 peer_id: node_b.peer_id.clone(),
                 public: false,
                 shutdown_script: None,
-                funding_amount: node_a_funding_amount,
+                funding_amount: 100000000000,
                 funding_udt_type_script: None,
                 commitment_fee_rate: None,
                 commitment_delay_epoch: None,
@@ -205,6 +3537,11 @@ async fn do_test_channel_commitment_tx_after_add_tlc(algorithm: HashAlgorithm) {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: None,
+                open_timeout_seconds: None,
+                zero_conf: None,
             },
             rpc_reply,
         ))
@@ -227,8 +3564,11 @@ async fn do_test_channel_commitment_tx_after_add_tlc(algorithm: HashAlgorithm) {
         NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
             AcceptChannelCommand {
                 temp_channel_id: open_channel_result.channel_id,
-                funding_amount: node_b_funidng_amount,
+                funding_amount: 6200000000,
                 shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: None,
             },
             rpc_reply,
         ))
@@ -238,6 +3578,21 @@ async fn do_test_channel_commitment_tx_after_add_tlc(algorithm: HashAlgorithm) {
         .expect("accept channel success");
     let new_channel_id = accept_channel_result.new_channel_id;
 
+    let commitment_tx = node_b
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::RemoteCommitmentSigned(peer_id, channel_id, num, tx) => {
+                println!(
+                    "Commitment tx (#{}) {:?} from {:?} for channel {:?} received",
+                    num, &tx, peer_id, channel_id
+                );
+                assert_eq!(peer_id, &node_a.peer_id);
+                assert_eq!(channel_id, &new_channel_id);
+                Some(tx.clone())
+            }
+            _ => None,
+        })
+        .await;
+
     node_a
         .expect_event(|event| match event {
             NetworkServiceEvent::ChannelReady(peer_id, channel_id, _funding_tx_hash) => {
@@ -268,223 +3623,173 @@ async fn do_test_channel_commitment_tx_after_add_tlc(algorithm: HashAlgorithm) {
         })
         .await;
 
-    let preimage = [1; 32];
-    let digest = algorithm.hash(&preimage);
-    let tlc_amount = 1000000000;
-
-    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
-        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
-            ChannelCommandWithId {
-                channel_id: new_channel_id,
-                command: ChannelCommand::AddTlc(
-                    AddTlcCommand {
-                        amount: tlc_amount,
-                        hash_algorithm: algorithm,
-                        payment_hash: Some(digest.into()),
-                        expiry: LockTime::new(100),
-                        preimage: None,
-                        onion_packet: vec![],
-                        previous_tlc: None,
-                    },
-                    rpc_reply,
-                ),
-            },
-        ))
-    })
-    .expect("node_b alive")
-    .expect("successfully added tlc");
-
-    dbg!(&add_tlc_result);
-
-    // Since we currently automatically send a `CommitmentSigned` message
-    // after sending a `AddTlc` message, we can expect the `RemoteCommitmentSigned`
-    // to be received by node b.
-    let node_b_commitment_tx = node_b
-        .expect_to_process_event(|event| match event {
-            NetworkServiceEvent::RemoteCommitmentSigned(peer_id, channel_id, num, tx) => {
-                println!(
-                    "Commitment tx (#{}) {:?} from {:?} for channel {:?} received",
-                    num, &tx, peer_id, channel_id
-                );
-                assert_eq!(peer_id, &node_a.peer_id);
-                assert_eq!(channel_id, &new_channel_id);
-                Some(tx.clone())
-            }
-            _ => None,
-        })
-        .await;
-
-    call!(node_b.network_actor, |rpc_reply| {
-        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
-            ChannelCommandWithId {
+    node_a
+        .network_actor
+        .send_message(NetworkActorMessage::Command(
+            NetworkActorCommand::ControlFiberChannel(ChannelCommandWithId {
                 channel_id: new_channel_id,
-                command: ChannelCommand::RemoveTlc(
-                    RemoveTlcCommand {
-                        id: add_tlc_result.tlc_id,
-                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
-                            payment_preimage: preimage.into(),
-                        }),
-                    },
-                    rpc_reply,
-                ),
-            },
+                command: ChannelCommand::CommitmentSigned(),
+            }),
         ))
-    })
-    .expect("node_b alive")
-    .expect("successfully removed tlc");
+        .expect("node_a alive");
 
-    // Since we currently automatically send a `CommitmentSigned` message
-    // after sending a `RemoveTlc` message, we can expect the `RemoteCommitmentSigned`
-    // to be received by node a.
-    let node_a_commitment_tx = node_a
+    let (x_only_aggregated_pubkey, signature, output, output_data) = node_a
         .expect_to_process_event(|event| match event {
-            NetworkServiceEvent::RemoteCommitmentSigned(peer_id, channel_id, num, tx) => {
-                println!(
-                    "Commitment tx (#{}) {:?} from {:?} for channel {:?} received",
-                    num, &tx, peer_id, channel_id
-                );
+            NetworkServiceEvent::RevokeAndAckReceived(
+                peer_id,
+                channel_id,
+                commitment_number,
+                x_only_aggregated_pubkey,
+                signature,
+                output,
+                output_data,
+            ) => {
                 assert_eq!(peer_id, &node_b.peer_id);
                 assert_eq!(channel_id, &new_channel_id);
-                Some(tx.clone())
+                assert_eq!(*commitment_number, 1u64);
+                Some((
+                    x_only_aggregated_pubkey.clone(),
+                    signature.clone(),
+                    output.clone(),
+                    output_data.clone(),
+                ))
             }
             _ => None,
         })
         .await;
 
     assert_eq!(
-        node_a.submit_tx(node_a_commitment_tx.clone()).await,
+        node_a.submit_tx(commitment_tx.clone()).await,
         Status::Committed
     );
 
+    println!("commitment_tx: {:?}", commitment_tx);
+
+    let tx = Transaction::default()
+        .as_advanced_builder()
+        .cell_deps(get_cell_deps(vec![Contract::CommitmentLock], &None))
+        .input(
+            CellInput::new_builder()
+                .previous_output(commitment_tx.output_pts().get(0).unwrap().clone())
+                .build(),
+        )
+        .output(output)
+        .output_data(output_data)
+        .build();
+
+    let empty_witness_args = [16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0];
+    let witness = [
+        empty_witness_args.to_vec(),
+        vec![0xFF],
+        1u64.to_be_bytes().to_vec(),
+        x_only_aggregated_pubkey.to_vec(),
+        signature.serialize().to_vec(),
+    ]
+    .concat();
+
+    let revocation_tx = tx.as_advanced_builder().witness(witness.pack()).build();
+
     assert_eq!(
-        node_b.submit_tx(node_b_commitment_tx.clone()).await,
+        node_a.submit_tx(revocation_tx.clone()).await,
         Status::Committed
     );
 }
 
 #[tokio::test]
-async fn test_channel_commitment_tx_after_add_tlc_ckbhash() {
-    do_test_channel_commitment_tx_after_add_tlc(HashAlgorithm::CkbHash).await
+async fn test_channel_with_simple_update_operation() {
+    for algorithm in HashAlgorithm::supported_algorithms() {
+        do_test_channel_with_simple_update_operation(algorithm).await
+    }
 }
 
 #[tokio::test]
-async fn test_channel_commitment_tx_after_add_tlc_sha256() {
-    do_test_channel_commitment_tx_after_add_tlc(HashAlgorithm::Sha256).await
-}
-
-async fn create_nodes_with_established_channel(
-    node_a_funding_amount: u128,
-    node_b_funding_amount: u128,
-    public: bool,
-) -> (NetworkNode, NetworkNode, Hash256) {
-    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
-
-    let message = |rpc_reply| {
-        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
-            OpenChannelCommand {
-                peer_id: node_b.peer_id.clone(),
-                public,
-                shutdown_script: None,
-                funding_amount: node_a_funding_amount,
-                funding_udt_type_script: None,
-                commitment_fee_rate: None,
-                commitment_delay_epoch: None,
-                funding_fee_rate: None,
-                tlc_locktime_expiry_delta: None,
-                tlc_min_value: None,
-                tlc_max_value: None,
-                tlc_fee_proportional_millionths: None,
-                max_tlc_number_in_flight: None,
-                max_tlc_value_in_flight: None,
-            },
-            rpc_reply,
-        ))
-    };
-    let open_channel_result = call!(node_a.network_actor, message)
-        .expect("node_a alive")
-        .expect("open channel success");
+async fn test_add_tlc_with_preimage() {
+    let (node_a, _node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
 
-    node_b
-        .expect_event(|event| match event {
-            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, channel_id) => {
-                println!("A channel ({:?}) to {:?} create", &channel_id, peer_id);
-                assert_eq!(peer_id, &node_a.peer_id);
-                true
-            }
-            _ => false,
-        })
-        .await;
-    let message = |rpc_reply| {
-        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
-            AcceptChannelCommand {
-                temp_channel_id: open_channel_result.channel_id,
-                funding_amount: node_b_funding_amount,
-                shutdown_script: None,
-            },
-            rpc_reply,
-        ))
+    let preimage: Hash256 = [3; 32].into();
+    let add_tlc = |payment_hash: Hash256| {
+        let network_actor = node_a.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id,
+                        command: ChannelCommand::AddTlc(
+                            AddTlcCommand {
+                                amount: 1000000000,
+                                hash_algorithm: HashAlgorithm::Sha256,
+                                payment_hash: Some(payment_hash),
+                                expiry: LockTime::new(100),
+                                preimage: Some(preimage),
+                                onion_packet: vec![],
+                                previous_tlc: None,
+                            },
+                            rpc_reply,
+                        ),
+                    },
+                ))
+            })
+            .expect("node_a alive")
+        }
     };
-    let accept_channel_result = call!(node_b.network_actor, message)
-        .expect("node_b alive")
-        .expect("accept channel success");
-    let new_channel_id = accept_channel_result.new_channel_id;
-
-    node_a
-        .expect_event(|event| match event {
-            NetworkServiceEvent::ChannelReady(peer_id, channel_id, _funding_tx_hash) => {
-                println!(
-                    "A channel ({:?}) to {:?} is now ready",
-                    &channel_id, &peer_id
-                );
-                assert_eq!(peer_id, &node_b.peer_id);
-                assert_eq!(channel_id, &new_channel_id);
-                true
-            }
-            _ => false,
-        })
-        .await;
 
-    node_b
-        .expect_event(|event| match event {
-            NetworkServiceEvent::ChannelReady(peer_id, channel_id, _funding_tx_hash) => {
-                println!(
-                    "A channel ({:?}) to {:?} is now ready",
-                    &channel_id, &peer_id
-                );
-                assert_eq!(peer_id, &node_a.peer_id);
-                assert_eq!(channel_id, &new_channel_id);
-                true
-            }
-            _ => false,
-        })
-        .await;
-    (node_a, node_b, new_channel_id)
-}
+    // the preimage must match the payment hash
+    let result = add_tlc(HashAlgorithm::CkbHash.hash(preimage).into()).await;
+    assert!(result.is_err());
 
-async fn do_test_remove_tlc_with_wrong_hash_algorithm(
-    correct_algorithm: HashAlgorithm,
-    wrong_algorithm: HashAlgorithm,
-) {
-    let node_a_funding_amount = 100000000000;
-    let node_b_funding_amount = 6200000000;
+    let add_tlc_result = add_tlc(HashAlgorithm::Sha256.hash(preimage).into())
+        .await
+        .expect("successfully added tlc");
+    let state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel state");
+    assert!(state.get_offered_tlc(add_tlc_result.tlc_id).is_some());
+}
 
-    let (node_a, node_b, new_channel_id) =
-        create_nodes_with_established_channel(node_a_funding_amount, node_b_funding_amount, false)
-            .await;
+#[tokio::test]
+async fn test_latest_remote_commitment_transaction() {
+    let (node_a, node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
 
-    let preimage = [1; 32];
-    let digest = correct_algorithm.hash(&preimage);
-    let tlc_amount = 1000000000;
+    // The remote commitment transaction signed by a node is the one held by its peer,
+    // the transaction hash doesn't cover the witnesses.
+    let check_remote_commitment_txs = || {
+        let state_a = node_a
+            .store
+            .get_channel_actor_state(&channel_id)
+            .expect("channel state");
+        let state_b = node_b
+            .store
+            .get_channel_actor_state(&channel_id)
+            .expect("channel state");
+        for (state, peer_state) in [(&state_a, &state_b), (&state_b, &state_a)] {
+            let remote_commitment_tx = state
+                .latest_remote_commitment_transaction
+                .clone()
+                .expect("remote commitment tx")
+                .into_view();
+            let peer_commitment_tx = peer_state
+                .latest_commitment_transaction
+                .clone()
+                .expect("latest commitment tx")
+                .into_view();
+            assert_eq!(remote_commitment_tx.hash(), peer_commitment_tx.hash());
+        }
+    };
+    check_remote_commitment_txs();
 
-    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+    // Both commitment transactions are signed again with the new tlc.
+    call!(node_a.network_actor, |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
             ChannelCommandWithId {
-                channel_id: new_channel_id,
+                channel_id,
                 command: ChannelCommand::AddTlc(
                     AddTlcCommand {
-                        amount: tlc_amount,
-                        hash_algorithm: correct_algorithm,
-                        payment_hash: Some(digest.into()),
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: Some(HashAlgorithm::CkbHash.hash([5; 32]).into()),
                         expiry: LockTime::new(100),
                         preimage: None,
                         onion_packet: vec![],
@@ -495,45 +3800,27 @@ async fn do_test_remove_tlc_with_wrong_hash_algorithm(
             },
         ))
     })
-    .expect("node_b alive")
+    .expect("node_a alive")
     .expect("successfully added tlc");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    check_remote_commitment_txs();
+}
 
-    dbg!(&add_tlc_result);
-
-    dbg!("Sleeping for some time to wait for the AddTlc processed by both party");
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-    call!(node_b.network_actor, |rpc_reply| {
-        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
-            ChannelCommandWithId {
-                channel_id: new_channel_id,
-                command: ChannelCommand::RemoveTlc(
-                    RemoveTlcCommand {
-                        id: add_tlc_result.tlc_id,
-                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
-                            payment_preimage: preimage.into(),
-                        }),
-                    },
-                    rpc_reply,
-                ),
-            },
-        ))
-    })
-    .expect("node_b alive")
-    .expect("successfully removed tlc");
-
-    dbg!("Sleeping for some time to wait for the RemoveTlc processed by both party");
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+#[tokio::test]
+async fn test_audit_channel() {
+    let (node_a, node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
 
+    let preimage = [4; 32];
     let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
             ChannelCommandWithId {
-                channel_id: new_channel_id,
+                channel_id,
                 command: ChannelCommand::AddTlc(
                     AddTlcCommand {
-                        amount: tlc_amount,
-                        hash_algorithm: wrong_algorithm,
-                        payment_hash: Some(digest.into()),
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: Some(HashAlgorithm::CkbHash.hash(&preimage).into()),
                         expiry: LockTime::new(100),
                         preimage: None,
                         onion_packet: vec![],
@@ -544,18 +3831,14 @@ async fn do_test_remove_tlc_with_wrong_hash_algorithm(
             },
         ))
     })
-    .expect("node_b alive")
+    .expect("node_a alive")
     .expect("successfully added tlc");
+    tokio::time::sleep(Duration::from_secs(1)).await;
 
-    dbg!(&add_tlc_result);
-
-    dbg!("Sleeping for some time to wait for the AddTlc processed by both party");
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-    let remove_tlc_result = call!(node_b.network_actor, |rpc_reply| {
+    call!(node_b.network_actor, |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
             ChannelCommandWithId {
-                channel_id: new_channel_id,
+                channel_id,
                 command: ChannelCommand::RemoveTlc(
                     RemoveTlcCommand {
                         id: add_tlc_result.tlc_id,
@@ -568,46 +3851,51 @@ async fn do_test_remove_tlc_with_wrong_hash_algorithm(
             },
         ))
     })
-    .expect("node_b alive");
-
-    dbg!(&remove_tlc_result);
-    assert!(remove_tlc_result.is_err());
-}
+    .expect("node_b alive")
+    .expect("successfully removed tlc");
+    tokio::time::sleep(Duration::from_secs(1)).await;
 
-#[tokio::test]
-async fn test_remove_tlc_with_wrong_hash_algorithm() {
-    let supported_algorithms = HashAlgorithm::supported_algorithms();
-    for algorithm1 in &supported_algorithms {
-        for algorithm2 in &supported_algorithms {
-            if algorithm2 == algorithm1 {
-                continue;
-            }
-            do_test_remove_tlc_with_wrong_hash_algorithm(*algorithm1, *algorithm2).await;
-        }
+    for node in [&node_a, &node_b] {
+        let state = node
+            .store
+            .get_channel_actor_state(&channel_id)
+            .expect("channel state");
+        let audit = state.audit();
+        assert!(
+            audit.issues.is_empty(),
+            "unexpected issues: {:?}",
+            audit.issues
+        );
+        assert!(audit.revoked_remote_commitments > 0);
+        assert_eq!(
+            audit.remote_commitment_points,
+            audit.revoked_remote_commitments + 1
+        );
     }
-}
-
-async fn do_test_channel_with_simple_update_operation(algorithm: HashAlgorithm) {
-    let node_a_funding_amount = 100000000000;
-    let node_b_funding_amount = 6200000000;
 
-    let (mut node_a, mut node_b, new_channel_id) =
-        create_nodes_with_established_channel(node_a_funding_amount, node_b_funding_amount, false)
-            .await;
+    // Losing a commitment point of the peer is reported.
+    let mut state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel state");
+    state.remote_commitment_points.pop();
+    assert_eq!(state.audit().issues.len(), 1);
+}
 
-    let preimage = [1; 32];
-    let digest = algorithm.hash(&preimage);
-    let tlc_amount = 1000000000;
+#[tokio::test]
+async fn test_audit_channel_on_commitment_update() {
+    let (node_a, node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
 
-    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+    call!(node_a.network_actor, |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
             ChannelCommandWithId {
-                channel_id: new_channel_id,
+                channel_id,
                 command: ChannelCommand::AddTlc(
                     AddTlcCommand {
-                        amount: tlc_amount,
-                        hash_algorithm: algorithm,
-                        payment_hash: Some(digest.into()),
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: Some(HashAlgorithm::CkbHash.hash([5; 32]).into()),
                         expiry: LockTime::new(100),
                         preimage: None,
                         onion_packet: vec![],
@@ -618,312 +3906,637 @@ async fn do_test_channel_with_simple_update_operation(algorithm: HashAlgorithm)
             },
         ))
     })
-    .expect("node_b alive")
+    .expect("node_a alive")
     .expect("successfully added tlc");
+    tokio::time::sleep(Duration::from_secs(1)).await;
 
-    dbg!(&add_tlc_result);
+    for node in [&node_a, &node_b] {
+        let state = node
+            .store
+            .get_channel_actor_state(&channel_id)
+            .expect("channel state");
+        let audit = state
+            .last_commitment_audit
+            .as_ref()
+            .expect("commitment update audited");
+        assert!(
+            audit.issues.is_empty(),
+            "unexpected issues: {:?}",
+            audit.issues
+        );
+        assert_eq!(
+            audit.commitment_numbers,
+            state.get_current_commitment_numbers()
+        );
+    }
 
-    dbg!("Sleeping for some time to wait for the AddTlc processed by both party");
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    // The commitment numbers beyond the range of the commitment secrets are reported.
+    let mut state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel state");
+    state.commitment_numbers.remote = 1 << 48;
+    assert_eq!(state.audit().issues.len(), 1);
 
-    call!(node_b.network_actor, |rpc_reply| {
+    // A commitment point of the peer which is not rotated is reported.
+    let mut state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel state");
+    let previous_point = state.remote_commitment_points[state.remote_commitment_points.len() - 2];
+    *state.remote_commitment_points.last_mut().unwrap() = previous_point;
+    let issues = state.audit().issues;
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].contains("is not rotated"), "{:?}", issues);
+}
+
+#[tokio::test]
+async fn test_peer_with_active_channel_bypasses_max_peers() {
+    init_tracing();
+
+    // Node b accepts at most one peer.
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .fiber_config_updater(move |config| {
+                if i == 1 {
+                    config.max_peers = Some(1);
+                }
+            })
+            .build()
+    })
+    .await;
+    let [node_a, node_b]: [NetworkNode; 2] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
+    };
+    let (mut node_a, mut node_b, _channel_id) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, false).await;
+
+    // Node c takes the only peer slot of node b while node a is disconnected.
+    let node_a_peer_id = node_a.peer_id.clone();
+    node_a.stop().await;
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::PeerDisConnected(id, _) if id == &node_a_peer_id)
+        })
+        .await;
+    let mut node_c = NetworkNode::new_with_node_name("Node 2").await;
+    node_c.connect_to(&node_b).await;
+
+    // Node a is still accepted, as it has an active channel with node b.
+    node_a.start().await;
+    node_a.connect_to_nonblocking(&node_b).await;
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::PeerConnected(id, _) if id == &node_a_peer_id)
+        })
+        .await;
+    let health = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::Health((), rpc_reply))
+    })
+    .expect("node_b alive")
+    .expect("get health");
+    assert_eq!(health.peers_count, 2);
+    assert_eq!(health.rejected_connections_count, 0);
+}
+
+#[tokio::test]
+async fn test_channel_halted_by_audit_not_reestablished_after_restart() {
+    let (mut node_a, _node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    node_a.stop().await;
+    let mut state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel state");
+    let halted = ChannelHaltReason::AuditFailed {
+        issues: vec!["Missing the latest commitment transaction".to_string()],
+    };
+    state.halted = Some(halted.clone());
+    node_a
+        .store
+        .insert_channel_actor_state(state)
+        .expect("insert channel state");
+    node_a.start().await;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // The halted channel is not reestablished, so it can't be updated any more.
+    let result = call!(node_a.network_actor, |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
             ChannelCommandWithId {
-                channel_id: new_channel_id,
-                command: ChannelCommand::RemoveTlc(
-                    RemoveTlcCommand {
-                        id: add_tlc_result.tlc_id,
-                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
-                            payment_preimage: preimage.into(),
-                        }),
+                channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: Some(HashAlgorithm::CkbHash.hash([5; 32]).into()),
+                        expiry: LockTime::new(100),
+                        preimage: None,
+                        onion_packet: vec![],
+                        previous_tlc: None,
                     },
                     rpc_reply,
                 ),
             },
         ))
-    })
-    .expect("node_b alive")
-    .expect("successfully removed tlc");
+    });
+    assert!(!matches!(result, Ok(Ok(_))));
+    let state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel state");
+    assert_eq!(state.halted, Some(halted));
+}
 
-    let fee_rate = FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE);
-    call!(node_b.network_actor, |rpc_reply| {
+#[tokio::test]
+async fn test_export_redacted_channel_state() {
+    let (node_a, _node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let preimage = [6; 32];
+    call!(node_a.network_actor, |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
             ChannelCommandWithId {
-                channel_id: new_channel_id,
-                command: ChannelCommand::Shutdown(
-                    ShutdownCommand {
-                        close_script: Script::default().as_builder().build(),
-                        fee_rate,
-                        force: false,
+                channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: Some(HashAlgorithm::CkbHash.hash(preimage).into()),
+                        expiry: LockTime::new(100),
+                        preimage: Some(preimage.into()),
+                        onion_packet: vec![],
+                        previous_tlc: None,
                     },
                     rpc_reply,
                 ),
             },
         ))
     })
-    .expect("node_b alive")
-    .expect("successfully shutdown channel");
-
-    let node_a_shutdown_tx_hash = node_a
-        .expect_to_process_event(|event| match event {
-            NetworkServiceEvent::ChannelClosed(peer_id, channel_id, tx_hash) => {
-                println!(
-                    "Shutdown tx ({:?}) from {:?} for channel {:?} received",
-                    &tx_hash, &peer_id, channel_id
-                );
-                assert_eq!(peer_id, &node_b.peer_id);
-                assert_eq!(channel_id, &new_channel_id);
-                Some(tx_hash.clone())
-            }
-            _ => None,
-        })
-        .await;
-
-    dbg!(&node_a_shutdown_tx_hash);
-
-    let node_b_shutdown_tx_hash = node_b
-        .expect_to_process_event(|event| match event {
-            NetworkServiceEvent::ChannelClosed(peer_id, channel_id, tx_hash) => {
-                println!(
-                    "Shutdown tx ({:?}) from {:?} for channel {:?} received",
-                    &tx_hash, &peer_id, channel_id
-                );
-                assert_eq!(peer_id, &node_a.peer_id);
-                assert_eq!(channel_id, &new_channel_id);
-                Some(tx_hash.clone())
-            }
-            _ => None,
-        })
-        .await;
-
-    dbg!(&node_b_shutdown_tx_hash);
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+    tokio::time::sleep(Duration::from_secs(1)).await;
 
-    assert_eq!(node_a_shutdown_tx_hash, node_b_shutdown_tx_hash);
+    // The preimage of the offered tlc is saved in the channel state.
+    let state = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .expect("channel state");
+    let original = serde_json::to_string(&state).expect("serialize channel state");
+    assert!(original.contains(&hex::encode(preimage)));
+    let exported = serde_json::to_string(&state.redacted()).expect("serialize channel state");
+    assert!(!exported.contains(&hex::encode(preimage)));
 
+    // The exported state can be loaded into a test store to reproduce a problem.
+    let store = MemoryStore::default();
+    let imported = store.import_channel_state(&exported);
+    assert_eq!(imported.get_id(), channel_id);
     assert_eq!(
-        node_a.trace_tx_hash(node_a_shutdown_tx_hash.clone()).await,
-        Status::Committed
-    );
-    assert_eq!(
-        node_b.trace_tx_hash(node_b_shutdown_tx_hash.clone()).await,
-        Status::Committed
+        imported.get_current_commitment_numbers(),
+        state.get_current_commitment_numbers()
     );
-
-    // TODO: maybe also check shutdown tx outputs and output balances here.
+    assert_eq!(imported.to_local_amount, state.to_local_amount);
+    assert!(imported.latest_commitment_transaction.is_none());
+    assert!(imported.signer != state.signer);
+    assert!(store.get_channel_actor_state(&channel_id).is_some());
 }
 
 #[tokio::test]
-async fn test_open_channel_with_invalid_ckb_amount_range() {
-    init_tracing();
+async fn test_channel_event_log() {
+    let (node_a, node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
 
-    let [node_a, node_b] = NetworkNode::new_n_interconnected_nodes().await;
-    let message = |rpc_reply| {
-        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
-            OpenChannelCommand {
-                peer_id: node_b.peer_id.clone(),
-                public: true,
-                shutdown_script: None,
-                funding_amount: 0xfffffffffffffffffffffffffffffff,
-                funding_udt_type_script: None,
-                commitment_fee_rate: None,
-                commitment_delay_epoch: None,
-                funding_fee_rate: None,
-                tlc_locktime_expiry_delta: None,
-                tlc_min_value: None,
-                tlc_max_value: None,
-                tlc_fee_proportional_millionths: None,
-                max_tlc_number_in_flight: None,
-                max_tlc_value_in_flight: None,
+    let preimage = [2; 32];
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: Some(HashAlgorithm::CkbHash.hash(&preimage).into()),
+                        expiry: LockTime::new(100),
+                        preimage: None,
+                        onion_packet: vec![],
+                        previous_tlc: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::RemoveTlc(
+                    RemoveTlcCommand {
+                        id: add_tlc_result.tlc_id,
+                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+                            payment_preimage: preimage.into(),
+                        }),
+                    },
+                    rpc_reply,
+                ),
             },
-            rpc_reply,
         ))
-    };
-    let open_channel_result = call!(node_a.network_actor, message).expect("node_a alive");
-    eprintln!("{:?}", open_channel_result.as_ref().err().unwrap());
-    assert!(open_channel_result
-        .err()
-        .unwrap()
-        .contains("The funding amount should be less than 18446744073709551615"));
+    })
+    .expect("node_b alive")
+    .expect("successfully removed tlc");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let events: Vec<_> = node_a
+        .store
+        .get_channel_events(&channel_id, None, usize::MAX)
+        .into_iter()
+        .map(|entry| entry.event)
+        .collect();
+    let tlc_id = TLCId::Offered(add_tlc_result.tlc_id);
+    let added = events
+        .iter()
+        .position(
+            |event| matches!(event, ChannelLogEvent::TlcAdded { tlc_id: id, .. } if *id == tlc_id),
+        )
+        .expect("tlc added event");
+    let settled = events
+        .iter()
+        .position(|event| event == &ChannelLogEvent::TlcSettled { tlc_id })
+        .expect("tlc settled event");
+    assert!(added < settled);
+    assert!(events.iter().any(|event| matches!(
+        event,
+        ChannelLogEvent::StateChanged {
+            to: ChannelState::ChannelReady(),
+            ..
+        }
+    )));
+
+    // paginate the log with the cursor
+    let first_page = node_a.store.get_channel_events(&channel_id, None, 1);
+    assert_eq!(first_page.len(), 1);
+    let second_page = node_a
+        .store
+        .get_channel_events(&channel_id, Some(first_page[0].index), 1);
+    assert_eq!(second_page[0].index, first_page[0].index + 1);
 }
 
 #[tokio::test]
-async fn test_revoke_old_commitment_transaction() {
+async fn test_tlc_fail_delay() {
     init_tracing();
 
-    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+    // Node b fails back the received tlcs after 2 seconds.
+    let nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .fiber_config_updater(move |config| {
+                if i == 1 {
+                    config.tlc_fail_delay_ms = Some(2000);
+                }
+            })
+            .build()
+    })
+    .await;
+    let [node_a, node_b]: [NetworkNode; 2] = match nodes.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!(),
+    };
+    let (node_a, node_b, channel_id) =
+        establish_channel_between_nodes(node_a, node_b, 100000000000, 6200000000, false).await;
 
-    let message = |rpc_reply| {
-        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
-            OpenChannelCommand {
-                peer_id: node_b.peer_id.clone(),
-                public: false,
-                shutdown_script: None,
-                funding_amount: 100000000000,
-                funding_udt_type_script: None,
-                commitment_fee_rate: None,
-                commitment_delay_epoch: None,
-                funding_fee_rate: None,
-                tlc_locktime_expiry_delta: None,
-                tlc_min_value: None,
-                tlc_max_value: None,
-                tlc_fee_proportional_millionths: None,
-                max_tlc_number_in_flight: None,
-                max_tlc_value_in_flight: None,
-            },
-            rpc_reply,
-        ))
+    let add_tlc = |preimage: [u8; 32]| {
+        let network_actor = node_a.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id,
+                        command: ChannelCommand::AddTlc(
+                            AddTlcCommand {
+                                amount: 1000000000,
+                                hash_algorithm: HashAlgorithm::CkbHash,
+                                payment_hash: Some(HashAlgorithm::CkbHash.hash(preimage).into()),
+                                expiry: LockTime::new(100),
+                                preimage: None,
+                                onion_packet: vec![],
+                                previous_tlc: None,
+                            },
+                            rpc_reply,
+                        ),
+                    },
+                ))
+            })
+        }
+    };
+    let remove_tlc = |id: u64, reason: RemoveTlcReason| {
+        let network_actor = node_b.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id,
+                        command: ChannelCommand::RemoveTlc(
+                            RemoveTlcCommand { id, reason },
+                            rpc_reply,
+                        ),
+                    },
+                ))
+            })
+        }
+    };
+    let has_event = |event: ChannelLogEvent| {
+        node_b
+            .store
+            .get_channel_events(&channel_id, None, usize::MAX)
+            .into_iter()
+            .any(|entry| entry.event == event)
     };
-    let open_channel_result = call!(node_a.network_actor, message)
-        .expect("node_a alive")
-        .expect("open channel success");
 
-    node_b
-        .expect_event(|event| match event {
-            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, channel_id) => {
-                println!("A channel ({:?}) to {:?} create", &channel_id, peer_id);
-                assert_eq!(peer_id, &node_a.peer_id);
-                true
-            }
-            _ => false,
-        })
-        .await;
-    let message = |rpc_reply| {
-        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
-            AcceptChannelCommand {
-                temp_channel_id: open_channel_result.channel_id,
-                funding_amount: 6200000000,
-                shutdown_script: None,
-            },
-            rpc_reply,
-        ))
+    let failed_tlc = add_tlc([3; 32])
+        .await
+        .expect("node_a alive")
+        .expect("successfully added tlc");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    remove_tlc(
+        failed_tlc.tlc_id,
+        RemoveTlcReason::RemoveTlcFail(TlcErrPacket::new(TlcErr::new(
+            TlcErrorCode::IncorrectOrUnknownPaymentDetails,
+        ))),
+    )
+    .await
+    .expect("node_b alive")
+    .expect("successfully removed tlc");
+    let failed = ChannelLogEvent::TlcFailed {
+        tlc_id: TLCId::Received(failed_tlc.tlc_id),
     };
-    let accept_channel_result = call!(node_b.network_actor, message)
-        .expect("node_b alive")
-        .expect("accept channel success");
-    let new_channel_id = accept_channel_result.new_channel_id;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(!has_event(failed.clone()));
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert!(has_event(failed));
 
-    let commitment_tx = node_b
-        .expect_to_process_event(|event| match event {
-            NetworkServiceEvent::RemoteCommitmentSigned(peer_id, channel_id, num, tx) => {
-                println!(
-                    "Commitment tx (#{}) {:?} from {:?} for channel {:?} received",
-                    num, &tx, peer_id, channel_id
-                );
-                assert_eq!(peer_id, &node_a.peer_id);
-                assert_eq!(channel_id, &new_channel_id);
-                Some(tx.clone())
-            }
-            _ => None,
-        })
-        .await;
+    // The fulfilled tlcs are not delayed.
+    let preimage = [4; 32];
+    let fulfilled_tlc = add_tlc(preimage)
+        .await
+        .expect("node_a alive")
+        .expect("successfully added tlc");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    remove_tlc(
+        fulfilled_tlc.tlc_id,
+        RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+            payment_preimage: preimage.into(),
+        }),
+    )
+    .await
+    .expect("node_b alive")
+    .expect("successfully removed tlc");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(has_event(ChannelLogEvent::TlcSettled {
+        tlc_id: TLCId::Received(fulfilled_tlc.tlc_id),
+    }));
+}
 
-    node_a
-        .expect_event(|event| match event {
-            NetworkServiceEvent::ChannelReady(peer_id, channel_id, _funding_tx_hash) => {
-                println!(
-                    "A channel ({:?}) to {:?} is now ready",
-                    &channel_id, &peer_id
-                );
-                assert_eq!(peer_id, &node_b.peer_id);
-                assert_eq!(channel_id, &new_channel_id);
-                true
-            }
-            _ => false,
+#[test]
+fn test_tlc_fulfill_delay_bounded_by_expiry() {
+    assert_eq!(TlcFulfillDelay::default().sample(u64::MAX), None);
+
+    let delay = TlcFulfillDelay { jitter_ms: 30000 };
+    for _ in 0..100 {
+        assert!(delay
+            .sample(u64::MAX)
+            .map_or(true, |d| d <= Duration::from_millis(30000)));
+        // Only half of the time left before the expiry can be spent on the delay, and a block
+        // takes at least 8 seconds on average.
+        assert!(delay
+            .sample(1)
+            .map_or(true, |d| d <= Duration::from_millis(4000)));
+    }
+    assert_eq!(delay.sample(0), None);
+}
+
+#[tokio::test]
+async fn test_tlc_fulfill_delay_resumed_after_restart() {
+    init_tracing();
+
+    // Node b delays the fulfillments it forwards to node a for up to 10 seconds.
+    let ([node_a, mut node_b, node_c], [channel_ab, _]) =
+        create_3_nodes_with_public_channels(|i| {
+            NetworkNodeConfigBuilder::new()
+                .node_name(Some(format!("Node {i}")))
+                .fiber_config_updater(move |config| {
+                    if i == 1 {
+                        config.tlc_fulfill_delay_jitter_ms = Some(10000);
+                    }
+                })
+                .build()
         })
         .await;
+    node_b.chain_tip.update(1000);
 
-    node_b
-        .expect_event(|event| match event {
-            NetworkServiceEvent::ChannelReady(peer_id, channel_id, _funding_tx_hash) => {
-                println!(
-                    "A channel ({:?}) to {:?} is now ready",
-                    &channel_id, &peer_id
-                );
-                assert_eq!(peer_id, &node_a.peer_id);
-                assert_eq!(channel_id, &new_channel_id);
-                true
+    let has_delayed_removals = |node: &NetworkNode| {
+        node.store
+            .get_channel_actor_state(&channel_ab)
+            .map_or(false, |state| !state.delayed_tlc_removals.is_empty())
+    };
+    // The delay is random, retry until a fulfillment is delayed long enough to be observed.
+    let mut payment_hash = None;
+    for _ in 0..5 {
+        let hash = send_keysend_payment(&node_a, &node_c).await;
+        for _ in 0..50 {
+            if has_delayed_removals(&node_b) {
+                payment_hash = Some(hash);
+                break;
             }
-            _ => false,
-        })
-        .await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        if payment_hash.is_some() {
+            break;
+        }
+    }
+    let payment_hash = payment_hash.expect("a delayed fulfillment");
 
-    node_a
-        .network_actor
-        .send_message(NetworkActorMessage::Command(
-            NetworkActorCommand::ControlFiberChannel(ChannelCommandWithId {
-                channel_id: new_channel_id,
-                command: ChannelCommand::CommitmentSigned(),
-            }),
-        ))
-        .expect("node_a alive");
+    // The delayed fulfillment is saved and resumed after node b restarts.
+    node_b.restart().await;
+    let mut status = get_payment_status(&node_a, payment_hash).await;
+    for _ in 0..300 {
+        if status == PaymentSessionStatus::Success {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        status = get_payment_status(&node_a, payment_hash).await;
+    }
+    assert_eq!(status, PaymentSessionStatus::Success);
+    assert!(!has_delayed_removals(&node_b));
+}
 
-    let (x_only_aggregated_pubkey, signature, output, output_data) = node_a
-        .expect_to_process_event(|event| match event {
-            NetworkServiceEvent::RevokeAndAckReceived(
-                peer_id,
-                channel_id,
-                commitment_number,
-                x_only_aggregated_pubkey,
-                signature,
-                output,
-                output_data,
-            ) => {
-                assert_eq!(peer_id, &node_b.peer_id);
-                assert_eq!(channel_id, &new_channel_id);
-                assert_eq!(*commitment_number, 1u64);
-                Some((
-                    x_only_aggregated_pubkey.clone(),
-                    signature.clone(),
-                    output.clone(),
-                    output_data.clone(),
-                ))
-            }
-            _ => None,
+#[tokio::test]
+async fn test_payment_timed_out_with_attempt_in_flight() {
+    init_tracing();
+
+    let ([node_a, mut node_b, node_c], [channel_ab, _]) =
+        create_3_nodes_with_public_channels(|i| {
+            NetworkNodeConfigBuilder::new()
+                .node_name(Some(format!("Node {i}")))
+                .build()
         })
         .await;
 
+    let command = SendPaymentCommand {
+        target_pubkey: Some(node_c.fiber_config.public_key().into()),
+        amount: Some(10000000),
+        payment_hash: Some(gen_sha256_hash()),
+        final_cltv_delta: None,
+        invoice: None,
+        timeout: Some(2),
+        max_fee_amount: None,
+        max_parts: None,
+        keysend: None,
+        udt_type_script: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: None,
+    };
+    let retried_command = command.clone();
+    let payment_hash = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::SendPayment(command, rpc_reply))
+    })
+    .expect("node_a alive")
+    .expect("send payment")
+    .payment_hash;
+    // Node b goes offline, so the attempt is never resolved.
+    node_b.stop().await;
     assert_eq!(
-        node_a.submit_tx(commitment_tx.clone()).await,
-        Status::Committed
+        get_payment_status(&node_a, payment_hash).await,
+        PaymentSessionStatus::Inflight
     );
 
-    println!("commitment_tx: {:?}", commitment_tx);
+    // The deadline is recorded, but the payment stays in flight as its tlc is not removed.
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let payment = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::GetPayment(payment_hash, rpc_reply))
+    })
+    .expect("node_a alive")
+    .expect("get payment");
+    assert_eq!(payment.status, PaymentSessionStatus::Inflight);
+    assert!(payment.deadline_exceeded);
+    assert_eq!(payment.failure_reason, None);
 
-    let tx = Transaction::default()
-        .as_advanced_builder()
-        .cell_deps(get_cell_deps(vec![Contract::CommitmentLock], &None))
-        .input(
-            CellInput::new_builder()
-                .previous_output(commitment_tx.output_pts().get(0).unwrap().clone())
-                .build(),
-        )
-        .output(output)
-        .output_data(output_data)
-        .build();
+    // Retrying the payment is refused, only one tlc is ever offered for it.
+    let retry_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::SendPayment(retried_command, rpc_reply))
+    })
+    .expect("node_a alive");
+    assert!(retry_result
+        .err()
+        .expect("retry refused")
+        .contains("Payment session already exists"));
+    let state = node_a
+        .store
+        .get_channel_actor_state(&channel_ab)
+        .expect("channel state");
+    assert_eq!(state.get_next_offering_tlc_id(), 1);
+    assert_eq!(node_a.store.clear_failed_payment_sessions(u128::MAX), 0);
+    assert_eq!(node_a.store.prune_payment_sessions(u128::MAX, 0), 0);
+    assert!(node_a.store.get_payment_session(payment_hash).is_some());
+}
 
-    let empty_witness_args = [16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0];
-    let witness = [
-        empty_witness_args.to_vec(),
-        vec![0xFF],
-        1u64.to_be_bytes().to_vec(),
-        x_only_aggregated_pubkey.to_vec(),
-        signature.serialize().to_vec(),
-    ]
-    .concat();
+#[tokio::test]
+async fn test_trampoline_forwarding_checks_expiry_budget() {
+    init_tracing();
 
-    let revocation_tx = tx.as_advanced_builder().witness(witness.pack()).build();
+    let ([node_a, node_b, node_c], [channel_ab, _]) = create_3_nodes_with_public_channels(|i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("Node {i}")))
+            .build()
+    })
+    .await;
 
+    // Node a pays node c through the trampoline node b, which finds the route to node c.
+    let send_trampoline_payment = || async {
+        let command = SendPaymentCommand {
+            target_pubkey: Some(node_c.fiber_config.public_key().into()),
+            amount: Some(10000000),
+            payment_hash: None,
+            final_cltv_delta: None,
+            invoice: None,
+            timeout: None,
+            max_fee_amount: Some(100000),
+            max_parts: None,
+            keysend: Some(true),
+            udt_type_script: None,
+            allow_self_payment: false,
+            outgoing_channel: None,
+            probe: None,
+            trampoline_node: Some(node_b.fiber_config.public_key().into()),
+            keep_reserve: None,
+            max_fee_percent: None,
+            max_fee_rate_ppm: None,
+            fees_paid_by: None,
+        };
+        let payment_hash = call!(node_a.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::SendPayment(command, rpc_reply))
+        })
+        .expect("node_a alive")
+        .expect("send payment")
+        .payment_hash;
+        let mut status = get_payment_status(&node_a, payment_hash).await;
+        for _ in 0..100 {
+            if matches!(
+                status,
+                PaymentSessionStatus::Success | PaymentSessionStatus::Failed
+            ) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            status = get_payment_status(&node_a, payment_hash).await;
+        }
+        status
+    };
+
+    // The locktime reserved by node a covers the expiry delta of node b and the route to node c.
     assert_eq!(
-        node_a.submit_tx(revocation_tx.clone()).await,
-        Status::Committed
+        send_trampoline_payment().await,
+        PaymentSessionStatus::Success
     );
-}
 
-#[tokio::test]
-async fn test_channel_with_simple_update_operation() {
-    for algorithm in HashAlgorithm::supported_algorithms() {
-        do_test_channel_with_simple_update_operation(algorithm).await
-    }
+    // Node b rejects the payment when its expiry delta exceeds the reserved locktime.
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: channel_ab,
+                command: ChannelCommand::Update(
+                    UpdateCommand {
+                        enabled: None,
+                        tlc_locktime_expiry_delta: Some(DEFAULT_TRAMPOLINE_EXPIRY_DELTA + 1),
+                        tlc_minimum_value: None,
+                        tlc_maximum_value: None,
+                        tlc_fee_proportional_millionths: None,
+                        rebalance_policy: None,
+                        dust_tlc_limit: None,
+                        max_htlc_fraction_of_capacity: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("update channel");
+    assert_eq!(
+        send_trampoline_payment().await,
+        PaymentSessionStatus::Failed
+    );
 }
 
 #[tokio::test]
@@ -947,6 +4560,11 @@ async fn test_create_channel() {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: None,
+                open_timeout_seconds: None,
+                zero_conf: None,
             },
             rpc_reply,
         ))
@@ -971,6 +4589,9 @@ async fn test_create_channel() {
                 temp_channel_id: open_channel_result.channel_id,
                 funding_amount: 6200000000,
                 shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: None,
             },
             rpc_reply,
         ))
@@ -1072,6 +4693,11 @@ async fn test_reestablish_channel() {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                external_funding_tx: None,
+                open_timeout_seconds: None,
+                zero_conf: None,
             },
             rpc_reply,
         ))
@@ -1097,6 +4723,9 @@ async fn test_reestablish_channel() {
                 temp_channel_id: open_channel_result.channel_id,
                 funding_amount: 6200000000,
                 shutdown_script: None,
+                max_inbound_tlc_value: None,
+                max_outbound_tlc_value: None,
+                zero_conf: None,
             },
             rpc_reply,
         ))
@@ -1180,6 +4809,18 @@ async fn test_reestablish_channel() {
             _ => false,
         })
         .await;
+
+    // Wait for the reestablished channel state to be persisted.
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    let channel_id = node_a.store.get_channel_ids_by_peer(&node_b.peer_id)[0];
+    let stats = node_a
+        .store
+        .get_channel_actor_state(&channel_id)
+        .unwrap()
+        .connection_stats;
+    assert!(stats.connected_at.is_some());
+    assert!(stats.last_disconnected_at.is_some());
+    assert_eq!(stats.disconnection_count, 1);
 }
 
 #[tokio::test]
@@ -1200,6 +4841,7 @@ async fn test_force_close_channel_when_remote_is_offline() {
                     ShutdownCommand {
                         close_script: Script::default(),
                         fee_rate: FeeRate::from_u64(1000),
+                        max_fee_rate: None,
                         force: true,
                     },
                     rpc_reply,
@@ -1231,6 +4873,37 @@ async fn test_commitment_tx_capacity() {
     );
 }
 
+#[tokio::test]
+async fn test_channel_max_receivable_amount() {
+    let (amount_a, amount_b) = (16200000000, 10200000000);
+    let (node_a, node_b, channel_id) =
+        create_nodes_with_established_channel(amount_a, amount_b, true).await;
+
+    // Each party can receive at most the balance of the other party, the reserved ckb amount excluded.
+    let state_a = node_a.store.get_channel_actor_state(&channel_id).unwrap();
+    let state_b = node_b.store.get_channel_actor_state(&channel_id).unwrap();
+    assert!(state_a.to_remote_amount > 0);
+    assert_eq!(
+        state_a.get_max_receivable_amount(),
+        state_a.to_remote_amount
+    );
+    assert_eq!(
+        state_b.get_max_receivable_amount(),
+        state_b.to_remote_amount
+    );
+    assert_eq!(state_a.to_remote_amount, state_b.to_local_amount);
+
+    // The amount is capped by the maximal amount of a single received tlc.
+    let mut state_a = state_a;
+    state_a.max_inbound_tlc_value = Some(1000);
+    assert_eq!(state_a.get_max_receivable_amount(), 1000);
+    state_a.max_inbound_tlc_value = Some(state_a.to_remote_amount + 1);
+    assert_eq!(
+        state_a.get_max_receivable_amount(),
+        state_a.to_remote_amount
+    );
+}
+
 #[tokio::test]
 async fn test_connect_to_peers_with_mutual_channel_on_restart() {
     let node_a_funding_amount = 100000000000;
@@ -1,10 +1,15 @@
 use crate::fiber::types::Pubkey;
 use crate::{
     fiber::{
-        graph::{ChannelInfo, GraphError, NetworkGraph, NodeInfo, PathEdge},
+        graph::{
+            ChannelInfo, GossipFilter, GraphError, NetworkGraph, NetworkGraphStateStore, NodeInfo,
+            PathEdge, PaymentFailureReason, PaymentFeeCap, PaymentSession, PaymentSessionStatus,
+            RejectedRoute, RouteRejectReason,
+        },
         network::{get_chain_hash, SendPaymentCommand, SendPaymentData},
-        types::{ChannelAnnouncement, ChannelUpdate, Hash256, NodeAnnouncement},
+        types::{ChannelAnnouncement, ChannelUpdate, Hash256, NodeAnnouncement, TrampolineHopData},
     },
+    invoice::FeesPaidBy,
     store::Store,
 };
 use ckb_types::{
@@ -12,6 +17,7 @@ use ckb_types::{
     prelude::Entity,
 };
 use secp256k1::{PublicKey, SecretKey, XOnlyPublicKey};
+use std::collections::HashMap;
 
 use super::test_utils::generate_keypair;
 
@@ -27,6 +33,7 @@ struct MockNetworkGraph {
     pub keys: Vec<PublicKey>,
     pub edges: Vec<(usize, usize, OutPoint)>,
     pub graph: NetworkGraph<Store>,
+    pub store: Store,
 }
 
 impl MockNetworkGraph {
@@ -35,7 +42,7 @@ impl MockNetworkGraph {
         let store = Store::new(temp_path.path());
         let keypairs = generate_key_pairs(node_num + 1);
         let (secret_key1, public_key1) = keypairs[0];
-        let mut graph = NetworkGraph::new(store, public_key1.into());
+        let mut graph = NetworkGraph::new(store.clone(), public_key1.into());
         graph.add_node(NodeInfo {
             node_id: public_key1.into(),
             timestamp: 0,
@@ -66,6 +73,7 @@ impl MockNetworkGraph {
             keys: keypairs.into_iter().map(|x| x.1).collect(),
             edges: vec![],
             graph,
+            store,
         }
     }
 
@@ -117,6 +125,7 @@ impl MockNetworkGraph {
             timestamp: 0,
             node1_to_node2: None,
             node2_to_node1: None,
+            private: false,
         };
         self.graph.add_channel(channel_info);
         let channel_update = ChannelUpdate {
@@ -180,8 +189,16 @@ impl MockNetworkGraph {
     ) -> Result<Vec<PathEdge>, GraphError> {
         let source = self.keys[source].into();
         let target = self.keys[target].into();
-        self.graph
-            .find_route(source, target, amount, Some(max_fee), None, false)
+        self.graph.find_route(
+            source,
+            target,
+            amount,
+            Some(max_fee),
+            None,
+            false,
+            None,
+            None,
+        )
     }
 
     pub fn find_route_udt(
@@ -201,6 +218,8 @@ impl MockNetworkGraph {
             Some(max_fee),
             Some(udt_type_script),
             false,
+            None,
+            None,
         )
     }
 
@@ -283,6 +302,64 @@ fn test_graph_find_path_basic() {
     assert!(route.is_err());
 }
 
+#[test]
+fn test_graph_add_private_channel() {
+    let mut network = MockNetworkGraph::new(3);
+    let node1: Pubkey = network.keys[1].into();
+    let node2: Pubkey = network.keys[2].into();
+    let channel_outpoint = OutPoint::from_slice(&[0xff; 36]).unwrap();
+    let announcement = |node2_id: &Pubkey, capacity| {
+        ChannelAnnouncement::new_unsigned(
+            &node1,
+            node2_id,
+            channel_outpoint.clone(),
+            get_chain_hash(),
+            &XOnlyPublicKey::from_slice([0x01; 32].as_ref()).unwrap(),
+            capacity,
+            None,
+        )
+    };
+    let update = ChannelUpdate::new_unsigned(
+        get_chain_hash(),
+        channel_outpoint.clone(),
+        0,
+        0,
+        0,
+        144,
+        0,
+        0,
+        1000,
+    );
+
+    assert!(network
+        .graph
+        .add_private_channel(announcement(&node1, 1000), update.clone())
+        .is_err());
+    assert!(network
+        .graph
+        .add_private_channel(announcement(&node2, 0), update.clone())
+        .is_err());
+    network
+        .graph
+        .add_private_channel(announcement(&node2, 1000), update.clone())
+        .expect("add private channel");
+    assert!(network
+        .graph
+        .add_private_channel(announcement(&node2, 1000), update)
+        .is_err());
+
+    let channel = network.graph.get_channel(&channel_outpoint).unwrap();
+    assert!(channel.private);
+    assert!(channel.node1_to_node2.is_some());
+    assert!(channel.node2_to_node1.is_some());
+
+    // The private channel can be used in both directions.
+    let route = network.find_route(1, 2, 100, 1000).unwrap();
+    assert_eq!(route[0].channel_outpoint, channel_outpoint);
+    let route = network.find_route(2, 1, 100, 1000).unwrap();
+    assert_eq!(route[0].channel_outpoint, channel_outpoint);
+}
+
 #[test]
 fn test_graph_find_path_three_nodes() {
     let mut network = MockNetworkGraph::new(3);
@@ -485,6 +562,8 @@ fn test_graph_find_path_err() {
         Some(1000),
         None,
         false,
+        None,
+        None,
     );
     assert!(route.is_err());
 
@@ -495,10 +574,75 @@ fn test_graph_find_path_err() {
         Some(1000),
         None,
         false,
+        None,
+        None,
     );
     assert!(route.is_err());
 }
 
+#[test]
+fn test_graph_find_route_with_outgoing_channel() {
+    let mut network = MockNetworkGraph::new(3);
+    // Two channels between node0 and node2, node0 is the source node.
+    network.add_edge(0, 2, Some(1000), Some(2));
+    network.add_edge(0, 2, Some(1000), Some(1));
+    network.add_edge(0, 2, Some(50), Some(1));
+    network.add_edge(2, 3, Some(1000), Some(1));
+    let (node0, node3) = (network.keys[0], network.keys[3]);
+
+    for i in 0..2 {
+        let outgoing_channel = network.edges[i].2.clone();
+        let route = network
+            .graph
+            .find_route(
+                node0.into(),
+                node3.into(),
+                100,
+                Some(1000),
+                None,
+                false,
+                Some(&outgoing_channel),
+                None,
+            )
+            .unwrap();
+        assert_eq!(route.len(), 2);
+        assert_eq!(route[0].channel_outpoint, outgoing_channel);
+        assert_eq!(route[1].channel_outpoint, network.edges[3].2);
+    }
+
+    // The outgoing channel can't carry the payment, no other channel is chosen.
+    let route = network.graph.find_route(
+        node0.into(),
+        node3.into(),
+        100,
+        Some(1000),
+        None,
+        false,
+        Some(&network.edges[2].2),
+        None,
+    );
+    assert!(route
+        .unwrap_err()
+        .to_string()
+        .contains("no path found through outgoing channel"));
+
+    // The outgoing channel is not a channel of the source node.
+    let route = network.graph.find_route(
+        node0.into(),
+        node3.into(),
+        100,
+        Some(1000),
+        None,
+        false,
+        Some(&network.edges[3].2),
+        None,
+    );
+    assert!(route
+        .unwrap_err()
+        .to_string()
+        .contains("outgoing channel not found"));
+}
+
 #[test]
 fn test_graph_build_route_three_nodes() {
     let mut network = MockNetworkGraph::new(3);
@@ -521,6 +665,14 @@ fn test_graph_build_route_three_nodes() {
         udt_type_script: None,
         preimage: None,
         allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
     });
     eprintln!("return {:?}", route);
     assert!(route.is_ok());
@@ -560,6 +712,14 @@ fn test_graph_build_route_exceed_max_htlc_value() {
         udt_type_script: None,
         preimage: None,
         allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
     });
     assert!(route.is_err());
 }
@@ -586,6 +746,14 @@ fn test_graph_build_route_below_min_htlc_value() {
         udt_type_script: None,
         preimage: None,
         allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
     });
     assert!(route.is_err());
 }
@@ -631,6 +799,14 @@ fn test_graph_mark_failed_channel() {
         udt_type_script: None,
         preimage: None,
         allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
     });
     eprintln!("return {:?}", route);
     assert!(route.is_err());
@@ -652,6 +828,14 @@ fn test_graph_mark_failed_channel() {
         udt_type_script: None,
         preimage: None,
         allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
     });
     eprintln!("return {:?}", route);
     assert!(route.is_ok());
@@ -681,6 +865,14 @@ fn test_graph_mark_failed_node() {
         udt_type_script: None,
         preimage: None,
         allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
     });
     eprintln!("return {:?}", route);
     assert!(route.is_ok());
@@ -699,6 +891,14 @@ fn test_graph_mark_failed_node() {
         udt_type_script: None,
         preimage: None,
         allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
     });
     eprintln!("return {:?}", route);
     assert!(route.is_ok());
@@ -719,6 +919,14 @@ fn test_graph_mark_failed_node() {
         udt_type_script: None,
         preimage: None,
         allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
     });
     eprintln!("return {:?}", route);
     assert!(route.is_err());
@@ -737,6 +945,14 @@ fn test_graph_mark_failed_node() {
         udt_type_script: None,
         preimage: None,
         allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
     });
     eprintln!("return {:?}", route);
     assert!(route.is_err());
@@ -764,6 +980,13 @@ fn test_graph_payment_self_default_is_false() {
         keysend: Some(false),
         udt_type_script: None,
         allow_self_payment: false,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: None,
     };
     let payment_data = SendPaymentData::new(command, node0.into());
     let error = payment_data.unwrap_err().to_string();
@@ -782,6 +1005,14 @@ fn test_graph_payment_self_default_is_false() {
         udt_type_script: None,
         preimage: None,
         allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
     });
     assert!(route.is_err());
 }
@@ -809,6 +1040,13 @@ fn test_graph_payment_pay_single_path() {
         keysend: Some(false),
         udt_type_script: None,
         allow_self_payment: true,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: None,
     };
     let payment_data = SendPaymentData::new(command, node0.into());
     assert!(payment_data.is_ok());
@@ -842,6 +1080,13 @@ fn test_graph_payment_pay_self_will_ok() {
         keysend: Some(false),
         udt_type_script: None,
         allow_self_payment: true,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: None,
     };
     let payment_data = SendPaymentData::new(command, node0.into());
     assert!(payment_data.is_ok());
@@ -863,3 +1108,649 @@ fn test_graph_payment_pay_self_will_ok() {
     network.add_edge(2, 0, Some(1000), Some(2));
     network.build_route_with_expect(&payment_data, vec![2, 0]);
 }
+
+#[test]
+fn test_payment_session_timeout() {
+    let payment_data = |timeout| SendPaymentData {
+        target_pubkey: generate_keypair().1.into(),
+        amount: 100,
+        payment_hash: Hash256::default(),
+        invoice: None,
+        final_cltv_delta: Some(100),
+        timeout,
+        max_fee_amount: Some(1000),
+        max_parts: None,
+        keysend: false,
+        udt_type_script: None,
+        preimage: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
+    };
+
+    assert!(!PaymentSession::new(payment_data(None), 5).is_timed_out());
+    assert!(!PaymentSession::new(payment_data(Some(10)), 5).is_timed_out());
+
+    let mut session = PaymentSession::new(payment_data(Some(0)), 5);
+    assert!(session.is_timed_out());
+    session.set_failed_status("Payment timed out", PaymentFailureReason::Timeout);
+    assert_eq!(session.status, PaymentSessionStatus::Failed);
+    assert_eq!(session.failure_reason, Some(PaymentFailureReason::Timeout));
+
+    session.set_success_status();
+    assert_eq!(session.failure_reason, None);
+}
+
+#[test]
+fn test_graph_build_route_for_probe_payment() {
+    let mut network = MockNetworkGraph::new(5);
+    network.add_edge(0, 2, Some(500), Some(2));
+    network.add_edge(2, 3, Some(500), Some(2));
+
+    let node0 = network.keys[0];
+    let command = |payment_hash, amount| SendPaymentCommand {
+        target_pubkey: Some(network.keys[3].into()),
+        amount,
+        payment_hash,
+        final_cltv_delta: Some(100),
+        invoice: None,
+        timeout: Some(10),
+        max_fee_amount: Some(1000),
+        max_parts: None,
+        keysend: None,
+        udt_type_script: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: Some(true),
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: None,
+    };
+
+    let error =
+        SendPaymentData::new(command(Some(Hash256::default()), None), node0.into()).unwrap_err();
+    assert!(error.contains("probe payment should not have invoice or payment_hash"));
+
+    // The probe payment has a random payment hash without preimage and a tiny default amount.
+    let payment_data = SendPaymentData::new(command(None, None), node0.into()).unwrap();
+    assert!(payment_data.probe);
+    assert_eq!(payment_data.amount, 1);
+    assert_eq!(payment_data.preimage, None);
+    assert_ne!(payment_data.payment_hash, Hash256::default());
+    let other_payment_data = SendPaymentData::new(command(None, Some(100)), node0.into()).unwrap();
+    assert_eq!(other_payment_data.amount, 100);
+    assert_ne!(other_payment_data.payment_hash, payment_data.payment_hash);
+
+    network.build_route_with_expect(&payment_data, vec![2, 3]);
+
+    let mut session = PaymentSession::new(payment_data, 5);
+    session.set_probe_succeeded_status();
+    assert_eq!(session.status, PaymentSessionStatus::ProbeSucceeded);
+    assert_eq!(session.failure_reason, None);
+}
+
+#[test]
+fn test_graph_build_route_for_trampoline_payment() {
+    let mut network = MockNetworkGraph::new(5);
+    network.add_edge(0, 1, Some(100000), Some(1000));
+    network.add_edge(1, 2, Some(100000), Some(1000));
+
+    let node0 = network.keys[0];
+    let target: Pubkey = network.keys[4].into();
+    let trampoline_node: Pubkey = network.keys[2].into();
+    let command = |trampoline_node, max_fee_amount| SendPaymentCommand {
+        target_pubkey: Some(target),
+        amount: Some(1000),
+        payment_hash: Some(Hash256::default()),
+        final_cltv_delta: Some(100),
+        invoice: None,
+        timeout: Some(10),
+        max_fee_amount,
+        max_parts: None,
+        keysend: None,
+        udt_type_script: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: Some(trampoline_node),
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: None,
+    };
+
+    let error = SendPaymentData::new(command(target, Some(100)), node0.into()).unwrap_err();
+    assert!(error.contains("trampoline_node should be neither the target nor the source"));
+    let error = SendPaymentData::new(command(trampoline_node, None), node0.into()).unwrap_err();
+    assert!(error.contains("max_fee_amount is required for trampoline payment"));
+
+    // The target is unknown to the sender, the route ends at the trampoline node.
+    let payment_data =
+        SendPaymentData::new(command(trampoline_node, Some(100)), node0.into()).unwrap();
+    network.build_route_with_expect(&payment_data, vec![1, 2]);
+
+    let route = network.graph.build_route(&payment_data).unwrap();
+    let trampoline_hop = route.last().unwrap();
+    assert_eq!(trampoline_hop.next_hop, None);
+    assert_eq!(
+        trampoline_hop.trampoline,
+        Some(TrampolineHopData {
+            target_pubkey: target,
+            amount: 1000,
+            preimage: None,
+        })
+    );
+    // The trampoline node gets all the fee budget left after the fee of the route to it.
+    assert!(trampoline_hop.amount > 1000);
+    assert!(route[0].amount > trampoline_hop.amount);
+    assert!(route[0].amount <= 1000 + 100);
+    assert!(route[..route.len() - 1]
+        .iter()
+        .all(|hop| hop.trampoline.is_none()));
+
+    // The fee budget is not enough for the route to the trampoline node.
+    let payment_data =
+        SendPaymentData::new(command(trampoline_node, Some(1)), node0.into()).unwrap();
+    assert!(network.graph.build_route(&payment_data).is_err());
+}
+
+#[test]
+fn test_graph_find_circular_route_not_back_through_outgoing_channel() {
+    let mut network = MockNetworkGraph::new(3);
+    network.add_edge(0, 1, Some(1000), Some(1));
+    network.add_edge(1, 2, Some(1000), Some(1));
+    network.add_edge(2, 0, Some(1000), Some(1));
+    // Allow node1 to send back to node0 through the first channel.
+    let outgoing_channel = network.edges[0].2.clone();
+    network
+        .graph
+        .process_channel_update(ChannelUpdate {
+            signature: None,
+            chain_hash: get_chain_hash(),
+            version: 1,
+            message_flags: 0,
+            channel_flags: 0,
+            tlc_locktime_expiry_delta: 144,
+            tlc_fee_proportional_millionths: 1,
+            tlc_maximum_value: 10000,
+            tlc_minimum_value: 0,
+            channel_outpoint: outgoing_channel.clone(),
+        })
+        .unwrap();
+    let node0 = network.keys[0];
+
+    let route = network
+        .graph
+        .find_route(
+            node0.into(),
+            node0.into(),
+            100,
+            Some(1000),
+            None,
+            true,
+            Some(&outgoing_channel),
+            None,
+        )
+        .unwrap();
+    let channels: Vec<_> = route
+        .iter()
+        .map(|edge| edge.channel_outpoint.clone())
+        .collect();
+    assert_eq!(
+        channels,
+        vec![
+            outgoing_channel,
+            network.edges[1].2.clone(),
+            network.edges[2].2.clone()
+        ]
+    );
+}
+
+#[test]
+fn test_graph_build_circular_route() {
+    let mut network = MockNetworkGraph::new(3);
+    network.add_edge(0, 1, Some(1000), Some(1000));
+    network.add_edge(1, 2, Some(1000), Some(2000));
+    network.add_edge(2, 0, Some(1000), Some(3000));
+    network.add_edge(1, 0, Some(1000), Some(1000));
+    let [outgoing_channel, middle_channel, incoming_channel, direct_channel] =
+        [0, 1, 2, 3].map(|i| network.edges[i].2.clone());
+
+    let route = network
+        .graph
+        .build_circular_route(&outgoing_channel, &incoming_channel, 100, None, None)
+        .unwrap();
+    let channels: Vec<_> = route
+        .iter()
+        .filter_map(|hop| hop.channel_outpoint.clone())
+        .collect();
+    assert_eq!(
+        channels,
+        vec![
+            outgoing_channel.clone(),
+            middle_channel.clone(),
+            incoming_channel.clone()
+        ]
+    );
+    assert_eq!(route.last().unwrap().amount, 100);
+    // node2 charges 3000 ppm for 100, and node1 charges 2000 ppm for 101.
+    assert_eq!(route[0].amount, 102);
+
+    // The route comes back through the given incoming channel even if there is a shorter one.
+    let route = network
+        .graph
+        .build_circular_route(&outgoing_channel, &direct_channel, 100, None, None)
+        .unwrap();
+    assert_eq!(route.len(), 3);
+    assert_eq!(route[1].channel_outpoint, Some(direct_channel));
+
+    assert!(network
+        .graph
+        .build_circular_route(&outgoing_channel, &outgoing_channel, 100, None, None)
+        .is_err());
+    // The middle channel is not a channel of node0.
+    assert!(network
+        .graph
+        .build_circular_route(&outgoing_channel, &middle_channel, 100, None, None)
+        .is_err());
+    assert!(network
+        .graph
+        .build_circular_route(&outgoing_channel, &incoming_channel, 100, Some(1), None)
+        .is_err());
+}
+
+#[test]
+fn test_graph_build_route_with_keep_reserve() {
+    let mut network = MockNetworkGraph::new(4);
+    network.add_edge(0, 1, Some(1000), Some(1));
+    network.add_edge(1, 3, Some(1000), Some(1));
+    network.add_edge(0, 2, Some(1000), Some(100000));
+    network.add_edge(2, 3, Some(1000), Some(100000));
+    let node0 = network.keys[0];
+
+    let command = SendPaymentCommand {
+        target_pubkey: Some(network.keys[3].into()),
+        amount: Some(100),
+        payment_hash: Some(Hash256::default()),
+        final_cltv_delta: Some(100),
+        invoice: None,
+        timeout: Some(10),
+        max_fee_amount: Some(1000),
+        max_parts: None,
+        keysend: None,
+        udt_type_script: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: None,
+        keep_reserve: Some(10),
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: None,
+    };
+    let mut payment_data = SendPaymentData::new(command, node0.into()).unwrap();
+    network.build_route_with_expect(&payment_data, vec![1, 3]);
+
+    // The cheaper channel can't send the payment while keeping the reserve.
+    let limits = |limit1, limit2| {
+        HashMap::from([
+            (network.edges[0].2.clone(), limit1),
+            (network.edges[2].2.clone(), limit2),
+        ])
+    };
+    payment_data.first_hop_limits = Some(limits(50, 500));
+    network.build_route_with_expect(&payment_data, vec![2, 3]);
+
+    payment_data.first_hop_limits = Some(limits(50, 50));
+    let error = network.graph.build_route(&payment_data).unwrap_err();
+    assert!(matches!(
+        error,
+        GraphError::PathFind(message) if message.contains("keeps the reserve 10")
+    ));
+}
+
+#[test]
+fn test_graph_build_route_with_max_fee_percent() {
+    let mut network = MockNetworkGraph::new(3);
+    network.add_edge(0, 1, Some(1000), Some(100000));
+    network.add_edge(1, 2, Some(1000), Some(100000));
+    let node0 = network.keys[0];
+
+    let command = SendPaymentCommand {
+        target_pubkey: Some(network.keys[2].into()),
+        amount: Some(100),
+        payment_hash: Some(Hash256::default()),
+        final_cltv_delta: Some(100),
+        invoice: None,
+        timeout: Some(10),
+        max_fee_amount: Some(1000),
+        max_parts: None,
+        keysend: None,
+        udt_type_script: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: Some(5),
+        max_fee_rate_ppm: None,
+        fees_paid_by: None,
+    };
+    // The fee of the route is 10% of the amount.
+    let mut payment_data = SendPaymentData::new(command, node0.into()).unwrap();
+    let error = network.graph.build_route(&payment_data).unwrap_err();
+    assert!(matches!(
+        error,
+        GraphError::PathFind(message) if message.contains("exceeds 5% of the amount 100")
+    ));
+
+    payment_data.max_fee_percent = Some(10);
+    let route = network.graph.build_route(&payment_data).unwrap();
+    assert_eq!(route[0].amount, 110);
+
+    // 0 means no limit.
+    payment_data.max_fee_percent = Some(0);
+    assert!(network.graph.build_route(&payment_data).is_ok());
+}
+
+#[test]
+fn test_graph_build_route_with_max_fee_rate_ppm() {
+    let mut network = MockNetworkGraph::new(4);
+    // The route through node 1 charges 10% and the route through node 2 charges 30%.
+    network.add_edge(0, 1, Some(10000), Some(0));
+    network.add_edge(1, 3, Some(10000), Some(100000));
+    network.add_edge(0, 2, Some(10000), Some(0));
+    network.add_edge(2, 3, Some(10000), Some(300000));
+    let node0 = network.keys[0];
+
+    let command = SendPaymentCommand {
+        target_pubkey: Some(network.keys[3].into()),
+        amount: Some(1000),
+        payment_hash: Some(Hash256::default()),
+        final_cltv_delta: Some(100),
+        invoice: None,
+        timeout: Some(10),
+        max_fee_amount: Some(1000),
+        max_parts: None,
+        keysend: None,
+        udt_type_script: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: Some(50000),
+        fees_paid_by: None,
+    };
+    // The rate cap of 5% (50 shannons) is more restrictive than the fee amount cap.
+    let mut payment_data = SendPaymentData::new(command, node0.into()).unwrap();
+    assert_eq!(
+        payment_data.fee_cap(),
+        Some((50, PaymentFeeCap::MaxFeeRate))
+    );
+    assert!(network.graph.build_route(&payment_data).is_err());
+
+    payment_data.max_fee_rate_ppm = Some(200000);
+    assert_eq!(
+        payment_data.fee_cap(),
+        Some((200, PaymentFeeCap::MaxFeeRate))
+    );
+    let route = network.graph.build_route(&payment_data).unwrap();
+    assert_eq!(route[0].amount, 1100);
+
+    // The fee amount cap is applied when it's more restrictive than the rate cap.
+    payment_data.max_fee_amount = Some(99);
+    assert_eq!(
+        payment_data.fee_cap(),
+        Some((99, PaymentFeeCap::MaxFeeAmount))
+    );
+    assert!(network.graph.build_route(&payment_data).is_err());
+
+    payment_data.max_fee_amount = None;
+    payment_data.max_fee_rate_ppm = None;
+    assert_eq!(payment_data.fee_cap(), None);
+    assert!(network.graph.build_route(&payment_data).is_ok());
+}
+
+#[test]
+fn test_graph_build_route_with_fees_paid_by_receiver() {
+    let mut network = MockNetworkGraph::new(3);
+    network.add_edge(0, 1, Some(10000), Some(0));
+    network.add_edge(1, 2, Some(10000), Some(100000));
+    let node0 = network.keys[0];
+
+    let command = SendPaymentCommand {
+        target_pubkey: Some(network.keys[2].into()),
+        amount: Some(1100),
+        payment_hash: Some(Hash256::default()),
+        final_cltv_delta: Some(100),
+        invoice: None,
+        timeout: Some(10),
+        max_fee_amount: Some(1000),
+        max_parts: None,
+        keysend: None,
+        udt_type_script: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: Some(FeesPaidBy::Receiver),
+    };
+    // The fee of 10% is deducted from the amount, so the first hop sends no more than
+    // the amount, and the target receives the amount net of the fee.
+    let mut payment_data = SendPaymentData::new(command, node0.into()).unwrap();
+    let route = network.graph.build_route(&payment_data).unwrap();
+    assert_eq!(route[0].amount, 1100);
+    assert_eq!(route.last().unwrap().amount, 1000);
+
+    payment_data.fees_paid_by = FeesPaidBy::Sender;
+    let route = network.graph.build_route(&payment_data).unwrap();
+    assert_eq!(route[0].amount, 1210);
+    assert_eq!(route.last().unwrap().amount, 1100);
+}
+
+#[test]
+fn test_graph_gossip_filter() {
+    let mut network = MockNetworkGraph::new(6);
+    network.add_edge(1, 2, Some(1000), Some(1));
+    network.add_edge(2, 3, Some(1000), Some(1));
+    network.add_edge(4, 5, Some(1000), Some(1));
+    let keys = network.keys.clone();
+    let channel_announcement = |node1: usize, node2: usize| ChannelAnnouncement {
+        chain_hash: get_chain_hash(),
+        node1_id: keys[node1].into(),
+        node2_id: keys[node2].into(),
+        channel_outpoint: OutPoint::from_slice(&[0xff; 36]).unwrap(),
+        node1_signature: None,
+        node2_signature: None,
+        capacity: 1000,
+        ckb_key: XOnlyPublicKey::from_slice([0x01; 32].as_ref()).unwrap(),
+        ckb_signature: None,
+        udt_type_script: None,
+        features: 0,
+    };
+
+    // Everything is accepted without a filter.
+    assert!(network
+        .graph
+        .is_channel_in_gossip_filter(&channel_announcement(4, 6)));
+    assert!(network.graph.is_node_in_gossip_filter(&keys[6].into()));
+
+    network.graph.set_gossip_filter(Some(GossipFilter {
+        nodes: vec![keys[1].into()],
+        max_hops: 0,
+    }));
+    assert!(network
+        .graph
+        .is_channel_in_gossip_filter(&channel_announcement(1, 6)));
+    // Our own channels are always accepted.
+    assert!(network
+        .graph
+        .is_channel_in_gossip_filter(&channel_announcement(0, 6)));
+    assert!(!network
+        .graph
+        .is_channel_in_gossip_filter(&channel_announcement(2, 6)));
+    assert!(!network
+        .graph
+        .is_channel_in_gossip_filter(&channel_announcement(4, 6)));
+    assert!(network.graph.is_node_in_gossip_filter(&keys[2].into()));
+    assert!(!network.graph.is_node_in_gossip_filter(&keys[3].into()));
+
+    network.graph.set_gossip_filter(Some(GossipFilter {
+        nodes: vec![keys[1].into()],
+        max_hops: 1,
+    }));
+    assert!(network
+        .graph
+        .is_channel_in_gossip_filter(&channel_announcement(2, 6)));
+    assert!(!network
+        .graph
+        .is_channel_in_gossip_filter(&channel_announcement(3, 6)));
+    assert!(!network
+        .graph
+        .is_channel_in_gossip_filter(&channel_announcement(4, 6)));
+    assert!(network.graph.is_node_in_gossip_filter(&keys[3].into()));
+    assert!(!network.graph.is_node_in_gossip_filter(&keys[4].into()));
+}
+
+#[test]
+fn test_graph_find_rejected_route() {
+    let mut network = MockNetworkGraph::new(3);
+    network.add_edge(0, 2, Some(1000000), Some(50000));
+    network.add_edge(2, 3, Some(1000000), Some(50000));
+    let node3 = network.keys[3];
+    let payment_data = SendPaymentData {
+        target_pubkey: node3.into(),
+        amount: 1000,
+        payment_hash: Hash256::default(),
+        invoice: None,
+        final_cltv_delta: Some(100),
+        timeout: Some(10),
+        max_fee_amount: None,
+        max_parts: None,
+        keysend: false,
+        udt_type_script: None,
+        preimage: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: false,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: Some(0),
+        max_fee_rate_ppm: None,
+        fees_paid_by: FeesPaidBy::Sender,
+        first_hop_limits: None,
+    };
+    let route = network.graph.build_route(&payment_data).unwrap();
+    let fee = route[0].amount - payment_data.amount;
+    assert!(fee > 0);
+    // The route is not rejected without the fee constraints.
+    assert_eq!(network.graph.find_rejected_route(&payment_data), None);
+
+    let payment_data = SendPaymentData {
+        max_fee_amount: Some(fee - 1),
+        ..payment_data
+    };
+    assert!(network.graph.build_route(&payment_data).is_err());
+    assert_eq!(
+        network.graph.find_rejected_route(&payment_data),
+        Some(RejectedRoute {
+            fee,
+            reason: RouteRejectReason::MaxFeeAmount,
+        })
+    );
+
+    let payment_data = SendPaymentData {
+        max_fee_amount: Some(fee),
+        max_fee_rate_ppm: Some(1),
+        ..payment_data
+    };
+    assert_eq!(
+        network.graph.find_rejected_route(&payment_data),
+        Some(RejectedRoute {
+            fee,
+            reason: RouteRejectReason::MaxFeeRate,
+        })
+    );
+
+    let payment_data = SendPaymentData {
+        max_fee_amount: None,
+        max_fee_rate_ppm: None,
+        max_fee_percent: Some(1),
+        ..payment_data
+    };
+    assert!(network.graph.build_route(&payment_data).is_err());
+    assert_eq!(
+        network.graph.find_rejected_route(&payment_data),
+        Some(RejectedRoute {
+            fee,
+            reason: RouteRejectReason::MaxFeePercent,
+        })
+    );
+}
+
+#[test]
+fn test_graph_load_from_snapshot() {
+    let mut network = MockNetworkGraph::new(3);
+    network.add_edge(1, 2, Some(1000), Some(1));
+    network.add_edge(2, 3, Some(1000), Some(1));
+    assert!(network.graph.save_snapshot());
+    // The snapshot is not saved again until the graph is changed.
+    assert!(!network.graph.save_snapshot());
+    let snapshot = network
+        .store
+        .get_graph_snapshot()
+        .expect("snapshot is saved");
+    assert_eq!(snapshot.channels.len(), 2);
+    assert_eq!(snapshot.nodes.len(), 4);
+
+    let mut graph = NetworkGraph::new(network.store.clone(), network.keys[0].into());
+    let mut channels: Vec<_> = graph.channels().cloned().collect();
+    channels.sort_by_key(|channel| channel.out_point().as_slice().to_vec());
+    assert_eq!(channels, snapshot.channels);
+    assert_eq!(graph.nodes().count(), 4);
+    assert_eq!(graph.get_best_height(), snapshot.best_height);
+    assert_eq!(
+        graph.get_last_update_timestamp(),
+        snapshot.last_update_timestamp
+    );
+    // The graph loaded from the snapshot doesn't need to save it again.
+    assert!(!graph.save_snapshot());
+
+    // The changes after the snapshot are logged and loaded along with the snapshot.
+    network.add_edge(1, 3, Some(1000), Some(1));
+    let changes = network.store.get_graph_changes(snapshot.change_index);
+    assert_eq!(changes.channels.len(), 1);
+    assert_eq!(network.store.get_graph_snapshot(), Some(snapshot.clone()));
+    let mut graph = NetworkGraph::new(network.store.clone(), network.keys[0].into());
+    assert_eq!(graph.channels().count(), 3);
+
+    // Only the changes are applied to the saved snapshot, and the applied changes are removed.
+    assert!(graph.save_snapshot());
+    let new_snapshot = network.store.get_graph_snapshot().unwrap();
+    assert_eq!(new_snapshot.channels.len(), 3);
+    assert_eq!(new_snapshot.channels[..2], snapshot.channels[..]);
+    assert_eq!(new_snapshot.change_index, changes.next_index);
+    assert_eq!(
+        network.store.get_graph_changes(0).next_index,
+        changes.next_index
+    );
+    assert!(!graph.save_snapshot());
+
+    // The snapshot and the logged changes are removed once the snapshot is disabled.
+    let store = network.store.clone().with_graph_snapshot(false);
+    assert_eq!(store.get_graph_snapshot(), None);
+    assert_eq!(store.get_graph_changes(0), Default::default());
+}
@@ -1,8 +1,13 @@
 use super::test_utils::{init_tracing, NetworkNode};
 use crate::{
     fiber::{
+        config::{ChannelAcceptancePolicy, GraphSyncPeerStrategy},
+        fee::SuggestedFeeRates,
         graph::{ChannelInfo, NetworkGraphStateStore},
-        network::{get_chain_hash, NetworkActorStateStore},
+        network::{
+            get_chain_hash, normalize_peer_address, ConnectionRateLimiter, NetworkActorStateStore,
+            SendPaymentCommand, SetConnectionLimitsCommand,
+        },
         tests::test_utils::NetworkNodeConfigBuilder,
         types::{
             ChannelAnnouncement, ChannelUpdate, FiberBroadcastMessage, FiberMessage,
@@ -13,7 +18,7 @@ use crate::{
     NetworkServiceEvent,
 };
 use ckb_hash::blake2b_256;
-use ckb_jsonrpc_types::Status;
+use ckb_jsonrpc_types::{FeeRateStatistics, Status};
 use ckb_types::{
     core::TransactionView,
     packed::{CellOutput, ScriptBuilder},
@@ -22,10 +27,17 @@ use ckb_types::{
     packed::OutPoint,
     prelude::{Builder, Entity, Pack},
 };
-use std::{borrow::Cow, str::FromStr};
+use ractor::call;
+use std::{
+    borrow::Cow,
+    net::{IpAddr, Ipv4Addr},
+    str::FromStr,
+    time::{Duration, Instant},
+};
 use tentacle::{
     multiaddr::{MultiAddr, Protocol},
     secio::PeerId,
+    service::ServiceEvent,
 };
 
 fn get_test_priv_key() -> Privkey {
@@ -385,6 +397,57 @@ async fn test_channel_update_version() {
     );
 }
 
+#[tokio::test]
+async fn test_channel_update_too_far_in_future() {
+    let (node, channel_info, _priv_key, sk1, _sk2) = create_a_channel().await;
+
+    let create_channel_update = |version: u64, key: &Privkey| {
+        let mut channel_update = ChannelUpdate::new_unsigned(
+            get_chain_hash(),
+            channel_info.announcement_msg.channel_outpoint.clone(),
+            version,
+            0,
+            0,
+            42,
+            0,
+            0,
+            10,
+        );
+
+        channel_update.signature = Some(key.sign(channel_update.message_to_sign()));
+        node.network_actor
+            .send_message(NetworkActorMessage::Event(NetworkActorEvent::PeerMessage(
+                get_test_peer_id(),
+                FiberMessage::BroadcastMessage(FiberBroadcastMessage::ChannelUpdate(
+                    channel_update.clone(),
+                )),
+            )))
+            .expect("send message to network actor");
+        channel_update
+    };
+    let now = std::time::UNIX_EPOCH.elapsed().unwrap().as_secs();
+    let get_last_update_message = || {
+        let new_channel_info = node
+            .store
+            .get_channels(Some(channel_info.announcement_msg.channel_outpoint.clone()));
+        assert_eq!(new_channel_info.len(), 1);
+        new_channel_info[0]
+            .node2_to_node1
+            .as_ref()
+            .map(|info| info.last_update_message.clone())
+    };
+
+    // A channel update beyond the default skew tolerance of one hour is dropped.
+    let _channel_update_future = create_channel_update(now + 2 * 60 * 60, &sk1);
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    assert_eq!(get_last_update_message(), None);
+
+    // A channel update within the tolerance is accepted.
+    let channel_update = create_channel_update(now + 60, &sk1);
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    assert_eq!(get_last_update_message(), Some(channel_update));
+}
+
 #[tokio::test]
 async fn test_sync_node_announcement_version() {
     init_tracing();
@@ -492,6 +555,182 @@ async fn test_sync_node_announcement_on_startup() {
     assert!(!node.is_empty());
 }
 
+#[tokio::test]
+async fn test_graph_sync_status_with_preferred_peer_strategy() {
+    init_tracing();
+
+    let mut node1 = new_synced_node("node1").await;
+    let mut node2 = NetworkNode::new_with_config(
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some("node2".to_string()))
+            .fiber_config_updater(|config| {
+                config.graph_sync_peer_strategy = Some(GraphSyncPeerStrategy::Preferred);
+            })
+            .build(),
+    )
+    .await;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::GraphSyncStatus((), rpc_reply))
+    };
+    let status = call!(node2.network_actor, message)
+        .expect("node2 alive")
+        .expect("get graph sync status");
+    assert_eq!(status.status, "Running");
+    assert_eq!(status.strategy, GraphSyncPeerStrategy::Preferred);
+    assert_eq!(status.progress, 0);
+    assert!(status.queried_peers.is_empty());
+
+    node1.connect_to(&node2).await;
+    node2
+        .expect_event(|c| matches!(c, NetworkServiceEvent::SyncingCompleted))
+        .await;
+
+    let status = call!(node2.network_actor, message)
+        .expect("node2 alive")
+        .expect("get graph sync status");
+    assert_eq!(status.status, "Done");
+    assert_eq!(status.progress, 100);
+    assert_eq!(status.queried_peers, vec![node1.peer_id.clone()]);
+    assert_eq!(status.succeeded, 1);
+    assert_eq!(status.failed, 0);
+}
+
+#[tokio::test]
+async fn test_send_payment_gated_until_node_ready() {
+    init_tracing();
+
+    let mut node1 = new_synced_node("node1").await;
+    let mut node2 = NetworkNode::new_with_config(
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some("node2".to_string()))
+            .fiber_config_updater(|config| {
+                config.payment_ready_min_peers = Some(1);
+            })
+            .build(),
+    )
+    .await;
+
+    let health =
+        |rpc_reply| NetworkActorMessage::Command(NetworkActorCommand::Health((), rpc_reply));
+    let response = call!(node2.network_actor, health)
+        .expect("node2 alive")
+        .expect("get health");
+    assert!(!response.ready_for_payments);
+    assert_eq!(response.peers_count, 0);
+
+    let command = SendPaymentCommand {
+        target_pubkey: Some(get_test_priv_key().pubkey()),
+        amount: Some(100),
+        payment_hash: None,
+        final_cltv_delta: None,
+        invoice: None,
+        timeout: None,
+        max_fee_amount: None,
+        max_parts: None,
+        keysend: Some(true),
+        udt_type_script: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: None,
+    };
+    let error = call!(node2.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::SendPayment(command, rpc_reply))
+    })
+    .expect("node2 alive")
+    .unwrap_err();
+    assert!(
+        error.contains("Node not ready"),
+        "unexpected error: {}",
+        error
+    );
+
+    node1.connect_to(&node2).await;
+    node2
+        .expect_event(|c| matches!(c, NetworkServiceEvent::SyncingCompleted))
+        .await;
+    let response = call!(node2.network_actor, health)
+        .expect("node2 alive")
+        .expect("get health");
+    assert!(response.ready_for_payments);
+    assert_eq!(response.peers_count, 1);
+}
+
+async fn send_keysend_payment(node: &NetworkNode) -> String {
+    let command = SendPaymentCommand {
+        target_pubkey: Some(get_test_pub_key()),
+        amount: Some(100),
+        payment_hash: None,
+        final_cltv_delta: None,
+        invoice: None,
+        timeout: None,
+        max_fee_amount: None,
+        max_parts: None,
+        keysend: Some(true),
+        udt_type_script: None,
+        allow_self_payment: false,
+        outgoing_channel: None,
+        probe: None,
+        trampoline_node: None,
+        keep_reserve: None,
+        max_fee_percent: None,
+        max_fee_rate_ppm: None,
+        fees_paid_by: None,
+    };
+    call!(node.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::SendPayment(command, rpc_reply))
+    })
+    .expect("node alive")
+    .unwrap_err()
+}
+
+#[tokio::test]
+async fn test_send_keysend_payment_to_recipient_without_keysend_feature() {
+    init_tracing();
+
+    let node = new_synced_node("node").await;
+    let announce_features = |version: u64, features: u64| {
+        let mut node_announcement = create_fake_node_announcement_mesage_version1();
+        node_announcement.version = version;
+        node_announcement.features = features;
+        node_announcement.signature =
+            Some(get_test_priv_key().sign(node_announcement.message_to_sign()));
+        node.network_actor
+            .send_message(NetworkActorMessage::Event(NetworkActorEvent::PeerMessage(
+                get_test_peer_id(),
+                FiberMessage::BroadcastMessage(FiberBroadcastMessage::NodeAnnouncement(
+                    node_announcement,
+                )),
+            )))
+            .expect("send message to network actor");
+    };
+
+    // The recipient announcing other features only is rejected before any attempt.
+    announce_features(1, 1 << 1);
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    let error = send_keysend_payment(&node).await;
+    assert!(
+        error.contains("does not support keysend payments"),
+        "unexpected error: {}",
+        error
+    );
+
+    // The recipient announcing no features may be running an older version, and is not checked.
+    announce_features(2, 0);
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    let error = send_keysend_payment(&node).await;
+    assert!(
+        !error.contains("does not support keysend payments"),
+        "unexpected error: {}",
+        error
+    );
+}
+
 // Test that we can sync the network graph with peers.
 // We will first create a node and announce a fake node announcement to the network.
 // Then we will create another node and connect to the first node.
@@ -642,3 +881,366 @@ async fn test_saving_and_connecting_to_node() {
     )
     .await;
 }
+
+#[tokio::test]
+async fn test_gossip_rate_limit() {
+    init_tracing();
+
+    let mut node = NetworkNode::new_with_config(
+        NetworkNodeConfigBuilder::new()
+            .fiber_config_updater(|config| {
+                config.gossip_rate_limit_max_messages = Some(1);
+                config.gossip_rate_limit_interval_seconds = Some(3600);
+            })
+            .build(),
+    )
+    .await;
+    node.network_actor
+        .send_message(NetworkActorMessage::Command(
+            NetworkActorCommand::MarkSyncingDone,
+        ))
+        .expect("send message to network actor");
+    node.expect_event(|c| matches!(c, NetworkServiceEvent::SyncingCompleted))
+        .await;
+
+    let test_pub_key = get_test_pub_key();
+    let test_peer_id = get_test_peer_id();
+    for announcement in [
+        create_fake_node_announcement_mesage_version1(),
+        create_fake_node_announcement_mesage_version2(),
+    ] {
+        node.network_actor
+            .send_message(NetworkActorMessage::Event(NetworkActorEvent::PeerMessage(
+                test_peer_id.clone(),
+                FiberMessage::BroadcastMessage(FiberBroadcastMessage::NodeAnnouncement(
+                    announcement,
+                )),
+            )))
+            .expect("send message to network actor");
+    }
+
+    // Wait for the broadcast messages to be processed.
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    let node_info = node.store.get_nodes(Some(test_pub_key));
+    match node_info.first() {
+        Some(n) if n.anouncement_msg.version == 1 => {}
+        _ => panic!(
+            "Must have version 1 announcement message as the second one is rate limited, found {:?}",
+            &node_info
+        ),
+    }
+
+    // Messages from other peers are not affected by the rate limit of this peer.
+    let (other_peer_id, _) = get_fake_peer_id_and_address();
+    node.network_actor
+        .send_message(NetworkActorMessage::Event(NetworkActorEvent::PeerMessage(
+            other_peer_id,
+            FiberMessage::BroadcastMessage(FiberBroadcastMessage::NodeAnnouncement(
+                create_fake_node_announcement_mesage_version3(),
+            )),
+        )))
+        .expect("send message to network actor");
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    let node_info = node.store.get_nodes(Some(test_pub_key));
+    match node_info.first() {
+        Some(n) if n.anouncement_msg.version == 3 => {}
+        _ => panic!(
+            "Must have version 3 announcement message, found {:?}",
+            &node_info
+        ),
+    }
+}
+
+#[test]
+fn test_channel_acceptance_policy() {
+    let peer_id = PeerId::random();
+    let policy = ChannelAcceptancePolicy {
+        min_ckb_funding_amount: 100,
+        max_ckb_funding_amount: 1000,
+        ..Default::default()
+    };
+    assert!(policy.check(&peer_id, 100, None).is_ok());
+    assert!(policy.check(&peer_id, 1000, None).is_ok());
+    assert!(policy.check(&peer_id, 99, None).is_err());
+    assert!(policy.check(&peer_id, 1001, None).is_err());
+    assert!(policy
+        .check(&peer_id, 100, Some(&ScriptBuilder::default().build()))
+        .unwrap_err()
+        .contains("Invalid UDT type script"));
+
+    let policy = ChannelAcceptancePolicy {
+        allowed_peers: vec![peer_id.clone()],
+        ..Default::default()
+    };
+    assert!(policy.check(&peer_id, 0, None).is_ok());
+    assert!(policy.check(&PeerId::random(), 0, None).is_err());
+
+    let policy = ChannelAcceptancePolicy {
+        denied_peers: vec![peer_id.clone()],
+        ..Default::default()
+    };
+    assert!(policy.check(&peer_id, 0, None).is_err());
+    assert!(policy.check(&PeerId::random(), 0, None).is_ok());
+}
+
+#[test]
+fn test_suggested_fee_rates_from_statistics() {
+    let statistics = |mean: u64, median: u64| {
+        Some(FeeRateStatistics {
+            mean: mean.into(),
+            median: median.into(),
+        })
+    };
+
+    // No transactions in recent blocks, fall back to the minimal fee rate.
+    assert_eq!(
+        SuggestedFeeRates::from_statistics(None, None, None, 1000),
+        SuggestedFeeRates {
+            low: 1000,
+            medium: 1000,
+            high: 1000,
+        }
+    );
+    assert_eq!(
+        SuggestedFeeRates::from_statistics(
+            statistics(3000, 2000),
+            statistics(2000, 2500),
+            statistics(4000, 5000),
+            1000
+        ),
+        SuggestedFeeRates {
+            low: 2000,
+            medium: 2500,
+            high: 5000,
+        }
+    );
+    // The fee rates are never decreasing from low to high.
+    assert_eq!(
+        SuggestedFeeRates::from_statistics(
+            statistics(3000, 4000),
+            statistics(500, 2000),
+            None,
+            1000
+        ),
+        SuggestedFeeRates {
+            low: 3000,
+            medium: 3000,
+            high: 3000,
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_suggest_fee_rates() {
+    init_tracing();
+
+    let node = NetworkNode::new().await;
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::SuggestFeeRates((), rpc_reply))
+    };
+    // The mock chain has no fee rate statistics.
+    let fee_rates = call!(node.network_actor, message)
+        .expect("node alive")
+        .expect("suggest fee rates");
+    assert_eq!(
+        fee_rates,
+        SuggestedFeeRates {
+            low: 1000,
+            medium: 1000,
+            high: 1000,
+        }
+    );
+
+    let node = NetworkNode::new_with_config(
+        NetworkNodeConfigBuilder::new()
+            .fiber_config_updater(|config| {
+                config.suggested_fee_rates = vec![2000, 3000, 5000];
+            })
+            .build(),
+    )
+    .await;
+    let fee_rates = call!(node.network_actor, message)
+        .expect("node alive")
+        .expect("suggest fee rates");
+    assert_eq!(
+        fee_rates,
+        SuggestedFeeRates {
+            low: 2000,
+            medium: 3000,
+            high: 5000,
+        }
+    );
+}
+
+#[test]
+fn test_normalize_peer_address() {
+    let peer_id = get_test_peer_id().to_base58();
+    let normalize = |address: &str| {
+        normalize_peer_address(&MultiAddr::from_str(address).expect("valid multiaddr"))
+            .map(|address| address.to_string())
+    };
+    let canonical = format!("/ip4/127.0.0.1/tcp/8228/p2p/{}", peer_id);
+    for address in [
+        canonical.clone(),
+        format!("/p2p/{}/ip4/127.0.0.1/tcp/8228", peer_id),
+        format!("/tcp/8228/ip4/127.0.0.1/p2p/{}", peer_id),
+        format!(
+            "/ip4/127.0.0.1/tcp/8228/tcp/8228/p2p/{}/p2p/{}",
+            peer_id, peer_id
+        ),
+        format!("/ip6/::ffff:127.0.0.1/tcp/8228/p2p/{}", peer_id),
+    ] {
+        assert_eq!(normalize(&address).unwrap(), canonical);
+    }
+    assert_eq!(
+        normalize(&format!(
+            "/dns4/Node.Example.COM./tcp/443/wss/p2p/{}",
+            peer_id
+        ))
+        .unwrap(),
+        format!("/dns4/node.example.com/tcp/443/wss/p2p/{}", peer_id)
+    );
+
+    for address in [
+        // no peer id
+        "/ip4/127.0.0.1/tcp/8228".to_string(),
+        // no host
+        format!("/tcp/8228/p2p/{}", peer_id),
+        // no port
+        format!("/ip4/127.0.0.1/p2p/{}", peer_id),
+        format!("/ip4/127.0.0.1/tcp/0/p2p/{}", peer_id),
+        format!("/ip4/224.0.0.1/tcp/8228/p2p/{}", peer_id),
+        format!("/ip6/ff02::1/tcp/8228/p2p/{}", peer_id),
+        // conflicting components
+        format!("/ip4/127.0.0.1/tcp/8228/tcp/8229/p2p/{}", peer_id),
+        format!("/ip4/127.0.0.1/ip4/127.0.0.2/tcp/8228/p2p/{}", peer_id),
+    ] {
+        assert!(
+            normalize(&address).is_err(),
+            "{} should be rejected",
+            address
+        );
+    }
+}
+
+#[test]
+fn test_connection_rate_limiter_backoff() {
+    let interval = Duration::from_secs(60);
+    let mut limiter = ConnectionRateLimiter::new(2, interval);
+    let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    let other_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+    let start = Instant::now();
+    let at = |seconds: u64| start + Duration::from_secs(seconds);
+
+    // The address exceeding the limit is banned for one interval.
+    assert!(limiter.check_at(ip, at(0)));
+    assert!(limiter.check_at(ip, at(1)));
+    assert!(!limiter.check_at(ip, at(2)));
+    assert!(limiter.check_at(other_ip, at(2)));
+    assert!(!limiter.check_at(ip, at(61)));
+
+    // The backoff doubles on the violation in the next window.
+    assert!(limiter.check_at(ip, at(62)));
+    assert!(limiter.check_at(ip, at(63)));
+    assert!(!limiter.check_at(ip, at(64)));
+    assert!(!limiter.check_at(ip, at(183)));
+
+    // A window without violation resets the backoff, the next violation is banned for one
+    // interval again.
+    assert!(limiter.check_at(ip, at(184)));
+    assert!(limiter.check_at(ip, at(244)));
+    assert!(limiter.check_at(ip, at(245)));
+    assert!(!limiter.check_at(ip, at(246)));
+    assert!(!limiter.check_at(ip, at(305)));
+    assert!(limiter.check_at(ip, at(306)));
+}
+
+#[tokio::test]
+async fn test_inbound_connection_limits() {
+    init_tracing();
+
+    let mut node = NetworkNode::new_with_config(
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some("node".to_string()))
+            .fiber_config_updater(|config| {
+                config.max_peers = Some(1);
+                config.connection_rate_limit_interval_seconds = Some(3600);
+            })
+            .build(),
+    )
+    .await;
+    let mut peers = vec![];
+    for i in 0..3 {
+        peers.push(
+            NetworkNode::new_with_config(
+                NetworkNodeConfigBuilder::new()
+                    .node_name(Some(format!("peer{}", i)))
+                    .build(),
+            )
+            .await,
+        );
+    }
+    let health = |node: &NetworkNode| {
+        let actor = node.network_actor.clone();
+        async move {
+            call!(actor, |rpc_reply| NetworkActorMessage::Command(
+                NetworkActorCommand::Health((), rpc_reply)
+            ))
+            .expect("node alive")
+            .expect("get health")
+        }
+    };
+    // The rejected peer may not see the connection as established, so wait for the session
+    // to be closed instead.
+    async fn expect_rejected(peer: &mut NetworkNode, node_id: &PeerId) {
+        peer.expect_event(|event| match event {
+            NetworkServiceEvent::ServiceEvent(ServiceEvent::SessionClose { session_context }) => {
+                session_context
+                    .remote_pubkey
+                    .as_ref()
+                    .map(PeerId::from_public_key)
+                    .as_ref()
+                    == Some(node_id)
+            }
+            _ => false,
+        })
+        .await;
+    }
+
+    peers[0].connect_to(&node).await;
+    // The number of peers reaches the limit.
+    peers[1].connect_to_nonblocking(&node).await;
+    expect_rejected(&mut peers[1], &node.peer_id).await;
+    let response = health(&node).await;
+    assert_eq!(response.peers_count, 1);
+    assert_eq!(response.inbound_peers_count, 1);
+    assert_eq!(response.outbound_peers_count, 0);
+    assert_eq!(response.rejected_connections_count, 1);
+
+    // The limits are changed at runtime, all the peers connect from the same address, so the
+    // third connection exceeds the rate limit.
+    call!(
+        node.network_actor,
+        |rpc_reply| NetworkActorMessage::Command(NetworkActorCommand::SetConnectionLimits(
+            SetConnectionLimitsCommand {
+                max_peers: Some(0),
+                connection_rate_limit_max_connections: Some(2),
+            },
+            rpc_reply
+        ))
+    )
+    .expect("node alive")
+    .expect("set connection limits");
+    peers[1].connect_to_nonblocking(&node).await;
+    let peer_id = peers[1].peer_id.clone();
+    node.expect_event(
+        |event| matches!(event, NetworkServiceEvent::PeerConnected(id, _) if id == &peer_id),
+    )
+    .await;
+    peers[2].connect_to_nonblocking(&node).await;
+    expect_rejected(&mut peers[2], &node.peer_id).await;
+    let response = health(&node).await;
+    assert_eq!(response.peers_count, 2);
+    assert_eq!(response.inbound_peers_count, 2);
+    assert_eq!(response.rejected_connections_count, 2);
+}
@@ -1,5 +1,6 @@
+use ckb_hash::blake2b_256;
 use ckb_jsonrpc_types::JsonBytes;
-use ckb_types::packed::OutPoint;
+use ckb_types::packed::{OutPoint, Script};
 use ckb_types::{core::TransactionView, packed::Byte32};
 use ractor::{Actor, ActorRef};
 use rand::Rng;
@@ -21,15 +22,20 @@ use tokio::{
     sync::{mpsc, OnceCell},
     time::sleep,
 };
+use zeroize::Zeroizing;
 
 use crate::{
     actors::{RootActor, RootActorMessage},
+    ckb::contracts::{get_script_by_contract, Contract},
     ckb::tests::test_utils::{submit_tx, trace_tx, trace_tx_hash, MockChainActor},
-    ckb::CkbChainMessage,
-    fiber::channel::{ChannelActorState, ChannelActorStateStore, ChannelState},
+    ckb::{ChainTip, CkbChainMessage},
+    fiber::channel::{
+        ChannelActorState, ChannelActorStateStore, ChannelLogEntry, ChannelLogEvent, ChannelState,
+        ClosedChannel, PeerFeePolicy, ProcessingChannelError,
+    },
     fiber::graph::NetworkGraphStateStore,
-    fiber::graph::PaymentSession,
-    fiber::graph::{ChannelInfo, NetworkGraph, NodeInfo},
+    fiber::graph::{ChannelInfo, GraphChanges, GraphSnapshot, NetworkGraph, NodeInfo},
+    fiber::graph::{PaymentSession, PaymentSessionStatus},
     fiber::network::NetworkActorStartArguments,
     fiber::network::{NetworkActor, NetworkActorCommand, NetworkActorMessage},
     fiber::network::{NetworkActorStateStore, PersistentNetworkActorState},
@@ -152,6 +158,8 @@ pub struct NetworkNode {
     pub listening_addrs: Vec<MultiAddr>,
     pub network_actor: ActorRef<NetworkActorMessage>,
     pub chain_actor: ActorRef<CkbChainMessage>,
+    pub chain_tip: ChainTip,
+    pub default_shutdown_script: Script,
     pub peer_id: PeerId,
     pub event_emitter: mpsc::Receiver<NetworkServiceEvent>,
 }
@@ -270,12 +278,13 @@ impl NetworkNode {
             .expect("start mock chain actor")
             .0;
 
-        let secp = Secp256k1::new();
-        let secret_key = SecretKey::from_slice(&[0xcd; 32]).expect("32 bytes, within curve order");
-        let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+        let chain_tip = ChainTip::default();
+        let pubkey_hash = blake2b_256(fiber_config.public_key().inner_ref());
+        let default_shutdown_script =
+            get_script_by_contract(Contract::Secp256k1Lock, &pubkey_hash[0..20]);
         let network_graph = Arc::new(TokioRwLock::new(NetworkGraph::new(
             store.clone(),
-            public_key.into(),
+            fiber_config.public_key().into(),
         )));
         let network_actor = Actor::spawn_linked(
             Some(format!("network actor at {:?}", base_dir.as_ref())),
@@ -289,7 +298,8 @@ impl NetworkNode {
                 config: fiber_config.clone(),
                 tracker: new_tokio_task_tracker(),
                 channel_subscribers: Default::default(),
-                default_shutdown_script: Default::default(),
+                default_shutdown_script: default_shutdown_script.clone(),
+                chain_tip: chain_tip.clone(),
             },
             root.get_cell(),
         )
@@ -323,6 +333,8 @@ impl NetworkNode {
             listening_addrs: announced_addrs,
             network_actor,
             chain_actor,
+            chain_tip,
+            default_shutdown_script,
             peer_id,
             event_emitter: event_receiver,
         }
@@ -460,15 +472,30 @@ impl NetworkNode {
     }
 }
 
+// A channel or node saved to the store, the graph snapshot is updated with the changes.
+#[derive(Clone, Debug)]
+enum GraphChange {
+    Channel(OutPoint),
+    Node(Pubkey),
+}
+
 #[derive(Clone, Default)]
 pub struct MemoryStore {
     network_actor_sate_map: Arc<RwLock<HashMap<PeerId, PersistentNetworkActorState>>>,
     channel_actor_state_map: Arc<RwLock<HashMap<Hash256, ChannelActorState>>>,
     channels_map: Arc<RwLock<HashMap<OutPoint, ChannelInfo>>>,
     nodes_map: Arc<RwLock<HashMap<Pubkey, NodeInfo>>>,
+    graph_snapshot: Arc<RwLock<Option<GraphSnapshot>>>,
+    graph_changes: Arc<RwLock<Vec<GraphChange>>>,
     payment_sessions: Arc<RwLock<HashMap<Hash256, PaymentSession>>>,
     invoice_store: Arc<RwLock<HashMap<Hash256, CkbInvoice>>>,
     invoice_hash_to_preimage: Arc<RwLock<HashMap<Hash256, Hash256>>>,
+    invoice_client_tokens: Arc<RwLock<HashMap<String, Hash256>>>,
+    invoice_settled_at: Arc<RwLock<HashMap<Hash256, u64>>>,
+    channel_event_logs: Arc<RwLock<HashMap<Hash256, Vec<ChannelLogEntry>>>>,
+    channel_labels: Arc<RwLock<HashMap<Hash256, String>>>,
+    peer_fee_policies: Arc<RwLock<HashMap<PeerId, PeerFeePolicy>>>,
+    closed_channels: Arc<RwLock<Vec<ClosedChannel>>>,
 }
 
 impl NetworkActorStateStore for MemoryStore {
@@ -503,11 +530,19 @@ impl NetworkGraphStateStore for MemoryStore {
         }
     }
 
-    fn insert_channel(&self, channel: ChannelInfo) {
-        self.channels_map
+    fn insert_channel(&self, channel: ChannelInfo) -> bool {
+        let mut channels = self.channels_map.write().unwrap();
+        if let Some(old) = channels.get(&channel.out_point()) {
+            if channel.is_outdated_by(old) {
+                return false;
+            }
+        }
+        self.graph_changes
             .write()
             .unwrap()
-            .insert(channel.out_point(), channel);
+            .push(GraphChange::Channel(channel.out_point()));
+        channels.insert(channel.out_point(), channel);
+        true
     }
 
     fn get_nodes(&self, node_id: Option<Pubkey>) -> Vec<NodeInfo> {
@@ -541,11 +576,44 @@ impl NetworkGraphStateStore for MemoryStore {
         unimplemented!("currently not used in mock store");
     }
 
-    fn insert_node(&self, node: NodeInfo) {
-        self.nodes_map
+    fn insert_node(&self, node: NodeInfo) -> bool {
+        let mut nodes = self.nodes_map.write().unwrap();
+        if let Some(old) = nodes.get(&node.node_id) {
+            if node.is_outdated_by(old) {
+                return false;
+            }
+        }
+        self.graph_changes
             .write()
             .unwrap()
-            .insert(node.node_id.clone(), node);
+            .push(GraphChange::Node(node.node_id));
+        nodes.insert(node.node_id, node);
+        true
+    }
+
+    fn get_graph_snapshot(&self) -> Option<GraphSnapshot> {
+        self.graph_snapshot.read().unwrap().clone()
+    }
+
+    fn get_graph_changes(&self, from: u64) -> GraphChanges {
+        let graph_changes = self.graph_changes.read().unwrap();
+        let mut changes = GraphChanges {
+            next_index: graph_changes.len() as u64,
+            ..Default::default()
+        };
+        for change in graph_changes.iter().skip(from as usize) {
+            match change {
+                GraphChange::Channel(outpoint) => changes
+                    .channels
+                    .extend(self.get_channels(Some(outpoint.clone()))),
+                GraphChange::Node(node_id) => changes.nodes.extend(self.get_nodes(Some(*node_id))),
+            }
+        }
+        changes
+    }
+
+    fn insert_graph_snapshot(&self, snapshot: GraphSnapshot) {
+        *self.graph_snapshot.write().unwrap() = Some(snapshot);
     }
 
     fn get_payment_session(&self, id: Hash256) -> Option<PaymentSession> {
@@ -558,6 +626,59 @@ impl NetworkGraphStateStore for MemoryStore {
             .unwrap()
             .insert(session.payment_hash(), session);
     }
+
+    fn prune_payment_sessions(&self, before: u128, keep_recent: usize) -> usize {
+        let mut payment_sessions = self.payment_sessions.write().unwrap();
+        let mut sessions: Vec<_> = payment_sessions.values().cloned().collect();
+        sessions.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        let mut pruned = 0;
+        for session in sessions.into_iter().skip(keep_recent) {
+            if session.status.is_final()
+                && !session.has_unresolved_attempt()
+                && session.last_updated_at < before
+            {
+                payment_sessions.remove(&session.payment_hash());
+                pruned += 1;
+            }
+        }
+        pruned
+    }
+
+    fn get_failed_payment_sessions(&self, limit: usize) -> Vec<PaymentSession> {
+        let mut sessions: Vec<_> = self
+            .payment_sessions
+            .read()
+            .unwrap()
+            .values()
+            .filter(|session| session.status == PaymentSessionStatus::Failed)
+            .cloned()
+            .collect();
+        sessions.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        sessions.truncate(limit);
+        sessions
+    }
+
+    fn clear_failed_payment_sessions(&self, before: u128) -> usize {
+        let mut payment_sessions = self.payment_sessions.write().unwrap();
+        let count = payment_sessions.len();
+        payment_sessions.retain(|_, session| {
+            session.status != PaymentSessionStatus::Failed
+                || session.has_unresolved_attempt()
+                || session.created_at >= before
+        });
+        count - payment_sessions.len()
+    }
+}
+
+impl MemoryStore {
+    // Loads a channel state exported by the export_channel_state RPC, to reproduce a reported problem.
+    pub fn import_channel_state(&self, exported: &str) -> ChannelActorState {
+        let state: ChannelActorState =
+            serde_json::from_str(exported).expect("valid exported channel state");
+        self.insert_channel_actor_state(state.clone())
+            .expect("insert channel state");
+        state
+    }
 }
 
 impl ChannelActorStateStore for MemoryStore {
@@ -569,15 +690,150 @@ impl ChannelActorStateStore for MemoryStore {
             .cloned()
     }
 
-    fn insert_channel_actor_state(&self, state: ChannelActorState) {
-        self.channel_actor_state_map
-            .write()
-            .unwrap()
-            .insert(state.id, state);
+    fn insert_channel_actor_state(
+        &self,
+        state: ChannelActorState,
+    ) -> Result<(), ProcessingChannelError> {
+        let mut map = self.channel_actor_state_map.write().unwrap();
+        if let Some(current) = map.get(&state.id) {
+            if !current.is_same_channel(&state) {
+                return Err(ProcessingChannelError::ChannelIdCollision(state.id));
+            }
+        }
+        map.insert(state.id, state);
+        Ok(())
+    }
+
+    fn insert_closed_channel_actor_state(
+        &self,
+        state: ChannelActorState,
+        closed_channel: ClosedChannel,
+    ) -> Result<(), ProcessingChannelError> {
+        self.insert_channel_actor_state(state)?;
+        self.insert_closed_channel(closed_channel);
+        Ok(())
     }
 
     fn delete_channel_actor_state(&self, id: &Hash256) {
         self.channel_actor_state_map.write().unwrap().remove(id);
+        self.channel_labels.write().unwrap().remove(id);
+    }
+
+    fn append_channel_events(
+        &self,
+        id: &Hash256,
+        events: Vec<ChannelLogEvent>,
+        timestamp: u64,
+        max_size: u64,
+    ) {
+        let mut logs = self.channel_event_logs.write().unwrap();
+        let log = logs.entry(*id).or_default();
+        let mut next_index = log.last().map_or(0, |entry| entry.index + 1);
+        for event in events {
+            log.push(ChannelLogEntry {
+                index: next_index,
+                timestamp,
+                event,
+            });
+            next_index += 1;
+        }
+        let excess = log.len().saturating_sub(max_size as usize);
+        log.drain(..excess);
+    }
+
+    fn get_channel_events(
+        &self,
+        id: &Hash256,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Vec<ChannelLogEntry> {
+        self.channel_event_logs
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|log| {
+                log.iter()
+                    .filter(|entry| after.map_or(true, |after| entry.index > after))
+                    .take(limit)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn get_channel_label(&self, id: &Hash256) -> Option<String> {
+        self.channel_labels.read().unwrap().get(id).cloned()
+    }
+
+    fn set_channel_label(&self, id: &Hash256, label: Option<String>) {
+        let mut labels = self.channel_labels.write().unwrap();
+        match label {
+            Some(label) => labels.insert(*id, label),
+            None => labels.remove(id),
+        };
+    }
+
+    fn get_peer_fee_policy(&self, peer_id: &PeerId) -> Option<PeerFeePolicy> {
+        self.peer_fee_policies.read().unwrap().get(peer_id).cloned()
+    }
+
+    fn set_peer_fee_policy(&self, peer_id: &PeerId, policy: Option<PeerFeePolicy>) {
+        let mut policies = self.peer_fee_policies.write().unwrap();
+        match policy {
+            Some(policy) => policies.insert(peer_id.clone(), policy),
+            None => policies.remove(peer_id),
+        };
+    }
+
+    fn insert_closed_channel(&self, closed_channel: ClosedChannel) {
+        let mut closed_channels = self.closed_channels.write().unwrap();
+        closed_channels.push(closed_channel);
+        closed_channels.sort_by(|a, b| {
+            (a.closed_at, a.channel_id.as_ref()).cmp(&(b.closed_at, b.channel_id.as_ref()))
+        });
+    }
+
+    fn get_closed_channels(
+        &self,
+        limit: usize,
+        after: Option<JsonBytes>,
+        closed_after: Option<u64>,
+        closed_before: Option<u64>,
+    ) -> (Vec<ClosedChannel>, JsonBytes) {
+        let cursor_of = |c: &ClosedChannel| {
+            [c.closed_at.to_be_bytes().as_slice(), c.channel_id.as_ref()].concat()
+        };
+        let closed_channels: Vec<_> = self
+            .closed_channels
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|c| match &after {
+                Some(after) => cursor_of(c).as_slice() > after.as_bytes(),
+                None => c.closed_at >= closed_after.unwrap_or_default(),
+            })
+            .filter(|c| closed_before.map_or(true, |before| c.closed_at < before))
+            .take(limit)
+            .cloned()
+            .collect();
+        let last_cursor = closed_channels.last().map(cursor_of).unwrap_or_default();
+        (closed_channels, JsonBytes::from_vec(last_cursor))
+    }
+
+    fn prune_closed_channels(&self, before: u64) -> usize {
+        let mut closed_channels = self.closed_channels.write().unwrap();
+        let pruned: Vec<_> = closed_channels
+            .iter()
+            .filter(|c| c.closed_at < before)
+            .map(|c| c.channel_id)
+            .collect();
+        closed_channels.retain(|c| c.closed_at >= before);
+        drop(closed_channels);
+        for channel_id in &pruned {
+            self.delete_channel_actor_state(channel_id);
+            self.channel_event_logs.write().unwrap().remove(channel_id);
+        }
+        pruned.len()
     }
 
     fn get_channel_ids_by_peer(&self, peer_id: &PeerId) -> Vec<Hash256> {
@@ -633,6 +889,7 @@ impl InvoiceStore for MemoryStore {
     ) -> Result<(), InvoiceError> {
         let id = invoice.payment_hash();
         if let Some(preimage) = preimage {
+            invoice.check_payment_preimage(&preimage)?;
             self.invoice_hash_to_preimage
                 .write()
                 .unwrap()
@@ -642,12 +899,78 @@ impl InvoiceStore for MemoryStore {
         Ok(())
     }
 
-    fn get_invoice_preimage(&self, hash: &Hash256) -> Option<Hash256> {
+    fn get_invoice_preimage(&self, hash: &Hash256) -> Option<Zeroizing<Hash256>> {
         self.invoice_hash_to_preimage
             .read()
             .unwrap()
             .get(hash)
             .cloned()
+            .map(Zeroizing::new)
+    }
+
+    fn remove_invoice_preimage(&self, hash: &Hash256) {
+        self.invoice_hash_to_preimage.write().unwrap().remove(hash);
+    }
+
+    fn insert_invoice_with_client_token(
+        &self,
+        invoice: CkbInvoice,
+        preimage: Option<Hash256>,
+        client_token: &str,
+    ) -> Result<(), InvoiceError> {
+        let id = *invoice.payment_hash();
+        self.insert_invoice(invoice, preimage)?;
+        self.invoice_client_tokens
+            .write()
+            .unwrap()
+            .insert(client_token.to_string(), id);
+        Ok(())
+    }
+
+    fn insert_invoice_settled_at(&self, id: &Hash256, settled_at: u64) {
+        self.invoice_settled_at
+            .write()
+            .unwrap()
+            .insert(*id, settled_at);
+    }
+
+    fn get_invoice_settled_at(&self, id: &Hash256) -> Option<u64> {
+        self.invoice_settled_at.read().unwrap().get(id).copied()
+    }
+
+    fn get_invoice_by_client_token(&self, client_token: &str) -> Option<CkbInvoice> {
+        let id = *self
+            .invoice_client_tokens
+            .read()
+            .unwrap()
+            .get(client_token)?;
+        self.get_invoice(&id)
+    }
+
+    fn get_invoices_by_udt_type(
+        &self,
+        udt_type_script: Option<&Script>,
+        limit: usize,
+        after: Option<Hash256>,
+    ) -> Vec<CkbInvoice> {
+        let mut invoices: Vec<_> = self
+            .invoice_store
+            .read()
+            .unwrap()
+            .values()
+            .filter(|invoice| invoice.udt_type_script() == udt_type_script)
+            .cloned()
+            .collect();
+        invoices.sort_by(|a, b| a.payment_hash().as_ref().cmp(b.payment_hash().as_ref()));
+        invoices
+            .into_iter()
+            .filter(|invoice| {
+                after.map_or(true, |after| {
+                    invoice.payment_hash().as_ref() > after.as_ref()
+                })
+            })
+            .take(limit)
+            .collect()
     }
 }
 
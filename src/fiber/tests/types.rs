@@ -1,11 +1,13 @@
 use super::test_utils::generate_seckey;
 use crate::fiber::{
+    config::AnnouncedNodeName,
     gen::fiber as molecule_fiber,
     hash_algorithm::HashAlgorithm,
     tests::test_utils::generate_pubkey,
     types::{
-        secp256k1_instance, AddTlc, PaymentHopData, PeeledOnionPacket, Privkey, Pubkey, TlcErr,
-        TlcErrPacket, TlcErrorCode,
+        get_onion_payload_size, secp256k1_instance, AddTlc, HopData, NodeAnnouncement,
+        PaymentHopData, PeeledOnionPacket, Privkey, Pubkey, TlcErr, TlcErrPacket, TlcErrorCode,
+        TrampolineHopData, NODE_FEATURE_KEYSEND,
     },
 };
 use ckb_types::packed::OutPointBuilder;
@@ -26,6 +28,31 @@ fn test_serde_public_key() {
     assert_eq!(pubkey, public_key)
 }
 
+#[test]
+fn test_node_announcement_features() {
+    let private_key: Privkey = generate_seckey().into();
+    let node_announcement = NodeAnnouncement::new(
+        AnnouncedNodeName::from_str("node").expect("valid name"),
+        vec![],
+        &private_key,
+        1,
+        0,
+    );
+    assert!(node_announcement.supports_feature(NODE_FEATURE_KEYSEND));
+
+    // The features are covered by the signature and kept by the molecule serialization.
+    let node_announcement_mol: molecule_fiber::NodeAnnouncement = node_announcement.clone().into();
+    let deserialized = NodeAnnouncement::try_from(node_announcement_mol).unwrap();
+    assert_eq!(deserialized, node_announcement);
+    let signature = deserialized.signature.clone().unwrap();
+    assert!(signature.verify(&deserialized.node_id, &deserialized.message_to_sign()));
+
+    let mut tampered = node_announcement;
+    tampered.features = 0;
+    assert!(!tampered.supports_feature(NODE_FEATURE_KEYSEND));
+    assert!(!signature.verify(&tampered.node_id, &tampered.message_to_sign()));
+}
+
 #[test]
 fn test_add_tlc_serialization() {
     let add_tlc = AddTlc {
@@ -58,6 +85,7 @@ fn test_peeled_onion_packet() {
             channel_outpoint: Some(OutPointBuilder::default().build().into()),
             tlc_hash_algorithm: HashAlgorithm::Sha256,
             preimage: None,
+            trampoline: None,
         },
         PaymentHopData {
             payment_hash,
@@ -67,6 +95,7 @@ fn test_peeled_onion_packet() {
             channel_outpoint: Some(OutPointBuilder::default().build().into()),
             tlc_hash_algorithm: HashAlgorithm::Sha256,
             preimage: None,
+            trampoline: None,
         },
         PaymentHopData {
             payment_hash,
@@ -76,6 +105,11 @@ fn test_peeled_onion_packet() {
             channel_outpoint: Some(OutPointBuilder::default().build().into()),
             tlc_hash_algorithm: HashAlgorithm::Sha256,
             preimage: None,
+            trampoline: Some(TrampolineHopData {
+                target_pubkey: generate_pubkey().into(),
+                amount: 7,
+                preimage: None,
+            }),
         },
     ];
     let packet = PeeledOnionPacket::create(generate_seckey().into(), hops_infos.clone(), &secp)
@@ -98,6 +132,34 @@ fn test_peeled_onion_packet() {
     assert!(packet.is_last());
 }
 
+#[test]
+fn test_onion_payload_size() {
+    let secp = Secp256k1::new();
+    let hops_infos = |hops: usize| {
+        (0..=hops)
+            .map(|i| PaymentHopData {
+                payment_hash: [1; 32].into(),
+                amount: 1000,
+                expiry: 100,
+                next_hop: (i < hops).then(generate_pubkey).map(Into::into),
+                channel_outpoint: Some(OutPointBuilder::default().build().into()),
+                tlc_hash_algorithm: HashAlgorithm::Sha256,
+                preimage: None,
+                trampoline: None,
+            })
+            .collect::<Vec<_>>()
+    };
+    // The packet can be created as long as the payload fits in the packet data.
+    let mut hops = 1;
+    while get_onion_payload_size(&hops_infos(hops + 1)) <= PaymentHopData::PACKET_DATA_LEN {
+        hops += 1;
+    }
+    assert!(PeeledOnionPacket::create(generate_seckey().into(), hops_infos(hops), &secp).is_ok());
+    assert!(
+        PeeledOnionPacket::create(generate_seckey().into(), hops_infos(hops + 1), &secp).is_err()
+    );
+}
+
 #[test]
 fn test_tlc_fail_error() {
     let tlc_fail_detail = TlcErr::new(TlcErrorCode::InvalidOnionVersion);
@@ -34,11 +34,13 @@ use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::marker::PhantomData;
 use std::str::FromStr;
+use std::time::Duration;
 use strum::{AsRefStr, EnumString};
 use tentacle::multiaddr::MultiAddr;
 use tentacle::secio::PeerId;
 use thiserror::Error;
 use tracing::{debug, trace};
+use zeroize::Zeroize;
 
 pub fn secp256k1_instance() -> &'static Secp256k1<All> {
     static INSTANCE: OnceCell<Secp256k1<All>> = OnceCell::new();
@@ -238,6 +240,12 @@ impl AsRef<[u8]> for Hash256 {
     }
 }
 
+impl Zeroize for Hash256 {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
 impl From<&Hash256> for MByte32 {
     fn from(hash: &Hash256) -> Self {
         MByte32::new_builder()
@@ -667,6 +675,10 @@ impl OpenChannel {
     pub fn is_public(&self) -> bool {
         self.channel_flags.contains(ChannelFlags::PUBLIC)
     }
+
+    pub fn is_zero_conf(&self) -> bool {
+        self.channel_flags.contains(ChannelFlags::ZERO_CONF)
+    }
 }
 
 impl From<OpenChannel> for molecule_fiber::OpenChannel {
@@ -752,6 +764,14 @@ pub struct AcceptChannel {
     pub second_per_commitment_point: Pubkey,
     pub channel_announcement_nonce: Option<PubNonce>,
     pub next_local_nonce: PubNonce,
+    // The flags of the OpenChannel message agreed by the acceptor, only ZERO_CONF is used now.
+    pub channel_flags: ChannelFlags,
+}
+
+impl AcceptChannel {
+    pub fn is_zero_conf(&self) -> bool {
+        self.channel_flags.contains(ChannelFlags::ZERO_CONF)
+    }
 }
 
 impl From<AcceptChannel> for molecule_fiber::AcceptChannel {
@@ -778,6 +798,7 @@ impl From<AcceptChannel> for molecule_fiber::AcceptChannel {
                     .build(),
             )
             .next_local_nonce((&accept_channel.next_local_nonce).into())
+            .channel_flags((accept_channel.channel_flags.bits() as u64).pack())
             .build()
     }
 }
@@ -808,6 +829,13 @@ impl TryFrom<molecule_fiber::AcceptChannel> for AcceptChannel {
                 .next_local_nonce()
                 .try_into()
                 .map_err(|err| Error::Musig2(format!("{err}")))?,
+            channel_flags: {
+                let channel_flags: u64 = accept_channel.channel_flags().unpack();
+                u8::try_from(channel_flags)
+                    .ok()
+                    .and_then(ChannelFlags::from_bits)
+                    .ok_or(anyhow!("Invalid channel flags: {}", channel_flags))?
+            },
         })
     }
 }
@@ -1098,6 +1126,38 @@ impl TryFrom<molecule_fiber::Shutdown> for Shutdown {
     }
 }
 
+/// Sent by the receiver of a `Shutdown` whose fee rate is too low, proposing the fee rate
+/// it is willing to accept. The shutdown initiator either takes it or keeps the channel
+/// in `ShuttingDown` until the user accepts it or force closes.
+#[derive(Debug, Clone)]
+pub struct ShutdownFeeCounter {
+    pub channel_id: Hash256,
+    pub close_script: Script,
+    pub fee_rate: FeeRate,
+}
+
+impl From<ShutdownFeeCounter> for molecule_fiber::ShutdownFeeCounter {
+    fn from(counter: ShutdownFeeCounter) -> Self {
+        molecule_fiber::ShutdownFeeCounter::new_builder()
+            .channel_id(counter.channel_id.into())
+            .close_script(counter.close_script)
+            .fee_rate(counter.fee_rate.as_u64().pack())
+            .build()
+    }
+}
+
+impl TryFrom<molecule_fiber::ShutdownFeeCounter> for ShutdownFeeCounter {
+    type Error = Error;
+
+    fn try_from(counter: molecule_fiber::ShutdownFeeCounter) -> Result<Self, Self::Error> {
+        Ok(ShutdownFeeCounter {
+            channel_id: counter.channel_id().into(),
+            close_script: counter.close_script(),
+            fee_rate: FeeRate::from_u64(counter.fee_rate().unpack()),
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ClosingSigned {
     pub channel_id: Hash256,
@@ -1380,7 +1440,7 @@ pub enum TlcErrorCode {
     PermanentNodeFailure = PERM | NODE | 2,
     // unused right now
     RequiredNodeFeatureMissing = PERM | NODE | 3,
-    // unused right now, maybe need to add onion version in future?
+    // the onion packet version is not negotiated in the channel
     InvalidOnionVersion = BADONION | PERM | 4,
     InvalidOnionHmac = BADONION | PERM | 5,
     InvalidOnionKey = BADONION | PERM | 6,
@@ -1579,6 +1639,52 @@ impl TryFrom<molecule_fiber::AnnouncementSignatures> for AnnouncementSignatures
     }
 }
 
+/// The node accepts keysend payments, whose preimage is carried by the onion packet.
+pub const NODE_FEATURE_KEYSEND: u64 = 1 << 0;
+/// The node accepts tlcs whose payment hash is computed with `HashAlgorithm::CkbHash`.
+pub const NODE_FEATURE_CKB_HASH: u64 = 1 << 1;
+/// The node accepts tlcs whose payment hash is computed with `HashAlgorithm::Sha256`.
+pub const NODE_FEATURE_SHA256: u64 = 1 << 2;
+/// The node understands version 0 of the onion packet. Bits 9 to 15 are reserved
+/// for the following onion versions, see `onion_version_feature`.
+pub const NODE_FEATURE_ONION_V0: u64 = 1 << 8;
+
+/// The features of a peer which has not announced any, i.e. a node predating feature bits.
+/// Such a node supports everything this node supported before the bits were introduced.
+pub const LEGACY_NODE_FEATURES: u64 =
+    NODE_FEATURE_KEYSEND | NODE_FEATURE_CKB_HASH | NODE_FEATURE_SHA256 | NODE_FEATURE_ONION_V0;
+
+/// All the feature bits this node may announce.
+pub const SUPPORTED_NODE_FEATURES: u64 = LEGACY_NODE_FEATURES;
+
+pub fn hash_algorithm_feature(hash_algorithm: HashAlgorithm) -> u64 {
+    match hash_algorithm {
+        HashAlgorithm::CkbHash => NODE_FEATURE_CKB_HASH,
+        HashAlgorithm::Sha256 => NODE_FEATURE_SHA256,
+    }
+}
+
+pub fn onion_version_feature(version: u8) -> u64 {
+    if version < 8 {
+        NODE_FEATURE_ONION_V0 << version
+    } else {
+        0
+    }
+}
+
+/// The feature bits announced by a node with the given hash algorithms and onion versions.
+pub fn node_features(hash_algorithms: &[HashAlgorithm], onion_versions: &[u8]) -> u64 {
+    hash_algorithms
+        .iter()
+        .map(|algorithm| hash_algorithm_feature(*algorithm))
+        .chain(
+            onion_versions
+                .iter()
+                .map(|version| onion_version_feature(*version)),
+        )
+        .fold(NODE_FEATURE_KEYSEND, |features, feature| features | feature)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct NodeAnnouncement {
     // Signature to this message, may be empty the message is not signed yet.
@@ -1613,7 +1719,7 @@ impl NodeAnnouncement {
     ) -> Self {
         Self {
             signature: None,
-            features: Default::default(),
+            features: SUPPORTED_NODE_FEATURES,
             version,
             node_id,
             alias,
@@ -1642,6 +1748,10 @@ impl NodeAnnouncement {
         unsigned
     }
 
+    pub fn supports_feature(&self, feature: u64) -> bool {
+        self.features & feature == feature
+    }
+
     pub fn message_to_sign(&self) -> [u8; 32] {
         let unsigned_announcement = NodeAnnouncement {
             signature: None,
@@ -2135,6 +2245,10 @@ impl FiberMessage {
         FiberMessage::ChannelNormalOperation(FiberChannelMessage::Shutdown(shutdown))
     }
 
+    pub fn shutdown_fee_counter(counter: ShutdownFeeCounter) -> Self {
+        FiberMessage::ChannelNormalOperation(FiberChannelMessage::ShutdownFeeCounter(counter))
+    }
+
     pub fn closing_signed(closing_signed: ClosingSigned) -> Self {
         FiberMessage::ChannelNormalOperation(FiberChannelMessage::ClosingSigned(closing_signed))
     }
@@ -2190,6 +2304,7 @@ pub enum FiberChannelMessage {
     TxInitRBF(TxInitRBF),
     TxAckRBF(TxAckRBF),
     Shutdown(Shutdown),
+    ShutdownFeeCounter(ShutdownFeeCounter),
     ClosingSigned(ClosingSigned),
     AddTlc(AddTlc),
     RevokeAndAck(RevokeAndAck),
@@ -2213,6 +2328,7 @@ impl FiberChannelMessage {
             FiberChannelMessage::TxInitRBF(tx_init_rbf) => tx_init_rbf.channel_id,
             FiberChannelMessage::TxAckRBF(tx_ack_rbf) => tx_ack_rbf.channel_id,
             FiberChannelMessage::Shutdown(shutdown) => shutdown.channel_id,
+            FiberChannelMessage::ShutdownFeeCounter(counter) => counter.channel_id,
             FiberChannelMessage::ClosingSigned(closing_signed) => closing_signed.channel_id,
             FiberChannelMessage::AddTlc(add_tlc) => add_tlc.channel_id,
             FiberChannelMessage::RevokeAndAck(revoke_and_ack) => revoke_and_ack.channel_id,
@@ -2304,6 +2420,22 @@ impl FiberBroadcastMessage {
             }
         }
     }
+
+    /// Whether the timestamp of the message is ahead of `now` by more than `max_skew`.
+    /// The version of a node announcement is in milliseconds, while the version of a
+    /// channel update is in seconds. Channel announcements carry no timestamp.
+    pub fn is_too_far_in_future(&self, now: Duration, max_skew: Duration) -> bool {
+        let limit = now.saturating_add(max_skew);
+        match self {
+            FiberBroadcastMessage::NodeAnnouncement(node_announcement) => {
+                u128::from(node_announcement.version) > limit.as_millis()
+            }
+            FiberBroadcastMessage::ChannelAnnouncement(_) => false,
+            FiberBroadcastMessage::ChannelUpdate(channel_update) => {
+                channel_update.version > limit.as_secs()
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -2794,6 +2926,9 @@ impl From<FiberMessage> for molecule_fiber::FiberMessageUnion {
                 FiberChannelMessage::Shutdown(shutdown) => {
                     molecule_fiber::FiberMessageUnion::Shutdown(shutdown.into())
                 }
+                FiberChannelMessage::ShutdownFeeCounter(counter) => {
+                    molecule_fiber::FiberMessageUnion::ShutdownFeeCounter(counter.into())
+                }
                 FiberChannelMessage::ClosingSigned(closing_signed) => {
                     molecule_fiber::FiberMessageUnion::ClosingSigned(closing_signed.into())
                 }
@@ -2926,6 +3061,11 @@ impl TryFrom<molecule_fiber::FiberMessageUnion> for FiberMessage {
                     shutdown.try_into()?,
                 ))
             }
+            molecule_fiber::FiberMessageUnion::ShutdownFeeCounter(counter) => {
+                FiberMessage::ChannelNormalOperation(FiberChannelMessage::ShutdownFeeCounter(
+                    counter.try_into()?,
+                ))
+            }
             molecule_fiber::FiberMessageUnion::ClosingSigned(closing_signed) => {
                 FiberMessage::ChannelNormalOperation(FiberChannelMessage::ClosingSigned(
                     closing_signed.try_into()?,
@@ -3069,6 +3209,20 @@ pub struct PaymentHopData {
     pub next_hop: Option<Pubkey>,
     #[serde_as(as = "Option<EntityHex>")]
     pub channel_outpoint: Option<OutPoint>,
+    // this is only specified in the last hop of a trampoline payment, which is the trampoline node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trampoline: Option<TrampolineHopData>,
+}
+
+/// The final target of a trampoline payment, the trampoline node completes the route to the target
+/// and keeps the amount it received minus `amount` and the fee of the route as its fee.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TrampolineHopData {
+    pub target_pubkey: Pubkey,
+    // the amount to be received by the target
+    pub amount: u128,
+    // this is only specified in the keysend mode
+    pub preimage: Option<Hash256>,
 }
 
 /// Trait for hop data
@@ -3116,6 +3270,14 @@ pub struct PeeledOnionPacket<T> {
 }
 
 pub type PaymentOnionPacket = OnionPacket<PaymentHopData>;
+
+/// The versions of the onion packet format supported by this node, the latest version first.
+pub const SUPPORTED_ONION_VERSIONS: [u8; 1] = [0];
+
+/// The version of an onion packet is the first byte of the serialized packet.
+pub fn get_onion_packet_version(onion_packet: &[u8]) -> Option<u8> {
+    onion_packet.first().copied()
+}
 pub type PeeledPaymentOnionPacket = PeeledOnionPacket<PaymentHopData>;
 
 impl<T> OnionPacket<T> {
@@ -3250,6 +3412,20 @@ impl<T: HopData> PeeledOnionPacket<T> {
 
 const HOP_DATA_HEAD_LEN: usize = std::mem::size_of::<u64>();
 
+/// The length of the hmac appended to the hop data of each hop in the onion packet.
+const HOP_DATA_HMAC_LEN: usize = 32;
+
+/// The size of the onion packet data taken by the hops, i.e. the packed hop data and the hmac
+/// of every hop except the origin node. It can't exceed `T::PACKET_DATA_LEN`.
+/// - `hops_info`: the same as in `PeeledOnionPacket::create`.
+pub fn get_onion_payload_size<T: HopData>(hops_infos: &[T]) -> usize {
+    hops_infos
+        .iter()
+        .skip(1)
+        .map(|hop_data| HOP_DATA_HEAD_LEN + hop_data.serialize().len() + HOP_DATA_HMAC_LEN)
+        .sum()
+}
+
 /// TODO: when JSON is replaced, this function may return `data` directly.
 fn pack_hop_data<T: HopData>(hop_data: &T) -> Vec<u8> {
     let mut serialized = hop_data.serialize();
@@ -1,5 +1,9 @@
+use crate::fiber::types::Hash256;
+use jsonrpsee::types::ErrorObjectOwned;
+use serde_json::json;
 use std::fmt::Display;
 use std::num::ParseIntError;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -54,5 +58,52 @@ pub enum InvoiceError {
     #[error("Hex decode error: {0}")]
     HexDecodeError(#[from] hex::FromHexError),
     #[error("Duplicated inovice found: {0}")]
-    DuplicatedInvoice(String),
+    DuplicatedInvoice(Hash256),
+    #[error("Invalid amount range: {0}")]
+    InvalidAmountRange(String),
+    #[error("Expiry time {0:?} exceeds the maximal expiry time {1:?}")]
+    ExpiryTooLong(Duration, Duration),
+    /// The preimage doesn't hash to the payment hash under the hash algorithm of the invoice
+    #[error("Payment preimage doesn't match the payment hash: {0}")]
+    PreimageMismatch(Hash256),
+}
+
+impl InvoiceError {
+    /// The rpc error code of the error, which is stable so that clients can tell the errors apart.
+    pub fn rpc_error_code(&self) -> i32 {
+        match self {
+            InvoiceError::Bech32Error(_) => 1001,
+            InvoiceError::MoleculeError(_) => 1002,
+            InvoiceError::ParseAmountError(_) => 1003,
+            InvoiceError::UnknownCurrency(_) => 1004,
+            InvoiceError::UnknownSiPrefix(_) => 1005,
+            InvoiceError::MalformedHRP(_) => 1006,
+            InvoiceError::TooShortDataPart => 1007,
+            InvoiceError::UnexpectedEndOfTaggedFields => 1008,
+            InvoiceError::IntegerOverflowError => 1009,
+            InvoiceError::InvalidRecoveryId => 1010,
+            InvoiceError::InvalidSliceLength(_) => 1011,
+            InvoiceError::InvalidSignature => 1012,
+            InvoiceError::DuplicatedAttributeKey(_) => 1013,
+            InvoiceError::BothPaymenthashAndPreimage => 1014,
+            InvoiceError::SignError => 1015,
+            InvoiceError::HexDecodeError(_) => 1016,
+            InvoiceError::DuplicatedInvoice(_) => 1017,
+            InvoiceError::InvalidAmountRange(_) => 1018,
+            InvoiceError::ExpiryTooLong(_, _) => 1019,
+            InvoiceError::PreimageMismatch(_) => 1020,
+        }
+    }
+}
+
+impl From<InvoiceError> for ErrorObjectOwned {
+    fn from(val: InvoiceError) -> Self {
+        let data = match &val {
+            InvoiceError::DuplicatedInvoice(payment_hash) => {
+                Some(json!({ "payment_hash": payment_hash }))
+            }
+            _ => None,
+        };
+        ErrorObjectOwned::owned(val.rpc_error_code(), val.to_string(), data)
+    }
 }
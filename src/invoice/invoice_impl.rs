@@ -26,6 +26,10 @@ use std::{cmp::Ordering, str::FromStr};
 
 pub(crate) const SIGNATURE_U5_SIZE: usize = 104;
 
+/// The maximal routing fees in percentage of the amount which the receiver absorbs
+/// for an invoice paid by the receiver, unless a `min_amount` is specified.
+pub const DEFAULT_RECEIVER_MAX_FEE_PERCENT: u128 = 5;
+
 /// The currency of the invoice, can also used to represent the CKB network chain.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Currency {
@@ -34,6 +38,16 @@ pub enum Currency {
     Fibd,
 }
 
+/// Who pays the routing fees of a payment.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum FeesPaidBy {
+    /// The fees are paid on top of the amount by the sender.
+    #[default]
+    Sender = 0,
+    /// The amount is gross, and the receiver gets the amount net of the fees.
+    Receiver = 1,
+}
+
 impl TryFrom<u8> for Currency {
     type Error = InvoiceError;
 
@@ -86,6 +100,9 @@ pub enum Attribute {
     PayeePublicKey(PublicKey),
     HashAlgorithm(HashAlgorithm),
     Feature(u64),
+    MinAmount(#[serde_as(as = "U128Hex")] u128),
+    MaxAmount(#[serde_as(as = "U128Hex")] u128),
+    FeesPaidBy(FeesPaidBy),
 }
 
 #[serde_as]
@@ -208,10 +225,22 @@ impl CkbInvoice {
         let hash = Message::from_digest_slice(&self.hash()[..])
             .expect("Hash is 32 bytes long, same as MESSAGE_SIZE");
 
-        let res = secp256k1::Secp256k1::new()
-            .recover_ecdsa(&hash, &self.signature.as_ref().unwrap().0)
-            .unwrap();
-        Ok(res)
+        secp256k1::Secp256k1::new().recover_ecdsa(&hash, &self.signature.as_ref().unwrap().0)
+    }
+
+    /// Recovers the payee public key from the signature and checks whether it matches the
+    /// included payee public key. Returns the recovered public key and the match result, which is
+    /// None if the payee public key is not included, or returns None if the invoice is not signed.
+    pub fn verify_signature(&self) -> Result<Option<(PublicKey, Option<bool>)>, InvoiceError> {
+        if self.signature.is_none() {
+            return Ok(None);
+        }
+        let recovered = self.recover_payee_pub_key().map_err(|e| match e {
+            secp256k1::Error::InvalidRecoveryId => InvoiceError::InvalidRecoveryId,
+            _ => InvoiceError::InvalidSignature,
+        })?;
+        let matched = self.payee_pub_key().map(|included| included == &recovered);
+        Ok(Some((recovered, matched)))
     }
 
     pub fn is_signed(&self) -> bool {
@@ -222,6 +251,17 @@ impl CkbInvoice {
         &self.data.payment_hash
     }
 
+    /// Checks that the preimage hashes to the payment hash under the hash algorithm of the invoice,
+    /// otherwise the payments of the invoice can't be settled with the preimage.
+    pub fn check_payment_preimage(&self, preimage: &Hash256) -> Result<(), InvoiceError> {
+        let algorithm = self.hash_algorithm().copied().unwrap_or_default();
+        let hash: Hash256 = algorithm.hash(preimage.as_ref()).into();
+        if &hash != self.payment_hash() {
+            return Err(InvoiceError::PreimageMismatch(*self.payment_hash()));
+        }
+        Ok(())
+    }
+
     pub fn is_expired(&self) -> bool {
         self.expiry_time().map_or(false, |expiry| {
             self.data.timestamp + expiry.as_millis()
@@ -276,6 +316,38 @@ impl CkbInvoice {
     );
     attr_getter!(fallback_address, FallbackAddr, String);
     attr_getter!(hash_algorithm, HashAlgorithm, HashAlgorithm);
+    attr_getter!(min_amount, MinAmount, u128);
+    attr_getter!(max_amount, MaxAmount, u128);
+    attr_getter!(fees_paid_by, FeesPaidBy, FeesPaidBy);
+
+    /// The maximal routing fees deducted from the amount of an invoice paid by the receiver,
+    /// i.e. the amount less the `min_amount`, or `DEFAULT_RECEIVER_MAX_FEE_PERCENT` of the amount.
+    pub fn receiver_max_fee(&self) -> Option<u128> {
+        if self.fees_paid_by() != Some(&FeesPaidBy::Receiver) {
+            return None;
+        }
+        let amount = self.amount?;
+        Some(match self.min_amount() {
+            Some(min_amount) => amount.saturating_sub(*min_amount),
+            None => amount * DEFAULT_RECEIVER_MAX_FEE_PERCENT / 100,
+        })
+    }
+
+    /// Check whether a payment of `amount` is acceptable for the invoice. An invoice with
+    /// an amount range accepts any amount within the range (both bounds are inclusive),
+    /// an invoice paid by the receiver accepts the amount net of the fees up to
+    /// `receiver_max_fee`, otherwise the amount must match the invoice amount if it's specified.
+    pub fn is_amount_acceptable(&self, amount: u128) -> bool {
+        if let (Some(expected), Some(max_fee)) = (self.amount, self.receiver_max_fee()) {
+            return amount <= expected && amount + max_fee >= expected;
+        }
+        let (min_amount, max_amount) = (self.min_amount(), self.max_amount());
+        if min_amount.is_none() && max_amount.is_none() {
+            return self.amount.map_or(true, |expected| expected == amount);
+        }
+        min_amount.map_or(true, |min| amount >= *min)
+            && max_amount.map_or(true, |max| amount <= *max)
+    }
 }
 
 /// Recoverable signature
@@ -379,6 +451,16 @@ impl FromStr for CkbInvoice {
     type Err = InvoiceError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invoice = CkbInvoice::from_str_unchecked(s)?;
+        invoice.check_signature()?;
+        Ok(invoice)
+    }
+}
+
+impl CkbInvoice {
+    /// Parses the invoice string without checking the signature, so that an invoice with an
+    /// invalid signature can still be inspected.
+    pub(crate) fn from_str_unchecked(s: &str) -> Result<Self, InvoiceError> {
         let (hrp, data, var) = bech32::decode(s).map_err(InvoiceError::Bech32Error)?;
 
         if var == bech32::Variant::Bech32 {
@@ -408,14 +490,12 @@ impl FromStr for CkbInvoice {
             None
         };
 
-        let invoice = CkbInvoice {
+        Ok(CkbInvoice {
             currency,
             amount,
             signature,
             data: invoice_data.try_into().unwrap(),
-        };
-        invoice.check_signature()?;
-        Ok(invoice)
+        })
     }
 }
 
@@ -463,6 +543,17 @@ impl From<Attribute> for InvoiceAttr {
                     .value(Byte::new(hash_algorithm as u8))
                     .build(),
             ),
+            Attribute::MinAmount(value) => {
+                InvoiceAttrUnion::MinAmount(MinAmount::new_builder().value(value.pack()).build())
+            }
+            Attribute::MaxAmount(value) => {
+                InvoiceAttrUnion::MaxAmount(MaxAmount::new_builder().value(value.pack()).build())
+            }
+            Attribute::FeesPaidBy(fees_paid_by) => InvoiceAttrUnion::FeesPaidBy(
+                gen_invoice::FeesPaidBy::new_builder()
+                    .value(Byte::new(fees_paid_by as u8))
+                    .build(),
+            ),
         };
         InvoiceAttr::new_builder().set(a).build()
     }
@@ -504,6 +595,16 @@ impl From<InvoiceAttr> for Attribute {
                 let hash_algorithm = value.try_into().unwrap_or_default();
                 Attribute::HashAlgorithm(hash_algorithm)
             }
+            InvoiceAttrUnion::MinAmount(x) => Attribute::MinAmount(x.value().unpack()),
+            InvoiceAttrUnion::MaxAmount(x) => Attribute::MaxAmount(x.value().unpack()),
+            InvoiceAttrUnion::FeesPaidBy(x) => {
+                // Consider unknown value as the default one.
+                let fees_paid_by = match u8::from(x.value()) {
+                    1 => FeesPaidBy::Receiver,
+                    _ => FeesPaidBy::default(),
+                };
+                Attribute::FeesPaidBy(fees_paid_by)
+            }
         }
     }
 }
@@ -513,6 +614,7 @@ pub struct InvoiceBuilder {
     amount: Option<u128>,
     payment_hash: Option<Hash256>,
     payment_preimage: Option<Hash256>,
+    max_expiry_time: Option<Duration>,
     attrs: Vec<Attribute>,
 }
 
@@ -537,6 +639,7 @@ impl InvoiceBuilder {
             amount: None,
             payment_hash: None,
             payment_preimage: None,
+            max_expiry_time: None,
             attrs: Vec::new(),
         }
     }
@@ -566,6 +669,12 @@ impl InvoiceBuilder {
         self
     }
 
+    /// The invoice fails to build if its expiry time exceeds `max_expiry_time`.
+    pub fn max_expiry_time(mut self, max_expiry_time: Duration) -> Self {
+        self.max_expiry_time = Some(max_expiry_time);
+        self
+    }
+
     pub fn udt_type_script(self, script: Script) -> Self {
         self.add_attr(Attribute::UdtScript(CkbScript(script)))
     }
@@ -574,11 +683,17 @@ impl InvoiceBuilder {
         self.add_attr(Attribute::HashAlgorithm(algorithm))
     }
 
+    pub fn fees_paid_by(self, fees_paid_by: FeesPaidBy) -> Self {
+        self.add_attr(Attribute::FeesPaidBy(fees_paid_by))
+    }
+
     attr_setter!(description, Description, String);
     attr_setter!(payee_pub_key, PayeePublicKey, PublicKey);
     attr_setter!(expiry_time, ExpiryTime, Duration);
     attr_setter!(fallback_address, FallbackAddr, String);
     attr_setter!(final_cltv, FinalHtlcMinimumCltvExpiry, u64);
+    attr_setter!(min_amount, MinAmount, u128);
+    attr_setter!(max_amount, MaxAmount, u128);
 
     pub fn build(self) -> Result<CkbInvoice, InvoiceError> {
         let preimage = self.payment_preimage;
@@ -605,6 +720,8 @@ impl InvoiceBuilder {
         };
 
         self.check_duplicated_attrs()?;
+        self.check_amount_range()?;
+        self.check_expiry_time()?;
         let timestamp = std::time::UNIX_EPOCH.elapsed().unwrap().as_millis();
         Ok(CkbInvoice {
             currency: self.currency,
@@ -638,6 +755,73 @@ impl InvoiceBuilder {
         }
         Ok(())
     }
+
+    fn check_expiry_time(&self) -> Result<(), InvoiceError> {
+        let Some(max_expiry_time) = self.max_expiry_time else {
+            return Ok(());
+        };
+        for attr in &self.attrs {
+            if let Attribute::ExpiryTime(expiry_time) = attr {
+                if *expiry_time > max_expiry_time {
+                    return Err(InvoiceError::ExpiryTooLong(*expiry_time, max_expiry_time));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_amount_range(&self) -> Result<(), InvoiceError> {
+        let (mut min_amount, mut max_amount, mut fees_paid_by) = (None, None, None);
+        for attr in &self.attrs {
+            match attr {
+                Attribute::MinAmount(value) => min_amount = Some(*value),
+                Attribute::MaxAmount(value) => max_amount = Some(*value),
+                Attribute::FeesPaidBy(value) => fees_paid_by = Some(*value),
+                _ => {}
+            }
+        }
+        // an invoice paid by the receiver has a gross amount, and an optional `min_amount`
+        // as the minimal amount net of the fees
+        if fees_paid_by == Some(FeesPaidBy::Receiver) {
+            let Some(amount) = self.amount else {
+                return Err(InvoiceError::InvalidAmountRange(
+                    "amount is required when fees are paid by the receiver".to_string(),
+                ));
+            };
+            if max_amount.is_some() {
+                return Err(InvoiceError::InvalidAmountRange(
+                    "max_amount can't be set when fees are paid by the receiver".to_string(),
+                ));
+            }
+            if let Some(min) = min_amount {
+                if min > amount {
+                    return Err(InvoiceError::InvalidAmountRange(format!(
+                        "min_amount {} is greater than amount {}",
+                        min, amount
+                    )));
+                }
+            }
+            return Ok(());
+        }
+        if min_amount.is_none() && max_amount.is_none() {
+            return Ok(());
+        }
+        // an invoice with an amount range is an amountless invoice with bounds
+        if self.amount.is_some() {
+            return Err(InvoiceError::InvalidAmountRange(
+                "amount can't be set together with min_amount or max_amount".to_string(),
+            ));
+        }
+        if let (Some(min), Some(max)) = (min_amount, max_amount) {
+            if min > max {
+                return Err(InvoiceError::InvalidAmountRange(format!(
+                    "min_amount {} is greater than max_amount {}",
+                    min, max
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl TryFrom<gen_invoice::RawCkbInvoice> for CkbInvoice {
@@ -1,4 +1,6 @@
 use crate::{fiber::types::Hash256, invoice::CkbInvoice};
+use ckb_types::packed::Script;
+use zeroize::Zeroizing;
 
 use super::InvoiceError;
 
@@ -9,5 +11,30 @@ pub trait InvoiceStore {
         invoice: CkbInvoice,
         preimage: Option<Hash256>,
     ) -> Result<(), InvoiceError>;
-    fn get_invoice_preimage(&self, id: &Hash256) -> Option<Hash256>;
+    /// Gets the preimage of the invoice, it's wiped from memory when dropped.
+    fn get_invoice_preimage(&self, id: &Hash256) -> Option<Zeroizing<Hash256>>;
+    /// Removes the preimage of the invoice, so that the invoice can't be settled anymore.
+    fn remove_invoice_preimage(&self, id: &Hash256);
+    /// Inserts the invoice along with the client token of the request creating it, so that a
+    /// retried request with the same token gets the same invoice.
+    fn insert_invoice_with_client_token(
+        &self,
+        invoice: CkbInvoice,
+        preimage: Option<Hash256>,
+        client_token: &str,
+    ) -> Result<(), InvoiceError>;
+    /// Records the timestamp in milliseconds when a received payment of the invoice is settled.
+    fn insert_invoice_settled_at(&self, id: &Hash256, settled_at: u64);
+    /// Gets the timestamp in milliseconds when a received payment of the invoice is settled.
+    fn get_invoice_settled_at(&self, id: &Hash256) -> Option<u64>;
+    /// Gets the invoice inserted with the client token.
+    fn get_invoice_by_client_token(&self, client_token: &str) -> Option<CkbInvoice>;
+    /// Gets at most `limit` invoices denominated in the UDT, or in native CKB if `udt_type_script`
+    /// is None. The invoices are ordered by the payment hash, starting after the payment hash `after`.
+    fn get_invoices_by_udt_type(
+        &self,
+        udt_type_script: Option<&Script>,
+        limit: usize,
+        after: Option<Hash256>,
+    ) -> Vec<CkbInvoice>;
 }
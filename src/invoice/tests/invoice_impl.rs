@@ -9,7 +9,8 @@ use crate::{
     invoice::{
         invoice_impl::{CkbScript, InvoiceData, SIGNATURE_U5_SIZE},
         utils::{ar_decompress, ar_encompress, rand_sha256_hash},
-        Attribute, CkbInvoice, Currency, InvoiceBuilder, InvoiceError, InvoiceSignature,
+        Attribute, CkbInvoice, Currency, FeesPaidBy, InvoiceBuilder, InvoiceError,
+        InvoiceSignature,
     },
 };
 
@@ -296,6 +297,54 @@ fn test_invoice_signature_check() {
     assert_eq!(invoice.err(), Some(InvoiceError::InvalidSignature));
 }
 
+#[test]
+fn test_invoice_verify_signature() {
+    let (public_key, private_key) = gen_rand_keypair();
+    let sign = |hash: &Message| Secp256k1::new().sign_ecdsa_recoverable(hash, &private_key);
+
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1280))
+        .payment_hash(rand_sha256_hash())
+        .payee_pub_key(public_key)
+        .build_with_sign(sign)
+        .unwrap();
+    assert_eq!(
+        invoice.verify_signature(),
+        Ok(Some((public_key, Some(true))))
+    );
+
+    // the payee public key is not included, it can only be recovered
+    let invoice_without_payee = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1280))
+        .payment_hash(rand_sha256_hash())
+        .build_with_sign(sign)
+        .unwrap();
+    assert_eq!(
+        invoice_without_payee.verify_signature(),
+        Ok(Some((public_key, None)))
+    );
+
+    let unsigned_invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1280))
+        .payment_hash(rand_sha256_hash())
+        .build()
+        .unwrap();
+    assert_eq!(unsigned_invoice.verify_signature(), Ok(None));
+
+    // the tampered invoice is rejected by parsing, but can be inspected when parsed unchecked
+    let mut tampered_invoice = invoice.clone();
+    tampered_invoice.amount = Some(1281);
+    let encoded = tampered_invoice.to_string();
+    assert_eq!(
+        encoded.parse::<CkbInvoice>().err(),
+        Some(InvoiceError::InvalidSignature)
+    );
+    let decoded = CkbInvoice::from_str_unchecked(&encoded).unwrap();
+    let (recovered, matched) = decoded.verify_signature().unwrap().unwrap();
+    assert_ne!(recovered, public_key);
+    assert_eq!(matched, Some(false));
+}
+
 #[test]
 fn test_invoice_builder_duplicated_attr() {
     let gen_payment_hash = rand_sha256_hash();
@@ -452,3 +501,153 @@ fn test_invoice_check_expired() {
     std::thread::sleep(Duration::from_secs(2));
     assert_eq!(invoice.is_expired(), true);
 }
+
+#[test]
+fn test_invoice_amount_range() {
+    let private_key = gen_rand_private_key();
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .payment_hash(rand_sha256_hash())
+        .min_amount(1000)
+        .max_amount(2000)
+        .build_with_sign(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &private_key))
+        .unwrap();
+    assert_eq!(invoice.min_amount(), Some(&1000));
+    assert_eq!(invoice.max_amount(), Some(&2000));
+    assert!(!invoice.is_amount_acceptable(999));
+    assert!(invoice.is_amount_acceptable(1000));
+    assert!(invoice.is_amount_acceptable(2000));
+    assert!(!invoice.is_amount_acceptable(2001));
+
+    let decoded = invoice.to_string().parse::<CkbInvoice>().unwrap();
+    assert_eq!(decoded, invoice);
+    let res = serde_json::to_string(&invoice).unwrap();
+    assert_eq!(serde_json::from_str::<CkbInvoice>(&res).unwrap(), invoice);
+
+    // only the lower bound is set
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .payment_hash(rand_sha256_hash())
+        .min_amount(1000)
+        .build()
+        .unwrap();
+    assert!(!invoice.is_amount_acceptable(999));
+    assert!(invoice.is_amount_acceptable(u128::MAX));
+
+    // an invoice with an exact amount only accepts that amount
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1280))
+        .payment_hash(rand_sha256_hash())
+        .build()
+        .unwrap();
+    assert!(invoice.is_amount_acceptable(1280));
+    assert!(!invoice.is_amount_acceptable(1281));
+}
+
+#[test]
+fn test_invoice_builder_invalid_amount_range() {
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .payment_hash(rand_sha256_hash())
+        .min_amount(2000)
+        .max_amount(1000)
+        .build();
+    assert!(matches!(
+        invoice.err(),
+        Some(InvoiceError::InvalidAmountRange(_))
+    ));
+
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1280))
+        .payment_hash(rand_sha256_hash())
+        .max_amount(2000)
+        .build();
+    assert!(matches!(
+        invoice.err(),
+        Some(InvoiceError::InvalidAmountRange(_))
+    ));
+}
+
+#[test]
+fn test_invoice_fees_paid_by_receiver() {
+    let private_key = gen_rand_private_key();
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(10000))
+        .payment_hash(rand_sha256_hash())
+        .fees_paid_by(FeesPaidBy::Receiver)
+        .build_with_sign(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &private_key))
+        .unwrap();
+    assert_eq!(invoice.fees_paid_by(), Some(&FeesPaidBy::Receiver));
+    // The receiver absorbs up to 5% of the amount by default.
+    assert_eq!(invoice.receiver_max_fee(), Some(500));
+    assert!(!invoice.is_amount_acceptable(9499));
+    assert!(invoice.is_amount_acceptable(9500));
+    assert!(invoice.is_amount_acceptable(10000));
+    assert!(!invoice.is_amount_acceptable(10001));
+
+    let decoded = invoice.to_string().parse::<CkbInvoice>().unwrap();
+    assert_eq!(decoded, invoice);
+    let res = serde_json::to_string(&invoice).unwrap();
+    assert_eq!(serde_json::from_str::<CkbInvoice>(&res).unwrap(), invoice);
+
+    // The min_amount is the minimal amount net of the fees.
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(10000))
+        .payment_hash(rand_sha256_hash())
+        .fees_paid_by(FeesPaidBy::Receiver)
+        .min_amount(9900)
+        .build()
+        .unwrap();
+    assert_eq!(invoice.receiver_max_fee(), Some(100));
+    assert!(!invoice.is_amount_acceptable(9899));
+    assert!(invoice.is_amount_acceptable(9900));
+
+    // The fees are paid by the sender by default.
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(10000))
+        .payment_hash(rand_sha256_hash())
+        .fees_paid_by(FeesPaidBy::Sender)
+        .build()
+        .unwrap();
+    assert_eq!(invoice.receiver_max_fee(), None);
+    assert!(!invoice.is_amount_acceptable(9999));
+
+    for builder in [
+        // the gross amount is required
+        InvoiceBuilder::new(Currency::Fibb).fees_paid_by(FeesPaidBy::Receiver),
+        InvoiceBuilder::new(Currency::Fibb)
+            .amount(Some(10000))
+            .fees_paid_by(FeesPaidBy::Receiver)
+            .max_amount(20000),
+        InvoiceBuilder::new(Currency::Fibb)
+            .amount(Some(10000))
+            .fees_paid_by(FeesPaidBy::Receiver)
+            .min_amount(10001),
+    ] {
+        assert!(matches!(
+            builder.payment_hash(rand_sha256_hash()).build().err(),
+            Some(InvoiceError::InvalidAmountRange(_))
+        ));
+    }
+}
+
+#[test]
+fn test_invoice_builder_max_expiry_time() {
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .payment_hash(rand_sha256_hash())
+        .expiry_time(Duration::from_secs(3600))
+        .max_expiry_time(Duration::from_secs(3600))
+        .build()
+        .unwrap();
+    assert_eq!(invoice.expiry_time(), Some(&Duration::from_secs(3600)));
+
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .payment_hash(rand_sha256_hash())
+        .expiry_time(Duration::from_secs(3601))
+        .max_expiry_time(Duration::from_secs(3600))
+        .build();
+    assert_eq!(
+        invoice.err(),
+        Some(InvoiceError::ExpiryTooLong(
+            Duration::from_secs(3601),
+            Duration::from_secs(3600)
+        ))
+    );
+}
@@ -10,6 +10,7 @@ pub use cch::{start_cch, CchActor, CchConfig};
 pub mod rpc;
 pub use rpc::{start_rpc, RpcConfig};
 pub mod invoice;
+pub mod metrics;
 pub mod store;
 pub mod watchtower;
 
@@ -5,6 +5,7 @@ use fnn::ckb::{
     CkbChainActor,
 };
 use fnn::fiber::{channel::ChannelSubscribers, graph::NetworkGraph};
+use fnn::metrics::start_metrics_server;
 use fnn::store::Store;
 use fnn::tasks::{
     cancel_tasks_and_wait_for_completion, new_tokio_cancellation_token, new_tokio_task_tracker,
@@ -60,7 +61,13 @@ pub async fn main() {
     let token = new_tokio_cancellation_token();
     let root_actor = RootActor::start(tracker, token).await;
 
-    let store = Store::new(config.fiber.as_ref().unwrap().store_path());
+    let fiber_config = config.fiber.as_ref().unwrap();
+    let store = Store::new(fiber_config.store_path())
+        .with_max_value_sizes(fiber_config.store_max_value_sizes())
+        .with_write_policy(fiber_config.store_write_policy())
+        .with_read_max_retries(fiber_config.store_read_max_retries())
+        .with_channel_state_cache_size(fiber_config.store_channel_state_cache_size())
+        .with_graph_snapshot(fiber_config.graph_snapshot_interval_seconds() != 0);
     let subscribers = ChannelSubscribers::default();
 
     let (fiber_command_sender, network_graph) = match config.fiber.clone() {
@@ -86,10 +93,10 @@ pub async fn main() {
             const CHANNEL_SIZE: usize = 4000;
             let (event_sender, mut event_receiver) = mpsc::channel(CHANNEL_SIZE);
 
-            let network_graph = Arc::new(RwLock::new(NetworkGraph::new(
-                store.clone(),
-                node_public_key.clone().into(),
-            )));
+            let mut network_graph =
+                NetworkGraph::new(store.clone(), node_public_key.clone().into());
+            network_graph.set_gossip_filter(fiber_config.gossip_filter());
+            let network_graph = Arc::new(RwLock::new(network_graph));
 
             let secret_key = ckb_config.read_secret_key().unwrap();
             let secp = Secp256k1::new();
@@ -97,6 +104,14 @@ pub async fn main() {
             let default_shutdown_script =
                 get_script_by_contract(Contract::Secp256k1Lock, &pubkey_hash[0..20]);
 
+            if let Some(metrics_listening_addr) = fiber_config.metrics_listening_addr() {
+                info!("Starting metrics server");
+                if let Err(err) = start_metrics_server(metrics_listening_addr).await {
+                    error!("Failed to start metrics server: {}", err);
+                    return;
+                }
+            }
+
             info!("Starting fiber");
             let network_actor = start_network(
                 fiber_config,
@@ -0,0 +1,415 @@
+use hyper::{header::CONTENT_TYPE, server::conn::Http, service::service_fn, Body, Response};
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{net::TcpListener, select};
+use tracing::{debug, info};
+
+use crate::tasks::{new_tokio_cancellation_token, new_tokio_task_tracker};
+
+/// Counters and gauges of the node, exported in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    payments_sent: AtomicU64,
+    payments_received: AtomicU64,
+    payments_failed: AtomicU64,
+    forwarded_tlcs: AtomicU64,
+    forwarded_amount: AtomicU64,
+    active_channels: AtomicU64,
+    graph_nodes: AtomicU64,
+    graph_channels: AtomicU64,
+    gossip_messages_dropped: AtomicU64,
+    rpc_calls: Mutex<BTreeMap<String, Arc<RpcMethodStats>>>,
+    payments_succeeded_latency: Histogram,
+    payments_failed_latency: Histogram,
+    forwarded_tlcs_latency: Histogram,
+}
+
+// The upper bounds of the histogram buckets grow exponentially from 1ms to about 17 minutes.
+const HISTOGRAM_BUCKETS: usize = 21;
+
+/// A latency histogram with fixed exponential buckets, which can be updated without locking or
+/// allocation.
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; HISTOGRAM_BUCKETS],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn bucket_upper_bound_micros(index: usize) -> u64 {
+        1000 << index
+    }
+
+    pub fn observe(&self, latency: Duration) {
+        let micros = latency.as_micros().try_into().unwrap_or(u64::MAX);
+        // Latencies above the largest bound are only counted in the +Inf bucket, i.e. `count`.
+        if let Some(index) =
+            (0..HISTOGRAM_BUCKETS).find(|index| micros <= Self::bucket_upper_bound_micros(*index))
+        {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Render the samples of the histogram, `labels` are prepended to the `le` label of the buckets.
+    fn render(&self, output: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(
+                output,
+                "{}_bucket{{{}le=\"{}\"}} {}",
+                name,
+                labels,
+                Self::bucket_upper_bound_micros(index) as f64 / 1_000_000.0,
+                cumulative
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(output, "{}_bucket{{{}le=\"+Inf\"}} {}", name, labels, count);
+        let labels = labels.trim_end_matches(',');
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        let _ = writeln!(
+            output,
+            "{}_sum{} {}",
+            name,
+            labels,
+            self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        );
+        let _ = writeln!(output, "{}_count{} {}", name, labels, count);
+    }
+}
+
+/// The calls of an rpc method, shared with the in-flight calls to record their latencies.
+#[derive(Debug, Default)]
+pub struct RpcMethodStats {
+    calls: AtomicU64,
+    latency: Histogram,
+}
+
+impl RpcMethodStats {
+    pub fn record_latency(&self, latency: Duration) {
+        self.latency.observe(latency);
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Unknown methods called by clients are counted as "other" once there are too many methods,
+// so that the rpc calls can't grow the metrics without bound.
+const MAX_RPC_METHODS: usize = 256;
+
+static METRICS: once_cell::sync::Lazy<Metrics> = once_cell::sync::Lazy::new(Metrics::default);
+
+/// The metrics registry of this process.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    pub fn record_payment_sent(&self) {
+        self.payments_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_payment_received(&self) {
+        self.payments_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_payment_failed(&self) {
+        self.payments_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_forwarded_tlc(&self, amount: u128) {
+        self.forwarded_tlcs.fetch_add(1, Ordering::Relaxed);
+        self.forwarded_amount
+            .fetch_add(amount.try_into().unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    pub fn set_active_channels(&self, count: usize) {
+        self.active_channels.store(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn set_graph_size(&self, nodes: usize, channels: usize) {
+        self.graph_nodes.store(nodes as u64, Ordering::Relaxed);
+        self.graph_channels
+            .store(channels as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_gossip_message_dropped(&self) {
+        self.gossip_messages_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_payment_latency(&self, succeeded: bool, latency: Duration) {
+        if succeeded {
+            self.payments_succeeded_latency.observe(latency);
+        } else {
+            self.payments_failed_latency.observe(latency);
+        }
+    }
+
+    pub fn record_forwarded_tlc_latency(&self, latency: Duration) {
+        self.forwarded_tlcs_latency.observe(latency);
+    }
+
+    /// Count a call of the rpc method, the returned stats are used to record its latency.
+    pub fn record_rpc_call(&self, method: &str) -> Arc<RpcMethodStats> {
+        let mut rpc_calls = self.rpc_calls.lock().expect("lock rpc calls");
+        let method = if rpc_calls.contains_key(method) || rpc_calls.len() < MAX_RPC_METHODS {
+            method
+        } else {
+            "other"
+        };
+        // Only allocate the key for the first call of each method.
+        let stats = match rpc_calls.get(method) {
+            Some(stats) => stats.clone(),
+            None => rpc_calls.entry(method.to_string()).or_default().clone(),
+        };
+        stats.calls.fetch_add(1, Ordering::Relaxed);
+        stats
+    }
+
+    /// Render all the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let mut write_metric = |name: &str, metric_type: &str, help: &str, value: u64| {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} {}", name, metric_type);
+            let _ = writeln!(output, "{} {}", name, value);
+        };
+        let load = |value: &AtomicU64| value.load(Ordering::Relaxed);
+        write_metric(
+            "fiber_payments_sent_total",
+            "counter",
+            "Number of payments sent successfully.",
+            load(&self.payments_sent),
+        );
+        write_metric(
+            "fiber_payments_received_total",
+            "counter",
+            "Number of payments received.",
+            load(&self.payments_received),
+        );
+        write_metric(
+            "fiber_payments_failed_total",
+            "counter",
+            "Number of payments failed.",
+            load(&self.payments_failed),
+        );
+        write_metric(
+            "fiber_forwarded_tlcs_total",
+            "counter",
+            "Number of tlcs forwarded and fulfilled.",
+            load(&self.forwarded_tlcs),
+        );
+        write_metric(
+            "fiber_forwarded_amount_total",
+            "counter",
+            "Total amount of the tlcs forwarded and fulfilled.",
+            load(&self.forwarded_amount),
+        );
+        write_metric(
+            "fiber_active_channels",
+            "gauge",
+            "Number of channels in the ChannelReady state.",
+            load(&self.active_channels),
+        );
+        write_metric(
+            "fiber_graph_nodes",
+            "gauge",
+            "Number of nodes in the network graph.",
+            load(&self.graph_nodes),
+        );
+        write_metric(
+            "fiber_graph_channels",
+            "gauge",
+            "Number of channels in the network graph.",
+            load(&self.graph_channels),
+        );
+        write_metric(
+            "fiber_gossip_messages_dropped_total",
+            "counter",
+            "Number of gossip messages dropped by the per-peer rate limit.",
+            load(&self.gossip_messages_dropped),
+        );
+
+        let _ = writeln!(
+            output,
+            "# HELP fiber_rpc_calls_total Number of rpc calls by method."
+        );
+        let _ = writeln!(output, "# TYPE fiber_rpc_calls_total counter");
+        let rpc_calls = self.rpc_calls.lock().expect("lock rpc calls");
+        for (method, stats) in rpc_calls.iter() {
+            let _ = writeln!(
+                output,
+                "fiber_rpc_calls_total{{method=\"{}\"}} {}",
+                escape_label_value(method),
+                stats.calls.load(Ordering::Relaxed)
+            );
+        }
+
+        let _ = writeln!(
+            output,
+            "# HELP fiber_rpc_call_duration_seconds Latency of rpc calls by method."
+        );
+        let _ = writeln!(output, "# TYPE fiber_rpc_call_duration_seconds histogram");
+        for (method, stats) in rpc_calls.iter() {
+            stats.latency.render(
+                &mut output,
+                "fiber_rpc_call_duration_seconds",
+                &format!("method=\"{}\",", escape_label_value(method)),
+            );
+        }
+        drop(rpc_calls);
+
+        let _ = writeln!(
+            output,
+            "# HELP fiber_payment_duration_seconds Time from sending a payment to its final status."
+        );
+        let _ = writeln!(output, "# TYPE fiber_payment_duration_seconds histogram");
+        self.payments_succeeded_latency.render(
+            &mut output,
+            "fiber_payment_duration_seconds",
+            "status=\"succeeded\",",
+        );
+        self.payments_failed_latency.render(
+            &mut output,
+            "fiber_payment_duration_seconds",
+            "status=\"failed\",",
+        );
+
+        let _ = writeln!(
+            output,
+            "# HELP fiber_forwarded_tlc_duration_seconds Time from forwarding a tlc to the next hop fulfilling it."
+        );
+        let _ = writeln!(
+            output,
+            "# TYPE fiber_forwarded_tlc_duration_seconds histogram"
+        );
+        self.forwarded_tlcs_latency
+            .render(&mut output, "fiber_forwarded_tlc_duration_seconds", "");
+        output
+    }
+}
+
+/// Start a http server which serves the metrics in the Prometheus text format on any path.
+pub async fn start_metrics_server(listening_addr: &str) -> std::io::Result<()> {
+    let listener = TcpListener::bind(listening_addr).await?;
+    info!("Metrics server listening on {}", listener.local_addr()?);
+    new_tokio_task_tracker().spawn(async move {
+        let token = new_tokio_cancellation_token();
+        loop {
+            select! {
+                accepted = listener.accept() => {
+                    let Ok((stream, _)) = accepted else {
+                        continue;
+                    };
+                    new_tokio_task_tracker().spawn(async move {
+                        let service = service_fn(|_request| async {
+                            Ok::<_, Infallible>(
+                                Response::builder()
+                                    .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                                    .body(Body::from(metrics().render()))
+                                    .expect("build metrics response"),
+                            )
+                        });
+                        if let Err(err) = Http::new().serve_connection(stream, service).await {
+                            debug!("Failed to serve metrics connection: {:?}", err);
+                        }
+                    });
+                }
+                _ = token.cancelled() => {
+                    debug!("Cancellation received, stopping metrics server");
+                    break;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = Metrics::default();
+        metrics.record_payment_sent();
+        metrics.record_forwarded_tlc(100);
+        metrics.record_forwarded_tlc(200);
+        metrics.set_graph_size(3, 2);
+        metrics.record_gossip_message_dropped();
+        metrics.record_rpc_call("list_channels");
+        metrics.record_rpc_call("list_channels");
+        metrics.record_rpc_call("node_info");
+
+        let output = metrics.render();
+        assert!(output.contains("# TYPE fiber_payments_sent_total counter\n"));
+        assert!(output.contains("\nfiber_payments_sent_total 1\n"));
+        assert!(output.contains("\nfiber_payments_failed_total 0\n"));
+        assert!(output.contains("\nfiber_forwarded_tlcs_total 2\n"));
+        assert!(output.contains("\nfiber_forwarded_amount_total 300\n"));
+        assert!(output.contains("\nfiber_graph_nodes 3\n"));
+        assert!(output.contains("\nfiber_graph_channels 2\n"));
+        assert!(output.contains("\nfiber_gossip_messages_dropped_total 1\n"));
+        assert!(output.contains("\nfiber_rpc_calls_total{method=\"list_channels\"} 2\n"));
+        assert!(output.contains("\nfiber_rpc_calls_total{method=\"node_info\"} 1\n"));
+    }
+
+    #[test]
+    fn test_render_latency_histograms() {
+        let metrics = Metrics::default();
+        let stats = metrics.record_rpc_call("list_channels");
+        stats.record_latency(Duration::from_micros(500));
+        stats.record_latency(Duration::from_millis(3));
+        metrics.record_payment_latency(true, Duration::from_secs(2));
+        metrics.record_payment_latency(false, Duration::from_secs(3600));
+
+        let output = metrics.render();
+        assert!(output.contains("# TYPE fiber_rpc_call_duration_seconds histogram\n"));
+        let rpc_bucket = |le: &str| {
+            format!(
+                "\nfiber_rpc_call_duration_seconds_bucket{{method=\"list_channels\",le=\"{}\"}} ",
+                le
+            )
+        };
+        assert!(output.contains(&(rpc_bucket("0.001") + "1\n")));
+        assert!(output.contains(&(rpc_bucket("0.002") + "1\n")));
+        assert!(output.contains(&(rpc_bucket("0.004") + "2\n")));
+        assert!(output.contains(&(rpc_bucket("+Inf") + "2\n")));
+        assert!(output
+            .contains("\nfiber_rpc_call_duration_seconds_sum{method=\"list_channels\"} 0.0035\n"));
+        assert!(output
+            .contains("\nfiber_rpc_call_duration_seconds_count{method=\"list_channels\"} 2\n"));
+        assert!(output.contains(
+            "\nfiber_payment_duration_seconds_bucket{status=\"succeeded\",le=\"2.048\"} 1\n"
+        ));
+        // Latencies above the largest bucket are only counted in +Inf.
+        assert!(output.contains(
+            "\nfiber_payment_duration_seconds_bucket{status=\"failed\",le=\"1048.576\"} 0\n"
+        ));
+        assert!(output.contains(
+            "\nfiber_payment_duration_seconds_bucket{status=\"failed\",le=\"+Inf\"} 1\n"
+        ));
+        assert!(output.contains("\nfiber_forwarded_tlc_duration_seconds_count 0\n"));
+    }
+}
@@ -0,0 +1,109 @@
+use jsonrpsee::{
+    server::middleware::rpc::RpcServiceT,
+    types::{error::METHOD_NOT_FOUND_CODE, ErrorObject, Request},
+    MethodResponse,
+};
+use std::{collections::HashSet, sync::Arc};
+use tower::Layer;
+use tracing::debug;
+
+use super::auth::READ_ONLY_METHODS;
+
+/// The entry of a method allowlist which stands for all the read-only methods.
+pub(crate) const READ_ONLY_METHODS_TAG: &str = "@read_only";
+
+/// Expands the configured allowlist into the set of the allowed methods, None means all the methods
+/// are allowed.
+pub(crate) fn resolve_allowed_methods(allowed_methods: &[String]) -> Option<Arc<HashSet<String>>> {
+    if allowed_methods.is_empty() {
+        return None;
+    }
+    let mut methods = HashSet::new();
+    for method in allowed_methods {
+        if method == READ_ONLY_METHODS_TAG {
+            methods.extend(READ_ONLY_METHODS.iter().map(|method| method.to_string()));
+        } else {
+            methods.insert(method.clone());
+        }
+    }
+    Some(Arc::new(methods))
+}
+
+/// Rpc middleware which rejects the calls of the methods not allowed on the listener.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RpcAllowlistLayer {
+    allowed_methods: Option<Arc<HashSet<String>>>,
+}
+
+impl RpcAllowlistLayer {
+    pub(crate) fn new(allowed_methods: Option<Arc<HashSet<String>>>) -> Self {
+        Self { allowed_methods }
+    }
+}
+
+impl<S> Layer<S> for RpcAllowlistLayer {
+    type Service = RpcAllowlist<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        RpcAllowlist {
+            service,
+            allowed_methods: self.allowed_methods.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RpcAllowlist<S> {
+    service: S,
+    allowed_methods: Option<Arc<HashSet<String>>>,
+}
+
+impl<'a, S> RpcServiceT<'a> for RpcAllowlist<S>
+where
+    S: RpcServiceT<'a>,
+{
+    type Future = futures::future::Either<S::Future, std::future::Ready<MethodResponse>>;
+
+    fn call(&self, request: Request<'a>) -> Self::Future {
+        let allowed = self
+            .allowed_methods
+            .as_ref()
+            .map_or(true, |methods| methods.contains(request.method_name()));
+        if allowed {
+            return futures::future::Either::Left(self.service.call(request));
+        }
+        let method_name = request.method_name().to_string();
+        debug!(
+            "Rejecting rpc method not allowed on the listener: {}",
+            method_name
+        );
+        futures::future::Either::Right(std::future::ready(MethodResponse::error(
+            request.id,
+            ErrorObject::owned(
+                METHOD_NOT_FOUND_CODE,
+                format!("Method {} is not allowed on this listener", method_name),
+                None::<()>,
+            ),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_allowed_methods() {
+        assert!(resolve_allowed_methods(&[]).is_none());
+
+        let methods = resolve_allowed_methods(&[
+            READ_ONLY_METHODS_TAG.to_string(),
+            "send_payment".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(methods.len(), READ_ONLY_METHODS.len() + 1);
+        assert!(methods.contains("node_info"));
+        assert!(methods.contains("send_payment"));
+        assert!(!methods.contains("open_channel"));
+    }
+}
@@ -0,0 +1,204 @@
+use hyper::{header::AUTHORIZATION, Body, Request, Response, StatusCode};
+use std::{
+    error::Error as StdError,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+use tracing::debug;
+
+/// Methods which don't change the state of the node, they can be called over http without the auth
+/// token. All the other methods are privileged and require the auth token if it is configured.
+/// A websocket connection always requires the token, since the handshake carries no method.
+pub(crate) const READ_ONLY_METHODS: &[&str] = &[
+    "list_channels",
+    "get_peer_channels",
+    "list_closed_channels",
+    "get_channel_update",
+    "get_payment",
+    "get_payment_by_invoice",
+    "list_failed_payments",
+    "check_inbound_capacity",
+    "list_rejected_tlcs",
+    "get_channel_open_status",
+    "get_channel_funding_address",
+    "get_channel_events",
+    "list_channels_needing_action",
+    "audit_channel",
+    "get_rebalance_route",
+    "get_receive_btc_order",
+    "parse_invoice",
+    "verify_invoice",
+    "get_invoice",
+    "list_invoices",
+    "has_preimage",
+    "node_info",
+    "suggest_fee_rates",
+    "graph_sync_status",
+    "health",
+    "graph_nodes",
+    "graph_channels",
+    "subscribe_graph_nodes",
+    "unsubscribe_graph_nodes",
+    "subscribe_graph_channels",
+    "unsubscribe_graph_channels",
+];
+
+/// Http middleware which requires a bearer token for privileged methods.
+#[derive(Clone, Debug)]
+pub(crate) struct RpcAuthLayer {
+    auth_token: Option<Arc<str>>,
+}
+
+impl RpcAuthLayer {
+    pub(crate) fn new(auth_token: Option<String>) -> Self {
+        Self {
+            auth_token: auth_token.map(Into::into),
+        }
+    }
+}
+
+impl<S> Layer<S> for RpcAuthLayer {
+    type Service = RpcAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RpcAuth {
+            inner,
+            auth_token: self.auth_token.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RpcAuth<S> {
+    inner: S,
+    auth_token: Option<Arc<str>>,
+}
+
+impl<S> Service<Request<Body>> for RpcAuth<S>
+where
+    S: Service<
+            Request<Body>,
+            Response = Response<Body>,
+            Error = Box<dyn StdError + Send + Sync + 'static>,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn StdError + Send + Sync + 'static>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let authorized = match &self.auth_token {
+            None => true,
+            Some(auth_token) => is_authorized(&request, auth_token),
+        };
+        if authorized {
+            return Box::pin(self.inner.call(request));
+        }
+
+        // The request is not authorized, only read-only methods are allowed.
+        // Take the service which is ready, and leave a clone in place.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            if !is_read_only_request(&body) {
+                debug!("Rejecting unauthorized rpc request: {:?}", &parts);
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .body(Body::from("Unauthorized"))
+                    .expect("build unauthorized response"));
+            }
+            inner
+                .call(Request::from_parts(parts, Body::from(body)))
+                .await
+        })
+    }
+}
+
+fn is_authorized(request: &Request<Body>, auth_token: &str) -> bool {
+    request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map_or(false, |token| constant_time_eq(token, auth_token))
+}
+
+// Compare the tokens without short-circuiting, so that the time taken
+// doesn't leak how many leading bytes of the token are correct.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+// Returns true only if the body is a valid json rpc request (or batch request)
+// and all the called methods are read-only.
+fn is_read_only_request(body: &[u8]) -> bool {
+    let is_read_only = |request: &serde_json::Value| {
+        request
+            .get("method")
+            .and_then(|method| method.as_str())
+            .map_or(false, |method| READ_ONLY_METHODS.contains(&method))
+    };
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Array(requests)) => {
+            !requests.is_empty() && requests.iter().all(is_read_only)
+        }
+        Ok(request) => is_read_only(&request),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_read_only_request() {
+        assert!(is_read_only_request(
+            br#"{"jsonrpc":"2.0","id":1,"method":"node_info","params":[]}"#
+        ));
+        assert!(!is_read_only_request(
+            br#"{"jsonrpc":"2.0","id":1,"method":"send_payment","params":[]}"#
+        ));
+        assert!(is_read_only_request(
+            br#"[{"jsonrpc":"2.0","id":1,"method":"graph_nodes"},{"jsonrpc":"2.0","id":2,"method":"parse_invoice"}]"#
+        ));
+        assert!(!is_read_only_request(
+            br#"[{"jsonrpc":"2.0","id":1,"method":"graph_nodes"},{"jsonrpc":"2.0","id":2,"method":"shutdown_channel"}]"#
+        ));
+        assert!(!is_read_only_request(b"[]"));
+        assert!(!is_read_only_request(b"not json"));
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let request = |value: &str| {
+            Request::builder()
+                .header(AUTHORIZATION, value)
+                .body(Body::empty())
+                .unwrap()
+        };
+        assert!(is_authorized(&request("Bearer secret"), "secret"));
+        assert!(!is_authorized(&request("Bearer secreT"), "secret"));
+        assert!(!is_authorized(&request("secret"), "secret"));
+        assert!(!is_authorized(
+            &Request::builder().body(Body::empty()).unwrap(),
+            "secret"
+        ));
+    }
+}
@@ -1,18 +1,33 @@
+use crate::ckb::contracts::is_standard_lock_script;
 use crate::fiber::{
     channel::{
-        AddTlcCommand, ChannelActorStateStore, ChannelCommand, ChannelCommandWithId, ChannelState,
-        RemoveTlcCommand, ShutdownCommand, UpdateCommand,
+        AddTlcCommand, ChannelActorState, ChannelActorStateStore, ChannelCommand,
+        ChannelCommandWithId, ChannelLogEntry, ChannelOpenStep, ChannelState, ClosedChannel,
+        DustTlcLimit, PeerFeePolicy, RebalancePolicy, RemoveTlcCommand, ShutdownCommand,
+        UpdateCommand, CHANNEL_DISABLED_FLAG, MIN_AVERAGE_BLOCK_INTERVAL_MS,
+    },
+    graph::{
+        NetworkGraphStateStore, PaymentFailureReason, PaymentFeeCap, PaymentSessionStatus,
+        RejectedRoute, RouteRejectReason,
     },
-    graph::PaymentSessionStatus,
     hash_algorithm::HashAlgorithm,
-    network::{AcceptChannelCommand, OpenChannelCommand, SendPaymentCommand},
-    serde_utils::{U128Hex, U64Hex},
-    types::{Hash256, LockTime, Pubkey, RemoveTlcFulfill, TlcErr, TlcErrPacket, TlcErrorCode},
+    network::{
+        AcceptChannelCommand, ChannelAttentionReason, ChannelAttentionThresholds,
+        GetRebalanceRouteCommand, OpenChannelCommand, SendPaymentCommand, SendPaymentResponse,
+    },
+    serde_utils::{EntityHex, U128Hex, U64Hex, U8Hex},
+    types::{
+        ChannelUpdate, EcdsaSignature, Hash256, LockTime, Pubkey, RemoveTlcFulfill, TlcErr,
+        TlcErrPacket, TlcErrorCode,
+    },
     NetworkActorCommand, NetworkActorMessage,
 };
+use crate::invoice::{CkbInvoice, FeesPaidBy};
 use crate::{handle_actor_call, handle_actor_cast, log_and_error};
-use ckb_jsonrpc_types::{EpochNumberWithFraction, Script};
+use ckb_jsonrpc_types::{EpochNumberWithFraction, JsonBytes, Script, Transaction};
 use ckb_types::core::{EpochNumberWithFraction as EpochNumberWithFractionCore, FeeRate};
+use ckb_types::packed::OutPoint;
+use futures::{stream, StreamExt};
 use jsonrpsee::{
     core::async_trait,
     proc_macros::rpc,
@@ -23,7 +38,9 @@ use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use std::cmp::Reverse;
 use std::str::FromStr;
+use std::time::{Duration, UNIX_EPOCH};
 use tentacle::secio::PeerId;
+use tokio::time::{sleep, Instant};
 
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug)]
@@ -52,6 +69,15 @@ pub(crate) struct OpenChannelParams {
     max_tlc_value_in_flight: Option<u128>,
     #[serde_as(as = "Option<U64Hex>")]
     max_tlc_number_in_flight: Option<u64>,
+    #[serde_as(as = "Option<U128Hex>")]
+    max_inbound_tlc_value: Option<u128>,
+    #[serde_as(as = "Option<U128Hex>")]
+    max_outbound_tlc_value: Option<u128>,
+    external_funding_tx: Option<Transaction>,
+    allow_nonstandard: Option<bool>,
+    #[serde_as(as = "Option<U64Hex>")]
+    open_timeout_seconds: Option<u64>,
+    zero_conf: Option<bool>,
 }
 
 #[derive(Clone, Serialize)]
@@ -66,6 +92,12 @@ pub(crate) struct AcceptChannelParams {
     #[serde_as(as = "U128Hex")]
     funding_amount: u128,
     shutdown_script: Option<Script>,
+    #[serde_as(as = "Option<U128Hex>")]
+    max_inbound_tlc_value: Option<u128>,
+    #[serde_as(as = "Option<U128Hex>")]
+    max_outbound_tlc_value: Option<u128>,
+    allow_nonstandard: Option<bool>,
+    zero_conf: Option<bool>,
 }
 
 #[derive(Clone, Serialize)]
@@ -109,6 +141,134 @@ pub(crate) struct Channel {
     received_tlc_balance: u128,
     #[serde_as(as = "U64Hex")]
     created_at: u64,
+    #[serde_as(as = "Option<U64Hex>")]
+    shutdown_fee: Option<u64>,
+    hash_algorithms: Vec<HashAlgorithm>,
+    #[serde_as(as = "U8Hex")]
+    onion_version: u8,
+    rebalance_policy: Option<RebalancePolicy>,
+    #[serde_as(as = "Option<U128Hex>")]
+    max_inbound_tlc_value: Option<u128>,
+    #[serde_as(as = "Option<U128Hex>")]
+    max_outbound_tlc_value: Option<u128>,
+    #[serde_as(as = "U64Hex")]
+    connected_duration: u64,
+    #[serde_as(as = "Option<U64Hex>")]
+    last_disconnected_at: Option<u64>,
+    #[serde_as(as = "U64Hex")]
+    disconnection_count: u64,
+    label: Option<String>,
+    zero_conf: bool,
+    funding_at_risk: bool,
+    #[serde_as(as = "U128Hex")]
+    funding_at_risk_amount: u128,
+    dust_tlc_limit: Option<DustTlcLimit>,
+    #[serde_as(as = "U128Hex")]
+    dust_tlc_exposure: u128,
+    #[serde_as(as = "Option<U64Hex>")]
+    max_htlc_fraction_of_capacity: Option<u64>,
+    #[serde_as(as = "Option<U128Hex>")]
+    max_tlc_value: Option<u128>,
+    #[serde_as(as = "Option<U64Hex>")]
+    features: Option<u64>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ListClosedChannelsParams {
+    #[serde_as(as = "Option<U64Hex>")]
+    limit: Option<u64>,
+    // the cursor returned by the previous call
+    after: Option<JsonBytes>,
+    #[serde_as(as = "Option<U64Hex>")]
+    closed_after: Option<u64>,
+    #[serde_as(as = "Option<U64Hex>")]
+    closed_before: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct ListClosedChannelsResult {
+    channels: Vec<ClosedChannelInfo>,
+    last_cursor: JsonBytes,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct ClosedChannelInfo {
+    channel_id: Hash256,
+    #[serde_as(as = "DisplayFromStr")]
+    peer_id: PeerId,
+    funding_udt_type_script: Option<Script>,
+    state: ChannelState,
+    #[serde_as(as = "U128Hex")]
+    local_balance: u128,
+    #[serde_as(as = "U128Hex")]
+    remote_balance: u128,
+    #[serde_as(as = "U64Hex")]
+    local_reserved_ckb_amount: u64,
+    #[serde_as(as = "U64Hex")]
+    remote_reserved_ckb_amount: u64,
+    closing_tx_hash: Option<Hash256>,
+    #[serde_as(as = "U64Hex")]
+    closed_at: u64,
+}
+
+impl From<ClosedChannel> for ClosedChannelInfo {
+    fn from(closed_channel: ClosedChannel) -> Self {
+        ClosedChannelInfo {
+            channel_id: closed_channel.channel_id,
+            peer_id: closed_channel.peer_id,
+            funding_udt_type_script: closed_channel.funding_udt_type_script.map(Into::into),
+            state: ChannelState::Closed(closed_channel.close_flags),
+            local_balance: closed_channel.local_balance,
+            remote_balance: closed_channel.remote_balance,
+            local_reserved_ckb_amount: closed_channel.local_reserved_ckb_amount,
+            remote_reserved_ckb_amount: closed_channel.remote_reserved_ckb_amount,
+            closing_tx_hash: closed_channel.closing_tx_hash,
+            closed_at: closed_channel.closed_at,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct GetPeerChannelsParams {
+    #[serde_as(as = "DisplayFromStr")]
+    peer_id: PeerId,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct GetPeerChannelsResult {
+    #[serde_as(as = "DisplayFromStr")]
+    peer_id: PeerId,
+    channels: Vec<Channel>,
+    // The balances with the peer summed by the asset, as the amounts of different assets
+    // are not comparable.
+    balances: Vec<PeerBalance>,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct PeerBalance {
+    funding_udt_type_script: Option<Script>,
+    #[serde_as(as = "U64Hex")]
+    channel_count: u64,
+    #[serde_as(as = "U128Hex")]
+    local_balance: u128,
+    #[serde_as(as = "U128Hex")]
+    remote_balance: u128,
+    #[serde_as(as = "U128Hex")]
+    offered_tlc_balance: u128,
+    #[serde_as(as = "U128Hex")]
+    received_tlc_balance: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct SetChannelLabelParams {
+    channel_id: Hash256,
+    // the label is removed if it's null or empty
+    label: Option<String>,
 }
 
 #[serde_as]
@@ -120,6 +280,8 @@ pub(crate) struct AddTlcParams {
     payment_hash: Hash256,
     expiry: LockTime,
     hash_algorithm: Option<HashAlgorithm>,
+    // the preimage of the payment hash held by the node, e.g. to settle the tlc directly in tests
+    preimage: Option<Hash256>,
 }
 
 #[serde_as]
@@ -129,6 +291,14 @@ pub(crate) struct AddTlcResult {
     tlc_id: u64,
 }
 
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct FailForwardedTlcParams {
+    channel_id: Hash256,
+    #[serde_as(as = "U64Hex")]
+    tlc_id: u64,
+}
+
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct RemoveTlcParams {
@@ -154,6 +324,35 @@ pub(crate) struct ShutdownChannelParams {
     force: Option<bool>,
     #[serde_as(as = "U64Hex")]
     fee_rate: u64,
+    #[serde_as(as = "Option<U64Hex>")]
+    max_fee_rate: Option<u64>,
+    allow_nonstandard: Option<bool>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ShutdownChannelsWithPeerParams {
+    #[serde_as(as = "DisplayFromStr")]
+    peer_id: PeerId,
+    close_script: Script,
+    force: Option<bool>,
+    #[serde_as(as = "U64Hex")]
+    fee_rate: u64,
+    #[serde_as(as = "Option<U64Hex>")]
+    max_fee_rate: Option<u64>,
+    allow_nonstandard: Option<bool>,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct ShutdownChannelsWithPeerResult {
+    channels: Vec<ShutdownChannelResult>,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct ShutdownChannelResult {
+    channel_id: Hash256,
+    // The error message if the channel failed to start shutting down.
+    error: Option<String>,
 }
 
 #[serde_as]
@@ -169,6 +368,93 @@ pub struct UpdateChannelParams {
     tlc_maximum_value: Option<u128>,
     #[serde_as(as = "Option<U128Hex>")]
     tlc_fee_proportional_millionths: Option<u128>,
+    rebalance_policy: Option<RebalancePolicy>,
+    dust_tlc_limit: Option<DustTlcLimit>,
+    #[serde_as(as = "Option<U64Hex>")]
+    max_htlc_fraction_of_capacity: Option<u64>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct PeerFeePolicyParams {
+    #[serde_as(as = "DisplayFromStr")]
+    peer_id: PeerId,
+    #[serde_as(as = "Option<U64Hex>")]
+    tlc_locktime_expiry_delta: Option<u64>,
+    #[serde_as(as = "Option<U128Hex>")]
+    tlc_minimum_value: Option<u128>,
+    #[serde_as(as = "Option<U128Hex>")]
+    tlc_maximum_value: Option<u128>,
+    #[serde_as(as = "Option<U128Hex>")]
+    tlc_fee_proportional_millionths: Option<u128>,
+    // remove the current policy of the peer before applying the given fields
+    reset: Option<bool>,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct PeerFeePolicyResult {
+    #[serde_as(as = "Option<U64Hex>")]
+    tlc_locktime_expiry_delta: Option<u64>,
+    #[serde_as(as = "Option<U128Hex>")]
+    tlc_minimum_value: Option<u128>,
+    #[serde_as(as = "Option<U128Hex>")]
+    tlc_maximum_value: Option<u128>,
+    #[serde_as(as = "Option<U128Hex>")]
+    tlc_fee_proportional_millionths: Option<u128>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct GetChannelUpdateParams {
+    channel_id: Hash256,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct RebroadcastChannelAnnouncementParams {
+    channel_id: Hash256,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct ChannelUpdatePolicy {
+    // The node which signed the channel update, the policy applies to the tlcs forwarded by it.
+    node_id: Pubkey,
+    #[serde_as(as = "U64Hex")]
+    version: u64,
+    enabled: bool,
+    #[serde_as(as = "U64Hex")]
+    tlc_locktime_expiry_delta: u64,
+    #[serde_as(as = "U128Hex")]
+    tlc_minimum_value: u128,
+    #[serde_as(as = "U128Hex")]
+    tlc_maximum_value: u128,
+    #[serde_as(as = "U128Hex")]
+    tlc_fee_proportional_millionths: u128,
+    signature: Option<EcdsaSignature>,
+}
+
+impl ChannelUpdatePolicy {
+    fn new(node_id: Pubkey, update: &ChannelUpdate) -> Self {
+        Self {
+            node_id,
+            version: update.version,
+            enabled: update.channel_flags & CHANNEL_DISABLED_FLAG == 0,
+            tlc_locktime_expiry_delta: update.tlc_locktime_expiry_delta,
+            tlc_minimum_value: update.tlc_minimum_value,
+            tlc_maximum_value: update.tlc_maximum_value,
+            tlc_fee_proportional_millionths: update.tlc_fee_proportional_millionths,
+            signature: update.signature.clone(),
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct GetChannelUpdateResult {
+    #[serde_as(as = "EntityHex")]
+    channel_outpoint: OutPoint,
+    local_update: Option<ChannelUpdatePolicy>,
+    remote_update: Option<ChannelUpdatePolicy>,
 }
 
 #[serde_as]
@@ -177,6 +463,40 @@ pub struct GetPaymentCommandParams {
     pub payment_hash: Hash256,
 }
 
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ListFailedPaymentsParams {
+    // the maximal number of the payments to return, default is 100
+    #[serde_as(as = "Option<U64Hex>")]
+    limit: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct ListFailedPaymentsResult {
+    payments: Vec<GetPaymentCommandResult>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ClearFailedPaymentsParams {
+    // only the failed payments created at least this many seconds ago are removed
+    #[serde_as(as = "U64Hex")]
+    older_than_seconds: u64,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct ClearFailedPaymentsResult {
+    #[serde_as(as = "U64Hex")]
+    cleared: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct GetPaymentByInvoiceParams {
+    // the encoded invoice which is paid
+    invoice: String,
+}
+
 #[serde_as]
 #[derive(Serialize, Deserialize, Clone)]
 pub struct GetPaymentCommandResult {
@@ -187,6 +507,65 @@ pub struct GetPaymentCommandResult {
     #[serde_as(as = "U128Hex")]
     pub last_updated_at: u128,
     pub failed_error: Option<String>,
+    pub failure_reason: Option<PaymentFailureReason>,
+    pub deadline_exceeded: bool,
+    #[serde_as(as = "Option<U128Hex>")]
+    pub fee: Option<u128>,
+    #[serde_as(as = "Option<U64Hex>")]
+    pub fee_ratio_millionths: Option<u64>,
+    pub fee_cap: Option<PaymentFeeCap>,
+    pub rejected_route: Option<RejectedRouteResult>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RejectedRouteResult {
+    #[serde_as(as = "U128Hex")]
+    pub fee: u128,
+    pub reason: RouteRejectReason,
+}
+
+impl From<SendPaymentResponse> for GetPaymentCommandResult {
+    fn from(response: SendPaymentResponse) -> Self {
+        GetPaymentCommandResult {
+            payment_hash: response.payment_hash,
+            status: response.status,
+            created_at: response.created_at,
+            last_updated_at: response.last_updated_at,
+            failed_error: response.failed_error,
+            failure_reason: response.failure_reason,
+            deadline_exceeded: response.deadline_exceeded,
+            fee: response.fee,
+            fee_ratio_millionths: response.fee_ratio_millionths,
+            fee_cap: response.fee_cap,
+            rejected_route: response.rejected_route.map(Into::into),
+        }
+    }
+}
+
+impl From<RejectedRoute> for RejectedRouteResult {
+    fn from(route: RejectedRoute) -> Self {
+        Self {
+            fee: route.fee,
+            reason: route.reason,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct CheckInboundCapacityParams {
+    #[serde_as(as = "U128Hex")]
+    amount: u128,
+    udt_type_script: Option<Script>,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct CheckInboundCapacityResult {
+    can_receive: bool,
+    #[serde_as(as = "U128Hex")]
+    max_receivable_amount: u128,
 }
 
 #[serde_as]
@@ -210,26 +589,290 @@ pub(crate) struct SendPaymentCommandParams {
     // the encoded invoice to send to the recipient
     invoice: Option<String>,
 
-    // the payment timeout in seconds, if the payment is not completed within this time, it will be cancelled
+    // the payment timeout in seconds, if the payment is not completed within this time, it will be cancelled,
+    // the timeout bounds the total time of all the attempts, default is the node's payment_timeout_seconds
+    #[serde_as(as = "Option<U64Hex>")]
+    timeout: Option<u64>,
+
+    // the maximum fee amounts in shannons that the sender is willing to pay
+    #[serde_as(as = "Option<U128Hex>")]
+    max_fee_amount: Option<u128>,
+
+    // max parts for the payment, only used for multi-part payments
+    #[serde_as(as = "Option<U64Hex>")]
+    max_parts: Option<u64>,
+
+    // keysend payment
+    keysend: Option<bool>,
+
+    // udt type script for the payment
+    udt_type_script: Option<Script>,
+
+    // allow self payment, default is false
+    allow_self_payment: Option<bool>,
+
+    // the channel to be used as the first hop of the payment
+    outgoing_channel: Option<Hash256>,
+
+    // probe payment to discover whether the route to the target is viable, default is false
+    probe: Option<bool>,
+
+    // the trampoline node to complete the route to the target
+    trampoline_node: Option<Pubkey>,
+
+    // the outbound balance to be left in the first channel of the payment
+    #[serde_as(as = "Option<U128Hex>")]
+    keep_reserve: Option<u128>,
+
+    // the maximal fee of the route in percentage of the amount, 0 means no limit,
+    // default is the node's payment_max_fee_percent
+    #[serde_as(as = "Option<U64Hex>")]
+    max_fee_percent: Option<u64>,
+
+    // the maximal fee of the route in parts per million of the amount,
+    // the more restrictive one of this and max_fee_amount is applied
+    #[serde_as(as = "Option<U64Hex>")]
+    max_fee_rate_ppm: Option<u64>,
+
+    // who pays the routing fees, default is the one specified by the invoice, or the sender
+    fees_paid_by: Option<FeesPaidBy>,
+}
+
+// The maximal number of payments in a batch.
+const MAX_BATCH_PAYMENTS: usize = 100;
+// The number of payments of a batch which are sent and waited for at the same time.
+const BATCH_PAYMENTS_CONCURRENCY: usize = 8;
+// How long to wait for the payments of a batch to finish, in seconds.
+const DEFAULT_BATCH_PAYMENTS_WAIT_TIMEOUT_SECONDS: u64 = 60;
+const BATCH_PAYMENTS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct BatchSendPaymentParams {
+    // the payments to send, each one is the same as the params of send_payment
+    payments: Vec<SendPaymentCommandParams>,
+    // how long to wait for the payments to finish in seconds, the payments not finished by then
+    // are returned with their current status, default is 60 seconds
+    #[serde_as(as = "Option<U64Hex>")]
+    wait_timeout: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct BatchSendPaymentItem {
+    // the payment, None if it's rejected by send_payment
+    payment: Option<GetPaymentCommandResult>,
+    // the error of send_payment or get_payment
+    error: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct BatchSendPaymentResult {
+    // the results in the same order as the payments in the params
+    payments: Vec<BatchSendPaymentItem>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct GetChannelOpenStatusParams {
+    // the temporary channel id returned by open_channel, or the final channel id
+    channel_id: Hash256,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct GetChannelOpenStatusResult {
+    channel_id: Hash256,
+    step: ChannelOpenStep,
+    state: Option<ChannelState>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct GetChannelFundingAddressParams {
+    // the temporary channel id returned by open_channel, or the final channel id
+    temporary_channel_id: Hash256,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct GetChannelFundingAddressResult {
+    channel_id: Hash256,
+    funding_script: Script,
+    funding_udt_type_script: Option<Script>,
+    #[serde_as(as = "U64Hex")]
+    ckb_amount: u64,
+    #[serde_as(as = "Option<U128Hex>")]
+    udt_amount: Option<u128>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct GetChannelEventsParams {
+    channel_id: Hash256,
+    #[serde_as(as = "Option<U64Hex>")]
+    limit: Option<u64>,
+    // the index of the last event returned by the previous call
+    #[serde_as(as = "Option<U64Hex>")]
+    after: Option<u64>,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct GetChannelEventsResult {
+    events: Vec<ChannelLogEntry>,
+    #[serde_as(as = "Option<U64Hex>")]
+    last_cursor: Option<u64>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ListRejectedTlcsParams {
+    // only list the tlcs rejected from this peer
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    peer_id: Option<PeerId>,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct ListRejectedTlcsResult {
+    rejected_tlcs: Vec<RejectedTlcInfo>,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct RejectedTlcInfo {
+    #[serde_as(as = "DisplayFromStr")]
+    peer_id: PeerId,
+    channel_id: Hash256,
+    #[serde_as(as = "U64Hex")]
+    tlc_id: u64,
+    payment_hash: Hash256,
+    #[serde_as(as = "U128Hex")]
+    amount: u128,
+    error_code: TlcErrorCode,
+    reason: String,
+    #[serde_as(as = "U64Hex")]
+    timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct AuditChannelParams {
+    channel_id: Hash256,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct AuditChannelResult {
+    channel_id: Hash256,
+    state: ChannelState,
+    #[serde_as(as = "U64Hex")]
+    local_commitment_number: u64,
+    #[serde_as(as = "U64Hex")]
+    remote_commitment_number: u64,
+    #[serde_as(as = "U64Hex")]
+    remote_commitment_points: u64,
+    #[serde_as(as = "U64Hex")]
+    revoked_remote_commitments: u64,
+    consistent: bool,
+    issues: Vec<String>,
+    // the time of the audit run on the last commitment update, None if no update is audited yet
+    #[serde_as(as = "Option<U64Hex>")]
+    last_audited_at: Option<u64>,
+    // the inconsistencies found by the audit run on the last commitment update
+    last_audit_issues: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct GetCommitmentTransactionsParams {
+    channel_id: Hash256,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct GetCommitmentTransactionsResult {
+    channel_id: Hash256,
+    state: ChannelState,
+    #[serde_as(as = "U64Hex")]
+    local_commitment_number: u64,
+    #[serde_as(as = "U64Hex")]
+    remote_commitment_number: u64,
+    // the latest commitment transaction signed by both parties, broadcasting it force closes the channel
+    local_commitment_transaction: Option<Transaction>,
+    // the latest commitment transaction signed for the remote party, without witnesses
+    remote_commitment_transaction: Option<Transaction>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ExportChannelStateParams {
+    channel_id: Hash256,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct ExportChannelStateResult {
+    channel_id: Hash256,
+    // the redacted channel state, in the same format as saved in the store
+    channel_state: ChannelActorState,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct GetRebalanceRouteParams {
+    // the channel to send the balance out
+    source_channel_id: Hash256,
+    // the channel to receive the balance back
+    destination_channel_id: Hash256,
+    // the amount to be received through the destination channel
+    #[serde_as(as = "U128Hex")]
+    amount: u128,
+    // the maximal fee of the route, default is no limit
+    #[serde_as(as = "Option<U128Hex>")]
+    max_fee_amount: Option<u128>,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct GetRebalanceRouteResult {
+    #[serde_as(as = "U128Hex")]
+    amount: u128,
+    #[serde_as(as = "U128Hex")]
+    fee: u128,
+    hops: Vec<RebalanceRouteHop>,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct RebalanceRouteHop {
+    target: Pubkey,
+    #[serde_as(as = "EntityHex")]
+    channel_outpoint: OutPoint,
+    #[serde_as(as = "U128Hex")]
+    amount: u128,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ListChannelsNeedingActionParams {
+    // only list the channels with this peer
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    peer_id: Option<PeerId>,
+    // report the pending tlcs which expire within this duration, default is 1 hour
     #[serde_as(as = "Option<U64Hex>")]
-    timeout: Option<u64>,
-
-    // the maximum fee amounts in shannons that the sender is willing to pay
-    #[serde_as(as = "Option<U128Hex>")]
-    max_fee_amount: Option<u128>,
-
-    // max parts for the payment, only used for multi-part payments
+    tlc_expiry_threshold_seconds: Option<u64>,
+    // report the channels stuck in a transitional state longer than this duration, default is 1 hour
     #[serde_as(as = "Option<U64Hex>")]
-    max_parts: Option<u64>,
-
-    // keysend payment
-    keysend: Option<bool>,
+    stuck_state_threshold_seconds: Option<u64>,
+}
 
-    // udt type script for the payment
-    udt_type_script: Option<Script>,
+#[derive(Clone, Serialize)]
+pub(crate) struct ListChannelsNeedingActionResult {
+    channels: Vec<ChannelNeedingAction>,
+}
 
-    // allow self payment, default is false
-    allow_self_payment: Option<bool>,
+#[serde_as]
+#[derive(Clone, Serialize)]
+pub(crate) struct ChannelNeedingAction {
+    #[serde_as(as = "DisplayFromStr")]
+    peer_id: PeerId,
+    channel_id: Hash256,
+    state: ChannelState,
+    reason: ChannelAttentionReason,
 }
 
 #[rpc(server)]
@@ -252,6 +895,24 @@ trait ChannelRpc {
         params: ListChannelsParams,
     ) -> Result<ListChannelsResult, ErrorObjectOwned>;
 
+    #[method(name = "get_peer_channels")]
+    async fn get_peer_channels(
+        &self,
+        params: GetPeerChannelsParams,
+    ) -> Result<GetPeerChannelsResult, ErrorObjectOwned>;
+
+    #[method(name = "list_closed_channels")]
+    async fn list_closed_channels(
+        &self,
+        params: ListClosedChannelsParams,
+    ) -> Result<ListClosedChannelsResult, ErrorObjectOwned>;
+
+    #[method(name = "set_channel_label")]
+    async fn set_channel_label(
+        &self,
+        params: SetChannelLabelParams,
+    ) -> Result<(), ErrorObjectOwned>;
+
     #[method(name = "commitment_signed")]
     async fn commitment_signed(
         &self,
@@ -264,46 +925,287 @@ trait ChannelRpc {
     #[method(name = "remove_tlc")]
     async fn remove_tlc(&self, params: RemoveTlcParams) -> Result<(), ErrorObjectOwned>;
 
+    #[method(name = "fail_forwarded_tlc")]
+    async fn fail_forwarded_tlc(
+        &self,
+        params: FailForwardedTlcParams,
+    ) -> Result<(), ErrorObjectOwned>;
+
     #[method(name = "shutdown_channel")]
     async fn shutdown_channel(&self, params: ShutdownChannelParams)
         -> Result<(), ErrorObjectOwned>;
 
+    #[method(name = "shutdown_channels_with_peer")]
+    async fn shutdown_channels_with_peer(
+        &self,
+        params: ShutdownChannelsWithPeerParams,
+    ) -> Result<ShutdownChannelsWithPeerResult, ErrorObjectOwned>;
+
     #[method(name = "update_channel")]
     async fn update_channel(&self, params: UpdateChannelParams) -> Result<(), ErrorObjectOwned>;
 
+    #[method(name = "get_channel_update")]
+    async fn get_channel_update(
+        &self,
+        params: GetChannelUpdateParams,
+    ) -> Result<GetChannelUpdateResult, ErrorObjectOwned>;
+
+    #[method(name = "rebroadcast_channel_announcement")]
+    async fn rebroadcast_channel_announcement(
+        &self,
+        params: RebroadcastChannelAnnouncementParams,
+    ) -> Result<(), ErrorObjectOwned>;
+
+    #[method(name = "peer_fee_policy")]
+    async fn peer_fee_policy(
+        &self,
+        params: PeerFeePolicyParams,
+    ) -> Result<PeerFeePolicyResult, ErrorObjectOwned>;
+
     #[method(name = "send_payment")]
     async fn send_payment(
         &self,
         params: SendPaymentCommandParams,
     ) -> Result<GetPaymentCommandResult, ErrorObjectOwned>;
 
+    #[method(name = "batch_send_payment")]
+    async fn batch_send_payment(
+        &self,
+        params: BatchSendPaymentParams,
+    ) -> Result<BatchSendPaymentResult, ErrorObjectOwned>;
+
     #[method(name = "get_payment")]
     async fn get_payment(
         &self,
         params: GetPaymentCommandParams,
     ) -> Result<GetPaymentCommandResult, ErrorObjectOwned>;
+
+    #[method(name = "get_payment_by_invoice")]
+    async fn get_payment_by_invoice(
+        &self,
+        params: GetPaymentByInvoiceParams,
+    ) -> Result<GetPaymentCommandResult, ErrorObjectOwned>;
+
+    #[method(name = "list_failed_payments")]
+    async fn list_failed_payments(
+        &self,
+        params: ListFailedPaymentsParams,
+    ) -> Result<ListFailedPaymentsResult, ErrorObjectOwned>;
+
+    #[method(name = "clear_failed_payments")]
+    async fn clear_failed_payments(
+        &self,
+        params: ClearFailedPaymentsParams,
+    ) -> Result<ClearFailedPaymentsResult, ErrorObjectOwned>;
+
+    #[method(name = "check_inbound_capacity")]
+    async fn check_inbound_capacity(
+        &self,
+        params: CheckInboundCapacityParams,
+    ) -> Result<CheckInboundCapacityResult, ErrorObjectOwned>;
+
+    #[method(name = "list_rejected_tlcs")]
+    async fn list_rejected_tlcs(
+        &self,
+        params: ListRejectedTlcsParams,
+    ) -> Result<ListRejectedTlcsResult, ErrorObjectOwned>;
+
+    #[method(name = "get_channel_open_status")]
+    async fn get_channel_open_status(
+        &self,
+        params: GetChannelOpenStatusParams,
+    ) -> Result<GetChannelOpenStatusResult, ErrorObjectOwned>;
+
+    #[method(name = "get_channel_funding_address")]
+    async fn get_channel_funding_address(
+        &self,
+        params: GetChannelFundingAddressParams,
+    ) -> Result<GetChannelFundingAddressResult, ErrorObjectOwned>;
+
+    #[method(name = "get_channel_events")]
+    async fn get_channel_events(
+        &self,
+        params: GetChannelEventsParams,
+    ) -> Result<GetChannelEventsResult, ErrorObjectOwned>;
+
+    #[method(name = "list_channels_needing_action")]
+    async fn list_channels_needing_action(
+        &self,
+        params: ListChannelsNeedingActionParams,
+    ) -> Result<ListChannelsNeedingActionResult, ErrorObjectOwned>;
+
+    #[method(name = "audit_channel")]
+    async fn audit_channel(
+        &self,
+        params: AuditChannelParams,
+    ) -> Result<AuditChannelResult, ErrorObjectOwned>;
+
+    #[method(name = "get_commitment_transactions")]
+    async fn get_commitment_transactions(
+        &self,
+        params: GetCommitmentTransactionsParams,
+    ) -> Result<GetCommitmentTransactionsResult, ErrorObjectOwned>;
+
+    #[method(name = "export_channel_state")]
+    async fn export_channel_state(
+        &self,
+        params: ExportChannelStateParams,
+    ) -> Result<ExportChannelStateResult, ErrorObjectOwned>;
+
+    #[method(name = "get_rebalance_route")]
+    async fn get_rebalance_route(
+        &self,
+        params: GetRebalanceRouteParams,
+    ) -> Result<GetRebalanceRouteResult, ErrorObjectOwned>;
+}
+
+// Reject the scripts which are not standard lock scripts, so that the channel balance
+// won't be locked by an unspendable script accidentally.
+fn check_shutdown_params(
+    close_script: &Script,
+    allow_nonstandard: Option<bool>,
+    fee_rate: u64,
+    max_fee_rate: Option<u64>,
+) -> Result<(), String> {
+    check_shutdown_script(close_script, allow_nonstandard)?;
+    if max_fee_rate.is_some_and(|max_fee_rate| max_fee_rate < fee_rate) {
+        return Err(format!(
+            "max_fee_rate {:?} is less than fee_rate {}",
+            max_fee_rate, fee_rate
+        ));
+    }
+    Ok(())
+}
+
+fn check_shutdown_script(script: &Script, allow_nonstandard: Option<bool>) -> Result<(), String> {
+    if allow_nonstandard.unwrap_or(false) || is_standard_lock_script(&script.clone().into()) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Shutdown script {:?} is not a standard lock script, set allow_nonstandard to true to use it anyway",
+            script
+        ))
+    }
 }
 
 pub(crate) struct ChannelRpcServerImpl<S> {
     actor: ActorRef<NetworkActorMessage>,
     store: S,
+    // The dust tlc limit of the CKB channels which don't set their own limit.
+    default_dust_tlc_limit: DustTlcLimit,
 }
 
 impl<S> ChannelRpcServerImpl<S> {
-    pub(crate) fn new(actor: ActorRef<NetworkActorMessage>, store: S) -> Self {
-        ChannelRpcServerImpl { actor, store }
+    pub(crate) fn new(
+        actor: ActorRef<NetworkActorMessage>,
+        store: S,
+        default_dust_tlc_limit: DustTlcLimit,
+    ) -> Self {
+        ChannelRpcServerImpl {
+            actor,
+            store,
+            default_dust_tlc_limit,
+        }
+    }
+}
+
+impl<S> ChannelRpcServerImpl<S>
+where
+    S: ChannelActorStateStore + NetworkGraphStateStore + Send + Sync + 'static,
+{
+    fn new_channel(
+        &self,
+        peer_id: PeerId,
+        channel_id: Hash256,
+        state: &ChannelActorState,
+        now: u64,
+    ) -> Channel {
+        let dust_tlc_limit = state.get_dust_tlc_limit(self.default_dust_tlc_limit);
+        Channel {
+            channel_id,
+            peer_id,
+            funding_udt_type_script: state.funding_udt_type_script.clone().map(Into::into),
+            state: state.state,
+            local_balance: state.get_local_balance(),
+            remote_balance: state.get_remote_balance(),
+            offered_tlc_balance: state.get_offered_tlc_balance(),
+            received_tlc_balance: state.get_received_tlc_balance(),
+            created_at: state.get_created_at_in_microseconds(),
+            shutdown_fee: state.get_shutdown_fee(),
+            hash_algorithms: state.hash_algorithms.clone(),
+            onion_version: state.get_onion_version(),
+            rebalance_policy: state.rebalance_policy.clone(),
+            max_inbound_tlc_value: state.max_inbound_tlc_value,
+            max_outbound_tlc_value: state.max_outbound_tlc_value,
+            connected_duration: state.connection_stats.total_connected_duration(now),
+            last_disconnected_at: state.connection_stats.last_disconnected_at,
+            disconnection_count: state.connection_stats.disconnection_count,
+            label: self.store.get_channel_label(&channel_id),
+            zero_conf: state.zero_conf,
+            funding_at_risk: state.is_funding_at_risk(),
+            funding_at_risk_amount: state.get_funding_at_risk_amount(),
+            dust_tlc_limit,
+            dust_tlc_exposure: dust_tlc_limit
+                .map_or(0, |limit| state.get_dust_tlc_exposure(&limit)),
+            max_htlc_fraction_of_capacity: state.max_htlc_fraction_of_capacity,
+            max_tlc_value: state.get_max_tlc_value(),
+            features: state.features,
+        }
+    }
+
+    /// Sends a payment of a batch and waits until it's finished or the deadline is reached.
+    async fn send_payment_and_wait(
+        &self,
+        params: SendPaymentCommandParams,
+        deadline: Instant,
+    ) -> BatchSendPaymentItem {
+        let mut payment = match self.send_payment(params).await {
+            Ok(payment) => payment,
+            Err(err) => {
+                return BatchSendPaymentItem {
+                    payment: None,
+                    error: Some(err.message().to_string()),
+                }
+            }
+        };
+        while !payment.status.is_final() && Instant::now() < deadline {
+            sleep(BATCH_PAYMENTS_POLL_INTERVAL).await;
+            match self
+                .get_payment(GetPaymentCommandParams {
+                    payment_hash: payment.payment_hash,
+                })
+                .await
+            {
+                Ok(latest) => payment = latest,
+                Err(err) => {
+                    return BatchSendPaymentItem {
+                        payment: Some(payment),
+                        error: Some(err.message().to_string()),
+                    }
+                }
+            }
+        }
+        BatchSendPaymentItem {
+            payment: Some(payment),
+            error: None,
+        }
     }
 }
 
 #[async_trait]
 impl<S> ChannelRpcServer for ChannelRpcServerImpl<S>
 where
-    S: ChannelActorStateStore + Send + Sync + 'static,
+    S: ChannelActorStateStore + NetworkGraphStateStore + Send + Sync + 'static,
 {
     async fn open_channel(
         &self,
         params: OpenChannelParams,
     ) -> Result<OpenChannelResult, ErrorObjectOwned> {
+        if let Some(shutdown_script) = &params.shutdown_script {
+            if let Err(err) = check_shutdown_script(shutdown_script, params.allow_nonstandard) {
+                return log_and_error!(params, err);
+            }
+        }
         let message = |rpc_reply| {
             NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
                 OpenChannelCommand {
@@ -327,6 +1229,11 @@ where
                     tlc_fee_proportional_millionths: params.tlc_fee_proportional_millionths,
                     max_tlc_value_in_flight: params.max_tlc_value_in_flight,
                     max_tlc_number_in_flight: params.max_tlc_number_in_flight,
+                    max_inbound_tlc_value: params.max_inbound_tlc_value,
+                    max_outbound_tlc_value: params.max_outbound_tlc_value,
+                    external_funding_tx: params.external_funding_tx.clone().map(|tx| tx.into()),
+                    open_timeout_seconds: params.open_timeout_seconds,
+                    zero_conf: params.zero_conf,
                 },
                 rpc_reply,
             ))
@@ -340,12 +1247,20 @@ where
         &self,
         params: AcceptChannelParams,
     ) -> Result<AcceptChannelResult, ErrorObjectOwned> {
+        if let Some(shutdown_script) = &params.shutdown_script {
+            if let Err(err) = check_shutdown_script(shutdown_script, params.allow_nonstandard) {
+                return log_and_error!(params, err);
+            }
+        }
         let message = |rpc_reply| {
             NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
                 AcceptChannelCommand {
                     temp_channel_id: params.temporary_channel_id,
                     funding_amount: params.funding_amount,
                     shutdown_script: params.shutdown_script.clone().map(|s| s.into()),
+                    max_inbound_tlc_value: params.max_inbound_tlc_value,
+                    max_outbound_tlc_value: params.max_outbound_tlc_value,
+                    zero_conf: params.zero_conf,
                 },
                 rpc_reply,
             ))
@@ -360,27 +1275,23 @@ where
         &self,
         params: ListChannelsParams,
     ) -> Result<ListChannelsResult, ErrorObjectOwned> {
-        let mut channels: Vec<_> = self
+        let now = UNIX_EPOCH
+            .elapsed()
+            .expect("duration since epoch")
+            .as_millis() as u64;
+        let (peer_ids, channel_ids): (Vec<_>, Vec<_>) = self
             .store
             .get_active_channel_states(params.peer_id)
             .into_iter()
-            .filter_map(|(peer_id, channel_id, _state)| {
-                self.store
-                    .get_channel_actor_state(&channel_id)
-                    .map(|state| Channel {
-                        channel_id,
-                        peer_id,
-                        funding_udt_type_script: state
-                            .funding_udt_type_script
-                            .clone()
-                            .map(Into::into),
-                        state: state.state,
-                        local_balance: state.get_local_balance(),
-                        remote_balance: state.get_remote_balance(),
-                        offered_tlc_balance: state.get_offered_tlc_balance(),
-                        received_tlc_balance: state.get_received_tlc_balance(),
-                        created_at: state.get_created_at_in_microseconds(),
-                    })
+            .map(|(peer_id, channel_id, _state)| (peer_id, channel_id))
+            .unzip();
+        let states = self.store.get_channel_actor_states(&channel_ids);
+        let mut channels: Vec<_> = peer_ids
+            .into_iter()
+            .zip(channel_ids)
+            .zip(states)
+            .filter_map(|((peer_id, channel_id), state)| {
+                state.map(|state| self.new_channel(peer_id, channel_id, &state, now))
             })
             .collect();
         // Sort by created_at in descending order
@@ -388,6 +1299,111 @@ where
         Ok(ListChannelsResult { channels })
     }
 
+    async fn list_closed_channels(
+        &self,
+        params: ListClosedChannelsParams,
+    ) -> Result<ListClosedChannelsResult, ErrorObjectOwned> {
+        let default_max_limit = 500;
+        let (closed_channels, last_cursor) = self.store.get_closed_channels(
+            params.limit.unwrap_or(default_max_limit) as usize,
+            params.after,
+            params.closed_after,
+            params.closed_before,
+        );
+        Ok(ListClosedChannelsResult {
+            channels: closed_channels.into_iter().map(Into::into).collect(),
+            last_cursor,
+        })
+    }
+
+    async fn get_peer_channels(
+        &self,
+        params: GetPeerChannelsParams,
+    ) -> Result<GetPeerChannelsResult, ErrorObjectOwned> {
+        let now = UNIX_EPOCH
+            .elapsed()
+            .expect("duration since epoch")
+            .as_millis() as u64;
+        let channel_ids = self.store.get_channel_ids_by_peer(&params.peer_id);
+        let mut channels: Vec<_> = self
+            .store
+            .get_channel_actor_states(&channel_ids)
+            .into_iter()
+            .zip(channel_ids)
+            .filter_map(|(state, channel_id)| {
+                state
+                    .filter(|state| !state.is_closed())
+                    .map(|state| self.new_channel(params.peer_id.clone(), channel_id, &state, now))
+            })
+            .collect();
+        channels.sort_by_key(|channel| Reverse(channel.created_at));
+
+        let mut balances: Vec<PeerBalance> = vec![];
+        for channel in &channels {
+            let index = match balances
+                .iter()
+                .position(|b| b.funding_udt_type_script == channel.funding_udt_type_script)
+            {
+                Some(index) => index,
+                None => {
+                    balances.push(PeerBalance {
+                        funding_udt_type_script: channel.funding_udt_type_script.clone(),
+                        channel_count: 0,
+                        local_balance: 0,
+                        remote_balance: 0,
+                        offered_tlc_balance: 0,
+                        received_tlc_balance: 0,
+                    });
+                    balances.len() - 1
+                }
+            };
+            let balance = &mut balances[index];
+            balance.channel_count += 1;
+            balance.local_balance += channel.local_balance;
+            balance.remote_balance += channel.remote_balance;
+            balance.offered_tlc_balance += channel.offered_tlc_balance;
+            balance.received_tlc_balance += channel.received_tlc_balance;
+        }
+        Ok(GetPeerChannelsResult {
+            peer_id: params.peer_id,
+            channels,
+            balances,
+        })
+    }
+
+    async fn set_channel_label(
+        &self,
+        params: SetChannelLabelParams,
+    ) -> Result<(), ErrorObjectOwned> {
+        let max_label_length = 256;
+        if self
+            .store
+            .get_channel_actor_state(&params.channel_id)
+            .is_none()
+        {
+            return log_and_error!(
+                params,
+                format!("Channel not found: {:?}", params.channel_id)
+            );
+        }
+        let label = params
+            .label
+            .as_ref()
+            .map(|label| label.trim().to_string())
+            .filter(|label| !label.is_empty());
+        if label
+            .as_ref()
+            .is_some_and(|label| label.chars().count() > max_label_length)
+        {
+            return log_and_error!(
+                params,
+                format!("Label is longer than {} characters", max_label_length)
+            );
+        }
+        self.store.set_channel_label(&params.channel_id, label);
+        Ok(())
+    }
+
     async fn commitment_signed(
         &self,
         params: CommitmentSignedParams,
@@ -402,6 +1418,19 @@ where
     }
 
     async fn add_tlc(&self, params: AddTlcParams) -> Result<AddTlcResult, ErrorObjectOwned> {
+        if let Some(preimage) = params.preimage {
+            let hash_algorithm = params.hash_algorithm.unwrap_or_default();
+            let payment_hash: Hash256 = hash_algorithm.hash(preimage).into();
+            if payment_hash != params.payment_hash {
+                return log_and_error!(
+                    params,
+                    format!(
+                        "preimage doesn't match the payment hash with hash algorithm {:?}",
+                        hash_algorithm
+                    )
+                );
+            }
+        }
         let message = |rpc_reply| -> NetworkActorMessage {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
                 ChannelCommandWithId {
@@ -409,7 +1438,7 @@ where
                     command: ChannelCommand::AddTlc(
                         AddTlcCommand {
                             amount: params.amount,
-                            preimage: None,
+                            preimage: params.preimage,
                             payment_hash: Some(params.payment_hash),
                             expiry: params.expiry,
                             hash_algorithm: params.hash_algorithm.unwrap_or_default(),
@@ -426,6 +1455,20 @@ where
         })
     }
 
+    async fn fail_forwarded_tlc(
+        &self,
+        params: FailForwardedTlcParams,
+    ) -> Result<(), ErrorObjectOwned> {
+        let message = |rpc_reply| -> NetworkActorMessage {
+            NetworkActorMessage::Command(NetworkActorCommand::FailForwardedTlc(
+                params.channel_id,
+                params.tlc_id,
+                rpc_reply,
+            ))
+        };
+        handle_actor_call!(self.actor, message, params)
+    }
+
     async fn remove_tlc(&self, params: RemoveTlcParams) -> Result<(), ErrorObjectOwned> {
         let err_code = match &params.reason {
             RemoveTlcReason::RemoveTlcFail { error_code } => {
@@ -474,6 +1517,14 @@ where
         &self,
         params: ShutdownChannelParams,
     ) -> Result<(), ErrorObjectOwned> {
+        if let Err(err) = check_shutdown_params(
+            &params.close_script,
+            params.allow_nonstandard,
+            params.fee_rate,
+            params.max_fee_rate,
+        ) {
+            return log_and_error!(params, err);
+        }
         let message = |rpc_reply| -> NetworkActorMessage {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
                 ChannelCommandWithId {
@@ -482,6 +1533,7 @@ where
                         ShutdownCommand {
                             close_script: params.close_script.clone().into(),
                             fee_rate: FeeRate::from_u64(params.fee_rate),
+                            max_fee_rate: params.max_fee_rate.map(FeeRate::from_u64),
                             force: params.force.unwrap_or(false),
                         },
                         rpc_reply,
@@ -492,6 +1544,46 @@ where
         handle_actor_call!(self.actor, message, params)
     }
 
+    async fn shutdown_channels_with_peer(
+        &self,
+        params: ShutdownChannelsWithPeerParams,
+    ) -> Result<ShutdownChannelsWithPeerResult, ErrorObjectOwned> {
+        if let Err(err) = check_shutdown_params(
+            &params.close_script,
+            params.allow_nonstandard,
+            params.fee_rate,
+            params.max_fee_rate,
+        ) {
+            return log_and_error!(params, err);
+        }
+        let mut channels = vec![];
+        for channel_id in self.store.get_active_channel_ids_by_peer(&params.peer_id) {
+            let message = |rpc_reply| -> NetworkActorMessage {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id,
+                        command: ChannelCommand::Shutdown(
+                            ShutdownCommand {
+                                close_script: params.close_script.clone().into(),
+                                fee_rate: FeeRate::from_u64(params.fee_rate),
+                                max_fee_rate: params.max_fee_rate.map(FeeRate::from_u64),
+                                force: params.force.unwrap_or(false),
+                            },
+                            rpc_reply,
+                        ),
+                    },
+                ))
+            };
+            let error = match call!(self.actor, message) {
+                Ok(Ok(())) => None,
+                Ok(Err(err)) => Some(err),
+                Err(err) => Some(err.to_string()),
+            };
+            channels.push(ShutdownChannelResult { channel_id, error });
+        }
+        Ok(ShutdownChannelsWithPeerResult { channels })
+    }
+
     async fn update_channel(&self, params: UpdateChannelParams) -> Result<(), ErrorObjectOwned> {
         let message = |rpc_reply| -> NetworkActorMessage {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -504,6 +1596,9 @@ where
                             tlc_minimum_value: params.tlc_minimum_value,
                             tlc_maximum_value: params.tlc_maximum_value,
                             tlc_fee_proportional_millionths: params.tlc_fee_proportional_millionths,
+                            rebalance_policy: params.rebalance_policy.clone(),
+                            dust_tlc_limit: params.dust_tlc_limit,
+                            max_htlc_fraction_of_capacity: params.max_htlc_fraction_of_capacity,
                         },
                         rpc_reply,
                     ),
@@ -513,6 +1608,107 @@ where
         handle_actor_call!(self.actor, message, params)
     }
 
+    async fn rebroadcast_channel_announcement(
+        &self,
+        params: RebroadcastChannelAnnouncementParams,
+    ) -> Result<(), ErrorObjectOwned> {
+        let message = |rpc_reply| -> NetworkActorMessage {
+            NetworkActorMessage::Command(NetworkActorCommand::RebroadcastChannelAnnouncement(
+                params.channel_id,
+                rpc_reply,
+            ))
+        };
+        handle_actor_call!(self.actor, message, params)
+    }
+
+    async fn get_channel_update(
+        &self,
+        params: GetChannelUpdateParams,
+    ) -> Result<GetChannelUpdateResult, ErrorObjectOwned> {
+        let state = match self.store.get_channel_actor_state(&params.channel_id) {
+            Some(state) if state.funding_tx.is_some() => state,
+            Some(_) => {
+                return log_and_error!(
+                    params,
+                    format!("Channel {:?} is not funded yet", params.channel_id)
+                )
+            }
+            None => {
+                return log_and_error!(params, format!("Channel {:?} not found", params.channel_id))
+            }
+        };
+        let channel_outpoint = state.get_funding_transaction_outpoint();
+        // Our own update is always the latest one signed by us, while the remote update
+        // is only known after it is gossiped to the network graph.
+        let local_update = state
+            .public_channel_info
+            .as_ref()
+            .and_then(|info| info.channel_update.as_ref())
+            .map(|update| ChannelUpdatePolicy::new(state.local_pubkey, update));
+        let remote_update = self
+            .store
+            .get_channels(Some(channel_outpoint.clone()))
+            .into_iter()
+            .next()
+            .and_then(|channel| {
+                [&channel.node1_to_node2, &channel.node2_to_node1]
+                    .into_iter()
+                    .flatten()
+                    .map(|info| &info.last_update_message)
+                    .find(|update| {
+                        let node_id = if update.message_flags & 1 == 0 {
+                            channel.node1()
+                        } else {
+                            channel.node2()
+                        };
+                        node_id == state.remote_pubkey
+                    })
+                    .map(|update| ChannelUpdatePolicy::new(state.remote_pubkey, update))
+            });
+        Ok(GetChannelUpdateResult {
+            channel_outpoint,
+            local_update,
+            remote_update,
+        })
+    }
+
+    async fn peer_fee_policy(
+        &self,
+        params: PeerFeePolicyParams,
+    ) -> Result<PeerFeePolicyResult, ErrorObjectOwned> {
+        let reset = params.reset.unwrap_or_default();
+        let current = if reset {
+            PeerFeePolicy::default()
+        } else {
+            self.store
+                .get_peer_fee_policy(&params.peer_id)
+                .unwrap_or_default()
+        };
+        let policy = PeerFeePolicy {
+            tlc_locktime_expiry_delta: params
+                .tlc_locktime_expiry_delta
+                .or(current.tlc_locktime_expiry_delta),
+            tlc_min_value: params.tlc_minimum_value.or(current.tlc_min_value),
+            tlc_max_value: params.tlc_maximum_value.or(current.tlc_max_value),
+            tlc_fee_proportional_millionths: params
+                .tlc_fee_proportional_millionths
+                .or(current.tlc_fee_proportional_millionths),
+        };
+        if reset || policy != current {
+            // an empty policy is removed, so that the node's config applies again
+            self.store.set_peer_fee_policy(
+                &params.peer_id,
+                (policy != PeerFeePolicy::default()).then(|| policy.clone()),
+            );
+        }
+        Ok(PeerFeePolicyResult {
+            tlc_locktime_expiry_delta: policy.tlc_locktime_expiry_delta,
+            tlc_minimum_value: policy.tlc_min_value,
+            tlc_maximum_value: policy.tlc_max_value,
+            tlc_fee_proportional_millionths: policy.tlc_fee_proportional_millionths,
+        })
+    }
+
     async fn send_payment(
         &self,
         params: SendPaymentCommandParams,
@@ -531,6 +1727,13 @@ where
                     keysend: params.keysend,
                     udt_type_script: params.udt_type_script.clone().map(|s| s.into()),
                     allow_self_payment: params.allow_self_payment.unwrap_or(false),
+                    outgoing_channel: params.outgoing_channel,
+                    probe: params.probe,
+                    trampoline_node: params.trampoline_node,
+                    keep_reserve: params.keep_reserve,
+                    max_fee_percent: params.max_fee_percent,
+                    max_fee_rate_ppm: params.max_fee_rate_ppm,
+                    fees_paid_by: params.fees_paid_by,
                 },
                 rpc_reply,
             ))
@@ -541,9 +1744,43 @@ where
             created_at: response.created_at,
             last_updated_at: response.last_updated_at,
             failed_error: response.failed_error,
+            failure_reason: response.failure_reason,
+            deadline_exceeded: response.deadline_exceeded,
+            fee: response.fee,
+            fee_ratio_millionths: response.fee_ratio_millionths,
+            fee_cap: response.fee_cap,
+            rejected_route: response.rejected_route.map(Into::into),
         })
     }
 
+    async fn batch_send_payment(
+        &self,
+        params: BatchSendPaymentParams,
+    ) -> Result<BatchSendPaymentResult, ErrorObjectOwned> {
+        if params.payments.len() > MAX_BATCH_PAYMENTS {
+            return log_and_error!(
+                params,
+                format!(
+                    "At most {} payments can be sent in a batch",
+                    MAX_BATCH_PAYMENTS
+                )
+            );
+        }
+        let deadline = Instant::now()
+            + Duration::from_secs(
+                params
+                    .wait_timeout
+                    .unwrap_or(DEFAULT_BATCH_PAYMENTS_WAIT_TIMEOUT_SECONDS),
+            );
+        // The payments are independent, some of them may fail while the others succeed.
+        let payments = stream::iter(params.payments)
+            .map(|payment| self.send_payment_and_wait(payment, deadline))
+            .buffered(BATCH_PAYMENTS_CONCURRENCY)
+            .collect()
+            .await;
+        Ok(BatchSendPaymentResult { payments })
+    }
+
     async fn get_payment(
         &self,
         params: GetPaymentCommandParams,
@@ -560,6 +1797,298 @@ where
             last_updated_at: response.last_updated_at,
             created_at: response.created_at,
             failed_error: response.failed_error,
+            failure_reason: response.failure_reason,
+            deadline_exceeded: response.deadline_exceeded,
+            fee: response.fee,
+            fee_ratio_millionths: response.fee_ratio_millionths,
+            fee_cap: response.fee_cap,
+            rejected_route: response.rejected_route.map(Into::into),
+        })
+    }
+
+    async fn get_payment_by_invoice(
+        &self,
+        params: GetPaymentByInvoiceParams,
+    ) -> Result<GetPaymentCommandResult, ErrorObjectOwned> {
+        let invoice = match params.invoice.parse::<CkbInvoice>() {
+            Ok(invoice) => invoice,
+            Err(e) => return log_and_error!(params, format!("Invalid invoice: {}", e)),
+        };
+        self.get_payment(GetPaymentCommandParams {
+            payment_hash: *invoice.payment_hash(),
+        })
+        .await
+    }
+
+    async fn list_failed_payments(
+        &self,
+        params: ListFailedPaymentsParams,
+    ) -> Result<ListFailedPaymentsResult, ErrorObjectOwned> {
+        let default_max_limit = 100;
+        let payments = self
+            .store
+            .get_failed_payment_sessions(params.limit.unwrap_or(default_max_limit) as usize)
+            .into_iter()
+            .map(|session| SendPaymentResponse::from(session).into())
+            .collect();
+        Ok(ListFailedPaymentsResult { payments })
+    }
+
+    async fn clear_failed_payments(
+        &self,
+        params: ClearFailedPaymentsParams,
+    ) -> Result<ClearFailedPaymentsResult, ErrorObjectOwned> {
+        let now = UNIX_EPOCH.elapsed().unwrap().as_millis();
+        let before = now.saturating_sub(params.older_than_seconds as u128 * 1000);
+        let cleared = self.store.clear_failed_payment_sessions(before);
+        Ok(ClearFailedPaymentsResult {
+            cleared: cleared as u64,
+        })
+    }
+
+    async fn check_inbound_capacity(
+        &self,
+        params: CheckInboundCapacityParams,
+    ) -> Result<CheckInboundCapacityResult, ErrorObjectOwned> {
+        // Payments are not split yet, so the whole amount must be received through one channel.
+        let max_receivable_amount = self
+            .store
+            .get_active_channel_states(None)
+            .into_iter()
+            .filter_map(|(_peer_id, channel_id, _state)| {
+                self.store.get_channel_actor_state(&channel_id)
+            })
+            .filter(|state| {
+                state.funding_udt_type_script.clone().map(Script::from) == params.udt_type_script
+            })
+            .map(|state| state.get_max_receivable_amount())
+            .max()
+            .unwrap_or_default();
+        Ok(CheckInboundCapacityResult {
+            can_receive: max_receivable_amount >= params.amount,
+            max_receivable_amount,
+        })
+    }
+
+    async fn list_rejected_tlcs(
+        &self,
+        params: ListRejectedTlcsParams,
+    ) -> Result<ListRejectedTlcsResult, ErrorObjectOwned> {
+        let peer_id = params.peer_id.clone();
+        let message = |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ListRejectedTlcs((), rpc_reply))
+        };
+        handle_actor_call!(self.actor, message, params).map(|response| ListRejectedTlcsResult {
+            rejected_tlcs: response
+                .into_iter()
+                .filter(|tlc| peer_id.as_ref().map_or(true, |id| id == &tlc.peer_id))
+                .map(|tlc| RejectedTlcInfo {
+                    peer_id: tlc.peer_id,
+                    channel_id: tlc.channel_id,
+                    tlc_id: tlc.tlc_id,
+                    payment_hash: tlc.payment_hash,
+                    amount: tlc.amount,
+                    error_code: tlc.error_code,
+                    reason: tlc.reason,
+                    timestamp: tlc.timestamp,
+                })
+                .collect(),
+        })
+    }
+
+    async fn get_channel_open_status(
+        &self,
+        params: GetChannelOpenStatusParams,
+    ) -> Result<GetChannelOpenStatusResult, ErrorObjectOwned> {
+        let message = |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::GetChannelOpenStatus(
+                params.channel_id,
+                rpc_reply,
+            ))
+        };
+        handle_actor_call!(self.actor, message, params).map(|response| GetChannelOpenStatusResult {
+            channel_id: response.channel_id,
+            step: response.step,
+            state: response.state,
+            error: response.error,
+        })
+    }
+
+    async fn get_channel_funding_address(
+        &self,
+        params: GetChannelFundingAddressParams,
+    ) -> Result<GetChannelFundingAddressResult, ErrorObjectOwned> {
+        let message = |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::GetChannelFundingAddress(
+                params.temporary_channel_id,
+                rpc_reply,
+            ))
+        };
+        handle_actor_call!(self.actor, message, params).map(|response| {
+            GetChannelFundingAddressResult {
+                channel_id: response.channel_id,
+                funding_script: response.funding_script.into(),
+                funding_udt_type_script: response.funding_udt_type_script.map(Into::into),
+                ckb_amount: response.ckb_amount,
+                udt_amount: response.udt_amount,
+            }
+        })
+    }
+
+    async fn get_channel_events(
+        &self,
+        params: GetChannelEventsParams,
+    ) -> Result<GetChannelEventsResult, ErrorObjectOwned> {
+        let default_max_limit = 500;
+        let events = self.store.get_channel_events(
+            &params.channel_id,
+            params.after,
+            params.limit.unwrap_or(default_max_limit) as usize,
+        );
+        let last_cursor = events.last().map(|entry| entry.index);
+        Ok(GetChannelEventsResult {
+            events,
+            last_cursor,
+        })
+    }
+
+    async fn list_channels_needing_action(
+        &self,
+        params: ListChannelsNeedingActionParams,
+    ) -> Result<ListChannelsNeedingActionResult, ErrorObjectOwned> {
+        let default_threshold_seconds = 3600;
+        let thresholds = ChannelAttentionThresholds {
+            // The tlc expiries are in blocks, count the blocks with the shortest average block
+            // interval so that no tlc expiring within the duration is missed.
+            tlc_expiry_blocks: params
+                .tlc_expiry_threshold_seconds
+                .unwrap_or(default_threshold_seconds)
+                .saturating_mul(1000)
+                .div_ceil(MIN_AVERAGE_BLOCK_INTERVAL_MS),
+            stuck_state: params
+                .stuck_state_threshold_seconds
+                .unwrap_or(default_threshold_seconds)
+                .saturating_mul(1000),
+        };
+        let peer_id = params.peer_id.clone();
+        let message = |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ListChannelsNeedingAction(
+                thresholds.clone(),
+                rpc_reply,
+            ))
+        };
+        handle_actor_call!(self.actor, message, params).map(|response| {
+            ListChannelsNeedingActionResult {
+                channels: response
+                    .into_iter()
+                    .filter(|channel| peer_id.as_ref().map_or(true, |id| id == &channel.peer_id))
+                    .map(|channel| ChannelNeedingAction {
+                        peer_id: channel.peer_id,
+                        channel_id: channel.channel_id,
+                        state: channel.state,
+                        reason: channel.reason,
+                    })
+                    .collect(),
+            }
+        })
+    }
+
+    async fn audit_channel(
+        &self,
+        params: AuditChannelParams,
+    ) -> Result<AuditChannelResult, ErrorObjectOwned> {
+        let Some(state) = self.store.get_channel_actor_state(&params.channel_id) else {
+            return log_and_error!(
+                params,
+                format!("Channel not found: {:?}", params.channel_id)
+            );
+        };
+        let audit = state.audit();
+        let last_audit = state.last_commitment_audit;
+        Ok(AuditChannelResult {
+            channel_id: params.channel_id,
+            state: state.state,
+            local_commitment_number: audit.commitment_numbers.local,
+            remote_commitment_number: audit.commitment_numbers.remote,
+            remote_commitment_points: audit.remote_commitment_points,
+            revoked_remote_commitments: audit.revoked_remote_commitments,
+            consistent: audit.issues.is_empty(),
+            issues: audit.issues,
+            last_audited_at: last_audit.as_ref().map(|audit| audit.audited_at),
+            last_audit_issues: last_audit.map(|audit| audit.issues).unwrap_or_default(),
+        })
+    }
+
+    async fn get_commitment_transactions(
+        &self,
+        params: GetCommitmentTransactionsParams,
+    ) -> Result<GetCommitmentTransactionsResult, ErrorObjectOwned> {
+        let Some(state) = self.store.get_channel_actor_state(&params.channel_id) else {
+            return log_and_error!(
+                params,
+                format!("Channel not found: {:?}", params.channel_id)
+            );
+        };
+        let commitment_numbers = state.get_current_commitment_numbers();
+        Ok(GetCommitmentTransactionsResult {
+            channel_id: params.channel_id,
+            state: state.state,
+            local_commitment_number: commitment_numbers.local,
+            remote_commitment_number: commitment_numbers.remote,
+            local_commitment_transaction: state
+                .latest_commitment_transaction
+                .clone()
+                .map(Into::into),
+            remote_commitment_transaction: state
+                .latest_remote_commitment_transaction
+                .clone()
+                .map(Into::into),
+        })
+    }
+
+    async fn export_channel_state(
+        &self,
+        params: ExportChannelStateParams,
+    ) -> Result<ExportChannelStateResult, ErrorObjectOwned> {
+        let Some(state) = self.store.get_channel_actor_state(&params.channel_id) else {
+            return log_and_error!(
+                params,
+                format!("Channel not found: {:?}", params.channel_id)
+            );
+        };
+        Ok(ExportChannelStateResult {
+            channel_id: params.channel_id,
+            channel_state: state.redacted(),
+        })
+    }
+
+    async fn get_rebalance_route(
+        &self,
+        params: GetRebalanceRouteParams,
+    ) -> Result<GetRebalanceRouteResult, ErrorObjectOwned> {
+        let message = |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::GetRebalanceRoute(
+                GetRebalanceRouteCommand {
+                    source_channel: params.source_channel_id,
+                    destination_channel: params.destination_channel_id,
+                    amount: params.amount,
+                    max_fee_amount: params.max_fee_amount,
+                },
+                rpc_reply,
+            ))
+        };
+        handle_actor_call!(self.actor, message, params).map(|route| GetRebalanceRouteResult {
+            amount: route.amount,
+            fee: route.fee,
+            hops: route
+                .hops
+                .into_iter()
+                .map(|hop| RebalanceRouteHop {
+                    target: hop.target,
+                    channel_outpoint: hop.channel_outpoint,
+                    amount: hop.amount,
+                })
+                .collect(),
         })
     }
 }
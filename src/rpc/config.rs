@@ -1,4 +1,6 @@
 use clap_serde_derive::ClapSerde;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 #[derive(ClapSerde, Debug, Clone)]
 pub struct RpcConfig {
@@ -6,4 +8,45 @@ pub struct RpcConfig {
     /// listening port for rpc service
     #[arg(name = "RPC_LISTENING_ADDR", long = "rpc-listening-addr", env)]
     pub listening_addr: Option<String>,
+
+    /// listening address for the websocket-only rpc service, which serves the same methods as the rpc service [default: disabled]
+    #[arg(name = "RPC_WS_LISTENING_ADDR", long = "rpc-ws-listening-addr", env)]
+    pub ws_listening_addr: Option<String>,
+
+    /// bearer token required by privileged rpc methods, read-only methods can be called without it over http, websocket connections always require it [default: no auth]
+    #[arg(name = "RPC_AUTH_TOKEN", long = "rpc-auth-token", env)]
+    pub auth_token: Option<String>,
+
+    /// methods allowed on the rpc and websocket listeners (separated by `,`), `@read_only` stands for all the read-only methods [default: all methods]
+    #[arg(name = "RPC_ALLOWED_METHODS", long = "rpc-allowed-methods", env, value_parser, num_args = 0.., value_delimiter = ',')]
+    pub allowed_methods: Vec<String>,
+
+    #[arg(
+        name = "RPC_ADDITIONAL_LISTENERS",
+        long = "rpc-additional-listeners",
+        env,
+        help = "a list of additional rpc listeners, each with its own listening address and method allowlist [default: none]"
+    )]
+    pub additional_listeners: Option<RpcListeners>,
+}
+
+/// An additional rpc listener, which serves only the methods in its allowlist.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RpcListenerConfig {
+    pub listening_addr: String,
+    /// Methods allowed on this listener, `@read_only` stands for all the read-only methods,
+    /// empty means all the methods.
+    #[serde(default)]
+    pub allowed_methods: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RpcListeners(pub Vec<RpcListenerConfig>);
+
+impl FromStr for RpcListeners {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
 }
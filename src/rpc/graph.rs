@@ -1,18 +1,33 @@
 use crate::ckb::config::UdtCfgInfos as ConfigUdtCfgInfos;
-use crate::fiber::graph::{NetworkGraph, NetworkGraphStateStore};
+use crate::fiber::config::{
+    DEFAULT_TLC_FEE_PROPORTIONAL_MILLIONTHS, DEFAULT_TLC_LOCKTIME_EXPIRY_DELTA,
+    DEFAULT_TLC_MAX_VALUE, DEFAULT_TLC_MIN_VALUE,
+};
+use crate::fiber::graph::{
+    ChannelInfo as GraphChannelInfo, NetworkGraph, NetworkGraphStateStore,
+    NodeInfo as GraphNodeInfo,
+};
 use crate::fiber::serde_utils::EntityHex;
 use crate::fiber::serde_utils::{U128Hex, U32Hex, U64Hex};
-use crate::fiber::types::{Hash256, Pubkey};
+use crate::fiber::types::{ChannelAnnouncement, ChannelUpdate, Hash256, Pubkey};
 use ckb_jsonrpc_types::{DepType, JsonBytes, Script, ScriptHashType};
 use ckb_types::packed::OutPoint;
 use ckb_types::H256;
-use jsonrpsee::{core::async_trait, proc_macros::rpc, types::ErrorObjectOwned};
+use jsonrpsee::{
+    core::{async_trait, SubscriptionResult},
+    proc_macros::rpc,
+    types::{error::CALL_EXECUTION_FAILED_CODE, ErrorObjectOwned},
+    PendingSubscriptionSink, SubscriptionMessage,
+};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::sync::Arc;
 use tentacle::multiaddr::MultiAddr;
 use tokio::sync::RwLock;
 
+// The default number of the nodes or channels returned by a page.
+const DEFAULT_PAGE_LIMIT: u64 = 500;
+
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub(crate) struct GraphNodesParams {
@@ -90,6 +105,25 @@ struct NodeInfo {
     #[serde_as(as = "U64Hex")]
     auto_accept_min_ckb_funding_amount: u64,
     udt_cfg_infos: UdtCfgInfos,
+    #[serde_as(as = "U64Hex")]
+    features: u64,
+}
+
+impl From<&GraphNodeInfo> for NodeInfo {
+    fn from(node_info: &GraphNodeInfo) -> Self {
+        NodeInfo {
+            alias: node_info.anouncement_msg.alias.as_str().to_string(),
+            addresses: node_info.anouncement_msg.addresses.clone(),
+            node_id: node_info.node_id,
+            timestamp: node_info.timestamp,
+            chain_hash: node_info.anouncement_msg.chain_hash,
+            udt_cfg_infos: node_info.anouncement_msg.udt_cfg_infos.clone().into(),
+            auto_accept_min_ckb_funding_amount: node_info
+                .anouncement_msg
+                .auto_accept_min_ckb_funding_amount,
+            features: node_info.anouncement_msg.features,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -128,6 +162,31 @@ struct ChannelInfo {
     capacity: u128,
     chain_hash: Hash256,
     udt_type_script: Option<Script>,
+    private: bool,
+}
+
+impl ChannelInfo {
+    fn new(channel_info: &GraphChannelInfo, chain_hash: Hash256) -> Self {
+        ChannelInfo {
+            channel_outpoint: channel_info.out_point(),
+            funding_tx_block_number: channel_info.funding_tx_block_number,
+            funding_tx_index: channel_info.funding_tx_index,
+            node1: channel_info.node1(),
+            node2: channel_info.node2(),
+            capacity: channel_info.capacity(),
+            last_updated_timestamp: channel_info.channel_last_update_time(),
+            created_timestamp: channel_info.timestamp,
+            node1_to_node2_fee_rate: channel_info.node1_to_node2.as_ref().map(|cu| cu.fee_rate),
+            node2_to_node1_fee_rate: channel_info.node2_to_node1.as_ref().map(|cu| cu.fee_rate),
+            chain_hash,
+            udt_type_script: channel_info
+                .announcement_msg
+                .udt_type_script
+                .clone()
+                .map(|s| s.into()),
+            private: channel_info.private,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -136,6 +195,26 @@ pub(crate) struct GraphChannelsResult {
     last_cursor: JsonBytes,
 }
 
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct AddPrivateChannelParams {
+    #[serde_as(as = "EntityHex")]
+    channel_outpoint: OutPoint,
+    node1: Pubkey,
+    node2: Pubkey,
+    #[serde_as(as = "U128Hex")]
+    capacity: u128,
+    udt_type_script: Option<Script>,
+    #[serde_as(as = "Option<U64Hex>")]
+    tlc_locktime_expiry_delta: Option<u64>,
+    #[serde_as(as = "Option<U128Hex>")]
+    tlc_min_value: Option<u128>,
+    #[serde_as(as = "Option<U128Hex>")]
+    tlc_max_value: Option<u128>,
+    #[serde_as(as = "Option<U128Hex>")]
+    tlc_fee_proportional_millionths: Option<u128>,
+}
+
 #[rpc(server)]
 trait GraphRpc {
     #[method(name = "graph_nodes")]
@@ -149,13 +228,25 @@ trait GraphRpc {
         &self,
         params: GraphChannelsParams,
     ) -> Result<GraphChannelsResult, ErrorObjectOwned>;
+
+    #[subscription(name = "subscribe_graph_nodes", unsubscribe = "unsubscribe_graph_nodes", item = GraphNodesResult)]
+    async fn subscribe_graph_nodes(&self, params: GraphNodesParams) -> SubscriptionResult;
+
+    #[subscription(name = "subscribe_graph_channels", unsubscribe = "unsubscribe_graph_channels", item = GraphChannelsResult)]
+    async fn subscribe_graph_channels(&self, params: GraphChannelsParams) -> SubscriptionResult;
+
+    #[method(name = "add_private_channel")]
+    async fn add_private_channel(
+        &self,
+        params: AddPrivateChannelParams,
+    ) -> Result<(), ErrorObjectOwned>;
 }
 
 pub(crate) struct GraphRpcServerImpl<S>
 where
     S: NetworkGraphStateStore,
 {
-    _store: S,
+    store: S,
     network_graph: Arc<RwLock<NetworkGraph<S>>>,
 }
 
@@ -165,7 +256,7 @@ where
 {
     pub(crate) fn new(network_graph: Arc<RwLock<NetworkGraph<S>>>, store: S) -> Self {
         GraphRpcServerImpl {
-            _store: store,
+            store,
             network_graph,
         }
     }
@@ -181,26 +272,12 @@ where
         params: GraphNodesParams,
     ) -> Result<GraphNodesResult, ErrorObjectOwned> {
         let network_graph = self.network_graph.read().await;
-        let default_max_limit = 500;
         let (nodes, last_cursor) = network_graph.get_nodes_with_params(
-            params.limit.unwrap_or(default_max_limit) as usize,
+            params.limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize,
             params.after,
         );
 
-        let nodes = nodes
-            .iter()
-            .map(|node_info| NodeInfo {
-                alias: node_info.anouncement_msg.alias.as_str().to_string(),
-                addresses: node_info.anouncement_msg.addresses.clone(),
-                node_id: node_info.node_id,
-                timestamp: node_info.timestamp,
-                chain_hash: node_info.anouncement_msg.chain_hash,
-                udt_cfg_infos: node_info.anouncement_msg.udt_cfg_infos.clone().into(),
-                auto_accept_min_ckb_funding_amount: node_info
-                    .anouncement_msg
-                    .auto_accept_min_ckb_funding_amount,
-            })
-            .collect();
+        let nodes = nodes.iter().map(Into::into).collect();
         Ok(GraphNodesResult { nodes, last_cursor })
     }
 
@@ -208,38 +285,165 @@ where
         &self,
         params: GraphChannelsParams,
     ) -> Result<GraphChannelsResult, ErrorObjectOwned> {
-        let default_max_limit = 500;
         let network_graph = self.network_graph.read().await;
         let chain_hash = network_graph.chain_hash();
         let (channels, last_cursor) = network_graph.get_channels_with_params(
-            params.limit.unwrap_or(default_max_limit) as usize,
+            params.limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize,
             params.after,
         );
 
         let channels = channels
             .iter()
-            .map(|channel_info| ChannelInfo {
-                channel_outpoint: channel_info.out_point(),
-                funding_tx_block_number: channel_info.funding_tx_block_number,
-                funding_tx_index: channel_info.funding_tx_index,
-                node1: channel_info.node1(),
-                node2: channel_info.node2(),
-                capacity: channel_info.capacity(),
-                last_updated_timestamp: channel_info.channel_last_update_time(),
-                created_timestamp: channel_info.timestamp,
-                node1_to_node2_fee_rate: channel_info.node1_to_node2.as_ref().map(|cu| cu.fee_rate),
-                node2_to_node1_fee_rate: channel_info.node2_to_node1.as_ref().map(|cu| cu.fee_rate),
-                chain_hash,
-                udt_type_script: channel_info
-                    .announcement_msg
-                    .udt_type_script
-                    .clone()
-                    .map(|s| s.into()),
-            })
+            .map(|channel_info| ChannelInfo::new(channel_info, chain_hash))
             .collect();
         Ok(GraphChannelsResult {
             channels,
             last_cursor,
         })
     }
+
+    // The nodes are read from the store page by page, so that the whole graph is never loaded
+    // into memory at once. The stream ends with a page of fewer than `limit` nodes.
+    async fn subscribe_graph_nodes(
+        &self,
+        pending: PendingSubscriptionSink,
+        params: GraphNodesParams,
+    ) -> SubscriptionResult {
+        let sink = pending.accept().await?;
+        let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).max(1) as usize;
+        let mut after = params.after;
+        loop {
+            let (nodes, last_cursor) = self.store.get_nodes_with_params(limit, after, None);
+            let done = nodes.len() < limit;
+            let page = GraphNodesResult {
+                nodes: nodes.iter().map(Into::into).collect(),
+                last_cursor: last_cursor.clone(),
+            };
+            sink.send(SubscriptionMessage::from_json(&page)?).await?;
+            if done {
+                return Ok(());
+            }
+            after = Some(last_cursor);
+        }
+    }
+
+    // Same as `subscribe_graph_nodes`, the channels are read from the store page by page.
+    async fn subscribe_graph_channels(
+        &self,
+        pending: PendingSubscriptionSink,
+        params: GraphChannelsParams,
+    ) -> SubscriptionResult {
+        let sink = pending.accept().await?;
+        let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).max(1) as usize;
+        let chain_hash = self.network_graph.read().await.chain_hash();
+        let mut after = params.after;
+        loop {
+            let (channels, last_cursor) = self.store.get_channels_with_params(limit, after, None);
+            let done = channels.len() < limit;
+            let page = GraphChannelsResult {
+                channels: channels
+                    .iter()
+                    .map(|channel_info| ChannelInfo::new(channel_info, chain_hash))
+                    .collect(),
+                last_cursor: last_cursor.clone(),
+            };
+            sink.send(SubscriptionMessage::from_json(&page)?).await?;
+            if done {
+                return Ok(());
+            }
+            after = Some(last_cursor);
+        }
+    }
+
+    async fn add_private_channel(
+        &self,
+        params: AddPrivateChannelParams,
+    ) -> Result<(), ErrorObjectOwned> {
+        let mut network_graph = self.network_graph.write().await;
+        let chain_hash = network_graph.chain_hash();
+        // There is no funding key known for a private channel,
+        // the announcement is never signed nor gossiped.
+        let ckb_key = params.node1.0.x_only_public_key().0;
+        let announcement = ChannelAnnouncement::new_unsigned(
+            &params.node1,
+            &params.node2,
+            params.channel_outpoint.clone(),
+            chain_hash,
+            &ckb_key,
+            params.capacity,
+            params.udt_type_script.clone().map(Into::into),
+        );
+        let update = ChannelUpdate::new_unsigned(
+            chain_hash,
+            params.channel_outpoint.clone(),
+            std::time::UNIX_EPOCH.elapsed().unwrap().as_millis() as u64,
+            0,
+            0,
+            params
+                .tlc_locktime_expiry_delta
+                .unwrap_or(DEFAULT_TLC_LOCKTIME_EXPIRY_DELTA),
+            params.tlc_min_value.unwrap_or(DEFAULT_TLC_MIN_VALUE),
+            params.tlc_max_value.unwrap_or(DEFAULT_TLC_MAX_VALUE),
+            params
+                .tlc_fee_proportional_millionths
+                .unwrap_or(DEFAULT_TLC_FEE_PROPORTIONAL_MILLIONTHS),
+        );
+        network_graph
+            .add_private_channel(announcement, update)
+            .map_err(|err| {
+                ErrorObjectOwned::owned(CALL_EXECUTION_FAILED_CODE, err.to_string(), Some(params))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fiber::config::AnnouncedNodeName;
+    use crate::fiber::types::NodeAnnouncement;
+    use crate::store::Store;
+    use secp256k1::{Keypair, PublicKey, Secp256k1};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    fn gen_rand_pubkey() -> Pubkey {
+        let secp = Secp256k1::new();
+        let key_pair = Keypair::new(&secp, &mut rand::thread_rng());
+        PublicKey::from_keypair(&key_pair).into()
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_graph_nodes() {
+        let dir = tempdir().unwrap();
+        let store = Store::new(dir.path().join("store"));
+        for _ in 0..5 {
+            let node_id = gen_rand_pubkey();
+            store.insert_node(GraphNodeInfo {
+                node_id,
+                anouncement_msg: NodeAnnouncement::new_unsigned(
+                    AnnouncedNodeName::from_str("node").expect("valid name"),
+                    vec![],
+                    node_id,
+                    1,
+                    0,
+                ),
+                timestamp: 0,
+            });
+        }
+        let network_graph = Arc::new(RwLock::new(NetworkGraph::new(
+            store.clone(),
+            gen_rand_pubkey(),
+        )));
+        let module = GraphRpcServerImpl::new(network_graph, store).into_rpc();
+
+        let mut sub = module
+            .subscribe_unbounded("subscribe_graph_nodes", [json!({ "limit": "0x2" })])
+            .await
+            .unwrap();
+        let mut page_sizes = vec![];
+        while let Some(page) = sub.next::<GraphNodesResult>().await {
+            page_sizes.push(page.unwrap().0.nodes.len());
+        }
+        assert_eq!(page_sizes, vec![2, 2, 1]);
+    }
 }
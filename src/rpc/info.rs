@@ -1,6 +1,7 @@
 use crate::fiber::serde_utils::U32Hex;
 use crate::fiber::{
     channel::ChannelActorStateStore,
+    config::GraphSyncPeerStrategy,
     serde_utils::{U128Hex, U64Hex},
     types::{Hash256, Pubkey},
     NetworkActorCommand, NetworkActorMessage,
@@ -45,12 +46,58 @@ pub(crate) struct NodeInfoResult {
     channel_count: u32,
     #[serde_as(as = "U32Hex")]
     pending_channel_count: u32,
+    #[serde_as(as = "U128Hex")]
+    funding_at_risk_amount: u128,
     #[serde_as(as = "U32Hex")]
     peers_count: u32,
     network_sync_status: String,
     udt_cfg_infos: UdtCfgInfos,
 }
 
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct GraphSyncStatusResult {
+    status: String,
+    strategy: GraphSyncPeerStrategy,
+    #[serde_as(as = "U64Hex")]
+    progress: u64,
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    queried_peers: Vec<PeerId>,
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    syncing_peers: Vec<PeerId>,
+    #[serde_as(as = "U32Hex")]
+    succeeded_count: u32,
+    #[serde_as(as = "U32Hex")]
+    failed_count: u32,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct HealthResult {
+    ready_for_payments: bool,
+    #[serde_as(as = "U32Hex")]
+    peers_count: u32,
+    #[serde_as(as = "U64Hex")]
+    graph_sync_progress: u64,
+    #[serde_as(as = "U32Hex")]
+    inbound_peers_count: u32,
+    #[serde_as(as = "U32Hex")]
+    outbound_peers_count: u32,
+    #[serde_as(as = "U64Hex")]
+    rejected_connections_count: u64,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct SuggestFeeRatesResult {
+    #[serde_as(as = "U64Hex")]
+    low: u64,
+    #[serde_as(as = "U64Hex")]
+    medium: u64,
+    #[serde_as(as = "U64Hex")]
+    high: u64,
+}
+
 pub(crate) struct InfoRpcServerImpl<S> {
     actor: ActorRef<NetworkActorMessage>,
     _store: S,
@@ -66,6 +113,15 @@ impl<S> InfoRpcServerImpl<S> {
 trait InfoRpc {
     #[method(name = "node_info")]
     async fn node_info(&self) -> Result<NodeInfoResult, ErrorObjectOwned>;
+
+    #[method(name = "suggest_fee_rates")]
+    async fn suggest_fee_rates(&self) -> Result<SuggestFeeRatesResult, ErrorObjectOwned>;
+
+    #[method(name = "graph_sync_status")]
+    async fn graph_sync_status(&self) -> Result<GraphSyncStatusResult, ErrorObjectOwned>;
+
+    #[method(name = "health")]
+    async fn health(&self) -> Result<HealthResult, ErrorObjectOwned>;
 }
 
 #[async_trait]
@@ -97,9 +153,52 @@ where
             tlc_fee_proportional_millionths: response.tlc_fee_proportional_millionths,
             channel_count: response.channel_count,
             pending_channel_count: response.pending_channel_count,
+            funding_at_risk_amount: response.funding_at_risk_amount,
             peers_count: response.peers_count,
             network_sync_status: response.network_sync_status,
             udt_cfg_infos: response.udt_cfg_infos.into(),
         })
     }
+
+    async fn suggest_fee_rates(&self) -> Result<SuggestFeeRatesResult, ErrorObjectOwned> {
+        let message = |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::SuggestFeeRates((), rpc_reply))
+        };
+
+        handle_actor_call!(self.actor, message, ()).map(|response| SuggestFeeRatesResult {
+            low: response.low,
+            medium: response.medium,
+            high: response.high,
+        })
+    }
+
+    async fn graph_sync_status(&self) -> Result<GraphSyncStatusResult, ErrorObjectOwned> {
+        let message = |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::GraphSyncStatus((), rpc_reply))
+        };
+
+        handle_actor_call!(self.actor, message, ()).map(|response| GraphSyncStatusResult {
+            status: response.status,
+            strategy: response.strategy,
+            progress: response.progress,
+            queried_peers: response.queried_peers,
+            syncing_peers: response.syncing_peers,
+            succeeded_count: response.succeeded as u32,
+            failed_count: response.failed as u32,
+        })
+    }
+
+    async fn health(&self) -> Result<HealthResult, ErrorObjectOwned> {
+        let message =
+            |rpc_reply| NetworkActorMessage::Command(NetworkActorCommand::Health((), rpc_reply));
+
+        handle_actor_call!(self.actor, message, ()).map(|response| HealthResult {
+            ready_for_payments: response.ready_for_payments,
+            peers_count: response.peers_count,
+            graph_sync_progress: response.graph_sync_progress,
+            inbound_peers_count: response.inbound_peers_count,
+            outbound_peers_count: response.outbound_peers_count,
+            rejected_connections_count: response.rejected_connections_count,
+        })
+    }
 }
@@ -1,8 +1,9 @@
+use crate::fiber::config::{DEFAULT_INVOICE_EXPIRY_SECONDS, DEFAULT_MAX_INVOICE_EXPIRY_SECONDS};
 use crate::fiber::graph::{NetworkGraphStateStore, PaymentSessionStatus};
 use crate::fiber::hash_algorithm::HashAlgorithm;
 use crate::fiber::serde_utils::{U128Hex, U64Hex};
-use crate::fiber::types::{Hash256, Privkey};
-use crate::invoice::{CkbInvoice, Currency, InvoiceBuilder, InvoiceStore};
+use crate::fiber::types::{Hash256, Privkey, Pubkey};
+use crate::invoice::{CkbInvoice, Currency, FeesPaidBy, InvoiceBuilder, InvoiceStore};
 use crate::FiberConfig;
 use ckb_jsonrpc_types::Script;
 use jsonrpsee::types::error::CALL_EXECUTION_FAILED_CODE;
@@ -16,8 +17,14 @@ use tentacle::secio::SecioKeyPair;
 #[serde_as]
 #[derive(Serialize, Deserialize)]
 pub(crate) struct NewInvoiceParams {
-    #[serde_as(as = "U128Hex")]
-    amount: u128,
+    #[serde_as(as = "Option<U128Hex>")]
+    amount: Option<u128>,
+    // the invoice accepts any amount within [min_amount, max_amount] instead of an exact amount,
+    // the amount should not be set if any of them is set
+    #[serde_as(as = "Option<U128Hex>")]
+    min_amount: Option<u128>,
+    #[serde_as(as = "Option<U128Hex>")]
+    max_amount: Option<u128>,
     description: Option<String>,
     currency: Currency,
     payment_preimage: Hash256,
@@ -30,6 +37,12 @@ pub(crate) struct NewInvoiceParams {
     final_htlc_timeout: Option<u64>,
     udt_type_script: Option<Script>,
     hash_algorithm: Option<HashAlgorithm>,
+    // who pays the routing fees, the amount is gross and min_amount is the minimal amount
+    // net of the fees if they are paid by the receiver, default is the sender
+    fees_paid_by: Option<FeesPaidBy>,
+    // a token chosen by the client to retry the request safely, the invoice created by the first
+    // request with the token is returned for the retried requests
+    client_token: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -43,9 +56,33 @@ pub(crate) struct ParseInvoiceParams {
     invoice: String,
 }
 
+#[serde_as]
 #[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct ParseInvoiceResult {
     invoice: CkbInvoice,
+    // the acceptable amount range of the invoice, None if the bound is not set
+    #[serde_as(as = "Option<U128Hex>")]
+    min_amount: Option<u128>,
+    #[serde_as(as = "Option<U128Hex>")]
+    max_amount: Option<u128>,
+    // who pays the routing fees of the invoice
+    fees_paid_by: FeesPaidBy,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct VerifyInvoiceParams {
+    invoice: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct VerifyInvoiceResult {
+    invoice: CkbInvoice,
+    signed: bool,
+    // the payee public key recovered from the signature, None if the invoice is not signed
+    payee_pub_key: Option<Pubkey>,
+    // whether the recovered public key matches the payee public key included in the invoice,
+    // None if the invoice is not signed or doesn't include the payee public key
+    payee_pub_key_matched: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -68,6 +105,38 @@ pub(crate) struct GetInvoiceResult {
     status: InvoiceStatus,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct HasPreimageParams {
+    payment_hash: Hash256,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct HasPreimageResult {
+    has_preimage: bool,
+    // the timestamp in milliseconds when a received payment with the payment hash is settled
+    #[serde_as(as = "Option<U64Hex>")]
+    settled_at: Option<u64>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ListInvoicesParams {
+    // only the invoices denominated in this UDT are returned, the native CKB invoices are
+    // returned if it's not specified
+    udt_type_script: Option<Script>,
+    #[serde_as(as = "Option<U64Hex>")]
+    limit: Option<u64>,
+    // the payment hash of the last invoice returned by the previous call
+    after: Option<Hash256>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ListInvoicesResult {
+    invoices: Vec<InvoiceResult>,
+    last_cursor: Option<Hash256>,
+}
+
 #[rpc(server)]
 trait InvoiceRpc {
     #[method(name = "new_invoice")]
@@ -82,20 +151,47 @@ trait InvoiceRpc {
         params: ParseInvoiceParams,
     ) -> Result<ParseInvoiceResult, ErrorObjectOwned>;
 
+    #[method(name = "verify_invoice")]
+    async fn verify_invoice(
+        &self,
+        params: VerifyInvoiceParams,
+    ) -> Result<VerifyInvoiceResult, ErrorObjectOwned>;
+
     #[method(name = "get_invoice")]
     async fn get_invoice(
         &self,
         payment_hash: GetInvoiceParams,
     ) -> Result<GetInvoiceResult, ErrorObjectOwned>;
+
+    #[method(name = "list_invoices")]
+    async fn list_invoices(
+        &self,
+        params: ListInvoicesParams,
+    ) -> Result<ListInvoicesResult, ErrorObjectOwned>;
+
+    #[method(name = "has_preimage")]
+    async fn has_preimage(
+        &self,
+        params: HasPreimageParams,
+    ) -> Result<HasPreimageResult, ErrorObjectOwned>;
 }
 
 pub(crate) struct InvoiceRpcServerImpl<S> {
     store: S,
     keypair: Option<(PublicKey, SecretKey)>,
+    default_expiry: u64,
+    max_expiry: u64,
 }
 
 impl<S> InvoiceRpcServerImpl<S> {
     pub(crate) fn new(store: S, config: Option<FiberConfig>) -> Self {
+        let (default_expiry, max_expiry) = config.as_ref().map_or(
+            (
+                DEFAULT_INVOICE_EXPIRY_SECONDS,
+                DEFAULT_MAX_INVOICE_EXPIRY_SECONDS,
+            ),
+            |config| (config.default_invoice_expiry(), config.max_invoice_expiry()),
+        );
         let keypair = config.map(|config| {
             let kp = config
                 .read_or_generate_secret_key()
@@ -110,7 +206,12 @@ impl<S> InvoiceRpcServerImpl<S> {
             );
             keypair
         });
-        Self { store, keypair }
+        Self {
+            store,
+            keypair,
+            default_expiry,
+            max_expiry,
+        }
     }
 }
 
@@ -123,16 +224,33 @@ where
         &self,
         params: NewInvoiceParams,
     ) -> Result<InvoiceResult, ErrorObjectOwned> {
+        if let Some(invoice) = params
+            .client_token
+            .as_ref()
+            .and_then(|client_token| self.store.get_invoice_by_client_token(client_token))
+        {
+            return Ok(InvoiceResult {
+                invoice_address: invoice.to_string(),
+                invoice,
+            });
+        }
         let mut invoice_builder = InvoiceBuilder::new(params.currency)
-            .amount(Some(params.amount))
+            .amount(params.amount)
             .payment_preimage(params.payment_preimage);
+        if let Some(min_amount) = params.min_amount {
+            invoice_builder = invoice_builder.min_amount(min_amount);
+        };
+        if let Some(max_amount) = params.max_amount {
+            invoice_builder = invoice_builder.max_amount(max_amount);
+        };
         if let Some(description) = params.description.clone() {
             invoice_builder = invoice_builder.description(description);
         };
-        if let Some(expiry) = params.expiry {
-            let duration: Duration = Duration::from_secs(expiry);
-            invoice_builder = invoice_builder.expiry_time(duration);
-        };
+        let expiry = params.expiry.unwrap_or(self.default_expiry);
+        invoice_builder = invoice_builder.expiry_time(Duration::from_secs(expiry));
+        if self.max_expiry > 0 {
+            invoice_builder = invoice_builder.max_expiry_time(Duration::from_secs(self.max_expiry));
+        }
         if let Some(fallback_address) = params.fallback_address.clone() {
             invoice_builder = invoice_builder.fallback_address(fallback_address);
         };
@@ -145,6 +263,9 @@ where
         if let Some(hash_algorithm) = params.hash_algorithm {
             invoice_builder = invoice_builder.hash_algorithm(hash_algorithm);
         };
+        if let Some(fees_paid_by) = params.fees_paid_by {
+            invoice_builder = invoice_builder.fees_paid_by(fees_paid_by);
+        };
 
         let invoice = if let Some((public_key, secret_key)) = &self.keypair {
             invoice_builder = invoice_builder.payee_pub_key(public_key.clone());
@@ -154,44 +275,50 @@ where
             invoice_builder.build()
         };
 
-        match invoice {
-            Ok(invoice) => match self
+        let invoice = invoice?;
+        match &params.client_token {
+            Some(client_token) => self.store.insert_invoice_with_client_token(
+                invoice.clone(),
+                Some(params.payment_preimage),
+                client_token,
+            )?,
+            None => self
                 .store
-                .insert_invoice(invoice.clone(), Some(params.payment_preimage))
-            {
-                Ok(_) => Ok(InvoiceResult {
-                    invoice_address: invoice.to_string(),
-                    invoice,
-                }),
-                Err(e) => {
-                    return Err(ErrorObjectOwned::owned(
-                        CALL_EXECUTION_FAILED_CODE,
-                        e.to_string(),
-                        Some(params),
-                    ))
-                }
-            },
-            Err(e) => Err(ErrorObjectOwned::owned(
-                CALL_EXECUTION_FAILED_CODE,
-                e.to_string(),
-                Some(params),
-            )),
+                .insert_invoice(invoice.clone(), Some(params.payment_preimage))?,
         }
+        Ok(InvoiceResult {
+            invoice_address: invoice.to_string(),
+            invoice,
+        })
     }
 
     async fn parse_invoice(
         &self,
         params: ParseInvoiceParams,
     ) -> Result<ParseInvoiceResult, ErrorObjectOwned> {
-        let result: Result<CkbInvoice, _> = params.invoice.parse();
-        match result {
-            Ok(invoice) => Ok(ParseInvoiceResult { invoice }),
-            Err(e) => Err(ErrorObjectOwned::owned(
-                CALL_EXECUTION_FAILED_CODE,
-                e.to_string(),
-                Some(params),
-            )),
-        }
+        let invoice: CkbInvoice = params.invoice.parse()?;
+        Ok(ParseInvoiceResult {
+            min_amount: invoice.min_amount().copied(),
+            max_amount: invoice.max_amount().copied(),
+            fees_paid_by: invoice.fees_paid_by().copied().unwrap_or_default(),
+            invoice,
+        })
+    }
+
+    async fn verify_invoice(
+        &self,
+        params: VerifyInvoiceParams,
+    ) -> Result<VerifyInvoiceResult, ErrorObjectOwned> {
+        // The signature is verified below, a mismatched payee public key is reported
+        // instead of failing the parsing.
+        let invoice = CkbInvoice::from_str_unchecked(&params.invoice)?;
+        let verified = invoice.verify_signature()?;
+        Ok(VerifyInvoiceResult {
+            signed: verified.is_some(),
+            payee_pub_key: verified.map(|(pub_key, _)| pub_key.into()),
+            payee_pub_key_matched: verified.and_then(|(_, matched)| matched),
+            invoice,
+        })
     }
 
     async fn get_invoice(
@@ -228,4 +355,40 @@ where
             )),
         }
     }
+    async fn list_invoices(
+        &self,
+        params: ListInvoicesParams,
+    ) -> Result<ListInvoicesResult, ErrorObjectOwned> {
+        let default_max_limit = 500;
+        let udt_type_script = params.udt_type_script.map(Into::into);
+        let invoices = self.store.get_invoices_by_udt_type(
+            udt_type_script.as_ref(),
+            params.limit.unwrap_or(default_max_limit) as usize,
+            params.after,
+        );
+        let last_cursor = invoices.last().map(|invoice| *invoice.payment_hash());
+        Ok(ListInvoicesResult {
+            invoices: invoices
+                .into_iter()
+                .map(|invoice| InvoiceResult {
+                    invoice_address: invoice.to_string(),
+                    invoice,
+                })
+                .collect(),
+            last_cursor,
+        })
+    }
+
+    async fn has_preimage(
+        &self,
+        params: HasPreimageParams,
+    ) -> Result<HasPreimageResult, ErrorObjectOwned> {
+        Ok(HasPreimageResult {
+            has_preimage: self
+                .store
+                .get_invoice_preimage(&params.payment_hash)
+                .is_some(),
+            settled_at: self.store.get_invoice_settled_at(&params.payment_hash),
+        })
+    }
 }
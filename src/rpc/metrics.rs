@@ -0,0 +1,65 @@
+use jsonrpsee::{server::middleware::rpc::RpcServiceT, types::Request, MethodResponse};
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::Instant,
+};
+use tower::Layer;
+
+use crate::metrics::{metrics, RpcMethodStats};
+
+/// Rpc middleware which counts the calls of each method and records their latencies.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct RpcMetricsLayer;
+
+impl<S> Layer<S> for RpcMetricsLayer {
+    type Service = RpcMetrics<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        RpcMetrics { service }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RpcMetrics<S> {
+    service: S,
+}
+
+impl<'a, S> RpcServiceT<'a> for RpcMetrics<S>
+where
+    S: RpcServiceT<'a>,
+{
+    type Future = RpcMetricsFuture<S::Future>;
+
+    fn call(&self, request: Request<'a>) -> Self::Future {
+        let stats = metrics().record_rpc_call(request.method_name());
+        RpcMetricsFuture {
+            future: self.service.call(request),
+            stats,
+            started_at: Instant::now(),
+        }
+    }
+}
+
+/// Records the latency of an rpc call when its response is ready.
+#[pin_project]
+pub(crate) struct RpcMetricsFuture<F> {
+    #[pin]
+    future: F,
+    stats: Arc<RpcMethodStats>,
+    started_at: Instant,
+}
+
+impl<F: Future<Output = MethodResponse>> Future for RpcMetricsFuture<F> {
+    type Output = MethodResponse;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let response = ready!(this.future.poll(cx));
+        this.stats.record_latency(this.started_at.elapsed());
+        Poll::Ready(response)
+    }
+}
@@ -1,9 +1,12 @@
+mod allowlist;
+mod auth;
 mod cch;
 mod channel;
 mod config;
 mod graph;
 mod info;
 mod invoice;
+mod metrics;
 mod peer;
 mod utils;
 
@@ -11,28 +14,74 @@ use crate::rpc::info::InfoRpcServer;
 use crate::{
     cch::CchMessage,
     fiber::{
-        channel::ChannelActorStateStore,
+        channel::{ChannelActorStateStore, DustTlcLimit},
+        config::{DEFAULT_DUST_TLC_THRESHOLD, DEFAULT_MAX_DUST_TLC_EXPOSURE},
         graph::{NetworkGraph, NetworkGraphStateStore},
         NetworkActorMessage,
     },
     invoice::{InvoiceCommand, InvoiceStore},
+    tasks::{new_tokio_cancellation_token, new_tokio_task_tracker},
     FiberConfig,
 };
+use allowlist::{resolve_allowed_methods, RpcAllowlistLayer};
+use auth::RpcAuthLayer;
 use cch::{CchRpcServer, CchRpcServerImpl};
 use channel::{ChannelRpcServer, ChannelRpcServerImpl};
 pub use config::RpcConfig;
 use graph::{GraphRpcServer, GraphRpcServerImpl};
 use info::InfoRpcServerImpl;
 use invoice::{InvoiceRpcServer, InvoiceRpcServerImpl};
-use jsonrpsee::server::{Server, ServerHandle};
+use jsonrpsee::server::{middleware::rpc::RpcServiceBuilder, PingConfig, Server, ServerHandle};
+use jsonrpsee::Methods;
+use metrics::RpcMetricsLayer;
 use peer::{PeerRpcServer, PeerRpcServerImpl};
 use ractor::ActorRef;
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc, time::Duration};
 use tokio::sync::{mpsc::Sender, RwLock};
+use tower::layer::util::{Identity, Stack};
+use tracing::{debug, info, warn};
 
 pub type InvoiceCommandWithReply = (InvoiceCommand, Sender<crate::Result<String>>);
 
-fn build_server(addr: &str) -> Server {
+type RpcServer = Server<
+    Stack<RpcAuthLayer, Identity>,
+    Stack<RpcMetricsLayer, Stack<RpcAllowlistLayer, Identity>>,
+>;
+
+// The number of messages buffered for each websocket connection, the server stops reading
+// new requests from a client which doesn't read its responses and subscription notifications.
+const WS_MESSAGE_BUFFER_CAPACITY: u32 = 1024;
+
+// Ping the websocket clients periodically, and close the connections to the clients
+// which have been inactive for too long, e.g. disconnected without closing the connection.
+const WS_PING_INTERVAL: Duration = Duration::from_secs(30);
+const WS_INACTIVE_LIMIT: Duration = Duration::from_secs(90);
+
+fn build_server(
+    addr: &str,
+    auth_token: Option<String>,
+    allowed_methods: Option<Arc<HashSet<String>>>,
+    ws_only: bool,
+) -> RpcServer {
+    let http_middleware = tower::ServiceBuilder::new().layer(RpcAuthLayer::new(auth_token));
+    // The allowlist is checked before the metrics layer, so rejected calls are not counted.
+    let rpc_middleware = RpcServiceBuilder::new()
+        .layer(RpcAllowlistLayer::new(allowed_methods))
+        .layer(RpcMetricsLayer);
+    let mut builder = Server::builder();
+    if ws_only {
+        builder = builder
+            .ws_only()
+            .set_message_buffer_capacity(WS_MESSAGE_BUFFER_CAPACITY)
+            .enable_ws_ping(
+                PingConfig::new()
+                    .ping_interval(WS_PING_INTERVAL)
+                    .inactive_limit(WS_INACTIVE_LIMIT),
+            );
+    }
+    let builder = builder
+        .set_http_middleware(http_middleware)
+        .set_rpc_middleware(rpc_middleware);
     #[cfg(not(release))]
     {
         // Use socket2 to set reuse address and reuse port,
@@ -49,13 +98,11 @@ fn build_server(addr: &str) -> Server {
         socket.bind(&addr.into()).unwrap();
         socket.listen(4096).unwrap();
 
-        jsonrpsee::server::Server::builder()
-            .build_from_tcp(socket)
-            .unwrap()
+        builder.build_from_tcp(socket).unwrap()
     }
     #[cfg(release)]
     {
-        Server::builder().build(addr).await.unwrap()
+        builder.build(addr).await.unwrap()
     }
 }
 
@@ -70,12 +117,29 @@ pub async fn start_rpc<
     network_graph: Arc<RwLock<NetworkGraph<S>>>,
 ) -> ServerHandle {
     let listening_addr = config.listening_addr.as_deref().unwrap_or("[::]:0");
-    let server = build_server(listening_addr);
+    let allowed_methods = resolve_allowed_methods(&config.allowed_methods);
+    let server = build_server(
+        listening_addr,
+        config.auth_token.clone(),
+        allowed_methods.clone(),
+        false,
+    );
+    let default_dust_tlc_limit = fiber_config.as_ref().map_or(
+        DustTlcLimit {
+            threshold: DEFAULT_DUST_TLC_THRESHOLD,
+            max_exposure: DEFAULT_MAX_DUST_TLC_EXPOSURE,
+        },
+        |config| DustTlcLimit {
+            threshold: config.dust_tlc_threshold(),
+            max_exposure: config.max_dust_tlc_exposure(),
+        },
+    );
     let mut methods = InvoiceRpcServerImpl::new(store.clone(), fiber_config).into_rpc();
     if let Some(network_actor) = network_actor {
         let info = InfoRpcServerImpl::new(network_actor.clone(), store.clone());
         let peer = PeerRpcServerImpl::new(network_actor.clone());
-        let channel = ChannelRpcServerImpl::new(network_actor, store.clone());
+        let channel =
+            ChannelRpcServerImpl::new(network_actor, store.clone(), default_dust_tlc_limit);
         let network_graph = GraphRpcServerImpl::new(network_graph, store.clone());
         methods.merge(info.into_rpc()).unwrap();
         methods.merge(peer.into_rpc()).unwrap();
@@ -86,5 +150,48 @@ pub async fn start_rpc<
         let cch = CchRpcServerImpl::new(cch_actor);
         methods.merge(cch.into_rpc()).unwrap();
     }
+    warn_unknown_allowed_methods(&methods, allowed_methods.as_deref());
+    if let Some(ws_listening_addr) = config.ws_listening_addr.as_deref() {
+        let ws_server = build_server(
+            ws_listening_addr,
+            config.auth_token.clone(),
+            allowed_methods,
+            true,
+        );
+        info!("Websocket rpc server listening on {}", ws_listening_addr);
+        stop_on_cancellation(ws_server.start(methods.clone()), "websocket rpc server");
+    }
+    for listener in config.additional_listeners.iter().flat_map(|l| l.0.iter()) {
+        let allowed_methods = resolve_allowed_methods(&listener.allowed_methods);
+        warn_unknown_allowed_methods(&methods, allowed_methods.as_deref());
+        let server = build_server(
+            &listener.listening_addr,
+            config.auth_token.clone(),
+            allowed_methods,
+            false,
+        );
+        info!(
+            "Additional rpc server listening on {}",
+            listener.listening_addr
+        );
+        stop_on_cancellation(server.start(methods.clone()), "additional rpc server");
+    }
     server.start(methods)
 }
+
+fn stop_on_cancellation(handle: ServerHandle, name: &'static str) {
+    new_tokio_task_tracker().spawn(async move {
+        new_tokio_cancellation_token().cancelled().await;
+        debug!("Cancellation received, stopping {}", name);
+        let _ = handle.stop();
+        handle.stopped().await;
+    });
+}
+
+fn warn_unknown_allowed_methods(methods: &Methods, allowed_methods: Option<&HashSet<String>>) {
+    for method in allowed_methods.into_iter().flatten() {
+        if methods.method(method).is_none() {
+            warn!("Allowed rpc method {} is not provided by the node", method);
+        }
+    }
+}
@@ -1,10 +1,14 @@
-use crate::fiber::{NetworkActorCommand, NetworkActorMessage};
-use crate::log_and_error;
+use crate::fiber::{
+    network::{normalize_peer_address, SetConnectionLimitsCommand},
+    serde_utils::U64Hex,
+    NetworkActorCommand, NetworkActorMessage,
+};
+use crate::{handle_actor_call, log_and_error};
 use jsonrpsee::{
     core::async_trait, proc_macros::rpc, types::error::CALL_EXECUTION_FAILED_CODE,
     types::ErrorObjectOwned,
 };
-use ractor::ActorRef;
+use ractor::{call, ActorRef};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use tentacle::{multiaddr::MultiAddr, secio::PeerId};
@@ -22,6 +26,15 @@ pub(crate) struct DisconnectPeerParams {
     peer_id: PeerId,
 }
 
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct SetConnectionLimitsParams {
+    #[serde_as(as = "Option<U64Hex>")]
+    max_peers: Option<u64>,
+    #[serde_as(as = "Option<U64Hex>")]
+    connection_rate_limit_max_connections: Option<u64>,
+}
+
 #[rpc(server)]
 trait PeerRpc {
     #[method(name = "connect_peer")]
@@ -29,6 +42,12 @@ trait PeerRpc {
 
     #[method(name = "disconnect_peer")]
     async fn disconnect_peer(&self, params: DisconnectPeerParams) -> Result<(), ErrorObjectOwned>;
+
+    #[method(name = "set_connection_limits")]
+    async fn set_connection_limits(
+        &self,
+        params: SetConnectionLimitsParams,
+    ) -> Result<(), ErrorObjectOwned>;
 }
 
 pub(crate) struct PeerRpcServerImpl {
@@ -47,6 +66,9 @@ impl PeerRpcServer for PeerRpcServerImpl {
         let message =
             NetworkActorMessage::Command(NetworkActorCommand::ConnectPeer(params.address.clone()));
         if params.save.unwrap_or(true) {
+            if let Err(err) = normalize_peer_address(&params.address) {
+                return log_and_error!(params, err.to_string());
+            }
             crate::handle_actor_cast!(
                 self.actor,
                 NetworkActorMessage::Command(NetworkActorCommand::SavePeerAddress(
@@ -64,4 +86,21 @@ impl PeerRpcServer for PeerRpcServerImpl {
         ));
         crate::handle_actor_cast!(self.actor, message, params)
     }
+
+    async fn set_connection_limits(
+        &self,
+        params: SetConnectionLimitsParams,
+    ) -> Result<(), ErrorObjectOwned> {
+        let message = |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::SetConnectionLimits(
+                SetConnectionLimitsCommand {
+                    max_peers: params.max_peers,
+                    connection_rate_limit_max_connections: params
+                        .connection_rate_limit_max_connections,
+                },
+                rpc_reply,
+            ))
+        };
+        handle_actor_call!(self.actor, message, params)
+    }
 }
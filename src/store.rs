@@ -1,7 +1,14 @@
 use crate::{
     fiber::{
-        channel::{ChannelActorState, ChannelActorStateStore, ChannelState},
-        graph::{ChannelInfo, NetworkGraphStateStore, NodeInfo, PaymentSession},
+        channel::{
+            ChannelActorState, ChannelActorStateStore, ChannelLogEntry, ChannelLogEvent,
+            ChannelState, ClosedChannel, PeerFeePolicy, ProcessingChannelError,
+        },
+        config::StoreWritePolicy,
+        graph::{
+            ChannelInfo, GraphChanges, GraphSnapshot, NetworkGraphStateStore, NodeInfo,
+            PaymentSession, PaymentSessionStatus,
+        },
         network::{NetworkActorStateStore, PersistentNetworkActorState},
         types::{Hash256, Pubkey},
     },
@@ -11,27 +18,296 @@ use crate::{
 use ckb_jsonrpc_types::JsonBytes;
 use ckb_types::packed::{OutPoint, Script};
 use ckb_types::prelude::Entity;
+use lru::LruCache;
 use rocksdb::{prelude::*, DBIterator, Direction, IteratorMode, WriteBatch, DB};
+use serde::de::DeserializeOwned;
 use serde_json;
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tentacle::secio::PeerId;
+use tracing::error;
+use zeroize::Zeroizing;
+
+/// The default maximal size of a stored value, 64 MiB.
+pub const DEFAULT_MAX_VALUE_SIZE: usize = 64 * 1024 * 1024;
+/// The default maximal size of a stored gossip value (ChannelInfo and NodeInfo), 1 MiB.
+/// Gossip values come from the peers, so they are limited more strictly.
+pub const DEFAULT_MAX_GOSSIP_VALUE_SIZE: usize = 1024 * 1024;
+
+/// The default maximal number of retries of a store read failed with a transient error.
+pub const DEFAULT_READ_MAX_RETRIES: u32 = 3;
+/// The default capacity of the cache of the deserialized channel states, 0 means disabled.
+pub const DEFAULT_CHANNEL_STATE_CACHE_SIZE: usize = 0;
+/// The delay before the first retry of a failed read, doubled on each following retry.
+const READ_RETRY_BASE_DELAY: Duration = Duration::from_millis(10);
+/// The maximal delay between the retries of a failed read.
+const READ_RETRY_MAX_DELAY: Duration = Duration::from_secs(1);
+
+// The prefixes of the RocksDB status messages which may succeed on retrying, e.g. the IO hiccups,
+// other errors like the corruptions and the invalid arguments are permanent.
+const TRANSIENT_ERROR_PREFIXES: &[&str] = &[
+    "IO error",
+    "Resource busy",
+    "Operation timed out",
+    "Operation failed. Try again.",
+    "Result incomplete",
+];
+
+pub(crate) fn is_transient_error(error: &rocksdb::Error) -> bool {
+    let message: &str = error.as_ref();
+    TRANSIENT_ERROR_PREFIXES
+        .iter()
+        .any(|prefix| message.starts_with(prefix))
+}
+
+/// Runs the read, and retries it with an exponential backoff for at most `max_retries` times if it
+/// fails with a transient error. The permanent errors are returned immediately. The store calls
+/// are synchronous, so the backoff just sleeps the calling thread.
+pub(crate) fn retry_read<T>(
+    max_retries: u32,
+    mut read: impl FnMut() -> Result<T, rocksdb::Error>,
+) -> Result<T, rocksdb::Error> {
+    let mut retries = 0;
+    loop {
+        match read() {
+            Err(error) if retries < max_retries && is_transient_error(&error) => {
+                let delay = READ_RETRY_BASE_DELAY
+                    .saturating_mul(2u32.saturating_pow(retries))
+                    .min(READ_RETRY_MAX_DELAY);
+                error!(
+                    "Store read failed with a transient error, retrying in {:?}: {}",
+                    delay,
+                    error.as_ref() as &str
+                );
+                std::thread::sleep(delay);
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The maximal sizes of the stored values by the key prefix. Values exceeding the maximal
+/// size are rejected before deserializing, to avoid the excessive allocation caused by
+/// maliciously crafted or corrupted values.
+#[derive(Clone, Debug)]
+pub struct MaxValueSizes {
+    default: usize,
+    prefixes: HashMap<u8, usize>,
+}
+
+impl MaxValueSizes {
+    pub fn new(max_value_size: usize, max_gossip_value_size: usize) -> Self {
+        Self {
+            default: max_value_size,
+            prefixes: HashMap::new(),
+        }
+        .with_prefix(CHANNEL_INFO_PREFIX, max_gossip_value_size)
+        .with_prefix(NODE_INFO_PREFIX, max_gossip_value_size)
+    }
+
+    /// Set the maximal size of the values with the given key prefix.
+    pub fn with_prefix(mut self, prefix: u8, max_size: usize) -> Self {
+        self.prefixes.insert(prefix, max_size);
+        self
+    }
+
+    pub fn get(&self, prefix: u8) -> usize {
+        self.prefixes.get(&prefix).copied().unwrap_or(self.default)
+    }
+}
+
+impl Default for MaxValueSizes {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_VALUE_SIZE, DEFAULT_MAX_GOSSIP_VALUE_SIZE)
+    }
+}
+
+/// An LRU cache of the deserialized channel states, to avoid deserializing the frequently
+/// accessed channels again and again.
+struct ChannelStateCache {
+    states: LruCache<Hash256, ChannelActorState>,
+    // Incremented on every write of the channel states. A state read from the db is cached only if
+    // no write happens during the read, so that a concurrent write is never shadowed by a stale state.
+    version: u64,
+}
 
 #[derive(Clone)]
 pub struct Store {
     pub(crate) db: Arc<DB>,
+    max_value_sizes: Arc<MaxValueSizes>,
+    write_policy: StoreWritePolicy,
+    read_max_retries: u32,
+    channel_state_cache: Option<Arc<Mutex<ChannelStateCache>>>,
+    // Whether to log the changed channels and nodes to update the graph snapshot incrementally.
+    graph_snapshot_enabled: bool,
 }
 
 impl Store {
     pub fn new<P: AsRef<Path>>(path: P) -> Self {
         let db = Arc::new(DB::open_default(path).expect("Failed to open rocksdb"));
-        Self { db }
+        Self {
+            db,
+            max_value_sizes: Default::default(),
+            write_policy: Default::default(),
+            read_max_retries: DEFAULT_READ_MAX_RETRIES,
+            channel_state_cache: None,
+            graph_snapshot_enabled: true,
+        }
     }
 
-    fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<Vec<u8>> {
-        self.db
-            .get(key.as_ref())
+    /// Disabling the graph snapshot removes the persisted one along with the logged changes,
+    /// which would be stale if the snapshot is enabled again.
+    pub fn with_graph_snapshot(mut self, enabled: bool) -> Self {
+        if !enabled {
+            let mut batch = self.batch();
+            batch.delete([GRAPH_SNAPSHOT_PREFIX]);
+            self.db
+                .prefix_iterator([GRAPH_CHANGE_PREFIX].as_ref())
+                .take_while(|(key, _)| key.starts_with(&[GRAPH_CHANGE_PREFIX]))
+                .for_each(|(key, _)| batch.delete(key));
+            batch.commit();
+        }
+        self.graph_snapshot_enabled = enabled;
+        self
+    }
+
+    /// Cache at most `size` deserialized channel states in memory, 0 disables the cache.
+    pub fn with_channel_state_cache_size(mut self, size: usize) -> Self {
+        self.channel_state_cache = (size > 0).then(|| {
+            Arc::new(Mutex::new(ChannelStateCache {
+                states: LruCache::new(size),
+                version: 0,
+            }))
+        });
+        self
+    }
+
+    pub fn with_read_max_retries(mut self, read_max_retries: u32) -> Self {
+        self.read_max_retries = read_max_retries;
+        self
+    }
+
+    pub fn with_write_policy(mut self, write_policy: StoreWritePolicy) -> Self {
+        self.write_policy = write_policy;
+        self
+    }
+
+    pub fn with_max_value_sizes(mut self, max_value_sizes: MaxValueSizes) -> Self {
+        self.max_value_sizes = Arc::new(max_value_sizes);
+        self
+    }
+
+    // Log the change of the channel or node saved with the key in the batch, the change is
+    // indexed after the last logged one. The graph is only saved by the network graph,
+    // which holds a write lock, so the index is never taken by a concurrent change.
+    fn log_graph_change(&self, batch: &mut Batch, changed_key: Vec<u8>) {
+        if !self.graph_snapshot_enabled {
+            return;
+        }
+        let last_key = [
+            [GRAPH_CHANGE_PREFIX].as_slice(),
+            u64::MAX.to_be_bytes().as_slice(),
+        ]
+        .concat();
+        let next_index = self
+            .db
+            .iterator(IteratorMode::From(last_key.as_ref(), Direction::Reverse))
+            .next()
+            .filter(|(key, _)| key.starts_with(&[GRAPH_CHANGE_PREFIX]))
+            .map_or(0, |(key, _)| graph_change_index(key.as_ref()) + 1);
+        batch.put_kv(KeyValue::GraphChange(next_index, changed_key));
+    }
+
+    // Whether the value stored with the key prefix is within the maximal size of the prefix,
+    // the oversized value is logged.
+    fn is_value_size_allowed(&self, prefix: u8, value: &[u8], name: &str) -> bool {
+        let max_size = self.max_value_sizes.get(prefix);
+        if value.len() > max_size {
+            error!(
+                "Rejected the stored {} of {} bytes, which exceeds the maximal size {}",
+                name,
+                value.len(),
+                max_size
+            );
+            return false;
+        }
+        true
+    }
+
+    // Deserialize the value stored with the key prefix, the value exceeding the maximal size
+    // of the prefix is rejected (returns None) without being deserialized.
+    fn deserialize<T: DeserializeOwned>(&self, prefix: u8, value: &[u8], name: &str) -> Option<T> {
+        if !self.is_value_size_allowed(prefix, value, name) {
+            return None;
+        }
+        Some(
+            serde_json::from_slice(value)
+                .unwrap_or_else(|err| panic!("deserialize {} should be OK: {}", name, err)),
+        )
+    }
+
+    // Reads the value of the key, the error is returned once the retries are exhausted.
+    fn try_get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+        retry_read(self.read_max_retries, || self.db.get(key.as_ref()))
             .map(|v| v.map(|vi| vi.to_vec()))
-            .expect("get should be OK")
+    }
+
+    // Reads the value of the key for the getters which have no way to return the error, the
+    // failed read is logged and the value is treated as missing.
+    fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<Vec<u8>> {
+        self.try_get(key).unwrap_or_else(|error| {
+            error!("Store read failed: {}", error.as_ref() as &str);
+            None
+        })
+    }
+
+    // Reads the values of the keys with a single RocksDB multi_get, the result is in the
+    // same order as `keys`. The values are treated as missing if the read fails.
+    fn multi_get<K: AsRef<[u8]>>(&self, keys: &[K]) -> Vec<Option<Vec<u8>>> {
+        retry_read(self.read_max_retries, || {
+            self.db
+                .multi_get(keys.iter().map(|key| key.as_ref()))
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map(|values| {
+            values
+                .into_iter()
+                .map(|v| v.map(|vi| vi.to_vec()))
+                .collect()
+        })
+        .unwrap_or_else(|error| {
+            error!("Store read failed: {}", error.as_ref() as &str);
+            vec![None; keys.len()]
+        })
+    }
+
+    // Reads the stored channel state to check the collision of the channel id, a failed read
+    // is returned as an error instead of treating the channel id as free. An oversized state
+    // is never deserialized, the channel id is treated as occupied by another channel.
+    fn get_stored_channel_actor_state(
+        &self,
+        id: &Hash256,
+    ) -> Result<Option<ChannelActorState>, ProcessingChannelError> {
+        if let Some(state) = self.get_cached_channel_state(id) {
+            return Ok(Some(state));
+        }
+        let key = [&[CHANNEL_ACTOR_STATE_PREFIX], id.as_ref()].concat();
+        let value = self
+            .try_get(key)
+            .map_err(|error| ProcessingChannelError::StoreReadFailed(error.as_ref().to_string()))?;
+        match value {
+            Some(v) => self
+                .deserialize(CHANNEL_ACTOR_STATE_PREFIX, v.as_ref(), "ChannelActorState")
+                .map(Some)
+                .ok_or(ProcessingChannelError::ChannelIdCollision(*id)),
+            None => Ok(None),
+        }
     }
 
     #[allow(dead_code)]
@@ -56,13 +332,85 @@ impl Store {
         Batch {
             db: Arc::clone(&self.db),
             wb: WriteBatch::default(),
+            sync: self.write_policy == StoreWritePolicy::Sync,
+        }
+    }
+
+    // The batch for the writes which must survive a machine crash regardless of the write policy,
+    // e.g. the channel states, losing which may cause the loss of funds.
+    fn durable_batch(&self) -> Batch {
+        Batch {
+            sync: true,
+            ..self.batch()
+        }
+    }
+
+    fn get_cached_channel_state(&self, id: &Hash256) -> Option<ChannelActorState> {
+        let cache = self.channel_state_cache.as_ref()?;
+        cache.lock().unwrap().states.get(id).cloned()
+    }
+
+    fn channel_state_cache_version(&self) -> Option<u64> {
+        self.channel_state_cache
+            .as_ref()
+            .map(|cache| cache.lock().unwrap().version)
+    }
+
+    // Caches the state read from the db, unless the states are written since `version`.
+    fn cache_channel_state(&self, version: Option<u64>, state: &ChannelActorState) {
+        if let (Some(cache), Some(version)) = (self.channel_state_cache.as_ref(), version) {
+            let mut cache = cache.lock().unwrap();
+            if cache.version == version {
+                cache.states.put(state.id, state.clone());
+            }
+        }
+    }
+
+    fn invalidate_channel_state(&self, id: &Hash256) {
+        if let Some(cache) = self.channel_state_cache.as_ref() {
+            let mut cache = cache.lock().unwrap();
+            cache.version += 1;
+            cache.states.pop(id);
         }
     }
+
+    // The invoice and its client token are saved in one batch, so that a retried request never
+    // finds the invoice without the token.
+    fn insert_invoice_kvs(
+        &self,
+        invoice: CkbInvoice,
+        preimage: Option<Hash256>,
+        client_token: Option<&str>,
+    ) -> Result<(), InvoiceError> {
+        let mut batch = self.batch();
+        let hash = invoice.payment_hash();
+        if self.get_invoice(hash).is_some() {
+            return Err(InvoiceError::DuplicatedInvoice(*hash));
+        }
+        if let Some(preimage) = preimage {
+            invoice.check_payment_preimage(&preimage)?;
+            batch.put_kv(KeyValue::CkbInvoicePreimage(*hash, preimage));
+        }
+        if let Some(client_token) = client_token {
+            batch.put_kv(KeyValue::CkbInvoiceClientToken(
+                client_token.to_string(),
+                *hash,
+            ));
+        }
+        batch.put_kv(KeyValue::CkbInvoiceUdtIndex(
+            invoice.udt_type_script().cloned(),
+            *hash,
+        ));
+        batch.put_kv(KeyValue::CkbInvoice(*invoice.payment_hash(), invoice));
+        batch.commit();
+        return Ok(());
+    }
 }
 
 pub struct Batch {
     db: Arc<DB>,
     wb: WriteBatch,
+    sync: bool,
 }
 
 impl Batch {
@@ -75,6 +423,39 @@ impl Batch {
                     serde_json::to_vec(&state).expect("serialize ChannelActorState should be OK"),
                 );
             }
+            KeyValue::ChannelLogEntry(id, entry) => {
+                let key = [
+                    &[CHANNEL_EVENT_LOG_PREFIX],
+                    id.as_ref(),
+                    &entry.index.to_be_bytes(),
+                ]
+                .concat();
+                self.put(
+                    key,
+                    serde_json::to_vec(&entry).expect("serialize ChannelLogEntry should be OK"),
+                );
+            }
+            KeyValue::ChannelLabel(id, label) => {
+                let key = [&[CHANNEL_LABEL_PREFIX], id.as_ref()].concat();
+                self.put(
+                    key,
+                    serde_json::to_vec(&label).expect("serialize String should be OK"),
+                );
+            }
+            KeyValue::ClosedChannel(closed_channel) => {
+                self.put(
+                    closed_channel_key(closed_channel.closed_at, &closed_channel.channel_id),
+                    serde_json::to_vec(&closed_channel)
+                        .expect("serialize ClosedChannel should be OK"),
+                );
+            }
+            KeyValue::PeerFeePolicy(peer_id, policy) => {
+                let key = [&[PEER_FEE_POLICY_PREFIX], peer_id.as_bytes()].concat();
+                self.put(
+                    key,
+                    serde_json::to_vec(&policy).expect("serialize PeerFeePolicy should be OK"),
+                );
+            }
             KeyValue::CkbInvoice(id, invoice) => {
                 let key = [&[CKB_INVOICE_PREFIX], id.as_ref()].concat();
                 self.put(
@@ -84,9 +465,34 @@ impl Batch {
             }
             KeyValue::CkbInvoicePreimage(id, preimage) => {
                 let key = [&[CKB_INVOICE_PREIMAGE_PREFIX], id.as_ref()].concat();
+                let preimage = Zeroizing::new(preimage);
+                self.put(
+                    key,
+                    Zeroizing::new(
+                        serde_json::to_vec(&*preimage).expect("serialize Hash256 should be OK"),
+                    ),
+                );
+            }
+            KeyValue::CkbInvoiceClientToken(client_token, id) => {
+                let key = [&[CKB_INVOICE_CLIENT_TOKEN_PREFIX], client_token.as_bytes()].concat();
                 self.put(
                     key,
-                    serde_json::to_vec(&preimage).expect("serialize Hash256 should be OK"),
+                    serde_json::to_vec(&id).expect("serialize Hash256 should be OK"),
+                );
+            }
+            KeyValue::CkbInvoiceUdtIndex(udt_type_script, id) => {
+                let key = [
+                    invoice_udt_index_prefix(udt_type_script.as_ref()).as_slice(),
+                    id.as_ref(),
+                ]
+                .concat();
+                self.put(key, []);
+            }
+            KeyValue::CkbInvoiceSettledAt(id, settled_at) => {
+                let key = [&[CKB_INVOICE_SETTLED_AT_PREFIX], id.as_ref()].concat();
+                self.put(
+                    key,
+                    serde_json::to_vec(&settled_at).expect("serialize u64 should be OK"),
                 );
             }
             KeyValue::PeerIdChannelId((peer_id, channel_id), state) => {
@@ -131,6 +537,22 @@ impl Batch {
                     serde_json::to_vec(&channel).expect("serialize ChannelInfo should be OK"),
                 );
             }
+            KeyValue::GraphSnapshot(snapshot) => {
+                self.put(
+                    [GRAPH_SNAPSHOT_PREFIX],
+                    serde_json::to_vec(&snapshot).expect("serialize GraphSnapshot should be OK"),
+                );
+            }
+            KeyValue::GraphChange(index, changed_key) => {
+                self.put(
+                    [
+                        [GRAPH_CHANGE_PREFIX].as_slice(),
+                        index.to_be_bytes().as_slice(),
+                    ]
+                    .concat(),
+                    changed_key,
+                );
+            }
             KeyValue::PaymentSession(payment_hash, payment_session) => {
                 let key = [&[PAYMENT_SESSION_PREFIX], payment_hash.as_ref()].concat();
                 self.put(
@@ -185,7 +607,11 @@ impl Batch {
     }
 
     fn commit(self) {
-        self.db.write(&self.wb).expect("commit should be OK")
+        let mut write_options = WriteOptions::default();
+        write_options.set_sync(self.sync);
+        self.db
+            .write_opt(&self.wb, &write_options)
+            .expect("commit should be OK")
     }
 }
 
@@ -194,12 +620,21 @@ impl Batch {
 /// | KeyPrefix::  | Key::              | Value::                     |
 /// +--------------+--------------------+-----------------------------+
 /// | 0            | Hash256            | ChannelActorState           |
+/// | 1            | Hash256 | Index    | ChannelLogEntry             |
+/// | 2            | Hash256            | String                      |
+/// | 3            | PeerId             | PeerFeePolicy               |
+/// | 4            | Timestamp | Hash256 | ClosedChannel               |
 /// | 16           | PeerId             | PersistentNetworkActorState |
 /// | 32           | Hash256            | CkbInvoice                  |
+/// | 34           | ClientToken        | Hash256                     |
+/// | 35           | ScriptHash | Hash256 | ()                          |
+/// | 36           | Hash256            | u64                         |
 /// | 64           | PeerId | Hash256   | ChannelState                |
 /// | 96           | ChannelId          | ChannelInfo                 |
 /// | 97           | Block | Index      | ChannelId                   |
 /// | 98           | Timestamp          | ChannelId                   |
+/// | 99           |                    | GraphSnapshot               |
+/// | 100          | Index              | ChannelInfo/NodeInfo Key    |
 /// | 128          | NodeId             | NodeInfo                    |
 /// | 129          | Timestamp          | NodeId                      |
 /// | 160          | PeerId             | MultiAddr                   |
@@ -208,26 +643,67 @@ impl Batch {
 /// +--------------+--------------------+-----------------------------+
 ///
 
-const CHANNEL_ACTOR_STATE_PREFIX: u8 = 0;
+pub(crate) const CHANNEL_ACTOR_STATE_PREFIX: u8 = 0;
+const CHANNEL_EVENT_LOG_PREFIX: u8 = 1;
+const CHANNEL_LABEL_PREFIX: u8 = 2;
+const PEER_FEE_POLICY_PREFIX: u8 = 3;
+const CLOSED_CHANNEL_PREFIX: u8 = 4;
 const PEER_ID_NETWORK_ACTOR_STATE_PREFIX: u8 = 16;
-const CKB_INVOICE_PREFIX: u8 = 32;
+pub(crate) const CKB_INVOICE_PREFIX: u8 = 32;
 const CKB_INVOICE_PREIMAGE_PREFIX: u8 = 33;
+const CKB_INVOICE_CLIENT_TOKEN_PREFIX: u8 = 34;
+const CKB_INVOICE_UDT_INDEX_PREFIX: u8 = 35;
+const CKB_INVOICE_SETTLED_AT_PREFIX: u8 = 36;
 const PEER_ID_CHANNEL_ID_PREFIX: u8 = 64;
 pub(crate) const CHANNEL_INFO_PREFIX: u8 = 96;
 const CHANNEL_ANNOUNCEMENT_INDEX_PREFIX: u8 = 97;
 const CHANNEL_UPDATE_INDEX_PREFIX: u8 = 98;
+const GRAPH_SNAPSHOT_PREFIX: u8 = 99;
+const GRAPH_CHANGE_PREFIX: u8 = 100;
 pub(crate) const NODE_INFO_PREFIX: u8 = 128;
 const NODE_ANNOUNCEMENT_INDEX_PREFIX: u8 = 129;
 const PAYMENT_SESSION_PREFIX: u8 = 192;
 const WATCHTOWER_CHANNEL_PREFIX: u8 = 224;
 
+// The key prefix of the invoices denominated in the UDT, the script hash of native CKB
+// invoices is all zeros.
+fn invoice_udt_index_prefix(udt_type_script: Option<&Script>) -> Vec<u8> {
+    let script_hash = udt_type_script
+        .map(|script| script.calc_script_hash().as_slice().to_vec())
+        .unwrap_or_else(|| vec![0u8; 32]);
+    [&[CKB_INVOICE_UDT_INDEX_PREFIX], script_hash.as_slice()].concat()
+}
+
+// The closed channels are ordered by the time they are closed.
+fn graph_change_index(key: &[u8]) -> u64 {
+    u64::from_be_bytes(key[1..].try_into().expect("index should be 8 bytes"))
+}
+
+fn closed_channel_key(closed_at: u64, channel_id: &Hash256) -> Vec<u8> {
+    [
+        &[CLOSED_CHANNEL_PREFIX],
+        closed_at.to_be_bytes().as_slice(),
+        channel_id.as_ref(),
+    ]
+    .concat()
+}
+
 enum KeyValue {
     ChannelActorState(Hash256, ChannelActorState),
+    ChannelLogEntry(Hash256, ChannelLogEntry),
+    ChannelLabel(Hash256, String),
+    ClosedChannel(ClosedChannel),
+    PeerFeePolicy(PeerId, PeerFeePolicy),
     CkbInvoice(Hash256, CkbInvoice),
     CkbInvoicePreimage(Hash256, Hash256),
+    CkbInvoiceClientToken(String, Hash256),
+    CkbInvoiceUdtIndex(Option<Script>, Hash256),
+    CkbInvoiceSettledAt(Hash256, u64),
     PeerIdChannelId((PeerId, Hash256), ChannelState),
     NodeInfo(Pubkey, NodeInfo),
     ChannelInfo(OutPoint, ChannelInfo),
+    GraphSnapshot(GraphSnapshot),
+    GraphChange(u64, Vec<u8>),
     WatchtowerChannel(Hash256, ChannelData),
     PaymentSession(Hash256, PaymentSession),
     NetworkActorState(PeerId, PersistentNetworkActorState),
@@ -242,9 +718,12 @@ impl NetworkActorStateStore for Store {
             .db
             .prefix_iterator(key.as_ref())
             .find(|(col_key, _)| col_key.starts_with(&key));
-        iter.map(|(_key, value)| {
-            serde_json::from_slice(value.as_ref())
-                .expect("deserialize PersistentNetworkActorState should be OK")
+        iter.and_then(|(_key, value)| {
+            self.deserialize(
+                PEER_ID_NETWORK_ACTOR_STATE_PREFIX,
+                value.as_ref(),
+                "PersistentNetworkActorState",
+            )
         })
     }
 
@@ -257,29 +736,95 @@ impl NetworkActorStateStore for Store {
 
 impl ChannelActorStateStore for Store {
     fn get_channel_actor_state(&self, id: &Hash256) -> Option<ChannelActorState> {
+        if let Some(state) = self.get_cached_channel_state(id) {
+            return Some(state);
+        }
+        let version = self.channel_state_cache_version();
         let mut key = Vec::with_capacity(33);
         key.extend_from_slice(&[CHANNEL_ACTOR_STATE_PREFIX]);
         key.extend_from_slice(id.as_ref());
 
-        self.get(key).map(|v| {
-            serde_json::from_slice(v.as_ref()).expect("deserialize ChannelActorState should be OK")
-        })
+        let state: Option<ChannelActorState> = self.get(key).and_then(|v| {
+            self.deserialize(CHANNEL_ACTOR_STATE_PREFIX, v.as_ref(), "ChannelActorState")
+        });
+        if let Some(state) = state.as_ref() {
+            self.cache_channel_state(version, state);
+        }
+        state
     }
 
-    fn insert_channel_actor_state(&self, state: ChannelActorState) {
-        let mut batch = self.batch();
+    fn get_channel_actor_states(&self, ids: &[Hash256]) -> Vec<Option<ChannelActorState>> {
+        let mut states: Vec<_> = ids
+            .iter()
+            .map(|id| self.get_cached_channel_state(id))
+            .collect();
+        let missing: Vec<_> = (0..ids.len()).filter(|&i| states[i].is_none()).collect();
+        if missing.is_empty() {
+            return states;
+        }
+        let version = self.channel_state_cache_version();
+        let keys: Vec<_> = missing
+            .iter()
+            .map(|&i| [&[CHANNEL_ACTOR_STATE_PREFIX], ids[i].as_ref()].concat())
+            .collect();
+        for (i, value) in missing.into_iter().zip(self.multi_get(&keys)) {
+            states[i] = value.and_then(|v| {
+                self.deserialize(CHANNEL_ACTOR_STATE_PREFIX, v.as_ref(), "ChannelActorState")
+            });
+            if let Some(state) = states[i].as_ref() {
+                self.cache_channel_state(version, state);
+            }
+        }
+        states
+    }
+
+    fn insert_channel_actor_state(
+        &self,
+        state: ChannelActorState,
+    ) -> Result<(), ProcessingChannelError> {
+        if let Some(current) = self.get_stored_channel_actor_state(&state.id)? {
+            if !current.is_same_channel(&state) {
+                return Err(ProcessingChannelError::ChannelIdCollision(state.id));
+            }
+        }
+        let mut batch = self.durable_batch();
+        batch.put_kv(KeyValue::ChannelActorState(state.id, state.clone()));
+        batch.put_kv(KeyValue::PeerIdChannelId(
+            (state.get_remote_peer_id(), state.id),
+            state.state,
+        ));
+        batch.commit();
+        self.invalidate_channel_state(&state.id);
+        Ok(())
+    }
+
+    fn insert_closed_channel_actor_state(
+        &self,
+        state: ChannelActorState,
+        closed_channel: ClosedChannel,
+    ) -> Result<(), ProcessingChannelError> {
+        if let Some(current) = self.get_stored_channel_actor_state(&state.id)? {
+            if !current.is_same_channel(&state) {
+                return Err(ProcessingChannelError::ChannelIdCollision(state.id));
+            }
+        }
+        let mut batch = self.durable_batch();
         batch.put_kv(KeyValue::ChannelActorState(state.id, state.clone()));
         batch.put_kv(KeyValue::PeerIdChannelId(
             (state.get_remote_peer_id(), state.id),
             state.state,
         ));
+        batch.put_kv(KeyValue::ClosedChannel(closed_channel));
         batch.commit();
+        self.invalidate_channel_state(&state.id);
+        Ok(())
     }
 
     fn delete_channel_actor_state(&self, id: &Hash256) {
         if let Some(state) = self.get_channel_actor_state(id) {
-            let mut batch = self.batch();
+            let mut batch = self.durable_batch();
             batch.delete([&[CHANNEL_ACTOR_STATE_PREFIX], id.as_ref()].concat());
+            batch.delete([&[CHANNEL_LABEL_PREFIX], id.as_ref()].concat());
             batch.delete(
                 [
                     &[PEER_ID_CHANNEL_ID_PREFIX],
@@ -289,7 +834,197 @@ impl ChannelActorStateStore for Store {
                 .concat(),
             );
             batch.commit();
+            self.invalidate_channel_state(id);
+        }
+    }
+
+    fn append_channel_events(
+        &self,
+        id: &Hash256,
+        events: Vec<ChannelLogEvent>,
+        timestamp: u64,
+        max_size: u64,
+    ) {
+        let prefix = [&[CHANNEL_EVENT_LOG_PREFIX], id.as_ref()].concat();
+        let index_of = |key: &[u8]| {
+            u64::from_be_bytes(
+                key[prefix.len()..]
+                    .try_into()
+                    .expect("index should be 8 bytes"),
+            )
+        };
+        // the entries are appended after the last entry in the log
+        let last_key = [prefix.as_slice(), &u64::MAX.to_be_bytes()].concat();
+        let next_index = self
+            .db
+            .iterator(IteratorMode::From(last_key.as_ref(), Direction::Reverse))
+            .next()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .map_or(0, |(key, _)| index_of(key.as_ref()) + 1);
+        let end_index = next_index + events.len() as u64;
+        // the entries before this index are removed to keep at most max_size entries
+        let keep_from = end_index.saturating_sub(max_size);
+
+        let mut batch = self.batch();
+        for (index, event) in (next_index..end_index).zip(events) {
+            if index >= keep_from {
+                batch.put_kv(KeyValue::ChannelLogEntry(
+                    *id,
+                    ChannelLogEntry {
+                        index,
+                        timestamp,
+                        event,
+                    },
+                ));
+            }
+        }
+        self.db
+            .iterator(IteratorMode::From(prefix.as_ref(), Direction::Forward))
+            .take_while(|(key, _)| key.starts_with(&prefix) && index_of(key.as_ref()) < keep_from)
+            .for_each(|(key, _)| batch.delete(key));
+        batch.commit();
+    }
+
+    fn get_channel_events(
+        &self,
+        id: &Hash256,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Vec<ChannelLogEntry> {
+        let prefix = [&[CHANNEL_EVENT_LOG_PREFIX], id.as_ref()].concat();
+        let start = after.map_or(prefix.clone(), |after| {
+            [prefix.as_slice(), &after.saturating_add(1).to_be_bytes()].concat()
+        });
+        self.db
+            .iterator(IteratorMode::From(start.as_ref(), Direction::Forward))
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .filter_map(|(_, value)| {
+                self.deserialize(CHANNEL_EVENT_LOG_PREFIX, value.as_ref(), "ChannelLogEntry")
+            })
+            .take(limit)
+            .collect()
+    }
+
+    fn get_channel_label(&self, id: &Hash256) -> Option<String> {
+        let key = [&[CHANNEL_LABEL_PREFIX], id.as_ref()].concat();
+        self.get(key)
+            .and_then(|v| self.deserialize(CHANNEL_LABEL_PREFIX, v.as_ref(), "String"))
+    }
+
+    fn set_channel_label(&self, id: &Hash256, label: Option<String>) {
+        let mut batch = self.batch();
+        match label {
+            Some(label) => batch.put_kv(KeyValue::ChannelLabel(*id, label)),
+            None => batch.delete([&[CHANNEL_LABEL_PREFIX], id.as_ref()].concat()),
+        }
+        batch.commit();
+    }
+
+    fn get_peer_fee_policy(&self, peer_id: &PeerId) -> Option<PeerFeePolicy> {
+        let key = [&[PEER_FEE_POLICY_PREFIX], peer_id.as_bytes()].concat();
+        self.get(key)
+            .and_then(|v| self.deserialize(PEER_FEE_POLICY_PREFIX, v.as_ref(), "PeerFeePolicy"))
+    }
+
+    fn set_peer_fee_policy(&self, peer_id: &PeerId, policy: Option<PeerFeePolicy>) {
+        let mut batch = self.batch();
+        match policy {
+            Some(policy) => batch.put_kv(KeyValue::PeerFeePolicy(peer_id.clone(), policy)),
+            None => batch.delete([&[PEER_FEE_POLICY_PREFIX], peer_id.as_bytes()].concat()),
+        }
+        batch.commit();
+    }
+
+    fn insert_closed_channel(&self, closed_channel: ClosedChannel) {
+        let mut batch = self.batch();
+        batch.put_kv(KeyValue::ClosedChannel(closed_channel));
+        batch.commit();
+    }
+
+    fn get_closed_channels(
+        &self,
+        limit: usize,
+        after: Option<JsonBytes>,
+        closed_after: Option<u64>,
+        closed_before: Option<u64>,
+    ) -> (Vec<ClosedChannel>, JsonBytes) {
+        let prefix = vec![CLOSED_CHANNEL_PREFIX];
+        let start = match &after {
+            Some(after) => after.as_bytes().to_vec(),
+            None => [
+                prefix.as_slice(),
+                closed_after.unwrap_or_default().to_be_bytes().as_slice(),
+            ]
+            .concat(),
+        };
+        let end = closed_before
+            .map(|before| [prefix.as_slice(), before.to_be_bytes().as_slice()].concat());
+        let mode = IteratorMode::From(start.as_ref(), Direction::Forward);
+        let mut last_key = Vec::new();
+        let closed_channels: Vec<_> = self
+            .db
+            .iterator(mode)
+            .take_while(|(key, _)| {
+                key.starts_with(&prefix)
+                    && end
+                        .as_ref()
+                        .map_or(true, |end| key.as_ref() < end.as_slice())
+            })
+            // the closed channel of the cursor itself is not returned again
+            .filter(|(key, _)| after.is_none() || key.as_ref() != start.as_slice())
+            .filter_map(|(key, value)| {
+                let closed_channel =
+                    self.deserialize(CLOSED_CHANNEL_PREFIX, value.as_ref(), "ClosedChannel")?;
+                last_key = key.to_vec();
+                Some(closed_channel)
+            })
+            .take(limit)
+            .collect();
+        (closed_channels, JsonBytes::from_bytes(last_key.into()))
+    }
+
+    fn prune_closed_channels(&self, before: u64) -> usize {
+        let prefix = vec![CLOSED_CHANNEL_PREFIX];
+        let end = [prefix.as_slice(), before.to_be_bytes().as_slice()].concat();
+        let pruned: Vec<(Box<[u8]>, Hash256)> = self
+            .db
+            .prefix_iterator(prefix.as_ref())
+            .take_while(|(key, _)| key.starts_with(&prefix) && key.as_ref() < end.as_slice())
+            .map(|(key, _)| {
+                let channel_id: [u8; 32] = key[key.len() - 32..]
+                    .try_into()
+                    .expect("channel id should be 32 bytes");
+                (key, channel_id.into())
+            })
+            .collect();
+        // All the keys of the pruned channels are deleted in one batch, so that an interrupted
+        // pruning never leaves the state, label or event log of a channel without its archive.
+        let mut batch = self.durable_batch();
+        for (key, channel_id) in &pruned {
+            batch.delete(key);
+            if let Some(state) = self.get_channel_actor_state(channel_id) {
+                batch.delete(
+                    [
+                        &[PEER_ID_CHANNEL_ID_PREFIX],
+                        state.get_remote_peer_id().as_bytes(),
+                        channel_id.as_ref(),
+                    ]
+                    .concat(),
+                );
+            }
+            batch.delete([&[CHANNEL_ACTOR_STATE_PREFIX], channel_id.as_ref()].concat());
+            batch.delete([&[CHANNEL_LABEL_PREFIX], channel_id.as_ref()].concat());
+            let log_prefix = [&[CHANNEL_EVENT_LOG_PREFIX], channel_id.as_ref()].concat();
+            self.db
+                .prefix_iterator(log_prefix.as_ref())
+                .take_while(|(key, _)| key.starts_with(&log_prefix))
+                .for_each(|(key, _)| batch.delete(key));
+        }
+        batch.commit();
+        for (_, channel_id) in &pruned {
+            self.invalidate_channel_state(channel_id);
         }
+        pruned.len()
     }
 
     fn get_channel_ids_by_peer(&self, peer_id: &tentacle::secio::PeerId) -> Vec<Hash256> {
@@ -316,16 +1051,16 @@ impl ChannelActorStateStore for Store {
             .db
             .prefix_iterator(prefix.as_ref())
             .take_while(|(key, _)| key.starts_with(&prefix));
-        iter.map(|(key, value)| {
+        iter.filter_map(|(key, value)| {
             let key_len = key.len();
             let peer_id = PeerId::from_bytes(key[1..key_len - 32].into())
                 .expect("deserialize peer id should be OK");
             let channel_id: [u8; 32] = key[key_len - 32..]
                 .try_into()
                 .expect("channel id should be 32 bytes");
-            let state = serde_json::from_slice(value.as_ref())
-                .expect("deserialize ChannelState should be OK");
-            (peer_id, channel_id.into(), state)
+            let state =
+                self.deserialize(PEER_ID_CHANNEL_ID_PREFIX, value.as_ref(), "ChannelState")?;
+            Some((peer_id, channel_id.into(), state))
         })
         .collect()
     }
@@ -337,9 +1072,8 @@ impl InvoiceStore for Store {
         key.extend_from_slice(&[CKB_INVOICE_PREFIX]);
         key.extend_from_slice(id.as_ref());
 
-        self.get(key).map(|v| {
-            serde_json::from_slice(v.as_ref()).expect("deserialize CkbInvoice should be OK")
-        })
+        self.get(key)
+            .and_then(|v| self.deserialize(CKB_INVOICE_PREFIX, v.as_ref(), "CkbInvoice"))
     }
 
     fn insert_invoice(
@@ -347,26 +1081,77 @@ impl InvoiceStore for Store {
         invoice: CkbInvoice,
         preimage: Option<Hash256>,
     ) -> Result<(), InvoiceError> {
-        let mut batch = self.batch();
-        let hash = invoice.payment_hash();
-        if self.get_invoice(hash).is_some() {
-            return Err(InvoiceError::DuplicatedInvoice(hash.to_string()));
-        }
-        if let Some(preimage) = preimage {
-            batch.put_kv(KeyValue::CkbInvoicePreimage(*hash, preimage));
-        }
-        batch.put_kv(KeyValue::CkbInvoice(*invoice.payment_hash(), invoice));
-        batch.commit();
-        return Ok(());
+        self.insert_invoice_kvs(invoice, preimage, None)
     }
 
-    fn get_invoice_preimage(&self, id: &Hash256) -> Option<Hash256> {
+    fn get_invoice_preimage(&self, id: &Hash256) -> Option<Zeroizing<Hash256>> {
         let mut key = Vec::with_capacity(33);
         key.extend_from_slice(&[CKB_INVOICE_PREIMAGE_PREFIX]);
         key.extend_from_slice(id.as_ref());
 
+        self.get(key).and_then(|v| {
+            let v = Zeroizing::new(v);
+            self.deserialize(CKB_INVOICE_PREIMAGE_PREFIX, v.as_ref(), "Hash256")
+                .map(Zeroizing::new)
+        })
+    }
+
+    fn remove_invoice_preimage(&self, id: &Hash256) {
+        let mut batch = self.batch();
+        batch.delete([&[CKB_INVOICE_PREIMAGE_PREFIX], id.as_ref()].concat());
+        batch.commit();
+    }
+
+    fn insert_invoice_with_client_token(
+        &self,
+        invoice: CkbInvoice,
+        preimage: Option<Hash256>,
+        client_token: &str,
+    ) -> Result<(), InvoiceError> {
+        self.insert_invoice_kvs(invoice, preimage, Some(client_token))
+    }
+
+    fn insert_invoice_settled_at(&self, id: &Hash256, settled_at: u64) {
+        let mut batch = self.batch();
+        batch.put_kv(KeyValue::CkbInvoiceSettledAt(*id, settled_at));
+        batch.commit();
+    }
+
+    fn get_invoice_settled_at(&self, id: &Hash256) -> Option<u64> {
+        let key = [&[CKB_INVOICE_SETTLED_AT_PREFIX], id.as_ref()].concat();
+        self.get(key)
+            .and_then(|v| self.deserialize(CKB_INVOICE_SETTLED_AT_PREFIX, v.as_ref(), "u64"))
+    }
+
+    fn get_invoice_by_client_token(&self, client_token: &str) -> Option<CkbInvoice> {
+        let key = [&[CKB_INVOICE_CLIENT_TOKEN_PREFIX], client_token.as_bytes()].concat();
         self.get(key)
-            .map(|v| serde_json::from_slice(v.as_ref()).expect("deserialize Hash256 should be OK"))
+            .and_then(|v| self.deserialize(CKB_INVOICE_CLIENT_TOKEN_PREFIX, v.as_ref(), "Hash256"))
+            .and_then(|hash| self.get_invoice(&hash))
+    }
+
+    fn get_invoices_by_udt_type(
+        &self,
+        udt_type_script: Option<&Script>,
+        limit: usize,
+        after: Option<Hash256>,
+    ) -> Vec<CkbInvoice> {
+        let index_prefix = invoice_udt_index_prefix(udt_type_script);
+        let start = after.map_or(index_prefix.clone(), |after| {
+            [index_prefix.as_slice(), after.as_ref()].concat()
+        });
+        let mode = IteratorMode::From(start.as_ref(), Direction::Forward);
+        self.db
+            .iterator(mode)
+            .take_while(|(key, _)| key.starts_with(&index_prefix))
+            // the invoice of the cursor itself is not returned again
+            .filter(|(key, _)| key.as_ref() != start.as_slice())
+            .filter_map(|(key, _)| {
+                let id: [u8; 32] = key[index_prefix.len()..].try_into().ok()?;
+                self.get_invoice(&id.into())
+            })
+            .take(limit)
+            .collect()
     }
 }
 
@@ -409,8 +1194,8 @@ impl NetworkGraphStateStore for Store {
                         return None;
                     }
                 }
-                let channel: ChannelInfo = serde_json::from_slice(value.as_ref())
-                    .expect("deserialize ChannelInfo should be OK");
+                let channel: ChannelInfo =
+                    self.deserialize(CHANNEL_INFO_PREFIX, value.as_ref(), "ChannelInfo")?;
                 if !channel.is_explicitly_disabled() {
                     last_key = col_key.to_vec();
                     Some(channel)
@@ -459,11 +1244,9 @@ impl NetworkGraphStateStore for Store {
                         return None;
                     }
                 }
+                let node = self.deserialize(NODE_INFO_PREFIX, value.as_ref(), "NodeInfo")?;
                 last_key = col_key.to_vec();
-                Some(
-                    serde_json::from_slice(value.as_ref())
-                        .expect("deserialize NodeInfo should be OK"),
-                )
+                Some(node)
             })
             .skip(skip)
             .take(limit)
@@ -471,23 +1254,118 @@ impl NetworkGraphStateStore for Store {
         (nodes, JsonBytes::from_bytes(last_key.into()))
     }
 
-    fn insert_channel(&self, channel: ChannelInfo) {
+    fn insert_channel(&self, channel: ChannelInfo) -> bool {
+        let key = [&[CHANNEL_INFO_PREFIX], channel.out_point().as_slice()].concat();
+        if let Some(old) = self.get(&key).and_then(|v| {
+            self.deserialize::<ChannelInfo>(CHANNEL_INFO_PREFIX, v.as_ref(), "ChannelInfo")
+        }) {
+            if channel.is_outdated_by(&old) {
+                return false;
+            }
+        }
         let mut batch = self.batch();
         batch.put_kv(KeyValue::ChannelInfo(channel.out_point(), channel.clone()));
+        self.log_graph_change(&mut batch, key);
         batch.commit();
+        true
     }
 
-    fn insert_node(&self, node: NodeInfo) {
+    fn insert_node(&self, node: NodeInfo) -> bool {
+        let key = [&[NODE_INFO_PREFIX], node.node_id.serialize().as_slice()].concat();
+        if let Some(old) = self
+            .get(&key)
+            .and_then(|v| self.deserialize::<NodeInfo>(NODE_INFO_PREFIX, v.as_ref(), "NodeInfo"))
+        {
+            if node.is_outdated_by(&old) {
+                return false;
+            }
+        }
         let mut batch = self.batch();
         batch.put_kv(KeyValue::NodeInfo(node.node_id, node.clone()));
+        self.log_graph_change(&mut batch, key);
+        batch.commit();
+        true
+    }
+
+    fn get_graph_snapshot(&self) -> Option<GraphSnapshot> {
+        // The snapshot is only a cache of the stored channels and nodes, a broken or oversized
+        // one is ignored instead of failing the startup.
+        self.get([GRAPH_SNAPSHOT_PREFIX])
+            .filter(|v| self.is_value_size_allowed(GRAPH_SNAPSHOT_PREFIX, v, "GraphSnapshot"))
+            .and_then(|v| match serde_json::from_slice(v.as_ref()) {
+                Ok(snapshot) => Some(snapshot),
+                Err(err) => {
+                    error!("Failed to deserialize the graph snapshot: {}", err);
+                    None
+                }
+            })
+    }
+
+    fn get_graph_changes(&self, from: u64) -> GraphChanges {
+        let start = [
+            [GRAPH_CHANGE_PREFIX].as_slice(),
+            from.to_be_bytes().as_slice(),
+        ]
+        .concat();
+        let mut changed_keys = HashSet::new();
+        let mut changes = GraphChanges {
+            channels: vec![],
+            nodes: vec![],
+            next_index: from,
+        };
+        for (key, changed_key) in self
+            .db
+            .iterator(IteratorMode::From(start.as_ref(), Direction::Forward))
+            .take_while(|(key, _)| key.starts_with(&[GRAPH_CHANGE_PREFIX]))
+        {
+            changes.next_index = graph_change_index(key.as_ref()) + 1;
+            if !changed_keys.insert(changed_key.clone()) {
+                continue;
+            }
+            let Some(value) = self.get(&changed_key) else {
+                continue;
+            };
+            match changed_key.first() {
+                Some(&CHANNEL_INFO_PREFIX) => changes.channels.extend(self.deserialize(
+                    CHANNEL_INFO_PREFIX,
+                    value.as_ref(),
+                    "ChannelInfo",
+                )),
+                Some(&NODE_INFO_PREFIX) => changes.nodes.extend(self.deserialize(
+                    NODE_INFO_PREFIX,
+                    value.as_ref(),
+                    "NodeInfo",
+                )),
+                _ => {}
+            }
+        }
+        changes
+    }
+
+    fn insert_graph_snapshot(&self, snapshot: GraphSnapshot) {
+        // The last change covered by the snapshot is kept, so that the index of the next change
+        // still follows it. Applying the change again to the snapshot is harmless.
+        let last_covered_index = snapshot.change_index.saturating_sub(1);
+        let end = [
+            [GRAPH_CHANGE_PREFIX].as_slice(),
+            last_covered_index.to_be_bytes().as_slice(),
+        ]
+        .concat();
+        let mut batch = self.batch();
+        self.db
+            .prefix_iterator([GRAPH_CHANGE_PREFIX].as_ref())
+            .take_while(|(key, _)| {
+                key.starts_with(&[GRAPH_CHANGE_PREFIX]) && key.as_ref() < end.as_slice()
+            })
+            .for_each(|(key, _)| batch.delete(key));
+        batch.put_kv(KeyValue::GraphSnapshot(snapshot));
         batch.commit();
     }
 
     fn get_payment_session(&self, payment_hash: Hash256) -> Option<PaymentSession> {
         let prefix = [&[PAYMENT_SESSION_PREFIX], payment_hash.as_ref()].concat();
-        self.get(prefix).map(|v| {
-            serde_json::from_slice(v.as_ref()).expect("deserialize PaymentSession should be OK")
-        })
+        self.get(prefix)
+            .and_then(|v| self.deserialize(PAYMENT_SESSION_PREFIX, v.as_ref(), "PaymentSession"))
     }
 
     fn insert_payment_session(&self, session: PaymentSession) {
@@ -495,6 +1373,77 @@ impl NetworkGraphStateStore for Store {
         batch.put_kv(KeyValue::PaymentSession(session.payment_hash(), session));
         batch.commit();
     }
+
+    fn prune_payment_sessions(&self, before: u128, keep_recent: usize) -> usize {
+        let prefix = vec![PAYMENT_SESSION_PREFIX];
+        let mut sessions: Vec<(Box<[u8]>, PaymentSession)> = self
+            .db
+            .prefix_iterator(prefix.as_ref())
+            .take_while(|(col_key, _)| col_key.starts_with(&prefix))
+            .filter_map(|(col_key, value)| {
+                self.deserialize(PAYMENT_SESSION_PREFIX, value.as_ref(), "PaymentSession")
+                    .map(|session| (col_key, session))
+            })
+            .collect();
+        sessions.sort_by(|(_, a), (_, b)| b.created_at.cmp(&a.created_at));
+
+        let mut batch = self.batch();
+        let mut pruned = 0;
+        for (key, session) in sessions.into_iter().skip(keep_recent) {
+            if session.status.is_final()
+                && !session.has_unresolved_attempt()
+                && session.last_updated_at < before
+            {
+                batch.delete(key);
+                pruned += 1;
+            }
+        }
+        batch.commit();
+        pruned
+    }
+
+    fn get_failed_payment_sessions(&self, limit: usize) -> Vec<PaymentSession> {
+        let prefix = vec![PAYMENT_SESSION_PREFIX];
+        let mut sessions: Vec<PaymentSession> = self
+            .db
+            .prefix_iterator(prefix.as_ref())
+            .take_while(|(col_key, _)| col_key.starts_with(&prefix))
+            .filter_map(|(_key, value)| {
+                self.deserialize(PAYMENT_SESSION_PREFIX, value.as_ref(), "PaymentSession")
+            })
+            .filter(|session: &PaymentSession| session.status == PaymentSessionStatus::Failed)
+            .collect();
+        sessions.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        sessions.truncate(limit);
+        sessions
+    }
+
+    fn clear_failed_payment_sessions(&self, before: u128) -> usize {
+        let prefix = vec![PAYMENT_SESSION_PREFIX];
+        let mut batch = self.batch();
+        let mut cleared = 0;
+        // The payment sessions are only keyed by the payment hash, there is no index to remove.
+        for (key, value) in self
+            .db
+            .prefix_iterator(prefix.as_ref())
+            .take_while(|(col_key, _)| col_key.starts_with(&prefix))
+        {
+            let Some(session): Option<PaymentSession> =
+                self.deserialize(PAYMENT_SESSION_PREFIX, value.as_ref(), "PaymentSession")
+            else {
+                continue;
+            };
+            if session.status == PaymentSessionStatus::Failed
+                && !session.has_unresolved_attempt()
+                && session.created_at < before
+            {
+                batch.delete(key);
+                cleared += 1;
+            }
+        }
+        batch.commit();
+        cleared
+    }
 }
 
 impl WatchtowerStore for Store {
@@ -504,8 +1453,8 @@ impl WatchtowerStore for Store {
             .db
             .prefix_iterator(prefix.as_ref())
             .take_while(|(col_key, _)| col_key.starts_with(&prefix));
-        iter.map(|(_key, value)| {
-            serde_json::from_slice(value.as_ref()).expect("deserialize ChannelData should be OK")
+        iter.filter_map(|(_key, value)| {
+            self.deserialize(WATCHTOWER_CHANNEL_PREFIX, value.as_ref(), "ChannelData")
         })
         .collect()
     }
@@ -532,9 +1481,8 @@ impl WatchtowerStore for Store {
 
     fn update_revocation(&self, channel_id: Hash256, revocation_data: RevocationData) {
         let key = [&[WATCHTOWER_CHANNEL_PREFIX], channel_id.as_ref()].concat();
-        if let Some(mut channel_data) = self.get(key).map(|v| {
-            serde_json::from_slice::<ChannelData>(v.as_ref())
-                .expect("deserialize ChannelData should be OK")
+        if let Some(mut channel_data) = self.get(key).and_then(|v| {
+            self.deserialize::<ChannelData>(WATCHTOWER_CHANNEL_PREFIX, v.as_ref(), "ChannelData")
         }) {
             channel_data.revocation_data = Some(revocation_data);
             let mut batch = self.batch();
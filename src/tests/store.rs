@@ -1,15 +1,37 @@
+use crate::fiber::channel::ChannelActorState;
+use crate::fiber::channel::ChannelActorStateStore;
+use crate::fiber::channel::ChannelLogEvent;
+use crate::fiber::channel::ChannelState;
+use crate::fiber::channel::CloseFlags;
+use crate::fiber::channel::ClosedChannel;
+use crate::fiber::channel::PeerFeePolicy;
+use crate::fiber::channel::ProcessingChannelError;
+use crate::fiber::channel::TLCId;
 use crate::fiber::config::AnnouncedNodeName;
+use crate::fiber::config::StoreWritePolicy;
 use crate::fiber::graph::ChannelInfo;
+use crate::fiber::graph::ChannelUpdateInfo;
 use crate::fiber::graph::NetworkGraphStateStore;
 use crate::fiber::graph::NodeInfo;
+use crate::fiber::graph::PaymentSession;
+use crate::fiber::graph::PaymentSessionStatus;
+use crate::fiber::hash_algorithm::HashAlgorithm;
+use crate::fiber::network::get_chain_hash;
+use crate::fiber::network::SendPaymentData;
 use crate::fiber::tests::test_utils::gen_sha256_hash;
 use crate::fiber::types::ChannelAnnouncement;
+use crate::fiber::types::ChannelUpdate;
 use crate::fiber::types::Hash256;
 use crate::fiber::types::NodeAnnouncement;
 use crate::fiber::types::Pubkey;
 use crate::invoice::*;
+use crate::store::is_transient_error;
+use crate::store::retry_read;
+use crate::store::MaxValueSizes;
 use crate::store::Store;
+use crate::store::CHANNEL_ACTOR_STATE_PREFIX;
 use crate::store::CHANNEL_INFO_PREFIX;
+use crate::store::CKB_INVOICE_PREFIX;
 use crate::store::NODE_INFO_PREFIX;
 use crate::watchtower::*;
 use ckb_jsonrpc_types::JsonBytes;
@@ -17,12 +39,16 @@ use ckb_types::packed::Bytes;
 use ckb_types::packed::CellOutput;
 use ckb_types::packed::OutPoint;
 use ckb_types::packed::Script;
+use ckb_types::packed::Transaction;
 use ckb_types::prelude::*;
+use jsonrpsee::types::ErrorObjectOwned;
 use musig2::CompactSignature;
+use rocksdb::prelude::Put;
 use secp256k1::Keypair;
 use secp256k1::PublicKey;
 use secp256k1::Secp256k1;
 use tempfile::tempdir;
+use tentacle::secio::PeerId;
 
 fn gen_rand_public_key() -> PublicKey {
     let secp = Secp256k1::new();
@@ -59,6 +85,7 @@ fn mock_channel() -> ChannelInfo {
         timestamp: 0,
         node1_to_node2: None,
         node2_to_node1: None,
+        private: false,
         announcement_msg: ChannelAnnouncement::new_unsigned(
             &node1,
             &node2,
@@ -74,6 +101,28 @@ fn mock_channel() -> ChannelInfo {
     }
 }
 
+#[test]
+fn test_store_write_policy() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("write_policy_store");
+
+    for policy in [StoreWritePolicy::Sync, StoreWritePolicy::Async] {
+        let store = Store::new(&path).with_write_policy(policy);
+        let invoice = InvoiceBuilder::new(Currency::Fibb)
+            .amount(Some(1280))
+            .payment_preimage(gen_sha256_hash())
+            .build()
+            .unwrap();
+        let hash = *invoice.payment_hash();
+        store.insert_invoice(invoice.clone(), None).unwrap();
+        drop(store);
+
+        // the writes are readable after reopening the store with either policy
+        let store = Store::new(&path);
+        assert_eq!(store.get_invoice(&hash), Some(invoice));
+    }
+}
+
 #[test]
 fn test_store_invoice() {
     let dir = tempdir().unwrap();
@@ -94,10 +143,179 @@ fn test_store_invoice() {
         .insert_invoice(invoice.clone(), Some(preimage))
         .unwrap();
     assert_eq!(store.get_invoice(hash), Some(invoice.clone()));
-    assert_eq!(store.get_invoice_preimage(hash), Some(preimage));
+    assert_eq!(store.get_invoice_preimage(hash).as_deref(), Some(&preimage));
+
+    let error = store
+        .insert_invoice(invoice.clone(), Some(preimage))
+        .unwrap_err();
+    assert_eq!(error, InvoiceError::DuplicatedInvoice(*hash));
+    let rpc_error = ErrorObjectOwned::from(error);
+    assert_eq!(rpc_error.code(), 1017);
+    assert_eq!(
+        rpc_error.data().map(|data| data.get()),
+        Some(format!("{{\"payment_hash\":\"{:#x}\"}}", hash).as_str())
+    );
 
     let invalid_hash = gen_sha256_hash();
     assert_eq!(store.get_invoice_preimage(&invalid_hash), None);
+
+    store.remove_invoice_preimage(hash);
+    assert_eq!(store.get_invoice_preimage(hash), None);
+    assert_eq!(store.get_invoice(hash), Some(invoice));
+}
+
+#[test]
+fn test_store_invoice_with_mismatched_preimage() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("invoice_store");
+    let store = Store::new(path);
+
+    let preimage = gen_sha256_hash();
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1280))
+        .payment_preimage(preimage)
+        .build()
+        .unwrap();
+    let hash = *invoice.payment_hash();
+    let error = store
+        .insert_invoice(invoice.clone(), Some(gen_sha256_hash()))
+        .unwrap_err();
+    assert_eq!(error, InvoiceError::PreimageMismatch(hash));
+    assert_eq!(ErrorObjectOwned::from(error).code(), 1020);
+    assert_eq!(store.get_invoice(&hash), None);
+    assert_eq!(store.get_invoice_preimage(&hash), None);
+
+    // The preimage is hashed by the hash algorithm of the invoice.
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1280))
+        .payment_hash(HashAlgorithm::CkbHash.hash(preimage).into())
+        .hash_algorithm(HashAlgorithm::Sha256)
+        .build()
+        .unwrap();
+    let hash = *invoice.payment_hash();
+    assert_eq!(
+        store.insert_invoice(invoice.clone(), Some(preimage)),
+        Err(InvoiceError::PreimageMismatch(hash))
+    );
+
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1280))
+        .payment_hash(HashAlgorithm::CkbHash.hash(preimage).into())
+        .hash_algorithm(HashAlgorithm::CkbHash)
+        .build()
+        .unwrap();
+    store
+        .insert_invoice(invoice.clone(), Some(preimage))
+        .unwrap();
+    assert_eq!(
+        store.get_invoice_preimage(&hash).as_deref(),
+        Some(&preimage)
+    );
+}
+
+#[test]
+fn test_store_invoice_settled_at() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("invoice_store");
+    let store = Store::new(&path);
+
+    let hash = gen_sha256_hash();
+    assert_eq!(store.get_invoice_settled_at(&hash), None);
+    store.insert_invoice_settled_at(&hash, 1700000000000);
+    assert_eq!(store.get_invoice_settled_at(&hash), Some(1700000000000));
+    assert_eq!(store.get_invoice_settled_at(&gen_sha256_hash()), None);
+
+    // The settlement time is persisted across restarts.
+    drop(store);
+    let store = Store::new(&path);
+    assert_eq!(store.get_invoice_settled_at(&hash), Some(1700000000000));
+}
+
+#[test]
+fn test_store_invoice_with_client_token() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("invoice_store");
+    let store = Store::new(&path);
+
+    let preimage = gen_sha256_hash();
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1280))
+        .payment_preimage(preimage)
+        .build()
+        .unwrap();
+    assert_eq!(store.get_invoice_by_client_token("token"), None);
+    store
+        .insert_invoice_with_client_token(invoice.clone(), Some(preimage), "token")
+        .unwrap();
+    assert_eq!(
+        store.get_invoice(invoice.payment_hash()),
+        Some(invoice.clone())
+    );
+    assert_eq!(
+        store.get_invoice_by_client_token("token"),
+        Some(invoice.clone())
+    );
+    assert_eq!(store.get_invoice_by_client_token("other token"), None);
+
+    // The token is persisted across restarts.
+    drop(store);
+    let store = Store::new(&path);
+    assert_eq!(store.get_invoice_by_client_token("token"), Some(invoice));
+}
+
+#[test]
+fn test_store_invoices_by_udt_type() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("invoice_store");
+    let store = Store::new(path);
+
+    let udt_type_script = Script::new_builder().args([1u8; 20].pack()).build();
+    let other_udt_type_script = Script::new_builder().args([2u8; 20].pack()).build();
+    let mut ckb_invoices = vec![];
+    let mut udt_invoices = vec![];
+    for i in 0..6 {
+        let preimage = gen_sha256_hash();
+        let mut builder = InvoiceBuilder::new(Currency::Fibb)
+            .amount(Some(1280))
+            .payment_preimage(preimage);
+        if i % 2 == 1 {
+            builder = builder.udt_type_script(udt_type_script.clone());
+        }
+        let invoice = builder.build().unwrap();
+        store
+            .insert_invoice(invoice.clone(), Some(preimage))
+            .unwrap();
+        if i % 2 == 1 {
+            udt_invoices.push(invoice);
+        } else {
+            ckb_invoices.push(invoice);
+        }
+    }
+    ckb_invoices.sort_by(|a, b| a.payment_hash().as_ref().cmp(b.payment_hash().as_ref()));
+    udt_invoices.sort_by(|a, b| a.payment_hash().as_ref().cmp(b.payment_hash().as_ref()));
+
+    assert_eq!(
+        store.get_invoices_by_udt_type(None, usize::MAX, None),
+        ckb_invoices
+    );
+    assert_eq!(
+        store.get_invoices_by_udt_type(Some(&udt_type_script), usize::MAX, None),
+        udt_invoices
+    );
+    assert_eq!(
+        store.get_invoices_by_udt_type(Some(&other_udt_type_script), usize::MAX, None),
+        vec![]
+    );
+
+    // paginate with the payment hash of the last returned invoice
+    let first_page = store.get_invoices_by_udt_type(Some(&udt_type_script), 2, None);
+    assert_eq!(first_page, udt_invoices[..2]);
+    let second_page = store.get_invoices_by_udt_type(
+        Some(&udt_type_script),
+        2,
+        Some(*first_page[1].payment_hash()),
+    );
+    assert_eq!(second_page, udt_invoices[2..]);
 }
 
 #[test]
@@ -164,6 +382,523 @@ fn test_store_nodes() {
     assert_eq!(res, nodes[1..=3]);
 }
 
+fn mock_channel_update_info(
+    channel: &ChannelInfo,
+    message_flags: u32,
+    version: u64,
+    fee_rate: u64,
+) -> ChannelUpdateInfo {
+    ChannelUpdateInfo {
+        version,
+        // The local time the update is processed, it doesn't order the updates.
+        timestamp: 1,
+        enabled: true,
+        cltv_expiry_delta: 144,
+        htlc_minimum_value: 0,
+        htlc_maximum_value: 0,
+        fee_rate,
+        last_update_message: ChannelUpdate::new_unsigned(
+            get_chain_hash(),
+            channel.out_point(),
+            version,
+            message_flags,
+            0,
+            144,
+            0,
+            0,
+            fee_rate as u128,
+        ),
+    }
+}
+
+#[test]
+fn test_store_ignore_outdated_channel() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("invoice_store");
+    let store = Store::new(path);
+
+    let mut newer = mock_channel();
+    newer.node1_to_node2 = Some(mock_channel_update_info(&newer, 1, 2, 1000));
+    let mut older = newer.clone();
+    older.node1_to_node2 = Some(mock_channel_update_info(&older, 1, 1, 1000));
+
+    assert!(store.insert_channel(newer.clone()));
+    assert!(!store.insert_channel(older));
+    assert_eq!(
+        store.get_channels(Some(newer.out_point())),
+        vec![newer.clone()]
+    );
+
+    // Re-inserting the same channel is ignored, even if it's processed at a later time.
+    let mut replayed = newer.clone();
+    replayed.timestamp += 1000;
+    assert!(!store.insert_channel(replayed));
+
+    // The update of the other direction processed in the same millisecond is kept.
+    let mut both_directions = newer.clone();
+    both_directions.node2_to_node1 = Some(mock_channel_update_info(&newer, 0, 2, 1000));
+    assert!(store.insert_channel(both_directions.clone()));
+    assert_eq!(
+        store.get_channels(Some(newer.out_point())),
+        vec![both_directions.clone()]
+    );
+
+    // An update of the same version with a different content is accepted.
+    let mut changed = both_directions.clone();
+    changed.node1_to_node2 = Some(mock_channel_update_info(&newer, 1, 2, 2000));
+    assert!(store.insert_channel(changed.clone()));
+
+    let mut latest = changed.clone();
+    latest.node2_to_node1 = Some(mock_channel_update_info(&newer, 0, 3, 1000));
+    assert!(store.insert_channel(latest.clone()));
+    assert_eq!(store.get_channels(Some(newer.out_point())), vec![latest]);
+}
+
+#[test]
+fn test_store_ignore_outdated_node() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("invoice_store");
+    let store = Store::new(path);
+
+    let (node_id, mut newer) = mock_node();
+    newer.anouncement_msg.version = 2;
+    let mut older = newer.clone();
+    older.anouncement_msg.version = 1;
+
+    assert!(store.insert_node(newer.clone()));
+    assert!(!store.insert_node(older));
+    // Re-inserting the same announcement is ignored, even if it's processed at a later time.
+    let mut replayed = newer.clone();
+    replayed.timestamp += 1000;
+    assert!(!store.insert_node(replayed));
+    assert_eq!(store.get_nodes(Some(node_id)), vec![newer.clone()]);
+
+    // A re-announcement of the same version with a different content is accepted, even if it's
+    // processed in the same millisecond.
+    let mut reannounced = newer.clone();
+    reannounced
+        .anouncement_msg
+        .auto_accept_min_ckb_funding_amount += 1;
+    assert!(store.insert_node(reannounced.clone()));
+    assert_eq!(store.get_nodes(Some(node_id)), vec![reannounced.clone()]);
+
+    let mut latest = reannounced.clone();
+    latest.anouncement_msg.version = 3;
+    assert!(store.insert_node(latest.clone()));
+    assert_eq!(store.get_nodes(Some(node_id)), vec![latest]);
+}
+
+fn mock_payment_session(
+    created_at: u128,
+    last_updated_at: u128,
+    status: PaymentSessionStatus,
+) -> PaymentSession {
+    let mut session = PaymentSession::new(
+        SendPaymentData {
+            target_pubkey: gen_rand_public_key().into(),
+            amount: 100,
+            payment_hash: gen_sha256_hash(),
+            invoice: None,
+            final_cltv_delta: None,
+            timeout: None,
+            max_fee_amount: None,
+            max_parts: None,
+            keysend: false,
+            udt_type_script: None,
+            preimage: None,
+            allow_self_payment: false,
+            outgoing_channel: None,
+            probe: false,
+            trampoline_node: None,
+            keep_reserve: None,
+            max_fee_percent: None,
+            max_fee_rate_ppm: None,
+            fees_paid_by: Default::default(),
+            first_hop_limits: None,
+        },
+        3,
+    );
+    session.status = status;
+    session.created_at = created_at;
+    session.last_updated_at = last_updated_at;
+    session
+}
+
+#[test]
+fn test_store_prune_payment_sessions() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("payment_store");
+    let store = Store::new(path);
+
+    let old_success = mock_payment_session(1, 10, PaymentSessionStatus::Success);
+    let old_failed = mock_payment_session(2, 20, PaymentSessionStatus::Failed);
+    let old_inflight = mock_payment_session(3, 30, PaymentSessionStatus::Inflight);
+    let new_success = mock_payment_session(4, 200, PaymentSessionStatus::Success);
+    let recent_failed = mock_payment_session(5, 50, PaymentSessionStatus::Failed);
+    for session in [
+        &old_success,
+        &old_failed,
+        &old_inflight,
+        &new_success,
+        &recent_failed,
+    ] {
+        store.insert_payment_session(session.clone());
+    }
+
+    // The most recent session is kept although it's old enough, and the unfinished
+    // session is never pruned.
+    assert_eq!(store.prune_payment_sessions(100, 1), 2);
+    assert!(store
+        .get_payment_session(old_success.payment_hash())
+        .is_none());
+    assert!(store
+        .get_payment_session(old_failed.payment_hash())
+        .is_none());
+    assert!(store
+        .get_payment_session(old_inflight.payment_hash())
+        .is_some());
+    assert!(store
+        .get_payment_session(new_success.payment_hash())
+        .is_some());
+    assert!(store
+        .get_payment_session(recent_failed.payment_hash())
+        .is_some());
+
+    assert_eq!(store.prune_payment_sessions(100, 0), 1);
+    assert!(store
+        .get_payment_session(recent_failed.payment_hash())
+        .is_none());
+    assert_eq!(store.prune_payment_sessions(100, 0), 0);
+}
+
+#[test]
+fn test_store_clear_failed_payment_sessions() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("payment_store");
+    let store = Store::new(path);
+
+    let old_failed = mock_payment_session(1, 10, PaymentSessionStatus::Failed);
+    let old_success = mock_payment_session(2, 20, PaymentSessionStatus::Success);
+    let new_failed = mock_payment_session(200, 200, PaymentSessionStatus::Failed);
+    for session in [&old_failed, &old_success, &new_failed] {
+        store.insert_payment_session(session.clone());
+    }
+
+    let failed: Vec<_> = store
+        .get_failed_payment_sessions(10)
+        .iter()
+        .map(|session| session.payment_hash())
+        .collect();
+    assert_eq!(
+        failed,
+        vec![new_failed.payment_hash(), old_failed.payment_hash()]
+    );
+    assert_eq!(store.get_failed_payment_sessions(1).len(), 1);
+
+    // Only the failed sessions created before the given time are removed.
+    assert_eq!(store.clear_failed_payment_sessions(100), 1);
+    assert!(store
+        .get_payment_session(old_failed.payment_hash())
+        .is_none());
+    assert!(store
+        .get_payment_session(old_success.payment_hash())
+        .is_some());
+    assert!(store
+        .get_payment_session(new_failed.payment_hash())
+        .is_some());
+
+    assert_eq!(store.clear_failed_payment_sessions(u128::MAX), 1);
+    assert!(store.get_failed_payment_sessions(10).is_empty());
+    assert!(store
+        .get_payment_session(old_success.payment_hash())
+        .is_some());
+}
+
+#[test]
+fn test_store_reject_oversized_value() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("invoice_store");
+    let store = Store::new(path).with_max_value_sizes(MaxValueSizes::new(1024 * 1024, 1024));
+
+    let (node_id, node) = mock_node();
+    assert!(store.insert_node(node.clone()));
+    assert_eq!(store.get_nodes(Some(node_id)), vec![node.clone()]);
+
+    // An oversized value is rejected before deserializing, even if it is not valid json.
+    let (oversized_node_id, _) = mock_node();
+    let key = [
+        &[NODE_INFO_PREFIX],
+        oversized_node_id.serialize().as_slice(),
+    ]
+    .concat();
+    store.db.put(key, vec![b'['; 1025]).unwrap();
+    assert_eq!(store.get_nodes(Some(oversized_node_id)), vec![]);
+    assert_eq!(store.get_nodes(None), vec![node]);
+
+    // The limit of gossip values doesn't apply to other values.
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1280))
+        .payment_preimage(gen_sha256_hash())
+        .add_attr(Attribute::Description("a".repeat(2048)))
+        .build()
+        .unwrap();
+    let hash = invoice.payment_hash();
+    store.insert_invoice(invoice.clone(), None).unwrap();
+    assert_eq!(store.get_invoice(hash), Some(invoice));
+
+    let oversized_hash = gen_sha256_hash();
+    let key = [&[CKB_INVOICE_PREFIX], oversized_hash.as_ref()].concat();
+    store.db.put(key, vec![b'['; 1024 * 1024 + 1]).unwrap();
+    assert_eq!(store.get_invoice(&oversized_hash), None);
+
+    // An oversized channel state is not returned, but its channel id is still occupied.
+    let state = mock_channel_actor_state(1);
+    let key = [&[CHANNEL_ACTOR_STATE_PREFIX], state.id.as_ref()].concat();
+    store.db.put(key, vec![b'['; 1024 * 1024 + 1]).unwrap();
+    assert!(store.get_channel_actor_state(&state.id).is_none());
+    assert!(matches!(
+        store.insert_channel_actor_state(state.clone()),
+        Err(ProcessingChannelError::ChannelIdCollision(id)) if id == state.id
+    ));
+}
+
+fn mock_channel_actor_state(seed: u8) -> ChannelActorState {
+    ChannelActorState::new_outbound_channel(
+        None,
+        &[seed; 32],
+        gen_rand_public_key().into(),
+        gen_rand_public_key().into(),
+        100000000000,
+        6200000000,
+        1000,
+        0,
+        1000,
+        None,
+        Script::default(),
+        100000000000,
+        100,
+        None,
+        None,
+        HashAlgorithm::supported_algorithms(),
+        vec![0],
+    )
+}
+
+fn mock_funding_tx(capacity: u64) -> Transaction {
+    Transaction::default()
+        .as_advanced_builder()
+        .output(CellOutput::new_builder().capacity(capacity.pack()).build())
+        .output_data(Bytes::default())
+        .build()
+        .data()
+}
+
+#[test]
+fn test_store_reject_channel_id_collision() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("channel_store");
+    let store = Store::new(path);
+
+    let mut state = mock_channel_actor_state(1);
+    store.insert_channel_actor_state(state.clone()).unwrap();
+
+    // Updating the state of the same channel is allowed.
+    state.to_local_amount -= 1;
+    store.insert_channel_actor_state(state.clone()).unwrap();
+
+    // Another channel with the same id doesn't overwrite the existing state.
+    let mut other = mock_channel_actor_state(2);
+    other.id = state.id;
+    assert!(matches!(
+        store.insert_channel_actor_state(other),
+        Err(ProcessingChannelError::ChannelIdCollision(id)) if id == state.id
+    ));
+    assert_eq!(
+        store
+            .get_channel_actor_state(&state.id)
+            .map(|current| current.to_local_amount),
+        Some(state.to_local_amount)
+    );
+
+    // The channels are distinguished by the funding outpoints once the funding txs are confirmed.
+    state.funding_tx = Some(mock_funding_tx(1));
+    state.funding_tx_confirmed_at = Some((1.into(), 0));
+    store.insert_channel_actor_state(state.clone()).unwrap();
+    let mut other = state.clone();
+    other.funding_tx = Some(mock_funding_tx(2));
+    assert!(matches!(
+        store.insert_channel_actor_state(other),
+        Err(ProcessingChannelError::ChannelIdCollision(_))
+    ));
+}
+
+#[test]
+fn test_store_get_channel_actor_states() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("channel_states_store");
+    let store = Store::new(path);
+
+    let states: Vec<_> = (1..=3).map(mock_channel_actor_state).collect();
+    for state in &states {
+        store.insert_channel_actor_state(state.clone()).unwrap();
+    }
+    let missing_id = gen_sha256_hash();
+    let ids = [states[2].id, missing_id, states[0].id, states[1].id];
+    let result: Vec<_> = store
+        .get_channel_actor_states(&ids)
+        .into_iter()
+        .map(|state| state.map(|state| state.id))
+        .collect();
+    assert_eq!(
+        result,
+        vec![
+            Some(states[2].id),
+            None,
+            Some(states[0].id),
+            Some(states[1].id)
+        ]
+    );
+    assert!(store.get_channel_actor_states(&[]).is_empty());
+}
+
+#[test]
+fn test_store_channel_state_cache() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("channel_state_cache_store");
+    let store = Store::new(path).with_channel_state_cache_size(2);
+
+    let mut state = mock_channel_actor_state(1);
+    let other = mock_channel_actor_state(2);
+    store.insert_channel_actor_state(state.clone()).unwrap();
+    store.insert_channel_actor_state(other.clone()).unwrap();
+    let local_amount = |store: &Store, id| {
+        store
+            .get_channel_actor_state(id)
+            .map(|state| state.to_local_amount)
+    };
+    assert_eq!(local_amount(&store, &state.id), Some(state.to_local_amount));
+
+    // The cached state is returned without reading the db.
+    let key = [&[CHANNEL_ACTOR_STATE_PREFIX], state.id.as_ref()].concat();
+    let mut changed = state.clone();
+    changed.to_local_amount -= 1;
+    store
+        .db
+        .put(&key, serde_json::to_vec(&changed).unwrap())
+        .unwrap();
+    assert_eq!(local_amount(&store, &state.id), Some(state.to_local_amount));
+    assert_eq!(
+        store.get_channel_actor_states(&[other.id, state.id])[1]
+            .as_ref()
+            .map(|state| state.to_local_amount),
+        Some(state.to_local_amount)
+    );
+
+    // The cache is invalidated by the writes through the store.
+    state.to_local_amount -= 2;
+    store.insert_channel_actor_state(state.clone()).unwrap();
+    assert_eq!(local_amount(&store, &state.id), Some(state.to_local_amount));
+    store.delete_channel_actor_state(&state.id);
+    assert_eq!(local_amount(&store, &state.id), None);
+    assert_eq!(local_amount(&store, &other.id), Some(other.to_local_amount));
+}
+
+#[test]
+fn test_store_closed_channels() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("closed_channels_store");
+    let store = Store::new(path);
+
+    let mut closed_channels = vec![];
+    for (seed, closed_at) in [(1, 300), (2, 100), (3, 200)] {
+        let mut state = mock_channel_actor_state(seed);
+        state.update_state(ChannelState::Closed(CloseFlags::COOPERATIVE));
+        state.closing_tx_hash = Some(gen_sha256_hash());
+        let closed_channel = ClosedChannel::new(&state, closed_at);
+        assert_eq!(closed_channel.close_flags, CloseFlags::COOPERATIVE);
+        assert_eq!(closed_channel.closing_tx_hash, state.closing_tx_hash);
+        store
+            .insert_closed_channel_actor_state(state.clone(), closed_channel.clone())
+            .unwrap();
+        store.set_channel_label(&state.id, Some(format!("channel-{}", seed)));
+        store.append_channel_events(
+            &state.id,
+            vec![ChannelLogEvent::TlcFailed {
+                tlc_id: TLCId::Offered(0),
+            }],
+            closed_at,
+            5,
+        );
+        closed_channels.push(closed_channel);
+    }
+    let closed_at =
+        |channels: &[ClosedChannel]| channels.iter().map(|c| c.closed_at).collect::<Vec<_>>();
+
+    // The closed channels are paginated in the order of the closing time.
+    let (page, cursor) = store.get_closed_channels(2, None, None, None);
+    assert_eq!(closed_at(&page), vec![100, 200]);
+    assert_eq!(page[0], closed_channels[1]);
+    let (page, cursor) = store.get_closed_channels(2, Some(cursor), None, None);
+    assert_eq!(closed_at(&page), vec![300]);
+    let (page, _) = store.get_closed_channels(2, Some(cursor), None, None);
+    assert!(page.is_empty());
+
+    let (page, _) = store.get_closed_channels(10, None, Some(200), None);
+    assert_eq!(closed_at(&page), vec![200, 300]);
+    let (page, _) = store.get_closed_channels(10, None, Some(100), Some(300));
+    assert_eq!(closed_at(&page), vec![100, 200]);
+
+    // The archives closed before the timestamp are pruned along with all the keys of the
+    // channels.
+    assert_eq!(store.prune_closed_channels(250), 2);
+    let (page, _) = store.get_closed_channels(10, None, None, None);
+    assert_eq!(closed_at(&page), vec![300]);
+    for pruned in &closed_channels[1..] {
+        let id = &pruned.channel_id;
+        assert!(store.get_channel_actor_state(id).is_none());
+        assert!(store.get_channel_label(id).is_none());
+        assert!(store.get_channel_events(id, None, 10).is_empty());
+    }
+    let kept = &closed_channels[0].channel_id;
+    assert!(store.get_channel_actor_state(kept).is_some());
+    assert_eq!(store.get_channel_label(kept), Some("channel-1".to_string()));
+    assert_eq!(store.get_channel_events(kept, None, 10).len(), 1);
+    assert_eq!(store.prune_closed_channels(250), 0);
+}
+
+#[test]
+fn test_store_multi_get_channel_actor_states_matches_single_gets() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("channel_states_multi_get_store");
+    let store = Store::new(path);
+
+    let mut ids: Vec<_> = (0..100)
+        .map(|i| {
+            let mut state = mock_channel_actor_state((i % 256) as u8);
+            state.id = gen_sha256_hash();
+            store.insert_channel_actor_state(state.clone()).unwrap();
+            state.id
+        })
+        .collect();
+    ids.insert(50, gen_sha256_hash());
+
+    let single_gets: Vec<_> = ids
+        .iter()
+        .map(|id| {
+            store
+                .get_channel_actor_state(id)
+                .map(|state| serde_json::to_value(state).unwrap())
+        })
+        .collect();
+    let multi_get: Vec<_> = store
+        .get_channel_actor_states(&ids)
+        .into_iter()
+        .map(|state| state.map(|state| serde_json::to_value(state).unwrap()))
+        .collect();
+    assert_eq!(multi_get, single_gets);
+    assert!(multi_get[50].is_none());
+}
+
 #[test]
 fn test_store_wacthtower() {
     let dir = tempdir().unwrap();
@@ -202,3 +937,139 @@ fn test_store_wacthtower() {
     store.remove_watch_channel(channel_id);
     assert_eq!(store.get_watch_channels(), vec![]);
 }
+
+#[test]
+fn test_store_channel_event_log() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("channel_event_log_store");
+    let store = Store::new(path);
+    let channel_id = gen_sha256_hash();
+    let other_channel_id = gen_sha256_hash();
+
+    let events = |ids: std::ops::Range<u64>| {
+        ids.map(|id| ChannelLogEvent::TlcFailed {
+            tlc_id: TLCId::Offered(id),
+        })
+        .collect::<Vec<_>>()
+    };
+    store.append_channel_events(&channel_id, events(0..3), 1, 5);
+    store.append_channel_events(&other_channel_id, events(0..1), 1, 5);
+    store.append_channel_events(&channel_id, events(3..6), 2, 5);
+
+    // the oldest entry is rotated out, the indexes keep increasing
+    let log = store.get_channel_events(&channel_id, None, usize::MAX);
+    assert_eq!(
+        log.iter().map(|entry| entry.index).collect::<Vec<_>>(),
+        vec![1, 2, 3, 4, 5]
+    );
+    assert_eq!(log[0].timestamp, 1);
+    assert_eq!(log[4].timestamp, 2);
+    assert_eq!(log[4].event, events(5..6)[0]);
+
+    let page = store.get_channel_events(&channel_id, Some(2), 2);
+    assert_eq!(
+        page.iter().map(|entry| entry.index).collect::<Vec<_>>(),
+        vec![3, 4]
+    );
+    assert_eq!(
+        store
+            .get_channel_events(&other_channel_id, None, usize::MAX)
+            .len(),
+        1
+    );
+
+    // appending more events than the maximal size only keeps the latest ones
+    store.append_channel_events(&channel_id, events(6..13), 3, 5);
+    let log = store.get_channel_events(&channel_id, None, usize::MAX);
+    assert_eq!(
+        log.iter().map(|entry| entry.index).collect::<Vec<_>>(),
+        vec![8, 9, 10, 11, 12]
+    );
+}
+
+#[test]
+fn test_store_channel_label() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("channel_label_store");
+    let store = Store::new(path);
+    let channel_id = gen_sha256_hash();
+    assert_eq!(store.get_channel_label(&channel_id), None);
+
+    store.set_channel_label(&channel_id, Some("exchange-A".to_string()));
+    assert_eq!(
+        store.get_channel_label(&channel_id),
+        Some("exchange-A".to_string())
+    );
+
+    store.set_channel_label(&channel_id, Some("cold-storage-backup".to_string()));
+    assert_eq!(
+        store.get_channel_label(&channel_id),
+        Some("cold-storage-backup".to_string())
+    );
+
+    store.set_channel_label(&channel_id, None);
+    assert_eq!(store.get_channel_label(&channel_id), None);
+}
+
+#[test]
+fn test_store_peer_fee_policy() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("peer_fee_policy_store");
+    let store = Store::new(path);
+    let peer_id = PeerId::random();
+    let other_peer_id = PeerId::random();
+    assert_eq!(store.get_peer_fee_policy(&peer_id), None);
+
+    let policy = PeerFeePolicy {
+        tlc_fee_proportional_millionths: Some(2000),
+        tlc_min_value: Some(100),
+        ..Default::default()
+    };
+    store.set_peer_fee_policy(&peer_id, Some(policy.clone()));
+    assert_eq!(store.get_peer_fee_policy(&peer_id), Some(policy));
+    assert_eq!(store.get_peer_fee_policy(&other_peer_id), None);
+
+    store.set_peer_fee_policy(&peer_id, None);
+    assert_eq!(store.get_peer_fee_policy(&peer_id), None);
+}
+
+#[test]
+fn test_store_retry_transient_read_errors() {
+    let io_error = || rocksdb::Error::new("IO error: No such device".to_string());
+    let corruption = || rocksdb::Error::new("Corruption: bad block".to_string());
+    assert!(is_transient_error(&io_error()));
+    assert!(!is_transient_error(&corruption()));
+
+    // the read succeeds after the transient failures within the retries
+    let mut attempts = 0;
+    let result = retry_read(3, || {
+        attempts += 1;
+        if attempts <= 2 {
+            Err(io_error())
+        } else {
+            Ok(attempts)
+        }
+    });
+    assert_eq!(result.ok(), Some(3));
+
+    // the transient error is surfaced once the retries are exhausted, after backing off
+    // 10 and 20 milliseconds
+    let mut attempts = 0;
+    let started = std::time::Instant::now();
+    let result: Result<(), _> = retry_read(2, || {
+        attempts += 1;
+        Err(io_error())
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 3);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(30));
+
+    // the permanent error is never retried
+    let mut attempts = 0;
+    let result: Result<(), _> = retry_read(3, || {
+        attempts += 1;
+        Err(corruption())
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+}